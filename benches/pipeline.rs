@@ -14,6 +14,7 @@ use http_body_util::Full;
 use tokio::net::TcpListener;
 use tokio::sync::oneshot;
 
+use hyper::rt::TokioIo;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::Response;
@@ -44,7 +45,7 @@ fn hello_world_16(b: &mut test::Bencher) {
                     Http::new()
                         .pipeline_flush(true)
                         .serve_connection(
-                            stream,
+                            TokioIo::new(stream),
                             service_fn(|_| async {
                                 Ok::<_, Infallible>(Response::new(Full::new(Bytes::from(
                                     "Hello, World!",
@@ -13,6 +13,7 @@ use futures_util::{stream, StreamExt};
 use http_body_util::{BodyExt, Full, StreamBody};
 use tokio::sync::oneshot;
 
+use hyper::rt::TokioIo;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::Response;
@@ -40,7 +41,7 @@ macro_rules! bench_server {
 
                         Http::new()
                             .serve_connection(
-                                stream,
+                                TokioIo::new(stream),
                                 service_fn(|_| async {
                                     Ok::<_, hyper::Error>(
                                         Response::builder()
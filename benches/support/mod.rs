@@ -1,3 +0,0 @@
-
-mod tokiort;
-pub use tokiort::TokioTimer;
\ No newline at end of file
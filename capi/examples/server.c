@@ -0,0 +1,316 @@
+#include <stdlib.h>
+#include <stdio.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <errno.h>
+#include <sys/select.h>
+#include <assert.h>
+
+#include <sys/types.h>
+#include <sys/socket.h>
+#include <netdb.h>
+#include <string.h>
+
+#include "hyper.h"
+
+
+struct conn_data {
+    int fd;
+    hyper_waker *read_waker;
+    hyper_waker *write_waker;
+};
+
+static size_t read_cb(void *userdata, hyper_context *ctx, uint8_t *buf, size_t buf_len) {
+    struct conn_data *conn = (struct conn_data *)userdata;
+    ssize_t ret = read(conn->fd, buf, buf_len);
+
+    if (ret >= 0) {
+        return ret;
+    }
+
+    if (errno != EAGAIN) {
+        // kaboom
+        return HYPER_IO_ERROR;
+    }
+
+    // would block, register interest
+    if (conn->read_waker != NULL) {
+        hyper_waker_free(conn->read_waker);
+    }
+    conn->read_waker = hyper_context_waker(ctx);
+    return HYPER_IO_PENDING;
+}
+
+static size_t write_cb(void *userdata, hyper_context *ctx, const uint8_t *buf, size_t buf_len) {
+    struct conn_data *conn = (struct conn_data *)userdata;
+    ssize_t ret = write(conn->fd, buf, buf_len);
+
+    if (ret >= 0) {
+        return ret;
+    }
+
+    if (errno != EAGAIN) {
+        // kaboom
+        return HYPER_IO_ERROR;
+    }
+
+    // would block, register interest
+    if (conn->write_waker != NULL) {
+        hyper_waker_free(conn->write_waker);
+    }
+    conn->write_waker = hyper_context_waker(ctx);
+    return HYPER_IO_PENDING;
+}
+
+static void free_conn_data(struct conn_data *conn) {
+    if (conn->read_waker) {
+        hyper_waker_free(conn->read_waker);
+        conn->read_waker = NULL;
+    }
+    if (conn->write_waker) {
+        hyper_waker_free(conn->write_waker);
+        conn->write_waker = NULL;
+    }
+
+    free(conn);
+}
+
+static int listen_on(const char *host, const char *port) {
+    struct addrinfo hints;
+    memset(&hints, 0, sizeof(struct addrinfo));
+    hints.ai_family = AF_UNSPEC;
+    hints.ai_socktype = SOCK_STREAM;
+    hints.ai_flags = AI_PASSIVE;
+
+    struct addrinfo *result, *rp;
+    if (getaddrinfo(host, port, &hints, &result) != 0) {
+        printf("dns failed for %s\n", host);
+        return -1;
+    }
+
+    int sfd;
+    for (rp = result; rp != NULL; rp = rp->ai_next) {
+        sfd = socket(rp->ai_family, rp->ai_socktype, rp->ai_protocol);
+        if (sfd == -1) {
+            continue;
+        }
+
+        int reuse = 1;
+        setsockopt(sfd, SOL_SOCKET, SO_REUSEADDR, &reuse, sizeof(reuse));
+
+        if (bind(sfd, rp->ai_addr, rp->ai_addrlen) == 0 && listen(sfd, 16) == 0) {
+            break;
+        }
+
+        close(sfd);
+    }
+
+    freeaddrinfo(result);
+
+    // no address succeeded
+    if (rp == NULL) {
+        printf("listen failed for %s\n", host);
+        return -1;
+    }
+
+    return sfd;
+}
+
+static int print_each_header(void *userdata,
+                             const uint8_t *name,
+                             size_t name_len,
+                             const uint8_t *value,
+                             size_t value_len) {
+    printf("%.*s: %.*s\n", (int) name_len, name, (int) value_len, value);
+    return HYPER_ITER_CONTINUE;
+}
+
+static int send_each_chunk(void *userdata, hyper_context *ctx, hyper_buf **chunk) {
+    int *sent = (int *)userdata;
+
+    if (*sent) {
+        // All done!
+        *chunk = NULL;
+        free(sent);
+    } else {
+        const char *body = "Hello, World!\n";
+        *chunk = hyper_buf_copy((const uint8_t *)body, strlen(body));
+        *sent = 1;
+    }
+
+    return HYPER_POLL_READY;
+}
+
+static void on_request(void *userdata, hyper_request *req, hyper_response_channel *channel) {
+    const uint8_t *method = hyper_request_method(req);
+    size_t method_len = hyper_request_method_len(req);
+    const uint8_t *path = hyper_request_path_and_query(req);
+    size_t path_len = hyper_request_path_and_query_len(req);
+
+    printf("\nRequest: %.*s %.*s\n", (int) method_len, method, (int) path_len, path);
+
+    hyper_headers *req_headers = hyper_request_headers(req);
+    hyper_headers_foreach(req_headers, print_each_header, NULL);
+
+    // This example doesn't read the request body
+    hyper_request_free(req);
+
+    // Prepare the response
+    hyper_response *resp = hyper_response_new();
+    hyper_response_set_status(resp, 200);
+
+    hyper_headers *resp_headers = hyper_response_headers(resp);
+    hyper_headers_set(resp_headers, (uint8_t *)"Content-Type", 12, (uint8_t *)"text/plain", 10);
+
+    int *sent = malloc(sizeof(int));
+    *sent = 0;
+
+    hyper_body *body = hyper_body_new();
+    hyper_body_set_userdata(body, (void *)sent);
+    hyper_body_set_data_func(body, send_each_chunk);
+    hyper_response_set_body(resp, body);
+
+    // Send it!
+    hyper_response_channel_send(channel, resp);
+}
+
+typedef enum {
+    EXAMPLE_NOT_SET = 0, // tasks we don't know about won't have a userdata set
+    EXAMPLE_SERVE
+} example_id;
+
+int main(int argc, char *argv[]) {
+    const char *host = argc > 1 ? argv[1] : "127.0.0.1";
+    const char *port = argc > 2 ? argv[2] : "8080";
+    printf("listening on port %s on %s...\n", port, host);
+
+    int listen_fd = listen_on(host, port);
+    if (listen_fd < 0) {
+        return 1;
+    }
+
+    fd_set fds_read;
+    fd_set fds_write;
+    fd_set fds_excep;
+
+    // We need an executor generally to poll futures
+    const hyper_executor *exec = hyper_executor_new();
+
+    // Prepare server options and the service, shared by all connections
+    hyper_serverconn_options *opts = hyper_serverconn_options_new();
+    hyper_serverconn_options_exec(opts, exec);
+
+    hyper_service *service = hyper_service_new(on_request);
+
+    // Serve one connection at a time
+    while (1) {
+        int fd = accept(listen_fd, NULL, NULL);
+        if (fd < 0) {
+            printf("accept failed\n");
+            return 1;
+        }
+
+        printf("accepted connection, serving with hyper v%s ...\n", hyper_version());
+        if (fcntl(fd, F_SETFL, O_NONBLOCK) != 0) {
+            printf("failed to set socket to non-blocking\n");
+            return 1;
+        }
+
+        struct conn_data *conn = malloc(sizeof(struct conn_data));
+
+        conn->fd = fd;
+        conn->read_waker = NULL;
+        conn->write_waker = NULL;
+
+        // Hookup the IO
+        hyper_io *io = hyper_io_new();
+        hyper_io_set_userdata(io, (void *)conn);
+        hyper_io_set_read(io, read_cb);
+        hyper_io_set_write(io, write_cb);
+
+        hyper_task *serve = hyper_serverconn_serve(io, opts, service);
+        hyper_task_set_userdata(serve, (void *)EXAMPLE_SERVE);
+        hyper_executor_push(exec, serve);
+
+        int done = 0;
+
+        // The polling state machine!
+        while (!done) {
+            // Poll all ready tasks and act on them...
+            while (1) {
+                hyper_task *task = hyper_executor_poll(exec);
+                if (!task) {
+                    break;
+                }
+                switch ((example_id) hyper_task_userdata(task)) {
+                case EXAMPLE_SERVE:
+                    ;
+                    if (hyper_task_type(task) == HYPER_TASK_ERROR) {
+                        hyper_error *err = hyper_task_value(task);
+                        // grab the error details
+                        char errbuf [256];
+                        size_t errlen = hyper_error_print(err, (uint8_t *)errbuf, sizeof(errbuf));
+                        printf("connection error: %.*s\n", (int) errlen, errbuf);
+                        hyper_error_free(err);
+                    } else {
+                        assert(hyper_task_type(task) == HYPER_TASK_EMPTY);
+                    }
+
+                    printf("\n -- Connection closed -- \n");
+
+                    hyper_task_free(task);
+                    done = 1;
+                    break;
+                case EXAMPLE_NOT_SET:
+                    // A background task for hyper completed...
+                    hyper_task_free(task);
+                    break;
+                }
+            }
+
+            if (done) {
+                break;
+            }
+
+            // All futures are pending on IO work, so select on the fd.
+
+            FD_ZERO(&fds_read);
+            FD_ZERO(&fds_write);
+            FD_ZERO(&fds_excep);
+
+            if (conn->read_waker) {
+                FD_SET(conn->fd, &fds_read);
+            }
+            if (conn->write_waker) {
+                FD_SET(conn->fd, &fds_write);
+            }
+
+            int sel_ret = select(conn->fd + 1, &fds_read, &fds_write, &fds_excep, NULL);
+
+            if (sel_ret < 0) {
+                printf("select() error\n");
+                return 1;
+            }
+
+            if (FD_ISSET(conn->fd, &fds_read)) {
+                hyper_waker_wake(conn->read_waker);
+                conn->read_waker = NULL;
+            }
+
+            if (FD_ISSET(conn->fd, &fds_write)) {
+                hyper_waker_wake(conn->write_waker);
+                conn->write_waker = NULL;
+            }
+        }
+
+        close(conn->fd);
+        free_conn_data(conn);
+    }
+
+    hyper_service_free(service);
+    hyper_serverconn_options_free(opts);
+    hyper_executor_free(exec);
+    close(listen_fd);
+
+    return 0;
+}
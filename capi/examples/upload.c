@@ -118,9 +118,7 @@ struct upload_body {
     size_t len;
 };
 
-static int poll_req_upload(void *userdata,
-                           hyper_context *ctx,
-                           hyper_buf **chunk) {
+static int poll_req_upload(void *userdata, hyper_buf **chunk) {
     struct upload_body* upload = userdata;
 
     ssize_t res = read(upload->fd, upload->buf, upload->len);
@@ -164,6 +162,7 @@ typedef enum {
     EXAMPLE_NOT_SET = 0, // tasks we don't know about won't have a userdata set
     EXAMPLE_HANDSHAKE,
     EXAMPLE_SEND,
+    EXAMPLE_REQ_BODY,
     EXAMPLE_RESP_BODY
 } example_id;
 
@@ -284,11 +283,19 @@ int main(int argc, char *argv[]) {
                 printf("    with expect-continue ...\n");
                 hyper_request_on_informational(req, print_informational, NULL);
 
-                // Prepare the req body
-                hyper_body *body = hyper_body_new();
-                hyper_body_set_userdata(body, &upload);
-                hyper_body_set_data_func(body, poll_req_upload);
-                hyper_request_set_body(req, body);
+                // Prepare the req body, which is read from the file as
+                // the connection is ready for more data
+                hyper_body_sender *sender = hyper_body_sender_new(poll_req_upload);
+                hyper_body_sender_set_userdata(sender, &upload);
+                hyper_request_set_body(req, hyper_body_sender_body(sender));
+
+                hyper_task *upload_task = hyper_body_sender_task(sender);
+                hyper_task_set_userdata(upload_task, (void *)EXAMPLE_REQ_BODY);
+                hyper_executor_push(exec, upload_task);
+
+                // The file is read synchronously, so the sender never needs
+                // to be resumed
+                hyper_body_sender_free(sender);
 
                 // Send it!
                 hyper_task *send = hyper_clientconn_send(client, req);
@@ -330,6 +337,16 @@ int main(int argc, char *argv[]) {
                 // No longer need the response
                 hyper_response_free(resp);
 
+                break;
+            case EXAMPLE_REQ_BODY:
+                ;
+                if (task_type == HYPER_TASK_ERROR) {
+                    printf("upload error!\n");
+                    return 1;
+                }
+                assert(task_type == HYPER_TASK_EMPTY);
+                hyper_task_free(task);
+
                 break;
             case EXAMPLE_RESP_BODY:
                 ;
@@ -69,6 +69,14 @@
  */
 #define HYPER_POLL_ERROR 3
 
+/*
+ Return in a body sender callback to indicate data isn't available yet.
+
+ The callback isn't called again until `hyper_body_sender_resume` is
+ called.
+ */
+#define HYPER_POLL_LATER 4
+
 /*
  A return code for many of hyper's methods.
  */
@@ -104,8 +112,79 @@ typedef enum hyper_code {
    The peer sent an HTTP message that could not be parsed.
    */
   HYPERE_INVALID_PEER_MESSAGE,
+  /*
+   A task was canceled with `hyper_task_cancel`, or a request was dropped
+   before the connection could process it.
+   */
+  HYPERE_CANCELED,
+  /*
+   An operation didn't complete in time, such as a header read timeout.
+   */
+  HYPERE_TIMEOUT,
+  /*
+   A channel was closed, such as sending a request on a connection that
+   already closed.
+   */
+  HYPERE_CLOSED,
+  /*
+   The IO transport failed, details may be available with
+   `hyper_error_os_error`.
+   */
+  HYPERE_IO,
+  /*
+   An HTTP/2 stream was reset, the reason is available with
+   `hyper_error_h2_reason`.
+   */
+  HYPERE_HTTP2_STREAM_RESET,
+  /*
+   An HTTP/2 protocol error, the reason is available with
+   `hyper_error_h2_reason`.
+   */
+  HYPERE_HTTP2,
 } hyper_code;
 
+/*
+ The category of an error parsing an HTTP message from the peer.
+ */
+typedef enum hyper_parse_error {
+  /*
+   The error isn't a parse error.
+   */
+  HYPER_PARSE_NONE,
+  /*
+   Invalid request method.
+   */
+  HYPER_PARSE_METHOD,
+  /*
+   Invalid or unsupported HTTP version.
+   */
+  HYPER_PARSE_VERSION,
+  /*
+   Invalid request target.
+   */
+  HYPER_PARSE_URI,
+  /*
+   The request target is too long.
+   */
+  HYPER_PARSE_URI_TOO_LONG,
+  /*
+   Invalid header.
+   */
+  HYPER_PARSE_HEADER,
+  /*
+   The message head is too large.
+   */
+  HYPER_PARSE_TOO_LARGE,
+  /*
+   Invalid response status code or reason phrase.
+   */
+  HYPER_PARSE_STATUS,
+  /*
+   An internal error while parsing.
+   */
+  HYPER_PARSE_INTERNAL,
+} hyper_parse_error;
+
 /*
  A descriptor for what type a `hyper_task` value is.
  */
@@ -130,6 +209,14 @@ typedef enum hyper_task_return_type {
    The value of this task is `hyper_buf *`.
    */
   HYPER_TASK_BUF,
+  /*
+   The value of this task is `hyper_headers *`.
+   */
+  HYPER_TASK_HEADERS,
+  /*
+   The value of this task is `hyper_upgraded *`.
+   */
+  HYPER_TASK_UPGRADED,
 } hyper_task_return_type;
 
 /*
@@ -137,11 +224,23 @@ typedef enum hyper_task_return_type {
  */
 typedef struct hyper_body hyper_body;
 
+/*
+ The sending half of a body, which provides its data either from a
+ callback as the connection is ready for it, or as it is pushed.
+ */
+typedef struct hyper_body_sender hyper_body_sender;
+
 /*
  A buffer of bytes that is sent or received on a `hyper_body`.
  */
 typedef struct hyper_buf hyper_buf;
 
+/*
+ A handle to cancel a `hyper_task`, even after it was pushed onto an
+ executor.
+ */
+typedef struct hyper_cancel_handle hyper_cancel_handle;
+
 /*
  An HTTP client connection handle.
 
@@ -193,20 +292,67 @@ typedef struct hyper_request hyper_request;
  */
 typedef struct hyper_response hyper_response;
 
+/*
+ A channel to send the response to a request received by a `hyper_service`.
+ */
+typedef struct hyper_response_channel hyper_response_channel;
+
+/*
+ An options builder to configure HTTP server connections.
+ */
+typedef struct hyper_serverconn_options hyper_serverconn_options;
+
+/*
+ A service that responds to the requests received on a server connection.
+ */
+typedef struct hyper_service hyper_service;
+
 /*
  An async task.
  */
 typedef struct hyper_task hyper_task;
 
+/*
+ A timer driven by the application's event loop.
+
+ hyper uses it for time-based features, such as HTTP/2 keep-alive pings
+ and header read timeouts. Each time hyper needs to wait, it calls the
+ schedule callback, and expects the application to wake the given waker
+ once the timeout elapses.
+ */
+typedef struct hyper_timer hyper_timer;
+
+/*
+ An upgraded connection, such as a tunnel established with `CONNECT`.
+
+ The connection is read and written with tasks, which may run at the same
+ time as each other. Only one read task and one write task should be
+ running at a time.
+ */
+typedef struct hyper_upgraded hyper_upgraded;
+
 /*
  A waker that is saved and used to waken a pending task.
  */
 typedef struct hyper_waker hyper_waker;
 
+/*
+ A callback to free the userdata of a handle, once hyper is done with it.
+ */
+typedef void (*hyper_userdata_drop)(void*);
+
+typedef void *(*hyper_malloc_callback)(size_t);
+
+typedef void (*hyper_free_callback)(void*);
+
 typedef int (*hyper_body_foreach_callback)(void*, const struct hyper_buf*);
 
 typedef int (*hyper_body_data_callback)(void*, struct hyper_context*, struct hyper_buf**);
 
+typedef int (*hyper_body_trailers_callback)(void*, struct hyper_context*, struct hyper_headers*);
+
+typedef int (*hyper_body_sender_callback)(void*, struct hyper_buf**);
+
 typedef void (*hyper_request_on_informational_callback)(void*, struct hyper_response*);
 
 typedef int (*hyper_headers_foreach_callback)(void*, const uint8_t*, size_t, const uint8_t*, size_t);
@@ -215,6 +361,14 @@ typedef size_t (*hyper_io_read_callback)(void*, struct hyper_context*, uint8_t*,
 
 typedef size_t (*hyper_io_write_callback)(void*, struct hyper_context*, const uint8_t*, size_t);
 
+typedef void (*hyper_service_callback)(void*, struct hyper_request*, struct hyper_response_channel*);
+
+typedef void (*hyper_executor_wakeup_callback)(void*);
+
+typedef uint64_t (*hyper_timer_schedule_callback)(void*, uint64_t, struct hyper_waker*);
+
+typedef void (*hyper_timer_cancel_callback)(void*, uint64_t);
+
 #ifdef __cplusplus
 extern "C" {
 #endif // __cplusplus
@@ -224,6 +378,21 @@ extern "C" {
  */
 const char *hyper_version(void);
 
+/*
+ Set the allocator used for the data hyper hands to the application.
+
+ Once set, the data of each `hyper_buf *` returned by a task, such as
+ the chunks of a body, and of the buffer from
+ `hyper_response_headers_raw`, is copied into memory from `malloc_fn`,
+ and released with `free_fn` once the buffer is freed. The callbacks
+ may be called from any thread, and `malloc_fn` must not return `NULL`.
+
+ This must be called before using any other hyper function, and can
+ only be called once. It returns `HYPERE_INVALID_ARG` if an allocator
+ was already set.
+ */
+enum hyper_code hyper_allocator_set(hyper_malloc_callback malloc_fn, hyper_free_callback free_fn);
+
 /*
  Create a new "empty" body.
 
@@ -250,6 +419,24 @@ void hyper_body_free(struct hyper_body *body);
  */
 struct hyper_task *hyper_body_data(struct hyper_body *body);
 
+/*
+ Return a task that will poll the body for its trailers.
+
+ This should be used once `hyper_body_data` has returned a
+ `HYPER_TASK_EMPTY` task, since trailers are only received after all
+ the data of the body. The task value may have different types
+ depending on the outcome:
+
+ - `HYPER_TASK_HEADERS`: Success, and trailers were received. The
+   `hyper_headers *` must be freed with `hyper_headers_free`.
+ - `HYPER_TASK_ERROR`: An error retrieving the trailers.
+ - `HYPER_TASK_EMPTY`: The body has no trailers.
+
+ This does not consume the `hyper_body *`, so it may be used to again.
+ However, it MUST NOT be used or freed until the related task completes.
+ */
+struct hyper_task *hyper_body_trailers(struct hyper_body *body);
+
 /*
  Return a task that will poll the body and execute the callback with each
  body chunk that is received.
@@ -271,6 +458,17 @@ struct hyper_task *hyper_body_foreach(struct hyper_body *body,
  */
 void hyper_body_set_userdata(struct hyper_body *body, void *userdata);
 
+/*
+ Set userdata on this body, and a callback to free it.
+
+ This is like `hyper_body_set_userdata`, except the `drop` callback is
+ called with the userdata once hyper frees the body, such as after
+ sending it.
+ */
+void hyper_body_set_userdata_with_drop(struct hyper_body *body,
+                                       void *userdata,
+                                       hyper_userdata_drop drop);
+
 /*
  Set the data callback for this body.
 
@@ -291,9 +489,173 @@ void hyper_body_set_userdata(struct hyper_body *body, void *userdata);
 
  If some error has occurred, you can return `HYPER_POLL_ERROR` to abort
  the body.
+
+ To only be asked for data once the connection is ready for it, such as
+ for large uploads, use `hyper_body_sender_new` instead.
  */
 void hyper_body_set_data_func(struct hyper_body *body, hyper_body_data_callback func);
 
+/*
+ Set the trailers callback for this body.
+
+ The callback is called once the data callback has indicated the body
+ has completed all data. It is passed the value from
+ `hyper_body_set_userdata`, and an empty `hyper_headers *` that the
+ trailers can be added to with `hyper_headers_set` or
+ `hyper_headers_add`. The `hyper_headers *` is only borrowed for the
+ duration of the callback.
+
+ Returning `HYPER_POLL_READY` will send the trailers, if any were
+ added. The same `HYPER_POLL_PENDING` and `HYPER_POLL_ERROR` return
+ values as the data callback can be used.
+
+ Trailers are currently only sent on HTTP/2 connections, and are
+ ignored on HTTP/1 connections.
+ */
+void hyper_body_set_trailers_func(struct hyper_body *body, hyper_body_trailers_callback func);
+
+/*
+ Create a body sender, which provides the data of a body from a callback.
+
+ Unlike the callback of `hyper_body_set_data_func`, this callback is
+ only called once the connection is ready to send more data, so a large
+ body is only read as fast as it can be written. It is passed the value
+ from `hyper_body_sender_set_userdata`.
+
+ If there is data available, the `hyper_buf **` argument should be set
+ to a `hyper_buf *` containing the data, and `HYPER_POLL_READY` should
+ be returned. Returning `HYPER_POLL_READY` while the `hyper_buf **`
+ argument points to `NULL` will indicate the body has completed all
+ data.
+
+ If more data isn't available yet, `HYPER_POLL_LATER` should be
+ returned, and `hyper_body_sender_resume` called once it is. The
+ callback won't be called again until then.
+
+ If some error has occurred, you can return `HYPER_POLL_ERROR` to abort
+ the body.
+
+ The body is retrieved with `hyper_body_sender_body`, and the data is
+ sent by pushing the task from `hyper_body_sender_task` to an executor.
+
+ The callback may instead be `NULL`, to push the data with
+ `hyper_body_sender_try_send_data` as it becomes available.
+ */
+struct hyper_body_sender *hyper_body_sender_new(hyper_body_sender_callback func);
+
+/*
+ Set userdata on this body sender, which will be passed to the callback.
+
+ This must be set before calling `hyper_body_sender_task`.
+ */
+void hyper_body_sender_set_userdata(struct hyper_body_sender *sender, void *userdata);
+
+/*
+ Set userdata on this body sender, and a callback to free it.
+
+ This is like `hyper_body_sender_set_userdata`, except the `drop`
+ callback is called with the userdata once both the sender and its task
+ are freed.
+ */
+void hyper_body_sender_set_userdata_with_drop(struct hyper_body_sender *sender,
+                                              void *userdata,
+                                              hyper_userdata_drop drop);
+
+/*
+ Take the body that receives the data of this sender.
+
+ The body can be set on a request or a response like any other body.
+ This returns `NULL` if the body was already taken.
+ */
+struct hyper_body *hyper_body_sender_body(struct hyper_body_sender *sender);
+
+/*
+ Return a task that will send the data provided by the callback.
+
+ The task value may have different types depending on the outcome:
+
+ - `HYPER_TASK_EMPTY`: All the data of the body was sent.
+ - `HYPER_TASK_ERROR`: The body was aborted by the callback, or dropped
+   by the connection before all its data was sent.
+
+ This returns `NULL` if the task was already taken, or the sender has
+ no callback. The sender must still be freed with
+ `hyper_body_sender_free`, and it can be used to resume the task until
+ then.
+ */
+struct hyper_task *hyper_body_sender_task(struct hyper_body_sender *sender);
+
+/*
+ Resume a body sender whose callback returned `HYPER_POLL_LATER`.
+
+ The callback will be called again once the connection is ready for
+ more data.
+ */
+void hyper_body_sender_resume(const struct hyper_body_sender *sender);
+
+/*
+ Return a task that will complete once this sender can accept more
+ data.
+
+ The task value may have different types depending on the outcome:
+
+ - `HYPER_TASK_EMPTY`: The next `hyper_body_sender_try_send_data` will
+   succeed.
+ - `HYPER_TASK_ERROR`: The body was dropped by the connection.
+
+ This returns `NULL` if the sender's data is provided by its task.
+
+ This does not consume the `hyper_body_sender *`. However, it MUST NOT
+ be used or freed until the related task completes.
+ */
+struct hyper_task *hyper_body_sender_ready(struct hyper_body_sender *sender);
+
+/*
+ Try to send a buffer of data on this sender.
+
+ This takes ownership of the `hyper_buf *` if it returns `HYPERE_OK`.
+ Otherwise, the sender can't accept more data right now, or the body
+ was dropped by the connection, and the `hyper_buf *` is still owned
+ by the caller. A task from `hyper_body_sender_ready` tells when more
+ data can be sent.
+
+ This returns `HYPERE_INVALID_ARG` if the sender's data is provided by
+ its task.
+ */
+enum hyper_code hyper_body_sender_try_send_data(struct hyper_body_sender *sender,
+                                                struct hyper_buf *buf);
+
+/*
+ Send trailers once all the data of the body was sent.
+
+ This takes ownership of the `hyper_headers *`, which should be created
+ with `hyper_headers_new`. Trailers can only be sent once, and are
+ currently only sent on HTTP/2 connections.
+
+ This returns `HYPERE_INVALID_ARG` if the sender's data is provided by
+ its task, and `HYPERE_ERROR` if the trailers were already sent or the
+ body was dropped by the connection.
+ */
+enum hyper_code hyper_body_sender_send_trailers(struct hyper_body_sender *sender,
+                                                struct hyper_headers *trailers);
+
+/*
+ Abort the body, so that it is not sent completely.
+
+ This consumes the `hyper_body_sender *`, you must not use it or free
+ it afterwards.
+ */
+void hyper_body_sender_abort(struct hyper_body_sender *sender);
+
+/*
+ Free a `hyper_body_sender *`.
+
+ If the data is pushed with `hyper_body_sender_try_send_data`, this
+ ends the body. If its task was taken, the task keeps sending the body,
+ but can no longer be resumed.
+ */
+void hyper_body_sender_free(struct hyper_body_sender *sender);
+
 /*
  Create a new `hyper_buf *` by copying the provided bytes.
 
@@ -384,6 +746,15 @@ void hyper_clientconn_options_free(struct hyper_clientconn_options *opts);
 void hyper_clientconn_options_exec(struct hyper_clientconn_options *opts,
                                    const struct hyper_executor *exec);
 
+/*
+ Set the timer used for time-based features, such as HTTP/2 keep-alive
+ pings.
+
+ This does not consume the `options` or the `timer`.
+ */
+void hyper_clientconn_options_timer(struct hyper_clientconn_options *opts,
+                                    const struct hyper_timer *timer);
+
 /*
  Set the whether to use HTTP2.
 
@@ -391,6 +762,78 @@ void hyper_clientconn_options_exec(struct hyper_clientconn_options *opts,
  */
 enum hyper_code hyper_clientconn_options_http2(struct hyper_clientconn_options *opts, int enabled);
 
+/*
+ Set the `SETTINGS_INITIAL_WINDOW_SIZE` option for HTTP/2 stream-level
+ flow control.
+
+ This disables the adaptive window. If not set, hyper will use a default.
+ */
+enum hyper_code hyper_clientconn_options_http2_initial_stream_window_size(struct hyper_clientconn_options *opts,
+                                                                          uint32_t size);
+
+/*
+ Set the max connection-level flow control window for HTTP/2.
+
+ This disables the adaptive window. If not set, hyper will use a default.
+ */
+enum hyper_code hyper_clientconn_options_http2_initial_connection_window_size(struct hyper_clientconn_options *opts,
+                                                                              uint32_t size);
+
+/*
+ Set whether to use an adaptive flow control window for HTTP/2.
+
+ Pass `0` to disable (default), `1` to enable. Enabling this overrides the
+ initial stream and connection window sizes.
+ */
+enum hyper_code hyper_clientconn_options_http2_adaptive_window(struct hyper_clientconn_options *opts,
+                                                               int enabled);
+
+/*
+ Set the maximum frame size to use for HTTP/2.
+
+ If not set, hyper will use a default.
+ */
+enum hyper_code hyper_clientconn_options_http2_max_frame_size(struct hyper_clientconn_options *opts,
+                                                              uint32_t size);
+
+/*
+ Set an interval in milliseconds for HTTP/2 Ping frames to be sent to
+ keep a connection alive.
+
+ Pass `0` to disable keep-alive (default). Pings are only sent if a
+ timer was set with `hyper_clientconn_options_timer`.
+ */
+enum hyper_code hyper_clientconn_options_http2_keep_alive_interval(struct hyper_clientconn_options *opts,
+                                                                   uint64_t interval_ms);
+
+/*
+ Set a timeout in milliseconds for receiving an acknowledgement of the
+ HTTP/2 keep-alive ping.
+
+ If the ping is not acknowledged within the timeout, the connection is
+ closed. The default is 20 seconds.
+ */
+enum hyper_code hyper_clientconn_options_http2_keep_alive_timeout(struct hyper_clientconn_options *opts,
+                                                                  uint64_t timeout_ms);
+
+/*
+ Set whether HTTP/2 keep-alive pings are also sent while the
+ connection is idle.
+
+ Pass `0` to only send pings while requests are in flight (default),
+ `1` to always send them.
+ */
+enum hyper_code hyper_clientconn_options_http2_keep_alive_while_idle(struct hyper_clientconn_options *opts,
+                                                                     int enabled);
+
+/*
+ Set the maximum number of HTTP/2 concurrent locally reset streams.
+
+ The default value is determined by the `h2` crate.
+ */
+enum hyper_code hyper_clientconn_options_http2_max_concurrent_reset_streams(struct hyper_clientconn_options *opts,
+                                                                            size_t max);
+
 /*
  Set the whether to include a copy of the raw headers in responses
  received on this connection.
@@ -432,6 +875,31 @@ enum hyper_code hyper_error_code(const struct hyper_error *err);
  */
 size_t hyper_error_print(const struct hyper_error *err, uint8_t *dst, size_t dst_len);
 
+/*
+ Get the OS error code of the IO error that caused this error, such as
+ an `errno` value.
+
+ Returns `0` if the error wasn't caused by an IO error, or if the IO
+ error had no OS error code.
+ */
+int hyper_error_os_error(const struct hyper_error *err);
+
+/*
+ Get the HTTP/2 error code that caused this error.
+
+ Returns `0` (`NO_ERROR`) if the error wasn't caused by an HTTP/2 error
+ with a reason, such as a `RST_STREAM` or `GOAWAY` frame.
+ */
+uint32_t hyper_error_h2_reason(const struct hyper_error *err);
+
+/*
+ Get the category of a parse error.
+
+ Returns `HYPER_PARSE_NONE` if the error code isn't
+ `HYPERE_INVALID_PEER_MESSAGE`.
+ */
+enum hyper_parse_error hyper_error_parse_error(const struct hyper_error *err);
+
 /*
  Construct a new HTTP request.
  */
@@ -527,6 +995,13 @@ enum hyper_code hyper_request_set_body(struct hyper_request *req, struct hyper_b
  `hyper_response *` which can be inspected as any other response. The
  body of the response will always be empty.
 
+ This can be used to read `103 Early Hints`, or to implement
+ `Expect: 100-continue`: provide the body with a `hyper_body_sender *`
+ whose callback returns `HYPER_POLL_LATER`, and call
+ `hyper_body_sender_resume` once the `100 Continue` response arrives.
+
+ Informational responses are only received on HTTP/1 connections.
+
  NOTE: The `hyper_response *` is just borrowed data, and will not
  be valid after the callback finishes. You must copy any data you wish
  to persist.
@@ -535,6 +1010,136 @@ enum hyper_code hyper_request_on_informational(struct hyper_request *req,
                                                hyper_request_on_informational_callback callback,
                                                void *data);
 
+/*
+ Set an informational (1xx) response callback, and a callback to free
+ its user data.
+
+ This is like `hyper_request_on_informational`, except the `drop`
+ callback is called with the user data once hyper is done with the
+ callback, or the request is freed.
+ */
+enum hyper_code hyper_request_on_informational_with_drop(struct hyper_request *req,
+                                                         hyper_request_on_informational_callback callback,
+                                                         void *data,
+                                                         hyper_userdata_drop drop);
+
+/*
+ Get a pointer to the HTTP method of this request.
+
+ This buffer is not null-terminated.
+
+ This buffer is owned by the request, and should not be used after
+ the request has been freed.
+
+ Use `hyper_request_method_len()` to get the length of this buffer.
+ */
+const uint8_t *hyper_request_method(const struct hyper_request *req);
+
+/*
+ Get the length of the HTTP method of this request.
+
+ Use `hyper_request_method()` to get the buffer pointer.
+ */
+size_t hyper_request_method_len(const struct hyper_request *req);
+
+/*
+ Get a pointer to the path and query of this request's URI.
+
+ This buffer is not null-terminated, and is empty if the URI has no
+ path, such as for a `CONNECT` request.
+
+ This buffer is owned by the request, and should not be used after
+ the request has been freed.
+
+ Use `hyper_request_path_and_query_len()` to get the length of this
+ buffer.
+ */
+const uint8_t *hyper_request_path_and_query(const struct hyper_request *req);
+
+/*
+ Get the length of the path and query of this request's URI.
+
+ Use `hyper_request_path_and_query()` to get the buffer pointer.
+ */
+size_t hyper_request_path_and_query_len(const struct hyper_request *req);
+
+/*
+ Get a pointer to the authority of this request's URI.
+
+ This buffer is not null-terminated, and is empty if the URI has no
+ authority. HTTP/1 requests usually carry it in the `Host` header
+ instead.
+
+ This buffer is owned by the request, and should not be used after
+ the request has been freed.
+
+ Use `hyper_request_authority_len()` to get the length of this buffer.
+ */
+const uint8_t *hyper_request_authority(const struct hyper_request *req);
+
+/*
+ Get the length of the authority of this request's URI.
+
+ Use `hyper_request_authority()` to get the buffer pointer.
+ */
+size_t hyper_request_authority_len(const struct hyper_request *req);
+
+/*
+ Get the HTTP version used by this request.
+
+ The returned value could be:
+
+ - `HYPER_HTTP_VERSION_1_0`
+ - `HYPER_HTTP_VERSION_1_1`
+ - `HYPER_HTTP_VERSION_2`
+ - `HYPER_HTTP_VERSION_NONE` if newer (or older).
+ */
+int hyper_request_version(const struct hyper_request *req);
+
+/*
+ Take ownership of the body of this request.
+
+ It is safe to free the request even after taking ownership of its body.
+ */
+struct hyper_body *hyper_request_body(struct hyper_request *req);
+
+/*
+ Construct a new HTTP response.
+
+ The response has a `200 OK` status and an empty body until changed.
+ */
+struct hyper_response *hyper_response_new(void);
+
+/*
+ Set the HTTP-Status code of this response.
+
+ The code must be within the range of 100-999.
+ */
+enum hyper_code hyper_response_set_status(struct hyper_response *resp, uint16_t status);
+
+/*
+ Set the reason-phrase of this response.
+
+ By default, the canonical reason of the status code is sent, such as
+ `OK` for `200`. The reason-phrase is only sent on HTTP/1 connections.
+
+ Returns `HYPERE_INVALID_ARG` if the bytes aren't a valid
+ reason-phrase, such as when they contain a newline.
+ */
+enum hyper_code hyper_response_set_reason_phrase(struct hyper_response *resp,
+                                                 const uint8_t *reason,
+                                                 size_t reason_len);
+
+/*
+ Set the body of the response.
+
+ The default is an empty body.
+
+ This takes ownership of the `hyper_body *`, you must not use it or
+ free it after setting it on the response.
+ */
+enum hyper_code hyper_response_set_body(struct hyper_response *resp, struct hyper_body *body);
+
 /*
  Free an HTTP response after using it.
  */
@@ -644,6 +1249,23 @@ enum hyper_code hyper_headers_add(struct hyper_headers *headers,
                                   const uint8_t *value,
                                   size_t value_len);
 
+/*
+ Create a new, empty set of headers.
+
+ These can be sent as the trailers of a body with
+ `hyper_body_sender_send_trailers`.
+ */
+struct hyper_headers *hyper_headers_new(void);
+
+/*
+ Free a `hyper_headers *` that was returned as the value of a task, or
+ created with `hyper_headers_new`.
+
+ The headers of a request or response are owned by it, and must not be
+ freed with this function.
+ */
+void hyper_headers_free(struct hyper_headers *headers);
+
 /*
  Create a new IO type used to represent a transport.
 
@@ -667,6 +1289,15 @@ void hyper_io_free(struct hyper_io *io);
  */
 void hyper_io_set_userdata(struct hyper_io *io, void *data);
 
+/*
+ Set the user data pointer for this IO, and a callback to free it.
+
+ This is like `hyper_io_set_userdata`, except the `drop` callback is
+ called with the user data once hyper frees the IO, such as when its
+ connection is closed.
+ */
+void hyper_io_set_userdata_with_drop(struct hyper_io *io, void *data, hyper_userdata_drop drop);
+
 /*
  Set the read function for this IO transport.
 
@@ -683,7 +1314,8 @@ void hyper_io_set_userdata(struct hyper_io *io, void *data);
  `HYPER_IO_PENDING`.
 
  If there is an irrecoverable error reading data, then `HYPER_IO_ERROR`
- should be the return value.
+ should be the return value. If `errno` is set at that point, it is
+ available from the resulting error with `hyper_error_os_error`.
  */
 void hyper_io_set_read(struct hyper_io *io, hyper_io_read_callback func);
 
@@ -700,10 +1332,146 @@ void hyper_io_set_read(struct hyper_io *io, hyper_io_read_callback func);
  Yeet.
 
  If there is an irrecoverable error reading data, then `HYPER_IO_ERROR`
- should be the return value.
+ should be the return value. If `errno` is set at that point, it is
+ available from the resulting error with `hyper_error_os_error`.
  */
 void hyper_io_set_write(struct hyper_io *io, hyper_io_write_callback func);
 
+/*
+ Creates a new set of HTTP serverconn options to be used when serving
+ connections.
+ */
+struct hyper_serverconn_options *hyper_serverconn_options_new(void);
+
+/*
+ Free a `hyper_serverconn_options *`.
+ */
+void hyper_serverconn_options_free(struct hyper_serverconn_options *opts);
+
+/*
+ Set the server background task executor.
+
+ This does not consume the `options` or the `exec`.
+ */
+void hyper_serverconn_options_exec(struct hyper_serverconn_options *opts,
+                                   const struct hyper_executor *exec);
+
+/*
+ Set the timer used for time-based features, such as header read
+ timeouts.
+
+ This does not consume the `options` or the `timer`.
+ */
+void hyper_serverconn_options_timer(struct hyper_serverconn_options *opts,
+                                    const struct hyper_timer *timer);
+
+/*
+ Set the whether or not header case is preserved.
+
+ Pass `0` to allow lowercase normalization (default), `1` to retain original case.
+ */
+void hyper_serverconn_options_set_preserve_header_case(struct hyper_serverconn_options *opts,
+                                                       int enabled);
+
+/*
+ Set the whether to serve HTTP2 only.
+
+ Pass `0` to serve HTTP/1 (default), `1` to serve HTTP/2.
+ */
+enum hyper_code hyper_serverconn_options_http2(struct hyper_serverconn_options *opts, int enabled);
+
+/*
+ Set a timeout in milliseconds for reading the headers of HTTP/1
+ requests.
+
+ If a client doesn't send the whole head of a request within this
+ time, the connection is closed. Pass `0` to disable the timeout
+ (default). It only applies if a timer was set with
+ `hyper_serverconn_options_timer`.
+ */
+void hyper_serverconn_options_http1_header_read_timeout(struct hyper_serverconn_options *opts,
+                                                        uint64_t timeout_ms);
+
+/*
+ Set a timeout in milliseconds for the service to respond to a
+ request.
+
+ If the response isn't sent on the `hyper_response_channel` within
+ this time, a `503 Service Unavailable` response is sent instead. Pass
+ `0` to disable the timeout (default). It only applies if a timer was
+ set with `hyper_serverconn_options_timer`.
+ */
+void hyper_serverconn_options_service_timeout(struct hyper_serverconn_options *opts,
+                                              uint64_t timeout_ms);
+
+/*
+ Create a service from a callback.
+
+ The callback is called with each request received on a connection
+ served with this service. It is passed the value from
+ `hyper_service_set_userdata`, the `hyper_request *`, and a
+ `hyper_response_channel *`.
+
+ The callback takes ownership of the request and the channel. The
+ response must be sent on the channel with
+ `hyper_response_channel_send`, either during the callback or later on.
+ The request body can be read with `hyper_request_body` in the
+ meantime.
+ */
+struct hyper_service *hyper_service_new(hyper_service_callback service_fn);
+
+/*
+ Set userdata on this service, which will be passed to the callback.
+ */
+void hyper_service_set_userdata(struct hyper_service *service, void *userdata);
+
+/*
+ Set userdata on this service, and a callback to free it.
+
+ This is like `hyper_service_set_userdata`, except the `drop` callback
+ is called with the userdata once the service and all the connections
+ served with it are freed.
+ */
+void hyper_service_set_userdata_with_drop(struct hyper_service *service,
+                                          void *userdata,
+                                          hyper_userdata_drop drop);
+
+/*
+ Free a `hyper_service *`.
+ */
+void hyper_service_free(struct hyper_service *service);
+
+/*
+ Send the response to a request on this channel.
+
+ This consumes both the channel and the response.
+ */
+enum hyper_code hyper_response_channel_send(struct hyper_response_channel *channel,
+                                            struct hyper_response *response);
+
+/*
+ Free a `hyper_response_channel *` without sending a response.
+
+ The request is then answered with an error, which closes the
+ connection.
+ */
+void hyper_response_channel_free(struct hyper_response_channel *channel);
+
+/*
+ Serve an HTTP connection on the provided IO transport, passing each
+ request to the `service`.
+
+ The `io` is consumed in this function call, while the `options` and
+ the `service` are not, so they can be used to serve other connections.
+
+ The returned `hyper_task *` must be polled with an executor until the
+ connection closes. Its value is then empty, or a `hyper_error *` if
+ the connection failed.
+ */
+struct hyper_task *hyper_serverconn_serve(struct hyper_io *io,
+                                          const struct hyper_serverconn_options *options,
+                                          const struct hyper_service *service);
+
 /*
  Creates a new task executor.
  */
@@ -732,6 +1500,38 @@ enum hyper_code hyper_executor_push(const struct hyper_executor *exec, struct hy
  */
 struct hyper_task *hyper_executor_poll(const struct hyper_executor *exec);
 
+/*
+ Set a callback to notify the application that the executor has work
+ to do, so that it can be integrated into an event loop, such as one
+ using `epoll` or `kqueue`.
+
+ The callback is passed the userdata, and is called once a task is
+ pushed or woken up after `hyper_executor_poll` returned `NULL`. The
+ application should then call `hyper_executor_poll` until it returns
+ `NULL` again. The callback may be called from within
+ `hyper_executor_poll`, or from any thread that wakes a task, so a
+ typical callback only writes to an `eventfd` or a pipe watched by the
+ event loop. It must not call any `hyper_executor_*` function.
+
+ The callback may be `NULL` to remove it. The `drop` callback may be
+ `NULL`. Otherwise, it is called with the userdata once the callback is
+ replaced, or the executor is freed.
+ */
+enum hyper_code hyper_executor_set_wakeup(const struct hyper_executor *exec,
+                                          hyper_executor_wakeup_callback func,
+                                          void *userdata,
+                                          hyper_userdata_drop drop);
+
+/*
+ Check whether the executor has tasks that were pushed or woken up
+ since `hyper_executor_poll` last returned `NULL`.
+
+ Returns `1` if `hyper_executor_poll` should be called, and `0` if the
+ application can wait for the wakeup callback, or for its own IO
+ events, instead.
+ */
+int hyper_executor_has_pending(const struct hyper_executor *exec);
+
 /*
  Free a task.
  */
@@ -760,11 +1560,55 @@ enum hyper_task_return_type hyper_task_type(struct hyper_task *task);
  */
 void hyper_task_set_userdata(struct hyper_task *task, void *userdata);
 
+/*
+ Set a user data pointer on this task, and a callback to free it.
+
+ This is like `hyper_task_set_userdata`, except the `drop` callback is
+ called with the userdata once the task is freed, or the userdata is
+ replaced.
+ */
+void hyper_task_set_userdata_with_drop(struct hyper_task *task,
+                                       void *userdata,
+                                       hyper_userdata_drop drop);
+
 /*
  Retrieve the userdata that has been set via `hyper_task_set_userdata`.
  */
 void *hyper_task_userdata(struct hyper_task *task);
 
+/*
+ Get a handle that can cancel this task.
+
+ The handle stays valid after the task is pushed onto an executor, so
+ it can be used to abort an in-flight request, for instance once a
+ timeout expires. See `hyper_task_cancel`.
+
+ The handle must be freed with `hyper_cancel_handle_free`, which can
+ be done before or after the task completes.
+ */
+struct hyper_cancel_handle *hyper_task_cancel_handle(struct hyper_task *task);
+
+/*
+ Cancel the task of this handle.
+
+ If the task hasn't completed yet, the work it was doing is abandoned:
+ an in-flight HTTP/2 request has its stream reset, and an in-flight
+ HTTP/1 request closes its connection. The task is then returned by
+ `hyper_executor_poll` with a `hyper_error *` value, whose code is
+ `HYPERE_CANCELED`.
+
+ Canceling a task that already completed does nothing. This does not
+ consume the handle.
+ */
+void hyper_task_cancel(const struct hyper_cancel_handle *handle);
+
+/*
+ Free a `hyper_cancel_handle *`.
+
+ This does not cancel the task.
+ */
+void hyper_cancel_handle_free(struct hyper_cancel_handle *handle);
+
 /*
  Copies a waker out of the task context.
  */
@@ -782,6 +1626,129 @@ void hyper_waker_free(struct hyper_waker *waker);
  */
 void hyper_waker_wake(struct hyper_waker *waker);
 
+/*
+ Create a timer from the callbacks of the application's event loop.
+
+ The schedule callback is passed the value from
+ `hyper_timer_set_userdata`, a timeout in milliseconds, and a
+ `hyper_waker *`. Once the timeout elapses, the application must call
+ `hyper_waker_wake` with the waker. The callback returns an id for the
+ scheduled timer.
+
+ The cancel callback is passed the userdata and the id of a timer that
+ hyper doesn't need anymore, and that hasn't fired yet. The application
+ must then free its waker with `hyper_waker_free` instead of waking it.
+
+ A timer is used by setting it on connection options, with
+ `hyper_clientconn_options_timer` or `hyper_serverconn_options_timer`.
+ */
+struct hyper_timer *hyper_timer_new(hyper_timer_schedule_callback schedule,
+                                    hyper_timer_cancel_callback cancel);
+
+/*
+ Set userdata on this timer, which will be passed to the callbacks.
+ */
+void hyper_timer_set_userdata(struct hyper_timer *timer, void *userdata);
+
+/*
+ Set userdata on this timer, and a callback to free it.
+
+ This is like `hyper_timer_set_userdata`, except the `drop` callback is
+ called with the userdata once the timer and all the connections it was
+ set on are freed.
+ */
+void hyper_timer_set_userdata_with_drop(struct hyper_timer *timer,
+                                        void *userdata,
+                                        hyper_userdata_drop drop);
+
+/*
+ Free a `hyper_timer *`.
+
+ Connections the timer was set on keep using its callbacks.
+ */
+void hyper_timer_free(struct hyper_timer *timer);
+
+/*
+ Return a task that will resolve to the upgraded connection of this
+ response.
+
+ This should be used once a response with a `101 Switching Protocols`
+ status, or a successful status to a `CONNECT` request, was received.
+ The task value may have different types depending on the outcome:
+
+ - `HYPER_TASK_UPGRADED`: Success, the value is a `hyper_upgraded *`.
+ - `HYPER_TASK_ERROR`: The connection couldn't be upgraded.
+
+ This does not consume the `hyper_response *`, which can be freed
+ before the task completes. The client connection must keep being
+ polled until then.
+ */
+struct hyper_task *hyper_response_upgrade(struct hyper_response *resp);
+
+/*
+ Return a task that will resolve to the upgraded connection of this
+ request.
+
+ This should be used on a request received by a `hyper_service`, which
+ is then answered with a `101 Switching Protocols` response, or a
+ successful response to a `CONNECT` request. The task value may have
+ different types depending on the outcome:
+
+ - `HYPER_TASK_UPGRADED`: Success, the value is a `hyper_upgraded *`.
+ - `HYPER_TASK_ERROR`: The connection couldn't be upgraded.
+
+ This does not consume the `hyper_request *`, which can be freed
+ before the task completes.
+ */
+struct hyper_task *hyper_request_upgrade(struct hyper_request *req);
+
+/*
+ Return a task that will read the next buffer of data from the upgraded
+ connection.
+
+ Any bytes that hyper had already read past the end of the HTTP message
+ are returned first. The task value may have different types depending
+ on the outcome:
+
+ - `HYPER_TASK_BUF`: Success, and more data was received.
+ - `HYPER_TASK_ERROR`: An error reading from the connection.
+ - `HYPER_TASK_EMPTY`: The connection reached EOF.
+
+ This does not consume the `hyper_upgraded *`.
+ */
+struct hyper_task *hyper_upgraded_read(const struct hyper_upgraded *upgraded);
+
+/*
+ Return a task that will write all the data of the buffer to the
+ upgraded connection, and flush it.
+
+ The task value is `HYPER_TASK_EMPTY` once the data is written, or
+ `HYPER_TASK_ERROR` if an error occurred.
+
+ This takes ownership of the `hyper_buf *`, but not of the
+ `hyper_upgraded *`.
+ */
+struct hyper_task *hyper_upgraded_write(const struct hyper_upgraded *upgraded,
+                                        struct hyper_buf *buf);
+
+/*
+ Return a task that will shut down the write side of the upgraded
+ connection.
+
+ The task value is `HYPER_TASK_EMPTY` once the connection is shut
+ down, or `HYPER_TASK_ERROR` if an error occurred.
+
+ This does not consume the `hyper_upgraded *`.
+ */
+struct hyper_task *hyper_upgraded_shutdown(const struct hyper_upgraded *upgraded);
+
+/*
+ Free a `hyper_upgraded *`.
+
+ The connection is closed once any tasks using it have completed.
+ */
+void hyper_upgraded_free(struct hyper_upgraded *upgraded);
+
 #ifdef __cplusplus
 } // extern "C"
 #endif // __cplusplus
@@ -3,8 +3,9 @@
 use std::net::SocketAddr;
 
 use bytes::Bytes;
-use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
-use hyper::body::Body as _;
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::{Body as _, BoxBody};
+use hyper::rt::TokioIo;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::{Method, Recv, Request, Response, StatusCode};
@@ -66,15 +67,11 @@ async fn echo(req: Request<Recv>) -> Result<Response<BoxBody<Bytes, hyper::Error
 }
 
 fn empty() -> BoxBody<Bytes, hyper::Error> {
-    Empty::<Bytes>::new()
-        .map_err(|never| match never {})
-        .boxed()
+    BoxBody::new(Empty::<Bytes>::new().map_err(|never| match never {}))
 }
 
 fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
-    Full::new(chunk.into())
-        .map_err(|never| match never {})
-        .boxed()
+    BoxBody::new(Full::new(chunk.into()).map_err(|never| match never {}))
 }
 
 #[tokio::main]
@@ -87,7 +84,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let (stream, _) = listener.accept().await?;
 
         tokio::task::spawn(async move {
-            if let Err(err) = Http::new().serve_connection(stream, service_fn(echo)).await {
+            if let Err(err) = Http::new()
+                .serve_connection(TokioIo::new(stream), service_fn(echo))
+                .await
+            {
                 println!("Error serving connection: {:?}", err);
             }
         });
@@ -1,5 +1,6 @@
 #![deny(warnings)]
 
+use hyper::rt::TokioIo;
 use hyper::{server::conn::Http, service::service_fn};
 use std::net::SocketAddr;
 use tokio::net::{TcpListener, TcpStream};
@@ -44,7 +45,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let client_stream = TcpStream::connect(addr).await.unwrap();
 
                 let (mut sender, conn) =
-                    hyper::client::conn::http1::handshake(client_stream).await?;
+                    hyper::client::conn::http1::handshake(TokioIo::new(client_stream)).await?;
                 tokio::task::spawn(async move {
                     if let Err(err) = conn.await {
                         println!("Connection failed: {:?}", err);
@@ -56,7 +57,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
 
         tokio::task::spawn(async move {
-            if let Err(err) = Http::new().serve_connection(stream, service).await {
+            if let Err(err) = Http::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
                 println!("Failed to servce connection: {:?}", err);
             }
         });
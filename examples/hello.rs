@@ -5,6 +5,7 @@ use std::net::SocketAddr;
 
 use bytes::Bytes;
 use http_body_util::Full;
+use hyper::rt::TokioIo;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::{Recv, Request, Response};
@@ -27,7 +28,7 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
         tokio::task::spawn(async move {
             if let Err(err) = Http::new()
-                .serve_connection(stream, service_fn(hello))
+                .serve_connection(TokioIo::new(stream), service_fn(hello))
                 .await
             {
                 println!("Error serving connection: {:?}", err);
@@ -3,13 +3,15 @@
 use std::net::SocketAddr;
 
 use bytes::Bytes;
-use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
+use http_body_util::{BodyExt, Empty, Full};
+use hyper::body::BoxBody;
 use hyper::client::conn::http1::Builder;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::upgrade::Upgraded;
 use hyper::{Method, Recv, Request, Response};
 
+use hyper::rt::TokioIo;
 use tokio::net::{TcpListener, TcpStream};
 
 // To try this example:
@@ -33,7 +35,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if let Err(err) = Http::new()
                 .http1_preserve_header_case(true)
                 .http1_title_case_headers(true)
-                .serve_connection(stream, service_fn(proxy))
+                .serve_connection(TokioIo::new(stream), service_fn(proxy))
                 .with_upgrades()
                 .await
             {
@@ -90,7 +92,7 @@ async fn proxy(req: Request<Recv>) -> Result<Response<BoxBody<Bytes, hyper::Erro
         let (mut sender, conn) = Builder::new()
             .http1_preserve_header_case(true)
             .http1_title_case_headers(true)
-            .handshake(stream)
+            .handshake(TokioIo::new(stream))
             .await?;
         tokio::task::spawn(async move {
             if let Err(err) = conn.await {
@@ -108,22 +110,19 @@ fn host_addr(uri: &http::Uri) -> Option<String> {
 }
 
 fn empty() -> BoxBody<Bytes, hyper::Error> {
-    Empty::<Bytes>::new()
-        .map_err(|never| match never {})
-        .boxed()
+    BoxBody::new(Empty::<Bytes>::new().map_err(|never| match never {}))
 }
 
 fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
-    Full::new(chunk.into())
-        .map_err(|never| match never {})
-        .boxed()
+    BoxBody::new(Full::new(chunk.into()).map_err(|never| match never {}))
 }
 
 // Create a TCP connection to host:port, build a tunnel between the connection and
 // the upgraded connection
-async fn tunnel(mut upgraded: Upgraded, addr: String) -> std::io::Result<()> {
+async fn tunnel(upgraded: Upgraded, addr: String) -> std::io::Result<()> {
     // Connect to remote server
     let mut server = TcpStream::connect(addr).await?;
+    let mut upgraded = TokioIo::new(upgraded);
 
     // Proxying data
     let (from_client, from_server) =
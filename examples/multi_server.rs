@@ -6,6 +6,7 @@ use std::net::SocketAddr;
 use bytes::Bytes;
 use futures_util::future::join;
 use http_body_util::Full;
+use hyper::rt::TokioIo;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::{Recv, Request, Response};
@@ -36,7 +37,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
             tokio::task::spawn(async move {
                 if let Err(err) = Http::new()
-                    .serve_connection(stream, service_fn(index1))
+                    .serve_connection(TokioIo::new(stream), service_fn(index1))
                     .await
                 {
                     println!("Error serving connection: {:?}", err);
@@ -52,7 +53,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
             tokio::task::spawn(async move {
                 if let Err(err) = Http::new()
-                    .serve_connection(stream, service_fn(index2))
+                    .serve_connection(TokioIo::new(stream), service_fn(index2))
                     .await
                 {
                     println!("Error serving connection: {:?}", err);
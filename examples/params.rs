@@ -8,6 +8,7 @@ use hyper::service::service_fn;
 use hyper::{Method, Recv, Request, Response, StatusCode};
 use tokio::net::TcpListener;
 
+use hyper::rt::TokioIo;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::net::SocketAddr;
@@ -127,7 +128,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
         tokio::task::spawn(async move {
             if let Err(err) = Http::new()
-                .serve_connection(stream, service_fn(param_example))
+                .serve_connection(TokioIo::new(stream), service_fn(param_example))
                 .await
             {
                 println!("Error serving connection: {:?}", err);
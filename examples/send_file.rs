@@ -7,6 +7,7 @@ use tokio::net::TcpListener;
 
 use bytes::Bytes;
 use http_body_util::Full;
+use hyper::rt::TokioIo;
 use hyper::service::service_fn;
 use hyper::{Method, Recv, Request, Response, Result, StatusCode};
 
@@ -27,7 +28,7 @@ async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
 
         tokio::task::spawn(async move {
             if let Err(err) = Http::new()
-                .serve_connection(stream, service_fn(response_examples))
+                .serve_connection(TokioIo::new(stream), service_fn(response_examples))
                 .await
             {
                 println!("Failed to serve connection: {:?}", err);
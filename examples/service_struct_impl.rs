@@ -5,6 +5,7 @@ use hyper::service::Service;
 use hyper::{Recv, Request, Response};
 use tokio::net::TcpListener;
 
+use hyper::rt::TokioIo;
 use std::future::Future;
 use std::net::SocketAddr;
 use std::pin::Pin;
@@ -23,7 +24,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
         tokio::task::spawn(async move {
             if let Err(err) = Http::new()
-                .serve_connection(stream, Svc { counter: 81818 })
+                .serve_connection(TokioIo::new(stream), Svc { counter: 81818 })
                 .await
             {
                 println!("Failed to serve connection: {:?}", err);
@@ -8,6 +8,7 @@ use tokio::net::TcpListener;
 
 use hyper::body::{Body as HttpBody, Bytes};
 use hyper::header::{HeaderMap, HeaderValue};
+use hyper::rt::TokioIo;
 use hyper::service::service_fn;
 use hyper::{Error, Response};
 use std::marker::PhantomData;
@@ -86,7 +87,7 @@ async fn run() -> Result<(), Box<dyn std::error::Error>> {
         tokio::task::spawn_local(async move {
             if let Err(err) = Http::new()
                 .with_executor(LocalExec)
-                .serve_connection(stream, service)
+                .serve_connection(TokioIo::new(stream), service)
                 .await
             {
                 println!("Error serving connection: {:?}", err);
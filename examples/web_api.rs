@@ -4,6 +4,7 @@ use std::net::SocketAddr;
 
 use bytes::{Buf, Bytes};
 use http_body_util::{BodyExt, Full};
+use hyper::rt::TokioIo;
 use hyper::server::conn::Http;
 use hyper::service::service_fn;
 use hyper::{header, Method, Recv, Request, Response, StatusCode};
@@ -11,7 +12,7 @@ use tokio::net::{TcpListener, TcpStream};
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
-type BoxBody = http_body_util::combinators::BoxBody<Bytes, hyper::Error>;
+type BoxBody = hyper::body::BoxBody<Bytes, hyper::Error>;
 
 static INDEX: &[u8] = b"<a href=\"test.html\">test.html</a>";
 static INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
@@ -31,7 +32,7 @@ async fn client_request_response() -> Result<Response<BoxBody>> {
     let port = req.uri().port_u16().expect("uri has no port");
     let stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
 
-    let (mut sender, conn) = hyper::client::conn::http1::handshake(stream).await?;
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
 
     tokio::task::spawn(async move {
         if let Err(err) = conn.await {
@@ -94,9 +95,7 @@ async fn response_examples(req: Request<Recv>) -> Result<Response<BoxBody>> {
 }
 
 fn full<T: Into<Bytes>>(chunk: T) -> BoxBody {
-    Full::new(chunk.into())
-        .map_err(|never| match never {})
-        .boxed()
+    BoxBody::new(Full::new(chunk.into()).map_err(|never| match never {}))
 }
 
 #[tokio::main]
@@ -113,7 +112,10 @@ async fn main() -> Result<()> {
         tokio::task::spawn(async move {
             let service = service_fn(move |req| response_examples(req));
 
-            if let Err(err) = Http::new().serve_connection(stream, service).await {
+            if let Err(err) = Http::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
                 println!("Failed to serve connection: {:?}", err);
             }
         });
@@ -1,19 +1,21 @@
 use std::fmt;
+#[cfg(all(feature = "http2", feature = "server"))]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(all(feature = "http2", feature = "server"))]
+use std::sync::Arc;
 
 use bytes::Bytes;
-use futures_channel::mpsc;
 use futures_channel::oneshot;
-use futures_core::Stream; // for mpsc::Receiver
 use http::HeaderMap;
 use http_body::{Body, SizeHint};
 
-use super::DecodedLength;
+use super::{BoxBody, DecodedLength, UnsyncBoxBody};
 use crate::common::Future;
-use crate::common::{task, watch, Pin, Poll};
+use crate::common::{spsc, task, watch, Pin, Poll};
 #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
-use crate::proto::h2::ping;
+use crate::proto::h2::{ping, TrailerPolicy};
 
-type BodySender = mpsc::Sender<Result<Bytes, crate::Error>>;
+type BodySender = spsc::Sender<Result<Bytes, crate::Error>>;
 type TrailersSender = oneshot::Sender<HeaderMap>;
 
 /// A stream of `Bytes`, used when receiving bodies.
@@ -34,7 +36,7 @@ enum Kind {
     Chan {
         content_length: DecodedLength,
         want_tx: watch::Sender,
-        data_rx: mpsc::Receiver<Result<Bytes, crate::Error>>,
+        data_rx: spsc::Receiver<Result<Bytes, crate::Error>>,
         trailers_rx: oneshot::Receiver<HeaderMap>,
     },
     #[cfg(all(feature = "http2", any(feature = "client", feature = "server")))]
@@ -42,11 +44,26 @@ enum Kind {
         ping: ping::Recorder,
         content_length: DecodedLength,
         recv: h2::RecvStream,
+        trailer_policy: TrailerPolicy,
+        #[cfg(feature = "server")]
+        tracking: Option<Box<H2Tracking>>,
     },
     #[cfg(feature = "ffi")]
     Ffi(crate::ffi::UserBody),
 }
 
+/// Server bookkeeping of an HTTP/2 request body.
+#[cfg(all(feature = "http2", feature = "server"))]
+#[derive(Default)]
+struct H2Tracking {
+    /// How many more bytes may be received, if limited.
+    remaining: Option<u64>,
+    /// Notifies the stream when the limit is exceeded.
+    exceeded: Option<oneshot::Sender<()>>,
+    /// Counts the bytes received.
+    received: Option<Arc<AtomicU64>>,
+}
+
 /// A sender half created through [`Body::channel()`].
 ///
 /// Useful when wanting to stream chunks from another thread.
@@ -70,7 +87,23 @@ pub(crate) struct Sender {
 const WANT_PENDING: usize = 1;
 const WANT_READY: usize = 2;
 
+/// How many chunks a channel body buffers by default.
+///
+/// An HTTP/1 connection uses the depth from `http1_body_channel_depth` on
+/// the client or server builder instead.
+pub(crate) const DEFAULT_CHANNEL_DEPTH: usize = 1;
+
 impl Recv {
+    /// Boxes this body, to be returned along with other body types.
+    pub fn boxed(self) -> BoxBody<Bytes, crate::Error> {
+        BoxBody::new(self)
+    }
+
+    /// Boxes this body into an [`UnsyncBoxBody`].
+    pub fn boxed_unsync(self) -> UnsyncBoxBody<Bytes, crate::Error> {
+        UnsyncBoxBody::new(self)
+    }
+
     /// Create a `Body` stream with an associated sender half.
     ///
     /// Useful when wanting to stream chunks from another thread.
@@ -81,7 +114,17 @@ impl Recv {
     }
 
     pub(crate) fn new_channel(content_length: DecodedLength, wanter: bool) -> (Sender, Recv) {
-        let (data_tx, data_rx) = mpsc::channel(0);
+        Self::new_channel_with_depth(content_length, wanter, DEFAULT_CHANNEL_DEPTH)
+    }
+
+    /// Like `new_channel`, but the sender can get `depth` chunks ahead of
+    /// the receiver before `poll_ready` is pending.
+    pub(crate) fn new_channel_with_depth(
+        content_length: DecodedLength,
+        wanter: bool,
+        depth: usize,
+    ) -> (Sender, Recv) {
+        let (data_tx, data_rx) = spsc::channel(depth);
         let (trailers_tx, trailers_rx) = oneshot::channel();
 
         // If wanter is true, `Sender::poll_ready()` won't becoming ready
@@ -124,6 +167,7 @@ impl Recv {
         recv: h2::RecvStream,
         mut content_length: DecodedLength,
         ping: ping::Recorder,
+        trailer_policy: TrailerPolicy,
     ) -> Self {
         // If the stream is already EOS, then the "unknown length" is clearly
         // actually ZERO.
@@ -134,11 +178,44 @@ impl Recv {
             ping,
             content_length,
             recv,
+            trailer_policy,
+            #[cfg(feature = "server")]
+            tracking: None,
         });
 
         body
     }
 
+    /// Limit how many bytes may be received on an HTTP/2 body. The returned
+    /// receiver completes if the limit is exceeded.
+    #[cfg(all(feature = "http2", feature = "server"))]
+    pub(crate) fn h2_limit(&mut self, max: u64) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        if let Some(tracking) = self.h2_tracking() {
+            tracking.remaining = Some(max);
+            tracking.exceeded = Some(tx);
+        }
+        rx
+    }
+
+    /// Add the length of everything received on an HTTP/2 body to `counter`.
+    #[cfg(all(feature = "http2", feature = "server"))]
+    pub(crate) fn h2_count_received(&mut self, counter: Arc<AtomicU64>) {
+        if let Some(tracking) = self.h2_tracking() {
+            tracking.received = Some(counter);
+        }
+    }
+
+    #[cfg(all(feature = "http2", feature = "server"))]
+    fn h2_tracking(&mut self) -> Option<&mut H2Tracking> {
+        match self.kind {
+            Kind::H2 {
+                ref mut tracking, ..
+            } => Some(tracking.get_or_insert_with(Default::default)),
+            _ => None,
+        }
+    }
+
     #[cfg(feature = "ffi")]
     pub(crate) fn as_ffi_mut(&mut self) -> &mut crate::ffi::UserBody {
         match self.kind {
@@ -165,7 +242,7 @@ impl Recv {
             } => {
                 want_tx.send(WANT_READY);
 
-                match ready!(Pin::new(data_rx).poll_next(cx)?) {
+                match ready!(data_rx.poll_recv(cx)).transpose()? {
                     Some(chunk) => {
                         len.sub_if(chunk.len() as u64);
                         Poll::Ready(Some(Ok(chunk)))
@@ -178,9 +255,32 @@ impl Recv {
                 ref ping,
                 recv: ref mut h2,
                 content_length: ref mut len,
+                #[cfg(feature = "server")]
+                ref mut tracking,
+                ..
             } => match ready!(h2.poll_data(cx)) {
                 Some(Ok(bytes)) => {
                     let _ = h2.flow_control().release_capacity(bytes.len());
+                    crate::common::trace::body_chunk("read", bytes.len());
+                    #[cfg(feature = "server")]
+                    if let Some(ref mut tracking) = *tracking {
+                        if let Some(ref received) = tracking.received {
+                            received.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                        }
+                        if let Some(ref mut remaining) = tracking.remaining {
+                            match remaining.checked_sub(bytes.len() as u64) {
+                                Some(rem) => *remaining = rem,
+                                None => {
+                                    if let Some(tx) = tracking.exceeded.take() {
+                                        let _ = tx.send(());
+                                    }
+                                    return Poll::Ready(Some(Err(
+                                        crate::Error::new_body_too_large(),
+                                    )));
+                                }
+                            }
+                        }
+                    }
                     len.sub_if(bytes.len() as u64);
                     ping.record_data(bytes.len());
                     Poll::Ready(Some(Ok(bytes)))
@@ -216,10 +316,16 @@ impl Body for Recv {
             Kind::H2 {
                 recv: ref mut h2,
                 ref ping,
+                trailer_policy,
                 ..
             } => match ready!(h2.poll_trailers(cx)) {
-                Ok(t) => {
+                Ok(mut t) => {
                     ping.record_non_data();
+                    if let Some(ref mut trailers) = t {
+                        trailer_policy
+                            .check(trailers)
+                            .map_err(crate::Error::new_trailer_forbidden)?;
+                    }
                     Poll::Ready(Ok(t))
                 }
                 Err(e) => Poll::Ready(Err(crate::Error::new_h2(e))),
@@ -330,6 +436,18 @@ impl Sender {
         tx.send(trailers).map_err(|_| crate::Error::new_closed())
     }
 
+    /// Try to send trailers on trailers channel.
+    ///
+    /// Returns `Err(HeaderMap)` if trailers were already sent, or the
+    /// receiver was dropped.
+    #[cfg(feature = "ffi")]
+    pub(crate) fn try_send_trailers(&mut self, trailers: HeaderMap) -> Result<(), HeaderMap> {
+        match self.trailers_tx.take() {
+            Some(tx) => tx.send(trailers),
+            None => Err(trailers),
+        }
+    }
+
     /// Try to send data on this channel.
     ///
     /// # Errors
@@ -343,24 +461,23 @@ impl Sender {
     /// that doesn't have an async context. If in an async context, prefer
     /// `send_data()` instead.
     pub(crate) fn try_send_data(&mut self, chunk: Bytes) -> Result<(), Bytes> {
-        self.data_tx
-            .try_send(Ok(chunk))
-            .map_err(|err| err.into_inner().expect("just sent Ok"))
+        self.data_tx.try_send(Ok(chunk)).map_err(|err| match err {
+            Ok(chunk) => chunk,
+            Err(_) => unreachable!("just sent Ok"),
+        })
     }
 
     /// Aborts the body in an abnormal fashion.
     #[allow(unused)]
-    pub(crate) fn abort(self) {
-        let _ = self
-            .data_tx
-            // clone so the send works even if buffer is full
-            .clone()
-            .try_send(Err(crate::Error::new_body_write_aborted()));
+    pub(crate) fn abort(mut self) {
+        // sent last, so it works even if the buffer is full
+        self.data_tx
+            .send_last(Err(crate::Error::new_body_write_aborted()));
     }
 
     #[cfg(feature = "http1")]
     pub(crate) fn send_error(&mut self, err: crate::Error) {
-        let _ = self.data_tx.try_send(Err(err));
+        self.data_tx.send_last(Err(err));
     }
 }
 
@@ -406,7 +523,7 @@ mod tests {
 
         assert_eq!(
             mem::size_of::<Sender>(),
-            mem::size_of::<usize>() * 5,
+            mem::size_of::<usize>() * 3,
             "Sender"
         );
 
@@ -474,6 +591,24 @@ mod tests {
         assert_eq!(chunk2, "chunk 2");
     }
 
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn channel_with_depth() {
+        let (mut tx, mut rx) =
+            Recv::new_channel_with_depth(DecodedLength::CHUNKED, /*wanter =*/ false, 3);
+
+        for chunk in ["chunk 1", "chunk 2", "chunk 3"] {
+            tx.try_send_data(chunk.into()).expect(chunk);
+        }
+        tx.try_send_data("chunk 4".into()).expect_err("send 4");
+        drop(tx);
+
+        for chunk in ["chunk 1", "chunk 2", "chunk 3"] {
+            assert_eq!(rx.data().await.unwrap().unwrap(), chunk);
+        }
+        assert!(rx.data().await.is_none());
+    }
+
     #[cfg(not(miri))]
     #[tokio::test]
     async fn channel_empty() {
@@ -0,0 +1,128 @@
+use std::error::Error as StdError;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+pin_project! {
+    /// A [`Body`] that is one of two body types.
+    ///
+    /// Handlers that branch between two bodies, such as a streamed file and an
+    /// in-memory error page, can return this instead of boxing them. Both must
+    /// have the same data type, and errors of either are boxed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use bytes::Bytes;
+    /// use http_body_util::Full;
+    /// use hyper::body::Either;
+    /// use hyper::{Recv, Request, Response};
+    ///
+    /// fn echo_or_hello(req: Request<Recv>) -> Response<Either<Recv, Full<Bytes>>> {
+    ///     if req.uri().path() == "/echo" {
+    ///         Response::new(Either::Left {
+    ///             body: req.into_body(),
+    ///         })
+    ///     } else {
+    ///         Response::new(Either::Right {
+    ///             body: Full::new(Bytes::from_static(b"hello")),
+    ///         })
+    ///     }
+    /// }
+    /// ```
+    #[derive(Clone, Copy, Debug)]
+    // `pin_project!` doesn't allow documenting the fields, so the variants
+    // describe them instead.
+    #[allow(missing_docs)]
+    #[project = EitherProj]
+    pub enum Either<L, R> {
+        /// The first body type, in `body`.
+        Left {
+            #[pin]
+            body: L,
+        },
+        /// The second body type, in `body`.
+        Right {
+            #[pin]
+            body: R,
+        },
+    }
+}
+
+impl<L, R> Body for Either<L, R>
+where
+    L: Body,
+    R: Body<Data = L::Data>,
+    L::Error: Into<BoxError>,
+    R::Error: Into<BoxError>,
+{
+    type Data = L::Data;
+    type Error = BoxError;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match self.project() {
+            EitherProj::Left { body } => body
+                .poll_data(cx)
+                .map(|opt| opt.map(|r| r.map_err(Into::into))),
+            EitherProj::Right { body } => body
+                .poll_data(cx)
+                .map(|opt| opt.map(|r| r.map_err(Into::into))),
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match self.project() {
+            EitherProj::Left { body } => body.poll_trailers(cx).map_err(Into::into),
+            EitherProj::Right { body } => body.poll_trailers(cx).map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            Either::Left { body } => body.is_end_stream(),
+            Either::Right { body } => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            Either::Left { body } => body.size_hint(),
+            Either::Right { body } => body.size_hint(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body::Body;
+    use http_body_util::Full;
+
+    use super::Either;
+
+    #[tokio::test]
+    async fn either_polls_its_body() {
+        let body: Either<Full<Bytes>, crate::Recv> = Either::Left {
+            body: Full::new(Bytes::from("left")),
+        };
+        assert_eq!(body.size_hint().exact(), Some(4));
+        let bytes = crate::body::to_bytes(body).await.unwrap();
+        assert_eq!(bytes, "left");
+
+        let body: Either<Full<Bytes>, crate::Recv> = Either::Right {
+            body: crate::Recv::empty(),
+        };
+        assert!(body.is_end_stream());
+    }
+}
@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+
+use super::BoxBody;
+
+/// A [`Body`] of a single chunk of bytes.
+///
+/// # Example
+///
+/// ```
+/// use hyper::body::Full;
+/// use hyper::Response;
+///
+/// let response = Response::new(Full::from("Hello World"));
+/// # drop(response);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Full {
+    data: Option<Bytes>,
+}
+
+impl Full {
+    /// Creates a body of `data`.
+    pub fn new(data: impl Into<Bytes>) -> Full {
+        let data = data.into();
+        Full {
+            data: if data.is_empty() { None } else { Some(data) },
+        }
+    }
+
+    /// Boxes this body, to be returned along with other body types.
+    pub fn boxed(self) -> BoxBody<Bytes, Infallible> {
+        BoxBody::new(self)
+    }
+}
+
+impl Body for Full {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.data.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.data.as_ref().map_or(0, |data| data.len() as u64))
+    }
+}
+
+impl From<Bytes> for Full {
+    fn from(data: Bytes) -> Full {
+        Full::new(data)
+    }
+}
+
+impl From<Vec<u8>> for Full {
+    fn from(data: Vec<u8>) -> Full {
+        Full::new(data)
+    }
+}
+
+impl From<&'static [u8]> for Full {
+    fn from(data: &'static [u8]) -> Full {
+        Full::new(data)
+    }
+}
+
+impl From<String> for Full {
+    fn from(data: String) -> Full {
+        Full::new(data)
+    }
+}
+
+impl From<&'static str> for Full {
+    fn from(data: &'static str) -> Full {
+        Full::new(data)
+    }
+}
+
+impl From<Cow<'static, str>> for Full {
+    fn from(data: Cow<'static, str>) -> Full {
+        match data {
+            Cow::Borrowed(data) => Full::from(data),
+            Cow::Owned(data) => Full::from(data),
+        }
+    }
+}
+
+/// A [`Body`] with no bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Empty {
+    _priv: (),
+}
+
+impl Empty {
+    /// Creates an empty body.
+    pub fn new() -> Empty {
+        Empty { _priv: () }
+    }
+
+    /// Boxes this body, to be returned along with other body types.
+    pub fn boxed(self) -> BoxBody<Bytes, Infallible> {
+        BoxBody::new(self)
+    }
+}
+
+impl Body for Empty {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(None)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        true
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body::Body;
+
+    use super::{Empty, Full};
+
+    #[tokio::test]
+    async fn full_and_empty() {
+        let full = Full::from("hello");
+        assert_eq!(full.size_hint().exact(), Some(5));
+        assert!(!full.is_end_stream());
+        let bytes = crate::body::to_bytes(full).await.unwrap();
+        assert_eq!(bytes, "hello");
+
+        assert!(Full::from("").is_end_stream());
+
+        let empty = Empty::new();
+        assert_eq!(empty.size_hint().exact(), Some(0));
+        assert!(empty.is_end_stream());
+    }
+}
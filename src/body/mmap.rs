@@ -0,0 +1,241 @@
+//! A body serving a memory-mapped file, with the `mmap` feature.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+
+use super::BoxBody;
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A body of a memory-mapped region of a file.
+///
+/// The file isn't read with a syscall per chunk. Instead, each chunk is a
+/// `Bytes` borrowing the mapped memory, and its pages are only read from
+/// the file when the connection writes them. This suits serving static
+/// content, such as files that are sent many times.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn example() -> std::io::Result<()> {
+/// use std::fs::File;
+///
+/// use hyper::body::{Mmap, MmapAdvice};
+/// use hyper::Response;
+///
+/// let file = File::open("index.html")?;
+/// // SAFETY: the file is never modified while served.
+/// let body = unsafe { Mmap::map(&file)? }.chunk_size(256 * 1024);
+/// body.advise(MmapAdvice::Sequential)?;
+/// let response = Response::new(body);
+/// # drop(response);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Mmap {
+    map: Arc<memmap2::Mmap>,
+    remaining: Bytes,
+    chunk_size: usize,
+}
+
+/// How the pages of a [`Mmap`] are expected to be read, to hint the kernel.
+///
+/// On platforms other than Unix, these hints are ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MmapAdvice {
+    /// No particular pattern, the default.
+    Normal,
+    /// In order, so pages can be read ahead aggressively.
+    Sequential,
+    /// In no order, so read ahead is of little use.
+    Random,
+    /// Soon, so pages should be read ahead now.
+    WillNeed,
+}
+
+/// Keeps the mapping alive while `Bytes` borrow it.
+struct Region(Arc<memmap2::Mmap>);
+
+impl AsRef<[u8]> for Region {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Mmap {
+    /// Maps the whole `file`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other
+    /// process, until the body and every chunk it returned are dropped.
+    /// Otherwise the bytes sent can change, or reading them can crash the
+    /// process.
+    pub unsafe fn map(file: &File) -> io::Result<Mmap> {
+        Mmap::new(memmap2::Mmap::map(file)?)
+    }
+
+    /// Maps `len` bytes of `file`, starting at `offset`.
+    ///
+    /// The offset doesn't need to be aligned to a page.
+    ///
+    /// # Safety
+    ///
+    /// The same as for [`Mmap::map`].
+    pub unsafe fn map_range(file: &File, offset: u64, len: usize) -> io::Result<Mmap> {
+        Mmap::new(
+            memmap2::MmapOptions::new()
+                .offset(offset)
+                .len(len)
+                .map(file)?,
+        )
+    }
+
+    fn new(map: memmap2::Mmap) -> io::Result<Mmap> {
+        let map = Arc::new(map);
+        Ok(Mmap {
+            remaining: Bytes::from_owner(Region(map.clone())),
+            map,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the most bytes returned in a single chunk.
+    ///
+    /// Default is 64KiB.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `size` is 0.
+    pub fn chunk_size(mut self, size: usize) -> Mmap {
+        assert!(size != 0, "Mmap chunk_size must be at least 1");
+        self.chunk_size = size;
+        self
+    }
+
+    /// Hints to the kernel how the mapped pages will be read.
+    pub fn advise(&self, advice: MmapAdvice) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            let advice = match advice {
+                MmapAdvice::Normal => memmap2::Advice::Normal,
+                MmapAdvice::Sequential => memmap2::Advice::Sequential,
+                MmapAdvice::Random => memmap2::Advice::Random,
+                MmapAdvice::WillNeed => memmap2::Advice::WillNeed,
+            };
+            self.map.advise(advice)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = advice;
+            Ok(())
+        }
+    }
+
+    /// The number of bytes not yet returned.
+    pub fn remaining(&self) -> usize {
+        self.remaining.len()
+    }
+
+    /// Boxes this body, to be returned along with other body types.
+    pub fn boxed(self) -> BoxBody<Bytes, Infallible> {
+        BoxBody::new(self)
+    }
+}
+
+impl fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mmap")
+            .field("len", &self.map.len())
+            .field("remaining", &self.remaining.len())
+            .field("chunk_size", &self.chunk_size)
+            .finish()
+    }
+}
+
+impl Body for Mmap {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.remaining.is_empty() {
+            return Poll::Ready(None);
+        }
+        let len = std::cmp::min(self.chunk_size, self.remaining.len());
+        Poll::Ready(Some(Ok(self.remaining.split_to(len))))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.remaining.is_empty()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining.len() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn file(name: &str, contents: &[u8]) -> File {
+        let path = std::env::temp_dir().join(format!("hyper-mmap-{}-{}", std::process::id(), name));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(contents).unwrap();
+        let file = File::open(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        file
+    }
+
+    async fn chunks(mut body: Mmap) -> Vec<Bytes> {
+        let mut chunks = Vec::new();
+        while let Some(chunk) =
+            futures_util::future::poll_fn(|cx| Pin::new(&mut body).poll_data(cx)).await
+        {
+            chunks.push(chunk.unwrap());
+        }
+        assert!(body.is_end_stream());
+        chunks
+    }
+
+    #[tokio::test]
+    async fn chunks_of_chunk_size() {
+        let file = file("chunks", b"hello mmap");
+        let body = unsafe { Mmap::map(&file) }.unwrap().chunk_size(4);
+        assert_eq!(body.size_hint().exact(), Some(10));
+        body.advise(MmapAdvice::Sequential).unwrap();
+
+        assert_eq!(chunks(body).await, ["hell", "o mm", "ap"]);
+    }
+
+    #[tokio::test]
+    async fn range() {
+        let file = file("range", b"hello mmap");
+        let body = unsafe { Mmap::map_range(&file, 6, 4) }.unwrap();
+        assert_eq!(body.remaining(), 4);
+
+        assert_eq!(chunks(body).await, ["mmap"]);
+    }
+}
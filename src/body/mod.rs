@@ -14,20 +14,50 @@
 //!   `Body`, and returned by hyper as a "receive stream" (so, for server
 //!   requests and client responses). It is also a decent default implementation
 //!   if you don't have very custom needs of your send streams.
+//!
+//! Handlers that return different body types can box them into a
+//! [`BoxBody`](BoxBody), or an [`UnsyncBoxBody`](UnsyncBoxBody) for bodies
+//! that aren't `Sync`. Handlers that return one of two body types can
+//! return an [`Either`] instead.
+//!
+//! For bodies known up front, [`Full`](Full) sends a single chunk of bytes,
+//! and [`Empty`](Empty) sends none.
+//!
+//! ## Flushing
+//!
+//! HTTP/1 connections buffer the chunks of a body that is ready to send
+//! more, and write them once the buffer is full or the body is pending.
+//! A response body that streams events, such as for long-polling or
+//! server-sent events, can have each event written right away with a
+//! [`Flusher`](crate::ext::Flusher). HTTP/2 connections send data as soon
+//! as the stream can.
 
 pub use bytes::{Buf, Bytes};
 pub use http_body::Body;
 pub use http_body::SizeHint;
+// The same types `BodyExt::boxed` returns, so the `boxed` methods on hyper's
+// bodies don't change what existing callers get.
+pub use http_body_util::combinators::{BoxBody, UnsyncBoxBody};
 
 pub use self::aggregate::aggregate;
 pub use self::body::Recv;
-pub(crate) use self::body::Sender;
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+pub(crate) use self::body::{Sender, DEFAULT_CHANNEL_DEPTH};
+pub use self::either::Either;
+pub use self::full::{Empty, Full};
 pub(crate) use self::length::DecodedLength;
+#[cfg(feature = "mmap")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mmap")))]
+pub use self::mmap::{Mmap, MmapAdvice};
 pub use self::to_bytes::to_bytes;
 
 mod aggregate;
 mod body;
+mod either;
+mod full;
 mod length;
+#[cfg(feature = "mmap")]
+mod mmap;
 mod to_bytes;
 
 fn _assert_send_sync() {
@@ -0,0 +1,143 @@
+//! A pool of connection buffers, shared across HTTP/1 connections.
+//!
+//! Each HTTP/1 connection allocates a buffer to read into, and one to write
+//! message heads into, and frees them when it closes. A server handling many
+//! short-lived connections spends a fair share of its time in the allocator
+//! doing so. A [`BufferPool`] installed on a builder, such as with
+//! [`server::conn::Http::http1_buffer_pool`] or
+//! [`client::conn::http1::Builder::buffer_pool`], keeps the buffers of
+//! closed connections to reuse for new ones instead.
+//!
+//! HTTP/2 connections don't use the pool. Their send buffers are allocated
+//! inside the `h2` crate, which has no way to be given buffers from outside.
+//!
+//! # Example
+//!
+//! ```
+//! use hyper::buffer::BufferPool;
+//! use hyper::server::conn::Http;
+//!
+//! // Keep up to 256 idle buffers of each kind.
+//! let pool = BufferPool::new(256);
+//! let mut http = Http::new();
+//! http.http1_buffer_pool(pool);
+//! ```
+//!
+//! [`server::conn::Http::http1_buffer_pool`]: crate::server::conn::Http::http1_buffer_pool
+//! [`client::conn::http1::Builder::buffer_pool`]: crate::client::conn::http1::Builder::buffer_pool
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use bytes::BytesMut;
+
+/// Buffers bigger than this are freed instead of kept, so that one large
+/// message doesn't pin its memory in the pool.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+/// Keeps the buffers of closed HTTP/1 connections to reuse for new ones.
+///
+/// Clones share the same buffers, so a pool can be installed on several
+/// builders.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Pool>,
+}
+
+struct Pool {
+    max_idle: usize,
+    idle: Mutex<Idle>,
+}
+
+#[derive(Default)]
+struct Idle {
+    read: Vec<BytesMut>,
+    write: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// Keep up to `max_idle` buffers of each kind, read and write, until
+    /// they are reused.
+    pub fn new(max_idle: usize) -> BufferPool {
+        BufferPool {
+            inner: Arc::new(Pool {
+                max_idle,
+                idle: Mutex::new(Idle::default()),
+            }),
+        }
+    }
+
+    /// The number of buffers kept, waiting to be reused.
+    pub fn idle(&self) -> usize {
+        let idle = self.inner.idle.lock().unwrap();
+        idle.read.len() + idle.write.len()
+    }
+
+    pub(crate) fn take_read(&self) -> Option<BytesMut> {
+        self.inner.idle.lock().unwrap().read.pop()
+    }
+
+    pub(crate) fn take_write(&self) -> Option<Vec<u8>> {
+        self.inner.idle.lock().unwrap().write.pop()
+    }
+
+    pub(crate) fn put_read(&self, mut buf: BytesMut) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut idle = self.inner.idle.lock().unwrap();
+        if idle.read.len() < self.inner.max_idle {
+            idle.read.push(buf);
+        }
+    }
+
+    pub(crate) fn put_write(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut idle = self.inner.idle.lock().unwrap();
+        if idle.write.len() < self.inner.max_idle {
+            idle.write.push(buf);
+        }
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("max_idle", &self.inner.max_idle)
+            .field("idle", &self.idle())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_up_to_max_idle() {
+        let pool = BufferPool::new(1);
+        pool.put_write(Vec::with_capacity(16));
+        pool.put_write(Vec::with_capacity(16));
+        pool.put_read(BytesMut::with_capacity(16));
+        assert_eq!(pool.idle(), 2);
+
+        let buf = pool.take_write().expect("pooled write buffer");
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 16);
+        assert!(pool.take_write().is_none());
+        assert!(pool.take_read().is_some());
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn frees_empty_and_large_buffers() {
+        let pool = BufferPool::new(8);
+        pool.put_write(Vec::new());
+        pool.put_read(BytesMut::with_capacity(MAX_POOLED_CAPACITY + 1));
+        assert_eq!(pool.idle(), 0);
+    }
+}
@@ -0,0 +1,306 @@
+//! Capturing the raw bytes of connections, with the `debug` feature.
+//!
+//! When TLS is terminated in the process, capturing packets outside of it
+//! shows nothing useful. A [`Capture`] installed on a connection builder,
+//! such as with [`client::conn::http1::Builder::capture`] or
+//! [`server::conn::Http::capture`], is given a copy of every byte read from
+//! and written to the transport instead.
+//!
+//! To keep the cost down, [`Sampled`] only captures some of the connections,
+//! and [`RingBuffer`] only keeps the most recent bytes in memory.
+//!
+//! # Example
+//!
+//! ```
+//! use hyper::capture::{RingBuffer, Sampled};
+//! use hyper::server::conn::Http;
+//!
+//! // Keep the last 64KiB of one in every 100 connections.
+//! let ring = RingBuffer::new(64 * 1024);
+//! let mut http = Http::new();
+//! http.capture(Sampled::new(ring.clone(), 100));
+//!
+//! // Later, such as when a request fails:
+//! for chunk in ring.snapshot() {
+//!     println!("{} {:?} {:?}", chunk.id(), chunk.direction(), chunk.bytes());
+//! }
+//! ```
+//!
+//! [`client::conn::http1::Builder::capture`]: crate::client::conn::http1::Builder::capture
+//! [`server::conn::Http::capture`]: crate::server::conn::Http::capture
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+
+/// Receives copies of the bytes of connections.
+///
+/// It is called from the tasks driving the connections, and so should be
+/// cheap, such as by copying the bytes to a buffer.
+pub trait Capture: Send + Sync {
+    /// Whether to capture the connection `id`, which just started.
+    ///
+    /// Default is to capture every connection.
+    fn sample(&self, _id: ConnectionId) -> bool {
+        true
+    }
+
+    /// `bytes` were read from, or written to, the transport of the
+    /// connection `id`.
+    fn bytes(&self, id: ConnectionId, direction: Direction, bytes: &[u8]);
+}
+
+/// Identifies a captured connection, unique within the process.
+///
+/// A server connection that switches to HTTP/2 after detecting the client
+/// connection preface gets a new ID, and is captured again from the
+/// preface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    /// The ID as a number.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// Whether bytes were read or written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Read from the transport.
+    Read,
+    /// Written to the transport.
+    Write,
+}
+
+/// Captures one in every `n` connections.
+#[derive(Debug)]
+pub struct Sampled<C> {
+    inner: C,
+    one_in: u64,
+}
+
+impl<C> Sampled<C> {
+    /// Capture one in every `one_in` connections with `inner`.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `one_in` is 0.
+    pub fn new(inner: C, one_in: u64) -> Sampled<C> {
+        assert!(one_in != 0, "Sampled one_in must be at least 1");
+        Sampled { inner, one_in }
+    }
+}
+
+impl<C: Capture> Capture for Sampled<C> {
+    fn sample(&self, id: ConnectionId) -> bool {
+        id.0 % self.one_in == 0 && self.inner.sample(id)
+    }
+
+    fn bytes(&self, id: ConnectionId, direction: Direction, bytes: &[u8]) {
+        self.inner.bytes(id, direction, bytes);
+    }
+}
+
+/// Keeps the most recently captured bytes in memory, up to a capacity.
+///
+/// Clones share the same buffer, so one can be installed on a builder, and
+/// another used to take a [`snapshot`](RingBuffer::snapshot).
+#[derive(Clone)]
+pub struct RingBuffer {
+    ring: Arc<Mutex<Ring>>,
+}
+
+struct Ring {
+    capacity: usize,
+    len: usize,
+    chunks: VecDeque<Chunk>,
+}
+
+/// Bytes captured at once, kept by a [`RingBuffer`].
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    id: ConnectionId,
+    direction: Direction,
+    bytes: Bytes,
+}
+
+impl RingBuffer {
+    /// Keep up to `capacity` bytes, dropping the oldest first.
+    pub fn new(capacity: usize) -> RingBuffer {
+        RingBuffer {
+            ring: Arc::new(Mutex::new(Ring {
+                capacity,
+                len: 0,
+                chunks: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// The bytes kept, oldest first.
+    pub fn snapshot(&self) -> Vec<Chunk> {
+        self.ring.lock().unwrap().chunks.iter().cloned().collect()
+    }
+
+    /// Drop all the bytes kept.
+    pub fn clear(&self) {
+        let mut ring = self.ring.lock().unwrap();
+        ring.chunks.clear();
+        ring.len = 0;
+    }
+}
+
+impl Capture for RingBuffer {
+    fn bytes(&self, id: ConnectionId, direction: Direction, bytes: &[u8]) {
+        let mut ring = self.ring.lock().unwrap();
+        // Only the end of a chunk bigger than the whole buffer fits.
+        let bytes = &bytes[bytes.len().saturating_sub(ring.capacity)..];
+        if bytes.is_empty() {
+            return;
+        }
+        while ring.len + bytes.len() > ring.capacity {
+            let oldest = ring.chunks.pop_front().expect("len counts the chunks");
+            ring.len -= oldest.bytes.len();
+        }
+        ring.len += bytes.len();
+        ring.chunks.push_back(Chunk {
+            id,
+            direction,
+            bytes: Bytes::copy_from_slice(bytes),
+        });
+    }
+}
+
+impl fmt::Debug for RingBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ring = self.ring.lock().unwrap();
+        f.debug_struct("RingBuffer")
+            .field("capacity", &ring.capacity)
+            .field("len", &ring.len)
+            .finish()
+    }
+}
+
+impl Chunk {
+    /// The connection the bytes were captured on.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// Whether the bytes were read or written.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// The bytes.
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+/// A `Capture` installed on a builder.
+#[derive(Clone)]
+pub(crate) struct CaptureHandle(Arc<dyn Capture>);
+
+impl CaptureHandle {
+    pub(crate) fn new<C: Capture + 'static>(capture: C) -> CaptureHandle {
+        CaptureHandle(Arc::new(capture))
+    }
+
+    /// A connection started, returns how to capture it, if sampled.
+    pub(crate) fn start(&self) -> Option<ConnCapture> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        let id = ConnectionId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        if self.0.sample(id) {
+            Some(ConnCapture {
+                id,
+                capture: self.0.clone(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Debug for CaptureHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Capture").finish()
+    }
+}
+
+/// The capture of a single connection.
+pub(crate) struct ConnCapture {
+    id: ConnectionId,
+    capture: Arc<dyn Capture>,
+}
+
+impl ConnCapture {
+    pub(crate) fn read(&self, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            self.capture.bytes(self.id, Direction::Read, bytes);
+        }
+    }
+
+    pub(crate) fn wrote(&self, bytes: &[u8]) {
+        if !bytes.is_empty() {
+            self.capture.bytes(self.id, Direction::Write, bytes);
+        }
+    }
+}
+
+impl fmt::Debug for ConnCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ConnCapture").field(&self.id).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kept(ring: &RingBuffer) -> Vec<u8> {
+        ring.snapshot()
+            .iter()
+            .flat_map(|chunk| chunk.bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let ring = RingBuffer::new(8);
+        let id = ConnectionId(0);
+        ring.bytes(id, Direction::Write, b"abc");
+        ring.bytes(id, Direction::Read, b"defg");
+        assert_eq!(kept(&ring), b"abcdefg");
+
+        ring.bytes(id, Direction::Write, b"hi");
+        assert_eq!(kept(&ring), b"defghi");
+        assert_eq!(ring.snapshot()[0].direction(), Direction::Read);
+
+        ring.bytes(id, Direction::Write, b"0123456789");
+        assert_eq!(kept(&ring), b"23456789");
+
+        ring.clear();
+        assert!(ring.snapshot().is_empty());
+    }
+
+    #[test]
+    fn sampled() {
+        let sampled = Sampled::new(RingBuffer::new(8), 3);
+        let captured = (0..9)
+            .filter(|&n| sampled.sample(ConnectionId(n)))
+            .collect::<Vec<_>>();
+        assert_eq!(captured, [0, 3, 6]);
+    }
+}
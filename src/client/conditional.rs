@@ -0,0 +1,250 @@
+//! Conditional requests and revalidation.
+//!
+//! These are building blocks for HTTP caches. A [`CachedResponse`] stores a
+//! previously received response along with its full body. It can add the
+//! matching validators to a new request, making it conditional, and then
+//! interpret the server's answer: a `304 Not Modified` is turned back into
+//! the stored response, while any other response is passed through.
+//!
+//! Deciding whether a response may be stored, and for how long, is left to
+//! the cache itself.
+
+use bytes::Bytes;
+use http::header::{
+    HeaderValue, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    TRANSFER_ENCODING,
+};
+use http::{HeaderMap, Request, Response, StatusCode, Version};
+
+/// The validators of a response, used to make a request conditional.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+}
+
+/// A stored response, which can be re-materialized after revalidation.
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// The outcome of revalidating a [`CachedResponse`].
+#[derive(Debug)]
+pub enum Revalidated<B> {
+    /// The server answered `304 Not Modified`, so this is the stored
+    /// response, with its headers updated from the `304`.
+    NotModified(Response<Bytes>),
+    /// The server sent a different response, which should replace the
+    /// stored one.
+    Modified(Response<B>),
+}
+
+// ===== impl Validators =====
+
+impl Validators {
+    /// Extract the `ETag` and `Last-Modified` validators from some headers.
+    pub fn from_headers(headers: &HeaderMap) -> Validators {
+        Validators {
+            etag: headers.get(ETAG).cloned(),
+            last_modified: headers.get(LAST_MODIFIED).cloned(),
+        }
+    }
+
+    /// The `ETag`, if there was one.
+    pub fn etag(&self) -> Option<&HeaderValue> {
+        self.etag.as_ref()
+    }
+
+    /// The `Last-Modified` date, if there was one.
+    pub fn last_modified(&self) -> Option<&HeaderValue> {
+        self.last_modified.as_ref()
+    }
+
+    /// Returns true if there are no validators, so a request can't be made
+    /// conditional.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Make a request conditional on these validators.
+    ///
+    /// This sets `If-None-Match` from the `ETag`, and `If-Modified-Since`
+    /// from the `Last-Modified` date. Conditional headers already present on
+    /// the request are left as-is.
+    pub fn apply<B>(&self, req: &mut Request<B>) {
+        let headers = req.headers_mut();
+        if let Some(ref etag) = self.etag {
+            headers.entry(IF_NONE_MATCH).or_insert_with(|| etag.clone());
+        }
+        if let Some(ref date) = self.last_modified {
+            headers
+                .entry(IF_MODIFIED_SINCE)
+                .or_insert_with(|| date.clone());
+        }
+    }
+}
+
+// ===== impl CachedResponse =====
+
+impl CachedResponse {
+    /// Store a response whose body has been fully received.
+    pub fn new(res: Response<Bytes>) -> CachedResponse {
+        let (parts, body) = res.into_parts();
+        CachedResponse {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+        }
+    }
+
+    /// The validators of the stored response.
+    pub fn validators(&self) -> Validators {
+        Validators::from_headers(&self.headers)
+    }
+
+    /// The headers of the stored response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The body of the stored response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Make a request conditional on the stored response's validators.
+    pub fn apply<B>(&self, req: &mut Request<B>) {
+        self.validators().apply(req);
+    }
+
+    /// Re-materialize the stored response.
+    pub fn to_response(&self) -> Response<Bytes> {
+        let mut res = Response::new(self.body.clone());
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers.clone();
+        res
+    }
+
+    /// Interpret the response to a conditional request.
+    ///
+    /// If it is a `304 Not Modified`, the headers it carries replace the
+    /// stored ones, as described in [RFC 7234 Section 4.3.4], and the stored
+    /// response is returned. Otherwise, the response is returned unchanged.
+    ///
+    /// [RFC 7234 Section 4.3.4]: https://tools.ietf.org/html/rfc7234#section-4.3.4
+    pub fn revalidate<B>(&mut self, res: Response<B>) -> Revalidated<B> {
+        if res.status() != StatusCode::NOT_MODIFIED {
+            return Revalidated::Modified(res);
+        }
+
+        let (parts, _) = res.into_parts();
+        let mut name = None;
+        for (key, value) in parts.headers {
+            // The framing of the stored body is kept, since a 304 never
+            // has a body of its own.
+            if let Some(key) = key {
+                name = if key == CONTENT_LENGTH || key == TRANSFER_ENCODING {
+                    None
+                } else {
+                    self.headers.remove(&key);
+                    Some(key)
+                };
+            }
+            if let Some(ref name) = name {
+                self.headers.append(name.clone(), value);
+            }
+        }
+
+        Revalidated::NotModified(self.to_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::header::{
+        CACHE_CONTROL, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    };
+    use http::{Request, Response, StatusCode};
+
+    use super::{CachedResponse, Revalidated, Validators};
+
+    fn cached() -> CachedResponse {
+        let res = Response::builder()
+            .header(ETAG, "\"abc\"")
+            .header(LAST_MODIFIED, "Wed, 21 Oct 2015 07:28:00 GMT")
+            .header(CACHE_CONTROL, "max-age=60")
+            .header(CONTENT_LENGTH, "5")
+            .body(Bytes::from_static(b"hello"))
+            .unwrap();
+        CachedResponse::new(res)
+    }
+
+    #[test]
+    fn apply_sets_conditional_headers() {
+        let mut req = Request::new(());
+        cached().apply(&mut req);
+        assert_eq!(req.headers()[IF_NONE_MATCH], "\"abc\"");
+        assert_eq!(
+            req.headers()[IF_MODIFIED_SINCE],
+            "Wed, 21 Oct 2015 07:28:00 GMT"
+        );
+
+        let mut req = Request::builder()
+            .header(IF_NONE_MATCH, "\"xyz\"")
+            .body(())
+            .unwrap();
+        cached().apply(&mut req);
+        assert_eq!(req.headers()[IF_NONE_MATCH], "\"xyz\"");
+    }
+
+    #[test]
+    fn empty_validators() {
+        let validators = Validators::default();
+        assert!(validators.is_empty());
+
+        let mut req = Request::new(());
+        validators.apply(&mut req);
+        assert!(req.headers().is_empty());
+    }
+
+    #[test]
+    fn not_modified_rematerializes_body() {
+        let mut cache = cached();
+        let res = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(CACHE_CONTROL, "max-age=120")
+            .header(CONTENT_LENGTH, "0")
+            .body(())
+            .unwrap();
+
+        match cache.revalidate(res) {
+            Revalidated::NotModified(res) => {
+                assert_eq!(res.status(), StatusCode::OK);
+                assert_eq!(res.headers()[CACHE_CONTROL], "max-age=120");
+                assert_eq!(res.headers()[CONTENT_LENGTH], "5");
+                assert_eq!(res.body(), "hello");
+            }
+            Revalidated::Modified(_) => panic!("expected NotModified"),
+        }
+        assert_eq!(cache.headers()[CACHE_CONTROL], "max-age=120");
+    }
+
+    #[test]
+    fn modified_passes_through() {
+        let mut cache = cached();
+        let res = Response::builder().body("new").unwrap();
+
+        match cache.revalidate(res) {
+            Revalidated::Modified(res) => assert_eq!(*res.body(), "new"),
+            Revalidated::NotModified(_) => panic!("expected Modified"),
+        }
+        assert_eq!(cache.body(), "hello");
+    }
+}
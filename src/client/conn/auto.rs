@@ -0,0 +1,340 @@
+//! Client connections that can be either HTTP/1 or HTTP/2.
+//!
+//! The [`Builder`] in this module picks the protocol of each connection
+//! from the [`VersionPolicy`] of the request it is made for:
+//!
+//! - [`VersionPolicy::Http1Only`] always starts HTTP/1.
+//! - [`VersionPolicy::Http2PriorKnowledge`] always starts HTTP/2, without
+//!   negotiating it first.
+//! - [`VersionPolicy::Negotiated`] uses the protocol the TLS connector
+//!   negotiated with ALPN: HTTP/2 for `h2`, and HTTP/1 otherwise.
+//!
+//! The returned [`SendRequest`] only sends requests whose policy the
+//! connection was established for, so requests forcing a version never
+//! silently use another one.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # async fn connect_tls() -> std::io::Result<(hyper::upgrade::Upgraded, Option<Vec<u8>>)> {
+//! #     unimplemented!()
+//! # }
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use bytes::Bytes;
+//! use http_body_util::Empty;
+//! use hyper::client::conn::auto;
+//! use hyper::ext::VersionPolicy;
+//! use hyper::Request;
+//!
+//! let req = Request::get("https://hyper.rs/").body(Empty::<Bytes>::new())?;
+//! let policy = req
+//!     .extensions()
+//!     .get::<VersionPolicy>()
+//!     .copied()
+//!     .unwrap_or(VersionPolicy::Negotiated);
+//!
+//! // Any IO implementing `hyper::rt::Read` and `hyper::rt::Write`, along
+//! // with the protocol its TLS handshake negotiated with ALPN, if any.
+//! let (stream, alpn) = connect_tls().await?;
+//! let (mut sender, conn) = auto::Builder::new()
+//!     .handshake(stream, policy, alpn.as_deref())
+//!     .await?;
+//! tokio::spawn(async move {
+//!     if let Err(err) = conn.await {
+//!         eprintln!("client connection error: {}", err);
+//!     }
+//! });
+//!
+//! let res = sender.send_request(req).await?;
+//! # drop(res);
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use futures_util::future::Either;
+use http::{Request, Response, Version};
+use pin_project_lite::pin_project;
+
+use super::{http1, http2};
+use crate::body::Body;
+use crate::common::exec::{Exec, H2ClientConnExec, H2ClientConnTaskExec};
+use crate::common::{task, Future, Pin, Poll};
+use crate::ext::VersionPolicy;
+use crate::rt::{Read, Write};
+use crate::Recv;
+
+/// A builder for client connections that can be either HTTP/1 or HTTP/2.
+///
+/// The options of each version are set on the wrapped [`http1::Builder`]
+/// and [`http2::Builder`].
+#[derive(Clone, Debug)]
+pub struct Builder<E = Exec> {
+    http1: http1::Builder,
+    http2: http2::Builder<E>,
+}
+
+/// The sender side of an established connection.
+pub struct SendRequest<B> {
+    inner: Sender<B>,
+    policy: VersionPolicy,
+}
+
+enum Sender<B> {
+    Http1(http1::SendRequest<B>),
+    Http2(http2::SendRequest<B>),
+}
+
+pin_project! {
+    /// A future that processes all HTTP state for the IO object.
+    ///
+    /// In most cases, this should just be spawned into an executor, so that
+    /// it can process incoming and outgoing messages, notice hangups, and the
+    /// like.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct Connection<T, B, E = Exec>
+    where
+        T: Read,
+        T: Write,
+        T: Unpin,
+        T: Send,
+        T: 'static,
+        B: Body,
+        B: 'static,
+    {
+        #[pin]
+        inner: ConnInner<T, B, E>,
+    }
+}
+
+pin_project! {
+    #[project = ConnInnerProj]
+    enum ConnInner<T, B, E>
+    where
+        T: Read,
+        T: Write,
+        T: Unpin,
+        T: Send,
+        T: 'static,
+        B: Body,
+        B: 'static,
+    {
+        Http1 {
+            #[pin]
+            conn: http1::Connection<T, B>,
+        },
+        Http2 {
+            #[pin]
+            conn: http2::Connection<T, B, E>,
+        },
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Creates a new connection builder.
+    #[inline]
+    pub fn new() -> Builder {
+        Builder {
+            http1: http1::Builder::new(),
+            http2: http2::Builder::new(),
+        }
+    }
+}
+
+impl<E> Builder<E> {
+    /// Set the executor used to spawn background HTTP/2 tasks.
+    pub fn with_executor<E2>(self, exec: E2) -> Builder<E2> {
+        Builder {
+            http1: self.http1,
+            http2: self.http2.with_executor(exec),
+        }
+    }
+
+    /// The options of the HTTP/1 connections.
+    pub fn http1(&mut self) -> &mut http1::Builder {
+        &mut self.http1
+    }
+
+    /// The options of the HTTP/2 connections.
+    pub fn http2(&mut self) -> &mut http2::Builder<E> {
+        &mut self.http2
+    }
+
+    /// Constructs a connection with the configured options and IO, for
+    /// requests with the given [`VersionPolicy`].
+    ///
+    /// `alpn` is the protocol negotiated with ALPN when the TLS handshake on
+    /// `io` was performed, if any. It is only used with
+    /// [`VersionPolicy::Negotiated`]: `h2` starts HTTP/2, while any other
+    /// value, or `None`, starts HTTP/1.
+    ///
+    /// Note, if [`Connection`] is not `await`-ed, [`SendRequest`] will
+    /// do nothing.
+    pub fn handshake<T, B>(
+        &self,
+        io: T,
+        policy: VersionPolicy,
+        alpn: Option<&[u8]>,
+    ) -> impl Future<Output = crate::Result<(SendRequest<B>, Connection<T, B, E>)>>
+    where
+        T: Read + Write + Unpin + Send + 'static,
+        B: Body + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: H2ClientConnTaskExec<B, T> + Unpin + Clone,
+    {
+        let http2 = match policy {
+            VersionPolicy::Http1Only => false,
+            VersionPolicy::Http2PriorKnowledge => true,
+            VersionPolicy::Negotiated => alpn == Some(b"h2"),
+        };
+        let handshake = if http2 {
+            Either::Right(self.http2.handshake(io))
+        } else {
+            Either::Left(self.http1.handshake(io))
+        };
+
+        async move {
+            let (inner, conn) = match handshake {
+                Either::Left(handshake) => {
+                    let (tx, conn) = handshake.await?;
+                    (Sender::Http1(tx), ConnInner::Http1 { conn })
+                }
+                Either::Right(handshake) => {
+                    let (tx, conn) = handshake.await?;
+                    (Sender::Http2(tx), ConnInner::Http2 { conn })
+                }
+            };
+            Ok((SendRequest { inner, policy }, Connection { inner: conn }))
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+// ===== impl SendRequest =====
+
+impl<B> SendRequest<B> {
+    /// Polls to determine whether this sender can be used yet for a request.
+    ///
+    /// If the associated connection is closed, this returns an Error.
+    pub fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
+        match self.inner {
+            Sender::Http1(ref mut tx) => tx.poll_ready(cx),
+            Sender::Http2(ref mut tx) => tx.poll_ready(cx),
+        }
+    }
+
+    /// Waits until the dispatcher is ready
+    ///
+    /// If the associated connection is closed, this returns an Error.
+    pub async fn ready(&mut self) -> crate::Result<()> {
+        futures_util::future::poll_fn(|cx| self.poll_ready(cx)).await
+    }
+
+    /// The HTTP version of the connection.
+    pub fn version(&self) -> Version {
+        match self.inner {
+            Sender::Http1(_) => Version::HTTP_11,
+            Sender::Http2(_) => Version::HTTP_2,
+        }
+    }
+
+    /// The [`VersionPolicy`] the connection was established for.
+    pub fn version_policy(&self) -> VersionPolicy {
+        self.policy
+    }
+
+    // Whether a request with this policy can be sent on the connection. Any
+    // HTTP/1 connection is fine for `Http1Only`, but the other policies also
+    // require the connection to have been established the same way.
+    fn accepts(&self, policy: Option<&VersionPolicy>) -> bool {
+        match policy {
+            None => true,
+            Some(VersionPolicy::Http1Only) => self.version() == Version::HTTP_11,
+            Some(policy) => *policy == self.policy,
+        }
+    }
+}
+
+impl<B> SendRequest<B>
+where
+    B: Body + 'static,
+{
+    /// Sends a `Request` on the associated connection.
+    ///
+    /// Returns a future that if successful, yields the `Response`.
+    ///
+    /// If the request has a [`VersionPolicy`] extension that the connection
+    /// wasn't established for, it is not sent, and an error is returned.
+    /// Requests without one are sent with whichever version the connection
+    /// uses.
+    pub fn send_request(
+        &mut self,
+        req: Request<B>,
+    ) -> impl Future<Output = crate::Result<Response<Recv>>> {
+        let sent = if self.accepts(req.extensions().get()) {
+            Some(match self.inner {
+                Sender::Http1(ref mut tx) => Either::Left(tx.send_request(req)),
+                Sender::Http2(ref mut tx) => Either::Right(tx.send_request(req)),
+            })
+        } else {
+            None
+        };
+
+        async move {
+            match sent {
+                Some(res) => res.await,
+                None => Err(crate::Error::new_user_version_policy()),
+            }
+        }
+    }
+}
+
+impl<B> fmt::Debug for SendRequest<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendRequest")
+            .field("version", &self.version())
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+// ===== impl Connection =====
+
+impl<T, B, E> fmt::Debug for Connection<T, B, E>
+where
+    T: Read + Write + Unpin + Send + 'static,
+    B: Body + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection").finish()
+    }
+}
+
+impl<T, B, E> Future for Connection<T, B, E>
+where
+    T: Read + Write + Unpin + Send + 'static,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: H2ClientConnExec<B, T> + Unpin,
+{
+    type Output = crate::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        match self.project().inner.project() {
+            ConnInnerProj::Http1 { conn } => conn.poll(cx),
+            ConnInnerProj::Http2 { conn } => conn.poll(cx),
+        }
+    }
+}
@@ -3,29 +3,41 @@
 use std::error::Error as StdError;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Instant;
 
 use bytes::Bytes;
 use http::{Request, Response};
 use httparse::ParserConfig;
-use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::Recv;
 use crate::body::Body;
 use super::super::dispatch;
+use crate::buffer::BufferPool;
+#[cfg(feature = "debug")]
+use crate::capture::{Capture, CaptureHandle};
 use crate::common::{
     exec::{BoxSendFuture, Exec},
+    io::{Counted, OnIo},
     task, Future, Pin, Poll,
 };
+use crate::common::time::Time;
+use crate::ext::{ConnTimings, ExchangeCounter, VersionPolicy};
+use crate::metrics::{ConnMetrics, Metrics, MetricsHandle};
 use crate::proto;
-use crate::rt::{Executor};
+use crate::rt::{Executor, Read, Write};
 use crate::upgrade::Upgraded;
+use super::{IoEvent, IoStats};
+
+pub use crate::proto::h1::{HeaderValuePolicy, Strictness, StrictnessLevel, TransferCodingPolicy};
 
 type Dispatcher<T, B> =
-    proto::dispatch::Dispatcher<proto::dispatch::Client<B>, B, T, proto::h1::ClientTransaction>;
+    proto::dispatch::Dispatcher<proto::dispatch::Client<B>, B, Counted<T>, proto::h1::ClientTransaction>;
 
 /// The sender side of an established connection.
 pub struct SendRequest<B> {
     dispatch: dispatch::Sender<Request<B>, Response<Recv>>,
+    metrics: Option<ConnMetrics>,
+    exchanges: Option<ExchangeCounter>,
 }
 
 /// Deconstructed parts of a `Connection`.
@@ -56,7 +68,7 @@ pub struct Parts<T> {
 #[must_use = "futures do nothing unless polled"]
 pub struct Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Send + 'static,
+    T: Read + Write + Send + 'static,
     B: Body + 'static,
 {
     inner: Option<Dispatcher<T, B>>,
@@ -64,7 +76,7 @@ where
 
 impl<T, B> Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    T: Read + Write + Send + Unpin + 'static,
     B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
 {
@@ -74,7 +86,7 @@ where
     pub fn into_parts(self) -> Parts<T> {
         let (io, read_buf, _) = self.inner.expect("already upgraded").into_inner();
         Parts {
-            io,
+            io: io.into_inner(),
             read_buf,
             _inner: (),
         }
@@ -92,7 +104,20 @@ where
     /// and [`try_ready!`](https://docs.rs/futures/0.1.25/futures/macro.try_ready.html)
     /// to work with this function; or use the `without_shutdown` wrapper.
     pub fn poll_without_shutdown(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
-        self.inner.as_mut().expect("algready upgraded").poll_without_shutdown(cx)
+        self.inner.as_mut().expect("already upgraded").poll_without_shutdown(cx)
+    }
+
+    /// Prevent shutdown of the underlying IO object at the end of service the request,
+    /// instead run `into_parts`. This is a convenience wrapper over `poll_without_shutdown`.
+    ///
+    /// The connection is done once the `SendRequest` is dropped and the
+    /// current exchange is finished, or after an upgrade.
+    pub fn without_shutdown(self) -> impl Future<Output = crate::Result<Parts<T>>> {
+        let mut conn = Some(self);
+        futures_util::future::poll_fn(move |cx| {
+            ready!(conn.as_mut().unwrap().poll_without_shutdown(cx))?;
+            Poll::Ready(Ok(conn.take().unwrap().into_parts()))
+        })
     }
 }
 
@@ -104,15 +129,33 @@ pub struct Builder {
     pub(super) exec: Exec,
     h09_responses: bool,
     h1_parser_config: ParserConfig,
+    h1_strictness: Strictness,
+    h1_header_value_policy: HeaderValuePolicy,
+    h1_validate_header_values: bool,
+    h1_transfer_coding_policy: TransferCodingPolicy,
     h1_writev: Option<bool>,
     h1_title_case_headers: bool,
     h1_preserve_header_case: bool,
+    h1_lazy_headers: bool,
+    h1_forward_lazy_headers: bool,
     #[cfg(feature = "ffi")]
     h1_headers_raw: bool,
     #[cfg(feature = "ffi")]
     h1_preserve_header_order: bool,
     h1_read_buf_exact_size: Option<usize>,
     h1_max_buf_size: Option<usize>,
+    h1_poll_budget: usize,
+    io_stats: Option<IoStats>,
+    on_io: Option<OnIo>,
+    metrics: Option<MetricsHandle>,
+    timings: bool,
+    connection_use: bool,
+    buffer_pool: Option<BufferPool>,
+    h1_body_channel_depth: usize,
+    h1_header_capacity: usize,
+    h1_max_headers: Option<usize>,
+    #[cfg(feature = "debug")]
+    capture: Option<CaptureHandle>,
 }
 
 /// Returns a handshake future over some IO.
@@ -123,7 +166,7 @@ pub async fn handshake<T, B>(
     io: T,
 ) -> crate::Result<(SendRequest<B>, Connection<T, B>)>
 where
-    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: Read + Write + Unpin + Send + 'static,
     B: Body + 'static,
     B::Data: Send,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -188,26 +231,53 @@ where
     ///   before calling this method.
     /// - Since absolute-form `Uri`s are not required, if received, they will
     ///   be serialized as-is.
+    ///
+    /// If the request has a [`VersionPolicy`] extension that requires another
+    /// version than HTTP/1, it is not sent, and an error is returned.
     pub fn send_request(
         &mut self,
         req: Request<B>,
     ) -> impl Future<Output = crate::Result<Response<Recv>>> {
-        let sent = self.dispatch.send(req);
+        let allowed =
+            req.extensions().get::<VersionPolicy>() != Some(&VersionPolicy::Http2PriorKnowledge);
+        let sent = if allowed {
+            Some(self.dispatch.send(req))
+        } else {
+            None
+        };
+        let metrics = match sent {
+            Some(Ok(_)) => self.metrics.as_ref().map(|m| (m.clone(), m.request())),
+            _ => None,
+        };
+        let connection_use = match sent {
+            Some(Ok(_)) => self.exchanges.as_ref().map(ExchangeCounter::next),
+            _ => None,
+        };
 
         async move {
-            match sent {
-                Ok(rx) => match rx.await {
+            let res = match sent {
+                None => Err(crate::Error::new_user_version_policy()),
+                Some(Ok(rx)) => match rx.await {
                     Ok(Ok(resp)) => Ok(resp),
                     Ok(Err(err)) => Err(err),
                     // this is definite bug if it happens, but it shouldn't happen!
                     Err(_canceled) => panic!("dispatch dropped without returning error"),
                 },
-                Err(_req) => {
+                Some(Err(_req)) => {
                     tracing::debug!("connection was not ready");
 
                     Err(crate::Error::new_canceled().with("connection was not ready"))
                 }
+            };
+            if let Some((metrics, sent_at)) = metrics {
+                metrics.response(sent_at, res.as_ref().ok().map(Response::status));
             }
+            res.map(|mut res| {
+                if let Some(connection_use) = connection_use {
+                    res.extensions_mut().insert(connection_use);
+                }
+                res
+            })
         }
     }
 
@@ -250,7 +320,7 @@ impl<B> fmt::Debug for SendRequest<B> {
 
 impl<T, B> fmt::Debug for Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + fmt::Debug + Send + 'static,
+    T: Read + Write + fmt::Debug + Send + 'static,
     B: Body + 'static,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -260,7 +330,7 @@ where
 
 impl<T, B> Future for Connection<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: Read + Write + Unpin + Send + 'static,
     B: Body + Send + 'static,
     B::Data: Send,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -273,7 +343,7 @@ where
             proto::Dispatched::Upgrade(pending) => match self.inner.take() {
                 Some(h1) => {
                     let (io, buf, _) = h1.into_inner();
-                    pending.fulfill(Upgraded::new(io, buf));
+                    pending.fulfill(Upgraded::new(io.into_inner(), buf));
                     Poll::Ready(Ok(()))
                 }
                 _ => {
@@ -297,13 +367,31 @@ impl Builder {
             h1_writev: None,
             h1_read_buf_exact_size: None,
             h1_parser_config: Default::default(),
+            h1_strictness: Strictness::default(),
+            h1_header_value_policy: HeaderValuePolicy::default(),
+            h1_validate_header_values: false,
+            h1_transfer_coding_policy: TransferCodingPolicy::default(),
             h1_title_case_headers: false,
             h1_preserve_header_case: false,
+            h1_lazy_headers: false,
+            h1_forward_lazy_headers: false,
             #[cfg(feature = "ffi")]
             h1_headers_raw: false,
             #[cfg(feature = "ffi")]
             h1_preserve_header_order: false,
             h1_max_buf_size: None,
+            h1_poll_budget: proto::DEFAULT_POLL_BUDGET,
+            io_stats: None,
+            on_io: None,
+            metrics: None,
+            timings: false,
+            connection_use: false,
+            buffer_pool: None,
+            h1_body_channel_depth: crate::body::DEFAULT_CHANNEL_DEPTH,
+            h1_header_capacity: 0,
+            h1_max_headers: None,
+            #[cfg(feature = "debug")]
+            capture: None,
         }
     }
 
@@ -395,6 +483,65 @@ impl Builder {
         self
     }
 
+    /// Set how strictly HTTP/1 responses are checked against [RFC 9112].
+    ///
+    /// This takes a [`StrictnessLevel`], or a [`Strictness`] to override
+    /// some of its checks. It replaces what was set with
+    /// [`http1_allow_spaces_after_header_name_in_responses`] and
+    /// [`http1_allow_obsolete_multiline_headers_in_responses`], which can
+    /// still be called after it.
+    ///
+    /// Note that this setting does not affect HTTP/2.
+    ///
+    /// Default is [`StrictnessLevel::Standard`].
+    ///
+    /// [RFC 9112]: https://www.rfc-editor.org/rfc/rfc9112
+    /// [`http1_allow_spaces_after_header_name_in_responses`]: Builder::http1_allow_spaces_after_header_name_in_responses
+    /// [`http1_allow_obsolete_multiline_headers_in_responses`]: Builder::http1_allow_obsolete_multiline_headers_in_responses
+    pub fn http1_strictness(&mut self, strictness: impl Into<Strictness>) -> &mut Builder {
+        let strictness = strictness.into();
+        strictness.apply_to(&mut self.h1_parser_config);
+        self.h1_strictness = strictness;
+        self
+    }
+
+    /// Set what to do with HTTP/1 response header values containing bytes
+    /// that aren't visible ASCII, space or tab.
+    ///
+    /// Note that this setting does not affect HTTP/2.
+    ///
+    /// Default is [`HeaderValuePolicy::Allow`].
+    pub fn http1_header_value_policy(&mut self, policy: HeaderValuePolicy) -> &mut Builder {
+        self.h1_header_value_policy = policy;
+        self
+    }
+
+    /// Set whether HTTP/1 request header values are checked before being
+    /// sent.
+    ///
+    /// Besides what `http` already rejects, values with bytes that aren't
+    /// visible ASCII, space or tab, or that start or end with whitespace,
+    /// make sending the request fail.
+    ///
+    /// Note that this setting does not affect HTTP/2.
+    ///
+    /// Default is false.
+    pub fn http1_validate_header_values(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_validate_header_values = enabled;
+        self
+    }
+
+    /// Set what to do with HTTP/1 responses whose `transfer-encoding` lists
+    /// a coding other than `chunked`, such as `gzip`.
+    ///
+    /// Note that this setting does not affect HTTP/2.
+    ///
+    /// Default is [`TransferCodingPolicy::PassThrough`].
+    pub fn http1_transfer_coding_policy(&mut self, policy: TransferCodingPolicy) -> &mut Builder {
+        self.h1_transfer_coding_policy = policy;
+        self
+    }
+
     /// Set whether HTTP/1 connections should try to use vectored writes,
     /// or always flatten into a single buffer.
     ///
@@ -441,6 +588,31 @@ impl Builder {
         self
     }
 
+    /// Set whether to only parse the response headers hyper needs.
+    ///
+    /// If enabled, the other headers are left unparsed in a
+    /// [`LazyHeaders`](crate::ext::LazyHeaders) extension on the `Response`,
+    /// instead of its `HeaderMap`.
+    ///
+    /// Default is false.
+    pub fn http1_lazy_headers(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_lazy_headers = enabled;
+        self
+    }
+
+    /// Set whether to write the headers of a
+    /// [`LazyHeaders`](crate::ext::LazyHeaders) extension on a `Request`.
+    ///
+    /// If enabled, they are written as received, such as when forwarding
+    /// them from a downstream client. Hop-by-hop headers, and headers the
+    /// `Request` already has in its `HeaderMap`, are skipped.
+    ///
+    /// Default is false.
+    pub fn http1_forward_lazy_headers(&mut self, enabled: bool) -> &mut Builder {
+        self.h1_forward_lazy_headers = enabled;
+        self
+    }
+
     /// Set whether to support preserving original header order.
     ///
     /// Currently, this will record the order in which headers are received, and store this
@@ -495,6 +667,143 @@ impl Builder {
         self
     }
 
+    /// Set how many times the connection is read, written and flushed in a
+    /// row each time it is polled, before yielding to other futures on the
+    /// same task.
+    ///
+    /// Default is 16.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `budget` is 0.
+    pub fn http1_poll_budget(&mut self, budget: usize) -> &mut Self {
+        assert!(budget != 0, "http1_poll_budget must be at least 1");
+        self.h1_poll_budget = budget;
+        self
+    }
+
+    /// Record the bytes read and written on the connection into `stats`.
+    ///
+    /// The counters are updated as the IO is polled, including vectored
+    /// writes, so they reflect exactly what went over the transport. Bytes
+    /// transferred after an HTTP upgrade are not counted.
+    ///
+    /// Default is to not record anything.
+    pub fn io_stats(&mut self, stats: IoStats) -> &mut Builder {
+        self.io_stats = Some(stats);
+        self
+    }
+
+    /// Call `f` each time bytes are read from or written to the connection.
+    ///
+    /// It is called from within the connection's IO polling, with the size
+    /// of each read or write, so it should be quick. Like with `io_stats`,
+    /// bytes transferred after an HTTP upgrade are not reported.
+    ///
+    /// Default is to not call anything.
+    pub fn io_callback<F>(&mut self, f: F) -> &mut Builder
+    where
+        F: Fn(IoEvent) + Send + Sync + 'static,
+    {
+        self.on_io = Some(OnIo::new(f));
+        self
+    }
+
+    /// Report the connection's bytes, requests, and handshake to `metrics`.
+    ///
+    /// Default is to not report anything.
+    pub fn metrics<M>(&mut self, metrics: M) -> &mut Builder
+    where
+        M: Metrics + 'static,
+    {
+        self.metrics = Some(MetricsHandle::new(metrics));
+        self
+    }
+
+    /// Set whether to add a [`Timings`](crate::ext::Timings) extension to
+    /// each response, with when its request was written, and its response
+    /// received.
+    ///
+    /// Default is false.
+    pub fn timings(&mut self, enabled: bool) -> &mut Builder {
+        self.timings = enabled;
+        self
+    }
+
+    /// Set whether to add a [`ConnectionUse`](crate::ext::ConnectionUse)
+    /// extension to each response, with how many requests the connection
+    /// has sent.
+    ///
+    /// Default is false.
+    pub fn connection_use(&mut self, enabled: bool) -> &mut Builder {
+        self.connection_use = enabled;
+        self
+    }
+
+    /// Take the connection's read and write buffers from `pool`, and give
+    /// them back once it closes.
+    ///
+    /// Default is to allocate new buffers for each connection.
+    pub fn buffer_pool(&mut self, pool: BufferPool) -> &mut Builder {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Set the maximum number of headers of responses.
+    ///
+    /// A response with more headers is an error. Up to 100 headers are
+    /// parsed into slots on the stack. With a larger maximum, slots on the
+    /// heap are only allocated for responses that have more than that.
+    ///
+    /// Default is 100.
+    pub fn http1_max_headers(&mut self, max: usize) -> &mut Builder {
+        self.h1_max_headers = Some(max);
+        self
+    }
+
+    /// Set how many headers responses are expected to have.
+    ///
+    /// The `HeaderMap` of each response is sized for at least this many
+    /// headers, so parsing responses with about as many headers doesn't grow
+    /// it.
+    ///
+    /// Default is 0, sizing it for the headers of each response.
+    pub fn http1_header_capacity(&mut self, capacity: usize) -> &mut Builder {
+        self.h1_header_capacity = capacity;
+        self
+    }
+
+    /// Set how many chunks of a response body may be read before they are
+    /// received from it.
+    ///
+    /// A deeper buffer lets the connection keep reading while earlier chunks
+    /// are handled, at the cost of holding more of the body in memory.
+    ///
+    /// Default is 1.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `depth` is 0.
+    pub fn http1_body_channel_depth(&mut self, depth: usize) -> &mut Builder {
+        assert!(depth != 0, "http1_body_channel_depth must be at least 1");
+        self.h1_body_channel_depth = depth;
+        self
+    }
+
+    /// Pass a copy of the bytes read and written on the connection to
+    /// `capture`.
+    ///
+    /// Default is to not capture anything.
+    #[cfg(feature = "debug")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+    pub fn capture<C>(&mut self, capture: C) -> &mut Builder
+    where
+        C: Capture + 'static,
+    {
+        self.capture = Some(CaptureHandle::new(capture));
+        self
+    }
+
     /// Constructs a connection with the configured options and IO.
     /// See [`client::conn`](crate::client::conn) for more.
     ///
@@ -505,7 +814,7 @@ impl Builder {
         io: T,
     ) -> impl Future<Output = crate::Result<(SendRequest<B>, Connection<T, B>)>>
     where
-        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        T: Read + Write + Unpin + Send + 'static,
         B: Body + 'static,
         B::Data: Send,
         B::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -515,9 +824,21 @@ impl Builder {
         async move {
             tracing::trace!("client handshake HTTP/1");
 
+            let started = Instant::now();
             let (tx, rx) = dispatch::channel();
-            let mut conn = proto::Conn::new(io);
+            let mut conn = proto::Conn::new(Counted::new(
+                io,
+                opts.io_stats,
+                opts.metrics.clone(),
+                opts.on_io,
+            ));
             conn.set_h1_parser_config(opts.h1_parser_config);
+            conn.set_strictness(opts.h1_strictness);
+            conn.set_header_value_policy(opts.h1_header_value_policy);
+            conn.set_transfer_coding_policy(opts.h1_transfer_coding_policy);
+            if opts.h1_validate_header_values {
+                conn.set_validate_header_values();
+            }
             if let Some(writev) = opts.h1_writev {
                 if writev {
                     conn.set_write_strategy_queue();
@@ -531,6 +852,12 @@ impl Builder {
             if opts.h1_preserve_header_case {
                 conn.set_preserve_header_case();
             }
+            if opts.h1_lazy_headers {
+                conn.set_lazy_headers();
+            }
+            if opts.h1_forward_lazy_headers {
+                conn.set_forward_lazy_headers();
+            }
             #[cfg(feature = "ffi")]
             if opts.h1_preserve_header_order {
                 conn.set_preserve_header_order();
@@ -549,11 +876,42 @@ impl Builder {
             if let Some(max) = opts.h1_max_buf_size {
                 conn.set_max_buf_size(max);
             }
-            let cd = proto::h1::dispatch::Client::new(rx);
-            let proto = proto::h1::Dispatcher::new(cd, conn);
+            if let Some(pool) = opts.buffer_pool.clone() {
+                conn.set_buffer_pool(pool);
+            }
+            conn.set_body_channel_depth(opts.h1_body_channel_depth);
+            conn.set_header_capacity(opts.h1_header_capacity);
+            if let Some(max) = opts.h1_max_headers {
+                conn.set_max_headers(max);
+            }
+            #[cfg(feature = "debug")]
+            if let Some(capture) = opts.capture.as_ref().and_then(CaptureHandle::start) {
+                conn.set_capture(capture);
+            }
+            let mut cd = proto::h1::dispatch::Client::new(rx);
+            if opts.timings {
+                cd.set_timings(ConnTimings {
+                    connected: started,
+                    tls_handshaked: None,
+                    time: Time::default(),
+                });
+            }
+            let mut proto = proto::h1::Dispatcher::new(cd, conn);
+            proto.set_poll_budget(opts.h1_poll_budget);
 
+            if let Some(ref metrics) = opts.metrics {
+                metrics.get().handshake_duration(started.elapsed());
+            }
             Ok((
-                SendRequest { dispatch: tx },
+                SendRequest {
+                    dispatch: tx,
+                    metrics: opts.metrics.as_ref().map(MetricsHandle::conn),
+                    exchanges: if opts.connection_use {
+                        Some(ExchangeCounter::default())
+                    } else {
+                        None
+                    },
+                },
                 Connection { inner: Some(proto) },
             ))
         }
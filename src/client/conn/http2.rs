@@ -2,27 +2,35 @@
 
 use std::error::Error as StdError;
 use std::fmt;
-use std::marker::PhantomData;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use http::{Request, Response};
-use tokio::io::{AsyncRead, AsyncWrite};
 
 use super::super::dispatch;
+use super::{IoEvent, IoStats};
 use crate::body::Body;
+#[cfg(feature = "debug")]
+use crate::capture::{Capture, CaptureHandle};
 use crate::common::time::Time;
 use crate::common::{
-    exec::{BoxSendFuture, Exec},
+    exec::{BoxSendFuture, Exec, H2ClientConnExec, H2ClientConnTaskExec},
+    io::{Counted, OnIo},
     task, Future, Pin, Poll,
 };
+use crate::ext::{ExchangeCounter, VersionPolicy};
+use crate::metrics::{ConnMetrics, Metrics, MetricsHandle};
 use crate::proto;
-use crate::rt::{Executor, Timer};
+use crate::rt::{Clock, Executor, Read, Timer, Write};
 use crate::Recv;
 
+pub use crate::proto::h2::TrailerPolicy;
+
 /// The sender side of an established connection.
 pub struct SendRequest<B> {
     dispatch: dispatch::UnboundedSender<Request<B>, Response<Recv>>,
+    metrics: Option<ConnMetrics>,
+    exchanges: Option<ExchangeCounter>,
 }
 
 /// A future that processes all HTTP state for the IO object.
@@ -30,22 +38,26 @@ pub struct SendRequest<B> {
 /// In most cases, this should just be spawned into an executor, so that it
 /// can process incoming and outgoing messages, notice hangups, and the like.
 #[must_use = "futures do nothing unless polled"]
-pub struct Connection<T, B>
+pub struct Connection<T, B, E = Exec>
 where
-    T: AsyncRead + AsyncWrite + Send + 'static,
+    T: Read + Write + Unpin,
     B: Body + 'static,
 {
-    inner: (PhantomData<T>, proto::h2::ClientTask<B>),
+    inner: proto::h2::ClientTask<B, E, T>,
 }
 
 /// A builder to configure an HTTP connection.
 ///
 /// After setting options, the builder is used to create a handshake future.
 #[derive(Clone, Debug)]
-pub struct Builder {
-    pub(super) exec: Exec,
+pub struct Builder<E = Exec> {
+    pub(super) exec: E,
     pub(super) timer: Time,
     h2_builder: proto::h2::client::Config,
+    io_stats: Option<IoStats>,
+    on_io: Option<OnIo>,
+    metrics: Option<MetricsHandle>,
+    connection_use: bool,
 }
 
 /// Returns a handshake future over some IO.
@@ -56,7 +68,7 @@ pub async fn handshake<T, B>(
     io: T,
 ) -> crate::Result<(SendRequest<B>, Connection<T, B>)>
 where
-    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T: Read + Write + Unpin + Send + 'static,
     B: Body + 'static,
     B::Data: Send,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -125,26 +137,53 @@ where
     ///   before calling this method.
     /// - Since absolute-form `Uri`s are not required, if received, they will
     ///   be serialized as-is.
+    ///
+    /// If the request has a [`VersionPolicy`] extension that requires another
+    /// version than HTTP/2, it is not sent, and an error is returned.
     pub fn send_request(
         &mut self,
         req: Request<B>,
     ) -> impl Future<Output = crate::Result<Response<Recv>>> {
-        let sent = self.dispatch.send(req);
+        let allowed =
+            req.extensions().get::<VersionPolicy>() != Some(&VersionPolicy::Http1Only);
+        let sent = if allowed {
+            Some(self.dispatch.send(req))
+        } else {
+            None
+        };
+        let metrics = match sent {
+            Some(Ok(_)) => self.metrics.as_ref().map(|m| (m.clone(), m.request())),
+            _ => None,
+        };
+        let connection_use = match sent {
+            Some(Ok(_)) => self.exchanges.as_ref().map(ExchangeCounter::next),
+            _ => None,
+        };
 
         async move {
-            match sent {
-                Ok(rx) => match rx.await {
+            let res = match sent {
+                None => Err(crate::Error::new_user_version_policy()),
+                Some(Ok(rx)) => match rx.await {
                     Ok(Ok(resp)) => Ok(resp),
                     Ok(Err(err)) => Err(err),
                     // this is definite bug if it happens, but it shouldn't happen!
                     Err(_canceled) => panic!("dispatch dropped without returning error"),
                 },
-                Err(_req) => {
+                Some(Err(_req)) => {
                     tracing::debug!("connection was not ready");
 
                     Err(crate::Error::new_canceled().with("connection was not ready"))
                 }
+            };
+            if let Some((metrics, sent_at)) = metrics {
+                metrics.response(sent_at, res.as_ref().ok().map(Response::status));
             }
+            res.map(|mut res| {
+                if let Some(connection_use) = connection_use {
+                    res.extensions_mut().insert(connection_use);
+                }
+                res
+            })
         }
     }
 
@@ -185,12 +224,12 @@ impl<B> fmt::Debug for SendRequest<B> {
 
 // ===== impl Connection
 
-impl<T, B> Connection<T, B>
+impl<T, B, E> Connection<T, B, E>
 where
-    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-    B: Body + Unpin + Send + 'static,
-    B::Data: Send,
+    T: Read + Write + Unpin + 'static,
+    B: Body + Unpin + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: H2ClientConnExec<B, T> + Unpin,
 {
     /// Returns whether the [extended CONNECT protocol][1] is enabled or not.
     ///
@@ -202,13 +241,13 @@ where
     /// [1]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
     /// [2]: https://datatracker.ietf.org/doc/html/rfc8441#section-3
     pub fn is_extended_connect_protocol_enabled(&self) -> bool {
-        self.inner.1.is_extended_connect_protocol_enabled()
+        self.inner.is_extended_connect_protocol_enabled()
     }
 }
 
-impl<T, B> fmt::Debug for Connection<T, B>
+impl<T, B, E> fmt::Debug for Connection<T, B, E>
 where
-    T: AsyncRead + AsyncWrite + fmt::Debug + Send + 'static,
+    T: Read + Write + Unpin + fmt::Debug,
     B: Body + 'static,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -216,17 +255,17 @@ where
     }
 }
 
-impl<T, B> Future for Connection<T, B>
+impl<T, B, E> Future for Connection<T, B, E>
 where
-    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
-    B: Body + Send + 'static,
-    B::Data: Send,
+    T: Read + Write + Unpin + 'static,
+    B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: H2ClientConnExec<B, T> + Unpin,
 {
     type Output = crate::Result<()>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
-        match ready!(Pin::new(&mut self.inner.1).poll(cx))? {
+        match ready!(Pin::new(&mut self.inner).poll(cx))? {
             proto::Dispatched::Shutdown => Poll::Ready(Ok(())),
             #[cfg(feature = "http1")]
             proto::Dispatched::Upgrade(_pending) => unreachable!("http2 cannot upgrade"),
@@ -242,8 +281,12 @@ impl Builder {
     pub fn new() -> Builder {
         Builder {
             exec: Exec::Default,
-            timer: Time::Empty,
+            timer: Time::default(),
             h2_builder: Default::default(),
+            io_stats: None,
+            on_io: None,
+            metrics: None,
+            connection_use: false,
         }
     }
 
@@ -255,13 +298,53 @@ impl Builder {
         self.exec = Exec::Executor(Arc::new(exec));
         self
     }
+}
+
+impl<E> Builder<E> {
+    /// Provide an executor to execute background HTTP2 tasks.
+    ///
+    /// Unlike [`executor`](Builder::executor), the tasks are given to the
+    /// executor as they are, instead of boxed. They are only `Send` if the
+    /// IO and body types are, so an executor of local tasks, such as one
+    /// using `tokio::task::spawn_local`, can drive a connection that isn't
+    /// `Send`.
+    ///
+    /// This covers every task of an HTTP/2 client connection: the
+    /// connection itself, request bodies and responses. Keep-alive pings
+    /// don't need a task of their own. For servers, the same is done by
+    /// `server::conn::Http::with_executor`.
+    pub fn with_executor<E2>(self, exec: E2) -> Builder<E2> {
+        Builder {
+            exec,
+            timer: self.timer,
+            h2_builder: self.h2_builder,
+            io_stats: self.io_stats,
+            on_io: self.on_io,
+            metrics: self.metrics,
+            connection_use: self.connection_use,
+        }
+    }
 
     /// Provide a timer to execute background HTTP2 tasks.
-    pub fn timer<M>(&mut self, timer: M) -> &mut Builder
+    pub fn timer<M>(&mut self, timer: M) -> &mut Self
     where
         M: Timer + Send + Sync + 'static,
     {
-        self.timer = Time::Timer(Arc::new(timer));
+        self.timer.set_timer(Arc::new(timer));
+        self
+    }
+
+    /// Provide a clock to read the current time from.
+    ///
+    /// The clock is used for keep-alive pings, and the timeout of requests
+    /// waiting for the connection.
+    ///
+    /// Default uses the system clock.
+    pub fn clock<C>(&mut self, clock: C) -> &mut Self
+    where
+        C: Clock + Send + Sync + 'static,
+    {
+        self.timer.set_clock(Arc::new(clock));
         self
     }
 
@@ -407,6 +490,180 @@ impl Builder {
         self
     }
 
+    /// Sets the [`SETTINGS_HEADER_TABLE_SIZE`][spec] option for HTTP2, the
+    /// size of the HPACK dynamic table the peer may use to compress the
+    /// headers it sends.
+    ///
+    /// Passing `None` will do nothing.
+    ///
+    /// If not set, the spec default of 4kb is used.
+    ///
+    /// This is only available on the client, since `h2` can't advertise a
+    /// table size for server connections. Those always use the spec default.
+    ///
+    /// [spec]: https://httpwg.org/specs/rfc9113.html#SETTINGS_HEADER_TABLE_SIZE
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_header_table_size(&mut self, sz: impl Into<Option<u32>>) -> &mut Self {
+        if let Some(sz) = sz.into() {
+            self.h2_builder.adaptive_header_table = None;
+            self.h2_builder.header_table_size = Some(sz);
+        }
+        self
+    }
+
+    /// Sets whether to size the HPACK dynamic table by how much the
+    /// received headers repeat.
+    ///
+    /// The table size is only advertised when a connection starts, so it is
+    /// picked from the headers received on the earlier connections made
+    /// with this builder: from the spec default of 4kb, up to 64kb when most
+    /// headers repeat. Connections made before a few hundred headers were
+    /// received on earlier ones use the spec default. Like
+    /// `http2_header_table_size`, this is only available on the client.
+    ///
+    /// Enabling this will override the size set in
+    /// `http2_header_table_size`.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_adaptive_header_table(&mut self, enabled: bool) -> &mut Self {
+        self.h2_builder.adaptive_header_table = if enabled {
+            Some(proto::h2::hpack::TableTuner::default())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Set what to do with fields that RFC 9110 forbids in trailers, such as
+    /// `content-length` or `host`, in request and response trailers.
+    ///
+    /// With [`TrailerPolicy::Reject`], a request body's stream is reset
+    /// instead of its trailers being sent, and reading the trailers of a
+    /// response body returns an error.
+    ///
+    /// Default is [`TrailerPolicy::Allow`].
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_trailer_policy(&mut self, policy: TrailerPolicy) -> &mut Self {
+        self.h2_builder.trailer_policy = policy;
+        self
+    }
+
+    /// Sets the maximum number of requests waiting for the server to allow
+    /// another stream.
+    ///
+    /// Once the server's `SETTINGS_MAX_CONCURRENT_STREAMS` is reached, new
+    /// requests are queued until a stream completes. When the queue is full,
+    /// further requests fail immediately with a canceled error instead.
+    ///
+    /// Default is `None`, an unbounded queue.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_max_pending_requests(&mut self, max: impl Into<Option<usize>>) -> &mut Self {
+        self.h2_builder.max_pending_requests = max.into();
+        self
+    }
+
+    /// Sets how long a request may wait for the server to allow another
+    /// stream.
+    ///
+    /// If the request is still queued when the timeout elapses, it fails with
+    /// an error where [`is_timeout`](crate::Error::is_timeout) is true.
+    ///
+    /// Requires a [`timer`](Builder::timer) to be configured.
+    ///
+    /// Default is `None`, waiting as long as necessary.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_pending_request_timeout(
+        &mut self,
+        timeout: impl Into<Option<Duration>>,
+    ) -> &mut Self {
+        self.h2_builder.pending_request_timeout = timeout.into();
+        self
+    }
+
+    /// Sets how many requests are started each time the connection is
+    /// polled, before yielding to other futures on the same task.
+    ///
+    /// Default is 16.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `budget` is 0.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_poll_budget(&mut self, budget: usize) -> &mut Self {
+        assert!(budget != 0, "http2_poll_budget must be at least 1");
+        self.h2_builder.poll_budget = budget;
+        self
+    }
+
+    /// Record the bytes read and written on the connection into `stats`.
+    ///
+    /// The counters are updated as the IO is polled, including vectored
+    /// writes, so they reflect exactly what went over the transport,
+    /// frame overhead included.
+    ///
+    /// Default is to not record anything.
+    pub fn io_stats(&mut self, stats: IoStats) -> &mut Self {
+        self.io_stats = Some(stats);
+        self
+    }
+
+    /// Call `f` each time bytes are read from or written to the connection.
+    ///
+    /// It is called from within the connection's IO polling, with the size
+    /// of each read or write, frame overhead included, so it should be
+    /// quick.
+    ///
+    /// Default is to not call anything.
+    pub fn io_callback<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(IoEvent) + Send + Sync + 'static,
+    {
+        self.on_io = Some(OnIo::new(f));
+        self
+    }
+
+    /// Report the connection's bytes, requests, and handshake to `metrics`.
+    ///
+    /// The handshake lasts until the server's preface is received.
+    ///
+    /// Default is to not report anything.
+    pub fn metrics<M>(&mut self, metrics: M) -> &mut Self
+    where
+        M: Metrics + 'static,
+    {
+        self.metrics = Some(MetricsHandle::new(metrics));
+        self
+    }
+
+    /// Set whether to add a [`ConnectionUse`](crate::ext::ConnectionUse)
+    /// extension to each response, with how many requests the connection
+    /// has sent.
+    ///
+    /// Default is false.
+    pub fn connection_use(&mut self, enabled: bool) -> &mut Self {
+        self.connection_use = enabled;
+        self
+    }
+
+    /// Pass a copy of the bytes read and written on the connection to
+    /// `capture`.
+    ///
+    /// Default is to not capture anything.
+    #[cfg(feature = "debug")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+    pub fn capture<C>(&mut self, capture: C) -> &mut Self
+    where
+        C: Capture + 'static,
+    {
+        self.h2_builder.capture = Some(CaptureHandle::new(capture));
+        self
+    }
+
     /// Constructs a connection with the configured options and IO.
     /// See [`client::conn`](crate::client::conn) for more.
     ///
@@ -415,28 +672,38 @@ impl Builder {
     pub fn handshake<T, B>(
         &self,
         io: T,
-    ) -> impl Future<Output = crate::Result<(SendRequest<B>, Connection<T, B>)>>
+    ) -> impl Future<Output = crate::Result<(SendRequest<B>, Connection<T, B, E>)>>
     where
-        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        T: Read + Write + Unpin + 'static,
         B: Body + 'static,
         B::Data: Send,
         B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: H2ClientConnTaskExec<B, T> + Unpin + Clone,
     {
         let opts = self.clone();
 
         async move {
             tracing::trace!("client handshake HTTP/1");
 
+            let started = Instant::now();
             let (tx, rx) = dispatch::channel();
+            let io = Counted::new(io, opts.io_stats, opts.metrics.clone(), opts.on_io);
             let h2 = proto::h2::client::handshake(io, rx, &opts.h2_builder, opts.exec, opts.timer)
                 .await?;
+            if let Some(ref metrics) = opts.metrics {
+                metrics.get().handshake_duration(started.elapsed());
+            }
             Ok((
                 SendRequest {
                     dispatch: tx.unbound(),
+                    metrics: opts.metrics.as_ref().map(MetricsHandle::conn),
+                    exchanges: if opts.connection_use {
+                        Some(ExchangeCounter::default())
+                    } else {
+                        None
+                    },
                 },
-                Connection {
-                    inner: (PhantomData, h2),
-                },
+                Connection { inner: h2 },
             ))
         }
     }
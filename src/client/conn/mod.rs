@@ -9,7 +9,9 @@
 //! higher-level [Client](super) API.
 //!
 //! ## Example
-//! A simple example that uses the `SendRequest` struct to talk HTTP over a Tokio TCP stream
+//! A simple example that uses the `SendRequest` struct to talk HTTP over a
+//! TCP stream. The stream can be of any type implementing hyper's
+//! [`Read`](crate::rt::Read) and [`Write`](crate::rt::Write) traits.
 //! ```no_run
 //! # #[cfg(all(feature = "client", feature = "http1"))]
 //! # mod rt {
@@ -17,7 +19,12 @@
 //! use http::{Request, StatusCode};
 //! use http_body_util::Empty;
 //! use hyper::client::conn;
-//! use tokio::net::TcpStream;
+//! # struct TcpStream;
+//! # impl TcpStream {
+//! #     async fn connect(_: &str) -> std::io::Result<hyper::upgrade::Upgraded> {
+//! #         unimplemented!()
+//! #     }
+//! # }
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -52,8 +59,14 @@
 //! # }
 //! ```
 
+#[cfg(all(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "http1", feature = "http2"))))]
+pub mod auto;
 #[cfg(feature = "http1")]
 pub mod http1;
 #[cfg(feature = "http2")]
 pub mod http2;
 
+
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub use crate::common::io::{IoEvent, IoStats};
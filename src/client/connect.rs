@@ -0,0 +1,330 @@
+//! Connecting to a remote address.
+//!
+//! The [`conn`](super::conn) handshakes work over any IO type. This module
+//! provides a [`Connector`] to establish that IO, for when the socket needs
+//! to be configured before connecting, such as on multi-homed hosts that
+//! must pick the source address or network interface.
+//!
+//! Besides TCP, a [`Destination`] can name a Unix domain socket or a Windows
+//! named pipe, which is how many local daemons (such as Docker or systemd)
+//! expose their HTTP APIs. [`Connector::connect`] returns a [`BoxedIo`] in
+//! every case, so the same code can do the handshake no matter the transport.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # async fn doc() -> std::io::Result<()> {
+//! use std::time::Duration;
+//! use hyper::client::connect::Connector;
+//!
+//! let mut connector = Connector::new();
+//! connector
+//!     .local_address(Some([10, 0, 0, 2].into()))
+//!     .nodelay(true)
+//!     .keepalive(Some(Duration::from_secs(60)));
+//!
+//! let stream = connector.connect_tcp(([10, 0, 0, 1], 80).into()).await?;
+//! # drop(stream);
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use tokio::net::{TcpSocket, TcpStream};
+
+use crate::common::io::Compat;
+use crate::common::{task, Pin, Poll};
+use crate::rt::{Read, ReadBufCursor, Write};
+
+/// Where to connect to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Destination {
+    /// A TCP socket address.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket.
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// The name of a Windows named pipe, such as `\\.\pipe\docker_engine`.
+    #[cfg(windows)]
+    NamedPipe(String),
+}
+
+/// A connected IO object, no matter the transport used.
+///
+/// This can be passed to any of the [`conn`](super::conn) handshakes.
+pub struct BoxedIo {
+    inner: Box<dyn Io + Send>,
+}
+
+trait Io: Read + Write + Unpin {}
+
+impl<T: Read + Write + Unpin> Io for T {}
+
+/// Configures and establishes connections.
+#[derive(Clone, Debug, Default)]
+pub struct Connector {
+    local_address_ipv4: Option<Ipv4Addr>,
+    local_address_ipv6: Option<Ipv6Addr>,
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    interface: Option<String>,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+}
+
+impl Connector {
+    /// Creates a new connector with the default options.
+    pub fn new() -> Connector {
+        Connector::default()
+    }
+
+    /// Set the local address the socket is bound to before connecting.
+    ///
+    /// The address is only used when connecting to a remote address of the
+    /// same family. Use [`local_addresses`](Connector::local_addresses) to
+    /// pick one address of each family.
+    ///
+    /// Default is `None`, letting the operating system choose.
+    pub fn local_address(&mut self, addr: Option<IpAddr>) -> &mut Self {
+        let (v4, v6) = match addr {
+            Some(IpAddr::V4(a)) => (Some(a), None),
+            Some(IpAddr::V6(a)) => (None, Some(a)),
+            None => (None, None),
+        };
+        self.local_address_ipv4 = v4;
+        self.local_address_ipv6 = v6;
+        self
+    }
+
+    /// Set the local IPv4 and IPv6 addresses the socket is bound to before
+    /// connecting, depending on the family of the remote address.
+    ///
+    /// Default is to let the operating system choose.
+    pub fn local_addresses(&mut self, addr_ipv4: Ipv4Addr, addr_ipv6: Ipv6Addr) -> &mut Self {
+        self.local_address_ipv4 = Some(addr_ipv4);
+        self.local_address_ipv6 = Some(addr_ipv6);
+        self
+    }
+
+    /// Bind the socket to a network interface, such as `"eth0"`, using
+    /// `SO_BINDTODEVICE`.
+    ///
+    /// This usually requires elevated privileges.
+    ///
+    /// Default is `None`.
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    pub fn interface<S: Into<String>>(&mut self, interface: Option<S>) -> &mut Self {
+        self.interface = interface.map(Into::into);
+        self
+    }
+
+    /// Set the value of `TCP_NODELAY` on connected sockets.
+    ///
+    /// Default is `false`.
+    pub fn nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Enable `SO_KEEPALIVE` on connected sockets, with the given time a
+    /// connection must be idle before keepalive probes are sent.
+    ///
+    /// Default is `None`, which leaves keepalive disabled.
+    pub fn keepalive(&mut self, time: Option<Duration>) -> &mut Self {
+        self.keepalive = time;
+        self
+    }
+
+    /// Connect to the destination.
+    ///
+    /// The socket options only apply to TCP destinations.
+    pub async fn connect(&self, dst: &Destination) -> io::Result<BoxedIo> {
+        match *dst {
+            Destination::Tcp(addr) => self.connect_tcp(addr).await.map(BoxedIo::new),
+            #[cfg(unix)]
+            Destination::Unix(ref path) => tokio::net::UnixStream::connect(path)
+                .await
+                .map(BoxedIo::new),
+            #[cfg(windows)]
+            Destination::NamedPipe(ref name) => {
+                tokio::net::windows::named_pipe::ClientOptions::new()
+                    .open(name)
+                    .map(BoxedIo::new)
+            }
+        }
+    }
+
+    /// Connect a TCP stream to the remote address, using the configured
+    /// socket options.
+    ///
+    /// This returns the Tokio `TcpStream` itself. Use
+    /// [`connect`](Connector::connect) for an IO that can be passed to the
+    /// handshakes directly.
+    pub async fn connect_tcp(&self, addr: SocketAddr) -> io::Result<TcpStream> {
+        let socket = self.tcp_socket(&addr)?;
+        let stream = socket.connect(addr).await?;
+        stream.set_nodelay(self.nodelay)?;
+        Ok(stream)
+    }
+
+    fn tcp_socket(&self, addr: &SocketAddr) -> io::Result<TcpSocket> {
+        let socket = Socket::new(
+            Domain::for_address(*addr),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        socket.set_nonblocking(true)?;
+
+        if let Some(time) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(ref interface) = self.interface {
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+
+        let local = match *addr {
+            SocketAddr::V4(_) => self.local_address_ipv4.map(IpAddr::V4),
+            SocketAddr::V6(_) => self.local_address_ipv6.map(IpAddr::V6),
+        };
+        if let Some(ip) = local {
+            socket.bind(&SocketAddr::new(ip, 0).into())?;
+        }
+
+        Ok(TcpSocket::from_std_stream(socket.into()))
+    }
+}
+
+// ===== impl Destination =====
+
+impl From<SocketAddr> for Destination {
+    fn from(addr: SocketAddr) -> Destination {
+        Destination::Tcp(addr)
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Destination::Tcp(ref addr) => fmt::Display::fmt(addr, f),
+            #[cfg(unix)]
+            Destination::Unix(ref path) => write!(f, "unix:{}", path.display()),
+            #[cfg(windows)]
+            Destination::NamedPipe(ref name) => f.write_str(name),
+        }
+    }
+}
+
+// ===== impl BoxedIo =====
+
+impl BoxedIo {
+    fn new<T>(io: T) -> BoxedIo
+    where
+        T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Unpin + 'static,
+    {
+        BoxedIo {
+            inner: Box::new(Compat::new(io)),
+        }
+    }
+}
+
+impl Read for BoxedIo {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl Write for BoxedIo {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+impl fmt::Debug for BoxedIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoxedIo").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Connector, Destination};
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn connect_with_local_address() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut connector = Connector::new();
+        connector
+            .local_address(Some([127, 0, 0, 1].into()))
+            .nodelay(true);
+        let stream = connector.connect_tcp(addr).await.unwrap();
+
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        assert!(stream.local_addr().unwrap().ip().is_loopback());
+        assert!(stream.nodelay().unwrap());
+    }
+
+    #[cfg(all(unix, not(miri)))]
+    #[tokio::test]
+    async fn connect_unix_socket() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        use crate::common::io::Compat;
+
+        let path = std::env::temp_dir().join(format!("hyper-connect-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+
+        let connector = Connector::new();
+        let dst = Destination::Unix(path.clone());
+        let (io, accepted) = tokio::join!(connector.connect(&dst), listener.accept());
+        let mut io = Compat::new(io.unwrap());
+        let (mut server, _) = accepted.unwrap();
+
+        io.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
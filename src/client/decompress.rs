@@ -0,0 +1,822 @@
+//! Transparent decompression of response bodies.
+//!
+//! The low-level client connections send exactly what they are given, and
+//! return exactly what the server sent. [`DecompressionLayer`] wraps a
+//! `tower::Service` sending requests to opt in to compressed responses:
+//! each request advertises the supported codings in `Accept-Encoding`, and
+//! each response body is wrapped in a [`Decompress`] body, decoding `gzip`,
+//! `deflate`, `br` and `zstd` content as it is streamed.
+//!
+//! The same steps are available on their own, for a single exchange:
+//!
+//! - [`set_accept_encoding`] advertises the supported codings on a request.
+//! - [`decompress`] wraps the body of a response in a [`Decompress`] body.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # async fn doc<S>(client: S) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+//! # where
+//! #     S: tower_service::Service<
+//! #         hyper::Request<http_body_util::Empty<bytes::Bytes>>,
+//! #         Response = hyper::Response<hyper::Recv>,
+//! #         Error = hyper::Error,
+//! #     >,
+//! # {
+//! use bytes::Bytes;
+//! use futures_util::future::poll_fn;
+//! use http_body_util::Empty;
+//! use hyper::client::decompress::DecompressionLayer;
+//! use hyper::Request;
+//! use tower_layer::Layer;
+//! use tower_service::Service;
+//!
+//! let mut client = DecompressionLayer::new().layer(client);
+//!
+//! let req = Request::get("/")
+//!     .header("host", "hyper.rs")
+//!     .body(Empty::<Bytes>::new())?;
+//! poll_fn(|cx| client.poll_ready(cx)).await?;
+//! let res = client.call(req).await?;
+//! let body = hyper::body::to_bytes(res.into_body()).await?;
+//! # drop(body);
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Write};
+use std::mem;
+
+use brotli::enc::StandardAlloc;
+use brotli::{BrotliResult, BrotliState};
+use bytes::{Buf, Bytes};
+use http::header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use http::{HeaderMap, Request, Response};
+use http_body::{Body, SizeHint};
+use pin_project_lite::pin_project;
+use zstd::stream::raw::{InBuffer, Operation, OutBuffer};
+
+use crate::common::{task, Future, Pin, Poll};
+
+const SUPPORTED_ENCODINGS: &str = "gzip, deflate, br, zstd";
+
+// The decoded bytes gathered before yielding a frame, so that a small chunk
+// of a highly compressed body can't allocate a huge buffer at once.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+// Size of the output buffer the decoders fill in each step. The gzip decoder
+// has one of the same size inside.
+const DECODE_BUF_SIZE: usize = 32 * 1024;
+
+/// Set the `Accept-Encoding` header to the codings [`decompress`] can handle.
+///
+/// An `Accept-Encoding` header already present on the request is left as-is.
+pub fn set_accept_encoding<B>(req: &mut Request<B>) {
+    req.headers_mut()
+        .entry(ACCEPT_ENCODING)
+        .or_insert_with(|| HeaderValue::from_static(SUPPORTED_ENCODINGS));
+}
+
+/// Wrap the body of a response to decode its `Content-Encoding`.
+///
+/// If the response uses a supported coding, the `Content-Encoding` and
+/// `Content-Length` headers are removed, since they describe the encoded
+/// bytes instead of what the returned body yields. Otherwise, the response
+/// is left untouched and the body passes the data through.
+pub fn decompress<B>(res: Response<B>) -> Response<Decompress<B>> {
+    let (mut parts, body) = res.into_parts();
+    let decoder = Decoder::from_headers(&parts.headers);
+    if decoder.is_some() {
+        parts.headers.remove(CONTENT_ENCODING);
+        parts.headers.remove(CONTENT_LENGTH);
+    }
+    Response::from_parts(parts, Decompress::new(body, decoder))
+}
+
+/// A [`tower::Layer`] that decompresses the responses of a client service.
+///
+/// See [`Decompression`] for what the wrapped service does.
+///
+/// [`tower::Layer`]: tower_layer::Layer
+#[derive(Clone, Debug, Default)]
+pub struct DecompressionLayer {
+    _priv: (),
+}
+
+impl DecompressionLayer {
+    /// Creates a new `DecompressionLayer`.
+    pub fn new() -> Self {
+        DecompressionLayer { _priv: () }
+    }
+}
+
+impl<S> tower_layer::Layer<S> for DecompressionLayer {
+    type Service = Decompression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Decompression::new(inner)
+    }
+}
+
+/// A client service that decompresses the responses of its inner service.
+///
+/// Each request has [`set_accept_encoding`] applied before it is sent, and
+/// each response is returned through [`decompress`].
+#[derive(Clone, Debug)]
+pub struct Decompression<S> {
+    inner: S,
+}
+
+impl<S> Decompression<S> {
+    /// Wraps a service sending requests.
+    pub fn new(inner: S) -> Self {
+        Decompression { inner }
+    }
+
+    /// Returns a reference to the wrapped service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> tower_service::Service<Request<ReqBody>> for Decompression<S>
+where
+    S: tower_service::Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<Decompress<ResBody>>;
+    type Error = S::Error;
+    type Future = DecompressionFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        set_accept_encoding(&mut req);
+        DecompressionFuture {
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+pin_project! {
+    /// The future returned by a [`Decompression`] service.
+    #[must_use = "futures do nothing unless polled"]
+    #[derive(Debug)]
+    pub struct DecompressionFuture<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F, B, E> Future for DecompressionFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<Decompress<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(self.project().inner.poll(cx))?;
+        Poll::Ready(Ok(decompress(res)))
+    }
+}
+
+pin_project! {
+    /// A body that decodes a compressed inner body.
+    ///
+    /// The decoded data is yielded in frames of about 64kb at most, however
+    /// little encoded data they come from. An encoded body that ends before
+    /// its coding does, or has data after it, is an error.
+    ///
+    /// Created with [`decompress`], or by a [`Decompression`] service.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Decompress<B> {
+        #[pin]
+        inner: B,
+        decoder: Option<Decoder>,
+        // Received from `inner`, but not decoded yet.
+        input: Bytes,
+        has_input: bool,
+        eof: bool,
+        done: bool,
+    }
+}
+
+enum Decoder {
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    Deflate(DeflateDecoder),
+    Brotli(Box<BrotliDecoder>),
+    Zstd(ZstdDecoder),
+}
+
+struct DeflateDecoder {
+    raw: flate2::Decompress,
+    out: Vec<u8>,
+    finished: bool,
+}
+
+struct BrotliDecoder {
+    state: BrotliState<StandardAlloc, StandardAlloc, StandardAlloc>,
+    buf: Box<[u8]>,
+    out: Vec<u8>,
+    finished: bool,
+}
+
+struct ZstdDecoder {
+    raw: zstd::stream::raw::Decoder<'static>,
+    out: Vec<u8>,
+    // Whether the input so far ends with a complete frame.
+    frame_done: bool,
+}
+
+impl<B> Decompress<B> {
+    fn new(inner: B, decoder: Option<Decoder>) -> Self {
+        Decompress {
+            inner,
+            decoder,
+            input: Bytes::new(),
+            has_input: false,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Consumes this body, returning the inner (still encoded) body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for Decompress<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Data = Bytes;
+    type Error = crate::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut me = self.project();
+        let decoder = match me.decoder {
+            Some(ref mut decoder) => decoder,
+            None => {
+                return match ready!(me.inner.poll_data(cx)) {
+                    Some(Ok(mut buf)) => {
+                        let len = buf.remaining();
+                        Poll::Ready(Some(Ok(buf.copy_to_bytes(len))))
+                    }
+                    Some(Err(e)) => Poll::Ready(Some(Err(crate::Error::new_body(e)))),
+                    None => Poll::Ready(None),
+                };
+            }
+        };
+
+        loop {
+            if *me.done {
+                return Poll::Ready(None);
+            }
+
+            if !me.input.has_remaining() && !*me.eof {
+                match ready!(me.inner.as_mut().poll_data(cx)) {
+                    Some(Ok(mut buf)) => {
+                        let len = buf.remaining();
+                        *me.has_input |= len > 0;
+                        *me.input = buf.copy_to_bytes(len);
+                    }
+                    Some(Err(e)) => {
+                        *me.done = true;
+                        return Poll::Ready(Some(Err(crate::Error::new_body(e))));
+                    }
+                    None => {
+                        *me.eof = true;
+                        // An empty body, such as in a response to HEAD, has
+                        // nothing to decode, even if it claims an encoding.
+                        if !*me.has_input {
+                            *me.done = true;
+                            return Poll::Ready(None);
+                        }
+                    }
+                }
+            }
+
+            // Decode until the decoder is stuck, or a frame is ready.
+            let mut progressed = true;
+            while progressed && decoder.output_len() < MAX_FRAME_SIZE {
+                let before = decoder.output_len();
+                match decoder.decode(me.input) {
+                    Ok(n) => {
+                        me.input.advance(n);
+                        progressed = n > 0 || decoder.output_len() > before;
+                    }
+                    Err(e) => {
+                        *me.done = true;
+                        return Poll::Ready(Some(Err(crate::Error::new_body(e))));
+                    }
+                }
+            }
+
+            if !progressed {
+                if me.input.has_remaining() {
+                    *me.done = true;
+                    let e = io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "unexpected data after the encoded body",
+                    );
+                    return Poll::Ready(Some(Err(crate::Error::new_body(e))));
+                }
+                if *me.eof {
+                    *me.done = true;
+                    if let Err(e) = decoder.finish() {
+                        return Poll::Ready(Some(Err(crate::Error::new_body(e))));
+                    }
+                }
+            }
+
+            let decoded = decoder.take();
+            if !decoded.is_empty() {
+                return Poll::Ready(Some(Ok(decoded)));
+            }
+        }
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project()
+            .inner
+            .poll_trailers(cx)
+            .map_err(crate::Error::new_body)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.decoder {
+            Some(_) => self.done,
+            None => self.inner.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.decoder {
+            // The decoded length can't be known ahead of time.
+            Some(_) => SizeHint::default(),
+            None => self.inner.size_hint(),
+        }
+    }
+}
+
+impl<B> fmt::Debug for Decompress<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Decompress")
+            .field("decoder", &self.decoder)
+            .finish()
+    }
+}
+
+// ===== impl Decoder =====
+
+impl Decoder {
+    fn from_headers(headers: &HeaderMap) -> Option<Decoder> {
+        let mut values = headers.get_all(CONTENT_ENCODING).iter();
+        let value = values.next()?;
+        if values.next().is_some() {
+            // Multiple codings were applied, which isn't supported.
+            return None;
+        }
+        let coding = value.to_str().ok()?.trim();
+
+        if coding.eq_ignore_ascii_case("gzip") || coding.eq_ignore_ascii_case("x-gzip") {
+            Some(Decoder::Gzip(flate2::write::GzDecoder::new(Vec::new())))
+        } else if coding.eq_ignore_ascii_case("deflate") {
+            Some(Decoder::Deflate(DeflateDecoder {
+                raw: flate2::Decompress::new(true),
+                out: Vec::new(),
+                finished: false,
+            }))
+        } else if coding.eq_ignore_ascii_case("br") {
+            Some(Decoder::Brotli(Box::new(BrotliDecoder {
+                state: BrotliState::new(
+                    StandardAlloc::default(),
+                    StandardAlloc::default(),
+                    StandardAlloc::default(),
+                ),
+                buf: vec![0; DECODE_BUF_SIZE].into_boxed_slice(),
+                out: Vec::new(),
+                finished: false,
+            })))
+        } else if coding.eq_ignore_ascii_case("zstd") {
+            zstd::stream::raw::Decoder::new().ok().map(|raw| {
+                Decoder::Zstd(ZstdDecoder {
+                    raw,
+                    out: Vec::new(),
+                    frame_done: false,
+                })
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Decodes some of `input`, returning how much of it was used.
+    ///
+    /// Each call only decodes up to a buffer's worth of output, which is
+    /// added to what `take` returns.
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        match *self {
+            Decoder::Gzip(ref mut d) => d.write(input),
+            Decoder::Deflate(ref mut d) => d.decode(input),
+            Decoder::Brotli(ref mut d) => d.decode(input),
+            Decoder::Zstd(ref mut d) => d.decode(input),
+        }
+    }
+
+    /// Checks that the encoded body was complete, once all of it was
+    /// decoded.
+    fn finish(&mut self) -> io::Result<()> {
+        match *self {
+            Decoder::Gzip(ref mut d) => d.try_finish(),
+            Decoder::Deflate(ref d) if d.finished => Ok(()),
+            Decoder::Brotli(ref d) if d.finished => Ok(()),
+            Decoder::Zstd(ref d) if d.frame_done => Ok(()),
+            Decoder::Deflate(_) | Decoder::Brotli(_) | Decoder::Zstd(_) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "encoded body ended early",
+            )),
+        }
+    }
+
+    fn output_len(&self) -> usize {
+        match *self {
+            Decoder::Gzip(ref d) => d.get_ref().len(),
+            Decoder::Deflate(ref d) => d.out.len(),
+            Decoder::Brotli(ref d) => d.out.len(),
+            Decoder::Zstd(ref d) => d.out.len(),
+        }
+    }
+
+    fn take(&mut self) -> Bytes {
+        let out = match *self {
+            Decoder::Gzip(ref mut d) => d.get_mut(),
+            Decoder::Deflate(ref mut d) => &mut d.out,
+            Decoder::Brotli(ref mut d) => &mut d.out,
+            Decoder::Zstd(ref mut d) => &mut d.out,
+        };
+        Bytes::from(mem::take(out))
+    }
+}
+
+impl DeflateDecoder {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        self.out.reserve(DECODE_BUF_SIZE);
+        let before = self.raw.total_in();
+        let status = self
+            .raw
+            .decompress_vec(input, &mut self.out, flate2::FlushDecompress::None)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.finished = status == flate2::Status::StreamEnd;
+        Ok((self.raw.total_in() - before) as usize)
+    }
+}
+
+impl BrotliDecoder {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        let mut available_in = input.len();
+        let mut input_offset = 0;
+        let mut available_out = self.buf.len();
+        let mut output_offset = 0;
+        let mut total_out = 0;
+        let result = brotli::BrotliDecompressStream(
+            &mut available_in,
+            &mut input_offset,
+            input,
+            &mut available_out,
+            &mut output_offset,
+            &mut self.buf,
+            &mut total_out,
+            &mut self.state,
+        );
+        self.out.extend_from_slice(&self.buf[..output_offset]);
+        match result {
+            BrotliResult::ResultFailure => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid brotli data",
+            )),
+            BrotliResult::ResultSuccess => {
+                self.finished = true;
+                Ok(input_offset)
+            }
+            BrotliResult::NeedsMoreInput | BrotliResult::NeedsMoreOutput => Ok(input_offset),
+        }
+    }
+}
+
+impl ZstdDecoder {
+    fn decode(&mut self, input: &[u8]) -> io::Result<usize> {
+        let start = self.out.len();
+        self.out.reserve(DECODE_BUF_SIZE);
+        let mut src = InBuffer::around(input);
+        let mut dst = OutBuffer::around_pos(&mut self.out, start);
+        let hint = self.raw.run(&mut src, &mut dst)?;
+        let written = dst.pos() - start;
+        // A hint of 0 means a frame was just completed. A step without
+        // progress says nothing new about the frame.
+        if src.pos() > 0 || written > 0 {
+            self.frame_done = hint == 0;
+        }
+        Ok(src.pos())
+    }
+}
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let coding = match *self {
+            Decoder::Gzip(_) => "gzip",
+            Decoder::Deflate(_) => "deflate",
+            Decoder::Brotli(_) => "br",
+            Decoder::Zstd(_) => "zstd",
+        };
+        f.write_str(coding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use http::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+    use http::{Request, Response};
+
+    use super::{
+        decompress, set_accept_encoding, Decompress, DecompressionLayer, DECODE_BUF_SIZE,
+        MAX_FRAME_SIZE,
+    };
+    use crate::body::{Body, Full};
+    use crate::common::{task, Future, Pin, Poll};
+    use crate::Recv;
+
+    const CODINGS: &[&str] = &["gzip", "deflate", "br", "zstd"];
+
+    fn encode(coding: &str, data: &[u8]) -> Vec<u8> {
+        match coding {
+            "gzip" => {
+                let mut enc =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(data).unwrap();
+                enc.finish().unwrap()
+            }
+            "deflate" => {
+                let mut enc =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(data).unwrap();
+                enc.finish().unwrap()
+            }
+            "br" => {
+                let mut out = Vec::new();
+                {
+                    let mut enc = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+                    enc.write_all(data).unwrap();
+                }
+                out
+            }
+            "zstd" => zstd::stream::encode_all(data, 0).unwrap(),
+            _ => unreachable!("unknown coding {}", coding),
+        }
+    }
+
+    // Decodes `encoded`, sent in a single chunk, returning the frames
+    // decoded before the end or an error.
+    async fn decode_frames(
+        coding: &str,
+        encoded: Vec<u8>,
+    ) -> (Vec<bytes::Bytes>, Option<crate::Error>) {
+        let res = Response::builder()
+            .header(CONTENT_ENCODING, coding)
+            .body(Full::new(bytes::Bytes::from(encoded)))
+            .unwrap();
+        collect_frames(decompress(res).into_body()).await
+    }
+
+    async fn collect_frames<B>(mut body: Decompress<B>) -> (Vec<bytes::Bytes>, Option<crate::Error>)
+    where
+        B: Body + Unpin,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let mut frames = Vec::new();
+        loop {
+            match futures_util::future::poll_fn(|cx| std::pin::Pin::new(&mut body).poll_data(cx))
+                .await
+            {
+                Some(Ok(frame)) => frames.push(frame),
+                Some(Err(e)) => return (frames, Some(e)),
+                None => return (frames, None),
+            }
+        }
+    }
+
+    fn assert_bounded(coding: &str, frames: &[bytes::Bytes]) {
+        for frame in frames {
+            assert!(
+                frame.len() <= MAX_FRAME_SIZE + DECODE_BUF_SIZE,
+                "{}: {}",
+                coding,
+                frame.len()
+            );
+        }
+    }
+
+    // A client connection as the `tower::Service` the layer wraps.
+    #[cfg(all(feature = "http1", feature = "tokio"))]
+    struct Sender(crate::client::conn::http1::SendRequest<Recv>);
+
+    #[cfg(all(feature = "http1", feature = "tokio"))]
+    impl tower_service::Service<Request<Recv>> for Sender {
+        type Response = Response<Recv>;
+        type Error = crate::Error;
+        type Future = Pin<Box<dyn Future<Output = crate::Result<Response<Recv>>>>>;
+
+        fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
+            self.0.poll_ready(cx)
+        }
+
+        fn call(&mut self, req: Request<Recv>) -> Self::Future {
+            Box::pin(self.0.send_request(req))
+        }
+    }
+
+    #[test]
+    fn accept_encoding_is_not_overridden() {
+        let mut req = Request::new(());
+        set_accept_encoding(&mut req);
+        assert_eq!(req.headers()[ACCEPT_ENCODING], "gzip, deflate, br, zstd");
+
+        let mut req = Request::builder()
+            .header(ACCEPT_ENCODING, "identity")
+            .body(())
+            .unwrap();
+        set_accept_encoding(&mut req);
+        assert_eq!(req.headers()[ACCEPT_ENCODING], "identity");
+    }
+
+    #[test]
+    fn unsupported_encoding_is_untouched() {
+        let res = Response::builder()
+            .header(CONTENT_ENCODING, "compress")
+            .header(CONTENT_LENGTH, "3")
+            .body(Recv::empty())
+            .unwrap();
+        let res = decompress(res);
+        assert_eq!(res.headers()[CONTENT_ENCODING], "compress");
+        assert_eq!(res.headers()[CONTENT_LENGTH], "3");
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn gzip_body_is_decoded() {
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(b"hello world").unwrap();
+        let encoded = enc.finish().unwrap();
+
+        let (mut tx, body) = Recv::channel();
+        let res = Response::builder()
+            .header(CONTENT_ENCODING, "gzip")
+            .header(CONTENT_LENGTH, encoded.len())
+            .body(body)
+            .unwrap();
+        let res = decompress(res);
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert!(res.headers().get(CONTENT_LENGTH).is_none());
+
+        tokio::spawn(async move {
+            let (a, b) = encoded.split_at(encoded.len() / 2);
+            tx.send_data(a.to_vec().into()).await.unwrap();
+            tx.send_data(b.to_vec().into()).await.unwrap();
+        });
+
+        let bytes = crate::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(bytes, "hello world");
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn each_coding_is_decoded() {
+        let data = b"hello world, ".repeat(1000);
+        for coding in CODINGS {
+            let (frames, err) = decode_frames(coding, encode(coding, &data)).await;
+            assert!(err.is_none(), "{}: {:?}", coding, err);
+            assert_eq!(frames.concat(), data, "{}", coding);
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn truncated_body_is_an_error() {
+        let data = b"hello world, ".repeat(1000);
+        for coding in CODINGS {
+            let mut encoded = encode(coding, &data);
+            encoded.truncate(encoded.len() - 4);
+            let (_, err) = decode_frames(coding, encoded).await;
+            assert!(err.is_some(), "{} body cut off wasn't an error", coding);
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn trailing_data_is_an_error() {
+        for coding in CODINGS {
+            let mut encoded = encode(coding, b"hello world");
+            encoded.extend_from_slice(b"garbage");
+            let (_, err) = decode_frames(coding, encoded).await;
+            assert!(err.is_some(), "{} trailing data wasn't an error", coding);
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn highly_compressed_chunk_is_decoded_in_bounded_frames() {
+        let data = vec![0; 4 * 1024 * 1024];
+        for coding in CODINGS {
+            let encoded = encode(coding, &data);
+            assert!(encoded.len() < 64 * 1024, "{}", coding);
+            let (frames, err) = decode_frames(coding, encoded).await;
+            assert!(err.is_none(), "{}: {:?}", coding, err);
+            assert_eq!(frames.iter().map(|f| f.len()).sum::<usize>(), data.len());
+            assert_bounded(coding, &frames);
+        }
+    }
+
+    #[cfg(all(feature = "http1", feature = "tokio", not(miri)))]
+    #[tokio::test]
+    async fn layer_decodes_responses_of_a_client_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tower_layer::Layer;
+        use tower_service::Service;
+
+        let (io, mut server) = tokio::io::duplex(64 * 1024);
+        let (tx, conn) = crate::client::conn::http1::handshake(crate::rt::TokioIo::new(io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let mut client = DecompressionLayer::new().layer(Sender(tx));
+
+        let data = vec![0; 4 * 1024 * 1024];
+        let encoded = encode("gzip", &data);
+        let server = async move {
+            let mut buf = [0; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            let head = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+            let res_head = format!(
+                "HTTP/1.1 200 OK\r\ncontent-encoding: gzip\r\ncontent-length: {}\r\n\r\n",
+                encoded.len()
+            );
+            server.write_all(res_head.as_bytes()).await.unwrap();
+            server.write_all(&encoded).await.unwrap();
+            (head, server)
+        };
+        let req = Request::builder()
+            .header("host", "hyper.rs")
+            .body(Recv::empty())
+            .unwrap();
+        let res = async move {
+            futures_util::future::poll_fn(|cx| client.poll_ready(cx))
+                .await
+                .unwrap();
+            client.call(req).await.unwrap()
+        };
+        let (res, (head, _server)) = tokio::join!(res, server);
+
+        assert!(
+            head.contains("\r\naccept-encoding: gzip, deflate, br, zstd\r\n"),
+            "{:?}",
+            head
+        );
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert!(res.headers().get(CONTENT_LENGTH).is_none());
+
+        let (frames, err) = collect_frames(res.into_body()).await;
+        assert!(err.is_none(), "{:?}", err);
+        assert_eq!(frames.iter().map(|f| f.len()).sum::<usize>(), data.len());
+        assert_bounded("gzip", &frames);
+    }
+}
@@ -16,3 +16,13 @@ cfg_feature! {
     pub mod conn;
     pub(super) mod dispatch;
 }
+
+pub mod conditional;
+
+#[cfg(feature = "tcp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tcp")))]
+pub mod connect;
+
+#[cfg(feature = "decompression")]
+#[cfg_attr(docsrs, doc(cfg(feature = "decompression")))]
+pub mod decompress;
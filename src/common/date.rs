@@ -1,54 +1,87 @@
 use std::cell::RefCell;
 use std::fmt::{self, Write};
 use std::str;
-use std::time::{Duration, SystemTime};
+use std::thread::LocalKey;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[cfg(feature = "http2")]
 use http::header::HeaderValue;
 use httpdate::HttpDate;
 
+use crate::common::time::Time;
+
 // "Sun, 06 Nov 1994 08:49:37 GMT".len()
 pub(crate) const DATE_VALUE_LENGTH: usize = 29;
 
+/// Writes the cached date, which `update` refreshed from the system clock.
+///
+/// With a user-provided clock, the date is read from it instead, as it may
+/// not move along with the system clock.
 #[cfg(feature = "http1")]
-pub(crate) fn extend(dst: &mut Vec<u8>) {
-    CACHED.with(|cache| {
-        dst.extend_from_slice(cache.borrow().buffer());
-    })
+pub(crate) fn extend(dst: &mut Vec<u8>, time: &Time) {
+    match time.clock() {
+        Some(clock) => with_checked(&CLOCK_CACHED, clock.system_now(), |date| {
+            dst.extend_from_slice(date)
+        }),
+        None => CACHED.with(|cache| dst.extend_from_slice(cache.borrow().buffer())),
+    }
 }
 
 #[cfg(feature = "http1")]
 pub(crate) fn update() {
     CACHED.with(|cache| {
-        cache.borrow_mut().check();
+        cache.borrow_mut().check(SystemTime::now());
     })
 }
 
 #[cfg(feature = "http2")]
-pub(crate) fn update_and_header_value() -> HeaderValue {
-    CACHED.with(|cache| {
+pub(crate) fn update_and_header_value(time: &Time) -> HeaderValue {
+    let (key, now) = match time.clock() {
+        Some(clock) => (&CLOCK_CACHED, clock.system_now()),
+        None => (&CACHED, SystemTime::now()),
+    };
+    with_checked(key, now, |date| {
+        HeaderValue::from_bytes(date).expect("Date format should be valid HeaderValue")
+    })
+}
+
+fn with_checked<R>(
+    key: &'static LocalKey<RefCell<CachedDate>>,
+    now: SystemTime,
+    f: impl FnOnce(&[u8]) -> R,
+) -> R {
+    key.with(|cache| {
         let mut cache = cache.borrow_mut();
-        cache.check();
-        HeaderValue::from_bytes(cache.buffer()).expect("Date format should be valid HeaderValue")
+        cache.check(now);
+        f(cache.buffer())
     })
 }
 
+/// The date of a second, formatted once and shared by every connection on
+/// the thread.
 struct CachedDate {
     bytes: [u8; DATE_VALUE_LENGTH],
     pos: usize,
-    next_update: SystemTime,
+    /// The second since the epoch that `bytes` is the date of.
+    secs: u64,
 }
 
+// One per thread, so the hot path never contends on it.
 thread_local!(static CACHED: RefCell<CachedDate> = RefCell::new(CachedDate::new()));
 
+// Dates from user-provided clocks, kept apart so that a mocked clock can't
+// leak its date into connections using the system clock.
+thread_local!(static CLOCK_CACHED: RefCell<CachedDate> = RefCell::new(CachedDate::new()));
+
 impl CachedDate {
     fn new() -> Self {
+        let now = SystemTime::now();
         let mut cache = CachedDate {
             bytes: [0; DATE_VALUE_LENGTH],
             pos: 0,
-            next_update: SystemTime::now(),
+            secs: secs_since_epoch(now),
         };
-        cache.update(cache.next_update);
+        cache.render(now);
         cache
     }
 
@@ -56,18 +89,16 @@ impl CachedDate {
         &self.bytes[..]
     }
 
-    fn check(&mut self) {
-        let now = SystemTime::now();
-        if now > self.next_update {
-            self.update(now);
+    /// Renders `now` if it is in another second than the cached date,
+    /// whether later or, for a clock set back, earlier.
+    fn check(&mut self, now: SystemTime) {
+        let secs = secs_since_epoch(now);
+        if secs != self.secs {
+            self.render(now);
+            self.secs = secs;
         }
     }
 
-    fn update(&mut self, now: SystemTime) {
-        self.render(now);
-        self.next_update = now + Duration::new(1, 0);
-    }
-
     fn render(&mut self, now: SystemTime) {
         self.pos = 0;
         let _ = write!(self, "{}", HttpDate::from(now));
@@ -75,6 +106,12 @@ impl CachedDate {
     }
 }
 
+fn secs_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
 impl fmt::Write for CachedDate {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         let len = s.len();
@@ -96,15 +133,67 @@ mod tests {
         assert_eq!(DATE_VALUE_LENGTH, "Sun, 06 Nov 1994 08:49:37 GMT".len());
     }
 
+    #[cfg(feature = "http1")]
+    #[test]
+    fn test_date_from_clock() {
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        struct Frozen;
+
+        impl crate::rt::Clock for Frozen {
+            fn now(&self) -> Instant {
+                Instant::now()
+            }
+
+            fn system_now(&self) -> SystemTime {
+                UNIX_EPOCH + Duration::from_secs(784_111_777)
+            }
+        }
+
+        let mut time = Time::default();
+        time.set_clock(Arc::new(Frozen));
+        let mut dst = Vec::new();
+        extend(&mut dst, &time);
+        assert_eq!(dst, b"Sun, 06 Nov 1994 08:49:37 GMT");
+
+        // Connections on the system clock don't see it.
+        let mut dst = Vec::new();
+        extend(&mut dst, &Time::default());
+        assert_ne!(dst, b"Sun, 06 Nov 1994 08:49:37 GMT");
+
+        // Nor does refreshing the system date change it.
+        update();
+        let mut dst = Vec::new();
+        extend(&mut dst, &time);
+        assert_eq!(dst, b"Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn test_date_check_renders_other_seconds() {
+        use std::time::Duration;
+
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut date = CachedDate::new();
+
+        date.check(at(784_111_777));
+        assert_eq!(date.buffer(), b"Sun, 06 Nov 1994 08:49:37 GMT");
+        date.check(at(784_111_777) + Duration::from_millis(999));
+        assert_eq!(date.buffer(), b"Sun, 06 Nov 1994 08:49:37 GMT");
+        // Also when the clock goes back.
+        date.check(at(784_111_776));
+        assert_eq!(date.buffer(), b"Sun, 06 Nov 1994 08:49:36 GMT");
+    }
+
     #[cfg(feature = "nightly")]
     #[bench]
     fn bench_date_check(b: &mut Bencher) {
         let mut date = CachedDate::new();
         // cache the first update
-        date.check();
+        date.check(SystemTime::now());
 
         b.iter(|| {
-            date.check();
+            date.check(SystemTime::now());
         });
     }
 
@@ -3,17 +3,42 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
-#[cfg(feature = "server")]
+#[cfg(any(feature = "server", all(feature = "client", feature = "http2")))]
 use crate::body::Body;
+#[cfg(all(feature = "http2", feature = "client"))]
+use crate::proto::h2::client::H2ClientFuture;
 #[cfg(all(feature = "http2", feature = "server"))]
 use crate::proto::h2::server::H2Stream;
 use crate::rt::Executor;
+#[cfg(all(feature = "http2", feature = "client"))]
+use crate::rt::{Read, Write};
 
 #[cfg(feature = "server")]
 pub trait ConnStreamExec<F, B: Body>: Clone {
     fn execute_h2stream(&mut self, fut: H2Stream<F, B>);
 }
 
+#[cfg(all(feature = "http2", feature = "client"))]
+pub trait H2ClientConnExec<B, T>
+where
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    fn execute_h2_future(&mut self, future: H2ClientFuture<B, T>);
+}
+
+// Spawns the connection task at handshake. Unlike the other tasks of an
+// HTTP/2 client connection, it doesn't hold the request bodies, so boxing it
+// as `Send` only needs the IO and the body's data to be `Send`.
+#[cfg(all(feature = "http2", feature = "client"))]
+pub trait H2ClientConnTaskExec<B, T>
+where
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    fn execute_h2_conn(&mut self, future: H2ClientFuture<B, T>);
+}
+
 pub(crate) type BoxSendFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
 
 // Either the user provides an executor for background tasks, or we panic.
@@ -60,6 +85,30 @@ where
     }
 }
 
+#[cfg(all(feature = "http2", feature = "client"))]
+impl<B, T> H2ClientConnExec<B, T> for Exec
+where
+    H2ClientFuture<B, T>: Future<Output = ()> + Send + 'static,
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    fn execute_h2_future(&mut self, future: H2ClientFuture<B, T>) {
+        self.execute(future)
+    }
+}
+
+#[cfg(all(feature = "http2", feature = "client"))]
+impl<B, T> H2ClientConnTaskExec<B, T> for Exec
+where
+    B: Body + 'static,
+    B::Data: Send,
+    T: Read + Write + Unpin + Send + 'static,
+{
+    fn execute_h2_conn(&mut self, future: H2ClientFuture<B, T>) {
+        self.execute(future.into_conn_task())
+    }
+}
+
 // ==== impl Executor =====
 
 #[cfg(feature = "server")]
@@ -74,6 +123,32 @@ where
     }
 }
 
+#[cfg(all(feature = "http2", feature = "client"))]
+impl<E, B, T> H2ClientConnExec<B, T> for E
+where
+    E: Executor<H2ClientFuture<B, T>>,
+    H2ClientFuture<B, T>: Future<Output = ()>,
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    fn execute_h2_future(&mut self, future: H2ClientFuture<B, T>) {
+        self.execute(future)
+    }
+}
+
+#[cfg(all(feature = "http2", feature = "client"))]
+impl<E, B, T> H2ClientConnTaskExec<B, T> for E
+where
+    E: Executor<H2ClientFuture<B, T>>,
+    H2ClientFuture<B, T>: Future<Output = ()>,
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    fn execute_h2_conn(&mut self, future: H2ClientFuture<B, T>) {
+        self.execute(future)
+    }
+}
+
 // If http2 is not enable, we just have a stub here, so that the trait bounds
 // that *would* have been needed are still checked. Why?
 //
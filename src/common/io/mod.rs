@@ -1,3 +1,23 @@
+#[cfg(any(
+    all(feature = "http2", any(feature = "client", feature = "server")),
+    feature = "tcp",
+    all(test, feature = "http1", any(feature = "client", feature = "server")),
+    all(test, feature = "futures-io"),
+))]
+mod compat;
 mod rewind;
+#[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+mod stats;
 
+#[cfg(any(
+    all(feature = "http2", any(feature = "client", feature = "server")),
+    feature = "tcp",
+    all(test, feature = "http1", any(feature = "client", feature = "server")),
+    all(test, feature = "futures-io"),
+))]
+pub(crate) use self::compat::Compat;
 pub(crate) use self::rewind::Rewind;
+#[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+pub(crate) use self::stats::{Counted, OnIo};
+#[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+pub use self::stats::{IoEvent, IoStats};
@@ -0,0 +1,325 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::marker::Unpin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::common::{task, Pin, Poll};
+use crate::metrics::MetricsHandle;
+use crate::rt::{Read, ReadBuf, ReadBufCursor, Write};
+
+/// Byte counters and timestamps for the IO of a single connection.
+///
+/// An `IoStats` is a cheaply cloneable handle. Give a clone to a connection
+/// builder, and keep another one around to inspect how many bytes were
+/// transferred, and when the first and last bytes were seen in each
+/// direction.
+#[derive(Clone, Default)]
+pub struct IoStats {
+    inner: Arc<Counters>,
+}
+
+/// A transfer over the transport of a connection, passed to the callback
+/// set with `io_callback` on a client connection builder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IoEvent {
+    /// This many bytes were read.
+    Read(usize),
+    /// This many bytes were written.
+    Written(usize),
+}
+
+/// The callback set with `io_callback`.
+#[derive(Clone)]
+pub(crate) struct OnIo(Arc<dyn Fn(IoEvent) + Send + Sync>);
+
+struct Counters {
+    /// The timestamps are stored as nanoseconds since this, plus one, so
+    /// that zero means none.
+    start: Instant,
+    read: Direction,
+    written: Direction,
+}
+
+#[derive(Default)]
+struct Direction {
+    bytes: AtomicU64,
+    first: AtomicU64,
+    last: AtomicU64,
+}
+
+impl IoStats {
+    /// Create a new, empty set of counters.
+    pub fn new() -> IoStats {
+        IoStats::default()
+    }
+
+    /// Total number of bytes read from the transport.
+    pub fn bytes_read(&self) -> u64 {
+        self.inner.read.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Total number of bytes written to the transport.
+    pub fn bytes_written(&self) -> u64 {
+        self.inner.written.bytes.load(Ordering::Relaxed)
+    }
+
+    /// When the first byte was read from the transport, if any.
+    pub fn first_byte_read_at(&self) -> Option<Instant> {
+        self.inner.at(&self.inner.read.first)
+    }
+
+    /// When the most recent byte was read from the transport, if any.
+    pub fn last_byte_read_at(&self) -> Option<Instant> {
+        self.inner.at(&self.inner.read.last)
+    }
+
+    /// When the first byte was written to the transport, if any.
+    pub fn first_byte_written_at(&self) -> Option<Instant> {
+        self.inner.at(&self.inner.written.first)
+    }
+
+    /// When the most recent byte was written to the transport, if any.
+    pub fn last_byte_written_at(&self) -> Option<Instant> {
+        self.inner.at(&self.inner.written.last)
+    }
+
+    fn record_read(&self, n: usize) {
+        self.inner.record(&self.inner.read, n);
+    }
+
+    fn record_written(&self, n: usize) {
+        self.inner.record(&self.inner.written, n);
+    }
+}
+
+impl fmt::Debug for IoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoStats")
+            .field("bytes_read", &self.bytes_read())
+            .field("bytes_written", &self.bytes_written())
+            .finish()
+    }
+}
+
+impl Default for Counters {
+    fn default() -> Counters {
+        Counters {
+            start: Instant::now(),
+            read: Direction::default(),
+            written: Direction::default(),
+        }
+    }
+}
+
+impl Counters {
+    fn record(&self, direction: &Direction, n: usize) {
+        if n == 0 {
+            return;
+        }
+        let nanos = Instant::now()
+            .saturating_duration_since(self.start)
+            .as_nanos();
+        let now = u64::try_from(nanos).unwrap_or(u64::MAX - 1) + 1;
+        direction.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        if direction.first.load(Ordering::Relaxed) == 0 {
+            let _ = direction
+                .first
+                .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+        }
+        direction.last.fetch_max(now, Ordering::Relaxed);
+    }
+
+    fn at(&self, timestamp: &AtomicU64) -> Option<Instant> {
+        match timestamp.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(self.start + Duration::from_nanos(nanos - 1)),
+        }
+    }
+}
+
+impl OnIo {
+    pub(crate) fn new<F>(f: F) -> OnIo
+    where
+        F: Fn(IoEvent) + Send + Sync + 'static,
+    {
+        OnIo(Arc::new(f))
+    }
+
+    fn call(&self, event: IoEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for OnIo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnIo").finish()
+    }
+}
+
+/// Wraps an IO, recording transferred bytes into an optional `IoStats`,
+/// optional `Metrics`, and an optional callback.
+#[derive(Debug)]
+pub(crate) struct Counted<T> {
+    inner: T,
+    stats: Option<IoStats>,
+    metrics: Option<MetricsHandle>,
+    on_io: Option<OnIo>,
+}
+
+impl<T> Counted<T> {
+    pub(crate) fn new(
+        io: T,
+        stats: Option<IoStats>,
+        metrics: Option<MetricsHandle>,
+        on_io: Option<OnIo>,
+    ) -> Self {
+        Counted {
+            inner: io,
+            stats,
+            metrics,
+            on_io,
+        }
+    }
+
+    fn record_read(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if let Some(ref stats) = self.stats {
+            stats.record_read(n);
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.get().bytes_read(n as u64);
+        }
+        if let Some(ref on_io) = self.on_io {
+            on_io.call(IoEvent::Read(n));
+        }
+    }
+
+    fn record_written(&self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if let Some(ref stats) = self.stats {
+            stats.record_written(n);
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.get().bytes_written(n as u64);
+        }
+        if let Some(ref on_io) = self.on_io {
+            on_io.call(IoEvent::Written(n));
+        }
+    }
+
+    #[cfg(feature = "http1")]
+    pub(crate) fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Read for Counted<T>
+where
+    T: Read + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        // Read through a buffer of our own, to know how much was read.
+        let n = unsafe {
+            let mut counted = ReadBuf::uninit(buf.as_mut());
+            ready!(Pin::new(&mut self.inner).poll_read(cx, counted.unfilled()))?;
+            counted.filled().len()
+        };
+        unsafe {
+            buf.advance(n);
+        }
+        self.record_read(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Write for Counted<T>
+where
+    T: Write + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.record_written(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let n = ready!(Pin::new(&mut self.inner).poll_write_vectored(cx, bufs))?;
+        self.record_written(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Counted, IoEvent, IoStats, OnIo};
+    use crate::common::io::Compat;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn counts_bytes_in_both_directions() {
+        let stats = IoStats::new();
+        let mock = tokio_test::io::Builder::new()
+            .read(b"hello")
+            .write(b"world!")
+            .build();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_io = {
+            let events = events.clone();
+            OnIo::new(move |event| events.lock().unwrap().push(event))
+        };
+        let mut io = Compat::new(Counted::new(
+            Compat::new(mock),
+            Some(stats.clone()),
+            None,
+            Some(on_io),
+        ));
+
+        let mut buf = [0; 5];
+        io.read_exact(&mut buf).await.expect("read");
+        io.write_all(b"world!").await.expect("write");
+
+        assert_eq!(stats.bytes_read(), 5);
+        assert_eq!(stats.bytes_written(), 6);
+        assert!(stats.first_byte_read_at().is_some());
+        assert!(stats.first_byte_written_at() <= stats.last_byte_written_at());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [IoEvent::Read(5), IoEvent::Written(6)]
+        );
+    }
+}
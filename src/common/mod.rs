@@ -14,9 +14,12 @@ pub(crate) mod date;
 pub(crate) mod exec;
 pub(crate) mod io;
 mod never;
+pub(crate) mod spsc;
 pub(crate) mod task;
 #[cfg(any(feature = "http1", feature = "http2", feature = "server"))]
 pub(crate) mod time;
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub(crate) mod trace;
 pub(crate) mod watch;
 
 #[cfg(any(feature = "http1", feature = "http2"))]
@@ -0,0 +1,303 @@
+//! A bounded SPSC channel.
+//!
+//! - There is exactly one sender and one receiver, so the ring of values
+//!   needs no lock: the sender only moves the tail, and the receiver only
+//!   moves the head.
+//! - Each side wakes the other after every value, but a side only registers
+//!   a waker once it has to wait, on a full ring for the sender or an empty
+//!   one for the receiver. Waking a side that isn't waiting finds no waker,
+//!   and costs a single atomic operation.
+//! - A last value can be sent past the capacity, which closes the channel
+//!   after it, such as to report an error.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{self, Poll};
+
+use futures_util::task::AtomicWaker;
+
+const TX_CLOSED: usize = 0b01;
+const RX_CLOSED: usize = 0b10;
+
+pub(crate) fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    debug_assert!(capacity > 0, "spsc::channel capacity of 0");
+
+    let slots = (0..capacity)
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+    let shared = Arc::new(Shared {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        last: UnsafeCell::new(None),
+        state: AtomicUsize::new(0),
+        tx_waker: AtomicWaker::new(),
+        rx_waker: AtomicWaker::new(),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+pub(crate) struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub(crate) struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Counts the values received, only stored by the receiver.
+    head: AtomicUsize,
+    /// Counts the values sent, only stored by the sender.
+    tail: AtomicUsize,
+    /// Written by the sender before setting `TX_CLOSED`, and only read by
+    /// the receiver after seeing it.
+    last: UnsafeCell<Option<T>>,
+    state: AtomicUsize,
+    tx_waker: AtomicWaker,
+    rx_waker: AtomicWaker,
+}
+
+// The slots are only accessed by one side at a time, as handed over through
+// `head` and `tail`.
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn slot(&self, count: usize) -> *mut MaybeUninit<T> {
+        self.slots[count % self.slots.len()].get()
+    }
+
+    fn is_full(&self, tail: usize) -> bool {
+        tail.wrapping_sub(self.head.load(Ordering::Acquire)) == self.slots.len()
+    }
+}
+
+impl<T> Sender<T> {
+    /// Whether a value can be sent, or an `Err` if the receiver is gone.
+    pub(crate) fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), ()>> {
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        for register in [false, true] {
+            if shared.state.load(Ordering::Acquire) != 0 {
+                return Poll::Ready(Err(()));
+            }
+            if !shared.is_full(tail) {
+                return Poll::Ready(Ok(()));
+            }
+            if register {
+                break;
+            }
+            // Check again after registering, in case the receiver made room
+            // in between.
+            shared.tx_waker.register(cx.waker());
+        }
+        Poll::Pending
+    }
+
+    /// Sends `value`, or gives it back if the ring is full or the channel
+    /// closed.
+    pub(crate) fn try_send(&mut self, value: T) -> Result<(), T> {
+        let shared = &*self.shared;
+        let tail = shared.tail.load(Ordering::Relaxed);
+        if shared.state.load(Ordering::Acquire) != 0 || shared.is_full(tail) {
+            return Err(value);
+        }
+        // SAFETY: the ring isn't full, so the receiver is done with this
+        // slot, and won't read it until `tail` is stored.
+        unsafe {
+            (*shared.slot(tail)).write(value);
+        }
+        shared.tail.store(tail.wrapping_add(1), Ordering::Release);
+        shared.rx_waker.wake();
+        Ok(())
+    }
+
+    /// Sends `value` even if the ring is full, and closes the channel after
+    /// it.
+    pub(crate) fn send_last(&mut self, value: T) {
+        let shared = &*self.shared;
+        if shared.state.load(Ordering::Acquire) & TX_CLOSED != 0 {
+            return;
+        }
+        // SAFETY: the receiver doesn't read `last` until `TX_CLOSED` is set.
+        unsafe {
+            *shared.last.get() = Some(value);
+        }
+        self.close();
+    }
+
+    fn close(&self) {
+        self.shared.state.fetch_or(TX_CLOSED, Ordering::AcqRel);
+        self.shared.rx_waker.wake();
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next value, or `None` once the sender is gone and every
+    /// value was received.
+    pub(crate) fn poll_recv(&mut self, cx: &mut task::Context<'_>) -> Poll<Option<T>> {
+        for register in [false, true] {
+            if let Some(ret) = self.try_recv() {
+                return Poll::Ready(ret);
+            }
+            if register {
+                break;
+            }
+            self.shared.rx_waker.register(cx.waker());
+        }
+        Poll::Pending
+    }
+
+    /// `None` if there is nothing to receive yet.
+    fn try_recv(&mut self) -> Option<Option<T>> {
+        let shared = &*self.shared;
+        // Load the state first, so every value sent before closing is seen.
+        let closed = shared.state.load(Ordering::Acquire) & TX_CLOSED != 0;
+        let head = shared.head.load(Ordering::Relaxed);
+        if head != shared.tail.load(Ordering::Acquire) {
+            // SAFETY: the sender stored `tail` after writing this slot, and
+            // won't write it again until `head` is stored.
+            let value = unsafe { ptr::read((*shared.slot(head)).as_ptr()) };
+            shared.head.store(head.wrapping_add(1), Ordering::Release);
+            shared.tx_waker.wake();
+            return Some(Some(value));
+        }
+        if closed {
+            // SAFETY: the sender is done with `last` once `TX_CLOSED` is set.
+            return Some(unsafe { (*shared.last.get()).take() });
+        }
+        None
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.state.fetch_or(RX_CLOSED, Ordering::AcqRel);
+        self.shared.tx_waker.wake();
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let mut head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        while head != tail {
+            // SAFETY: the slots from `head` to `tail` hold values never
+            // received.
+            unsafe {
+                ptr::drop_in_place((*self.slot(head)).as_mut_ptr());
+            }
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::channel;
+
+    #[test]
+    fn ring_wraps_around() {
+        let (mut tx, mut rx) = channel(2);
+        let mut rx_recv = tokio_test::task::spawn(());
+
+        for round in 0..3 {
+            tx.try_send(round * 2).unwrap();
+            tx.try_send(round * 2 + 1).unwrap();
+            assert_eq!(tx.try_send(99), Err(99), "ring is full");
+
+            for expected in [round * 2, round * 2 + 1] {
+                let value = rx_recv.enter(|cx, _| rx.poll_recv(cx));
+                assert_eq!(value, std::task::Poll::Ready(Some(expected)));
+            }
+        }
+
+        assert!(rx_recv.enter(|cx, _| rx.poll_recv(cx)).is_pending());
+    }
+
+    #[test]
+    fn send_last_past_capacity() {
+        let (mut tx, mut rx) = channel(1);
+        let mut rx_recv = tokio_test::task::spawn(());
+
+        tx.try_send("first").unwrap();
+        tx.send_last("last");
+        assert_eq!(tx.try_send("closed"), Err("closed"));
+
+        let mut recv = || rx_recv.enter(|cx, _| rx.poll_recv(cx));
+        assert_eq!(recv(), std::task::Poll::Ready(Some("first")));
+        assert_eq!(recv(), std::task::Poll::Ready(Some("last")));
+        assert_eq!(recv(), std::task::Poll::Ready(None));
+    }
+
+    #[test]
+    fn wakes_sender_when_room() {
+        let (mut tx, mut rx) = channel(1);
+        tx.try_send(1).unwrap();
+
+        let mut tx_ready = tokio_test::task::spawn(());
+        assert!(tx_ready.enter(|cx, _| tx.poll_ready(cx)).is_pending());
+
+        let mut rx_recv = tokio_test::task::spawn(());
+        assert!(rx_recv.enter(|cx, _| rx.poll_recv(cx)).is_ready());
+        assert!(tx_ready.is_woken());
+        assert!(tx_ready.enter(|cx, _| tx.poll_ready(cx)).is_ready());
+
+        drop(rx);
+        assert_eq!(
+            tx_ready.enter(|cx, _| tx.poll_ready(cx)),
+            std::task::Poll::Ready(Err(()))
+        );
+    }
+
+    #[test]
+    fn wakes_receiver_once_waiting() {
+        let (mut tx, mut rx) = channel(2);
+        let mut rx_recv = tokio_test::task::spawn(());
+
+        assert!(rx_recv.enter(|cx, _| rx.poll_recv(cx)).is_pending());
+        tx.try_send(1).unwrap();
+        assert!(rx_recv.is_woken());
+        assert_eq!(
+            rx_recv.enter(|cx, _| rx.poll_recv(cx)),
+            std::task::Poll::Ready(Some(1))
+        );
+
+        // Not waiting anymore, so nothing is registered to wake.
+        tx.try_send(2).unwrap();
+        assert!(!rx_recv.is_woken());
+        assert_eq!(
+            rx_recv.enter(|cx, _| rx.poll_recv(cx)),
+            std::task::Poll::Ready(Some(2))
+        );
+    }
+
+    #[test]
+    fn drops_values_never_received() {
+        let value = Arc::new(());
+        let (mut tx, rx) = channel(4);
+        tx.try_send(value.clone()).unwrap();
+        tx.send_last(value.clone());
+        drop((tx, rx));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -4,14 +4,16 @@ use std::{
     time::{Duration, Instant},
 };
 
+use crate::rt::Clock;
 use crate::rt::Sleep;
 use crate::rt::Timer;
 
-/// A user-provided timer to time background tasks.
-#[derive(Clone)]
-pub(crate) enum Time {
-    Timer(Arc<dyn Timer + Send + Sync>),
-    Empty,
+/// A user-provided timer to time background tasks, and clock to read the
+/// current time from.
+#[derive(Clone, Default)]
+pub(crate) struct Time {
+    timer: Option<Arc<dyn Timer + Send + Sync>>,
+    clock: Option<Arc<dyn Clock + Send + Sync>>,
 }
 
 impl fmt::Debug for Time {
@@ -55,30 +57,48 @@ impl<F> Future for HyperTimeout<F> where F: Future {
 */
 
 impl Time {
-    pub(crate) fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
-        match *self {
-            Time::Empty => {
-                panic!("You must supply a timer.")
-            }
-            Time::Timer(ref t) => t.sleep(duration),
+    pub(crate) fn set_timer(&mut self, timer: Arc<dyn Timer + Send + Sync>) {
+        self.timer = Some(timer);
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = Some(clock);
+    }
+
+    /// The user-provided clock, if any.
+    ///
+    /// Without one, the system clock is used.
+    #[cfg(feature = "server")]
+    pub(crate) fn clock(&self) -> Option<&(dyn Clock + Send + Sync)> {
+        self.clock.as_deref()
+    }
+
+    /// The current instant from the clock, or else from the timer, so that
+    /// deadlines computed from it match the timer's sleeps.
+    pub(crate) fn now(&self) -> Instant {
+        match (&self.clock, &self.timer) {
+            (Some(clock), _) => clock.now(),
+            (None, Some(timer)) => timer.now(),
+            (None, None) => Instant::now(),
         }
     }
 
+    pub(crate) fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+        self.timer().sleep(duration)
+    }
+
     pub(crate) fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
-        match *self {
-            Time::Empty => {
-                panic!("You must supply a timer.")
-            }
-            Time::Timer(ref t) => t.sleep_until(deadline),
-        }
+        self.timer().sleep_until(deadline)
     }
 
     pub(crate) fn reset(&self, sleep: &mut Pin<Box<dyn Sleep>>, new_deadline: Instant) {
-        match *self {
-            Time::Empty => {
-                panic!("You must supply a timer.")
-            }
-            Time::Timer(ref t) => t.reset(sleep, new_deadline),
+        self.timer().reset(sleep, new_deadline)
+    }
+
+    fn timer(&self) -> &(dyn Timer + Send + Sync) {
+        match self.timer {
+            Some(ref timer) => &**timer,
+            None => panic!("You must supply a timer."),
         }
     }
 }
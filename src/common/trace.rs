@@ -0,0 +1,139 @@
+//! Structured spans and events, emitted with the `tracing-spans` feature.
+//!
+//! The span, event, and field names here are documented in the crate root,
+//! and are stable. Without the feature, these do nothing.
+
+use tracing::Span;
+
+const ENABLED: bool = cfg!(feature = "tracing-spans");
+
+/// A span for the lifetime of a connection.
+///
+/// `role` is `"client"` or `"server"`, and `version` is `"HTTP/1.1"` or
+/// `"HTTP/2"`.
+pub(crate) fn conn_span(role: &'static str, version: &'static str) -> Span {
+    if !ENABLED {
+        return Span::none();
+    }
+    let span = tracing::debug_span!(target: "hyper::conn", "conn", role, version);
+    span.in_scope(|| tracing::debug!(target: "hyper::conn", "connection opened"));
+    span
+}
+
+/// The connection is done, with or without an error.
+pub(crate) fn conn_closed(error: Option<&crate::Error>) {
+    if !ENABLED {
+        return;
+    }
+    match error {
+        Some(error) => {
+            tracing::debug!(target: "hyper::conn", error = %error, "connection closed")
+        }
+        None => tracing::debug!(target: "hyper::conn", "connection closed"),
+    }
+}
+
+/// A request head was parsed.
+#[cfg(all(feature = "server", feature = "http1"))]
+pub(crate) fn request_parsed(
+    method: &http::Method,
+    uri: &http::Uri,
+    version: http::Version,
+    headers: usize,
+) {
+    if !ENABLED {
+        return;
+    }
+    tracing::debug!(
+        target: "hyper::parse",
+        method = %method,
+        uri = %uri,
+        version = ?version,
+        headers,
+        "request head parsed",
+    );
+}
+
+/// A response head was parsed.
+#[cfg(all(feature = "client", feature = "http1"))]
+pub(crate) fn response_parsed(status: http::StatusCode, version: http::Version, headers: usize) {
+    if !ENABLED {
+        return;
+    }
+    tracing::debug!(
+        target: "hyper::parse",
+        status = status.as_u16(),
+        version = ?version,
+        headers,
+        "response head parsed",
+    );
+}
+
+/// A chunk of a body was read or written.
+///
+/// `direction` is `"read"` or `"write"`.
+pub(crate) fn body_chunk(direction: &'static str, bytes: usize) {
+    if !ENABLED {
+        return;
+    }
+    tracing::trace!(target: "hyper::body", direction, bytes, "body chunk");
+}
+
+/// An HTTP/2 error, for a stream or the whole connection.
+///
+/// `scope` is `"stream"` or `"connection"`.
+#[cfg(feature = "http2")]
+pub(crate) fn h2_error(scope: &'static str, error: &h2::Error) {
+    if !ENABLED {
+        return;
+    }
+    tracing::debug!(
+        target: "hyper::h2",
+        scope,
+        reason = error.reason().map(u32::from),
+        io = error.is_io(),
+        error = %error,
+        "h2 error",
+    );
+}
+
+/// An HTTP/2 frame was sent or received, with the `debug` feature.
+///
+/// `direction` is `"sent"` or `"received"`.
+#[cfg(feature = "http2")]
+pub(crate) fn h2_frame(
+    direction: &'static str,
+    kind: &'static str,
+    stream_id: u32,
+    flags: u8,
+    len: u32,
+) {
+    if !ENABLED {
+        return;
+    }
+    tracing::debug!(
+        target: "hyper::h2::frame",
+        direction,
+        kind,
+        stream_id,
+        flags,
+        len,
+        "h2 frame",
+    );
+}
+
+/// A poll of a service or response body took longer than the threshold.
+///
+/// `source` is `"service"` or `"body"`.
+#[cfg(feature = "server")]
+pub(crate) fn slow_poll(source: &'static str, duration: std::time::Duration) {
+    if !ENABLED {
+        return;
+    }
+    tracing::warn!(
+        target: "hyper::poll",
+        source,
+        micros = duration.as_micros() as u64,
+        "slow poll",
+    );
+}
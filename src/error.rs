@@ -23,7 +23,7 @@ pub(super) enum Kind {
     User(User),
     /// A message reached EOF, but is not complete.
     #[allow(unused)]
-    IncompleteMessage,
+    IncompleteMessage(Incomplete),
     /// A connection received a message (or bytes) when not waiting for one.
     #[cfg(feature = "http1")]
     UnexpectedMessage,
@@ -34,19 +34,25 @@ pub(super) enum Kind {
     /// An `io::Error` that occurred while trying to read or write to a network stream.
     #[cfg(any(feature = "http1", feature = "http2"))]
     Io,
-    /// Error creating a TcpListener.
-    #[cfg(all(feature = "tcp", feature = "server"))]
-    Listen,
     /// User took too long to send headers
     #[cfg(all(feature = "http1", feature = "server"))]
     HeaderTimeout,
+    /// Connections didn't finish a graceful shutdown in time.
+    #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+    GracefulShutdownTimeout,
+    /// A request body was larger than the server allows.
+    #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+    BodyTooLarge,
+    /// Error performing the TLS handshake of a server connection.
+    #[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+    Tls,
     /// Error while reading a body from connection.
-    #[cfg(any(feature = "http1", feature = "http2"))]
+    #[cfg(any(feature = "http1", feature = "http2", feature = "decompression"))]
     Body,
     /// Error while writing a body to connection.
     #[cfg(any(feature = "http1", feature = "http2"))]
     BodyWrite,
-    /// Error calling AsyncWrite::shutdown()
+    /// Error calling Write::poll_shutdown()
     #[cfg(feature = "http1")]
     Shutdown,
 
@@ -76,10 +82,22 @@ pub(super) enum Header {
     Token,
     #[cfg(feature = "http1")]
     ContentLengthInvalid,
+    #[cfg(feature = "http1")]
+    ContentLengthOverflow,
+    #[cfg(feature = "http1")]
+    ContentLengthSigned,
+    #[cfg(feature = "http1")]
+    ContentLengthWhitespace,
+    #[cfg(feature = "http1")]
+    ContentLengthMismatch,
     #[cfg(all(feature = "http1", feature = "server"))]
     TransferEncodingInvalid,
     #[cfg(feature = "http1")]
     TransferEncodingUnexpected,
+    #[cfg(feature = "http1")]
+    TransferEncodingUnsupported,
+    #[cfg(feature = "http2")]
+    TrailerForbidden,
 }
 
 #[derive(Debug)]
@@ -102,6 +120,14 @@ pub(super) enum User {
     #[cfg(feature = "http1")]
     #[cfg(feature = "server")]
     UnsupportedStatusCode,
+    /// User tried to send a header value or reason phrase with bytes that
+    /// can't be sent.
+    #[cfg(feature = "http1")]
+    InvalidHeader(InvalidHeader),
+    /// User tried to send a trailer field that RFC 9110 forbids, with the
+    /// `Reject` trailer policy.
+    #[cfg(feature = "http2")]
+    ForbiddenTrailer,
 
     /// User tried polling for an upgrade that doesn't exist.
     NoUpgrade,
@@ -110,6 +136,10 @@ pub(super) enum User {
     #[cfg(feature = "http1")]
     ManualUpgrade,
 
+    /// User sent a request whose `VersionPolicy` doesn't match the connection.
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    VersionPolicy,
+
     /// User called `server::Connection::without_shutdown()` on an HTTP/2 conn.
     #[cfg(feature = "server")]
     WithoutShutdownNonHttp1,
@@ -119,6 +149,161 @@ pub(super) enum User {
     AbortedByCallback,
 }
 
+/// The kind of an [`Error`], returned by [`Error::kind`].
+///
+/// More kinds may be added in the future, so matching on this should
+/// include a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A message couldn't be parsed.
+    Parse(ParseKind),
+    /// Something took too long.
+    Timeout(TimeoutKind),
+    /// The connection closed before a message could complete.
+    IncompleteMessage,
+    /// A connection received a message, or bytes, when not waiting for one.
+    UnexpectedMessage,
+    /// A pending request was dropped before it was processed.
+    Canceled,
+    /// A sender's channel is closed.
+    ChannelClosed,
+    /// Reading from or writing to the transport failed.
+    Io,
+    /// Reading a body from the connection failed.
+    Body,
+    /// Writing a body to the connection failed.
+    BodyWrite,
+    /// The user aborted writing the outgoing body.
+    BodyWriteAborted,
+    /// A request body was larger than the server's configured maximum.
+    BodyTooLarge,
+    /// The TLS handshake of a server connection failed.
+    Tls,
+    /// Shutting down the transport failed.
+    Shutdown,
+    /// An HTTP/2 error, with the error code sent or received, if any.
+    Http2 {
+        /// The HTTP/2 error code, such as `0x2` for `INTERNAL_ERROR`.
+        code: Option<u32>,
+    },
+    /// The user's `Service` returned an error.
+    Service,
+    /// The user's body returned an error.
+    UserBody,
+    /// An outgoing header value or reason phrase had bytes that can't be
+    /// sent, such as CR, LF or NUL.
+    InvalidHeader,
+    /// hyper was used in a way it doesn't support, such as sending a header
+    /// in the wrong context, or polling for an upgrade that doesn't exist.
+    User,
+}
+
+/// The kind of an [`ErrorKind::Parse`] error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ParseKind {
+    /// An invalid method.
+    Method,
+    /// An invalid version.
+    Version,
+    /// An HTTP/2 connection preface, where an HTTP/1 message was expected.
+    VersionH2,
+    /// An invalid URI.
+    Uri,
+    /// A URI longer than the server allows.
+    UriTooLong,
+    /// An invalid header.
+    Header,
+    /// An invalid `content-length` header.
+    ContentLength,
+    /// A `content-length` header with a length that doesn't fit in a `u64`.
+    ContentLengthOverflow,
+    /// A `content-length` header with a `+` or `-` sign.
+    ContentLengthSigned,
+    /// A `content-length` header with whitespace in the length.
+    ContentLengthWhitespace,
+    /// Several `content-length` headers, or a list of lengths, that
+    /// disagree.
+    ContentLengthMismatch,
+    /// An invalid or unexpected `transfer-encoding` header.
+    TransferEncoding,
+    /// A message head larger than allowed, or with too many headers.
+    TooLarge,
+    /// An invalid status code or reason phrase.
+    Status,
+    /// An internal error in hyper or its dependencies.
+    Internal,
+}
+
+/// The kind of an [`ErrorKind::Timeout`] error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TimeoutKind {
+    /// A client didn't send a request head in time.
+    Header,
+    /// An HTTP/2 peer didn't acknowledge a keep-alive ping in time.
+    KeepAlive,
+    /// An HTTP/2 client request waited too long for a stream.
+    PendingRequest,
+    /// Connections didn't finish a graceful shutdown in time.
+    GracefulShutdown,
+}
+
+/// Where the connection closed in an [`ErrorKind::IncompleteMessage`] error,
+/// returned by [`Error::incomplete_message`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Incomplete {
+    stage: IncompleteStage,
+    request_sent: bool,
+}
+
+/// How much of the message being received had arrived when the connection
+/// closed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IncompleteStage {
+    /// No bytes of a message had arrived.
+    BeforeHead,
+    /// Part of the message head had arrived.
+    Head,
+    /// The head had arrived, but not all of the body.
+    Body,
+    /// The whole message had arrived, but the message being sent wasn't
+    /// finished.
+    AfterBody,
+}
+
+impl Incomplete {
+    /// How much of the message being received had arrived.
+    pub fn stage(&self) -> IncompleteStage {
+        self.stage
+    }
+
+    /// Whether the request had been fully written when the connection
+    /// closed.
+    ///
+    /// This is always false for servers.
+    pub fn request_sent(&self) -> bool {
+        self.request_sent
+    }
+}
+
+/// The field of an [`ErrorKind::InvalidHeader`] error, returned by
+/// [`Error::invalid_header`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidHeader {
+    name: Option<http::header::HeaderName>,
+}
+
+impl InvalidHeader {
+    /// The name of the header with the invalid value, or `None` if it was
+    /// the reason phrase.
+    pub fn name(&self) -> Option<&http::header::HeaderName> {
+        self.name.as_ref()
+    }
+}
+
 // Sentinel type to indicate the error was caused by a timeout.
 #[derive(Debug)]
 pub(super) struct TimedOut;
@@ -159,8 +344,48 @@ impl Error {
     }
 
     /// Returns true if the connection closed before a message could complete.
+    ///
+    /// This includes an incoming HTTP/1 body that the connection closed in
+    /// before its end, which is an incomplete message rather than a body
+    /// error, with [`IncompleteStage::Body`].
     pub fn is_incomplete_message(&self) -> bool {
-        matches!(self.inner.kind, Kind::IncompleteMessage)
+        matches!(self.inner.kind, Kind::IncompleteMessage(_))
+    }
+
+    /// Returns where the connection closed, if it closed before a message
+    /// could complete.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hyper::error::IncompleteStage;
+    ///
+    /// // No response arrived, so the request can be sent again if it is
+    /// // idempotent, or if the server can't have received all of it.
+    /// fn can_retry(err: &hyper::Error, idempotent: bool) -> bool {
+    ///     match err.incomplete_message() {
+    ///         Some(incomplete) if incomplete.stage() == IncompleteStage::BeforeHead => {
+    ///             idempotent || !incomplete.request_sent()
+    ///         }
+    ///         _ => false,
+    ///     }
+    /// }
+    /// ```
+    pub fn incomplete_message(&self) -> Option<Incomplete> {
+        match self.inner.kind {
+            Kind::IncompleteMessage(incomplete) => Some(incomplete),
+            _ => None,
+        }
+    }
+
+    /// Returns the field that made sending a message fail, if it had bytes
+    /// that can't be sent.
+    pub fn invalid_header(&self) -> Option<&InvalidHeader> {
+        match self.inner.kind {
+            #[cfg(feature = "http1")]
+            Kind::User(User::InvalidHeader(ref invalid)) => Some(invalid),
+            _ => None,
+        }
     }
 
     /// Returns true if the body write was aborted.
@@ -168,11 +393,90 @@ impl Error {
         matches!(self.inner.kind, Kind::User(User::BodyWriteAborted))
     }
 
+    /// Returns true if a request body was larger than the server's
+    /// configured maximum.
+    pub fn is_body_too_large(&self) -> bool {
+        #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+        {
+            matches!(self.inner.kind, Kind::BodyTooLarge)
+        }
+        #[cfg(not(all(feature = "server", any(feature = "http1", feature = "http2"))))]
+        {
+            false
+        }
+    }
+
+    /// Returns true if the TLS handshake of a server connection failed.
+    pub fn is_tls(&self) -> bool {
+        #[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+        {
+            matches!(self.inner.kind, Kind::Tls)
+        }
+        #[cfg(not(all(feature = "server", feature = "http1", feature = "http2")))]
+        {
+            false
+        }
+    }
+
     /// Returns true if the error was caused by a timeout.
     pub fn is_timeout(&self) -> bool {
         self.find_source::<TimedOut>().is_some()
     }
 
+    /// Returns the kind of this error, to match on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hyper::error::{ErrorKind, ParseKind};
+    ///
+    /// fn is_retryable(err: &hyper::Error) -> bool {
+    ///     match err.kind() {
+    ///         ErrorKind::Parse(ParseKind::TooLarge) => false,
+    ///         ErrorKind::IncompleteMessage | ErrorKind::ChannelClosed => true,
+    ///         _ => false,
+    ///     }
+    /// }
+    /// ```
+    pub fn kind(&self) -> ErrorKind {
+        let timed_out = self.find_source::<TimedOut>().is_some();
+        match self.inner.kind {
+            Kind::Parse(ref parse) => ErrorKind::Parse(parse.public()),
+            Kind::User(ref user) => user.public(),
+            Kind::IncompleteMessage(_) => ErrorKind::IncompleteMessage,
+            #[cfg(feature = "http1")]
+            Kind::UnexpectedMessage => ErrorKind::UnexpectedMessage,
+            Kind::Canceled if timed_out => ErrorKind::Timeout(TimeoutKind::PendingRequest),
+            Kind::Canceled => ErrorKind::Canceled,
+            Kind::ChannelClosed => ErrorKind::ChannelClosed,
+            #[cfg(any(feature = "http1", feature = "http2"))]
+            Kind::Io => ErrorKind::Io,
+            #[cfg(all(feature = "http1", feature = "server"))]
+            Kind::HeaderTimeout => ErrorKind::Timeout(TimeoutKind::Header),
+            #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+            Kind::GracefulShutdownTimeout => ErrorKind::Timeout(TimeoutKind::GracefulShutdown),
+            #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+            Kind::BodyTooLarge => ErrorKind::BodyTooLarge,
+            #[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+            Kind::Tls => ErrorKind::Tls,
+            #[cfg(any(feature = "http1", feature = "http2", feature = "decompression"))]
+            Kind::Body => ErrorKind::Body,
+            #[cfg(any(feature = "http1", feature = "http2"))]
+            Kind::BodyWrite => ErrorKind::BodyWrite,
+            #[cfg(feature = "http1")]
+            Kind::Shutdown => ErrorKind::Shutdown,
+            #[cfg(feature = "http2")]
+            Kind::Http2 if timed_out => ErrorKind::Timeout(TimeoutKind::KeepAlive),
+            #[cfg(feature = "http2")]
+            Kind::Http2 => ErrorKind::Http2 {
+                code: self
+                    .find_source::<h2::Error>()
+                    .and_then(|h2_err| h2_err.reason())
+                    .map(u32::from),
+            },
+        }
+    }
+
     /// Consumes the error, returning its cause.
     pub fn into_cause(self) -> Option<Box<dyn StdError + Send + Sync>> {
         self.inner.cause
@@ -190,7 +494,7 @@ impl Error {
     }
 
     #[cfg(any(all(feature = "http1", feature = "server"), feature = "ffi"))]
-    pub(super) fn kind(&self) -> &Kind {
+    pub(super) fn inner_kind(&self) -> &Kind {
         &self.inner.kind
     }
 
@@ -221,8 +525,11 @@ impl Error {
     }
 
     #[cfg(feature = "http1")]
-    pub(super) fn new_incomplete() -> Error {
-        Error::new(Kind::IncompleteMessage)
+    pub(super) fn new_incomplete(stage: IncompleteStage, request_sent: bool) -> Error {
+        Error::new(Kind::IncompleteMessage(Incomplete {
+            stage,
+            request_sent,
+        }))
     }
 
     #[cfg(feature = "http1")]
@@ -245,16 +552,11 @@ impl Error {
         Error::new(Kind::Io).with(cause)
     }
 
-    #[cfg(all(feature = "server", feature = "tcp"))]
-    pub(super) fn new_listen<E: Into<Cause>>(cause: E) -> Error {
-        Error::new(Kind::Listen).with(cause)
-    }
-
     pub(super) fn new_closed() -> Error {
         Error::new(Kind::ChannelClosed)
     }
 
-    #[cfg(any(feature = "http1", feature = "http2"))]
+    #[cfg(any(feature = "http1", feature = "http2", feature = "decompression"))]
     pub(super) fn new_body<E: Into<Cause>>(cause: E) -> Error {
         Error::new(Kind::Body).with(cause)
     }
@@ -283,12 +585,47 @@ impl Error {
         Error::new(Kind::HeaderTimeout)
     }
 
+    #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+    pub(super) fn new_graceful_shutdown_timeout() -> Error {
+        Error::new(Kind::GracefulShutdownTimeout).with(TimedOut)
+    }
+
+    #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+    pub(super) fn new_body_too_large() -> Error {
+        Error::new(Kind::BodyTooLarge)
+    }
+
+    #[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+    pub(super) fn new_tls<E: Into<Cause>>(cause: E) -> Error {
+        Error::new(Kind::Tls).with(cause)
+    }
+
     #[cfg(feature = "http1")]
     #[cfg(feature = "server")]
     pub(super) fn new_user_unsupported_status_code() -> Error {
         Error::new_user(User::UnsupportedStatusCode)
     }
 
+    #[cfg(feature = "http1")]
+    pub(super) fn new_user_invalid_header(name: Option<http::header::HeaderName>) -> Error {
+        let field = match name {
+            Some(ref name) => name.as_str().to_owned(),
+            None => "reason phrase".to_owned(),
+        };
+        Error::new_user(User::InvalidHeader(InvalidHeader { name })).with(field)
+    }
+
+    #[cfg(feature = "http2")]
+    pub(super) fn new_user_forbidden_trailer(name: http::header::HeaderName) -> Error {
+        Error::new_user(User::ForbiddenTrailer).with(name.as_str().to_owned())
+    }
+
+    #[cfg(feature = "http2")]
+    pub(super) fn new_trailer_forbidden(name: http::header::HeaderName) -> Error {
+        Error::new(Kind::Parse(Parse::Header(Header::TrailerForbidden)))
+            .with(name.as_str().to_owned())
+    }
+
     pub(super) fn new_user_no_upgrade() -> Error {
         Error::new_user(User::NoUpgrade)
     }
@@ -318,6 +655,11 @@ impl Error {
         Error::new(Kind::Shutdown).with(cause)
     }
 
+    #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+    pub(super) fn new_user_version_policy() -> Error {
+        Error::new_user(User::VersionPolicy)
+    }
+
     #[cfg(feature = "ffi")]
     pub(super) fn new_user_aborted_by_callback() -> Error {
         Error::new_user(User::AbortedByCallback)
@@ -350,6 +692,22 @@ impl Error {
             Kind::Parse(Parse::Header(Header::ContentLengthInvalid)) => {
                 "invalid content-length parsed"
             }
+            #[cfg(feature = "http1")]
+            Kind::Parse(Parse::Header(Header::ContentLengthOverflow)) => {
+                "content-length parsed is too large for a u64"
+            }
+            #[cfg(feature = "http1")]
+            Kind::Parse(Parse::Header(Header::ContentLengthSigned)) => {
+                "content-length parsed has a sign"
+            }
+            #[cfg(feature = "http1")]
+            Kind::Parse(Parse::Header(Header::ContentLengthWhitespace)) => {
+                "content-length parsed has whitespace"
+            }
+            #[cfg(feature = "http1")]
+            Kind::Parse(Parse::Header(Header::ContentLengthMismatch)) => {
+                "content-lengths parsed don't match"
+            }
             #[cfg(all(feature = "http1", feature = "server"))]
             Kind::Parse(Parse::Header(Header::TransferEncodingInvalid)) => {
                 "invalid transfer-encoding parsed"
@@ -358,21 +716,33 @@ impl Error {
             Kind::Parse(Parse::Header(Header::TransferEncodingUnexpected)) => {
                 "unexpected transfer-encoding parsed"
             }
+            #[cfg(feature = "http1")]
+            Kind::Parse(Parse::Header(Header::TransferEncodingUnsupported)) => {
+                "unsupported transfer-encoding parsed"
+            }
+            #[cfg(feature = "http2")]
+            Kind::Parse(Parse::Header(Header::TrailerForbidden)) => {
+                "forbidden trailer field parsed"
+            }
             Kind::Parse(Parse::TooLarge) => "message head is too large",
             Kind::Parse(Parse::Status) => "invalid HTTP status-code parsed",
             Kind::Parse(Parse::Internal) => {
                 "internal error inside Hyper and/or its dependencies, please report"
             }
-            Kind::IncompleteMessage => "connection closed before message completed",
+            Kind::IncompleteMessage(_) => "connection closed before message completed",
             #[cfg(feature = "http1")]
             Kind::UnexpectedMessage => "received unexpected message from connection",
             Kind::ChannelClosed => "channel closed",
             Kind::Canceled => "operation was canceled",
-            #[cfg(all(feature = "server", feature = "tcp"))]
-            Kind::Listen => "error creating server listener",
             #[cfg(all(feature = "http1", feature = "server"))]
             Kind::HeaderTimeout => "read header from client timeout",
-            #[cfg(any(feature = "http1", feature = "http2"))]
+            #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+            Kind::GracefulShutdownTimeout => "graceful shutdown timed out",
+            #[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+            Kind::BodyTooLarge => "request body exceeded the maximum size",
+            #[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+            Kind::Tls => "error performing TLS handshake",
+            #[cfg(any(feature = "http1", feature = "http2", feature = "decompression"))]
             Kind::Body => "error reading a body from connection",
             #[cfg(any(feature = "http1", feature = "http2"))]
             Kind::BodyWrite => "error writing a body to connection",
@@ -396,9 +766,15 @@ impl Error {
             Kind::User(User::UnsupportedStatusCode) => {
                 "response has 1xx status code, not supported by server"
             }
+            #[cfg(feature = "http1")]
+            Kind::User(User::InvalidHeader(_)) => "user sent header with invalid bytes",
+            #[cfg(feature = "http2")]
+            Kind::User(User::ForbiddenTrailer) => "user sent a forbidden trailer field",
             Kind::User(User::NoUpgrade) => "no upgrade available",
             #[cfg(feature = "http1")]
             Kind::User(User::ManualUpgrade) => "upgrade expected but low level API in use",
+            #[cfg(all(feature = "client", any(feature = "http1", feature = "http2")))]
+            Kind::User(User::VersionPolicy) => "request version policy not supported by connection",
             #[cfg(feature = "server")]
             Kind::User(User::WithoutShutdownNonHttp1) => {
                 "without_shutdown() called on a non-HTTP/1 connection"
@@ -452,6 +828,22 @@ impl Parse {
         Parse::Header(Header::ContentLengthInvalid)
     }
 
+    pub(crate) fn content_length_overflow() -> Self {
+        Parse::Header(Header::ContentLengthOverflow)
+    }
+
+    pub(crate) fn content_length_signed() -> Self {
+        Parse::Header(Header::ContentLengthSigned)
+    }
+
+    pub(crate) fn content_length_whitespace() -> Self {
+        Parse::Header(Header::ContentLengthWhitespace)
+    }
+
+    pub(crate) fn content_length_mismatch() -> Self {
+        Parse::Header(Header::ContentLengthMismatch)
+    }
+
     #[cfg(all(feature = "http1", feature = "server"))]
     pub(crate) fn transfer_encoding_invalid() -> Self {
         Parse::Header(Header::TransferEncodingInvalid)
@@ -460,6 +852,64 @@ impl Parse {
     pub(crate) fn transfer_encoding_unexpected() -> Self {
         Parse::Header(Header::TransferEncodingUnexpected)
     }
+
+    pub(crate) fn transfer_encoding_unsupported() -> Self {
+        Parse::Header(Header::TransferEncodingUnsupported)
+    }
+
+    pub(crate) fn header_value_invalid() -> Self {
+        Parse::Header(Header::Token)
+    }
+}
+
+impl Parse {
+    fn public(&self) -> ParseKind {
+        match *self {
+            Parse::Method => ParseKind::Method,
+            Parse::Version => ParseKind::Version,
+            #[cfg(feature = "http1")]
+            Parse::VersionH2 => ParseKind::VersionH2,
+            Parse::Uri => ParseKind::Uri,
+            Parse::UriTooLong => ParseKind::UriTooLong,
+            Parse::Header(Header::Token) => ParseKind::Header,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::ContentLengthInvalid) => ParseKind::ContentLength,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::ContentLengthOverflow) => ParseKind::ContentLengthOverflow,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::ContentLengthSigned) => ParseKind::ContentLengthSigned,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::ContentLengthWhitespace) => ParseKind::ContentLengthWhitespace,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::ContentLengthMismatch) => ParseKind::ContentLengthMismatch,
+            #[cfg(all(feature = "http1", feature = "server"))]
+            Parse::Header(Header::TransferEncodingInvalid) => ParseKind::TransferEncoding,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::TransferEncodingUnexpected) => ParseKind::TransferEncoding,
+            #[cfg(feature = "http1")]
+            Parse::Header(Header::TransferEncodingUnsupported) => ParseKind::TransferEncoding,
+            #[cfg(feature = "http2")]
+            Parse::Header(Header::TrailerForbidden) => ParseKind::Header,
+            Parse::TooLarge => ParseKind::TooLarge,
+            Parse::Status => ParseKind::Status,
+            Parse::Internal => ParseKind::Internal,
+        }
+    }
+}
+
+impl User {
+    fn public(&self) -> ErrorKind {
+        match *self {
+            #[cfg(any(feature = "http1", feature = "http2"))]
+            User::Body => ErrorKind::UserBody,
+            User::BodyWriteAborted => ErrorKind::BodyWriteAborted,
+            #[cfg(any(feature = "http1", feature = "http2"))]
+            User::Service => ErrorKind::Service,
+            #[cfg(feature = "http1")]
+            User::InvalidHeader(_) => ErrorKind::InvalidHeader,
+            _ => ErrorKind::User,
+        }
+    }
 }
 
 impl From<httparse::Error> for Parse {
@@ -525,6 +975,68 @@ mod tests {
         assert_eq!(mem::size_of::<Error>(), mem::size_of::<usize>());
     }
 
+    #[test]
+    fn kind() {
+        assert_eq!(Error::new_closed().kind(), ErrorKind::ChannelClosed);
+        assert_eq!(
+            Error::new_body_write_aborted().kind(),
+            ErrorKind::BodyWriteAborted
+        );
+        assert_eq!(
+            Error::from(Parse::Uri).kind(),
+            ErrorKind::Parse(ParseKind::Uri)
+        );
+        assert_eq!(Error::new_user_no_upgrade().kind(), ErrorKind::User);
+    }
+
+    #[cfg(feature = "http1")]
+    #[test]
+    fn incomplete_message() {
+        let err = Error::new_incomplete(IncompleteStage::Head, true);
+        assert_eq!(err.kind(), ErrorKind::IncompleteMessage);
+        let incomplete = err.incomplete_message().unwrap();
+        assert_eq!(incomplete.stage(), IncompleteStage::Head);
+        assert!(incomplete.request_sent());
+
+        assert_eq!(Error::new_closed().incomplete_message(), None);
+    }
+
+    #[cfg(feature = "http1")]
+    #[test]
+    fn kind_parse() {
+        assert_eq!(
+            Error::new_too_large().kind(),
+            ErrorKind::Parse(ParseKind::TooLarge)
+        );
+        assert_eq!(
+            Error::from(Parse::transfer_encoding_unexpected()).kind(),
+            ErrorKind::Parse(ParseKind::TransferEncoding)
+        );
+    }
+
+    #[cfg(all(feature = "http1", feature = "server"))]
+    #[test]
+    fn kind_timeout() {
+        assert_eq!(
+            Error::new_header_timeout().kind(),
+            ErrorKind::Timeout(TimeoutKind::Header)
+        );
+        assert_eq!(
+            Error::new_graceful_shutdown_timeout().kind(),
+            ErrorKind::Timeout(TimeoutKind::GracefulShutdown)
+        );
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn kind_h2() {
+        let err = Error::new_h2(h2::Error::from(h2::Reason::REFUSED_STREAM));
+        assert_eq!(err.kind(), ErrorKind::Http2 { code: Some(0x7) });
+
+        let err = Error::new_canceled().with(TimedOut);
+        assert_eq!(err.kind(), ErrorKind::Timeout(TimeoutKind::PendingRequest));
+    }
+
     #[cfg(feature = "http2")]
     #[test]
     fn h2_reason_unknown() {
@@ -10,16 +10,71 @@ use http::HeaderMap;
 use std::collections::HashMap;
 #[cfg(feature = "http2")]
 use std::fmt;
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+use std::sync::atomic::AtomicBool;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server"),
+))]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server"),
+))]
+use std::sync::Arc;
+#[cfg(any(
+    all(feature = "http1", any(feature = "client", feature = "server")),
+    all(feature = "server", feature = "http2"),
+))]
+use std::sync::Mutex;
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+use std::task::{Context, Poll, Waker};
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+use std::time::Instant;
+
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+use crate::common::time::Time;
 
 #[cfg(any(feature = "http1", feature = "ffi"))]
 mod h1_reason_phrase;
 #[cfg(any(feature = "http1", feature = "ffi"))]
 pub use h1_reason_phrase::ReasonPhrase;
 
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+mod h1_lazy_headers;
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+pub use h1_lazy_headers::LazyHeaders;
+
 #[cfg(feature = "http2")]
 /// Represents the `:protocol` pseudo-header used by
 /// the [Extended CONNECT Protocol].
 ///
+/// Clients insert this into the extensions of an HTTP/2 `CONNECT` request to
+/// send an extended `CONNECT`. The server must have enabled the protocol
+/// first, which
+/// [`http2::Connection::is_extended_connect_protocol_enabled`](crate::client::conn::http2::Connection::is_extended_connect_protocol_enabled)
+/// reports.
+///
+/// Servers find it in the extensions of the extended `CONNECT` requests
+/// they receive, if enabled with
+/// [`Http::http2_enable_connect_protocol`](crate::server::conn::Http::http2_enable_connect_protocol).
+///
+/// Either way, [`upgrade::on`](crate::upgrade::on) resolves to the tunneled
+/// stream once the server responds with a success status.
+///
+/// ```
+/// use hyper::ext::Protocol;
+/// use hyper::Request;
+///
+/// let req = Request::connect("https://example.com/chat")
+///     .extension(Protocol::WEBSOCKET)
+///     .body(())
+///     .unwrap();
+///
+/// let protocol = req.extensions().get::<Protocol>().unwrap();
+/// assert_eq!(protocol, "websocket");
+/// ```
+///
 /// [Extended CONNECT Protocol]: https://datatracker.ietf.org/doc/html/rfc8441#section-4
 #[derive(Clone, Eq, PartialEq)]
 pub struct Protocol {
@@ -28,6 +83,15 @@ pub struct Protocol {
 
 #[cfg(feature = "http2")]
 impl Protocol {
+    /// The `websocket` protocol, from [RFC 8441].
+    ///
+    /// [RFC 8441]: https://datatracker.ietf.org/doc/html/rfc8441
+    pub const WEBSOCKET: Protocol = Protocol::from_static("websocket");
+
+    /// The `webtransport` protocol, used to establish WebTransport sessions
+    /// over HTTP/2.
+    pub const WEBTRANSPORT: Protocol = Protocol::from_static("webtransport");
+
     /// Converts a static string to a protocol name.
     pub const fn from_static(value: &'static str) -> Self {
         Self {
@@ -72,6 +136,633 @@ impl fmt::Debug for Protocol {
     }
 }
 
+#[cfg(feature = "http2")]
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "http2")]
+impl PartialEq<str> for Protocol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+#[cfg(feature = "http2")]
+impl PartialEq<&'_ str> for Protocol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Forces the HTTP version used to send a client request.
+///
+/// Insert this into a request's extensions to describe how the connection
+/// it is sent on must have been established. Code picking a connection for
+/// the request, such as a pool, can use it to choose or create the right
+/// one, such as with the `client::conn::auto::Builder`. The `client::conn`
+/// types check it too: sending a request on a connection that doesn't match
+/// its policy fails, instead of silently using another version.
+///
+/// The `http1` and `http2` connections only reject requests forcing the
+/// other version, since they can't tell whether their version was
+/// negotiated. The `auto` connections know how they were established, and
+/// reject any request with another policy.
+#[cfg(feature = "client")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionPolicy {
+    /// The request must be sent over HTTP/1.
+    Http1Only,
+    /// The request must be sent over HTTP/2, without negotiating it first.
+    Http2PriorKnowledge,
+    /// The request can use whichever version was negotiated with ALPN.
+    Negotiated,
+}
+
+/// What an HTTP/1 server connection does with the rest of a request body
+/// that the service stopped reading.
+///
+/// A service can send its whole response while the client is still
+/// uploading the request body, such as to reject it early. The connection
+/// keeps reading the body for as long as the service holds on to it. Once
+/// the service drops the body without reading it to the end, and the
+/// response head has been written, the connection handles the rest of it
+/// according to the `UnreadBody` in the response's extensions.
+///
+/// Without one, only what is already buffered is discarded, and if that
+/// isn't the end of the body, the connection is closed after the response.
+///
+/// This has no effect on HTTP/2, where the stream of the request is reset
+/// instead, without affecting the connection.
+///
+/// # Example
+///
+/// ```
+/// # use bytes::Bytes;
+/// # use http_body_util::Full;
+/// use hyper::ext::UnreadBody;
+/// use hyper::{Response, StatusCode};
+///
+/// let mut res = Response::new(Full::new(Bytes::from("too large")));
+/// *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+/// // Keep the connection if the rest of the body is small.
+/// res.extensions_mut().insert(UnreadBody::Drain(64 * 1024));
+/// ```
+#[cfg(all(feature = "server", feature = "http1"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnreadBody {
+    /// Read and discard up to this many more bytes of the body, keeping the
+    /// connection alive if that reaches its end. If the body is longer, the
+    /// connection is closed after the response.
+    Drain(u64),
+    /// Stop reading, and close the connection after the response.
+    Close,
+}
+
+/// Options for how a server writes a single response.
+///
+/// Insert this into a response's extensions to change, for that response
+/// only, what the connection would otherwise do.
+///
+/// # Example
+///
+/// ```
+/// # use bytes::Bytes;
+/// # use http_body_util::Full;
+/// use hyper::ext::ResponseOptions;
+/// use hyper::Response;
+///
+/// let mut res = Response::new(Full::new(Bytes::from("goodbye")));
+/// res.extensions_mut()
+///     .insert(ResponseOptions::new().close_connection().disable_date_header());
+/// ```
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResponseOptions {
+    disable_chunked: bool,
+    close_connection: bool,
+    max_chunk_size: Option<usize>,
+    disable_date_header: bool,
+}
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+impl ResponseOptions {
+    /// Options that change nothing.
+    pub fn new() -> ResponseOptions {
+        ResponseOptions::default()
+    }
+
+    /// Don't use chunked encoding for an HTTP/1 body of unknown length.
+    ///
+    /// The end of the body is instead marked by closing the connection.
+    /// This has no effect on HTTP/2.
+    pub fn disable_chunked(mut self) -> ResponseOptions {
+        self.disable_chunked = true;
+        self
+    }
+
+    /// Close the HTTP/1 connection after this response, sending a
+    /// `connection: close` header.
+    ///
+    /// This has no effect on HTTP/2, where a response only ends its stream.
+    pub fn close_connection(mut self) -> ResponseOptions {
+        self.close_connection = true;
+        self
+    }
+
+    /// Set the largest chunk of a chunked HTTP/1 body.
+    ///
+    /// Larger chunks of the body are sent as several chunks, without copying
+    /// them. This has no effect on HTTP/2.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn max_chunk_size(mut self, size: usize) -> ResponseOptions {
+        assert!(size > 0, "max_chunk_size must be larger than zero");
+        self.max_chunk_size = Some(size);
+        self
+    }
+
+    /// Don't add a `date` header to this response, if the connection would.
+    pub fn disable_date_header(mut self) -> ResponseOptions {
+        self.disable_date_header = true;
+        self
+    }
+
+    #[cfg(feature = "http1")]
+    pub(crate) fn chunked(&self) -> bool {
+        !self.disable_chunked
+    }
+
+    #[cfg(feature = "http1")]
+    pub(crate) fn closes_connection(&self) -> bool {
+        self.close_connection
+    }
+
+    #[cfg(feature = "http1")]
+    pub(crate) fn chunk_size(&self) -> Option<usize> {
+        self.max_chunk_size
+    }
+
+    pub(crate) fn date_header(&self) -> bool {
+        !self.disable_date_header
+    }
+}
+
+/// A handle for a response body to have its HTTP/1 connection write the
+/// chunks it has yielded so far.
+///
+/// An HTTP/1 connection buffers the chunks of a body that keeps yielding
+/// them, and writes them once the buffer is full or the body is pending.
+/// A body that streams events, such as for long-polling or server-sent
+/// events, can instead call [`flush`](Flusher::flush) as it yields an event,
+/// so the event is written right after it.
+///
+/// Insert a clone of the handle into the response's extensions, and keep
+/// the other in the body. This has no effect on HTTP/2, which sends data as
+/// soon as the stream can.
+///
+/// # Example
+///
+/// ```
+/// # use bytes::Bytes;
+/// # use http_body_util::Full;
+/// use hyper::ext::Flusher;
+/// use hyper::Response;
+///
+/// let flusher = Flusher::new();
+/// let mut res = Response::new(Full::new(Bytes::from("data: ready\n\n")));
+/// res.extensions_mut().insert(flusher.clone());
+/// // Later, as the body yields an event:
+/// flusher.flush();
+/// ```
+#[cfg(all(feature = "server", feature = "http1"))]
+#[derive(Clone, Debug, Default)]
+pub struct Flusher(Arc<AtomicBool>);
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl Flusher {
+    /// Creates a handle, to be inserted into a response's extensions.
+    pub fn new() -> Flusher {
+        Flusher::default()
+    }
+
+    /// Asks for the body's chunks to be written once the connection has
+    /// taken the chunk the body yields next, or is yielding now.
+    pub fn flush(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// The request target of an HTTP/1 request, as received, when it couldn't
+/// be parsed as a URI.
+///
+/// With [`Http::http1_sanitize_request_target`] enabled, a target with bytes
+/// such as `<` or `>`, which a URI can't contain, has them percent-encoded in
+/// the request's URI instead of the request being rejected. This extension is
+/// then added to the request with the original bytes.
+///
+/// [`Http::http1_sanitize_request_target`]: crate::server::conn::Http::http1_sanitize_request_target
+#[cfg(all(feature = "server", feature = "http1"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawRequestTarget(Bytes);
+
+#[cfg(all(feature = "server", feature = "http1"))]
+impl RawRequestTarget {
+    pub(crate) fn new(target: &[u8]) -> RawRequestTarget {
+        RawRequestTarget(Bytes::copy_from_slice(target))
+    }
+
+    /// Returns the request target as received.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// The certificate chain a TLS client authenticated with.
+///
+/// This is added to the extensions of each request received on a connection
+/// served with
+/// [`auto::Builder::serve_tls_connection`](crate::server::conn::auto::Builder::serve_tls_connection),
+/// if the [`TlsStream`](crate::server::conn::TlsStream) reports one.
+#[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+#[derive(Clone, Debug)]
+pub struct PeerCertificates(Arc<[Bytes]>);
+
+#[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+impl PeerCertificates {
+    pub(crate) fn new(certs: Vec<Bytes>) -> Self {
+        PeerCertificates(certs.into())
+    }
+
+    /// The DER-encoded certificates, starting with the client's own.
+    pub fn as_slice(&self) -> &[Bytes] {
+        &self.0
+    }
+}
+
+/// The server name a TLS client requested with SNI.
+///
+/// This is added to the extensions of each request received on a connection
+/// served with
+/// [`auto::Builder::serve_tls_connection`](crate::server::conn::auto::Builder::serve_tls_connection),
+/// if the [`TlsStream`](crate::server::conn::TlsStream) reports one.
+#[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+#[derive(Clone, Debug)]
+pub struct ServerName(Arc<str>);
+
+#[cfg(all(feature = "server", feature = "http1", feature = "http2"))]
+impl ServerName {
+    pub(crate) fn new(name: &str) -> Self {
+        ServerName(name.into())
+    }
+
+    /// The requested server name.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Timestamps of an HTTP/1 exchange, for resource-timing style metrics.
+///
+/// If enabled with
+/// [`http1::Builder::timings`](crate::client::conn::http1::Builder::timings),
+/// this is added to the extensions of each response a client receives. If
+/// enabled with [`Http::http1_timings`](crate::server::conn::Http::http1_timings),
+/// it is added to the extensions of each request a server receives.
+///
+/// It is shared with the connection, so timestamps of what happens after the
+/// message is handed over, such as `body_complete`, are filled in later.
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+#[derive(Clone, Debug)]
+pub struct Timings(Arc<Mutex<Marks>>);
+
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+#[derive(Debug)]
+struct Marks {
+    connected: Instant,
+    tls_handshaked: Option<Instant>,
+    request_written: Option<Instant>,
+    first_byte: Option<Instant>,
+    headers_complete: Option<Instant>,
+    body_complete: Option<Instant>,
+}
+
+/// A timestamp of a `Timings`, recorded by the connection.
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Mark {
+    #[cfg(feature = "client")]
+    RequestWritten,
+    FirstByte,
+    HeadersComplete,
+    BodyComplete,
+}
+
+/// The timestamps of a connection, shared by the `Timings` of its messages.
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+#[derive(Clone, Debug)]
+pub(crate) struct ConnTimings {
+    pub(crate) connected: Instant,
+    pub(crate) tls_handshaked: Option<Instant>,
+    pub(crate) time: Time,
+}
+
+#[cfg(all(feature = "http1", any(feature = "client", feature = "server")))]
+impl Timings {
+    pub(crate) fn new(conn: &ConnTimings) -> Timings {
+        Timings(Arc::new(Mutex::new(Marks {
+            connected: conn.connected,
+            tls_handshaked: conn.tls_handshaked,
+            request_written: None,
+            first_byte: None,
+            headers_complete: None,
+            body_complete: None,
+        })))
+    }
+
+    /// Record `mark` at `at`, unless it already was.
+    pub(crate) fn mark(&self, mark: Mark, at: Instant) {
+        let mut marks = self.0.lock().unwrap();
+        let slot = match mark {
+            #[cfg(feature = "client")]
+            Mark::RequestWritten => &mut marks.request_written,
+            Mark::FirstByte => &mut marks.first_byte,
+            Mark::HeadersComplete => &mut marks.headers_complete,
+            Mark::BodyComplete => &mut marks.body_complete,
+        };
+        slot.get_or_insert(at);
+    }
+
+    /// When the connection was handed to hyper.
+    ///
+    /// For a client, this is when the handshake started. For a server, this
+    /// is when the connection started being served, or its TLS handshake
+    /// started with
+    /// [`auto::Builder::serve_tls_connection`](crate::server::conn::auto::Builder::serve_tls_connection).
+    pub fn connected(&self) -> Instant {
+        self.0.lock().unwrap().connected
+    }
+
+    /// When the TLS handshake completed.
+    ///
+    /// This is only known for servers, when the handshake is done by
+    /// [`auto::Builder::serve_tls_connection`](crate::server::conn::auto::Builder::serve_tls_connection).
+    pub fn tls_handshaked(&self) -> Option<Instant> {
+        self.0.lock().unwrap().tls_handshaked
+    }
+
+    /// When a client finished writing the request, including its body.
+    ///
+    /// This is always `None` for servers.
+    pub fn request_written(&self) -> Option<Instant> {
+        self.0.lock().unwrap().request_written
+    }
+
+    /// When the first byte of the incoming message was read.
+    pub fn first_byte(&self) -> Option<Instant> {
+        self.0.lock().unwrap().first_byte
+    }
+
+    /// When the head of the incoming message was completely read.
+    pub fn headers_complete(&self) -> Option<Instant> {
+        self.0.lock().unwrap().headers_complete
+    }
+
+    /// When the body of the incoming message was completely read.
+    pub fn body_complete(&self) -> Option<Instant> {
+        self.0.lock().unwrap().body_complete
+    }
+}
+
+/// How much the connection of an exchange has been used, such as for
+/// connection reuse ratios.
+///
+/// If enabled with
+/// [`http1::Builder::connection_use`](crate::client::conn::http1::Builder::connection_use)
+/// or
+/// [`http2::Builder::connection_use`](crate::client::conn::http2::Builder::connection_use),
+/// this is added to the extensions of each response a client receives. If
+/// enabled with [`Http::connection_use`](crate::server::conn::Http::connection_use),
+/// it is added to the extensions of each request a server receives.
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server"),
+))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionUse {
+    exchange: u64,
+}
+
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server"),
+))]
+impl ConnectionUse {
+    /// Whether the connection was established for this exchange, rather
+    /// than reused from an earlier one.
+    pub fn is_fresh(&self) -> bool {
+        self.exchange == 1
+    }
+
+    /// Whether the connection was used for an earlier exchange.
+    pub fn is_reused(&self) -> bool {
+        self.exchange > 1
+    }
+
+    /// The number of exchanges the connection has served, including this
+    /// one.
+    pub fn exchanges(&self) -> u64 {
+        self.exchange
+    }
+}
+
+/// Counts the exchanges of a connection, for their `ConnectionUse`.
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server"),
+))]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExchangeCounter(Arc<AtomicU64>);
+
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "client", feature = "server"),
+))]
+impl ExchangeCounter {
+    /// Another exchange started, returns its `ConnectionUse`.
+    pub(crate) fn next(&self) -> ConnectionUse {
+        ConnectionUse {
+            exchange: self.0.fetch_add(1, Ordering::Relaxed) + 1,
+        }
+    }
+}
+
+/// Notifies a service that the peer went away while its response was being
+/// generated, so expensive work can be canceled.
+///
+/// If enabled with
+/// [`Http::peer_disconnect`](crate::server::conn::Http::peer_disconnect),
+/// this is added to the extensions of each request a server receives.
+///
+/// It fires if the connection closes, or the HTTP/2 stream is reset, before
+/// the response has been completely written. A connection only notices the
+/// peer closing when it is read from, which HTTP/1 connections do while
+/// waiting for a response unless
+/// [`http1_half_close`](crate::server::conn::Http::http1_half_close) is
+/// enabled.
+///
+/// # Example
+///
+/// ```
+/// # async fn expensive() {}
+/// use hyper::ext::PeerDisconnect;
+/// # fn example(req: hyper::Request<hyper::Recv>) {
+/// let disconnect = req.extensions().get::<PeerDisconnect>().cloned();
+/// # let _ = async move {
+/// if let Some(disconnect) = disconnect {
+///     futures_util::future::select(
+///         Box::pin(expensive()),
+///         Box::pin(disconnect.disconnected()),
+///     )
+///     .await;
+/// }
+/// # };
+/// # }
+/// ```
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+#[derive(Clone, Debug)]
+pub struct PeerDisconnect(Arc<Disconnect>);
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+#[derive(Debug, Default)]
+struct Disconnect {
+    fired: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+/// Fires the `PeerDisconnect` of a request when dropped, unless its response
+/// was completely written.
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+#[derive(Debug)]
+pub(crate) struct DisconnectNotifier(Option<Arc<Disconnect>>);
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+impl PeerDisconnect {
+    /// Whether the peer has gone away.
+    pub fn is_disconnected(&self) -> bool {
+        self.0.fired.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the peer has gone away.
+    ///
+    /// This never completes if the response is written first.
+    pub fn disconnected(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let disconnect = self.0.clone();
+        futures_util::future::poll_fn(move |cx| disconnect.poll(cx))
+    }
+}
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+impl Disconnect {
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut wakers = self.wakers.lock().unwrap();
+        // Checked with the lock held, so a `fire` in between isn't missed.
+        if self.fired.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    fn fire(&self) {
+        self.fired.store(true, Ordering::SeqCst);
+        for waker in self.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+impl DisconnectNotifier {
+    /// The response was completely written, so the peer didn't go away.
+    pub(crate) fn complete(mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+impl Drop for DisconnectNotifier {
+    fn drop(&mut self) {
+        if let Some(ref disconnect) = self.0 {
+            disconnect.fire();
+        }
+    }
+}
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+type InsertExtension = Arc<dyn Fn(&mut http::Extensions) + Send + Sync>;
+
+/// Values that a server connection attaches to every request it receives.
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+#[derive(Clone, Default)]
+pub(crate) struct ConnExtensions {
+    inserts: Vec<InsertExtension>,
+    // Set if requests get a `ConnectionUse` extension.
+    exchanges: Option<ExchangeCounter>,
+    // Whether requests get a `PeerDisconnect` extension.
+    peer_disconnect: bool,
+}
+
+#[cfg(all(feature = "server", any(feature = "http1", feature = "http2")))]
+impl ConnExtensions {
+    pub(crate) fn insert<X>(&mut self, val: X)
+    where
+        X: Clone + Send + Sync + 'static,
+    {
+        self.inserts.push(Arc::new(move |ext| {
+            ext.insert(val.clone());
+        }));
+    }
+
+    pub(crate) fn count_exchanges(&mut self) {
+        self.exchanges = Some(ExchangeCounter::default());
+    }
+
+    pub(crate) fn notify_peer_disconnect(&mut self) {
+        self.peer_disconnect = true;
+    }
+
+    /// Adds a `PeerDisconnect` to `ext`, if enabled, returning what fires
+    /// it.
+    pub(crate) fn peer_disconnect(&self, ext: &mut http::Extensions) -> Option<DisconnectNotifier> {
+        if !self.peer_disconnect {
+            return None;
+        }
+        let disconnect = Arc::new(Disconnect::default());
+        ext.insert(PeerDisconnect(disconnect.clone()));
+        Some(DisconnectNotifier(Some(disconnect)))
+    }
+
+    pub(crate) fn apply(&self, ext: &mut http::Extensions) {
+        for insert in &self.inserts {
+            insert(ext);
+        }
+        if let Some(ref exchanges) = self.exchanges {
+            ext.insert(exchanges.next());
+        }
+    }
+}
+
 /// A map from header names to their original casing as received in an HTTP message.
 ///
 /// If an HTTP/1 response `res` is parsed on a connection whose option
@@ -0,0 +1,203 @@
+use std::fmt;
+
+use bytes::Bytes;
+use http::header::{HeaderName, HeaderValue};
+use http::HeaderMap;
+
+/// The headers of an HTTP/1 message that were received but not parsed into
+/// its `HeaderMap`.
+///
+/// With `http1_lazy_headers` enabled on a client or server builder, only the
+/// headers hyper needs to frame the message (`Connection`, `Content-Length`,
+/// `Transfer-Encoding`, `Expect` and `Upgrade`) are parsed into the
+/// `HeaderMap`. Every other header is validated, and left in a `LazyHeaders`
+/// in the extensions of the message, which keeps the received bytes and only
+/// builds a `HeaderName` or `HeaderValue` when asked to. A proxy forwarding
+/// most headers as they are skips that work for each of them.
+///
+/// # Forwarding
+///
+/// With `http1_forward_lazy_headers` enabled on the builder of the
+/// connection a message is sent on, the headers of a `LazyHeaders` in its
+/// extensions are written as they were received, before those in the
+/// `HeaderMap`. So a proxy can move it from a received request to the one it
+/// forwards, or likewise for responses. Hop-by-hop headers (`Connection`,
+/// `Keep-Alive`, `TE`, `Trailer`, `Upgrade` and `Proxy-*`) are never
+/// forwarded, and neither is a header the `HeaderMap` already has, so it
+/// can override one.
+///
+/// ```
+/// # #[cfg(all(feature = "client", feature = "server", feature = "http1"))]
+/// # fn forward(req: hyper::Request<hyper::Recv>) -> hyper::Request<hyper::Recv> {
+/// use hyper::ext::LazyHeaders;
+/// use hyper::Request;
+///
+/// let (mut parts, body) = req.into_parts();
+/// let lazy = parts.extensions.remove::<LazyHeaders>();
+/// if let Some(ref lazy) = lazy {
+///     // Headers can still be inspected, without parsing the others.
+///     if let Some(id) = lazy.get("x-request-id") {
+///         println!("forwarding {:?}", id);
+///     }
+/// }
+///
+/// let mut forwarded = Request::new(body);
+/// *forwarded.uri_mut() = parts.uri;
+/// *forwarded.headers_mut() = parts.headers;
+/// if let Some(lazy) = lazy {
+///     forwarded.extensions_mut().insert(lazy);
+/// }
+/// # forwarded
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct LazyHeaders {
+    bytes: Bytes,
+    entries: Vec<Entry>,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    name: (usize, usize),
+    value: (usize, usize),
+}
+
+impl LazyHeaders {
+    /// `bytes` is the message head the headers are found in.
+    pub(crate) fn new(bytes: Bytes) -> LazyHeaders {
+        LazyHeaders {
+            bytes,
+            entries: Vec::new(),
+        }
+    }
+
+    /// The header at these ranges of the head, already validated by the
+    /// parser.
+    pub(crate) fn push(&mut self, name: (usize, usize), value: (usize, usize)) {
+        self.entries.push(Entry { name, value });
+    }
+
+    /// The number of headers, counting repeated names once per line.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no headers.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The names and values of the headers, as received and in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> + '_ {
+        self.entries
+            .iter()
+            .map(move |entry| (self.slice(entry.name), self.slice(entry.value)))
+    }
+
+    /// The value of the first header named `name`, in any case.
+    pub fn get(&self, name: &str) -> Option<HeaderValue> {
+        self.entries
+            .iter()
+            .find(|entry| self.slice(entry.name).eq_ignore_ascii_case(name.as_bytes()))
+            .map(|entry| self.value(entry))
+    }
+
+    /// Removes every header named `name`, in any case, returning whether
+    /// there were any.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.entries.len();
+        let bytes = &self.bytes;
+        self.entries.retain(|entry| {
+            !bytes[entry.name.0..entry.name.1].eq_ignore_ascii_case(name.as_bytes())
+        });
+        self.entries.len() != len
+    }
+
+    /// Parses all of the headers, appending them to `headers`.
+    pub fn append_to(&self, headers: &mut HeaderMap) {
+        headers.reserve(self.entries.len());
+        for entry in &self.entries {
+            // The parser only accepts tokens as names, so this always works.
+            if let Ok(name) = HeaderName::from_bytes(self.slice(entry.name)) {
+                headers.append(name, self.value(entry));
+            }
+        }
+    }
+
+    /// Parses all of the headers into a new `HeaderMap`.
+    pub fn to_header_map(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        self.append_to(&mut headers);
+        headers
+    }
+
+    fn slice(&self, (start, end): (usize, usize)) -> &[u8] {
+        &self.bytes[start..end]
+    }
+
+    fn value(&self, entry: &Entry) -> HeaderValue {
+        let (start, end) = entry.value;
+        // SAFETY: the parser validated the value bytes.
+        unsafe { HeaderValue::from_maybe_shared_unchecked(self.bytes.slice(start..end)) }
+    }
+}
+
+impl fmt::Debug for LazyHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.iter().map(|(name, value)| {
+                (
+                    String::from_utf8_lossy(name),
+                    String::from_utf8_lossy(value),
+                )
+            }))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lazy() -> LazyHeaders {
+        let mut lazy = LazyHeaders::new(Bytes::from_static(b"X-Id: 1\r\nAccept: */*\r\nx-id: 2"));
+        lazy.push((0, 4), (6, 7));
+        lazy.push((9, 15), (17, 20));
+        lazy.push((22, 26), (28, 29));
+        lazy
+    }
+
+    #[test]
+    fn get_first_in_any_case() {
+        let lazy = lazy();
+        assert_eq!(lazy.len(), 3);
+        assert_eq!(lazy.get("x-id").unwrap(), "1");
+        assert_eq!(lazy.get("ACCEPT").unwrap(), "*/*");
+        assert!(lazy.get("host").is_none());
+    }
+
+    #[test]
+    fn iter_as_received() {
+        let lazy = lazy();
+        let headers = lazy.iter().collect::<Vec<_>>();
+        assert_eq!(headers[0], (&b"X-Id"[..], &b"1"[..]));
+        assert_eq!(headers[2], (&b"x-id"[..], &b"2"[..]));
+    }
+
+    #[test]
+    fn remove_all_in_any_case() {
+        let mut lazy = lazy();
+        assert!(lazy.remove("X-ID"));
+        assert!(!lazy.remove("x-id"));
+        assert_eq!(lazy.len(), 1);
+        assert_eq!(lazy.get("accept").unwrap(), "*/*");
+    }
+
+    #[test]
+    fn to_header_map_keeps_repeated() {
+        let headers = lazy().to_header_map();
+        assert_eq!(headers.len(), 3);
+        let ids = headers.get_all("x-id").iter().collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2"]);
+    }
+}
@@ -13,12 +13,23 @@ use bytes::Bytes;
 /// the response will not contain a `ReasonPhrase`.
 ///
 /// ```no_run
-/// # #[cfg(all(feature = "tcp", feature = "client", feature = "http1"))]
-/// # async fn fake_fetch() -> hyper::Result<()> {
-/// use hyper::{Client, Uri};
+/// # #[cfg(all(feature = "client", feature = "http1", feature = "tokio"))]
+/// # async fn fake_fetch() -> Result<(), Box<dyn std::error::Error>> {
+/// use bytes::Bytes;
+/// use http_body_util::Empty;
 /// use hyper::ext::ReasonPhrase;
+/// use hyper::rt::TokioIo;
+/// use hyper::Request;
+/// use tokio::net::TcpStream;
 ///
-/// let res = Client::new().get(Uri::from_static("http://example.com/non_canonical_reason")).await?;
+/// let stream = TcpStream::connect("example.com:80").await?;
+/// let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+/// tokio::spawn(conn);
+///
+/// let req = Request::get("/non_canonical_reason")
+///     .header("host", "example.com")
+///     .body(Empty::<Bytes>::new())?;
+/// let res = sender.send_request(req).await?;
 ///
 /// // Print out the non-canonical reason phrase, if it has one...
 /// if let Some(reason) = res.extensions().get::<ReasonPhrase>() {
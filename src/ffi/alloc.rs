@@ -0,0 +1,175 @@
+use std::alloc::{handle_alloc_error, Layout};
+use std::ffi::c_void;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use libc::size_t;
+
+use super::error::hyper_code;
+
+type hyper_malloc_callback = extern "C" fn(size_t) -> *mut c_void;
+type hyper_free_callback = extern "C" fn(*mut c_void);
+
+/// The allocator set with `hyper_allocator_set`, if any.
+///
+/// It can only be set once, and is never freed, so it can be read without
+/// a lock.
+static ALLOCATOR: AtomicPtr<Allocator> = AtomicPtr::new(ptr::null_mut());
+
+struct Allocator {
+    malloc: hyper_malloc_callback,
+    free: hyper_free_callback,
+}
+
+/// Bytes allocated with the application's allocator.
+pub(super) struct AllocBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    free: hyper_free_callback,
+}
+
+// The allocation is owned, and only ever read through a shared reference.
+unsafe impl Send for AllocBuf {}
+unsafe impl Sync for AllocBuf {}
+
+ffi_fn! {
+    /// Set the allocator used for the data hyper hands to the application.
+    ///
+    /// Once set, the data of each `hyper_buf *` returned by a task, such as
+    /// the chunks of a body, and of the buffer from
+    /// `hyper_response_headers_raw`, is copied into memory from `malloc_fn`,
+    /// and released with `free_fn` once the buffer is freed. The callbacks
+    /// may be called from any thread, and `malloc_fn` must not return `NULL`.
+    ///
+    /// This must be called before using any other hyper function, and can
+    /// only be called once. It returns `HYPERE_INVALID_ARG` if an allocator
+    /// was already set.
+    fn hyper_allocator_set(malloc_fn: hyper_malloc_callback, free_fn: hyper_free_callback) -> hyper_code {
+        let allocator = Box::into_raw(Box::new(Allocator {
+            malloc: malloc_fn,
+            free: free_fn,
+        }));
+        match ALLOCATOR.compare_exchange(
+            ptr::null_mut(),
+            allocator,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => hyper_code::HYPERE_OK,
+            Err(_) => {
+                drop(unsafe { Box::from_raw(allocator) });
+                hyper_code::HYPERE_INVALID_ARG
+            }
+        }
+    }
+}
+
+// ===== impl AllocBuf =====
+
+impl AllocBuf {
+    /// Copy the bytes with the application's allocator, if one was set.
+    pub(super) fn copy_from(bytes: &[u8]) -> Option<AllocBuf> {
+        // An empty buffer doesn't need any memory.
+        if bytes.is_empty() {
+            return None;
+        }
+        let allocator = unsafe { ALLOCATOR.load(Ordering::Acquire).as_ref() }?;
+
+        let ptr = match NonNull::new((allocator.malloc)(bytes.len()) as *mut u8) {
+            Some(ptr) => ptr,
+            None => handle_alloc_error(Layout::array::<u8>(bytes.len()).unwrap()),
+        };
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.as_ptr(), bytes.len()) };
+
+        Some(AllocBuf {
+            ptr,
+            len: bytes.len(),
+            free: allocator.free,
+        })
+    }
+
+    pub(super) fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AllocBuf {
+    fn drop(&mut self) {
+        (self.free)(self.ptr.as_ptr() as *mut c_void);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::body::Bytes;
+    use crate::ffi::body::{hyper_buf, hyper_buf_bytes, hyper_buf_free, hyper_buf_len};
+
+    // Other tests may allocate concurrently, so only track addresses.
+    static LIVE: Mutex<Option<HashSet<usize>>> = Mutex::new(None);
+
+    extern "C" fn tracked_malloc(size: size_t) -> *mut c_void {
+        let ptr = unsafe { libc::malloc(size) };
+        LIVE.lock()
+            .unwrap()
+            .get_or_insert_with(HashSet::new)
+            .insert(ptr as usize);
+        ptr
+    }
+
+    extern "C" fn tracked_free(ptr: *mut c_void) {
+        let removed = LIVE
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .remove(&(ptr as usize));
+        assert!(removed, "freed memory that wasn't allocated");
+        unsafe { libc::free(ptr) };
+    }
+
+    /// Unsets the allocator when dropped, so that it doesn't stay set for
+    /// the other tests.
+    ///
+    /// The allocator itself is leaked, since a concurrent `copy_from` may
+    /// still be using it. Buffers it allocated keep their `free` callback.
+    struct ResetAllocator;
+
+    impl Drop for ResetAllocator {
+        fn drop(&mut self) {
+            ALLOCATOR.store(ptr::null_mut(), Ordering::Release);
+        }
+    }
+
+    fn is_live(ptr: *const u8) -> bool {
+        LIVE.lock()
+            .unwrap()
+            .as_ref()
+            .map_or(false, |live| live.contains(&(ptr as usize)))
+    }
+
+    #[test]
+    fn test_allocator_returned_bufs() {
+        assert!(matches!(
+            hyper_allocator_set(tracked_malloc, tracked_free),
+            hyper_code::HYPERE_OK
+        ));
+        let _reset = ResetAllocator;
+        assert!(matches!(
+            hyper_allocator_set(tracked_malloc, tracked_free),
+            hyper_code::HYPERE_INVALID_ARG
+        ));
+
+        let buf = Box::into_raw(Box::new(hyper_buf::returned(Bytes::from_static(b"hello"))));
+        let ptr = hyper_buf_bytes(buf);
+        assert!(is_live(ptr));
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(ptr, hyper_buf_len(buf)) },
+            b"hello"
+        );
+        hyper_buf_free(buf);
+    }
+}
@@ -1,24 +1,47 @@
 use std::ffi::c_void;
 use std::mem::ManuallyDrop;
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
+use futures_util::task::AtomicWaker;
 use http::HeaderMap;
 use libc::{c_int, size_t};
 
+use super::alloc::AllocBuf;
+use super::error::hyper_code;
+use super::http_types::hyper_headers;
 use super::task::{hyper_context, hyper_task, hyper_task_return_type, AsTaskType};
-use super::{UserDataPointer, HYPER_ITER_CONTINUE};
-use crate::body::{Body as _, Bytes, Recv};
+use super::{hyper_userdata_drop, UserData, UserDataPointer, HYPER_ITER_CONTINUE};
+use crate::body::{Body as _, Bytes, DecodedLength, Recv, Sender};
 
 /// A streaming HTTP body.
 pub struct hyper_body(pub(super) Recv);
 
 /// A buffer of bytes that is sent or received on a `hyper_body`.
-pub struct hyper_buf(pub(crate) Bytes);
+pub struct hyper_buf(Buf);
+
+enum Buf {
+    Bytes(Bytes),
+    /// A copy made with the allocator set by `hyper_allocator_set`.
+    Alloc(AllocBuf),
+}
+
+/// The sending half of a body, which provides its data either from a
+/// callback as the connection is ready for it, or as it is pushed.
+pub struct hyper_body_sender {
+    tx: Option<Sender>,
+    body: Option<Recv>,
+    func: Option<hyper_body_sender_callback>,
+    userdata: Arc<UserData>,
+    resume: Arc<Resume>,
+}
 
 pub(crate) struct UserBody {
     data_func: hyper_body_data_callback,
-    userdata: *mut c_void,
+    trailers_func: hyper_body_trailers_callback,
+    userdata: UserData,
 }
 
 // ===== Body =====
@@ -28,6 +51,24 @@ type hyper_body_foreach_callback = extern "C" fn(*mut c_void, *const hyper_buf)
 type hyper_body_data_callback =
     extern "C" fn(*mut c_void, *mut hyper_context<'_>, *mut *mut hyper_buf) -> c_int;
 
+type hyper_body_trailers_callback =
+    extern "C" fn(*mut c_void, *mut hyper_context<'_>, *mut hyper_headers) -> c_int;
+
+type hyper_body_sender_callback = extern "C" fn(*mut c_void, *mut *mut hyper_buf) -> c_int;
+
+struct SendBody {
+    tx: Sender,
+    func: hyper_body_sender_callback,
+    userdata: Arc<UserData>,
+    resume: Arc<Resume>,
+    paused: bool,
+}
+
+struct Resume {
+    resumed: AtomicBool,
+    waker: AtomicWaker,
+}
+
 ffi_fn! {
     /// Create a new "empty" body.
     ///
@@ -60,7 +101,32 @@ ffi_fn! {
         let mut body = ManuallyDrop::new(non_null!(Box::from_raw(body) ?= ptr::null_mut()));
 
         Box::into_raw(hyper_task::boxed(async move {
-            body.0.data().await.map(|res| res.map(hyper_buf))
+            body.0.data().await.map(|res| res.map(hyper_buf::returned))
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Return a task that will poll the body for its trailers.
+    ///
+    /// This should be used once `hyper_body_data` has returned a
+    /// `HYPER_TASK_EMPTY` task, since trailers are only received after all
+    /// the data of the body. The task value may have different types
+    /// depending on the outcome:
+    ///
+    /// - `HYPER_TASK_HEADERS`: Success, and trailers were received. The
+    ///   `hyper_headers *` must be freed with `hyper_headers_free`.
+    /// - `HYPER_TASK_ERROR`: An error retrieving the trailers.
+    /// - `HYPER_TASK_EMPTY`: The body has no trailers.
+    ///
+    /// This does not consume the `hyper_body *`, so it may be used to again.
+    /// However, it MUST NOT be used or freed until the related task completes.
+    fn hyper_body_trailers(body: *mut hyper_body) -> *mut hyper_task {
+        // This doesn't take ownership of the Body, so don't allow destructor
+        let mut body = ManuallyDrop::new(non_null!(Box::from_raw(body) ?= ptr::null_mut()));
+
+        Box::into_raw(hyper_task::boxed(async move {
+            body.0.trailers().await.map(|trailers| trailers.map(hyper_headers::new))
         }))
     } ?= ptr::null_mut()
 }
@@ -83,7 +149,7 @@ ffi_fn! {
         Box::into_raw(hyper_task::boxed(async move {
             while let Some(item) = body.0.data().await {
                 let chunk = item?;
-                if HYPER_ITER_CONTINUE != func(userdata.0, &hyper_buf(chunk)) {
+                if HYPER_ITER_CONTINUE != func(userdata.0, &hyper_buf::new(chunk)) {
                     return Err(crate::Error::new_user_aborted_by_callback());
                 }
             }
@@ -96,7 +162,19 @@ ffi_fn! {
     /// Set userdata on this body, which will be passed to callback functions.
     fn hyper_body_set_userdata(body: *mut hyper_body, userdata: *mut c_void) {
         let b = non_null!(&mut *body ?= ());
-        b.0.as_ffi_mut().userdata = userdata;
+        b.0.as_ffi_mut().userdata = UserData::new(userdata, None);
+    }
+}
+
+ffi_fn! {
+    /// Set userdata on this body, and a callback to free it.
+    ///
+    /// This is like `hyper_body_set_userdata`, except the `drop` callback is
+    /// called with the userdata once hyper frees the body, such as after
+    /// sending it.
+    fn hyper_body_set_userdata_with_drop(body: *mut hyper_body, userdata: *mut c_void, drop: hyper_userdata_drop) {
+        let b = non_null!(&mut *body ?= ());
+        b.0.as_ffi_mut().userdata = UserData::new(userdata, Some(drop));
     }
 }
 
@@ -120,31 +198,337 @@ ffi_fn! {
     ///
     /// If some error has occurred, you can return `HYPER_POLL_ERROR` to abort
     /// the body.
+    ///
+    /// To only be asked for data once the connection is ready for it, such as
+    /// for large uploads, use `hyper_body_sender_new` instead.
     fn hyper_body_set_data_func(body: *mut hyper_body, func: hyper_body_data_callback) {
         let b = non_null!{ &mut *body ?= () };
         b.0.as_ffi_mut().data_func = func;
     }
 }
 
+ffi_fn! {
+    /// Set the trailers callback for this body.
+    ///
+    /// The callback is called once the data callback has indicated the body
+    /// has completed all data. It is passed the value from
+    /// `hyper_body_set_userdata`, and an empty `hyper_headers *` that the
+    /// trailers can be added to with `hyper_headers_set` or
+    /// `hyper_headers_add`. The `hyper_headers *` is only borrowed for the
+    /// duration of the callback.
+    ///
+    /// Returning `HYPER_POLL_READY` will send the trailers, if any were
+    /// added. The same `HYPER_POLL_PENDING` and `HYPER_POLL_ERROR` return
+    /// values as the data callback can be used.
+    ///
+    /// Trailers are currently only sent on HTTP/2 connections, and are
+    /// ignored on HTTP/1 connections.
+    fn hyper_body_set_trailers_func(body: *mut hyper_body, func: hyper_body_trailers_callback) {
+        let b = non_null!{ &mut *body ?= () };
+        b.0.as_ffi_mut().trailers_func = func;
+    }
+}
+
+// ===== impl hyper_body_sender =====
+
+ffi_fn! {
+    /// Create a body sender, which provides the data of a body from a callback.
+    ///
+    /// Unlike the callback of `hyper_body_set_data_func`, this callback is
+    /// only called once the connection is ready to send more data, so a large
+    /// body is only read as fast as it can be written. It is passed the value
+    /// from `hyper_body_sender_set_userdata`.
+    ///
+    /// If there is data available, the `hyper_buf **` argument should be set
+    /// to a `hyper_buf *` containing the data, and `HYPER_POLL_READY` should
+    /// be returned. Returning `HYPER_POLL_READY` while the `hyper_buf **`
+    /// argument points to `NULL` will indicate the body has completed all
+    /// data.
+    ///
+    /// If more data isn't available yet, `HYPER_POLL_LATER` should be
+    /// returned, and `hyper_body_sender_resume` called once it is. The
+    /// callback won't be called again until then.
+    ///
+    /// If some error has occurred, you can return `HYPER_POLL_ERROR` to abort
+    /// the body.
+    ///
+    /// The body is retrieved with `hyper_body_sender_body`, and the data is
+    /// sent by pushing the task from `hyper_body_sender_task` to an executor.
+    ///
+    /// The callback may instead be `NULL`, to push the data with
+    /// `hyper_body_sender_try_send_data` as it becomes available.
+    fn hyper_body_sender_new(func: Option<hyper_body_sender_callback>) -> *mut hyper_body_sender {
+        let (tx, body) = Recv::new_channel(DecodedLength::CHUNKED, /*wanter =*/ true);
+        Box::into_raw(Box::new(hyper_body_sender {
+            tx: Some(tx),
+            body: Some(body),
+            func,
+            userdata: Arc::new(UserData::empty()),
+            resume: Arc::new(Resume {
+                resumed: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            }),
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Set userdata on this body sender, which will be passed to the callback.
+    ///
+    /// This must be set before calling `hyper_body_sender_task`.
+    fn hyper_body_sender_set_userdata(sender: *mut hyper_body_sender, userdata: *mut c_void) {
+        non_null!(&mut *sender ?= ()).userdata = Arc::new(UserData::new(userdata, None));
+    }
+}
+
+ffi_fn! {
+    /// Set userdata on this body sender, and a callback to free it.
+    ///
+    /// This is like `hyper_body_sender_set_userdata`, except the `drop`
+    /// callback is called with the userdata once both the sender and its task
+    /// are freed.
+    fn hyper_body_sender_set_userdata_with_drop(sender: *mut hyper_body_sender, userdata: *mut c_void, drop: hyper_userdata_drop) {
+        non_null!(&mut *sender ?= ()).userdata = Arc::new(UserData::new(userdata, Some(drop)));
+    }
+}
+
+ffi_fn! {
+    /// Take the body that receives the data of this sender.
+    ///
+    /// The body can be set on a request or a response like any other body.
+    /// This returns `NULL` if the body was already taken.
+    fn hyper_body_sender_body(sender: *mut hyper_body_sender) -> *mut hyper_body {
+        let sender = non_null!(&mut *sender ?= ptr::null_mut());
+        match sender.body.take() {
+            Some(body) => Box::into_raw(Box::new(hyper_body(body))),
+            None => ptr::null_mut(),
+        }
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Return a task that will send the data provided by the callback.
+    ///
+    /// The task value may have different types depending on the outcome:
+    ///
+    /// - `HYPER_TASK_EMPTY`: All the data of the body was sent.
+    /// - `HYPER_TASK_ERROR`: The body was aborted by the callback, or dropped
+    ///   by the connection before all its data was sent.
+    ///
+    /// This returns `NULL` if the task was already taken, or the sender has
+    /// no callback. The sender must still be freed with
+    /// `hyper_body_sender_free`, and it can be used to resume the task until
+    /// then.
+    fn hyper_body_sender_task(sender: *mut hyper_body_sender) -> *mut hyper_task {
+        let sender = non_null!(&mut *sender ?= ptr::null_mut());
+        let func = match sender.func {
+            Some(func) => func,
+            None => return ptr::null_mut(),
+        };
+        let tx = match sender.tx.take() {
+            Some(tx) => tx,
+            None => return ptr::null_mut(),
+        };
+        let mut send = SendBody {
+            tx,
+            func,
+            userdata: sender.userdata.clone(),
+            resume: sender.resume.clone(),
+            paused: false,
+        };
+
+        Box::into_raw(hyper_task::boxed(async move {
+            futures_util::future::poll_fn(|cx| send.poll_send(cx)).await
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Resume a body sender whose callback returned `HYPER_POLL_LATER`.
+    ///
+    /// The callback will be called again once the connection is ready for
+    /// more data.
+    fn hyper_body_sender_resume(sender: *const hyper_body_sender) {
+        let sender = non_null!(&*sender ?= ());
+        sender.resume.resumed.store(true, Ordering::SeqCst);
+        sender.resume.waker.wake();
+    }
+}
+
+ffi_fn! {
+    /// Return a task that will complete once this sender can accept more
+    /// data.
+    ///
+    /// The task value may have different types depending on the outcome:
+    ///
+    /// - `HYPER_TASK_EMPTY`: The next `hyper_body_sender_try_send_data` will
+    ///   succeed.
+    /// - `HYPER_TASK_ERROR`: The body was dropped by the connection.
+    ///
+    /// This returns `NULL` if the sender's data is provided by its task.
+    ///
+    /// This does not consume the `hyper_body_sender *`. However, it MUST NOT
+    /// be used or freed until the related task completes.
+    fn hyper_body_sender_ready(sender: *mut hyper_body_sender) -> *mut hyper_task {
+        // This doesn't take ownership of the sender, so don't allow destructor
+        let mut sender = ManuallyDrop::new(non_null!(Box::from_raw(sender) ?= ptr::null_mut()));
+        if sender.tx.is_none() {
+            return ptr::null_mut();
+        }
+
+        Box::into_raw(hyper_task::boxed(async move {
+            let tx = sender.tx.as_mut().expect("checked above");
+            futures_util::future::poll_fn(|cx| tx.poll_ready(cx)).await
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Try to send a buffer of data on this sender.
+    ///
+    /// This takes ownership of the `hyper_buf *` if it returns `HYPERE_OK`.
+    /// Otherwise, the sender can't accept more data right now, or the body
+    /// was dropped by the connection, and the `hyper_buf *` is still owned
+    /// by the caller. A task from `hyper_body_sender_ready` tells when more
+    /// data can be sent.
+    ///
+    /// This returns `HYPERE_INVALID_ARG` if the sender's data is provided by
+    /// its task.
+    fn hyper_body_sender_try_send_data(sender: *mut hyper_body_sender, buf: *mut hyper_buf) -> hyper_code {
+        let sender = non_null!(&mut *sender ?= hyper_code::HYPERE_INVALID_ARG);
+        let tx = match sender.tx {
+            Some(ref mut tx) => tx,
+            None => return hyper_code::HYPERE_INVALID_ARG,
+        };
+        let buf = non_null!(&mut *buf ?= hyper_code::HYPERE_INVALID_ARG);
+
+        match tx.try_send_data(buf.to_bytes()) {
+            Ok(()) => {
+                drop(unsafe { Box::from_raw(buf) });
+                hyper_code::HYPERE_OK
+            }
+            Err(_) => hyper_code::HYPERE_ERROR,
+        }
+    }
+}
+
+ffi_fn! {
+    /// Send trailers once all the data of the body was sent.
+    ///
+    /// This takes ownership of the `hyper_headers *`, which should be created
+    /// with `hyper_headers_new`. Trailers can only be sent once, and are
+    /// currently only sent on HTTP/2 connections.
+    ///
+    /// This returns `HYPERE_INVALID_ARG` if the sender's data is provided by
+    /// its task, and `HYPERE_ERROR` if the trailers were already sent or the
+    /// body was dropped by the connection.
+    fn hyper_body_sender_send_trailers(sender: *mut hyper_body_sender, trailers: *mut hyper_headers) -> hyper_code {
+        let sender = non_null!(&mut *sender ?= hyper_code::HYPERE_INVALID_ARG);
+        let trailers = non_null!(Box::from_raw(trailers) ?= hyper_code::HYPERE_INVALID_ARG);
+        let tx = match sender.tx {
+            Some(ref mut tx) => tx,
+            None => return hyper_code::HYPERE_INVALID_ARG,
+        };
+
+        match tx.try_send_trailers(trailers.headers) {
+            Ok(()) => hyper_code::HYPERE_OK,
+            Err(_) => hyper_code::HYPERE_ERROR,
+        }
+    }
+}
+
+ffi_fn! {
+    /// Abort the body, so that it is not sent completely.
+    ///
+    /// This consumes the `hyper_body_sender *`, you must not use it or free
+    /// it afterwards.
+    fn hyper_body_sender_abort(sender: *mut hyper_body_sender) {
+        let mut sender = non_null!(Box::from_raw(sender) ?= ());
+        if let Some(tx) = sender.tx.take() {
+            tx.abort();
+        }
+    }
+}
+
+ffi_fn! {
+    /// Free a `hyper_body_sender *`.
+    ///
+    /// If the data is pushed with `hyper_body_sender_try_send_data`, this
+    /// ends the body. If its task was taken, the task keeps sending the body,
+    /// but can no longer be resumed.
+    fn hyper_body_sender_free(sender: *mut hyper_body_sender) {
+        drop(non_null!(Box::from_raw(sender) ?= ()));
+    }
+}
+
+// ===== impl SendBody =====
+
+impl SendBody {
+    fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        loop {
+            if self.paused {
+                // Register before checking, so a resume in between isn't missed.
+                self.resume.waker.register(cx.waker());
+                if !self.resume.resumed.swap(false, Ordering::SeqCst) {
+                    return Poll::Pending;
+                }
+                self.paused = false;
+            }
+
+            // Only ask for data once the connection wants it.
+            ready!(self.tx.poll_ready(cx))?;
+
+            self.resume.resumed.store(false, Ordering::SeqCst);
+            let mut out = std::ptr::null_mut();
+            match (self.func)(self.userdata.as_ptr(), &mut out) {
+                super::task::HYPER_POLL_READY => {
+                    if out.is_null() {
+                        return Poll::Ready(Ok(()));
+                    }
+                    let buf = unsafe { Box::from_raw(out) };
+                    if self.tx.try_send_data(buf.into_bytes()).is_err() {
+                        return Poll::Ready(Err(crate::Error::new_closed()));
+                    }
+                }
+                super::task::HYPER_POLL_LATER => {
+                    self.paused = true;
+                }
+                super::task::HYPER_POLL_ERROR => {
+                    self.tx.send_error(crate::Error::new_body_write_aborted());
+                    return Poll::Ready(Err(crate::Error::new_body_write_aborted()));
+                }
+                unexpected => {
+                    self.tx.send_error(crate::Error::new_body_write_aborted());
+                    return Poll::Ready(Err(crate::Error::new_body_write(format!(
+                        "unexpected hyper_body_sender_callback return code {}",
+                        unexpected
+                    ))));
+                }
+            }
+        }
+    }
+}
+
 // ===== impl UserBody =====
 
 impl UserBody {
     pub(crate) fn new() -> UserBody {
         UserBody {
             data_func: data_noop,
-            userdata: std::ptr::null_mut(),
+            trailers_func: trailers_noop,
+            userdata: UserData::empty(),
         }
     }
 
     pub(crate) fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Option<crate::Result<Bytes>>> {
         let mut out = std::ptr::null_mut();
-        match (self.data_func)(self.userdata, hyper_context::wrap(cx), &mut out) {
+        match (self.data_func)(self.userdata.as_ptr(), hyper_context::wrap(cx), &mut out) {
             super::task::HYPER_POLL_READY => {
                 if out.is_null() {
                     Poll::Ready(None)
                 } else {
                     let buf = unsafe { Box::from_raw(out) };
-                    Poll::Ready(Some(Ok(buf.0)))
+                    Poll::Ready(Some(Ok(buf.into_bytes())))
                 }
             }
             super::task::HYPER_POLL_PENDING => Poll::Pending,
@@ -160,9 +544,30 @@ impl UserBody {
 
     pub(crate) fn poll_trailers(
         &mut self,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<crate::Result<Option<HeaderMap>>> {
-        Poll::Ready(Ok(None))
+        let mut trailers = hyper_headers::default();
+        match (self.trailers_func)(
+            self.userdata.as_ptr(),
+            hyper_context::wrap(cx),
+            &mut trailers,
+        ) {
+            super::task::HYPER_POLL_READY => {
+                if trailers.headers.is_empty() {
+                    Poll::Ready(Ok(None))
+                } else {
+                    Poll::Ready(Ok(Some(trailers.headers)))
+                }
+            }
+            super::task::HYPER_POLL_PENDING => Poll::Pending,
+            super::task::HYPER_POLL_ERROR => {
+                Poll::Ready(Err(crate::Error::new_body_write_aborted()))
+            }
+            unexpected => Poll::Ready(Err(crate::Error::new_body_write(format!(
+                "unexpected hyper_body_trailers_func return code {}",
+                unexpected
+            )))),
+        }
     }
 }
 
@@ -175,11 +580,56 @@ extern "C" fn data_noop(
     super::task::HYPER_POLL_READY
 }
 
+/// cbindgen:ignore
+extern "C" fn trailers_noop(
+    _userdata: *mut c_void,
+    _: *mut hyper_context<'_>,
+    _: *mut hyper_headers,
+) -> c_int {
+    super::task::HYPER_POLL_READY
+}
+
 unsafe impl Send for UserBody {}
 unsafe impl Sync for UserBody {}
 
 // ===== Bytes =====
 
+impl hyper_buf {
+    pub(crate) fn new(bytes: Bytes) -> hyper_buf {
+        hyper_buf(Buf::Bytes(bytes))
+    }
+
+    /// A buffer returned to the application, copied with its allocator if
+    /// one was set.
+    pub(crate) fn returned(bytes: Bytes) -> hyper_buf {
+        match AllocBuf::copy_from(&bytes) {
+            Some(buf) => hyper_buf(Buf::Alloc(buf)),
+            None => hyper_buf::new(bytes),
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        match self.0 {
+            Buf::Bytes(ref bytes) => bytes,
+            Buf::Alloc(ref buf) => buf.as_slice(),
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Bytes {
+        match self.0 {
+            Buf::Bytes(ref bytes) => bytes.clone(),
+            Buf::Alloc(ref buf) => Bytes::copy_from_slice(buf.as_slice()),
+        }
+    }
+
+    pub(crate) fn into_bytes(self) -> Bytes {
+        match self.0 {
+            Buf::Bytes(bytes) => bytes,
+            Buf::Alloc(buf) => Bytes::copy_from_slice(buf.as_slice()),
+        }
+    }
+}
+
 ffi_fn! {
     /// Create a new `hyper_buf *` by copying the provided bytes.
     ///
@@ -191,7 +641,7 @@ ffi_fn! {
         let slice = unsafe {
             std::slice::from_raw_parts(buf, len)
         };
-        Box::into_raw(Box::new(hyper_buf(Bytes::copy_from_slice(slice))))
+        Box::into_raw(Box::new(hyper_buf::new(Bytes::copy_from_slice(slice))))
     } ?= ptr::null_mut()
 }
 
@@ -204,14 +654,14 @@ ffi_fn! {
     /// This pointer is borrowed data, and not valid once the `hyper_buf` is
     /// consumed/freed.
     fn hyper_buf_bytes(buf: *const hyper_buf) -> *const u8 {
-        unsafe { (*buf).0.as_ptr() }
+        unsafe { (*buf).as_bytes().as_ptr() }
     } ?= ptr::null()
 }
 
 ffi_fn! {
     /// Get the length of the bytes this buffer contains.
     fn hyper_buf_len(buf: *const hyper_buf) -> size_t {
-        unsafe { (*buf).0.len() }
+        unsafe { (*buf).as_bytes().len() }
     }
 }
 
@@ -227,3 +677,197 @@ unsafe impl AsTaskType for hyper_buf {
         hyper_task_return_type::HYPER_TASK_BUF
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi::http_types::{hyper_headers_free, hyper_headers_new, hyper_headers_set};
+    use crate::ffi::task::{
+        hyper_executor_free, hyper_executor_new, hyper_executor_poll, hyper_executor_push,
+        hyper_task_free, hyper_task_set_userdata, hyper_task_type, hyper_task_userdata,
+        hyper_task_value, HYPER_POLL_LATER, HYPER_POLL_READY,
+    };
+
+    extern "C" fn grpc_status_trailers(
+        _userdata: *mut c_void,
+        _: *mut hyper_context<'_>,
+        trailers: *mut hyper_headers,
+    ) -> c_int {
+        let name = b"grpc-status";
+        let value = b"0";
+        hyper_headers_set(
+            trailers,
+            name.as_ptr(),
+            name.len(),
+            value.as_ptr(),
+            value.len(),
+        );
+        super::super::task::HYPER_POLL_READY
+    }
+
+    #[test]
+    fn test_body_trailers_roundtrip() {
+        let body = hyper_body_new();
+        hyper_body_set_trailers_func(body, grpc_status_trailers);
+
+        let exec = hyper_executor_new();
+        hyper_executor_push(exec, hyper_body_trailers(body));
+        let task = hyper_executor_poll(exec);
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_HEADERS
+        ));
+
+        let trailers = hyper_task_value(task) as *mut hyper_headers;
+        assert_eq!(unsafe { &(*trailers).headers }["grpc-status"], "0");
+        hyper_headers_free(trailers);
+
+        hyper_task_free(task);
+        hyper_body_free(body);
+        hyper_executor_free(exec);
+    }
+    extern "C" fn hello_later(userdata: *mut c_void, chunk: *mut *mut hyper_buf) -> c_int {
+        let calls = unsafe { &mut *(userdata as *mut usize) };
+        *calls += 1;
+        match *calls {
+            1 => HYPER_POLL_LATER,
+            2 => {
+                let hello = b"hello";
+                unsafe { *chunk = hyper_buf_copy(hello.as_ptr(), hello.len()) };
+                HYPER_POLL_READY
+            }
+            _ => HYPER_POLL_READY,
+        }
+    }
+
+    #[test]
+    fn test_body_sender_resume() {
+        let mut calls = 0usize;
+        let sender = hyper_body_sender_new(Some(hello_later));
+        hyper_body_sender_set_userdata(sender, &mut calls as *mut usize as *mut c_void);
+        let body = hyper_body_sender_body(sender);
+
+        let exec = hyper_executor_new();
+        hyper_executor_push(exec, hyper_body_sender_task(sender));
+        // The callback isn't called until the body is polled for data.
+        assert!(hyper_executor_poll(exec).is_null());
+        assert_eq!(calls, 0);
+
+        let data = hyper_body_data(body);
+        hyper_task_set_userdata(data, body as *mut c_void);
+        hyper_executor_push(exec, data);
+        assert!(hyper_executor_poll(exec).is_null());
+        assert_eq!(calls, 1);
+
+        hyper_body_sender_resume(sender);
+        let task = loop {
+            let task = hyper_executor_poll(exec);
+            assert!(!task.is_null(), "data task should complete");
+            if hyper_task_userdata(task) == body as *mut c_void {
+                break task;
+            }
+            hyper_task_free(task);
+        };
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_BUF
+        ));
+        let buf = hyper_task_value(task) as *mut hyper_buf;
+        assert_eq!(unsafe { (*buf).as_bytes() }, b"hello");
+        hyper_buf_free(buf);
+
+        hyper_task_free(task);
+        hyper_body_free(body);
+        hyper_body_sender_free(sender);
+        hyper_executor_free(exec);
+    }
+    #[test]
+    fn test_body_sender_push() {
+        let sender = hyper_body_sender_new(None);
+        let body = hyper_body_sender_body(sender);
+        assert!(hyper_body_sender_task(sender).is_null());
+
+        let hello = b"hello";
+        let chunk = hyper_buf_copy(hello.as_ptr(), hello.len());
+        assert!(matches!(
+            hyper_body_sender_try_send_data(sender, chunk),
+            hyper_code::HYPERE_OK
+        ));
+        // The first chunk hasn't been received yet, so there's no room for another.
+        let chunk = hyper_buf_copy(hello.as_ptr(), hello.len());
+        assert!(matches!(
+            hyper_body_sender_try_send_data(sender, chunk),
+            hyper_code::HYPERE_ERROR
+        ));
+
+        let exec = hyper_executor_new();
+        hyper_executor_push(exec, hyper_body_data(body));
+        let task = hyper_executor_poll(exec);
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_BUF
+        ));
+        hyper_buf_free(hyper_task_value(task) as *mut hyper_buf);
+        hyper_task_free(task);
+
+        hyper_executor_push(exec, hyper_body_sender_ready(sender));
+        let task = hyper_executor_poll(exec);
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_EMPTY
+        ));
+        hyper_task_free(task);
+        assert!(matches!(
+            hyper_body_sender_try_send_data(sender, chunk),
+            hyper_code::HYPERE_OK
+        ));
+
+        let trailers = hyper_headers_new();
+        let name = b"grpc-status";
+        let value = b"0";
+        hyper_headers_set(
+            trailers,
+            name.as_ptr(),
+            name.len(),
+            value.as_ptr(),
+            value.len(),
+        );
+        assert!(matches!(
+            hyper_body_sender_send_trailers(sender, trailers),
+            hyper_code::HYPERE_OK
+        ));
+        // Freeing the sender ends the body.
+        hyper_body_sender_free(sender);
+
+        hyper_executor_push(exec, hyper_body_data(body));
+        let task = hyper_executor_poll(exec);
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_BUF
+        ));
+        hyper_buf_free(hyper_task_value(task) as *mut hyper_buf);
+        hyper_task_free(task);
+
+        hyper_executor_push(exec, hyper_body_data(body));
+        let task = hyper_executor_poll(exec);
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_EMPTY
+        ));
+        hyper_task_free(task);
+
+        hyper_executor_push(exec, hyper_body_trailers(body));
+        let task = hyper_executor_poll(exec);
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_HEADERS
+        ));
+        let trailers = hyper_task_value(task) as *mut hyper_headers;
+        assert_eq!(unsafe { &(*trailers).headers }["grpc-status"], "0");
+        hyper_headers_free(trailers);
+        hyper_task_free(task);
+
+        hyper_body_free(body);
+        hyper_executor_free(exec);
+    }
+}
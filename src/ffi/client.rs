@@ -1,7 +1,9 @@
 use std::ptr;
 use std::sync::Arc;
+#[cfg(feature = "http2")]
+use std::time::Duration;
 
-use libc::c_int;
+use libc::{c_int, size_t};
 
 use crate::client::conn;
 use crate::rt::Executor as _;
@@ -10,6 +12,7 @@ use super::error::hyper_code;
 use super::http_types::{hyper_request, hyper_response};
 use super::io::hyper_io;
 use super::task::{hyper_executor, hyper_task, hyper_task_return_type, AsTaskType, WeakExec};
+use super::timer::{hyper_timer, FfiTimer};
 
 /// An options builder to configure an HTTP client connection.
 pub struct hyper_clientconn_options {
@@ -18,8 +21,11 @@ pub struct hyper_clientconn_options {
     http1_preserve_header_case: bool,
     http1_preserve_header_order: bool,
     http2: bool,
+    #[cfg(feature = "http2")]
+    http2_builder: conn::http2::Builder,
     /// Use a `Weak` to prevent cycles.
     exec: WeakExec,
+    timer: Option<FfiTimer>,
 }
 
 /// An HTTP client connection handle.
@@ -49,14 +55,24 @@ ffi_fn! {
     /// The returned `hyper_task *` must be polled with an executor until the
     /// handshake completes, at which point the value can be taken.
     fn hyper_clientconn_handshake(io: *mut hyper_io, options: *mut hyper_clientconn_options) -> *mut hyper_task {
-        let options = non_null! { Box::from_raw(options) ?= ptr::null_mut() };
+        let mut options = non_null! { Box::from_raw(options) ?= ptr::null_mut() };
         let io = non_null! { Box::from_raw(io) ?= ptr::null_mut() };
 
         Box::into_raw(hyper_task::boxed(async move {
             #[cfg(feature = "http2")]
             {
             if options.http2 {
-                return conn::http2::Builder::new()
+                match options.timer {
+                    Some(ref timer) => {
+                        options.http2_builder.timer(timer.clone());
+                    }
+                    // Keep-alive pings can't be sent without a timer.
+                    None => {
+                        options.http2_builder.http2_keep_alive_interval(None);
+                    }
+                }
+                return options
+                    .http2_builder
                     .executor(options.exec.clone())
                     .handshake::<_, crate::Recv>(io)
                     .await
@@ -135,7 +151,10 @@ ffi_fn! {
             http1_preserve_header_case: false,
             http1_preserve_header_order: false,
             http2: false,
+            #[cfg(feature = "http2")]
+            http2_builder: conn::http2::Builder::new(),
             exec: WeakExec::new(),
+            timer: None,
         }))
     } ?= std::ptr::null_mut()
 }
@@ -182,6 +201,18 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Set the timer used for time-based features, such as HTTP/2 keep-alive
+    /// pings.
+    ///
+    /// This does not consume the `options` or the `timer`.
+    fn hyper_clientconn_options_timer(opts: *mut hyper_clientconn_options, timer: *const hyper_timer) {
+        let opts = non_null! { &mut *opts ?= () };
+        let timer = non_null! { &*timer ?= () };
+        opts.timer = Some(FfiTimer::new(timer));
+    }
+}
+
 ffi_fn! {
     /// Set the whether to use HTTP2.
     ///
@@ -203,6 +234,187 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Set the `SETTINGS_INITIAL_WINDOW_SIZE` option for HTTP/2 stream-level
+    /// flow control.
+    ///
+    /// This disables the adaptive window. If not set, hyper will use a default.
+    fn hyper_clientconn_options_http2_initial_stream_window_size(opts: *mut hyper_clientconn_options, size: u32) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_initial_stream_window_size(size);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(size);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the max connection-level flow control window for HTTP/2.
+    ///
+    /// This disables the adaptive window. If not set, hyper will use a default.
+    fn hyper_clientconn_options_http2_initial_connection_window_size(opts: *mut hyper_clientconn_options, size: u32) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_initial_connection_window_size(size);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(size);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set whether to use an adaptive flow control window for HTTP/2.
+    ///
+    /// Pass `0` to disable (default), `1` to enable. Enabling this overrides the
+    /// initial stream and connection window sizes.
+    fn hyper_clientconn_options_http2_adaptive_window(opts: *mut hyper_clientconn_options, enabled: c_int) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_adaptive_window(enabled != 0);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(enabled);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the maximum frame size to use for HTTP/2.
+    ///
+    /// If not set, hyper will use a default.
+    fn hyper_clientconn_options_http2_max_frame_size(opts: *mut hyper_clientconn_options, size: u32) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_max_frame_size(size);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(size);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set an interval in milliseconds for HTTP/2 Ping frames to be sent to
+    /// keep a connection alive.
+    ///
+    /// Pass `0` to disable keep-alive (default). Pings are only sent if a
+    /// timer was set with `hyper_clientconn_options_timer`.
+    fn hyper_clientconn_options_http2_keep_alive_interval(opts: *mut hyper_clientconn_options, interval_ms: u64) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            let interval = if interval_ms == 0 {
+                None
+            } else {
+                Some(Duration::from_millis(interval_ms))
+            };
+            opts.http2_builder.http2_keep_alive_interval(interval);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(interval_ms);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set a timeout in milliseconds for receiving an acknowledgement of the
+    /// HTTP/2 keep-alive ping.
+    ///
+    /// If the ping is not acknowledged within the timeout, the connection is
+    /// closed. The default is 20 seconds.
+    fn hyper_clientconn_options_http2_keep_alive_timeout(opts: *mut hyper_clientconn_options, timeout_ms: u64) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_keep_alive_timeout(Duration::from_millis(timeout_ms));
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(timeout_ms);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set whether HTTP/2 keep-alive pings are also sent while the
+    /// connection is idle.
+    ///
+    /// Pass `0` to only send pings while requests are in flight (default),
+    /// `1` to always send them.
+    fn hyper_clientconn_options_http2_keep_alive_while_idle(opts: *mut hyper_clientconn_options, enabled: c_int) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_keep_alive_while_idle(enabled != 0);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(enabled);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the maximum number of HTTP/2 concurrent locally reset streams.
+    ///
+    /// The default value is determined by the `h2` crate.
+    fn hyper_clientconn_options_http2_max_concurrent_reset_streams(opts: *mut hyper_clientconn_options, max: size_t) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2_builder.http2_max_concurrent_reset_streams(max);
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(max);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
 ffi_fn! {
     /// Set the whether to include a copy of the raw headers in responses
     /// received on this connection.
@@ -1,4 +1,4 @@
-use libc::size_t;
+use libc::{c_int, size_t};
 
 /// A more detailed error object returned by some hyper functions.
 pub struct hyper_error(crate::Error);
@@ -24,6 +24,46 @@ pub enum hyper_code {
     HYPERE_FEATURE_NOT_ENABLED,
     /// The peer sent an HTTP message that could not be parsed.
     HYPERE_INVALID_PEER_MESSAGE,
+    /// A task was canceled with `hyper_task_cancel`, or a request was dropped
+    /// before the connection could process it.
+    HYPERE_CANCELED,
+    /// An operation didn't complete in time, such as a header read timeout.
+    HYPERE_TIMEOUT,
+    /// A channel was closed, such as sending a request on a connection that
+    /// already closed.
+    HYPERE_CLOSED,
+    /// The IO transport failed, details may be available with
+    /// `hyper_error_os_error`.
+    HYPERE_IO,
+    /// An HTTP/2 stream was reset, the reason is available with
+    /// `hyper_error_h2_reason`.
+    HYPERE_HTTP2_STREAM_RESET,
+    /// An HTTP/2 protocol error, the reason is available with
+    /// `hyper_error_h2_reason`.
+    HYPERE_HTTP2,
+}
+
+/// The category of an error parsing an HTTP message from the peer.
+#[repr(C)]
+pub enum hyper_parse_error {
+    /// The error isn't a parse error.
+    HYPER_PARSE_NONE,
+    /// Invalid request method.
+    HYPER_PARSE_METHOD,
+    /// Invalid or unsupported HTTP version.
+    HYPER_PARSE_VERSION,
+    /// Invalid request target.
+    HYPER_PARSE_URI,
+    /// The request target is too long.
+    HYPER_PARSE_URI_TOO_LONG,
+    /// Invalid header.
+    HYPER_PARSE_HEADER,
+    /// The message head is too large.
+    HYPER_PARSE_TOO_LARGE,
+    /// Invalid response status code or reason phrase.
+    HYPER_PARSE_STATUS,
+    /// An internal error while parsing.
+    HYPER_PARSE_INTERNAL,
 }
 
 // ===== impl hyper_error =====
@@ -33,12 +73,77 @@ impl hyper_error {
         use crate::error::Kind as ErrorKind;
         use crate::error::User;
 
-        match self.0.kind() {
+        if self.0.is_timeout() {
+            return hyper_code::HYPERE_TIMEOUT;
+        }
+
+        match self.0.inner_kind() {
             ErrorKind::Parse(_) => hyper_code::HYPERE_INVALID_PEER_MESSAGE,
-            ErrorKind::IncompleteMessage => hyper_code::HYPERE_UNEXPECTED_EOF,
+            ErrorKind::IncompleteMessage(_) => hyper_code::HYPERE_UNEXPECTED_EOF,
             ErrorKind::User(User::AbortedByCallback) => hyper_code::HYPERE_ABORTED_BY_CALLBACK,
-            // TODO: add more variants
-            _ => hyper_code::HYPERE_ERROR,
+            ErrorKind::Canceled => hyper_code::HYPERE_CANCELED,
+            #[cfg(all(feature = "http1", feature = "server"))]
+            ErrorKind::HeaderTimeout => hyper_code::HYPERE_TIMEOUT,
+            ErrorKind::ChannelClosed => hyper_code::HYPERE_CLOSED,
+            _ => self.cause_code(),
+        }
+    }
+
+    /// The code of an error that is only described by its cause.
+    fn cause_code(&self) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            if let Some(err) = self.0.find_source::<h2::Error>() {
+                return if err.is_io() {
+                    hyper_code::HYPERE_IO
+                } else if err.is_reset() {
+                    hyper_code::HYPERE_HTTP2_STREAM_RESET
+                } else {
+                    hyper_code::HYPERE_HTTP2
+                };
+            }
+        }
+
+        if self.0.find_source::<std::io::Error>().is_some() {
+            hyper_code::HYPERE_IO
+        } else {
+            hyper_code::HYPERE_ERROR
+        }
+    }
+
+    fn os_error(&self) -> Option<i32> {
+        #[cfg(feature = "http2")]
+        {
+            if let Some(err) = self
+                .0
+                .find_source::<h2::Error>()
+                .and_then(h2::Error::get_io)
+            {
+                return err.raw_os_error();
+            }
+        }
+
+        self.0
+            .find_source::<std::io::Error>()
+            .and_then(std::io::Error::raw_os_error)
+    }
+
+    fn parse_error(&self) -> hyper_parse_error {
+        use crate::error::Kind as ErrorKind;
+        use crate::error::Parse;
+
+        match self.0.inner_kind() {
+            ErrorKind::Parse(Parse::Method) => hyper_parse_error::HYPER_PARSE_METHOD,
+            ErrorKind::Parse(Parse::Version) => hyper_parse_error::HYPER_PARSE_VERSION,
+            #[cfg(feature = "http1")]
+            ErrorKind::Parse(Parse::VersionH2) => hyper_parse_error::HYPER_PARSE_VERSION,
+            ErrorKind::Parse(Parse::Uri) => hyper_parse_error::HYPER_PARSE_URI,
+            ErrorKind::Parse(Parse::UriTooLong) => hyper_parse_error::HYPER_PARSE_URI_TOO_LONG,
+            ErrorKind::Parse(Parse::Header(_)) => hyper_parse_error::HYPER_PARSE_HEADER,
+            ErrorKind::Parse(Parse::TooLarge) => hyper_parse_error::HYPER_PARSE_TOO_LARGE,
+            ErrorKind::Parse(Parse::Status) => hyper_parse_error::HYPER_PARSE_STATUS,
+            ErrorKind::Parse(Parse::Internal) => hyper_parse_error::HYPER_PARSE_INTERNAL,
+            _ => hyper_parse_error::HYPER_PARSE_NONE,
         }
     }
 
@@ -83,3 +188,84 @@ ffi_fn! {
         non_null!(&*err ?= 0).print_to(dst)
     }
 }
+
+ffi_fn! {
+    /// Get the OS error code of the IO error that caused this error, such as
+    /// an `errno` value.
+    ///
+    /// Returns `0` if the error wasn't caused by an IO error, or if the IO
+    /// error had no OS error code.
+    fn hyper_error_os_error(err: *const hyper_error) -> c_int {
+        non_null!(&*err ?= 0).os_error().unwrap_or(0)
+    }
+}
+
+ffi_fn! {
+    /// Get the HTTP/2 error code that caused this error.
+    ///
+    /// Returns `0` (`NO_ERROR`) if the error wasn't caused by an HTTP/2 error
+    /// with a reason, such as a `RST_STREAM` or `GOAWAY` frame.
+    fn hyper_error_h2_reason(err: *const hyper_error) -> u32 {
+        let _err = non_null!(&*err ?= 0);
+
+        #[cfg(feature = "http2")]
+        {
+            _err.0
+                .find_source::<h2::Error>()
+                .and_then(h2::Error::reason)
+                .map_or(0, u32::from)
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            0
+        }
+    }
+}
+
+ffi_fn! {
+    /// Get the category of a parse error.
+    ///
+    /// Returns `HYPER_PARSE_NONE` if the error code isn't
+    /// `HYPERE_INVALID_PEER_MESSAGE`.
+    fn hyper_error_parse_error(err: *const hyper_error) -> hyper_parse_error {
+        non_null!(&*err ?= hyper_parse_error::HYPER_PARSE_NONE).parse_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let io = hyper_error(crate::Error::new_io(std::io::Error::from_raw_os_error(
+            libc::ECONNRESET,
+        )));
+        assert!(matches!(io.code(), hyper_code::HYPERE_IO));
+        assert_eq!(hyper_error_os_error(&io), libc::ECONNRESET);
+        assert_eq!(hyper_error_h2_reason(&io), 0);
+
+        let h2 = hyper_error(crate::Error::new_h2(h2::Reason::ENHANCE_YOUR_CALM.into()));
+        assert!(matches!(h2.code(), hyper_code::HYPERE_HTTP2));
+        assert_eq!(hyper_error_h2_reason(&h2), 0xb);
+        assert_eq!(hyper_error_os_error(&h2), 0);
+
+        let timeout = hyper_error(crate::Error::new_header_timeout());
+        assert!(matches!(timeout.code(), hyper_code::HYPERE_TIMEOUT));
+
+        let too_large = hyper_error(crate::Error::new_too_large());
+        assert!(matches!(
+            too_large.code(),
+            hyper_code::HYPERE_INVALID_PEER_MESSAGE
+        ));
+        assert!(matches!(
+            hyper_error_parse_error(&too_large),
+            hyper_parse_error::HYPER_PARSE_TOO_LARGE
+        ));
+        assert!(matches!(
+            hyper_error_parse_error(&io),
+            hyper_parse_error::HYPER_PARSE_NONE
+        ));
+    }
+}
@@ -1,14 +1,15 @@
 use bytes::Bytes;
 use libc::{c_int, size_t};
+use std::convert::TryFrom;
 use std::ffi::c_void;
 
 use super::body::{hyper_body, hyper_buf};
 use super::error::hyper_code;
 use super::task::{hyper_task_return_type, AsTaskType};
-use super::{UserDataPointer, HYPER_ITER_CONTINUE};
+use super::{hyper_userdata_drop, UserData, HYPER_ITER_CONTINUE};
 use crate::ext::{HeaderCaseMap, OriginalHeaderOrder, ReasonPhrase};
 use crate::header::{HeaderName, HeaderValue};
-use crate::{HeaderMap, Method, Recv, Request, Response, Uri};
+use crate::{HeaderMap, Method, Recv, Request, Response, StatusCode, Uri};
 
 /// An HTTP request.
 pub struct hyper_request(pub(super) Request<Recv>);
@@ -29,7 +30,7 @@ pub(crate) struct RawHeaders(pub(crate) hyper_buf);
 
 pub(crate) struct OnInformational {
     func: hyper_request_on_informational_callback,
-    data: UserDataPointer,
+    data: UserData,
 }
 
 type hyper_request_on_informational_callback = extern "C" fn(*mut c_void, *mut hyper_response);
@@ -212,13 +213,20 @@ ffi_fn! {
     /// `hyper_response *` which can be inspected as any other response. The
     /// body of the response will always be empty.
     ///
+    /// This can be used to read `103 Early Hints`, or to implement
+    /// `Expect: 100-continue`: provide the body with a `hyper_body_sender *`
+    /// whose callback returns `HYPER_POLL_LATER`, and call
+    /// `hyper_body_sender_resume` once the `100 Continue` response arrives.
+    ///
+    /// Informational responses are only received on HTTP/1 connections.
+    ///
     /// NOTE: The `hyper_response *` is just borrowed data, and will not
     /// be valid after the callback finishes. You must copy any data you wish
     /// to persist.
     fn hyper_request_on_informational(req: *mut hyper_request, callback: hyper_request_on_informational_callback, data: *mut c_void) -> hyper_code {
         let ext = OnInformational {
             func: callback,
-            data: UserDataPointer(data),
+            data: UserData::new(data, None),
         };
         let req = non_null!(&mut *req ?= hyper_code::HYPERE_INVALID_ARG);
         req.0.extensions_mut().insert(ext);
@@ -226,7 +234,145 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Set an informational (1xx) response callback, and a callback to free
+    /// its user data.
+    ///
+    /// This is like `hyper_request_on_informational`, except the `drop`
+    /// callback is called with the user data once hyper is done with the
+    /// callback, or the request is freed.
+    fn hyper_request_on_informational_with_drop(req: *mut hyper_request, callback: hyper_request_on_informational_callback, data: *mut c_void, drop: hyper_userdata_drop) -> hyper_code {
+        let ext = OnInformational {
+            func: callback,
+            data: UserData::new(data, Some(drop)),
+        };
+        let req = non_null!(&mut *req ?= hyper_code::HYPERE_INVALID_ARG);
+        req.0.extensions_mut().insert(ext);
+        hyper_code::HYPERE_OK
+    }
+}
+
+ffi_fn! {
+    /// Get a pointer to the HTTP method of this request.
+    ///
+    /// This buffer is not null-terminated.
+    ///
+    /// This buffer is owned by the request, and should not be used after
+    /// the request has been freed.
+    ///
+    /// Use `hyper_request_method_len()` to get the length of this buffer.
+    fn hyper_request_method(req: *const hyper_request) -> *const u8 {
+        non_null!(&*req ?= std::ptr::null()).0.method().as_str().as_ptr()
+    } ?= std::ptr::null()
+}
+
+ffi_fn! {
+    /// Get the length of the HTTP method of this request.
+    ///
+    /// Use `hyper_request_method()` to get the buffer pointer.
+    fn hyper_request_method_len(req: *const hyper_request) -> size_t {
+        non_null!(&*req ?= 0).0.method().as_str().len()
+    }
+}
+
+ffi_fn! {
+    /// Get a pointer to the path and query of this request's URI.
+    ///
+    /// This buffer is not null-terminated, and is empty if the URI has no
+    /// path, such as for a `CONNECT` request.
+    ///
+    /// This buffer is owned by the request, and should not be used after
+    /// the request has been freed.
+    ///
+    /// Use `hyper_request_path_and_query_len()` to get the length of this
+    /// buffer.
+    fn hyper_request_path_and_query(req: *const hyper_request) -> *const u8 {
+        non_null!(&*req ?= std::ptr::null()).path_and_query().as_ptr()
+    } ?= std::ptr::null()
+}
+
+ffi_fn! {
+    /// Get the length of the path and query of this request's URI.
+    ///
+    /// Use `hyper_request_path_and_query()` to get the buffer pointer.
+    fn hyper_request_path_and_query_len(req: *const hyper_request) -> size_t {
+        non_null!(&*req ?= 0).path_and_query().len()
+    }
+}
+
+ffi_fn! {
+    /// Get a pointer to the authority of this request's URI.
+    ///
+    /// This buffer is not null-terminated, and is empty if the URI has no
+    /// authority. HTTP/1 requests usually carry it in the `Host` header
+    /// instead.
+    ///
+    /// This buffer is owned by the request, and should not be used after
+    /// the request has been freed.
+    ///
+    /// Use `hyper_request_authority_len()` to get the length of this buffer.
+    fn hyper_request_authority(req: *const hyper_request) -> *const u8 {
+        non_null!(&*req ?= std::ptr::null()).authority().as_ptr()
+    } ?= std::ptr::null()
+}
+
+ffi_fn! {
+    /// Get the length of the authority of this request's URI.
+    ///
+    /// Use `hyper_request_authority()` to get the buffer pointer.
+    fn hyper_request_authority_len(req: *const hyper_request) -> size_t {
+        non_null!(&*req ?= 0).authority().len()
+    }
+}
+
+ffi_fn! {
+    /// Get the HTTP version used by this request.
+    ///
+    /// The returned value could be:
+    ///
+    /// - `HYPER_HTTP_VERSION_1_0`
+    /// - `HYPER_HTTP_VERSION_1_1`
+    /// - `HYPER_HTTP_VERSION_2`
+    /// - `HYPER_HTTP_VERSION_NONE` if newer (or older).
+    fn hyper_request_version(req: *const hyper_request) -> c_int {
+        version_code(non_null!(&*req ?= 0).0.version())
+    }
+}
+
+ffi_fn! {
+    /// Take ownership of the body of this request.
+    ///
+    /// It is safe to free the request even after taking ownership of its body.
+    fn hyper_request_body(req: *mut hyper_request) -> *mut hyper_body {
+        let body = std::mem::replace(non_null!(&mut *req ?= std::ptr::null_mut()).0.body_mut(), crate::Recv::empty());
+        Box::into_raw(Box::new(hyper_body(body)))
+    } ?= std::ptr::null_mut()
+}
+
 impl hyper_request {
+    #[cfg(feature = "server")]
+    pub(super) fn wrap(mut req: Request<Recv>) -> hyper_request {
+        let headers = std::mem::take(req.headers_mut());
+        let headers = hyper_headers::received(headers, req.extensions_mut());
+        req.extensions_mut().insert(headers);
+
+        hyper_request(req)
+    }
+
+    fn path_and_query(&self) -> &[u8] {
+        self.0
+            .uri()
+            .path_and_query()
+            .map_or(&[], |p| p.as_str().as_bytes())
+    }
+
+    fn authority(&self) -> &[u8] {
+        self.0
+            .uri()
+            .authority()
+            .map_or(&[], |a| a.as_str().as_bytes())
+    }
+
     pub(super) fn finalize_request(&mut self) {
         if let Some(headers) = self.0.extensions_mut().remove::<hyper_headers>() {
             *self.0.headers_mut() = headers.headers;
@@ -238,6 +384,69 @@ impl hyper_request {
 
 // ===== impl hyper_response =====
 
+ffi_fn! {
+    /// Construct a new HTTP response.
+    ///
+    /// The response has a `200 OK` status and an empty body until changed.
+    fn hyper_response_new() -> *mut hyper_response {
+        Box::into_raw(Box::new(hyper_response(Response::new(Recv::empty()))))
+    } ?= std::ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Set the HTTP-Status code of this response.
+    ///
+    /// The code must be within the range of 100-999.
+    fn hyper_response_set_status(resp: *mut hyper_response, status: u16) -> hyper_code {
+        let resp = non_null!(&mut *resp ?= hyper_code::HYPERE_INVALID_ARG);
+        match StatusCode::from_u16(status) {
+            Ok(status) => {
+                *resp.0.status_mut() = status;
+                hyper_code::HYPERE_OK
+            }
+            Err(_) => hyper_code::HYPERE_INVALID_ARG,
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the reason-phrase of this response.
+    ///
+    /// By default, the canonical reason of the status code is sent, such as
+    /// `OK` for `200`. The reason-phrase is only sent on HTTP/1 connections.
+    ///
+    /// Returns `HYPERE_INVALID_ARG` if the bytes aren't a valid
+    /// reason-phrase, such as when they contain a newline.
+    fn hyper_response_set_reason_phrase(resp: *mut hyper_response, reason: *const u8, reason_len: size_t) -> hyper_code {
+        let resp = non_null!(&mut *resp ?= hyper_code::HYPERE_INVALID_ARG);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(reason, reason_len as usize)
+        };
+        match ReasonPhrase::try_from(bytes) {
+            Ok(reason) => {
+                resp.0.extensions_mut().insert(reason);
+                hyper_code::HYPERE_OK
+            }
+            Err(_) => hyper_code::HYPERE_INVALID_ARG,
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set the body of the response.
+    ///
+    /// The default is an empty body.
+    ///
+    /// This takes ownership of the `hyper_body *`, you must not use it or
+    /// free it after setting it on the response.
+    fn hyper_response_set_body(resp: *mut hyper_response, body: *mut hyper_body) -> hyper_code {
+        let body = non_null!(Box::from_raw(body) ?= hyper_code::HYPERE_INVALID_ARG);
+        let resp = non_null!(&mut *resp ?= hyper_code::HYPERE_INVALID_ARG);
+        *resp.0.body_mut() = body.0;
+        hyper_code::HYPERE_OK
+    }
+}
+
 ffi_fn! {
     /// Free an HTTP response after using it.
     fn hyper_response_free(resp: *mut hyper_response) {
@@ -309,14 +518,7 @@ ffi_fn! {
     /// - `HYPER_HTTP_VERSION_2`
     /// - `HYPER_HTTP_VERSION_NONE` if newer (or older).
     fn hyper_response_version(resp: *const hyper_response) -> c_int {
-        use http::Version;
-
-        match non_null!(&*resp ?= 0).0.version() {
-            Version::HTTP_10 => super::HYPER_HTTP_VERSION_1_0,
-            Version::HTTP_11 => super::HYPER_HTTP_VERSION_1_1,
-            Version::HTTP_2 => super::HYPER_HTTP_VERSION_2,
-            _ => super::HYPER_HTTP_VERSION_NONE,
-        }
+        version_code(non_null!(&*resp ?= 0).0.version())
     }
 }
 
@@ -343,23 +545,21 @@ ffi_fn! {
 impl hyper_response {
     pub(super) fn wrap(mut resp: Response<Recv>) -> hyper_response {
         let headers = std::mem::take(resp.headers_mut());
-        let orig_casing = resp
-            .extensions_mut()
-            .remove::<HeaderCaseMap>()
-            .unwrap_or_else(HeaderCaseMap::default);
-        let orig_order = resp
-            .extensions_mut()
-            .remove::<OriginalHeaderOrder>()
-            .unwrap_or_else(OriginalHeaderOrder::default);
-        resp.extensions_mut().insert(hyper_headers {
-            headers,
-            orig_casing,
-            orig_order,
-        });
+        let headers = hyper_headers::received(headers, resp.extensions_mut());
+        resp.extensions_mut().insert(headers);
 
         hyper_response(resp)
     }
 
+    #[cfg(feature = "server")]
+    pub(super) fn finalize_response(&mut self) {
+        if let Some(headers) = self.0.extensions_mut().remove::<hyper_headers>() {
+            *self.0.headers_mut() = headers.headers;
+            self.0.extensions_mut().insert(headers.orig_casing);
+            self.0.extensions_mut().insert(headers.orig_order);
+        }
+    }
+
     fn reason_phrase(&self) -> &[u8] {
         if let Some(reason) = self.0.extensions().get::<ReasonPhrase>() {
             return reason.as_bytes();
@@ -381,6 +581,12 @@ unsafe impl AsTaskType for hyper_response {
 
 // ===== impl Headers =====
 
+unsafe impl AsTaskType for hyper_headers {
+    fn as_task_type(&self) -> hyper_task_return_type {
+        hyper_task_return_type::HYPER_TASK_HEADERS
+    }
+}
+
 type hyper_headers_foreach_callback =
     extern "C" fn(*mut c_void, *const u8, size_t, *const u8, size_t) -> c_int;
 
@@ -392,6 +598,27 @@ impl hyper_headers {
 
         ext.get_mut::<hyper_headers>().unwrap()
     }
+
+    pub(super) fn new(headers: HeaderMap) -> hyper_headers {
+        hyper_headers {
+            headers,
+            ..Default::default()
+        }
+    }
+
+    /// Wraps the headers of a received message, along with their original
+    /// casing and order if those were recorded in its extensions.
+    fn received(headers: HeaderMap, ext: &mut http::Extensions) -> hyper_headers {
+        hyper_headers {
+            headers,
+            orig_casing: ext
+                .remove::<HeaderCaseMap>()
+                .unwrap_or_else(HeaderCaseMap::default),
+            orig_order: ext
+                .remove::<OriginalHeaderOrder>()
+                .unwrap_or_else(OriginalHeaderOrder::default),
+        }
+    }
 }
 
 ffi_fn! {
@@ -498,6 +725,27 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Create a new, empty set of headers.
+    ///
+    /// These can be sent as the trailers of a body with
+    /// `hyper_body_sender_send_trailers`.
+    fn hyper_headers_new() -> *mut hyper_headers {
+        Box::into_raw(Box::new(hyper_headers::default()))
+    } ?= std::ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Free a `hyper_headers *` that was returned as the value of a task, or
+    /// created with `hyper_headers_new`.
+    ///
+    /// The headers of a request or response are owned by it, and must not be
+    /// freed with this function.
+    fn hyper_headers_free(headers: *mut hyper_headers) {
+        drop(non_null!(Box::from_raw(headers) ?= ()));
+    }
+}
+
 impl Default for hyper_headers {
     fn default() -> Self {
         Self {
@@ -508,6 +756,17 @@ impl Default for hyper_headers {
     }
 }
 
+fn version_code(version: http::Version) -> c_int {
+    use http::Version;
+
+    match version {
+        Version::HTTP_10 => super::HYPER_HTTP_VERSION_1_0,
+        Version::HTTP_11 => super::HYPER_HTTP_VERSION_1_1,
+        Version::HTTP_2 => super::HYPER_HTTP_VERSION_2,
+        _ => super::HYPER_HTTP_VERSION_NONE,
+    }
+}
+
 unsafe fn raw_name_value(
     name: *const u8,
     name_len: size_t,
@@ -534,13 +793,15 @@ unsafe fn raw_name_value(
 impl OnInformational {
     pub(crate) fn call(&mut self, resp: Response<Recv>) {
         let mut resp = hyper_response::wrap(resp);
-        (self.func)(self.data.0, &mut resp);
+        (self.func)(self.data.as_ptr(), &mut resp);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "client")]
+    use crate::common::io::Compat;
 
     #[test]
     fn test_headers_foreach_cases_preserved() {
@@ -654,4 +915,137 @@ mod tests {
             HYPER_ITER_CONTINUE
         }
     }
+
+    extern "C" fn record_informational(data: *mut c_void, resp: *mut hyper_response) {
+        let seen = unsafe { &mut *(data as *mut Vec<(u16, Option<String>)>) };
+        let headers = unsafe { &(*hyper_response_headers(resp)).headers };
+        let link = headers.get("link").map(|v| v.to_str().unwrap().to_owned());
+        seen.push((hyper_response_status(resp), link));
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_on_informational_each_1xx() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (io, mut server) = tokio::io::duplex(1024);
+        let (mut tx, conn) = crate::client::conn::http1::handshake(Compat::new(io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let mut seen: Vec<(u16, Option<String>)> = Vec::new();
+        let mut req = hyper_request(Request::new(Recv::empty()));
+        hyper_request_on_informational(
+            &mut req,
+            record_informational,
+            &mut seen as *mut _ as *mut c_void,
+        );
+        let res = tx.send_request(req.0);
+
+        let server = async move {
+            let mut buf = [0; 1024];
+            let _ = server.read(&mut buf).await.unwrap();
+            server
+                .write_all(
+                    b"HTTP/1.1 100 Continue\r\n\r\n\
+                      HTTP/1.1 103 Early Hints\r\nLink: </style.css>\r\n\r\n\
+                      HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            server
+        };
+        let (res, _server) = tokio::join!(res, server);
+
+        assert_eq!(res.unwrap().status(), 200);
+        assert_eq!(seen, [(100, None), (103, Some("</style.css>".to_owned()))]);
+    }
+
+    extern "C" fn record_status(data: *mut c_void, resp: *mut hyper_response) {
+        let status = unsafe { &*(data as *const std::sync::atomic::AtomicU16) };
+        status.store(
+            hyper_response_status(resp),
+            std::sync::atomic::Ordering::SeqCst,
+        );
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_on_informational_before_request_body() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (io, mut server) = tokio::io::duplex(1024);
+        let (mut tx, conn) = crate::client::conn::http1::handshake(Compat::new(io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let informational = AtomicU16::new(0);
+        let (mut body_tx, body) = Recv::channel();
+        let mut req = hyper_request(Request::new(body));
+        *req.0.method_mut() = Method::POST;
+        req.0
+            .headers_mut()
+            .insert("expect", "100-continue".parse().unwrap());
+        hyper_request_on_informational(
+            &mut req,
+            record_status,
+            &informational as *const AtomicU16 as *mut c_void,
+        );
+        let res = tokio::spawn(tx.send_request(req.0));
+
+        // Only the head is sent while the body waits for `100 Continue`.
+        let mut buf = vec![0; 1024];
+        let n = server.read(&mut buf).await.unwrap();
+        assert!(buf[..n].ends_with(b"\r\n\r\n"));
+        server
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await
+            .unwrap();
+
+        while informational.load(Ordering::SeqCst) != 100 {
+            tokio::task::yield_now().await;
+        }
+        body_tx.send_data("hello".into()).await.unwrap();
+        drop(body_tx);
+
+        let mut received = Vec::new();
+        while !received.ends_with(b"0\r\n\r\n") {
+            let n = server.read(&mut buf).await.unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(received, b"5\r\nhello\r\n0\r\n\r\n");
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(res.await.unwrap().unwrap().status(), 200);
+    }
+
+    #[test]
+    fn test_response_reason_phrase() {
+        let resp = hyper_response_new();
+        let custom = b"Totally Fine";
+        assert!(matches!(
+            hyper_response_set_reason_phrase(resp, custom.as_ptr(), custom.len()),
+            hyper_code::HYPERE_OK
+        ));
+        let reason = unsafe {
+            std::slice::from_raw_parts(
+                hyper_response_reason_phrase(resp),
+                hyper_response_reason_phrase_len(resp),
+            )
+        };
+        assert_eq!(reason, custom);
+
+        let invalid = b"Not\r\nOK";
+        assert!(matches!(
+            hyper_response_set_reason_phrase(resp, invalid.as_ptr(), invalid.len()),
+            hyper_code::HYPERE_INVALID_ARG
+        ));
+        hyper_response_free(resp);
+    }
 }
@@ -3,9 +3,10 @@ use std::pin::Pin;
 use std::task::{Context, Poll};
 
 use libc::size_t;
-use tokio::io::{AsyncRead, AsyncWrite};
 
 use super::task::hyper_context;
+use super::{hyper_userdata_drop, UserData};
+use crate::rt::{Read, ReadBufCursor, Write};
 
 /// Sentinel value to return from a read or write callback that the operation
 /// is pending.
@@ -23,7 +24,7 @@ type hyper_io_write_callback =
 pub struct hyper_io {
     read: hyper_io_read_callback,
     write: hyper_io_write_callback,
-    userdata: *mut c_void,
+    userdata: UserData,
 }
 
 ffi_fn! {
@@ -35,7 +36,7 @@ ffi_fn! {
         Box::into_raw(Box::new(hyper_io {
             read: read_noop,
             write: write_noop,
-            userdata: std::ptr::null_mut(),
+            userdata: UserData::empty(),
         }))
     } ?= std::ptr::null_mut()
 }
@@ -55,7 +56,18 @@ ffi_fn! {
     ///
     /// This value is passed as an argument to the read and write callbacks.
     fn hyper_io_set_userdata(io: *mut hyper_io, data: *mut c_void) {
-        non_null!(&mut *io ?= ()).userdata = data;
+        non_null!(&mut *io ?= ()).userdata = UserData::new(data, None);
+    }
+}
+
+ffi_fn! {
+    /// Set the user data pointer for this IO, and a callback to free it.
+    ///
+    /// This is like `hyper_io_set_userdata`, except the `drop` callback is
+    /// called with the user data once hyper frees the IO, such as when its
+    /// connection is closed.
+    fn hyper_io_set_userdata_with_drop(io: *mut hyper_io, data: *mut c_void, drop: hyper_userdata_drop) {
+        non_null!(&mut *io ?= ()).userdata = UserData::new(data, Some(drop));
     }
 }
 
@@ -75,7 +87,8 @@ ffi_fn! {
     /// `HYPER_IO_PENDING`.
     ///
     /// If there is an irrecoverable error reading data, then `HYPER_IO_ERROR`
-    /// should be the return value.
+    /// should be the return value. If `errno` is set at that point, it is
+    /// available from the resulting error with `hyper_error_os_error`.
     fn hyper_io_set_read(io: *mut hyper_io, func: hyper_io_read_callback) {
         non_null!(&mut *io ?= ()).read = func;
     }
@@ -94,7 +107,8 @@ ffi_fn! {
     /// Yeet.
     ///
     /// If there is an irrecoverable error reading data, then `HYPER_IO_ERROR`
-    /// should be the return value.
+    /// should be the return value. If `errno` is set at that point, it is
+    /// available from the resulting error with `hyper_error_os_error`.
     fn hyper_io_set_write(io: *mut hyper_io, func: hyper_io_write_callback) {
         non_null!(&mut *io ?= ()).write = func;
     }
@@ -120,33 +134,46 @@ extern "C" fn write_noop(
     0
 }
 
-impl AsyncRead for hyper_io {
+/// The error of a callback that returned `HYPER_IO_ERROR`.
+///
+/// The callback is expected to leave `errno` set by the system call that
+/// failed, as it would be right after a failed `read` or `write`.
+fn callback_error() -> std::io::Error {
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(code) if code != 0 => err,
+        _ => std::io::Error::new(std::io::ErrorKind::Other, "io error"),
+    }
+}
+
+impl Read for hyper_io {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        buf: &mut tokio::io::ReadBuf<'_>,
+        mut buf: ReadBufCursor<'_>,
     ) -> Poll<std::io::Result<()>> {
-        let buf_ptr = unsafe { buf.unfilled_mut() }.as_mut_ptr() as *mut u8;
+        let buf_ptr = unsafe { buf.as_mut() }.as_mut_ptr() as *mut u8;
         let buf_len = buf.remaining();
 
-        match (self.read)(self.userdata, hyper_context::wrap(cx), buf_ptr, buf_len) {
+        match (self.read)(
+            self.userdata.as_ptr(),
+            hyper_context::wrap(cx),
+            buf_ptr,
+            buf_len,
+        ) {
             HYPER_IO_PENDING => Poll::Pending,
-            HYPER_IO_ERROR => Poll::Ready(Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "io error",
-            ))),
+            HYPER_IO_ERROR => Poll::Ready(Err(callback_error())),
             ok => {
                 // We have to trust that the user's read callback actually
                 // filled in that many bytes... :(
-                unsafe { buf.assume_init(ok) };
-                buf.advance(ok);
+                unsafe { buf.advance(ok) };
                 Poll::Ready(Ok(()))
             }
         }
     }
 }
 
-impl AsyncWrite for hyper_io {
+impl Write for hyper_io {
     fn poll_write(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
@@ -155,12 +182,14 @@ impl AsyncWrite for hyper_io {
         let buf_ptr = buf.as_ptr();
         let buf_len = buf.len();
 
-        match (self.write)(self.userdata, hyper_context::wrap(cx), buf_ptr, buf_len) {
+        match (self.write)(
+            self.userdata.as_ptr(),
+            hyper_context::wrap(cx),
+            buf_ptr,
+            buf_len,
+        ) {
             HYPER_IO_PENDING => Poll::Pending,
-            HYPER_IO_ERROR => Poll::Ready(Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "io error",
-            ))),
+            HYPER_IO_ERROR => Poll::Ready(Err(callback_error())),
             ok => Poll::Ready(Ok(ok)),
         }
     }
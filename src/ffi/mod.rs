@@ -25,8 +25,11 @@
 //! `cargo`, staring with `1.64.0`, it can be compiled with the following command:
 //!
 //! ```notrust
-//! RUSTFLAGS="--cfg hyper_unstable_ffi" cargo rustc --features client,http1,http2,ffi --crate-type cdylib
+//! RUSTFLAGS="--cfg hyper_unstable_ffi" cargo rustc --features client,http1,http2,server,ffi --crate-type cdylib
 //! ```
+//!
+//! The `server` feature is optional, and adds the `hyper_serverconn` and
+//! `hyper_service` functions to serve connections from C.
 
 // We may eventually allow the FFI to be enabled without `client` or `http1`,
 // that is why we don't auto enable them as `ffi = ["client", "http1"]` in
@@ -47,19 +50,29 @@ compile_error!(
 #[macro_use]
 mod macros;
 
+mod alloc;
 mod body;
 mod client;
 mod error;
 mod http_types;
 mod io;
+#[cfg(feature = "server")]
+mod server;
 mod task;
+mod timer;
+mod upgrade;
 
+pub use self::alloc::*;
 pub use self::body::*;
 pub use self::client::*;
 pub use self::error::*;
 pub use self::http_types::*;
 pub use self::io::*;
+#[cfg(feature = "server")]
+pub use self::server::*;
 pub use self::task::*;
+pub use self::timer::*;
+pub use self::upgrade::*;
 
 /// Return in iter functions to continue iterating.
 pub const HYPER_ITER_CONTINUE: libc::c_int = 0;
@@ -83,6 +96,44 @@ struct UserDataPointer(*mut std::ffi::c_void);
 unsafe impl Send for UserDataPointer {}
 unsafe impl Sync for UserDataPointer {}
 
+/// A callback to free the userdata of a handle, once hyper is done with it.
+type hyper_userdata_drop = extern "C" fn(*mut std::ffi::c_void);
+
+/// A userdata pointer set on a handle, and the callback that frees it.
+///
+/// The callback is called when this is dropped, which happens when the
+/// userdata is replaced, or when hyper frees the handle (and any tasks or
+/// connections that share it).
+struct UserData {
+    ptr: UserDataPointer,
+    drop: Option<hyper_userdata_drop>,
+}
+
+impl UserData {
+    fn new(ptr: *mut std::ffi::c_void, drop: Option<hyper_userdata_drop>) -> UserData {
+        UserData {
+            ptr: UserDataPointer(ptr),
+            drop,
+        }
+    }
+
+    fn empty() -> UserData {
+        UserData::new(std::ptr::null_mut(), None)
+    }
+
+    fn as_ptr(&self) -> *mut std::ffi::c_void {
+        self.ptr.0
+    }
+}
+
+impl Drop for UserData {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            drop(self.ptr.0);
+        }
+    }
+}
+
 /// cbindgen:ignore
 static VERSION_CSTR: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
 
@@ -0,0 +1,306 @@
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_channel::oneshot;
+use libc::c_int;
+
+use crate::common::exec::Exec;
+use crate::common::{Future, Pin};
+use crate::server::conn::Http;
+use crate::service::Service;
+use crate::{Recv, Request, Response};
+
+use super::error::hyper_code;
+use super::http_types::{hyper_request, hyper_response};
+use super::io::hyper_io;
+use super::task::{hyper_executor, hyper_task, WeakExec};
+use super::timer::{hyper_timer, FfiTimer};
+use super::{hyper_userdata_drop, UserData};
+
+/// An options builder to configure HTTP server connections.
+pub struct hyper_serverconn_options {
+    http1_preserve_header_case: bool,
+    http2: bool,
+    http1_header_read_timeout: Option<Duration>,
+    service_timeout: Option<Duration>,
+    /// Use a `Weak` to prevent cycles.
+    exec: WeakExec,
+    timer: Option<FfiTimer>,
+}
+
+/// A service that responds to the requests received on a server connection.
+pub struct hyper_service {
+    service_fn: hyper_service_callback,
+    userdata: Arc<UserData>,
+}
+
+/// A channel to send the response to a request received by a `hyper_service`.
+pub struct hyper_response_channel {
+    tx: oneshot::Sender<Box<hyper_response>>,
+}
+
+type hyper_service_callback =
+    extern "C" fn(*mut c_void, *mut hyper_request, *mut hyper_response_channel);
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+// ===== impl hyper_serverconn_options =====
+
+ffi_fn! {
+    /// Creates a new set of HTTP serverconn options to be used when serving
+    /// connections.
+    fn hyper_serverconn_options_new() -> *mut hyper_serverconn_options {
+        Box::into_raw(Box::new(hyper_serverconn_options {
+            http1_preserve_header_case: false,
+            http2: false,
+            http1_header_read_timeout: None,
+            service_timeout: None,
+            exec: WeakExec::new(),
+            timer: None,
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Free a `hyper_serverconn_options *`.
+    fn hyper_serverconn_options_free(opts: *mut hyper_serverconn_options) {
+        drop(non_null! { Box::from_raw(opts) ?= () });
+    }
+}
+
+ffi_fn! {
+    /// Set the server background task executor.
+    ///
+    /// This does not consume the `options` or the `exec`.
+    fn hyper_serverconn_options_exec(opts: *mut hyper_serverconn_options, exec: *const hyper_executor) {
+        let opts = non_null! { &mut *opts ?= () };
+
+        let exec = non_null! { Arc::from_raw(exec) ?= () };
+        let weak_exec = hyper_executor::downgrade(&exec);
+        std::mem::forget(exec);
+
+        opts.exec = weak_exec;
+    }
+}
+
+ffi_fn! {
+    /// Set the timer used for time-based features, such as header read
+    /// timeouts.
+    ///
+    /// This does not consume the `options` or the `timer`.
+    fn hyper_serverconn_options_timer(opts: *mut hyper_serverconn_options, timer: *const hyper_timer) {
+        let opts = non_null! { &mut *opts ?= () };
+        let timer = non_null! { &*timer ?= () };
+        opts.timer = Some(FfiTimer::new(timer));
+    }
+}
+
+ffi_fn! {
+    /// Set the whether or not header case is preserved.
+    ///
+    /// Pass `0` to allow lowercase normalization (default), `1` to retain original case.
+    fn hyper_serverconn_options_set_preserve_header_case(opts: *mut hyper_serverconn_options, enabled: c_int) {
+        let opts = non_null! { &mut *opts ?= () };
+        opts.http1_preserve_header_case = enabled != 0;
+    }
+}
+
+ffi_fn! {
+    /// Set the whether to serve HTTP2 only.
+    ///
+    /// Pass `0` to serve HTTP/1 (default), `1` to serve HTTP/2.
+    fn hyper_serverconn_options_http2(opts: *mut hyper_serverconn_options, enabled: c_int) -> hyper_code {
+        #[cfg(feature = "http2")]
+        {
+            let opts = non_null! { &mut *opts ?= hyper_code::HYPERE_INVALID_ARG };
+            opts.http2 = enabled != 0;
+            hyper_code::HYPERE_OK
+        }
+
+        #[cfg(not(feature = "http2"))]
+        {
+            drop(opts);
+            drop(enabled);
+            hyper_code::HYPERE_FEATURE_NOT_ENABLED
+        }
+    }
+}
+
+ffi_fn! {
+    /// Set a timeout in milliseconds for reading the headers of HTTP/1
+    /// requests.
+    ///
+    /// If a client doesn't send the whole head of a request within this
+    /// time, the connection is closed. Pass `0` to disable the timeout
+    /// (default). It only applies if a timer was set with
+    /// `hyper_serverconn_options_timer`.
+    fn hyper_serverconn_options_http1_header_read_timeout(opts: *mut hyper_serverconn_options, timeout_ms: u64) {
+        let opts = non_null! { &mut *opts ?= () };
+        opts.http1_header_read_timeout = if timeout_ms == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(timeout_ms))
+        };
+    }
+}
+
+ffi_fn! {
+    /// Set a timeout in milliseconds for the service to respond to a
+    /// request.
+    ///
+    /// If the response isn't sent on the `hyper_response_channel` within
+    /// this time, a `503 Service Unavailable` response is sent instead. Pass
+    /// `0` to disable the timeout (default). It only applies if a timer was
+    /// set with `hyper_serverconn_options_timer`.
+    fn hyper_serverconn_options_service_timeout(opts: *mut hyper_serverconn_options, timeout_ms: u64) {
+        let opts = non_null! { &mut *opts ?= () };
+        opts.service_timeout = if timeout_ms == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(timeout_ms))
+        };
+    }
+}
+
+// ===== impl hyper_service =====
+
+ffi_fn! {
+    /// Create a service from a callback.
+    ///
+    /// The callback is called with each request received on a connection
+    /// served with this service. It is passed the value from
+    /// `hyper_service_set_userdata`, the `hyper_request *`, and a
+    /// `hyper_response_channel *`.
+    ///
+    /// The callback takes ownership of the request and the channel. The
+    /// response must be sent on the channel with
+    /// `hyper_response_channel_send`, either during the callback or later on.
+    /// The request body can be read with `hyper_request_body` in the
+    /// meantime.
+    fn hyper_service_new(service_fn: hyper_service_callback) -> *mut hyper_service {
+        Box::into_raw(Box::new(hyper_service {
+            service_fn,
+            userdata: Arc::new(UserData::empty()),
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Set userdata on this service, which will be passed to the callback.
+    fn hyper_service_set_userdata(service: *mut hyper_service, userdata: *mut c_void) {
+        non_null! { &mut *service ?= () }.userdata = Arc::new(UserData::new(userdata, None));
+    }
+}
+
+ffi_fn! {
+    /// Set userdata on this service, and a callback to free it.
+    ///
+    /// This is like `hyper_service_set_userdata`, except the `drop` callback
+    /// is called with the userdata once the service and all the connections
+    /// served with it are freed.
+    fn hyper_service_set_userdata_with_drop(service: *mut hyper_service, userdata: *mut c_void, drop: hyper_userdata_drop) {
+        non_null! { &mut *service ?= () }.userdata = Arc::new(UserData::new(userdata, Some(drop)));
+    }
+}
+
+ffi_fn! {
+    /// Free a `hyper_service *`.
+    fn hyper_service_free(service: *mut hyper_service) {
+        drop(non_null! { Box::from_raw(service) ?= () });
+    }
+}
+
+impl Service<Request<Recv>> for hyper_service {
+    type Response = Response<Recv>;
+    type Error = crate::Error;
+    type Future = BoxFuture<crate::Result<Response<Recv>>>;
+
+    fn call(&mut self, req: Request<Recv>) -> Self::Future {
+        let req = Box::new(hyper_request::wrap(req));
+        let (tx, rx) = oneshot::channel();
+        let channel = Box::new(hyper_response_channel { tx });
+
+        (self.service_fn)(
+            self.userdata.as_ptr(),
+            Box::into_raw(req),
+            Box::into_raw(channel),
+        );
+
+        Box::pin(async move {
+            match rx.await {
+                Ok(mut res) => {
+                    res.finalize_response();
+                    Ok(res.0)
+                }
+                // The channel was freed without sending a response.
+                Err(_canceled) => Err(crate::Error::new_user_aborted_by_callback()),
+            }
+        })
+    }
+}
+
+// ===== impl hyper_response_channel =====
+
+ffi_fn! {
+    /// Send the response to a request on this channel.
+    ///
+    /// This consumes both the channel and the response.
+    fn hyper_response_channel_send(channel: *mut hyper_response_channel, response: *mut hyper_response) -> hyper_code {
+        let channel = non_null! { Box::from_raw(channel) ?= hyper_code::HYPERE_INVALID_ARG };
+        let response = non_null! { Box::from_raw(response) ?= hyper_code::HYPERE_INVALID_ARG };
+        // If the connection is gone, there is no one to send the response to.
+        let _ = channel.tx.send(response);
+        hyper_code::HYPERE_OK
+    }
+}
+
+ffi_fn! {
+    /// Free a `hyper_response_channel *` without sending a response.
+    ///
+    /// The request is then answered with an error, which closes the
+    /// connection.
+    fn hyper_response_channel_free(channel: *mut hyper_response_channel) {
+        drop(non_null! { Box::from_raw(channel) ?= () });
+    }
+}
+
+// ===== serve =====
+
+ffi_fn! {
+    /// Serve an HTTP connection on the provided IO transport, passing each
+    /// request to the `service`.
+    ///
+    /// The `io` is consumed in this function call, while the `options` and
+    /// the `service` are not, so they can be used to serve other connections.
+    ///
+    /// The returned `hyper_task *` must be polled with an executor until the
+    /// connection closes. Its value is then empty, or a `hyper_error *` if
+    /// the connection failed.
+    fn hyper_serverconn_serve(io: *mut hyper_io, options: *const hyper_serverconn_options, service: *const hyper_service) -> *mut hyper_task {
+        let options = non_null! { &*options ?= ptr::null_mut() };
+        let service = non_null! { &*service ?= ptr::null_mut() };
+        let io = non_null! { Box::from_raw(io) ?= ptr::null_mut() };
+
+        let mut http = Http::new().with_executor(Exec::Executor(Arc::new(options.exec.clone())));
+        http.http1_preserve_header_case(options.http1_preserve_header_case);
+        #[cfg(feature = "http2")]
+        http.http2_only(options.http2);
+        if let Some(ref timer) = options.timer {
+            http = http.with_timer(timer.clone());
+            if let Some(timeout) = options.http1_header_read_timeout {
+                http.http1_header_read_timeout(timeout);
+            }
+            http.service_timeout(options.service_timeout);
+        }
+
+        let service = hyper_service {
+            service_fn: service.service_fn,
+            userdata: service.userdata.clone(),
+        };
+        let conn = http.serve_connection(io, service).with_upgrades();
+
+        Box::into_raw(hyper_task::boxed(conn))
+    } ?= ptr::null_mut()
+}
@@ -9,10 +9,11 @@ use std::sync::{
 use std::task::{Context, Poll};
 
 use futures_util::stream::{FuturesUnordered, Stream};
+use futures_util::task::AtomicWaker;
 use libc::c_int;
 
 use super::error::hyper_code;
-use super::UserDataPointer;
+use super::{hyper_userdata_drop, UserData};
 
 type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 type BoxAny = Box<dyn AsTaskType + Send + Sync>;
@@ -26,6 +27,11 @@ pub const HYPER_POLL_READY: c_int = 0;
 pub const HYPER_POLL_PENDING: c_int = 1;
 /// Return in a poll function indicate an error.
 pub const HYPER_POLL_ERROR: c_int = 3;
+/// Return in a body sender callback to indicate data isn't available yet.
+///
+/// The callback isn't called again until `hyper_body_sender_resume` is
+/// called.
+pub const HYPER_POLL_LATER: c_int = 4;
 
 /// A task executor for `hyper_task`s.
 pub struct hyper_executor {
@@ -45,20 +51,43 @@ pub struct hyper_executor {
     spawn_queue: Mutex<Vec<TaskFuture>>,
 
     /// This is used to track when a future calls `wake` while we are within
-    /// `hyper_executor::poll_next`.
+    /// `hyper_executor::poll_next`, and to notify the application once the
+    /// executor has work to do again.
     is_woken: Arc<ExecWaker>,
 }
 
 #[derive(Clone)]
 pub(crate) struct WeakExec(Weak<hyper_executor>);
 
-struct ExecWaker(AtomicBool);
+struct ExecWaker {
+    woken: AtomicBool,
+    wakeup: Mutex<Option<Wakeup>>,
+}
+
+struct Wakeup {
+    func: hyper_executor_wakeup_callback,
+    userdata: UserData,
+}
+
+type hyper_executor_wakeup_callback = extern "C" fn(*mut c_void);
 
 /// An async task.
 pub struct hyper_task {
-    future: BoxFuture<BoxAny>,
+    /// The future driving this task, dropped once it completes or is
+    /// canceled.
+    future: Option<BoxFuture<BoxAny>>,
     output: Option<BoxAny>,
-    userdata: UserDataPointer,
+    userdata: UserData,
+    cancel: Option<Arc<Cancel>>,
+}
+
+/// A handle to cancel a `hyper_task`, even after it was pushed onto an
+/// executor.
+pub struct hyper_cancel_handle(Arc<Cancel>);
+
+struct Cancel {
+    canceled: AtomicBool,
+    waker: AtomicWaker,
 }
 
 struct TaskFuture {
@@ -70,7 +99,7 @@ pub struct hyper_context<'a>(Context<'a>);
 
 /// A waker that is saved and used to waken a pending task.
 pub struct hyper_waker {
-    waker: std::task::Waker,
+    pub(super) waker: std::task::Waker,
 }
 
 /// A descriptor for what type a `hyper_task` value is.
@@ -86,6 +115,10 @@ pub enum hyper_task_return_type {
     HYPER_TASK_RESPONSE,
     /// The value of this task is `hyper_buf *`.
     HYPER_TASK_BUF,
+    /// The value of this task is `hyper_headers *`.
+    HYPER_TASK_HEADERS,
+    /// The value of this task is `hyper_upgraded *`.
+    HYPER_TASK_UPGRADED,
 }
 
 pub(crate) unsafe trait AsTaskType {
@@ -103,7 +136,10 @@ impl hyper_executor {
         Arc::new(hyper_executor {
             driver: Mutex::new(FuturesUnordered::new()),
             spawn_queue: Mutex::new(Vec::new()),
-            is_woken: Arc::new(ExecWaker(AtomicBool::new(false))),
+            is_woken: Arc::new(ExecWaker {
+                woken: AtomicBool::new(false),
+                wakeup: Mutex::new(None),
+            }),
         })
     }
 
@@ -116,6 +152,7 @@ impl hyper_executor {
             .lock()
             .unwrap()
             .push(TaskFuture { task: Some(task) });
+        self.is_woken.notify();
     }
 
     fn poll_next(&self) -> Option<Box<hyper_task>> {
@@ -126,9 +163,14 @@ impl hyper_executor {
         let mut cx = Context::from_waker(&waker);
 
         loop {
-            match Pin::new(&mut *self.driver.lock().unwrap()).poll_next(&mut cx) {
-                Poll::Ready(val) => return val,
-                Poll::Pending => {
+            // Release the driver lock before draining the queue below, which
+            // locks it again.
+            let poll = Pin::new(&mut *self.driver.lock().unwrap()).poll_next(&mut cx);
+            match poll {
+                Poll::Ready(Some(task)) => return Some(task),
+                // Even without any tasks left, clear `is_woken` below, so
+                // that the next push notifies the application.
+                Poll::Ready(None) | Poll::Pending => {
                     // Check if any of the pending tasks tried to spawn
                     // some new tasks. If so, drain into the driver and loop.
                     if self.drain_queue() {
@@ -137,7 +179,7 @@ impl hyper_executor {
 
                     // If the driver called `wake` while we were polling,
                     // we should poll again immediately!
-                    if self.is_woken.0.swap(false, Ordering::SeqCst) {
+                    if self.is_woken.woken.swap(false, Ordering::SeqCst) {
                         continue;
                     }
 
@@ -163,9 +205,23 @@ impl hyper_executor {
     }
 }
 
+// ===== impl ExecWaker =====
+
+impl ExecWaker {
+    fn notify(&self) {
+        // Only notify the application when the executor was idle, it will
+        // poll all the woken tasks at once.
+        if !self.woken.swap(true, Ordering::SeqCst) {
+            if let Some(ref wakeup) = *self.wakeup.lock().unwrap() {
+                (wakeup.func)(wakeup.userdata.as_ptr());
+            }
+        }
+    }
+}
+
 impl futures_util::task::ArcWake for ExecWaker {
     fn wake_by_ref(me: &Arc<ExecWaker>) {
-        me.0.store(true, Ordering::SeqCst);
+        me.notify();
     }
 }
 
@@ -228,6 +284,44 @@ ffi_fn! {
     } ?= ptr::null_mut()
 }
 
+ffi_fn! {
+    /// Set a callback to notify the application that the executor has work
+    /// to do, so that it can be integrated into an event loop, such as one
+    /// using `epoll` or `kqueue`.
+    ///
+    /// The callback is passed the userdata, and is called once a task is
+    /// pushed or woken up after `hyper_executor_poll` returned `NULL`. The
+    /// application should then call `hyper_executor_poll` until it returns
+    /// `NULL` again. The callback may be called from within
+    /// `hyper_executor_poll`, or from any thread that wakes a task, so a
+    /// typical callback only writes to an `eventfd` or a pipe watched by the
+    /// event loop. It must not call any `hyper_executor_*` function.
+    ///
+    /// The callback may be `NULL` to remove it. The `drop` callback may be
+    /// `NULL`. Otherwise, it is called with the userdata once the callback is
+    /// replaced, or the executor is freed.
+    fn hyper_executor_set_wakeup(exec: *const hyper_executor, func: Option<hyper_executor_wakeup_callback>, userdata: *mut c_void, drop: Option<hyper_userdata_drop>) -> hyper_code {
+        let exec = non_null!(&*exec ?= hyper_code::HYPERE_INVALID_ARG);
+        let userdata = UserData::new(userdata, drop);
+        let wakeup = func.map(|func| Wakeup { func, userdata });
+        *exec.is_woken.wakeup.lock().unwrap() = wakeup;
+        hyper_code::HYPERE_OK
+    }
+}
+
+ffi_fn! {
+    /// Check whether the executor has tasks that were pushed or woken up
+    /// since `hyper_executor_poll` last returned `NULL`.
+    ///
+    /// Returns `1` if `hyper_executor_poll` should be called, and `0` if the
+    /// application can wait for the wakeup callback, or for its own IO
+    /// events, instead.
+    fn hyper_executor_has_pending(exec: *const hyper_executor) -> c_int {
+        let exec = non_null!(&*exec ?= 0);
+        exec.is_woken.woken.load(Ordering::SeqCst) as c_int
+    }
+}
+
 // ===== impl hyper_task =====
 
 impl hyper_task {
@@ -237,12 +331,23 @@ impl hyper_task {
         F::Output: IntoDynTaskType + Send + Sync + 'static,
     {
         Box::new(hyper_task {
-            future: Box::pin(async move { fut.await.into_dyn_task_type() }),
+            future: Some(Box::pin(async move { fut.await.into_dyn_task_type() })),
             output: None,
-            userdata: UserDataPointer(ptr::null_mut()),
+            userdata: UserData::empty(),
+            cancel: None,
         })
     }
 
+    fn poll_canceled(&self, cx: &mut Context<'_>) -> bool {
+        match self.cancel {
+            Some(ref cancel) => {
+                cancel.waker.register(cx.waker());
+                cancel.canceled.load(Ordering::SeqCst)
+            }
+            None => false,
+        }
+    }
+
     fn output_type(&self) -> hyper_task_return_type {
         match self.output {
             None => hyper_task_return_type::HYPER_TASK_EMPTY,
@@ -255,14 +360,23 @@ impl Future for TaskFuture {
     type Output = Box<hyper_task>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match Pin::new(&mut self.task.as_mut().unwrap().future).poll(cx) {
-            Poll::Ready(val) => {
-                let mut task = self.task.take().unwrap();
-                task.output = Some(val);
-                Poll::Ready(task)
+        let task = self.task.as_mut().unwrap();
+
+        let val = if task.poll_canceled(cx) {
+            Box::new(crate::Error::new_canceled())
+        } else {
+            match Pin::new(task.future.as_mut().unwrap()).poll(cx) {
+                Poll::Ready(val) => val,
+                Poll::Pending => return Poll::Pending,
             }
-            Poll::Pending => Poll::Pending,
-        }
+        };
+
+        let mut task = self.task.take().unwrap();
+        // Dropping the future right away is what aborts the work it was
+        // doing, such as an in-flight request.
+        task.future = None;
+        task.output = Some(val);
+        Poll::Ready(task)
     }
 }
 
@@ -316,17 +430,80 @@ ffi_fn! {
             return;
         }
 
-        unsafe { (*task).userdata = UserDataPointer(userdata) };
+        unsafe { (*task).userdata = UserData::new(userdata, None) };
+    }
+}
+
+ffi_fn! {
+    /// Set a user data pointer on this task, and a callback to free it.
+    ///
+    /// This is like `hyper_task_set_userdata`, except the `drop` callback is
+    /// called with the userdata once the task is freed, or the userdata is
+    /// replaced.
+    fn hyper_task_set_userdata_with_drop(task: *mut hyper_task, userdata: *mut c_void, drop: hyper_userdata_drop) {
+        if task.is_null() {
+            return;
+        }
+
+        unsafe { (*task).userdata = UserData::new(userdata, Some(drop)) };
     }
 }
 
 ffi_fn! {
     /// Retrieve the userdata that has been set via `hyper_task_set_userdata`.
     fn hyper_task_userdata(task: *mut hyper_task) -> *mut c_void {
-        non_null!(&*task ?= ptr::null_mut()).userdata.0
+        non_null!(&*task ?= ptr::null_mut()).userdata.as_ptr()
     } ?= ptr::null_mut()
 }
 
+ffi_fn! {
+    /// Get a handle that can cancel this task.
+    ///
+    /// The handle stays valid after the task is pushed onto an executor, so
+    /// it can be used to abort an in-flight request, for instance once a
+    /// timeout expires. See `hyper_task_cancel`.
+    ///
+    /// The handle must be freed with `hyper_cancel_handle_free`, which can
+    /// be done before or after the task completes.
+    fn hyper_task_cancel_handle(task: *mut hyper_task) -> *mut hyper_cancel_handle {
+        let task = non_null!(&mut *task ?= ptr::null_mut());
+        let cancel = task.cancel.get_or_insert_with(|| {
+            Arc::new(Cancel {
+                canceled: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            })
+        });
+        Box::into_raw(Box::new(hyper_cancel_handle(cancel.clone())))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Cancel the task of this handle.
+    ///
+    /// If the task hasn't completed yet, the work it was doing is abandoned:
+    /// an in-flight HTTP/2 request has its stream reset, and an in-flight
+    /// HTTP/1 request closes its connection. The task is then returned by
+    /// `hyper_executor_poll` with a `hyper_error *` value, whose code is
+    /// `HYPERE_CANCELED`.
+    ///
+    /// Canceling a task that already completed does nothing. This does not
+    /// consume the handle.
+    fn hyper_task_cancel(handle: *const hyper_cancel_handle) {
+        let cancel = &non_null!(&*handle ?= ()).0;
+        cancel.canceled.store(true, Ordering::SeqCst);
+        cancel.waker.wake();
+    }
+}
+
+ffi_fn! {
+    /// Free a `hyper_cancel_handle *`.
+    ///
+    /// This does not cancel the task.
+    fn hyper_cancel_handle_free(handle: *mut hyper_cancel_handle) {
+        drop(non_null!(Box::from_raw(handle) ?= ()));
+    }
+}
+
 // ===== impl AsTaskType =====
 
 unsafe impl AsTaskType for () {
@@ -409,3 +586,143 @@ ffi_fn! {
         waker.waker.wake();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rt::Executor;
+
+    #[test]
+    fn test_poll_spawn_from_pending_task() {
+        let exec = hyper_executor::new();
+        let weak_exec = hyper_executor::downgrade(&exec);
+
+        let mut spawned = false;
+        exec.spawn(hyper_task::boxed(futures_util::future::poll_fn(
+            move |_| {
+                if !spawned {
+                    spawned = true;
+                    weak_exec.execute(Box::pin(async {}));
+                }
+                Poll::<()>::Pending
+            },
+        )));
+
+        let task = exec.poll_next().expect("spawned task should complete");
+        assert!(matches!(
+            task.output_type(),
+            hyper_task_return_type::HYPER_TASK_EMPTY
+        ));
+    }
+
+    #[test]
+    fn test_cancel_pending_task() {
+        struct DropFlag(Arc<AtomicBool>);
+
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let exec = hyper_executor::new();
+        let dropped = Arc::new(AtomicBool::new(false));
+        let flag = DropFlag(dropped.clone());
+        let mut task = hyper_task::boxed(futures_util::future::poll_fn(move |_| {
+            let _ = &flag;
+            Poll::<()>::Pending
+        }));
+        let handle = hyper_task_cancel_handle(&mut *task);
+        exec.spawn(task);
+        assert!(exec.poll_next().is_none());
+
+        hyper_task_cancel(handle);
+        let task = exec.poll_next().expect("canceled task should complete");
+        assert!(matches!(
+            task.output_type(),
+            hyper_task_return_type::HYPER_TASK_ERROR
+        ));
+        assert!(dropped.load(Ordering::SeqCst));
+
+        hyper_cancel_handle_free(handle);
+    }
+
+    #[test]
+    fn test_executor_wakeup() {
+        use std::sync::atomic::AtomicUsize;
+
+        extern "C" fn count_wakeup(userdata: *mut c_void) {
+            unsafe { &*(userdata as *const AtomicUsize) }.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let wakeups = AtomicUsize::new(0);
+        let exec = hyper_executor_new();
+        hyper_executor_set_wakeup(
+            exec,
+            Some(count_wakeup),
+            &wakeups as *const _ as *mut c_void,
+            None,
+        );
+        assert_eq!(hyper_executor_has_pending(exec), 0);
+
+        let waker = Arc::new(Mutex::new(None));
+        let task_waker = waker.clone();
+        let mut woken = false;
+        let task = hyper_task::boxed(futures_util::future::poll_fn(move |cx| {
+            if woken {
+                return Poll::Ready(());
+            }
+            woken = true;
+            *task_waker.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        }));
+        hyper_executor_push(exec, Box::into_raw(task));
+        assert_eq!(wakeups.load(Ordering::SeqCst), 1);
+        assert_eq!(hyper_executor_has_pending(exec), 1);
+
+        assert!(hyper_executor_poll(exec).is_null());
+        assert_eq!(hyper_executor_has_pending(exec), 0);
+
+        // Waking the task notifies the application only once.
+        let waker = waker.lock().unwrap().take().expect("task polled");
+        waker.wake_by_ref();
+        waker.wake();
+        assert_eq!(wakeups.load(Ordering::SeqCst), 2);
+        assert_eq!(hyper_executor_has_pending(exec), 1);
+
+        let task = hyper_executor_poll(exec);
+        assert!(!task.is_null());
+        hyper_task_free(task);
+        assert!(hyper_executor_poll(exec).is_null());
+        assert_eq!(hyper_executor_has_pending(exec), 0);
+
+        hyper_executor_free(exec);
+    }
+
+    #[test]
+    fn test_userdata_drop() {
+        use std::sync::atomic::AtomicUsize;
+
+        extern "C" fn count_drop(userdata: *mut c_void) {
+            unsafe { &*(userdata as *const AtomicUsize) }.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let first = AtomicUsize::new(0);
+        let second = AtomicUsize::new(0);
+        let task = Box::into_raw(hyper_task::boxed(async {}));
+        hyper_task_set_userdata_with_drop(task, &first as *const _ as *mut c_void, count_drop);
+        assert_eq!(first.load(Ordering::SeqCst), 0);
+
+        // Replacing the userdata drops the previous one.
+        hyper_task_set_userdata_with_drop(task, &second as *const _ as *mut c_void, count_drop);
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            hyper_task_userdata(task),
+            &second as *const _ as *mut c_void
+        );
+
+        hyper_task_free(task);
+        assert_eq!(first.load(Ordering::SeqCst), 1);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::task::{ArcWake, AtomicWaker};
+
+use crate::common::{task, Future, Pin, Poll};
+use crate::rt::{Sleep, Timer};
+
+use super::task::hyper_waker;
+use super::{hyper_userdata_drop, UserData};
+
+/// A timer driven by the application's event loop.
+///
+/// hyper uses it for time-based features, such as HTTP/2 keep-alive pings
+/// and header read timeouts. Each time hyper needs to wait, it calls the
+/// schedule callback, and expects the application to wake the given waker
+/// once the timeout elapses.
+pub struct hyper_timer {
+    schedule: hyper_timer_schedule_callback,
+    cancel: hyper_timer_cancel_callback,
+    userdata: Arc<UserData>,
+}
+
+type hyper_timer_schedule_callback = extern "C" fn(*mut c_void, u64, *mut hyper_waker) -> u64;
+type hyper_timer_cancel_callback = extern "C" fn(*mut c_void, u64);
+
+/// A `hyper_timer` shared with the connections it was set on.
+#[derive(Clone)]
+pub(crate) struct FfiTimer(Arc<hyper_timer>);
+
+struct FfiSleep {
+    timer: FfiTimer,
+    id: u64,
+    state: Arc<SleepState>,
+}
+
+struct SleepState {
+    fired: AtomicBool,
+    waker: AtomicWaker,
+}
+
+// ===== impl hyper_timer =====
+
+ffi_fn! {
+    /// Create a timer from the callbacks of the application's event loop.
+    ///
+    /// The schedule callback is passed the value from
+    /// `hyper_timer_set_userdata`, a timeout in milliseconds, and a
+    /// `hyper_waker *`. Once the timeout elapses, the application must call
+    /// `hyper_waker_wake` with the waker. The callback returns an id for the
+    /// scheduled timer.
+    ///
+    /// The cancel callback is passed the userdata and the id of a timer that
+    /// hyper doesn't need anymore, and that hasn't fired yet. The application
+    /// must then free its waker with `hyper_waker_free` instead of waking it.
+    ///
+    /// A timer is used by setting it on connection options, with
+    /// `hyper_clientconn_options_timer` or `hyper_serverconn_options_timer`.
+    fn hyper_timer_new(schedule: hyper_timer_schedule_callback, cancel: hyper_timer_cancel_callback) -> *mut hyper_timer {
+        Box::into_raw(Box::new(hyper_timer {
+            schedule,
+            cancel,
+            userdata: Arc::new(UserData::empty()),
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Set userdata on this timer, which will be passed to the callbacks.
+    fn hyper_timer_set_userdata(timer: *mut hyper_timer, userdata: *mut c_void) {
+        non_null!(&mut *timer ?= ()).userdata = Arc::new(UserData::new(userdata, None));
+    }
+}
+
+ffi_fn! {
+    /// Set userdata on this timer, and a callback to free it.
+    ///
+    /// This is like `hyper_timer_set_userdata`, except the `drop` callback is
+    /// called with the userdata once the timer and all the connections it was
+    /// set on are freed.
+    fn hyper_timer_set_userdata_with_drop(timer: *mut hyper_timer, userdata: *mut c_void, drop: hyper_userdata_drop) {
+        non_null!(&mut *timer ?= ()).userdata = Arc::new(UserData::new(userdata, Some(drop)));
+    }
+}
+
+ffi_fn! {
+    /// Free a `hyper_timer *`.
+    ///
+    /// Connections the timer was set on keep using its callbacks.
+    fn hyper_timer_free(timer: *mut hyper_timer) {
+        drop(non_null!(Box::from_raw(timer) ?= ()));
+    }
+}
+
+// ===== impl FfiTimer =====
+
+impl FfiTimer {
+    pub(crate) fn new(timer: &hyper_timer) -> FfiTimer {
+        FfiTimer(Arc::new(hyper_timer {
+            schedule: timer.schedule,
+            cancel: timer.cancel,
+            userdata: timer.userdata.clone(),
+        }))
+    }
+}
+
+impl Timer for FfiTimer {
+    fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+        let state = Arc::new(SleepState {
+            fired: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        });
+        let waker = Box::new(hyper_waker {
+            waker: futures_util::task::waker(state.clone()),
+        });
+
+        // Round up, so the timer never fires early.
+        let millis = (duration.as_nanos() + 999_999) / 1_000_000;
+        let millis = u64::try_from(millis).unwrap_or(u64::MAX);
+        let id = (self.0.schedule)(self.0.userdata.as_ptr(), millis, Box::into_raw(waker));
+
+        Box::new(FfiSleep {
+            timer: self.clone(),
+            id,
+            state,
+        })
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
+        self.sleep(deadline.saturating_duration_since(self.now()))
+    }
+}
+
+// ===== impl FfiSleep =====
+
+impl Future for FfiSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        self.state.waker.register(cx.waker());
+        if self.state.fired.load(Ordering::SeqCst) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Sleep for FfiSleep {}
+
+impl Drop for FfiSleep {
+    fn drop(&mut self) {
+        if !self.state.fired.load(Ordering::SeqCst) {
+            (self.timer.0.cancel)(self.timer.0.userdata.as_ptr(), self.id);
+        }
+    }
+}
+
+impl ArcWake for SleepState {
+    fn wake_by_ref(me: &Arc<SleepState>) {
+        me.fired.store(true, Ordering::SeqCst);
+        me.waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::ffi::task::{hyper_waker_free, hyper_waker_wake};
+
+    struct Scheduled {
+        wakers: Vec<(u64, u64, *mut hyper_waker)>,
+        canceled: Vec<u64>,
+    }
+
+    extern "C" fn schedule(userdata: *mut c_void, millis: u64, waker: *mut hyper_waker) -> u64 {
+        let scheduled = unsafe { &*(userdata as *const Mutex<Scheduled>) };
+        let mut scheduled = scheduled.lock().unwrap();
+        let id = scheduled.wakers.len() as u64;
+        scheduled.wakers.push((id, millis, waker));
+        id
+    }
+
+    extern "C" fn cancel(userdata: *mut c_void, id: u64) {
+        let scheduled = unsafe { &*(userdata as *const Mutex<Scheduled>) };
+        scheduled.lock().unwrap().canceled.push(id);
+    }
+
+    #[test]
+    fn test_timer_schedule_and_cancel() {
+        let scheduled = Mutex::new(Scheduled {
+            wakers: Vec::new(),
+            canceled: Vec::new(),
+        });
+        let timer = hyper_timer_new(schedule, cancel);
+        hyper_timer_set_userdata(timer, &scheduled as *const _ as *mut c_void);
+        let ffi_timer = FfiTimer::new(unsafe { &*timer });
+        hyper_timer_free(timer);
+
+        let mut fired = ffi_timer.sleep(Duration::from_micros(1500));
+        let canceled = ffi_timer.sleep(Duration::from_secs(1));
+
+        let (fired_waker, canceled_waker) = {
+            let scheduled = scheduled.lock().unwrap();
+            assert_eq!(scheduled.wakers[0].1, 2);
+            assert_eq!(scheduled.wakers[1].1, 1000);
+            (scheduled.wakers[0].2, scheduled.wakers[1].2)
+        };
+
+        let noop = futures_util::task::noop_waker();
+        let mut cx = task::Context::from_waker(&noop);
+        assert!(Pin::new(&mut fired).poll(&mut cx).is_pending());
+        hyper_waker_wake(fired_waker);
+        assert!(Pin::new(&mut fired).poll(&mut cx).is_ready());
+        drop(fired);
+
+        drop(canceled);
+        assert_eq!(scheduled.lock().unwrap().canceled, [1]);
+        hyper_waker_free(canceled_waker);
+    }
+    #[test]
+    fn test_timer_interval() {
+        let scheduled = Mutex::new(Scheduled {
+            wakers: Vec::new(),
+            canceled: Vec::new(),
+        });
+        let timer = hyper_timer_new(schedule, cancel);
+        hyper_timer_set_userdata(timer, &scheduled as *const _ as *mut c_void);
+        let ffi_timer = FfiTimer::new(unsafe { &*timer });
+        hyper_timer_free(timer);
+
+        let mut interval = ffi_timer.interval(Duration::from_millis(10));
+        let noop = futures_util::task::noop_waker();
+        let mut cx = task::Context::from_waker(&noop);
+        assert!(interval.poll_tick(&mut cx).is_pending());
+
+        let first = scheduled.lock().unwrap().wakers[0].2;
+        hyper_waker_wake(first);
+        assert!(interval.poll_tick(&mut cx).is_ready());
+        assert!(interval.poll_tick(&mut cx).is_pending());
+        assert_eq!(scheduled.lock().unwrap().wakers.len(), 2);
+
+        drop(interval);
+        assert_eq!(scheduled.lock().unwrap().canceled, [1]);
+        hyper_waker_free(scheduled.lock().unwrap().wakers[1].2);
+    }
+}
@@ -0,0 +1,269 @@
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::poll_fn;
+
+use super::body::hyper_buf;
+#[cfg(feature = "server")]
+use super::http_types::hyper_request;
+use super::http_types::hyper_response;
+use super::task::{hyper_task, hyper_task_return_type, AsTaskType};
+use crate::body::Bytes;
+use crate::common::{task, Pin, Poll};
+use crate::rt::{Read, ReadBuf, Write};
+use crate::upgrade::Upgraded;
+
+/// The size of the buffers returned by `hyper_upgraded_read`.
+const READ_BUF_SIZE: usize = 8 * 1024;
+
+/// An upgraded connection, such as a tunnel established with `CONNECT`.
+///
+/// The connection is read and written with tasks, which may run at the same
+/// time as each other. Only one read task and one write task should be
+/// running at a time.
+pub struct hyper_upgraded(Arc<Mutex<Upgraded>>);
+
+// ===== upgrade =====
+
+ffi_fn! {
+    /// Return a task that will resolve to the upgraded connection of this
+    /// response.
+    ///
+    /// This should be used once a response with a `101 Switching Protocols`
+    /// status, or a successful status to a `CONNECT` request, was received.
+    /// The task value may have different types depending on the outcome:
+    ///
+    /// - `HYPER_TASK_UPGRADED`: Success, the value is a `hyper_upgraded *`.
+    /// - `HYPER_TASK_ERROR`: The connection couldn't be upgraded.
+    ///
+    /// This does not consume the `hyper_response *`, which can be freed
+    /// before the task completes. The client connection must keep being
+    /// polled until then.
+    fn hyper_response_upgrade(resp: *mut hyper_response) -> *mut hyper_task {
+        let resp = non_null!(&mut *resp ?= ptr::null_mut());
+        let on_upgrade = crate::upgrade::on(&mut resp.0);
+
+        Box::into_raw(hyper_task::boxed(async move {
+            on_upgrade.await.map(hyper_upgraded::new)
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Return a task that will resolve to the upgraded connection of this
+    /// request.
+    ///
+    /// This should be used on a request received by a `hyper_service`, which
+    /// is then answered with a `101 Switching Protocols` response, or a
+    /// successful response to a `CONNECT` request. The task value may have
+    /// different types depending on the outcome:
+    ///
+    /// - `HYPER_TASK_UPGRADED`: Success, the value is a `hyper_upgraded *`.
+    /// - `HYPER_TASK_ERROR`: The connection couldn't be upgraded.
+    ///
+    /// This does not consume the `hyper_request *`, which can be freed
+    /// before the task completes.
+    #[cfg(feature = "server")]
+    fn hyper_request_upgrade(req: *mut hyper_request) -> *mut hyper_task {
+        let req = non_null!(&mut *req ?= ptr::null_mut());
+        let on_upgrade = crate::upgrade::on(&mut req.0);
+
+        Box::into_raw(hyper_task::boxed(async move {
+            on_upgrade.await.map(hyper_upgraded::new)
+        }))
+    } ?= ptr::null_mut()
+}
+
+// ===== impl hyper_upgraded =====
+
+impl hyper_upgraded {
+    fn new(upgraded: Upgraded) -> hyper_upgraded {
+        hyper_upgraded(Arc::new(Mutex::new(upgraded)))
+    }
+}
+
+ffi_fn! {
+    /// Return a task that will read the next buffer of data from the upgraded
+    /// connection.
+    ///
+    /// Any bytes that hyper had already read past the end of the HTTP message
+    /// are returned first. The task value may have different types depending
+    /// on the outcome:
+    ///
+    /// - `HYPER_TASK_BUF`: Success, and more data was received.
+    /// - `HYPER_TASK_ERROR`: An error reading from the connection.
+    /// - `HYPER_TASK_EMPTY`: The connection reached EOF.
+    ///
+    /// This does not consume the `hyper_upgraded *`.
+    fn hyper_upgraded_read(upgraded: *const hyper_upgraded) -> *mut hyper_task {
+        let io = non_null!(&*upgraded ?= ptr::null_mut()).0.clone();
+
+        Box::into_raw(hyper_task::boxed(async move {
+            let mut buf = vec![0; READ_BUF_SIZE];
+            let n = poll_fn(|cx| {
+                let mut read_buf = ReadBuf::new(&mut buf);
+                let mut io = io.lock().unwrap();
+                ready!(Pin::new(&mut *io).poll_read(cx, read_buf.unfilled()))?;
+                Poll::Ready(Ok(read_buf.filled().len()))
+            })
+            .await
+            .map_err(crate::Error::new_io)?;
+
+            if n == 0 {
+                return Ok(None);
+            }
+            buf.truncate(n);
+            Ok(Some(hyper_buf::returned(Bytes::from(buf))))
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Return a task that will write all the data of the buffer to the
+    /// upgraded connection, and flush it.
+    ///
+    /// The task value is `HYPER_TASK_EMPTY` once the data is written, or
+    /// `HYPER_TASK_ERROR` if an error occurred.
+    ///
+    /// This takes ownership of the `hyper_buf *`, but not of the
+    /// `hyper_upgraded *`.
+    fn hyper_upgraded_write(upgraded: *const hyper_upgraded, buf: *mut hyper_buf) -> *mut hyper_task {
+        let io = non_null!(&*upgraded ?= ptr::null_mut()).0.clone();
+        let mut buf = non_null!(Box::from_raw(buf) ?= ptr::null_mut()).into_bytes();
+
+        Box::into_raw(hyper_task::boxed(async move {
+            poll_fn(|cx| {
+                let mut io = io.lock().unwrap();
+                poll_write_all(&mut io, cx, &mut buf)
+            })
+            .await
+            .map_err(crate::Error::new_io)
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Return a task that will shut down the write side of the upgraded
+    /// connection.
+    ///
+    /// The task value is `HYPER_TASK_EMPTY` once the connection is shut
+    /// down, or `HYPER_TASK_ERROR` if an error occurred.
+    ///
+    /// This does not consume the `hyper_upgraded *`.
+    fn hyper_upgraded_shutdown(upgraded: *const hyper_upgraded) -> *mut hyper_task {
+        let io = non_null!(&*upgraded ?= ptr::null_mut()).0.clone();
+
+        Box::into_raw(hyper_task::boxed(async move {
+            poll_fn(|cx| Pin::new(&mut *io.lock().unwrap()).poll_shutdown(cx))
+                .await
+                .map_err(crate::Error::new_io)
+        }))
+    } ?= ptr::null_mut()
+}
+
+ffi_fn! {
+    /// Free a `hyper_upgraded *`.
+    ///
+    /// The connection is closed once any tasks using it have completed.
+    fn hyper_upgraded_free(upgraded: *mut hyper_upgraded) {
+        drop(non_null!(Box::from_raw(upgraded) ?= ()));
+    }
+}
+
+unsafe impl AsTaskType for hyper_upgraded {
+    fn as_task_type(&self) -> hyper_task_return_type {
+        hyper_task_return_type::HYPER_TASK_UPGRADED
+    }
+}
+
+fn poll_write_all(
+    io: &mut Upgraded,
+    cx: &mut task::Context<'_>,
+    buf: &mut Bytes,
+) -> Poll<std::io::Result<()>> {
+    use bytes::Buf;
+
+    while !buf.is_empty() {
+        let n = ready!(Pin::new(&mut *io).poll_write(cx, buf))?;
+        if n == 0 {
+            return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+        }
+        buf.advance(n);
+    }
+    Pin::new(io).poll_flush(cx)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::common::io::Compat;
+    use crate::ffi::body::hyper_buf_copy;
+    use crate::ffi::task::{
+        hyper_executor_free, hyper_executor_new, hyper_executor_poll, hyper_executor_push,
+        hyper_task_free, hyper_task_type, hyper_task_value,
+    };
+
+    fn run(
+        exec: *const super::super::task::hyper_executor,
+        task: *mut hyper_task,
+    ) -> *mut hyper_task {
+        hyper_executor_push(exec, task);
+        let task = hyper_executor_poll(exec);
+        assert!(!task.is_null(), "task should be ready");
+        task
+    }
+
+    #[test]
+    fn test_upgraded_read_buffered_then_write() {
+        let (io, mut peer) = tokio::io::duplex(64);
+        let upgraded = Box::into_raw(Box::new(hyper_upgraded::new(Upgraded::new(
+            Compat::new(io),
+            Bytes::from_static(b"early"),
+        ))));
+        let exec = hyper_executor_new();
+
+        // Bytes buffered by hyper come before anything read from the IO.
+        let task = run(exec, hyper_upgraded_read(upgraded));
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_BUF
+        ));
+        let buf = unsafe { Box::from_raw(hyper_task_value(task) as *mut hyper_buf) };
+        assert_eq!(buf.as_bytes(), b"early");
+        hyper_task_free(task);
+
+        let ping = b"ping";
+        let task = run(
+            exec,
+            hyper_upgraded_write(upgraded, hyper_buf_copy(ping.as_ptr(), ping.len())),
+        );
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_EMPTY
+        ));
+        hyper_task_free(task);
+
+        let mut received = [0; 4];
+        peer.read_exact(&mut received)
+            .now_or_never()
+            .expect("written")
+            .unwrap();
+        assert_eq!(&received, ping);
+
+        // EOF from the peer ends the reads.
+        peer.shutdown().now_or_never().expect("shutdown").unwrap();
+        drop(peer);
+        let task = run(exec, hyper_upgraded_read(upgraded));
+        assert!(matches!(
+            hyper_task_type(task),
+            hyper_task_return_type::HYPER_TASK_EMPTY
+        ));
+        hyper_task_free(task);
+
+        hyper_upgraded_free(upgraded);
+        hyper_executor_free(exec);
+    }
+}
@@ -6,6 +6,12 @@ use http::HeaderMap;
 #[cfg(all(feature = "http2", feature = "client"))]
 use http::Method;
 
+#[cfg(feature = "http1")]
+use tracing::debug;
+
+#[cfg(feature = "http1")]
+use crate::error::Parse;
+
 #[cfg(feature = "http1")]
 pub(super) fn connection_keep_alive(value: &HeaderValue) -> bool {
     connection_has(value, "keep-alive")
@@ -64,6 +70,67 @@ pub(super) fn content_length_parse_all_values(values: ValueIter<'_, HeaderValue>
     return content_length
 }
 
+/// Parses the `content-length` headers of a received HTTP/1 message.
+///
+/// Each value must be only digits, unless `allow_lists` is set, which also
+/// accepts a comma-separated list with whitespace around each length. In
+/// both cases, all the lengths must be equal.
+#[cfg(feature = "http1")]
+pub(super) fn content_length_parse_received<'a>(
+    values: impl IntoIterator<Item = &'a HeaderValue>,
+    allow_lists: bool,
+) -> Result<Option<u64>, Parse> {
+    let mut content_length = None;
+    for value in values {
+        let bytes = value.as_bytes();
+        let mut check = |bytes: &[u8]| {
+            let n = content_length_from_digits(bytes)?;
+            match content_length {
+                Some(len) if len != n => {
+                    debug!("multiple Content-Length values: [{}, {}]", len, n);
+                    Err(Parse::content_length_mismatch())
+                }
+                _ => {
+                    content_length = Some(n);
+                    Ok(())
+                }
+            }
+        };
+        if allow_lists {
+            for v in bytes.split(|&b| b == b',') {
+                check(trim_ows(v))?;
+            }
+        } else {
+            check(bytes)?;
+        }
+    }
+    Ok(content_length)
+}
+
+#[cfg(feature = "http1")]
+fn content_length_from_digits(bytes: &[u8]) -> Result<u64, Parse> {
+    let err = match bytes.first() {
+        None => Parse::content_length_invalid(),
+        Some(b'+') | Some(b'-') => Parse::content_length_signed(),
+        _ if bytes.iter().any(|&b| b == b' ' || b == b'\t') => Parse::content_length_whitespace(),
+        _ if !bytes.iter().all(u8::is_ascii_digit) => Parse::content_length_invalid(),
+        _ => return from_digits(bytes).ok_or_else(Parse::content_length_overflow),
+    };
+    debug!("illegal Content-Length value: {:?}", err);
+    Err(err)
+}
+
+#[cfg(feature = "http1")]
+fn trim_ows(mut bytes: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = bytes {
+        bytes = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = bytes {
+        bytes = rest;
+    }
+    bytes
+}
+
 fn from_digits(bytes: &[u8]) -> Option<u64> {
     // cannot use FromStr for u64, since it allows a signed prefix
     let mut result = 0u64;
@@ -121,6 +188,17 @@ pub(super) fn is_chunked(mut encodings: ValueIter<'_, HeaderValue>) -> bool {
     false
 }
 
+/// Whether a `transfer-encoding` value lists a coding other than chunked.
+#[cfg(feature = "http1")]
+pub(super) fn has_other_coding(value: &HeaderValue) -> bool {
+    match value.to_str() {
+        Ok(s) => s
+            .split(',')
+            .any(|coding| !coding.trim().eq_ignore_ascii_case("chunked")),
+        Err(_) => true,
+    }
+}
+
 #[cfg(feature = "http1")]
 pub(super) fn is_chunked_(value: &HeaderValue) -> bool {
     // chunked must always be the last encoding, according to spec
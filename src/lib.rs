@@ -49,8 +49,60 @@
 //! - `http2`: Enables HTTP/2 support.
 //! - `client`: Enables the HTTP `client`.
 //! - `server`: Enables the HTTP `server`.
+//! - `tcp`: Enables the `client::connect` utilities for configuring and
+//!   connecting sockets, over TCP, Unix domain sockets or Windows named pipes.
+//! - `decompression`: Enables decoding compressed response bodies in the
+//!   `client`.
+//! - `mmap`: Enables [`body::Mmap`], a body serving memory-mapped files.
+//! - `splice`: Makes `upgrade::tunnel` move data between TCP sockets with
+//!   `splice(2)` on Linux.
+//! - `tokio`: Enables the `rt` adapters to run hyper on the Tokio runtime.
+//! - `tower`: Enables `service` adapters between hyper and `tower` services.
+//! - `websocket`: Enables `upgrade::websocket`, to validate and accept
+//!   WebSocket handshakes.
+//! - `tracing-spans`: Enables structured [`tracing`] spans and events,
+//!   described below.
+//! - `debug`: Enables `tracing-spans`, and also logs every HTTP/2 frame sent and
+//!   received. It also enables [`capture`] of the raw bytes of connections.
+//!
+//! The HTTP/1 client and server don't depend on tokio. It is only pulled in
+//! by the `tokio`, `tcp` and `splice` features, and by `http2`, since the
+//! `h2` crate is built on tokio's IO traits. Each of them also enables the
+//! `tokio` adapters.
 //!
 //! [feature flags]: https://doc.rust-lang.org/cargo/reference/manifest.html#the-features-section
+//!
+//! # Tracing
+//!
+//! With the `tracing-spans` feature, hyper emits the following spans and events.
+//! Their targets, names, and fields are stable.
+//!
+//! - `hyper::conn`: a `conn` span, at `DEBUG`, for the lifetime of each
+//!   connection, with the fields `role` (`"client"` or `"server"`) and
+//!   `version` (`"HTTP/1.1"` or `"HTTP/2"`). Within it, the events
+//!   `connection opened`, and `connection closed`, with an `error` field if
+//!   the connection failed.
+//! - `hyper::parse`: the `DEBUG` events `request head parsed`, with the
+//!   fields `method`, `uri`, `version`, and `headers` (the number of
+//!   headers), and `response head parsed`, with `status`, `version`, and
+//!   `headers`. These are only emitted for HTTP/1.
+//! - `hyper::body`: a `TRACE` event `body chunk` for each chunk of a body
+//!   read or written, with the fields `direction` (`"read"` or `"write"`)
+//!   and `bytes`.
+//! - `hyper::h2`: a `DEBUG` event `h2 error` for each HTTP/2 error, with the
+//!   fields `scope` (`"stream"` or `"connection"`), `reason` (the error code,
+//!   if any), `io` (whether it was an IO error), and `error`.
+//! - `hyper::h2::frame`: with the `debug` feature, a `DEBUG` event `h2 frame`
+//!   for each HTTP/2 frame, with the fields `direction` (`"sent"` or
+//!   `"received"`), `kind` (such as `"HEADERS"`), `stream_id`, `flags`, and
+//!   `len` (the length of the payload).
+//! - `hyper::poll`: a `WARN` event `slow poll` when a poll of a server's
+//!   service or response body takes longer than the
+//!   [`slow_poll_threshold`][slow-poll], with the fields `source`
+//!   (`"service"` or `"body"`) and `micros`.
+//!
+//! [`tracing`]: https://docs.rs/tracing
+//! [slow-poll]: crate::server::conn::Http::slow_poll_threshold
 
 #[doc(hidden)]
 pub use http;
@@ -71,7 +123,7 @@ mod cfg;
 #[macro_use]
 mod common;
 pub mod body;
-mod error;
+pub mod error;
 pub mod ext;
 #[cfg(test)]
 mod mock;
@@ -84,9 +136,26 @@ pub mod ffi;
 
 cfg_proto! {
     mod headers;
+    pub mod metrics;
     mod proto;
 }
 
+cfg_feature! {
+    #![all(feature = "http1", any(feature = "client", feature = "server"))]
+
+    pub mod buffer;
+}
+
+cfg_feature! {
+    #![all(
+        feature = "debug",
+        any(feature = "http1", feature = "http2"),
+        any(feature = "client", feature = "server"),
+    )]
+
+    pub mod capture;
+}
+
 cfg_feature! {
     #![feature = "client"]
 
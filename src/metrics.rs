@@ -0,0 +1,254 @@
+//! Metrics of client and server connections.
+//!
+//! A [`Metrics`] implementation is installed on a connection builder, such
+//! as with [`client::conn::http1::Builder::metrics`] or
+//! [`server::conn::Http::metrics`], and is called as connections and
+//! requests make progress. It is up to the implementation to forward these
+//! to a metrics crate, so hyper doesn't depend on any.
+//!
+//! [`client::conn::http1::Builder::metrics`]: crate::client::conn::http1::Builder::metrics
+//! [`server::conn::Http::metrics`]: crate::server::conn::Http::metrics
+
+use std::fmt;
+#[cfg(feature = "client")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "client")]
+use std::time::Instant;
+
+use http::StatusCode;
+
+/// Receives counters and timings from connections.
+///
+/// Every method does nothing by default, so an implementation only needs to
+/// override those it records. They are called from the tasks driving the
+/// connections, and so should be cheap.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// use hyper::metrics::{Metrics, StatusClass};
+///
+/// #[derive(Default)]
+/// struct ServerErrors(AtomicU64);
+///
+/// impl Metrics for ServerErrors {
+///     fn response_status(&self, class: StatusClass) {
+///         if class == StatusClass::ServerError {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+/// ```
+pub trait Metrics: Send + Sync {
+    /// Bytes were read.
+    ///
+    /// For clients, these are the bytes read from the transport. For
+    /// servers, these are the request body bytes, counted once the request
+    /// completes.
+    fn bytes_read(&self, _bytes: u64) {}
+
+    /// Bytes were written.
+    ///
+    /// For clients, these are the bytes written to the transport. For
+    /// servers, these are the response body bytes, counted once the request
+    /// completes.
+    fn bytes_written(&self, _bytes: u64) {}
+
+    /// A request finished.
+    ///
+    /// For clients, this is the time from sending the request until its
+    /// response head was received, or it failed. For servers, this is the
+    /// time from receiving the request head until its response was
+    /// completely written, or it failed.
+    fn request_duration(&self, _duration: Duration) {}
+
+    /// A response was received by a client, or sent by a server.
+    fn response_status(&self, _class: StatusClass) {}
+
+    /// A client connection finished its handshake.
+    fn handshake_duration(&self, _duration: Duration) {}
+
+    /// A client sent a request on a connection that was already used for
+    /// another one.
+    fn connection_reused(&self) {}
+
+    /// A single poll of a server's service or response body took longer
+    /// than the [`slow_poll_threshold`].
+    ///
+    /// This usually means the service is blocking the thread, such as with
+    /// synchronous IO or a long computation, instead of yielding.
+    ///
+    /// [`slow_poll_threshold`]: crate::server::conn::Http::slow_poll_threshold
+    fn slow_poll(&self, _source: PollSource, _duration: Duration) {}
+
+    /// A [`ConcurrencyLimit`] had no room for another connection when one
+    /// was about to be accepted.
+    ///
+    /// [`ConcurrencyLimit`]: crate::server::conn::ConcurrencyLimit
+    fn limit_saturated(&self) {}
+
+    /// A [`ConcurrencyLimit`] rejected a connection or request over its
+    /// limit, answering it with `503 Service Unavailable`.
+    ///
+    /// [`ConcurrencyLimit`]: crate::server::conn::ConcurrencyLimit
+    fn limit_rejected(&self, _scope: LimitScope) {}
+
+    /// The number of connections served under a [`ConcurrencyLimit`]
+    /// changed.
+    ///
+    /// [`ConcurrencyLimit`]: crate::server::conn::ConcurrencyLimit
+    fn connections_in_flight(&self, _count: usize) {}
+
+    /// The number of requests served under a [`ConcurrencyLimit`] with a
+    /// request limit changed.
+    ///
+    /// [`ConcurrencyLimit`]: crate::server::conn::ConcurrencyLimit
+    fn requests_in_flight(&self, _count: usize) {}
+}
+
+/// What was over the limit when a [`ConcurrencyLimit`] rejected it.
+///
+/// [`ConcurrencyLimit`]: crate::server::conn::ConcurrencyLimit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LimitScope {
+    /// A connection, accepted over the connection limit.
+    Connection,
+    /// A request, over the request limit.
+    Request,
+}
+
+/// What was being polled when a poll was slow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PollSource {
+    /// The future returned by the service.
+    Service,
+    /// The body of a response.
+    Body,
+}
+
+impl PollSource {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            PollSource::Service => "service",
+            PollSource::Body => "body",
+        }
+    }
+}
+
+/// The class of a response status, by its first digit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StatusClass {
+    /// `1xx`
+    Informational,
+    /// `2xx`
+    Success,
+    /// `3xx`
+    Redirection,
+    /// `4xx`
+    ClientError,
+    /// `5xx`
+    ServerError,
+}
+
+impl StatusClass {
+    /// The class of `status`.
+    pub fn of(status: StatusCode) -> StatusClass {
+        match status.as_u16() {
+            100..=199 => StatusClass::Informational,
+            200..=299 => StatusClass::Success,
+            300..=399 => StatusClass::Redirection,
+            400..=499 => StatusClass::ClientError,
+            _ => StatusClass::ServerError,
+        }
+    }
+}
+
+/// A `Metrics` installed on a builder.
+#[derive(Clone)]
+pub(crate) struct MetricsHandle(Arc<dyn Metrics>);
+
+impl MetricsHandle {
+    pub(crate) fn new<M: Metrics + 'static>(metrics: M) -> MetricsHandle {
+        MetricsHandle(Arc::new(metrics))
+    }
+
+    pub(crate) fn get(&self) -> &dyn Metrics {
+        &*self.0
+    }
+
+    /// Starts counting the requests of a client connection.
+    #[cfg(feature = "client")]
+    pub(crate) fn conn(&self) -> ConnMetrics {
+        ConnMetrics {
+            metrics: self.clone(),
+            requests: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl fmt::Debug for MetricsHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish()
+    }
+}
+
+/// The metrics of a single client connection.
+#[cfg(feature = "client")]
+#[derive(Clone)]
+pub(crate) struct ConnMetrics {
+    metrics: MetricsHandle,
+    requests: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "client")]
+impl ConnMetrics {
+    /// A request is being sent, returns when.
+    pub(crate) fn request(&self) -> Instant {
+        if self.requests.fetch_add(1, Ordering::Relaxed) > 0 {
+            self.metrics.get().connection_reused();
+        }
+        Instant::now()
+    }
+
+    /// The request sent at `sent` finished, with the status of its response
+    /// if it didn't fail.
+    pub(crate) fn response(&self, sent: Instant, status: Option<StatusCode>) {
+        let metrics = self.metrics.get();
+        metrics.request_duration(sent.elapsed());
+        if let Some(status) = status {
+            metrics.response_status(StatusClass::of(status));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use super::StatusClass;
+
+    #[test]
+    fn status_class() {
+        assert_eq!(
+            StatusClass::of(StatusCode::CONTINUE),
+            StatusClass::Informational
+        );
+        assert_eq!(
+            StatusClass::of(StatusCode::NO_CONTENT),
+            StatusClass::Success
+        );
+        assert_eq!(StatusClass::of(StatusCode::FOUND), StatusClass::Redirection);
+        assert_eq!(
+            StatusClass::of(StatusCode::NOT_FOUND),
+            StatusClass::ClientError
+        );
+        assert_eq!(
+            StatusClass::of(StatusCode::SERVICE_UNAVAILABLE),
+            StatusClass::ServerError
+        );
+    }
+}
@@ -6,26 +6,37 @@ use std::time::Duration;
 
 use bytes::{Buf, Bytes};
 use http::header::{HeaderValue, CONNECTION};
-use http::{HeaderMap, Method, Version};
+use http::{Extensions, HeaderMap, Method, Version};
 use httparse::ParserConfig;
-use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, error, trace};
 
+use crate::error::IncompleteStage;
+
 use super::io::Buffered;
-use super::{Decoder, Encode, EncodedBuf, Encoder, Http1Transaction, ParseContext, Wants};
+use super::{
+    Decoder, Encode, EncodedBuf, Encoder, HeaderValuePolicy, Http1Transaction, ParseContext,
+    Strictness, TransferCodingPolicy, Wants,
+};
 use crate::body::DecodedLength;
 #[cfg(feature = "server")]
 use crate::common::time::Time;
 use crate::common::{task, Pin, Poll, Unpin};
+#[cfg(feature = "server")]
+use crate::ext::{Flusher, ResponseOptions, UnreadBody};
+#[cfg(feature = "server")]
+use crate::headers::connection_close;
 use crate::headers::connection_keep_alive;
 use crate::proto::{BodyLength, MessageHead};
 #[cfg(feature = "server")]
 use crate::rt::Sleep;
+use crate::rt::{Read, Write};
+#[cfg(feature = "server")]
+use crate::server::conn::parse_error::{OnParseError, ParseErrorAction};
 
 const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
 /// This handles a connection, which will have been established over an
-/// `AsyncRead + AsyncWrite` (like a socket), and will likely include multiple
+/// `Read + Write` (like a socket), and will likely include multiple
 /// `Transaction`s over HTTP.
 ///
 /// The connection will determine when a message begins and ends as well as
@@ -39,7 +50,7 @@ pub(crate) struct Conn<I, B, T> {
 
 impl<I, B, T> Conn<I, B, T>
 where
-    I: AsyncRead + AsyncWrite + Unpin,
+    I: Read + Write + Unpin,
     B: Buf,
     T: Http1Transaction,
 {
@@ -49,10 +60,19 @@ where
             state: State {
                 allow_half_close: false,
                 cached_headers: None,
+                cached_extensions: None,
+                header_capacity: 0,
+                max_headers: super::role::MAX_HEADERS,
                 error: None,
                 keep_alive: KA::Busy,
                 method: None,
                 h1_parser_config: ParserConfig::default(),
+                strictness: Strictness::default(),
+                bare_cr_scanned: 0,
+                header_value_policy: HeaderValuePolicy::default(),
+                validate_header_values: false,
+                sanitize_request_target: false,
+                transfer_coding_policy: TransferCodingPolicy::default(),
                 #[cfg(feature = "server")]
                 h1_header_read_timeout: None,
                 #[cfg(feature = "server")]
@@ -60,10 +80,42 @@ where
                 #[cfg(feature = "server")]
                 h1_header_read_timeout_running: false,
                 #[cfg(feature = "server")]
-                timer: Time::Empty,
+                h1_header_read_timeout_response: false,
+                #[cfg(feature = "server")]
+                on_parse_error: None,
+                #[cfg(feature = "server")]
+                timer: Time::default(),
+                #[cfg(feature = "server")]
+                max_body_size: None,
+                #[cfg(feature = "server")]
+                body_read: 0,
+                #[cfg(feature = "server")]
+                date_header: true,
+                keep_alive_header: true,
+                #[cfg(feature = "server")]
+                unread_body: None,
+                #[cfg(feature = "server")]
+                flusher: None,
+                #[cfg(feature = "server")]
+                drain_after_head: false,
+                #[cfg(feature = "server")]
+                drained: 0,
+                #[cfg(feature = "server")]
+                max_queued_responses: None,
+                #[cfg(feature = "server")]
+                queued_responses: 0,
+                #[cfg(feature = "server")]
+                write_batch: None,
+                #[cfg(feature = "server")]
+                write_batch_fut: None,
+                #[cfg(feature = "server")]
+                write_batch_state: WriteBatchState::Idle,
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
+                lazy_headers: false,
+                forward_lazy_headers: false,
+                body_channel_depth: crate::body::DEFAULT_CHANNEL_DEPTH,
                 title_case_headers: false,
                 h09_responses: false,
                 #[cfg(feature = "ffi")]
@@ -87,6 +139,24 @@ where
         self.state.timer = timer;
     }
 
+    #[cfg(feature = "server")]
+    pub(crate) fn set_max_body_size(&mut self, max: u64) {
+        self.state.max_body_size = Some(max);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_max_queued_responses(&mut self, max: usize) {
+        self.state.max_queued_responses = Some(max);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_write_batch(&mut self, max_delay: Duration, max_bytes: usize) {
+        self.state.write_batch = Some(WriteBatch {
+            max_delay,
+            max_bytes,
+        });
+    }
+
     #[cfg(feature = "server")]
     pub(crate) fn set_flush_pipeline(&mut self, enabled: bool) {
         self.io.set_flush_pipeline(enabled);
@@ -96,6 +166,15 @@ where
         self.io.set_write_strategy_queue();
     }
 
+    pub(crate) fn set_buffer_pool(&mut self, pool: crate::buffer::BufferPool) {
+        self.io.set_buffer_pool(pool);
+    }
+
+    #[cfg(feature = "debug")]
+    pub(crate) fn set_capture(&mut self, capture: crate::capture::ConnCapture) {
+        self.io.set_capture(capture);
+    }
+
     pub(crate) fn set_max_buf_size(&mut self, max: usize) {
         self.io.set_max_buf_size(max);
     }
@@ -114,6 +193,27 @@ where
         self.state.h1_parser_config = parser_config;
     }
 
+    pub(crate) fn set_strictness(&mut self, strictness: Strictness) {
+        self.state.strictness = strictness;
+    }
+
+    pub(crate) fn set_header_value_policy(&mut self, policy: HeaderValuePolicy) {
+        self.state.header_value_policy = policy;
+    }
+
+    pub(crate) fn set_validate_header_values(&mut self) {
+        self.state.validate_header_values = true;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_sanitize_request_target(&mut self) {
+        self.state.sanitize_request_target = true;
+    }
+
+    pub(crate) fn set_transfer_coding_policy(&mut self, policy: TransferCodingPolicy) {
+        self.state.transfer_coding_policy = policy;
+    }
+
     pub(crate) fn set_title_case_headers(&mut self) {
         self.state.title_case_headers = true;
     }
@@ -127,6 +227,26 @@ where
         self.state.preserve_header_order = true;
     }
 
+    pub(crate) fn set_lazy_headers(&mut self) {
+        self.state.lazy_headers = true;
+    }
+
+    pub(crate) fn set_forward_lazy_headers(&mut self) {
+        self.state.forward_lazy_headers = true;
+    }
+
+    pub(crate) fn set_header_capacity(&mut self, capacity: usize) {
+        self.state.header_capacity = capacity;
+    }
+
+    pub(crate) fn set_max_headers(&mut self, max: usize) {
+        self.state.max_headers = max;
+    }
+
+    pub(crate) fn set_body_channel_depth(&mut self, depth: usize) {
+        self.state.body_channel_depth = depth;
+    }
+
     #[cfg(feature = "client")]
     pub(crate) fn set_h09_responses(&mut self) {
         self.state.h09_responses = true;
@@ -137,6 +257,26 @@ where
         self.state.h1_header_read_timeout = Some(val);
     }
 
+    #[cfg(feature = "server")]
+    pub(crate) fn set_http1_header_read_timeout_response(&mut self) {
+        self.state.h1_header_read_timeout_response = true;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn set_on_parse_error(&mut self, hook: OnParseError) {
+        self.state.on_parse_error = Some(hook);
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn disable_date_header(&mut self) {
+        self.state.date_header = false;
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn disable_keep_alive_header(&mut self) {
+        self.state.keep_alive_header = false;
+    }
+
     #[cfg(feature = "server")]
     pub(crate) fn set_allow_half_close(&mut self) {
         self.state.allow_half_close = true;
@@ -159,16 +299,30 @@ where
         self.state.is_read_closed()
     }
 
+    pub(crate) fn body_channel_depth(&self) -> usize {
+        self.state.body_channel_depth
+    }
+
     pub(crate) fn is_write_closed(&self) -> bool {
         self.state.is_write_closed()
     }
 
+    pub(crate) fn has_initial_read_write_state(&self) -> bool {
+        matches!(self.state.reading, Reading::Init)
+            && matches!(self.state.writing, Writing::Init)
+            && self.io.read_buf().is_empty()
+    }
+
     pub(crate) fn can_read_head(&self) -> bool {
         if !matches!(self.state.reading, Reading::Init) {
             return false;
         }
 
         if T::should_read_first() {
+            #[cfg(feature = "server")]
+            if self.state.is_response_queue_full() {
+                return false;
+            }
             return true;
         }
 
@@ -187,6 +341,19 @@ where
         T::should_error_on_parse_eof() && !self.state.is_idle()
     }
 
+    /// The error for the connection closing at `stage` of the incoming
+    /// message.
+    fn incomplete(&self, stage: IncompleteStage) -> crate::Error {
+        let request_sent =
+            T::is_client() && matches!(self.state.writing, Writing::KeepAlive | Writing::Closed);
+        crate::Error::new_incomplete(stage, request_sent)
+    }
+
+    /// Whether some of the next incoming message was already read.
+    pub(crate) fn has_read_buffered(&self) -> bool {
+        !self.io.read_buf().is_empty()
+    }
+
     fn has_h2_prefix(&self) -> bool {
         let read_buf = self.io.read_buf();
         read_buf.len() >= 24 && read_buf[..24] == *H2_PREFACE
@@ -203,8 +370,17 @@ where
             cx,
             ParseContext {
                 cached_headers: &mut self.state.cached_headers,
+                cached_extensions: &mut self.state.cached_extensions,
+                header_capacity: self.state.header_capacity,
+                max_headers: self.state.max_headers,
                 req_method: &mut self.state.method,
                 h1_parser_config: self.state.h1_parser_config.clone(),
+                replace_bare_cr: self.state.strictness.replaces_bare_cr(),
+                bare_cr_scanned: &mut self.state.bare_cr_scanned,
+                allow_content_length_lists: self.state.strictness.allows_content_length_lists(),
+                sanitize_request_target: self.state.sanitize_request_target,
+                transfer_coding_policy: self.state.transfer_coding_policy,
+                header_value_policy: self.state.header_value_policy,
                 #[cfg(feature = "server")]
                 h1_header_read_timeout: self.state.h1_header_read_timeout,
                 #[cfg(feature = "server")]
@@ -216,6 +392,7 @@ where
                 preserve_header_case: self.state.preserve_header_case,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: self.state.preserve_header_order,
+                lazy_headers: self.state.lazy_headers,
                 h09_responses: self.state.h09_responses,
                 #[cfg(feature = "ffi")]
                 on_informational: &mut self.state.on_informational,
@@ -251,6 +428,20 @@ where
             Wants::EMPTY
         };
 
+        #[cfg(feature = "server")]
+        {
+            self.state.body_read = 0;
+            self.state.drained = 0;
+            if let (Some(max), Some(len)) = (self.state.max_body_size, msg.decode.into_opt()) {
+                if len > max {
+                    debug!("incoming body length ({}) is over the maximum", len);
+                    self.on_body_too_large();
+                    return Poll::Ready(Some(Err(crate::Error::new_body_too_large())));
+                }
+            }
+        }
+
+        let strict_chunks = self.state.strictness.rejects_invalid_chunk_size();
         if msg.decode == DecodedLength::ZERO {
             if msg.expect_continue {
                 debug!("ignoring expect-continue since body is empty");
@@ -260,10 +451,10 @@ where
                 self.try_keep_alive(cx);
             }
         } else if msg.expect_continue {
-            self.state.reading = Reading::Continue(Decoder::new(msg.decode));
+            self.state.reading = Reading::Continue(Decoder::new(msg.decode, strict_chunks));
             wants = wants.add(Wants::EXPECT);
         } else {
-            self.state.reading = Reading::Body(Decoder::new(msg.decode));
+            self.state.reading = Reading::Body(Decoder::new(msg.decode, strict_chunks));
         }
 
         Poll::Ready(Some(Ok((msg.head, msg.decode, wants))))
@@ -274,6 +465,10 @@ where
         // message should be reported as an error. If not, it is just
         // the connection closing gracefully.
         let must_error = self.should_error_on_eof();
+        let e = match e.incomplete_message() {
+            Some(incomplete) => self.incomplete(incomplete.stage()),
+            None => e,
+        };
         self.close_read();
         self.io.consume_leading_lines();
         let was_mid_parse = e.is_parse() || !self.io.read_buf().is_empty();
@@ -298,13 +493,23 @@ where
     pub(crate) fn poll_read_body(
         &mut self,
         cx: &mut task::Context<'_>,
-    ) -> Poll<Option<io::Result<Bytes>>> {
+    ) -> Poll<Option<crate::Result<Bytes>>> {
         debug_assert!(self.can_read_body());
 
         let (reading, ret) = match self.state.reading {
             Reading::Body(ref mut decoder) => {
                 match ready!(decoder.decode(cx, &mut self.io)) {
                     Ok(slice) => {
+                        #[cfg(feature = "server")]
+                        if let Some(max) = self.state.max_body_size {
+                            self.state.body_read += slice.len() as u64;
+                            if self.state.body_read > max {
+                                debug!("incoming body is over the maximum length");
+                                self.on_body_too_large();
+                                return Poll::Ready(Some(Err(crate::Error::new_body_too_large())));
+                            }
+                        }
+
                         let (reading, chunk) = if decoder.is_eof() {
                             debug!("incoming body completed");
                             (
@@ -328,7 +533,12 @@ where
                     }
                     Err(e) => {
                         debug!("incoming body decode error: {}", e);
-                        (Reading::Closed, Poll::Ready(Some(Err(e))))
+                        let err = if e.kind() == io::ErrorKind::UnexpectedEof {
+                            self.incomplete(IncompleteStage::Body).with(e)
+                        } else {
+                            crate::Error::new_body(e)
+                        };
+                        (Reading::Closed, Poll::Ready(Some(Err(err))))
                     }
                 }
             }
@@ -364,6 +574,12 @@ where
     ) -> Poll<crate::Result<()>> {
         debug_assert!(!self.can_read_head() && !self.can_read_body());
 
+        // Reading resumes once the queued responses are flushed.
+        #[cfg(feature = "server")]
+        if self.state.is_response_queue_full() && matches!(self.state.reading, Reading::Init) {
+            return Poll::Pending;
+        }
+
         if self.is_read_closed() {
             Poll::Pending
         } else if self.is_mid_message() {
@@ -399,7 +615,7 @@ where
         if num_read == 0 {
             let ret = if self.should_error_on_eof() {
                 trace!("found unexpected EOF on busy connection: {:?}", self.state);
-                Poll::Ready(Err(crate::Error::new_incomplete()))
+                Poll::Ready(Err(self.incomplete(IncompleteStage::BeforeHead)))
             } else {
                 trace!("found EOF on idle connection, closing");
                 Poll::Ready(Ok(()))
@@ -429,8 +645,14 @@ where
 
         if num_read == 0 {
             trace!("found unexpected EOF on busy connection: {:?}", self.state);
+            let stage = match self.state.reading {
+                Reading::Init => IncompleteStage::BeforeHead,
+                Reading::Continue(..) | Reading::Body(..) => IncompleteStage::Body,
+                Reading::KeepAlive | Reading::Closed => IncompleteStage::AfterBody,
+            };
+            let err = self.incomplete(stage);
             self.state.close_read();
-            Poll::Ready(Err(crate::Error::new_incomplete()))
+            Poll::Ready(Err(err))
         } else {
             Poll::Ready(Ok(()))
         }
@@ -546,6 +768,34 @@ where
 
         self.enforce_version(&mut head);
 
+        #[cfg(feature = "server")]
+        let mut options = ResponseOptions::default();
+        #[cfg(feature = "server")]
+        if T::is_server() {
+            self.state.unread_body = head.extensions.remove::<UnreadBody>();
+            self.state.flusher = head.extensions.remove::<Flusher>();
+            if self.state.drain_after_head {
+                self.state.drain_after_head = false;
+                self.state.notify_read = true;
+            }
+            options = head
+                .extensions
+                .remove::<ResponseOptions>()
+                .unwrap_or_default();
+            if options.closes_connection() {
+                let has_close = head
+                    .headers
+                    .get_all(CONNECTION)
+                    .iter()
+                    .any(connection_close);
+                if !has_close {
+                    head.headers
+                        .insert(CONNECTION, HeaderValue::from_static("close"));
+                }
+                self.state.disable_keep_alive();
+            }
+        }
+
         let buf = self.io.headers_buf();
         match super::role::encode_headers::<T>(
             Encode {
@@ -553,12 +803,22 @@ where
                 body,
                 #[cfg(feature = "server")]
                 keep_alive: self.state.wants_keep_alive(),
+                #[cfg(feature = "server")]
+                date_header: self.state.date_header && options.date_header(),
+                #[cfg(feature = "server")]
+                chunked: options.chunked(),
+                #[cfg(feature = "server")]
+                timer: &self.state.timer,
                 req_method: &mut self.state.method,
                 title_case_headers: self.state.title_case_headers,
+                validate_header_values: self.state.validate_header_values,
+                forward_lazy_headers: self.state.forward_lazy_headers,
             },
             buf,
         ) {
             Ok(encoder) => {
+                #[cfg(feature = "server")]
+                let encoder = encoder.set_max_chunk_size(options.chunk_size());
                 debug_assert!(self.state.cached_headers.is_none());
                 debug_assert!(head.headers.is_empty());
                 self.state.cached_headers = Some(head.headers);
@@ -569,6 +829,9 @@ where
                         head.extensions.remove::<crate::ffi::OnInformational>();
                 }
 
+                head.extensions.clear();
+                self.state.cached_extensions = Some(head.extensions);
+
                 Some(encoder)
             }
             Err(err) => {
@@ -593,11 +856,16 @@ where
                 // disable keep-alive so the server closes the connection
                 Version::HTTP_10 => self.state.disable_keep_alive(),
                 // If response is version 1.1 and keep-alive is wanted, add
-                // Connection: keep-alive header when not present
+                // Connection: keep-alive header when not present, or
+                // disable keep-alive if that header mustn't be added
                 Version::HTTP_11 => {
                     if self.state.wants_keep_alive() {
-                        head.headers
-                            .insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+                        if self.state.keep_alive_header {
+                            head.headers
+                                .insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+                        } else {
+                            self.state.disable_keep_alive();
+                        }
                     }
                 }
                 _ => (),
@@ -645,6 +913,16 @@ where
         self.state.writing = state;
     }
 
+    /// Whether the body asked, through its `Flusher`, for what it wrote so
+    /// far to be flushed.
+    pub(crate) fn wants_flush(&self) -> bool {
+        #[cfg(feature = "server")]
+        if let Some(ref flusher) = self.state.flusher {
+            return flusher.take();
+        }
+        false
+    }
+
     pub(crate) fn write_body_and_end(&mut self, chunk: B) {
         debug_assert!(self.can_write_body() && self.can_buffer_body());
         // empty chunks should be discarded at Dispatcher level
@@ -705,11 +983,7 @@ where
             if self.has_h2_prefix() {
                 return Err(crate::Error::new_version_h2());
             }
-            if let Some(msg) = T::on_error(&err) {
-                // Drop the cached headers so as to not trigger a debug
-                // assert in `write_head`...
-                self.state.cached_headers.take();
-                self.write_head(msg, None);
+            if self.write_error_response(&err) {
                 self.state.error = Some(err);
                 return Ok(());
             }
@@ -719,13 +993,146 @@ where
         Err(err)
     }
 
+    // Writes the response to a parse error, returning whether there is one.
+    fn write_error_response(&mut self, err: &crate::Error) -> bool {
+        #[cfg(feature = "server")]
+        if let Some(ref hook) = self.state.on_parse_error {
+            match hook.call(err) {
+                ParseErrorAction::Default => (),
+                ParseErrorAction::Respond(res) => {
+                    let (parts, body) = res.into_parts();
+                    let msg = match T::on_error_response(parts) {
+                        Some(msg) => msg,
+                        None => return false,
+                    };
+                    self.state.cached_headers.take();
+                    self.write_head(msg, Some(BodyLength::Known(body.len() as u64)));
+                    if let Writing::Body(_) = self.state.writing {
+                        // The body is known up front, so it goes right after
+                        // the head instead of through the encoder.
+                        self.io.headers_buf().extend_from_slice(&body);
+                        self.state.writing = Writing::Closed;
+                    }
+                    return true;
+                }
+                ParseErrorAction::Close => return false,
+            }
+        }
+        #[cfg(feature = "server")]
+        if let crate::error::Kind::HeaderTimeout = *err.inner_kind() {
+            if !self.state.h1_header_read_timeout_response {
+                return false;
+            }
+        }
+        match T::on_error(err) {
+            Some(msg) => {
+                // Drop the cached headers so as to not trigger a debug
+                // assert in `write_head`...
+                self.state.cached_headers.take();
+                self.write_head(msg, None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // When an incoming body is over the maximum size, stop reading, and
+    // send a 413 response if one hasn't been started yet.
+    #[cfg(feature = "server")]
+    fn on_body_too_large(&mut self) {
+        self.close_read();
+        self.state.disable_keep_alive();
+        if let Writing::Init = self.state.writing {
+            let err = crate::Error::new_body_too_large();
+            if self.write_error_response(&err) {
+                self.state.error = Some(err);
+            }
+        }
+    }
+
     pub(crate) fn poll_flush(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        #[cfg(feature = "server")]
+        if self.poll_write_batch(cx).is_pending() {
+            // Not flushed yet, but the next response can be written.
+            self.try_keep_alive(cx);
+            return Poll::Ready(Ok(()));
+        }
+        #[cfg(feature = "server")]
+        if self.state.is_response_queue_full() {
+            ready!(self.io.poll_flush_pipelined(cx))?;
+        } else {
+            ready!(Pin::new(&mut self.io).poll_flush(cx))?;
+        }
+        #[cfg(not(feature = "server"))]
         ready!(Pin::new(&mut self.io).poll_flush(cx))?;
         self.try_keep_alive(cx);
+
+        #[cfg(feature = "server")]
+        if !self.io.has_queued_writes() && self.state.queued_responses != 0 {
+            if self.state.is_response_queue_full() {
+                trace!("queued responses flushed, resuming reads");
+                self.state.notify_read = true;
+            }
+            self.state.queued_responses = 0;
+        }
         trace!("flushed({}): {:?}", T::LOG, self.state);
         Poll::Ready(Ok(()))
     }
 
+    /// Pending while the queued responses are held back, so those written
+    /// soon after are flushed along with them.
+    #[cfg(feature = "server")]
+    fn poll_write_batch(&mut self, cx: &mut task::Context<'_>) -> Poll<()> {
+        let batch = match self.state.write_batch {
+            Some(batch) => batch,
+            None => return Poll::Ready(()),
+        };
+
+        let queued = self.io.queued_writes_len();
+        if queued == 0 {
+            self.state.write_batch_state = WriteBatchState::Idle;
+            return Poll::Ready(());
+        }
+        // Only hold complete responses, on a connection that stays open.
+        let hold = queued < batch.max_bytes
+            && self.io.can_buffer()
+            && matches!(self.state.writing, Writing::Init | Writing::KeepAlive)
+            && !self.state.is_read_closed()
+            && self.state.wants_keep_alive()
+            && !self.state.is_response_queue_full();
+
+        match self.state.write_batch_state {
+            WriteBatchState::Flushing => return Poll::Ready(()),
+            _ if !hold => {
+                self.state.write_batch_state = WriteBatchState::Flushing;
+                return Poll::Ready(());
+            }
+            WriteBatchState::Idle => {
+                let deadline = self.state.timer.now() + batch.max_delay;
+                match self.state.write_batch_fut {
+                    Some(ref mut fut) => self.state.timer.reset(fut, deadline),
+                    None => {
+                        self.state.write_batch_fut = Some(crate::common::into_pin(
+                            self.state.timer.sleep_until(deadline),
+                        ));
+                    }
+                }
+                self.state.write_batch_state = WriteBatchState::Holding;
+            }
+            WriteBatchState::Holding => (),
+        }
+
+        let fut = self
+            .state
+            .write_batch_fut
+            .as_mut()
+            .expect("write batch timer set");
+        ready!(fut.as_mut().poll(cx));
+        trace!("write batch delay elapsed, flushing {} bytes", queued);
+        self.state.write_batch_state = WriteBatchState::Flushing;
+        Poll::Ready(())
+    }
+
     pub(crate) fn poll_shutdown(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
         match ready!(Pin::new(self.io.io_mut()).poll_shutdown(cx)) {
             Ok(()) => {
@@ -740,7 +1147,28 @@ where
     }
 
     /// If the read side can be cheaply drained, do so. Otherwise, close.
-    pub(super) fn poll_drain_or_close_read(&mut self, cx: &mut task::Context<'_>) {
+    ///
+    /// Servers first wait for the response head, since it may set an
+    /// `UnreadBody` saying how much to drain.
+    pub(super) fn poll_drain_or_close_read(&mut self, cx: &mut task::Context<'_>) -> Poll<()> {
+        #[cfg(feature = "server")]
+        if T::is_server() {
+            if let Writing::Init = self.state.writing {
+                trace!("body receiver dropped before response, waiting for it");
+                self.state.drain_after_head = true;
+                return Poll::Pending;
+            }
+            match self.state.unread_body {
+                Some(UnreadBody::Drain(max)) => return self.poll_drain_read(cx, max),
+                Some(UnreadBody::Close) => {
+                    trace!("closing unread body");
+                    self.close_read();
+                    return Poll::Ready(());
+                }
+                None => (),
+            }
+        }
+
         let _ = self.poll_read_body(cx);
 
         // If still in Reading::Body, just give up
@@ -748,6 +1176,34 @@ where
             Reading::Init | Reading::KeepAlive => trace!("body drained"),
             _ => self.close_read(),
         }
+        Poll::Ready(())
+    }
+
+    #[cfg(feature = "server")]
+    fn poll_drain_read(&mut self, cx: &mut task::Context<'_>, max: u64) -> Poll<()> {
+        // The client is waiting for a 100 Continue that won't be sent, so
+        // there is nothing to drain.
+        if let Reading::Continue(..) = self.state.reading {
+            trace!("closing unread body that wasn't sent yet");
+            self.close_read();
+            return Poll::Ready(());
+        }
+
+        while self.can_read_body() {
+            match ready!(self.poll_read_body(cx)) {
+                Some(Ok(chunk)) => {
+                    self.state.drained += chunk.len() as u64;
+                    if self.state.drained > max {
+                        debug!("unread body is over the drain limit ({}), closing", max);
+                        self.close_read();
+                    }
+                }
+                Some(Err(_)) => self.close_read(),
+                None => (),
+            }
+        }
+        trace!("body drained");
+        Poll::Ready(())
     }
 
     pub(crate) fn close_read(&mut self) {
@@ -769,6 +1225,13 @@ where
         }
     }
 
+    /// Whether an error response was written, and the error is returned
+    /// once it is flushed.
+    #[cfg(feature = "server")]
+    pub(crate) fn has_error_response(&self) -> bool {
+        self.state.error.is_some()
+    }
+
     pub(crate) fn take_error(&mut self) -> crate::Result<()> {
         if let Some(err) = self.state.error.take() {
             Err(err)
@@ -799,6 +1262,12 @@ struct State {
     allow_half_close: bool,
     /// Re-usable HeaderMap to reduce allocating new ones.
     cached_headers: Option<HeaderMap>,
+    /// Re-usable Extensions, cleared, for the same reason.
+    cached_extensions: Option<Extensions>,
+    /// How many headers a parsed message is expected to have.
+    header_capacity: usize,
+    /// How many headers a parsed message may have.
+    max_headers: usize,
     /// If an error occurs when there wasn't a direct way to return it
     /// back to the user, this is set.
     error: Option<crate::Error>,
@@ -810,17 +1279,78 @@ struct State {
     /// a body or not.
     method: Option<Method>,
     h1_parser_config: ParserConfig,
+    /// The RFC 9112 checks that aren't left to `h1_parser_config`.
+    strictness: Strictness,
+    /// How far the read buffer was searched for bare CRs.
+    bare_cr_scanned: usize,
+    header_value_policy: HeaderValuePolicy,
+    /// Whether outgoing header values are checked beyond what `http` does.
+    validate_header_values: bool,
+    /// Whether request targets `http::Uri` rejects are percent-encoded.
+    sanitize_request_target: bool,
+    /// What to do with transfer codings other than chunked.
+    transfer_coding_policy: TransferCodingPolicy,
     #[cfg(feature = "server")]
     h1_header_read_timeout: Option<Duration>,
     #[cfg(feature = "server")]
     h1_header_read_timeout_fut: Option<Pin<Box<dyn Sleep>>>,
     #[cfg(feature = "server")]
     h1_header_read_timeout_running: bool,
+    /// Whether to respond with a 408 when the header read timeout fires.
+    #[cfg(feature = "server")]
+    h1_header_read_timeout_response: bool,
+    /// Decides the response to parse errors, instead of `T::on_error`.
+    #[cfg(feature = "server")]
+    on_parse_error: Option<OnParseError>,
     #[cfg(feature = "server")]
     timer: Time,
+    /// The maximum size of an incoming body, if any.
+    #[cfg(feature = "server")]
+    max_body_size: Option<u64>,
+    /// How much of the current incoming body has been read.
+    #[cfg(feature = "server")]
+    body_read: u64,
+    /// Whether to add a `date` header to responses that lack one.
+    #[cfg(feature = "server")]
+    date_header: bool,
+    /// Whether to add `connection: keep-alive` to keep HTTP/1.0 connections
+    /// alive.
+    keep_alive_header: bool,
+    /// What to do with an incoming body the service stopped reading, as set
+    /// by the current response.
+    #[cfg(feature = "server")]
+    unread_body: Option<UnreadBody>,
+    /// Set by the current response, for its body to ask for flushes.
+    #[cfg(feature = "server")]
+    flusher: Option<Flusher>,
+    /// Set when the incoming body was dropped before the response head was
+    /// written, so reading is polled again once it is.
+    #[cfg(feature = "server")]
+    drain_after_head: bool,
+    /// How much of the incoming body has been drained.
+    #[cfg(feature = "server")]
+    drained: u64,
+    /// Stop reading requests while this many responses wait to be flushed.
+    #[cfg(feature = "server")]
+    max_queued_responses: Option<usize>,
+    /// How many responses were written since the last complete flush.
+    #[cfg(feature = "server")]
+    queued_responses: usize,
+    /// Hold written responses to flush them together, if set.
+    #[cfg(feature = "server")]
+    write_batch: Option<WriteBatch>,
+    #[cfg(feature = "server")]
+    write_batch_fut: Option<Pin<Box<dyn Sleep>>>,
+    #[cfg(feature = "server")]
+    write_batch_state: WriteBatchState,
     preserve_header_case: bool,
     #[cfg(feature = "ffi")]
     preserve_header_order: bool,
+    lazy_headers: bool,
+    /// Whether a `LazyHeaders` extension on an outgoing message is written.
+    forward_lazy_headers: bool,
+    /// How many chunks of an incoming body are read ahead of the receiver.
+    body_channel_depth: usize,
     title_case_headers: bool,
     h09_responses: bool,
     /// If set, called with each 1xx informational response received for
@@ -843,6 +1373,25 @@ struct State {
     version: Version,
 }
 
+/// How long, and up to how many bytes, responses are held to be written
+/// together.
+#[cfg(feature = "server")]
+#[derive(Clone, Copy)]
+struct WriteBatch {
+    max_delay: Duration,
+    max_bytes: usize,
+}
+
+#[cfg(feature = "server")]
+enum WriteBatchState {
+    /// Nothing is queued.
+    Idle,
+    /// The queued responses are held until `write_batch_fut` completes.
+    Holding,
+    /// The queued responses are flushed, without holding them again.
+    Flushing,
+}
+
 #[derive(Debug)]
 enum Reading {
     Init,
@@ -1007,6 +1556,11 @@ impl State {
         self.reading = Reading::Init;
         self.writing = Writing::Init;
 
+        #[cfg(feature = "server")]
+        if T::should_read_first() {
+            self.queued_responses += 1;
+        }
+
         // !T::should_read_first() means Client.
         //
         // If Client connection has just gone idle, the Dispatcher
@@ -1021,6 +1575,12 @@ impl State {
         matches!(self.keep_alive.status(), KA::Idle)
     }
 
+    #[cfg(feature = "server")]
+    fn is_response_queue_full(&self) -> bool {
+        self.max_queued_responses
+            .map_or(false, |max| self.queued_responses >= max)
+    }
+
     fn is_read_closed(&self) -> bool {
         matches!(self.reading, Reading::Closed)
     }
@@ -1047,7 +1607,7 @@ mod tests {
         b.bytes = len as u64;
 
         // an empty IO, we'll be skipping and using the read buffer anyways
-        let io = tokio_test::io::Builder::new().build();
+        let io = crate::common::io::Compat::new(tokio_test::io::Builder::new().build());
         let mut conn = Conn::<_, bytes::Bytes, crate::proto::h1::ServerTransaction>::new(io);
         *conn.io.read_buf_mut() = ::bytes::BytesMut::from(&s[..]);
         conn.state.cached_headers = Some(HeaderMap::with_capacity(2));
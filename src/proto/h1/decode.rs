@@ -20,6 +20,8 @@ use self::Kind::{Chunked, Eof, Length};
 #[derive(Clone, PartialEq)]
 pub(crate) struct Decoder {
     kind: Kind,
+    /// Whether chunk size lines are checked strictly against RFC 9112.
+    strict: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -49,6 +51,7 @@ enum Kind {
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum ChunkedState {
+    Start,
     Size,
     SizeLws,
     Extension,
@@ -69,27 +72,32 @@ impl Decoder {
     pub(crate) fn length(x: u64) -> Decoder {
         Decoder {
             kind: Kind::Length(x),
+            strict: false,
         }
     }
 
     pub(crate) fn chunked() -> Decoder {
         Decoder {
-            kind: Kind::Chunked(ChunkedState::Size, 0),
+            kind: Kind::Chunked(ChunkedState::Start, 0),
+            strict: false,
         }
     }
 
     pub(crate) fn eof() -> Decoder {
         Decoder {
             kind: Kind::Eof(false),
+            strict: false,
         }
     }
 
-    pub(super) fn new(len: DecodedLength) -> Self {
-        match len {
+    pub(super) fn new(len: DecodedLength, strict: bool) -> Self {
+        let mut decoder = match len {
             DecodedLength::CHUNKED => Decoder::chunked(),
             DecodedLength::CLOSE_DELIMITED => Decoder::eof(),
             length => Decoder::length(length.danger_len()),
-        }
+        };
+        decoder.strict = strict;
+        decoder
     }
 
     // methods
@@ -132,7 +140,7 @@ impl Decoder {
                 loop {
                     let mut buf = None;
                     // advances the chunked state
-                    *state = ready!(state.step(cx, body, size, &mut buf))?;
+                    *state = ready!(state.step(cx, body, size, &mut buf, self.strict))?;
                     if *state == ChunkedState::End {
                         trace!("end of chunked");
                         return Poll::Ready(Ok(Bytes::new()));
@@ -189,11 +197,13 @@ impl ChunkedState {
         body: &mut R,
         size: &mut u64,
         buf: &mut Option<Bytes>,
+        strict: bool,
     ) -> Poll<Result<ChunkedState, io::Error>> {
         use self::ChunkedState::*;
         match *self {
+            Start => ChunkedState::read_start(cx, body, size, strict),
             Size => ChunkedState::read_size(cx, body, size),
-            SizeLws => ChunkedState::read_size_lws(cx, body),
+            SizeLws => ChunkedState::read_size_lws(cx, body, strict),
             Extension => ChunkedState::read_extension(cx, body),
             SizeLf => ChunkedState::read_size_lf(cx, body, *size),
             Body => ChunkedState::read_body(cx, body, size, buf),
@@ -206,13 +216,34 @@ impl ChunkedState {
             End => Poll::Ready(Ok(ChunkedState::End)),
         }
     }
+    fn read_start<R: MemRead>(
+        cx: &mut task::Context<'_>,
+        rdr: &mut R,
+        size: &mut u64,
+        strict: bool,
+    ) -> Poll<Result<ChunkedState, io::Error>> {
+        trace!("Read chunk hex size start");
+        let b = byte!(rdr, cx);
+        // RFC 9112 requires at least one digit, so that a line without one
+        // isn't taken as the last chunk.
+        if strict && !b.is_ascii_hexdigit() {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid chunk size line: Missing Size",
+            )));
+        }
+        ChunkedState::size_byte(b, size)
+    }
     fn read_size<R: MemRead>(
         cx: &mut task::Context<'_>,
         rdr: &mut R,
         size: &mut u64,
     ) -> Poll<Result<ChunkedState, io::Error>> {
         trace!("Read chunk hex size");
-
+        let b = byte!(rdr, cx);
+        ChunkedState::size_byte(b, size)
+    }
+    fn size_byte(b: u8, size: &mut u64) -> Poll<Result<ChunkedState, io::Error>> {
         macro_rules! or_overflow {
             ($e:expr) => (
                 match $e {
@@ -226,7 +257,7 @@ impl ChunkedState {
         }
 
         let radix = 16;
-        match byte!(rdr, cx) {
+        match b {
             b @ b'0'..=b'9' => {
                 *size = or_overflow!(size.checked_mul(radix));
                 *size = or_overflow!(size.checked_add((b - b'0') as u64));
@@ -254,13 +285,15 @@ impl ChunkedState {
     fn read_size_lws<R: MemRead>(
         cx: &mut task::Context<'_>,
         rdr: &mut R,
+        strict: bool,
     ) -> Poll<Result<ChunkedState, io::Error>> {
         trace!("read_size_lws");
         match byte!(rdr, cx) {
             // LWS can follow the chunk size, but no more digits can come
             b'\t' | b' ' => Poll::Ready(Ok(ChunkedState::SizeLws)),
             b';' => Poll::Ready(Ok(ChunkedState::Extension)),
-            b'\r' => Poll::Ready(Ok(ChunkedState::SizeLf)),
+            // RFC 9112 only allows whitespace before an extension.
+            b'\r' if !strict => Poll::Ready(Ok(ChunkedState::SizeLf)),
             _ => Poll::Ready(Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "Invalid chunk size linear white space",
@@ -360,7 +393,7 @@ impl ChunkedState {
         rdr: &mut R,
     ) -> Poll<Result<ChunkedState, io::Error>> {
         match byte!(rdr, cx) {
-            b'\n' => Poll::Ready(Ok(ChunkedState::Size)),
+            b'\n' => Poll::Ready(Ok(ChunkedState::Start)),
             _ => Poll::Ready(Err(io::Error::new(
                 io::ErrorKind::InvalidInput,
                 "Invalid chunk body LF",
@@ -480,13 +513,14 @@ mod tests {
         use std::io::ErrorKind::{InvalidData, InvalidInput, UnexpectedEof};
 
         async fn read(s: &str) -> u64 {
-            let mut state = ChunkedState::Size;
+            let mut state = ChunkedState::Start;
             let rdr = &mut s.as_bytes();
             let mut size = 0;
             loop {
-                let result =
-                    futures_util::future::poll_fn(|cx| state.step(cx, rdr, &mut size, &mut None))
-                        .await;
+                let result = futures_util::future::poll_fn(|cx| {
+                    state.step(cx, rdr, &mut size, &mut None, false)
+                })
+                .await;
                 let desc = format!("read_size failed for {:?}", s);
                 state = result.expect(desc.as_str());
                 if state == ChunkedState::Body || state == ChunkedState::EndCr {
@@ -497,13 +531,18 @@ mod tests {
         }
 
         async fn read_err(s: &str, expected_err: io::ErrorKind) {
-            let mut state = ChunkedState::Size;
+            read_err_with(s, expected_err, false).await
+        }
+
+        async fn read_err_with(s: &str, expected_err: io::ErrorKind, strict: bool) {
+            let mut state = ChunkedState::Start;
             let rdr = &mut s.as_bytes();
             let mut size = 0;
             loop {
-                let result =
-                    futures_util::future::poll_fn(|cx| state.step(cx, rdr, &mut size, &mut None))
-                        .await;
+                let result = futures_util::future::poll_fn(|cx| {
+                    state.step(cx, rdr, &mut size, &mut None, strict)
+                })
+                .await;
                 state = match result {
                     Ok(s) => s,
                     Err(e) => {
@@ -555,6 +594,11 @@ mod tests {
         read_err("1;reject\nnewlines\r\n", InvalidData).await;
         // Overflow
         read_err("f0000000000000003\r\n", InvalidData).await;
+        // Strictly, a size is required, and whitespace only before an
+        // extension
+        read_err_with("\r\n", InvalidInput, true).await;
+        read_err_with(";ext\r\n", InvalidInput, true).await;
+        read_err_with("Ff   \r\n", InvalidInput, true).await;
     }
 
     #[cfg(not(miri))]
@@ -2,13 +2,14 @@ use std::error::Error as StdError;
 
 use bytes::{Buf, Bytes};
 use http::Request;
-use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, trace};
 
 use super::{Http1Transaction, Wants};
 use crate::body::{Body, DecodedLength, Recv};
 use crate::common::{task, Future, Pin, Poll, Unpin};
+use crate::ext::{ConnTimings, Mark, Timings};
 use crate::proto::{BodyLength, Conn, Dispatched, MessageHead, RequestHead};
+use crate::rt::{Read, Write};
 use crate::upgrade::OnUpgrade;
 
 pub(crate) struct Dispatcher<D, Bs: Body, I, T> {
@@ -17,28 +18,85 @@ pub(crate) struct Dispatcher<D, Bs: Body, I, T> {
     body_tx: Option<crate::body::Sender>,
     body_rx: Pin<Box<Option<Bs>>>,
     is_closing: bool,
+    // Set by a graceful shutdown, to close the connection if the client
+    // hasn't sent anything yet.
+    close_if_unused: bool,
+    // How many times to loop in a single poll before yielding.
+    poll_budget: usize,
+    // Entered while polling, with the `tracing-spans` feature.
+    span: tracing::Span,
 }
 
+type PollMsg<D> = Poll<
+    Option<
+        Result<
+            (<D as Dispatch>::PollItem, Option<<D as Dispatch>::PollBody>),
+            <D as Dispatch>::PollError,
+        >,
+    >,
+>;
+
 pub(crate) trait Dispatch {
     type PollItem;
     type PollBody;
     type PollError;
     type RecvItem;
-    fn poll_msg(
-        self: Pin<&mut Self>,
-        cx: &mut task::Context<'_>,
-    ) -> Poll<Option<Result<(Self::PollItem, Self::PollBody), Self::PollError>>>;
+    /// Polls the next message to write, with its body. A `None` body is
+    /// written as an empty one.
+    fn poll_msg(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> PollMsg<Self>;
     fn recv_msg(&mut self, msg: crate::Result<(Self::RecvItem, Recv)>) -> crate::Result<()>;
     fn poll_ready(&mut self, cx: &mut task::Context<'_>) -> Poll<Result<(), ()>>;
     fn should_poll(&self) -> bool;
+    /// Called with the length of each chunk of the incoming body.
+    fn on_read_body(&mut self, _len: usize) {}
+    /// Called with the length of each chunk of the outgoing body.
+    fn on_write_body(&mut self, _len: usize) {}
+    /// Called once an outgoing message has been completely written.
+    fn on_write_end(&mut self) {}
+    /// Called to poll the outgoing body with `poll`, so it can be timed.
+    fn time_body_poll<T>(&self, poll: impl FnOnce() -> T) -> T {
+        poll()
+    }
+    /// Called once the first bytes of an incoming message head were read.
+    fn on_read_head_start(&mut self) {}
+    /// Called once the body of an incoming message has been completely read.
+    fn on_read_body_end(&mut self) {}
 }
 
 cfg_server! {
+    use crate::common::time::Time;
+    use crate::ext::{ConnExtensions, DisconnectNotifier};
+    use crate::metrics::PollSource;
+    use crate::proto::ServiceTimeout;
+    use crate::rt::Sleep;
+    use crate::server::conn::summary::{time_poll, OnRequestComplete, Record};
     use crate::service::HttpService;
+    use std::collections::VecDeque;
 
     pub(crate) struct Server<S: HttpService<B>, B> {
         in_flight: Pin<Box<Option<S::Future>>>,
         pub(crate) service: S,
+        pub(crate) extensions: ConnExtensions,
+        // The number of requests received but not yet completely responded to.
+        active_requests: usize,
+        on_request_complete: Option<OnRequestComplete>,
+        // The requests not yet completely responded to, oldest first. There
+        // are at most two: one whose response is being written, and one
+        // whose request was read in the meantime.
+        records: VecDeque<Record>,
+        // Fire the `PeerDisconnect` of the requests not yet completely
+        // responded to, if dropped.
+        disconnects: VecDeque<DisconnectNotifier>,
+        pub(crate) service_timeout: ServiceTimeout,
+        timer: Time,
+        // Started when a request is dispatched, if there is a service timeout.
+        timeout_sleep: Option<Box<dyn Sleep + Unpin>>,
+        // Set if requests get a `Timings` extension.
+        timings: Option<ConnTimings>,
+        // When the head of the next request started arriving.
+        first_byte: Option<std::time::Instant>,
+        // The timings of the request whose body is being read.
+        reading: Option<Timings>,
     }
 }
 
@@ -49,6 +107,10 @@ cfg_client! {
             #[pin]
             rx: ClientRx<B>,
             rx_closed: bool,
+            // Set if responses get a `Timings` extension.
+            timings: Option<ConnTimings>,
+            // The timings of the request in flight.
+            current: Option<Timings>,
         }
     }
 
@@ -63,7 +125,7 @@ where
             RecvItem = MessageHead<T::Incoming>,
         > + Unpin,
     D::PollError: Into<Box<dyn StdError + Send + Sync>>,
-    I: AsyncRead + AsyncWrite + Unpin,
+    I: Read + Write + Unpin,
     T: Http1Transaction + Unpin,
     Bs: Body + 'static,
     Bs::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -75,9 +137,20 @@ where
             body_tx: None,
             body_rx: Box::pin(None),
             is_closing: false,
+            close_if_unused: false,
+            poll_budget: crate::proto::DEFAULT_POLL_BUDGET,
+            span: crate::common::trace::conn_span(
+                if T::is_server() { "server" } else { "client" },
+                "HTTP/1.1",
+            ),
         }
     }
 
+    pub(crate) fn set_poll_budget(&mut self, budget: usize) {
+        debug_assert!(budget > 0);
+        self.poll_budget = budget;
+    }
+
     #[cfg(feature = "server")]
     pub(crate) fn disable_keep_alive(&mut self) {
         self.conn.disable_keep_alive();
@@ -86,13 +159,24 @@ where
         }
     }
 
+    /// Like `disable_keep_alive`, but also closes the connection if no bytes
+    /// have been received yet.
+    ///
+    /// Bytes may still be waiting in the transport, so this is checked the
+    /// next time a read would block.
+    #[cfg(feature = "server")]
+    pub(crate) fn disable_keep_alive_or_close_unused(&mut self) {
+        self.disable_keep_alive();
+        self.close_if_unused = true;
+    }
+
     pub(crate) fn into_inner(self) -> (I, Bytes, D) {
         let (io, buf) = self.conn.into_inner();
         (io, buf, self.dispatch)
     }
 
     /// Run this dispatcher until HTTP says this connection is done,
-    /// but don't call `AsyncWrite::shutdown` on the underlying IO.
+    /// but don't call `Write::poll_shutdown` on the underlying IO.
     ///
     /// This is useful for old-style HTTP upgrades, but ignores
     /// newer-style upgrade API.
@@ -115,7 +199,15 @@ where
         cx: &mut task::Context<'_>,
         should_shutdown: bool,
     ) -> Poll<crate::Result<Dispatched>> {
-        Poll::Ready(ready!(self.poll_inner(cx, should_shutdown)).or_else(|e| {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        let result = ready!(self.poll_inner(cx, should_shutdown));
+        match result {
+            Ok(Dispatched::Shutdown) => crate::common::trace::conn_closed(None),
+            Ok(Dispatched::Upgrade(_)) => (),
+            Err(ref e) => crate::common::trace::conn_closed(Some(e)),
+        }
+        Poll::Ready(result.or_else(|e| {
             // An error means we're shutting down either way.
             // We just try to give the error to the user,
             // and close the connection with an Ok. If we
@@ -151,10 +243,7 @@ where
     fn poll_loop(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
         // Limit the looping on this connection, in case it is ready far too
         // often, so that other futures don't starve.
-        //
-        // 16 was chosen arbitrarily, as that is number of pipelined requests
-        // benchmarks often use. Perhaps it should be a config option instead.
-        for _ in 0..16 {
+        for _ in 0..self.poll_budget {
             let _ = self.poll_read(cx)?;
             let _ = self.poll_write(cx)?;
             let _ = self.poll_flush(cx)?;
@@ -183,7 +272,17 @@ where
             if self.is_closing {
                 return Poll::Ready(Ok(()));
             } else if self.conn.can_read_head() {
-                ready!(self.poll_read_head(cx))?;
+                match self.poll_read_head(cx) {
+                    Poll::Ready(result) => result?,
+                    Poll::Pending => {
+                        if self.close_if_unused && self.conn.has_initial_read_write_state() {
+                            trace!("closing connection without any request");
+                            self.close();
+                            continue;
+                        }
+                        return Poll::Pending;
+                    }
+                }
             } else if let Some(mut body) = self.body_tx.take() {
                 if self.conn.can_read_body() {
                     match body.poll_ready(cx) {
@@ -196,31 +295,47 @@ where
                             // user doesn't care about the body
                             // so we should stop reading
                             trace!("body receiver dropped before eof, draining or closing");
-                            self.conn.poll_drain_or_close_read(cx);
+                            if self.conn.poll_drain_or_close_read(cx).is_pending() {
+                                self.body_tx = Some(body);
+                                return Poll::Pending;
+                            }
                             continue;
                         }
                     }
                     match self.conn.poll_read_body(cx) {
-                        Poll::Ready(Some(Ok(chunk))) => match body.try_send_data(chunk) {
-                            Ok(()) => {
-                                self.body_tx = Some(body);
-                            }
-                            Err(_canceled) => {
-                                if self.conn.can_read_body() {
-                                    trace!("body receiver dropped before eof, closing");
-                                    self.conn.close_read();
+                        Poll::Ready(Some(Ok(chunk))) => {
+                            self.dispatch.on_read_body(chunk.len());
+                            crate::common::trace::body_chunk("read", chunk.len());
+                            match body.try_send_data(chunk) {
+                                Ok(()) => {
+                                    // If that was the last chunk, drop the
+                                    // sender now, since the response may
+                                    // already be written, in which case the
+                                    // next loop reads the next message head.
+                                    if self.conn.can_read_body() {
+                                        self.body_tx = Some(body);
+                                    } else {
+                                        self.dispatch.on_read_body_end();
+                                    }
+                                }
+                                Err(_canceled) => {
+                                    if self.conn.can_read_body() {
+                                        trace!("body receiver dropped before eof, closing");
+                                        self.conn.close_read();
+                                    }
                                 }
                             }
-                        },
+                        }
                         Poll::Ready(None) => {
                             // just drop, the body will close automatically
+                            self.dispatch.on_read_body_end();
                         }
                         Poll::Pending => {
                             self.body_tx = Some(body);
                             return Poll::Pending;
                         }
                         Poll::Ready(Some(Err(e))) => {
-                            body.send_error(crate::Error::new_body(e));
+                            body.send_error(e);
                         }
                     }
                 } else {
@@ -244,12 +359,22 @@ where
         }
 
         // dispatch is ready for a message, try to read one
-        match ready!(self.conn.poll_read_head(cx)) {
+        let head = self.conn.poll_read_head(cx);
+        if matches!(head, Poll::Ready(Some(Ok(_))))
+            || (head.is_pending() && self.conn.has_read_buffered())
+        {
+            self.dispatch.on_read_head_start();
+        }
+        match ready!(head) {
             Some(Ok((mut head, body_len, wants))) => {
                 let body = match body_len {
                     DecodedLength::ZERO => Recv::empty(),
                     other => {
-                        let (tx, rx) = Recv::new_channel(other, wants.contains(Wants::EXPECT));
+                        let (tx, rx) = Recv::new_channel_with_depth(
+                            other,
+                            wants.contains(Wants::EXPECT),
+                            self.conn.body_channel_depth(),
+                        );
                         self.body_tx = Some(tx);
                         rx
                     }
@@ -268,6 +393,13 @@ where
             }
             Some(Err(err)) => {
                 debug!("read_head error: {}", err);
+                // If a response was written for the error, such as a 413,
+                // reading is closed already. Let the response flush, and
+                // shut down with the error after.
+                #[cfg(feature = "server")]
+                if self.conn.has_error_response() {
+                    return Poll::Ready(Ok(()));
+                }
                 self.dispatch.recv_msg(Err(err))?;
                 // if here, the dispatcher gave the user the error
                 // somewhere else. we still need to shutdown, but
@@ -299,19 +431,26 @@ where
                 if let Some(msg) = ready!(Pin::new(&mut self.dispatch).poll_msg(cx)) {
                     let (head, body) = msg.map_err(crate::Error::new_user_service)?;
 
-                    let body_type = if body.is_end_stream() {
-                        self.body_rx.set(None);
-                        None
-                    } else {
-                        let btype = body
-                            .size_hint()
-                            .exact()
-                            .map(BodyLength::Known)
-                            .or_else(|| Some(BodyLength::Unknown));
-                        self.body_rx.set(Some(body));
-                        btype
+                    let body_type = match body {
+                        Some(body) if !body.is_end_stream() => {
+                            let btype = body
+                                .size_hint()
+                                .exact()
+                                .map(BodyLength::Known)
+                                .or_else(|| Some(BodyLength::Unknown));
+                            self.body_rx.set(Some(body));
+                            btype
+                        }
+                        _ => {
+                            self.body_rx.set(None);
+                            None
+                        }
                     };
+                    let has_body = body_type.is_some();
                     self.conn.write_head(head, body_type);
+                    if !has_body {
+                        self.dispatch.on_write_end();
+                    }
                 } else {
                     self.close();
                     return Poll::Ready(Ok(()));
@@ -330,16 +469,19 @@ where
                             body.is_end_stream(),
                         );
                         *clear_body = true;
+                        self.dispatch.on_write_end();
                         continue;
                     }
 
-                    let item = ready!(body.as_mut().poll_data(cx));
+                    let item = ready!(self.dispatch.time_body_poll(|| body.as_mut().poll_data(cx)));
                     if let Some(item) = item {
                         let chunk = item.map_err(|e| {
                             *clear_body = true;
                             crate::Error::new_user_body(e)
                         })?;
                         let eos = body.is_end_stream();
+                        self.dispatch.on_write_body(chunk.remaining());
+                        crate::common::trace::body_chunk("write", chunk.remaining());
                         if eos {
                             *clear_body = true;
                             if chunk.remaining() == 0 {
@@ -348,16 +490,23 @@ where
                             } else {
                                 self.conn.write_body_and_end(chunk);
                             }
+                            self.dispatch.on_write_end();
                         } else {
                             if chunk.remaining() == 0 {
                                 trace!("discarding empty chunk");
-                                continue;
+                            } else {
+                                self.conn.write_body(chunk);
+                            }
+                            if self.conn.wants_flush() {
+                                trace!("body asked for a flush");
+                                ready!(self.conn.poll_flush(cx))
+                                    .map_err(crate::Error::new_body_write)?;
                             }
-                            self.conn.write_body(chunk);
                         }
                     } else {
                         *clear_body = true;
                         self.conn.end_body()?;
+                        self.dispatch.on_write_end();
                     }
                 } else {
                     return Poll::Pending;
@@ -405,7 +554,7 @@ where
             RecvItem = MessageHead<T::Incoming>,
         > + Unpin,
     D::PollError: Into<Box<dyn StdError + Send + Sync>>,
-    I: AsyncRead + AsyncWrite + Unpin,
+    I: Read + Write + Unpin,
     T: Http1Transaction + Unpin,
     Bs: Body + 'static,
     Bs::Error: Into<Box<dyn StdError + Send + Sync>>,
@@ -453,9 +602,33 @@ cfg_server! {
             Server {
                 in_flight: Box::pin(None),
                 service,
+                extensions: ConnExtensions::default(),
+                active_requests: 0,
+                on_request_complete: None,
+                records: VecDeque::new(),
+                disconnects: VecDeque::new(),
+                service_timeout: ServiceTimeout::default(),
+                timer: Time::default(),
+                timeout_sleep: None,
+                timings: None,
+                first_byte: None,
+                reading: None,
             }
         }
 
+        pub(crate) fn set_timings(&mut self, timings: ConnTimings) {
+            self.timings = Some(timings);
+        }
+
+        pub(crate) fn set_on_request_complete(&mut self, callback: OnRequestComplete) {
+            self.on_request_complete = Some(callback);
+        }
+
+        pub(crate) fn set_service_timeout(&mut self, timeout: ServiceTimeout, timer: Time) {
+            self.service_timeout = timeout;
+            self.timer = timer;
+        }
+
         pub(crate) fn into_service(self) -> S {
             self.service
         }
@@ -464,6 +637,33 @@ cfg_server! {
     // Service is never pinned
     impl<S: HttpService<B>, B> Unpin for Server<S, B> {}
 
+    impl<S, Bs, I, T> Dispatcher<Server<S, Recv>, Bs, I, T>
+    where
+        S: HttpService<Recv>,
+        Bs: Body,
+    {
+        pub(crate) fn conn_extensions_mut(&mut self) -> &mut ConnExtensions {
+            &mut self.dispatch.extensions
+        }
+
+        pub(crate) fn in_flight(&self) -> usize {
+            self.dispatch.active_requests
+        }
+
+        /// The connection was served after a TLS handshake, from `started`
+        /// until `done`.
+        pub(crate) fn set_tls_handshake(
+            &mut self,
+            started: std::time::Instant,
+            done: std::time::Instant,
+        ) {
+            if let Some(ref mut timings) = self.dispatch.timings {
+                timings.connected = started;
+                timings.tls_handshaked = Some(done);
+            }
+        }
+    }
+
     impl<S, Bs> Dispatch for Server<S, Recv>
     where
         S: HttpService<Recv, ResBody = Bs>,
@@ -478,24 +678,55 @@ cfg_server! {
         fn poll_msg(
             mut self: Pin<&mut Self>,
             cx: &mut task::Context<'_>,
-        ) -> Poll<Option<Result<(Self::PollItem, Self::PollBody), Self::PollError>>> {
-            let mut this = self.as_mut();
+        ) -> PollMsg<Self> {
+            let this = &mut *self;
             let ret = if let Some(ref mut fut) = this.in_flight.as_mut().as_pin_mut() {
-                let resp = ready!(fut.as_mut().poll(cx)?);
-                let (parts, body) = resp.into_parts();
-                let head = MessageHead {
-                    version: parts.version,
-                    subject: parts.status,
-                    headers: parts.headers,
-                    extensions: parts.extensions,
-                };
-                Poll::Ready(Some(Ok((head, body))))
+                let res = time_poll(this.records.back(), PollSource::Service, || {
+                    fut.as_mut().poll(cx)
+                });
+                match res {
+                    Poll::Ready(resp) => {
+                        let (parts, body) = resp?.into_parts();
+                        if let Some(record) = this.records.back_mut() {
+                            record.response(parts.status);
+                        }
+                        let head = MessageHead {
+                            version: parts.version,
+                            subject: parts.status,
+                            headers: parts.headers,
+                            extensions: parts.extensions,
+                        };
+                        Poll::Ready(Some(Ok((head, Some(body)))))
+                    }
+                    Poll::Pending => {
+                        match this.timeout_sleep {
+                            Some(ref mut sleep) => ready!(Pin::new(sleep).poll(cx)),
+                            None => return Poll::Pending,
+                        }
+                        debug!("service timed out");
+                        if this.service_timeout.reset {
+                            // Closes the connection without a response.
+                            Poll::Ready(None)
+                        } else {
+                            let status = this.service_timeout.status;
+                            if let Some(record) = this.records.back_mut() {
+                                record.response(status);
+                            }
+                            let head = MessageHead {
+                                subject: this.service_timeout.status,
+                                ..Default::default()
+                            };
+                            Poll::Ready(Some(Ok((head, None))))
+                        }
+                    }
+                }
             } else {
                 unreachable!("poll_msg shouldn't be called if no inflight");
             };
 
             // Since in_flight finished, remove it
             this.in_flight.set(None);
+            this.timeout_sleep = None;
             ret
         }
 
@@ -507,8 +738,32 @@ cfg_server! {
             *req.headers_mut() = msg.headers;
             *req.version_mut() = msg.version;
             *req.extensions_mut() = msg.extensions;
+            self.extensions.apply(req.extensions_mut());
+            if let Some(notifier) = self.extensions.peer_disconnect(req.extensions_mut()) {
+                self.disconnects.push_back(notifier);
+            }
+            if let Some(ref conn) = self.timings {
+                let now = conn.time.now();
+                let timings = Timings::new(conn);
+                timings.mark(Mark::FirstByte, self.first_byte.take().unwrap_or(now));
+                timings.mark(Mark::HeadersComplete, now);
+                if req.body().is_end_stream() {
+                    timings.mark(Mark::BodyComplete, now);
+                } else {
+                    self.reading = Some(timings.clone());
+                }
+                req.extensions_mut().insert(timings);
+            }
+            self.active_requests += 1;
+            if let Some(ref callback) = self.on_request_complete {
+                let record = callback.record(&mut req);
+                self.records.push_back(record);
+            }
             let fut = self.service.call(req);
             self.in_flight.set(Some(fut));
+            if let Some(duration) = self.service_timeout.duration {
+                self.timeout_sleep = Some(self.timer.sleep(duration));
+            }
             Ok(())
         }
 
@@ -523,6 +778,44 @@ cfg_server! {
         fn should_poll(&self) -> bool {
             self.in_flight.is_some()
         }
+
+        fn on_read_body(&mut self, len: usize) {
+            if let Some(record) = self.records.back() {
+                record.read(len);
+            }
+        }
+
+        fn on_write_body(&mut self, len: usize) {
+            if let Some(record) = self.records.front_mut() {
+                record.wrote(len as u64);
+            }
+        }
+
+        fn time_body_poll<T>(&self, poll: impl FnOnce() -> T) -> T {
+            time_poll(self.records.front(), PollSource::Body, poll)
+        }
+
+        fn on_write_end(&mut self) {
+            self.active_requests = self.active_requests.saturating_sub(1);
+            if let Some(record) = self.records.pop_front() {
+                record.complete();
+            }
+            if let Some(notifier) = self.disconnects.pop_front() {
+                notifier.complete();
+            }
+        }
+
+        fn on_read_head_start(&mut self) {
+            if let Some(ref conn) = self.timings {
+                self.first_byte.get_or_insert_with(|| conn.time.now());
+            }
+        }
+
+        fn on_read_body_end(&mut self) {
+            if let (Some(conn), Some(timings)) = (&self.timings, self.reading.take()) {
+                timings.mark(Mark::BodyComplete, conn.time.now());
+            }
+        }
     }
 }
 
@@ -535,6 +828,18 @@ cfg_client! {
                 callback: None,
                 rx,
                 rx_closed: false,
+                timings: None,
+                current: None,
+            }
+        }
+
+        pub(crate) fn set_timings(&mut self, timings: ConnTimings) {
+            self.timings = Some(timings);
+        }
+
+        fn mark(&self, mark: Mark) {
+            if let (Some(conn), Some(timings)) = (&self.timings, &self.current) {
+                timings.mark(mark, conn.time.now());
             }
         }
     }
@@ -551,7 +856,7 @@ cfg_client! {
         fn poll_msg(
             mut self: Pin<&mut Self>,
             cx: &mut task::Context<'_>,
-        ) -> Poll<Option<Result<(Self::PollItem, Self::PollBody), crate::common::Never>>> {
+        ) -> PollMsg<Self> {
             let mut this = self.as_mut();
             debug_assert!(!this.rx_closed);
             match this.rx.poll_recv(cx) {
@@ -571,7 +876,8 @@ cfg_client! {
                                 extensions: parts.extensions,
                             };
                             this.callback = Some(cb);
-                            Poll::Ready(Some(Ok((head, body))))
+                            this.current = this.timings.as_ref().map(Timings::new);
+                            Poll::Ready(Some(Ok((head, Some(body)))))
                         }
                     }
                 }
@@ -589,7 +895,15 @@ cfg_client! {
             match msg {
                 Ok((msg, body)) => {
                     if let Some(cb) = self.callback.take() {
-                        let res = msg.into_response(body);
+                        let body_done = body.is_end_stream();
+                        let mut res = msg.into_response(body);
+                        self.mark(Mark::HeadersComplete);
+                        if body_done {
+                            self.mark(Mark::BodyComplete);
+                        }
+                        if let Some(ref timings) = self.current {
+                            res.extensions_mut().insert(timings.clone());
+                        }
                         cb.send(Ok(res));
                         Ok(())
                     } else {
@@ -637,12 +951,25 @@ cfg_client! {
         fn should_poll(&self) -> bool {
             self.callback.is_none()
         }
+
+        fn on_write_end(&mut self) {
+            self.mark(Mark::RequestWritten);
+        }
+
+        fn on_read_head_start(&mut self) {
+            self.mark(Mark::FirstByte);
+        }
+
+        fn on_read_body_end(&mut self) {
+            self.mark(Mark::BodyComplete);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::io::Compat;
     use crate::proto::h1::ClientTransaction;
     use std::time::Duration;
 
@@ -656,7 +983,7 @@ mod tests {
             // Block at 0 for now, but we will release this response before
             // the request is ready to write later...
             let (mut tx, rx) = crate::client::dispatch::channel();
-            let conn = Conn::<_, bytes::Bytes, ClientTransaction>::new(io);
+            let conn = Conn::<_, bytes::Bytes, ClientTransaction>::new(Compat::new(io));
             let mut dispatcher = Dispatcher::new(Client::new(rx), conn);
 
             // First poll is needed to allow tx to send...
@@ -674,7 +1001,7 @@ mod tests {
             let err = tokio_test::assert_ready_ok!(Pin::new(&mut res_rx).poll(cx))
                 .expect_err("callback should send error");
 
-            match (err.0.kind(), err.1) {
+            match (err.0.inner_kind(), err.1) {
                 (&crate::error::Kind::Canceled, Some(_)) => (),
                 other => panic!("expected Canceled, got {:?}", other),
             }
@@ -693,7 +1020,7 @@ mod tests {
             .build_with_handle();
 
         let (mut tx, rx) = crate::client::dispatch::channel();
-        let mut conn = Conn::<_, bytes::Bytes, ClientTransaction>::new(io);
+        let mut conn = Conn::<_, bytes::Bytes, ClientTransaction>::new(Compat::new(io));
         conn.set_write_strategy_queue();
 
         let dispatcher = Dispatcher::new(Client::new(rx), conn);
@@ -724,7 +1051,7 @@ mod tests {
             .build();
 
         let (mut tx, rx) = crate::client::dispatch::channel();
-        let conn = Conn::<_, bytes::Bytes, ClientTransaction>::new(io);
+        let conn = Conn::<_, bytes::Bytes, ClientTransaction>::new(Compat::new(io));
         let mut dispatcher = tokio_test::task::spawn(Dispatcher::new(Client::new(rx), conn));
 
         // First poll is needed to allow tx to send...
@@ -742,4 +1069,161 @@ mod tests {
         // If it is, it will trigger an assertion.
         assert!(dispatcher.poll().is_pending());
     }
+
+    #[cfg(feature = "server")]
+    mod flush {
+        use super::*;
+        use crate::ext::Flusher;
+        use crate::proto::h1::ServerTransaction;
+        use crate::rt::ReadBufCursor;
+        use std::io;
+        use std::sync::{Arc, Mutex};
+
+        // Reads a request, then stays pending, recording each write.
+        struct RecordIo {
+            read: &'static [u8],
+            writes: Arc<Mutex<Vec<Vec<u8>>>>,
+        }
+
+        impl crate::rt::Read for RecordIo {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _cx: &mut task::Context<'_>,
+                mut buf: ReadBufCursor<'_>,
+            ) -> Poll<io::Result<()>> {
+                if self.read.is_empty() {
+                    return Poll::Pending;
+                }
+                buf.put_slice(self.read);
+                self.read = &[];
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        impl crate::rt::Write for RecordIo {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                _cx: &mut task::Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                self.writes.lock().unwrap().push(buf.to_vec());
+                Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(
+                self: Pin<&mut Self>,
+                _cx: &mut task::Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(
+                self: Pin<&mut Self>,
+                _cx: &mut task::Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        // Yields its chunks without ever being pending, calling the
+        // `Flusher` with the first, if any.
+        struct ReadyBody {
+            chunks: Vec<&'static str>,
+            flusher: Option<Flusher>,
+            writes: Arc<Mutex<Vec<Vec<u8>>>>,
+            // What the IO had received each time the body was polled.
+            polled: Arc<Mutex<Vec<Vec<u8>>>>,
+        }
+
+        impl Body for ReadyBody {
+            type Data = Bytes;
+            type Error = std::convert::Infallible;
+
+            fn poll_data(
+                mut self: Pin<&mut Self>,
+                _cx: &mut task::Context<'_>,
+            ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+                let written = self.writes.lock().unwrap().concat();
+                self.polled.lock().unwrap().push(written);
+                if self.chunks.is_empty() {
+                    return Poll::Ready(None);
+                }
+                let chunk = self.chunks.remove(0);
+                if let Some(flusher) = self.flusher.take() {
+                    flusher.flush();
+                }
+                Poll::Ready(Some(Ok(Bytes::from(chunk))))
+            }
+
+            fn poll_trailers(
+                self: Pin<&mut Self>,
+                _cx: &mut task::Context<'_>,
+            ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+                Poll::Ready(Ok(None))
+            }
+        }
+
+        // Serves a single response with a body of `chunks`, returning the
+        // writes the IO received, and what it had received each time the
+        // body was polled.
+        fn serve(
+            chunks: Vec<&'static str>,
+            flusher: Option<Flusher>,
+        ) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+            let writes = Arc::new(Mutex::new(Vec::new()));
+            let polled = Arc::new(Mutex::new(Vec::new()));
+            let io = RecordIo {
+                read: b"GET / HTTP/1.1\r\n\r\n",
+                writes: writes.clone(),
+            };
+            let mut body = Some(ReadyBody {
+                chunks,
+                flusher: flusher.clone(),
+                writes: writes.clone(),
+                polled: polled.clone(),
+            });
+            let service = crate::service::service_fn(move |_| {
+                let mut res = crate::Response::new(body.take().expect("one request"));
+                if let Some(ref flusher) = flusher {
+                    res.extensions_mut().insert(flusher.clone());
+                }
+                async move { Ok::<_, std::convert::Infallible>(res) }
+            });
+            let conn = Conn::<_, Bytes, ServerTransaction>::new(io);
+            let mut dispatcher =
+                tokio_test::task::spawn(Dispatcher::new(Server::new(service), conn));
+
+            assert!(dispatcher.poll().is_pending());
+            let writes = writes.lock().unwrap().clone();
+            let polled = polled.lock().unwrap().clone();
+            (writes, polled)
+        }
+
+        #[test]
+        fn flusher_writes_chunk_before_next() {
+            let _ = pretty_env_logger::try_init();
+
+            let (writes, polled) = serve(vec!["event 1", "event 2"], Some(Flusher::new()));
+
+            // The first chunk reached the IO before the second was polled.
+            assert!(polled[0].is_empty());
+            assert!(polled[1].ends_with(b"7\r\nevent 1\r\n"), "{:?}", polled[1]);
+            // Without another flush asked for, the rest is written together.
+            assert_eq!(writes.len(), 2, "{:?}", writes);
+            assert_eq!(writes[1], b"7\r\nevent 2\r\n0\r\n\r\n");
+        }
+
+        #[test]
+        fn ordinary_body_isnt_flushed_per_chunk() {
+            let _ = pretty_env_logger::try_init();
+
+            let (writes, polled) = serve(vec!["event 1", "event 2", "event 3"], None);
+
+            // Nothing was written while the body was yielding chunks, and
+            // then the head and every chunk were written together.
+            assert!(polled.iter().all(Vec::is_empty));
+            assert_eq!(writes.len(), 1, "{:?}", writes);
+            assert!(writes[0].ends_with(b"7\r\nevent 3\r\n0\r\n\r\n"));
+        }
+    }
 }
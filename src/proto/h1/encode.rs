@@ -1,3 +1,4 @@
+use std::cmp;
 use std::fmt;
 use std::io::IoSlice;
 
@@ -14,6 +15,8 @@ type StaticBuf = &'static [u8];
 pub(crate) struct Encoder {
     kind: Kind,
     is_last: bool,
+    /// The largest chunk of a chunked body, larger ones being split.
+    max_chunk_size: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -45,14 +48,30 @@ enum BufKind<B> {
     Exact(B),
     Limited(Take<B>),
     Chunked(Chain<Chain<ChunkSize, B>, StaticBuf>),
+    SplitChunked(SplitChunkedBuf<B>),
     ChunkedEnd(StaticBuf),
 }
 
+/// A payload larger than the maximum chunk size, as several chunks.
+///
+/// Only the size line of the current chunk is kept. The next one is made
+/// once the current chunk has been advanced past, and the payload is still
+/// never copied.
+#[derive(Debug)]
+struct SplitChunkedBuf<B> {
+    max: usize,
+    size: ChunkSize,
+    payload: Take<B>,
+    crlf: StaticBuf,
+    end: StaticBuf,
+}
+
 impl Encoder {
     fn new(kind: Kind) -> Encoder {
         Encoder {
             kind,
             is_last: false,
+            max_chunk_size: None,
         }
     }
     pub(crate) fn chunked() -> Encoder {
@@ -82,6 +101,13 @@ impl Encoder {
         self.is_last
     }
 
+    #[cfg(feature = "server")]
+    pub(crate) fn set_max_chunk_size(mut self, max: Option<usize>) -> Self {
+        debug_assert_ne!(max, Some(0));
+        self.max_chunk_size = max;
+        self
+    }
+
     pub(crate) fn is_close_delimited(&self) -> bool {
         match self.kind {
             #[cfg(feature = "server")]
@@ -112,10 +138,12 @@ impl Encoder {
         let kind = match self.kind {
             Kind::Chunked => {
                 trace!("encoding chunked {}B", len);
-                let buf = ChunkSize::new(len)
-                    .chain(msg)
-                    .chain(b"\r\n" as &'static [u8]);
-                BufKind::Chunked(buf)
+                match self.max_chunk_size {
+                    Some(max) if len > max => {
+                        BufKind::SplitChunked(SplitChunkedBuf::new(msg, max, b""))
+                    }
+                    _ => BufKind::Chunked(chunk_of(msg, b"\r\n")),
+                }
             }
             Kind::Length(ref mut remaining) => {
                 trace!("sized write, len = {}", len);
@@ -147,10 +175,13 @@ impl Encoder {
         match self.kind {
             Kind::Chunked => {
                 trace!("encoding chunked {}B", len);
-                let buf = ChunkSize::new(len)
-                    .chain(msg)
-                    .chain(b"\r\n0\r\n\r\n" as &'static [u8]);
-                dst.buffer(buf);
+                let kind = match self.max_chunk_size {
+                    Some(max) if len > max => {
+                        BufKind::SplitChunked(SplitChunkedBuf::new(msg, max, b"0\r\n\r\n"))
+                    }
+                    _ => BufKind::Chunked(chunk_of(msg, b"\r\n0\r\n\r\n")),
+                };
+                dst.buffer(EncodedBuf { kind });
                 !self.is_last
             }
             Kind::Length(remaining) => {
@@ -192,6 +223,7 @@ where
             BufKind::Exact(ref b) => b.remaining(),
             BufKind::Limited(ref b) => b.remaining(),
             BufKind::Chunked(ref b) => b.remaining(),
+            BufKind::SplitChunked(ref b) => b.remaining(),
             BufKind::ChunkedEnd(ref b) => b.remaining(),
         }
     }
@@ -202,6 +234,7 @@ where
             BufKind::Exact(ref b) => b.chunk(),
             BufKind::Limited(ref b) => b.chunk(),
             BufKind::Chunked(ref b) => b.chunk(),
+            BufKind::SplitChunked(ref b) => b.chunk(),
             BufKind::ChunkedEnd(ref b) => b.chunk(),
         }
     }
@@ -212,6 +245,7 @@ where
             BufKind::Exact(ref mut b) => b.advance(cnt),
             BufKind::Limited(ref mut b) => b.advance(cnt),
             BufKind::Chunked(ref mut b) => b.advance(cnt),
+            BufKind::SplitChunked(ref mut b) => b.advance(cnt),
             BufKind::ChunkedEnd(ref mut b) => b.advance(cnt),
         }
     }
@@ -222,11 +256,126 @@ where
             BufKind::Exact(ref b) => b.chunks_vectored(dst),
             BufKind::Limited(ref b) => b.chunks_vectored(dst),
             BufKind::Chunked(ref b) => b.chunks_vectored(dst),
+            BufKind::SplitChunked(ref b) => b.chunks_vectored(dst),
             BufKind::ChunkedEnd(ref b) => b.chunks_vectored(dst),
         }
     }
 }
 
+/// A chunk of a chunked body: its size line, the payload, and the CRLF
+/// ending it, or the last chunk too.
+///
+/// The payload is never copied. Each part is its own `IoSlice`, so they can
+/// go out in a single vectored write.
+fn chunk_of<B: Buf>(payload: B, end: StaticBuf) -> Chain<Chain<ChunkSize, B>, StaticBuf> {
+    ChunkSize::new(payload.remaining())
+        .chain(payload)
+        .chain(end)
+}
+
+impl<B: Buf> SplitChunkedBuf<B> {
+    fn new(payload: B, max: usize, end: StaticBuf) -> SplitChunkedBuf<B> {
+        let len = cmp::min(max, payload.remaining());
+        SplitChunkedBuf {
+            max,
+            size: ChunkSize::new(len),
+            payload: payload.take(len),
+            crlf: b"\r\n",
+            end,
+        }
+    }
+
+    /// The payload bytes after the current chunk.
+    fn rest(&self) -> usize {
+        self.payload.get_ref().remaining() - self.payload.remaining()
+    }
+
+    /// Starts the next chunk, if the current one has been advanced past.
+    fn next_chunk(&mut self) {
+        if self.size.has_remaining() || self.payload.has_remaining() || self.crlf.has_remaining() {
+            return;
+        }
+        let len = cmp::min(self.max, self.rest());
+        if len > 0 {
+            self.size = ChunkSize::new(len);
+            self.payload.set_limit(len);
+            self.crlf = b"\r\n";
+        }
+    }
+}
+
+impl<B: Buf> Buf for SplitChunkedBuf<B> {
+    #[inline]
+    fn remaining(&self) -> usize {
+        // Each chunk after the current one adds its size line and CRLF.
+        fn framing(len: usize) -> usize {
+            ChunkSize::new(len).remaining() + 2
+        }
+
+        let rest = self.rest();
+        let mut n = self.size.remaining()
+            + self.payload.remaining()
+            + self.crlf.remaining()
+            + rest
+            + self.end.remaining();
+        n += rest / self.max * framing(self.max);
+        if rest % self.max > 0 {
+            n += framing(rest % self.max);
+        }
+        n
+    }
+
+    #[inline]
+    fn chunk(&self) -> &[u8] {
+        if self.size.has_remaining() {
+            self.size.chunk()
+        } else if self.payload.has_remaining() {
+            self.payload.chunk()
+        } else if self.crlf.has_remaining() {
+            self.crlf.chunk()
+        } else {
+            self.end.chunk()
+        }
+    }
+
+    #[inline]
+    fn advance(&mut self, mut cnt: usize) {
+        loop {
+            let size = cmp::min(cnt, self.size.remaining());
+            self.size.advance(size);
+            cnt -= size;
+
+            let payload = cmp::min(cnt, self.payload.remaining());
+            self.payload.advance(payload);
+            cnt -= payload;
+
+            let crlf = cmp::min(cnt, self.crlf.remaining());
+            self.crlf.advance(crlf);
+            cnt -= crlf;
+
+            self.next_chunk();
+            if cnt == 0 {
+                return;
+            }
+            if !self.size.has_remaining() {
+                self.end.advance(cnt);
+                return;
+            }
+        }
+    }
+
+    #[inline]
+    fn chunks_vectored<'t>(&'t self, dst: &mut [IoSlice<'t>]) -> usize {
+        let mut n = self.size.chunks_vectored(dst);
+        n += self.payload.chunks_vectored(&mut dst[n..]);
+        n += self.crlf.chunks_vectored(&mut dst[n..]);
+        if self.rest() == 0 {
+            n += self.end.chunks_vectored(&mut dst[n..]);
+        }
+        n
+    }
+}
+
 #[cfg(target_pointer_width = "32")]
 const USIZE_BYTES: usize = 4;
 
@@ -310,14 +459,6 @@ impl<B: Buf> From<Take<B>> for EncodedBuf<B> {
     }
 }
 
-impl<B: Buf> From<Chain<Chain<ChunkSize, B>, StaticBuf>> for EncodedBuf<B> {
-    fn from(buf: Chain<Chain<ChunkSize, B>, StaticBuf>) -> Self {
-        EncodedBuf {
-            kind: BufKind::Chunked(buf),
-        }
-    }
-}
-
 impl fmt::Display for NotEof {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "early end, expected {} more bytes", self.0)
@@ -328,7 +469,7 @@ impl std::error::Error for NotEof {}
 
 #[cfg(test)]
 mod tests {
-    use bytes::BufMut;
+    use bytes::{Buf, BufMut};
 
     use super::super::io::Cursor;
     use super::Encoder;
@@ -358,6 +499,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn chunked_vectored() {
+        use std::io::IoSlice;
+
+        use bytes::Buf;
+
+        let mut encoder = Encoder::chunked();
+        let mut buf = encoder.encode(b"foo bar".as_ref());
+
+        // The size line, payload and CRLF, without copying the payload.
+        let mut iovs = [IoSlice::new(&[]); 4];
+        assert_eq!(buf.chunks_vectored(&mut iovs), 3);
+        assert_eq!(&*iovs[0], b"7\r\n");
+        assert_eq!(&*iovs[1], b"foo bar");
+        assert_eq!(&*iovs[2], b"\r\n");
+
+        // A partial write resumes inside the payload.
+        buf.advance(5);
+        let mut iovs = [IoSlice::new(&[]); 4];
+        assert_eq!(buf.chunks_vectored(&mut iovs), 2);
+        assert_eq!(&*iovs[0], b"o bar");
+        assert_eq!(&*iovs[1], b"\r\n");
+
+        buf.advance(6);
+        assert_eq!(buf.remaining(), 1);
+        assert_eq!(buf.chunk(), b"\n");
+    }
+
+    #[test]
+    fn chunked_max_chunk_size() {
+        let mut encoder = Encoder::chunked().set_max_chunk_size(Some(4));
+        let mut dst = Vec::new();
+
+        let buf = encoder.encode(b"foo bar baz".as_ref());
+        assert_eq!(buf.remaining(), 26);
+        dst.put(buf);
+        assert_eq!(dst, b"4\r\nfoo \r\n4\r\nbar \r\n3\r\nbaz\r\n");
+
+        // Chunks no larger than the maximum are written as they are.
+        dst.put(encoder.encode(b"quux".as_ref()));
+        assert!(dst.ends_with(b"baz\r\n4\r\nquux\r\n"));
+    }
+
     #[test]
     fn length() {
         let max_len = 8;
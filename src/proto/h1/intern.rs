@@ -0,0 +1,111 @@
+//! Static header names and values for the most common headers received.
+//!
+//! `HeaderName::from_bytes` already maps the standard names to constants,
+//! but allocates for any other name, and every value parsed otherwise holds
+//! a reference into the read buffer. The names and values below are seen in
+//! most requests and responses, so they are matched against these tables
+//! first and shared instead.
+
+use http::header::{HeaderName, HeaderValue};
+
+/// Common names that aren't among the `http` crate's standard headers.
+static NAMES: &[&str] = &[
+    "keep-alive",
+    "proxy-connection",
+    "x-forwarded-for",
+    "x-forwarded-host",
+    "x-forwarded-proto",
+    "x-real-ip",
+    "x-request-id",
+    "x-requested-with",
+    "sec-fetch-dest",
+    "sec-fetch-mode",
+    "sec-fetch-site",
+    "sec-fetch-user",
+    "sec-ch-ua",
+    "sec-ch-ua-mobile",
+    "sec-ch-ua-platform",
+    "upgrade-insecure-requests",
+];
+
+/// Common values, matched exactly since values are case sensitive.
+static VALUES: &[&str] = &[
+    "0",
+    "1",
+    "close",
+    "keep-alive",
+    "Keep-Alive",
+    "upgrade",
+    "Upgrade",
+    "chunked",
+    "gzip",
+    "deflate",
+    "br",
+    "identity",
+    "gzip, deflate",
+    "gzip, deflate, br",
+    "*/*",
+    "no-cache",
+    "max-age=0",
+    "websocket",
+    "text/plain",
+    "text/html",
+    "application/json",
+    "application/octet-stream",
+    "application/x-www-form-urlencoded",
+    "http",
+    "https",
+];
+
+/// A static `HeaderName` for `bytes`, in any case, if it is a common one.
+pub(super) fn header_name(bytes: &[u8]) -> Option<HeaderName> {
+    NAMES
+        .iter()
+        .find(|name| name.len() == bytes.len() && name.as_bytes().eq_ignore_ascii_case(bytes))
+        .map(|name| HeaderName::from_static(name))
+}
+
+/// A static `HeaderValue` for `bytes`, if it is a common one.
+pub(super) fn header_value(bytes: &[u8]) -> Option<HeaderValue> {
+    VALUES
+        .iter()
+        .find(|value| value.as_bytes() == bytes)
+        .map(|value| HeaderValue::from_static(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_names_in_any_case() {
+        assert_eq!(
+            header_name(b"X-Forwarded-For").unwrap(),
+            HeaderName::from_static("x-forwarded-for")
+        );
+        assert_eq!(
+            header_name(b"keep-alive").unwrap(),
+            HeaderName::from_static("keep-alive")
+        );
+        assert!(header_name(b"x-forwarded-fo").is_none());
+        assert!(header_name(b"x-custom").is_none());
+    }
+
+    #[test]
+    fn interns_values_exactly() {
+        assert_eq!(header_value(b"gzip").unwrap(), "gzip");
+        assert_eq!(header_value(b"Keep-Alive").unwrap(), "Keep-Alive");
+        assert!(header_value(b"GZIP").is_none());
+        assert!(header_value(b"gzip ").is_none());
+    }
+
+    #[test]
+    fn tables_are_valid() {
+        for name in NAMES {
+            assert_eq!(HeaderName::from_static(name).as_str(), *name);
+        }
+        for value in VALUES {
+            assert_eq!(HeaderValue::from_static(value), *value);
+        }
+    }
+}
@@ -4,15 +4,20 @@ use std::fmt;
 use std::future::Future;
 use std::io::{self, IoSlice};
 use std::marker::Unpin;
-use std::mem::MaybeUninit;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Deref, DerefMut};
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tracing::{debug, trace};
 
 use super::{Http1Transaction, ParseContext, ParsedMessage};
+use crate::buffer::BufferPool;
+#[cfg(feature = "debug")]
+use crate::capture::ConnCapture;
 use crate::common::buf::BufList;
 use crate::common::{task, Pin, Poll};
+use crate::error::IncompleteStage;
+use crate::rt::{Read, ReadBuf, Write};
 
 /// The initial buffer size allocated before trying to read from IO.
 pub(crate) const INIT_BUFFER_SIZE: usize = 8192;
@@ -26,19 +31,25 @@ pub(crate) const MINIMUM_MAX_BUFFER_SIZE: usize = INIT_BUFFER_SIZE;
 pub(crate) const DEFAULT_MAX_BUFFER_SIZE: usize = 8192 + 4096 * 100;
 
 /// The maximum number of distinct `Buf`s to hold in a list before requiring
-/// a flush. Only affects when the buffer strategy is to queue buffers.
+/// a flush.
 ///
 /// Note that a flush can happen before reaching the maximum. This simply
 /// forces a flush if the queue gets this big.
 const MAX_BUF_LIST_BUFFERS: usize = 16;
 
+/// `Buf`s at least this long are queued even when the strategy is to
+/// flatten, since copying them costs more than writing them on their own.
+const MIN_QUEUED_LEN: usize = 16 * 1024;
+
 pub(crate) struct Buffered<T, B> {
     flush_pipeline: bool,
     io: T,
     read_blocked: bool,
-    read_buf: BytesMut,
+    read_buf: ReadBuffer,
     read_buf_strategy: ReadStrategy,
     write_buf: WriteBuf<B>,
+    #[cfg(feature = "debug")]
+    capture: Option<ConnCapture>,
 }
 
 impl<T, B> fmt::Debug for Buffered<T, B>
@@ -55,7 +66,7 @@ where
 
 impl<T, B> Buffered<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Read + Write + Unpin,
     B: Buf,
 {
     pub(crate) fn new(io: T) -> Buffered<T, B> {
@@ -69,10 +80,34 @@ where
             flush_pipeline: false,
             io,
             read_blocked: false,
-            read_buf: BytesMut::with_capacity(0),
+            read_buf: ReadBuffer {
+                bytes: BytesMut::with_capacity(0),
+                pool: None,
+            },
             read_buf_strategy: ReadStrategy::default(),
             write_buf,
+            #[cfg(feature = "debug")]
+            capture: None,
+        }
+    }
+
+    /// Take the buffers from `pool`, and give them back once done.
+    pub(crate) fn set_buffer_pool(&mut self, pool: BufferPool) {
+        // this should always be called only at construction time
+        debug_assert!(self.read_buf.is_empty() && !self.write_buf.headers.has_remaining());
+        if let Some(bytes) = pool.take_read() {
+            self.read_buf.bytes = bytes;
+        }
+        if let Some(bytes) = pool.take_write() {
+            self.write_buf.headers.bytes = bytes;
         }
+        self.read_buf.pool = Some(pool.clone());
+        self.write_buf.pool = Some(pool);
+    }
+
+    #[cfg(feature = "debug")]
+    pub(crate) fn set_capture(&mut self, capture: ConnCapture) {
+        self.capture = Some(capture);
     }
 
     #[cfg(feature = "server")]
@@ -181,8 +216,17 @@ where
                 &mut self.read_buf,
                 ParseContext {
                     cached_headers: parse_ctx.cached_headers,
+                    cached_extensions: parse_ctx.cached_extensions,
+                    header_capacity: parse_ctx.header_capacity,
+                    max_headers: parse_ctx.max_headers,
                     req_method: parse_ctx.req_method,
                     h1_parser_config: parse_ctx.h1_parser_config.clone(),
+                    replace_bare_cr: parse_ctx.replace_bare_cr,
+                    bare_cr_scanned: parse_ctx.bare_cr_scanned,
+                    allow_content_length_lists: parse_ctx.allow_content_length_lists,
+                    sanitize_request_target: parse_ctx.sanitize_request_target,
+                    transfer_coding_policy: parse_ctx.transfer_coding_policy,
+                    header_value_policy: parse_ctx.header_value_policy,
                     #[cfg(feature = "server")]
                     h1_header_read_timeout: parse_ctx.h1_header_read_timeout,
                     #[cfg(feature = "server")]
@@ -194,6 +238,7 @@ where
                     preserve_header_case: parse_ctx.preserve_header_case,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: parse_ctx.preserve_header_order,
+                    lazy_headers: parse_ctx.lazy_headers,
                     h09_responses: parse_ctx.h09_responses,
                     #[cfg(feature = "ffi")]
                     on_informational: parse_ctx.on_informational,
@@ -235,7 +280,13 @@ where
             }
             if ready!(self.poll_read_from_io(cx)).map_err(crate::Error::new_io)? == 0 {
                 trace!("parse eof");
-                return Poll::Ready(Err(crate::Error::new_incomplete()));
+                let stage = if self.read_buf.is_empty() {
+                    IncompleteStage::BeforeHead
+                } else {
+                    IncompleteStage::Head
+                };
+                // `Conn` knows whether the request was sent.
+                return Poll::Ready(Err(crate::Error::new_incomplete(stage, false)));
             }
         }
     }
@@ -253,10 +304,14 @@ where
         let dst = self.read_buf.chunk_mut();
         let dst = unsafe { &mut *(dst as *mut _ as *mut [MaybeUninit<u8>]) };
         let mut buf = ReadBuf::uninit(dst);
-        match Pin::new(&mut self.io).poll_read(cx, &mut buf) {
+        match Pin::new(&mut self.io).poll_read(cx, buf.unfilled()) {
             Poll::Ready(Ok(_)) => {
                 let n = buf.filled().len();
                 trace!("received {} bytes", n);
+                #[cfg(feature = "debug")]
+                if let Some(ref capture) = self.capture {
+                    capture.read(buf.filled());
+                }
                 unsafe {
                     // Safety: we just read that many bytes into the
                     // uninitialized part of the buffer, so this is okay.
@@ -275,7 +330,7 @@ where
     }
 
     pub(crate) fn into_inner(self) -> (T, Bytes) {
-        (self.io, self.read_buf.freeze())
+        (self.io, self.read_buf.into_bytes())
     }
 
     pub(crate) fn io_mut(&mut self) -> &mut T {
@@ -286,6 +341,28 @@ where
         self.read_blocked
     }
 
+    /// Flush, even if more pipelined messages are waiting to be read.
+    #[cfg(feature = "server")]
+    pub(crate) fn poll_flush_pipelined(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        let flush_pipeline = std::mem::replace(&mut self.flush_pipeline, false);
+        let ret = self.poll_flush(cx);
+        self.flush_pipeline = flush_pipeline;
+        ret
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn has_queued_writes(&self) -> bool {
+        self.write_buf.remaining() != 0
+    }
+
+    #[cfg(feature = "server")]
+    pub(crate) fn queued_writes_len(&self) -> usize {
+        self.write_buf.remaining()
+    }
+
     pub(crate) fn poll_flush(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
         if self.flush_pipeline && !self.read_buf.is_empty() {
             Poll::Ready(Ok(()))
@@ -293,7 +370,9 @@ where
             Pin::new(&mut self.io).poll_flush(cx)
         } else {
             if let WriteStrategy::Flatten = self.write_buf.strategy {
-                return self.poll_flush_flattened(cx);
+                if !self.write_buf.queue.has_remaining() {
+                    return self.poll_flush_flattened(cx);
+                }
             }
 
             const MAX_WRITEV_BUFS: usize = 64;
@@ -301,7 +380,17 @@ where
                 let n = {
                     let mut iovs = [IoSlice::new(&[]); MAX_WRITEV_BUFS];
                     let len = self.write_buf.chunks_vectored(&mut iovs);
-                    ready!(Pin::new(&mut self.io).poll_write_vectored(cx, &iovs[..len]))?
+                    let n = ready!(Pin::new(&mut self.io).poll_write_vectored(cx, &iovs[..len]))?;
+                    #[cfg(feature = "debug")]
+                    if let Some(ref capture) = self.capture {
+                        let mut rem = n;
+                        for iov in &iovs[..len] {
+                            let take = cmp::min(rem, iov.len());
+                            capture.wrote(&iov[..take]);
+                            rem -= take;
+                        }
+                    }
+                    n
                 };
                 // TODO(eliza): we have to do this manually because
                 // `poll_write_buf` doesn't exist in Tokio 0.3 yet...when
@@ -330,6 +419,10 @@ where
         loop {
             let n = ready!(Pin::new(&mut self.io).poll_write(cx, self.write_buf.headers.chunk()))?;
             debug!("flushed {} bytes", n);
+            #[cfg(feature = "debug")]
+            if let Some(ref capture) = self.capture {
+                capture.wrote(&self.write_buf.headers.chunk()[..n]);
+            }
             self.write_buf.headers.advance(n);
             if self.write_buf.headers.remaining() == 0 {
                 self.write_buf.headers.reset();
@@ -361,7 +454,7 @@ pub(crate) trait MemRead {
 
 impl<T, B> MemRead for Buffered<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Read + Write + Unpin,
     B: Buf,
 {
     fn read_mem(&mut self, cx: &mut task::Context<'_>, len: usize) -> Poll<io::Result<Bytes>> {
@@ -528,6 +621,41 @@ impl<T: AsRef<[u8]>> Buf for Cursor<T> {
     }
 }
 
+// the buffer read into, given back to its pool when dropped
+#[derive(Debug)]
+struct ReadBuffer {
+    bytes: BytesMut,
+    pool: Option<BufferPool>,
+}
+
+impl ReadBuffer {
+    fn into_bytes(mut self) -> Bytes {
+        mem::take(&mut self.bytes).freeze()
+    }
+}
+
+impl Deref for ReadBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &BytesMut {
+        &self.bytes
+    }
+}
+
+impl DerefMut for ReadBuffer {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.bytes
+    }
+}
+
+impl Drop for ReadBuffer {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool {
+            pool.put_read(mem::take(&mut self.bytes));
+        }
+    }
+}
+
 // an internal buffer to collect writes before flushes
 pub(super) struct WriteBuf<B> {
     /// Re-usable buffer that holds message headers
@@ -536,6 +664,8 @@ pub(super) struct WriteBuf<B> {
     /// Deque of user buffers if strategy is Queue
     queue: BufList<B>,
     strategy: WriteStrategy,
+    /// Where the headers buffer is given back to, when dropped
+    pool: Option<BufferPool>,
 }
 
 impl<B: Buf> WriteBuf<B> {
@@ -545,6 +675,15 @@ impl<B: Buf> WriteBuf<B> {
             max_buf_size: DEFAULT_MAX_BUFFER_SIZE,
             queue: BufList::new(),
             strategy,
+            pool: None,
+        }
+    }
+}
+
+impl<B> Drop for WriteBuf<B> {
+    fn drop(&mut self) {
+        if let Some(ref pool) = self.pool {
+            pool.put_write(mem::take(&mut self.headers.bytes));
         }
     }
 }
@@ -560,7 +699,11 @@ where
     pub(super) fn buffer<BB: Buf + Into<B>>(&mut self, mut buf: BB) {
         debug_assert!(buf.has_remaining());
         match self.strategy {
-            WriteStrategy::Flatten => {
+            // Once a large buf is queued, the ones after it are queued too,
+            // so they aren't written before it.
+            WriteStrategy::Flatten
+                if !self.queue.has_remaining() && buf.remaining() < MIN_QUEUED_LEN =>
+            {
                 let head = self.headers_mut();
 
                 head.maybe_unshift(buf.remaining());
@@ -583,7 +726,7 @@ where
                     buf.advance(adv);
                 }
             }
-            WriteStrategy::Queue => {
+            WriteStrategy::Flatten | WriteStrategy::Queue => {
                 trace!(
                     self.len = self.remaining(),
                     buf.len = buf.remaining(),
@@ -595,12 +738,9 @@ where
     }
 
     fn can_buffer(&self) -> bool {
-        match self.strategy {
-            WriteStrategy::Flatten => self.remaining() < self.max_buf_size,
-            WriteStrategy::Queue => {
-                self.queue.bufs_cnt() < MAX_BUF_LIST_BUFFERS && self.remaining() < self.max_buf_size
-            }
-        }
+        // Flattening still queues large bufs, so both strategies can have a
+        // queue to bound.
+        self.queue.bufs_cnt() < MAX_BUF_LIST_BUFFERS && self.remaining() < self.max_buf_size
     }
 
     fn headers_mut(&mut self) -> &mut Cursor<Vec<u8>> {
@@ -669,6 +809,7 @@ mod tests {
     use super::*;
     use std::time::Duration;
 
+    use crate::common::io::Compat;
     use tokio_test::io::Builder as Mock;
 
     // #[cfg(feature = "nightly")]
@@ -701,7 +842,7 @@ mod tests {
         // // so we are testing that the io_buf does not trigger a write
         // // when there is nothing to flush
         // let mock = Mock::new().build();
-        // let mut io_buf = Buffered::<_, Cursor<Vec<u8>>>::new(mock);
+        // let mut io_buf = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
         // io_buf.flush().await.expect("should short-circuit flush");
     }
 
@@ -719,22 +860,32 @@ mod tests {
             .wait(Duration::from_secs(1))
             .build();
 
-        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(mock);
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
 
         // We expect a `parse` to be not ready, and so can't await it directly.
         // Rather, this `poll_fn` will wrap the `Poll` result.
         futures_util::future::poll_fn(|cx| {
             let parse_ctx = ParseContext {
                 cached_headers: &mut None,
+                cached_extensions: &mut None,
+                header_capacity: 0,
+                max_headers: crate::proto::h1::role::MAX_HEADERS,
                 req_method: &mut None,
                 h1_parser_config: Default::default(),
+                replace_bare_cr: false,
+                bare_cr_scanned: &mut 0,
+                allow_content_length_lists: false,
+                sanitize_request_target: false,
+                transfer_coding_policy: crate::proto::h1::TransferCodingPolicy::PassThrough,
+                header_value_policy: crate::proto::h1::HeaderValuePolicy::Allow,
                 h1_header_read_timeout: None,
                 h1_header_read_timeout_fut: &mut None,
                 h1_header_read_timeout_running: &mut false,
-                timer: Time::Empty,
+                timer: Time::default(),
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
+                lazy_headers: false,
                 h09_responses: false,
                 #[cfg(feature = "ffi")]
                 on_informational: &mut None,
@@ -749,7 +900,7 @@ mod tests {
         .await;
 
         assert_eq!(
-            buffered.read_buf,
+            *buffered.read_buf,
             b"HTTP/1.1 200 OK\r\nServer: hyper\r\n"[..]
         );
     }
@@ -866,7 +1017,7 @@ mod tests {
     #[cfg(debug_assertions)] // needs to trigger a debug_assert
     fn write_buf_requires_non_empty_bufs() {
         let mock = Mock::new().build();
-        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(mock);
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
 
         buffered.buffer(Cursor::new(Vec::new()));
     }
@@ -878,7 +1029,7 @@ mod tests {
         let _ = pretty_env_logger::try_init();
 
         let mock = AsyncIo::new_buf(vec![], 1024);
-        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(mock);
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
 
 
         buffered.headers_buf().extend(b"hello ");
@@ -901,7 +1052,7 @@ mod tests {
 
         let mock = Mock::new().write(b"hello world, it's hyper!").build();
 
-        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(mock);
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
         buffered.write_buf.set_strategy(WriteStrategy::Flatten);
 
         buffered.headers_buf().extend(b"hello ");
@@ -948,6 +1099,34 @@ mod tests {
         assert_eq!(write_buf.headers.pos, 0);
     }
 
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn write_buf_flatten_queues_large_bufs() {
+        let _ = pretty_env_logger::try_init();
+
+        let large = vec![b'x'; MIN_QUEUED_LEN];
+        let mock = Mock::new()
+            .write(b"hello ")
+            .write(&large)
+            .write(b"bye")
+            .build();
+
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
+        buffered.write_buf.set_strategy(WriteStrategy::Flatten);
+
+        buffered.headers_buf().extend(b"hello ");
+        // Too large to copy, so it's queued as is.
+        buffered.buffer(Cursor::new(large.clone()));
+        // Small, but queued after it to keep the order.
+        buffered.buffer(Cursor::new(b"bye".to_vec()));
+        assert_eq!(buffered.write_buf.headers.remaining(), 6);
+        assert_eq!(buffered.write_buf.queue.bufs_cnt(), 2);
+
+        buffered.flush().await.expect("flush");
+
+        assert_eq!(buffered.write_buf.remaining(), 0);
+    }
+
     #[cfg(not(miri))]
     #[tokio::test]
     async fn write_buf_queue_disable_auto() {
@@ -960,7 +1139,7 @@ mod tests {
             .write(b"hyper!")
             .build();
 
-        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(mock);
+        let mut buffered = Buffered::<_, Cursor<Vec<u8>>>::new(Compat::new(mock));
         buffered.write_buf.set_strategy(WriteStrategy::Queue);
 
         // we have 4 buffers, and vec IO disabled, but explicitly said
@@ -2,7 +2,7 @@
 use std::{pin::Pin, time::Duration};
 
 use bytes::BytesMut;
-use http::{HeaderMap, Method};
+use http::{Extensions, HeaderMap, Method};
 use httparse::ParserConfig;
 
 use crate::body::DecodedLength;
@@ -18,13 +18,16 @@ pub(crate) use self::dispatch::Dispatcher;
 pub(crate) use self::encode::{EncodedBuf, Encoder};
 //TODO: move out of h1::io
 pub(crate) use self::io::MINIMUM_MAX_BUFFER_SIZE;
+pub use self::strict::{HeaderValuePolicy, Strictness, StrictnessLevel, TransferCodingPolicy};
 
 mod conn;
 mod decode;
 pub(crate) mod dispatch;
 mod encode;
+mod intern;
 mod io;
 mod role;
+mod strict;
 
 cfg_client! {
     pub(crate) type ClientTransaction = role::Client;
@@ -43,6 +46,12 @@ pub(crate) trait Http1Transaction {
 
     fn on_error(err: &crate::Error) -> Option<MessageHead<Self::Outgoing>>;
 
+    /// The head of a response the user built for a parse error.
+    #[cfg(feature = "server")]
+    fn on_error_response(_parts: http::response::Parts) -> Option<MessageHead<Self::Outgoing>> {
+        None
+    }
+
     fn is_client() -> bool {
         !Self::is_server()
     }
@@ -76,8 +85,25 @@ pub(crate) struct ParsedMessage<T> {
 
 pub(crate) struct ParseContext<'a> {
     cached_headers: &'a mut Option<HeaderMap>,
+    cached_extensions: &'a mut Option<Extensions>,
+    /// The least number of headers to size a parsed `HeaderMap` for.
+    header_capacity: usize,
+    /// The most headers a parsed message may have.
+    max_headers: usize,
     req_method: &'a mut Option<Method>,
     h1_parser_config: ParserConfig,
+    /// Whether to replace a CR without a LF in a head with a space.
+    replace_bare_cr: bool,
+    /// How much of the read buffer was already searched for bare CRs, so
+    /// that each byte of a head arriving in pieces is only checked once.
+    bare_cr_scanned: &'a mut usize,
+    /// Whether to accept a list of equal lengths in `content-length`.
+    allow_content_length_lists: bool,
+    /// Whether to percent-encode what `http::Uri` rejects in a request
+    /// target, instead of rejecting the request.
+    sanitize_request_target: bool,
+    transfer_coding_policy: TransferCodingPolicy,
+    header_value_policy: HeaderValuePolicy,
     #[cfg(feature = "server")]
     h1_header_read_timeout: Option<Duration>,
     #[cfg(feature = "server")]
@@ -89,6 +115,7 @@ pub(crate) struct ParseContext<'a> {
     preserve_header_case: bool,
     #[cfg(feature = "ffi")]
     preserve_header_order: bool,
+    lazy_headers: bool,
     h09_responses: bool,
     #[cfg(feature = "ffi")]
     on_informational: &'a mut Option<crate::ffi::OnInformational>,
@@ -102,8 +129,19 @@ pub(crate) struct Encode<'a, T> {
     body: Option<BodyLength>,
     #[cfg(feature = "server")]
     keep_alive: bool,
+    #[cfg(feature = "server")]
+    date_header: bool,
+    /// Whether a body of unknown length may be chunked.
+    #[cfg(feature = "server")]
+    chunked: bool,
+    #[cfg(feature = "server")]
+    timer: &'a Time,
     req_method: &'a mut Option<Method>,
     title_case_headers: bool,
+    /// Whether to check header values beyond what `http` does.
+    validate_header_values: bool,
+    /// Whether to write the headers of a `LazyHeaders` extension.
+    forward_lazy_headers: bool,
 }
 
 /// Extra flags that a request "wants", like expect-continue or upgrades.
@@ -1,13 +1,14 @@
+use std::cmp;
 use std::fmt::{self, Write};
 use std::mem::MaybeUninit;
-#[cfg(feature = "server")]
-use std::time::Instant;
 
 use bytes::Bytes;
 use bytes::BytesMut;
 #[cfg(feature = "server")]
 use http::header::ValueIter;
 use http::header::{self, Entry, HeaderName, HeaderValue};
+#[cfg(feature = "server")]
+use http::Uri;
 use http::{HeaderMap, Method, StatusCode, Version};
 use tracing::{debug, error, trace, trace_span, warn};
 
@@ -16,34 +17,60 @@ use crate::body::DecodedLength;
 use crate::common::date;
 use crate::error::Parse;
 use crate::ext::HeaderCaseMap;
+use crate::ext::LazyHeaders;
 #[cfg(feature = "ffi")]
 use crate::ext::OriginalHeaderOrder;
+#[cfg(feature = "server")]
+use crate::ext::RawRequestTarget;
 use crate::headers;
+use crate::proto::h1::{intern, strict};
 use crate::proto::h1::{
     Encode, Encoder, Http1Transaction, ParseContext, ParseResult, ParsedMessage,
+    TransferCodingPolicy,
 };
 use crate::proto::{BodyLength, MessageHead, RequestHead, RequestLine};
 
-const MAX_HEADERS: usize = 100;
+/// The number of header slots parsed into on the stack, and the default
+/// maximum number of headers.
+pub(super) const MAX_HEADERS: usize = 100;
 const AVERAGE_HEADER_SIZE: usize = 30; // totally scientific
 #[cfg(feature = "server")]
 const MAX_URI_LEN: usize = (u16::MAX - 1) as usize;
 
+/// The headers hyper reads to frame a message, parsed even with lazy headers.
+const FRAMING_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "transfer-encoding",
+    "expect",
+    "upgrade",
+];
+
 macro_rules! header_name {
     ($bytes:expr) => {{
         {
-            match HeaderName::from_bytes($bytes) {
-                Ok(name) => name,
-                Err(e) => maybe_panic!(e),
+            let bytes = $bytes;
+            match intern::header_name(bytes) {
+                Some(name) => name,
+                None => match HeaderName::from_bytes(bytes) {
+                    Ok(name) => name,
+                    Err(e) => maybe_panic!(e),
+                },
             }
         }
     }};
 }
 
 macro_rules! header_value {
-    ($bytes:expr) => {{
+    ($slice:expr, $range:expr) => {{
         {
-            unsafe { HeaderValue::from_maybe_shared_unchecked($bytes) }
+            let (start, end) = $range;
+            match intern::header_value(&$slice[start..end]) {
+                Some(value) => value,
+                None => unsafe {
+                    HeaderValue::from_maybe_shared_unchecked($slice.slice(start..end))
+                },
+            }
         }
     }};
 }
@@ -78,7 +105,7 @@ where
     #[cfg(feature = "server")]
     if !*ctx.h1_header_read_timeout_running {
         if let Some(h1_header_read_timeout) = ctx.h1_header_read_timeout {
-            let deadline = Instant::now() + h1_header_read_timeout;
+            let deadline = ctx.timer.now() + h1_header_read_timeout;
             *ctx.h1_header_read_timeout_running = true;
             match ctx.h1_header_read_timeout_fut {
                 Some(h1_header_read_timeout_fut) => {
@@ -94,9 +121,38 @@ where
         }
     }
 
+    if ctx.replace_bare_cr {
+        replace_bare_cr(bytes, ctx.bare_cr_scanned);
+    }
+
     T::parse(bytes, ctx)
 }
 
+/// Replaces every CR not followed by a LF in the message head with a space,
+/// as RFC 9112 allows instead of rejecting the message.
+///
+/// The search starts around `scanned`, where the previous one for this head
+/// stopped, and `scanned` is reset once the end of the head is found.
+fn replace_bare_cr(bytes: &mut BytesMut, scanned: &mut usize) {
+    // Step back a byte, since the end of the head may have been split
+    // between a LF and the CRLF after it.
+    let mut i = cmp::min(*scanned, bytes.len()).saturating_sub(1);
+    // The last byte is left alone, since what follows it isn't known yet.
+    while i + 1 < bytes.len() {
+        match bytes[i] {
+            b'\r' if bytes[i + 1] != b'\n' => bytes[i] = b' ',
+            // Don't touch the body after the empty line ending the head.
+            b'\n' if bytes[i + 1] == b'\n' || bytes[i + 1..].starts_with(b"\r\n") => {
+                *scanned = 0;
+                return;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    *scanned = i;
+}
+
 pub(super) fn encode_headers<T>(
     enc: Encode<'_, T::Outgoing>,
     dst: &mut Vec<u8>,
@@ -106,9 +162,32 @@ where
 {
     let span = trace_span!("encode_headers");
     let _s = span.enter();
+    strict::check_line_bytes(
+        &enc.head.headers,
+        enc.head.extensions.get::<crate::ext::ReasonPhrase>(),
+    )?;
+    if enc.validate_header_values {
+        strict::validate_header_values(&enc.head.headers)?;
+    }
     T::encode(enc, dst)
 }
 
+/// Percent-encodes the bytes of a request target that RFC 3986 doesn't allow
+/// in a URI, then parses it.
+///
+/// This is only used for targets `http::Uri` rejected, such as ones with `<`
+/// or `>`. `http::Uri` accepts some other bytes RFC 3986 doesn't, such as `|`
+/// or `{`, which are also encoded then.
+#[cfg(feature = "server")]
+fn sanitize_request_target(target: &[u8]) -> Result<Uri, Parse> {
+    // The unreserved and reserved characters of RFC 3986, and `%`.
+    fn is_uri_byte(b: u8) -> bool {
+        b.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=%".contains(&b)
+    }
+
+    Uri::from_maybe_shared(strict::percent_encode(target, is_uri_byte)).map_err(|_| Parse::Uri)
+}
+
 // There are 2 main roles, Client and Server.
 
 #[cfg(feature = "client")]
@@ -132,23 +211,38 @@ impl Http1Transaction for Server {
         let version;
         let len;
         let headers_len;
+        let mut raw_target = None;
 
         // Unsafe: both headers_indices and headers are using uninitialized memory,
         // but we *never* read any of it until after httparse has assigned
         // values into it. By not zeroing out the stack memory, this saves
         // a good ~5% on pipeline benchmarks.
-        let mut headers_indices: [MaybeUninit<HeaderIndices>; MAX_HEADERS] = unsafe {
+        let mut stack_indices: [MaybeUninit<HeaderIndices>; MAX_HEADERS] = unsafe {
             // SAFETY: We can go safely from MaybeUninit array to array of MaybeUninit
             MaybeUninit::uninit().assume_init()
         };
+        let mut heap_indices: Vec<MaybeUninit<HeaderIndices>>;
+        let mut headers_indices = &mut stack_indices[..];
         {
             /* SAFETY: it is safe to go from MaybeUninit array to array of MaybeUninit */
-            let mut headers: [MaybeUninit<httparse::Header<'_>>; MAX_HEADERS] =
+            let mut stack_headers: [MaybeUninit<httparse::Header<'_>>; MAX_HEADERS] =
                 unsafe { MaybeUninit::uninit().assume_init() };
+            let mut heap_headers: Vec<MaybeUninit<httparse::Header<'_>>>;
             trace!(bytes = buf.len(), "Request.parse");
             let mut req = httparse::Request::new(&mut []);
             let bytes = buf.as_ref();
-            match req.parse_with_uninit_headers(bytes, &mut headers) {
+            let max_stack = cmp::min(ctx.max_headers, MAX_HEADERS);
+            let mut result = req.parse_with_uninit_headers(bytes, &mut stack_headers[..max_stack]);
+            // Slots for more headers are only allocated when there are more.
+            if matches!(result, Err(httparse::Error::TooManyHeaders)) && ctx.max_headers > max_stack
+            {
+                heap_headers = uninit_slots(ctx.max_headers);
+                heap_indices = uninit_slots(ctx.max_headers);
+                headers_indices = &mut heap_indices[..];
+                req = httparse::Request::new(&mut []);
+                result = req.parse_with_uninit_headers(bytes, &mut heap_headers);
+            }
+            match result {
                 Ok(httparse::Status::Complete(parsed_len)) => {
                     trace!("Request.parse Complete({})", parsed_len);
                     len = parsed_len;
@@ -156,10 +250,17 @@ impl Http1Transaction for Server {
                     if uri.len() > MAX_URI_LEN {
                         return Err(Parse::UriTooLong);
                     }
-                    subject = RequestLine(
-                        Method::from_bytes(req.method.unwrap().as_bytes())?,
-                        uri.parse()?,
-                    );
+                    let method = Method::from_bytes(req.method.unwrap().as_bytes())?;
+                    let uri = match uri.parse() {
+                        Ok(parsed) => parsed,
+                        Err(_) if ctx.sanitize_request_target => {
+                            debug!("sanitizing invalid request target");
+                            raw_target = Some(RawRequestTarget::new(uri.as_bytes()));
+                            sanitize_request_target(uri.as_bytes())?
+                        }
+                        Err(err) => return Err(err.into()),
+                    };
+                    subject = RequestLine(method, uri);
                     version = if req.version.unwrap() == 1 {
                         keep_alive = true;
                         is_http_11 = true;
@@ -170,7 +271,7 @@ impl Http1Transaction for Server {
                         Version::HTTP_10
                     };
 
-                    record_header_indices(bytes, &req.headers, &mut headers_indices)?;
+                    record_header_indices(bytes, &req.headers, headers_indices)?;
                     headers_len = req.headers.len();
                 }
                 Ok(httparse::Status::Partial) => return Ok(None),
@@ -207,6 +308,7 @@ impl Http1Transaction for Server {
         let mut con_len = None;
         let mut is_te = false;
         let mut is_te_chunked = false;
+        let mut te_has_other = false;
         let mut wants_upgrade = subject.0 == Method::CONNECT;
 
         let mut header_case_map = if ctx.preserve_header_case {
@@ -222,15 +324,30 @@ impl Http1Transaction for Server {
             None
         };
 
+        let mut lazy_headers = if ctx.lazy_headers {
+            Some(LazyHeaders::new(slice.clone()))
+        } else {
+            None
+        };
+
         let mut headers = ctx.cached_headers.take().unwrap_or_else(HeaderMap::new);
 
-        headers.reserve(headers_len);
+        reserve_headers(&mut headers, headers_len, ctx.header_capacity);
 
         for header in &headers_indices[..headers_len] {
             // SAFETY: array is valid up to `headers_len`
             let header = unsafe { &*header.as_ptr() };
+            let encoded = ctx
+                .header_value_policy
+                .check(&slice[header.value.0..header.value.1])?;
+            if let (Some(lazy_headers), None) = (&mut lazy_headers, &encoded) {
+                if !is_framing_header(&slice[header.name.0..header.name.1]) {
+                    lazy_headers.push(header.name, header.value);
+                    continue;
+                }
+            }
             let name = header_name!(&slice[header.name.0..header.name.1]);
-            let value = header_value!(slice.slice(header.value.0..header.value.1));
+            let value = encoded.unwrap_or_else(|| header_value!(slice, header.value));
 
             match name {
                 header::TRANSFER_ENCODING => {
@@ -243,6 +360,7 @@ impl Http1Transaction for Server {
                         return Err(Parse::transfer_encoding_unexpected());
                     }
                     is_te = true;
+                    te_has_other |= headers::has_other_coding(&value);
                     if headers::is_chunked_(&value) {
                         is_te_chunked = true;
                         decoder = DecodedLength::CHUNKED;
@@ -254,15 +372,18 @@ impl Http1Transaction for Server {
                     if is_te {
                         continue;
                     }
-                    let len = headers::content_length_parse(&value)
-                        .ok_or_else(Parse::content_length_invalid)?;
+                    let len = headers::content_length_parse_received(
+                        Some(&value),
+                        ctx.allow_content_length_lists,
+                    )?
+                    .expect("one value was parsed");
                     if let Some(prev) = con_len {
                         if prev != len {
                             debug!(
                                 "multiple Content-Length headers with different values: [{}, {}]",
                                 prev, len,
                             );
-                            return Err(Parse::content_length_invalid());
+                            return Err(Parse::content_length_mismatch());
                         }
                         // we don't need to append this secondary length
                         continue;
@@ -306,12 +427,24 @@ impl Http1Transaction for Server {
             headers.append(name, value);
         }
 
-        if is_te && !is_te_chunked {
-            debug!("request with transfer-encoding header, but not chunked, bad request");
-            return Err(Parse::transfer_encoding_invalid());
+        match ctx.transfer_coding_policy {
+            TransferCodingPolicy::Reject if te_has_other => {
+                debug!("request with unsupported transfer-encoding");
+                return Err(Parse::transfer_encoding_unsupported());
+            }
+            TransferCodingPolicy::CloseDelimited if te_has_other => {
+                trace!("request with unsupported transfer-encoding, read till eof");
+                decoder = DecodedLength::CLOSE_DELIMITED;
+                keep_alive = false;
+            }
+            _ if is_te && !is_te_chunked => {
+                debug!("request with transfer-encoding header, but not chunked, bad request");
+                return Err(Parse::transfer_encoding_invalid());
+            }
+            _ => (),
         }
 
-        let mut extensions = http::Extensions::default();
+        let mut extensions = ctx.cached_extensions.take().unwrap_or_default();
 
         if let Some(header_case_map) = header_case_map {
             extensions.insert(header_case_map);
@@ -322,7 +455,16 @@ impl Http1Transaction for Server {
             extensions.insert(header_order);
         }
 
+        if let Some(lazy_headers) = lazy_headers {
+            extensions.insert(lazy_headers);
+        }
+
+        if let Some(raw_target) = raw_target {
+            extensions.insert(raw_target);
+        }
+
         *ctx.req_method = Some(subject.0.clone());
+        crate::common::trace::request_parsed(&subject.0, &subject.1, version, headers.len());
 
         Ok(Some(ParsedMessage {
             head: MessageHead {
@@ -415,16 +557,13 @@ impl Http1Transaction for Server {
             extend(dst, b"\r\n");
         }
 
-        let orig_headers;
-        let extensions = std::mem::take(&mut msg.head.extensions);
-        let orig_headers = match extensions.get::<HeaderCaseMap>() {
-            None if msg.title_case_headers => {
-                orig_headers = HeaderCaseMap::default();
-                Some(&orig_headers)
-            }
+        // Only the case map is taken out, the other extensions are still
+        // read while encoding, such as `LazyHeaders`.
+        let orig_headers = match msg.head.extensions.remove::<HeaderCaseMap>() {
+            None if msg.title_case_headers => Some(HeaderCaseMap::default()),
             orig_headers => orig_headers,
         };
-        let encoder = if let Some(orig_headers) = orig_headers {
+        let encoder = if let Some(ref orig_headers) = orig_headers {
             Self::encode_headers_with_original_case(
                 msg,
                 dst,
@@ -441,14 +580,19 @@ impl Http1Transaction for Server {
     }
 
     fn on_error(err: &crate::Error) -> Option<MessageHead<Self::Outgoing>> {
-        use crate::error::Kind;
-        let status = match *err.kind() {
+        use crate::error::{Header, Kind};
+        let status = match *err.inner_kind() {
+            Kind::Parse(Parse::Header(Header::TransferEncodingUnsupported)) => {
+                StatusCode::NOT_IMPLEMENTED
+            }
             Kind::Parse(Parse::Method)
             | Kind::Parse(Parse::Header(_))
             | Kind::Parse(Parse::Uri)
             | Kind::Parse(Parse::Version) => StatusCode::BAD_REQUEST,
             Kind::Parse(Parse::TooLarge) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
             Kind::Parse(Parse::UriTooLong) => StatusCode::URI_TOO_LONG,
+            Kind::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Kind::HeaderTimeout => StatusCode::REQUEST_TIMEOUT,
             _ => return None,
         };
 
@@ -458,6 +602,16 @@ impl Http1Transaction for Server {
         Some(msg)
     }
 
+    fn on_error_response(parts: http::response::Parts) -> Option<MessageHead<Self::Outgoing>> {
+        debug!("sending user response ({}) for parse error", parts.status);
+        Some(MessageHead {
+            version: parts.version,
+            subject: parts.status,
+            headers: parts.headers,
+            extensions: parts.extensions,
+        })
+    }
+
     fn is_server() -> bool {
         true
     }
@@ -638,6 +792,12 @@ impl Server {
         let mut must_write_chunked = false;
         let mut prev_con_len = None;
 
+        if msg.forward_lazy_headers {
+            if let Some(lazy_headers) = msg.head.extensions.get::<LazyHeaders>() {
+                wrote_date |= write_lazy_headers(lazy_headers, &msg.head.headers, dst);
+            }
+        }
+
         macro_rules! handle_is_name_written {
             () => {{
                 if is_name_written {
@@ -773,6 +933,7 @@ impl Server {
                     }
                     // check that we actually can send a chunked body...
                     if msg.head.version == Version::HTTP_10
+                        || !msg.chunked
                         || !Server::can_chunked(msg.req_method, msg.head.subject)
                     {
                         continue;
@@ -841,6 +1002,7 @@ impl Server {
             encoder = match msg.body {
                 Some(BodyLength::Unknown) => {
                     if msg.head.version == Version::HTTP_10
+                        || !msg.chunked
                         || !Server::can_chunked(msg.req_method, msg.head.subject)
                     {
                         Encoder::close_delimited()
@@ -893,10 +1055,10 @@ impl Server {
         }
 
         // cached date is much faster than formatting every request
-        if !wrote_date {
+        if !wrote_date && msg.date_header {
             dst.reserve(date::DATE_VALUE_LENGTH + 8);
             header_name_writer.write_header_name_with_colon(dst, "date: ", header::DATE);
-            date::extend(dst);
+            date::extend(dst, msg.timer);
             extend(dst, b"\r\n\r\n");
         } else {
             extend(dst, b"\r\n");
@@ -935,22 +1097,41 @@ impl Http1Transaction for Client {
         // Loop to skip information status code headers (100 Continue, etc).
         loop {
             // Unsafe: see comment in Server Http1Transaction, above.
-            let mut headers_indices: [MaybeUninit<HeaderIndices>; MAX_HEADERS] = unsafe {
+            let mut stack_indices: [MaybeUninit<HeaderIndices>; MAX_HEADERS] = unsafe {
                 // SAFETY: We can go safely from MaybeUninit array to array of MaybeUninit
                 MaybeUninit::uninit().assume_init()
             };
+            let mut heap_indices: Vec<MaybeUninit<HeaderIndices>>;
+            let mut headers_indices = &mut stack_indices[..];
             let (len, status, reason, version, headers_len) = {
                 // SAFETY: We can go safely from MaybeUninit array to array of MaybeUninit
-                let mut headers: [MaybeUninit<httparse::Header<'_>>; MAX_HEADERS] =
+                let mut stack_headers: [MaybeUninit<httparse::Header<'_>>; MAX_HEADERS] =
                     unsafe { MaybeUninit::uninit().assume_init() };
+                let mut heap_headers: Vec<MaybeUninit<httparse::Header<'_>>>;
                 trace!(bytes = buf.len(), "Response.parse");
                 let mut res = httparse::Response::new(&mut []);
                 let bytes = buf.as_ref();
-                match ctx.h1_parser_config.parse_response_with_uninit_headers(
+                let max_stack = cmp::min(ctx.max_headers, MAX_HEADERS);
+                let mut result = ctx.h1_parser_config.parse_response_with_uninit_headers(
                     &mut res,
                     bytes,
-                    &mut headers,
-                ) {
+                    &mut stack_headers[..max_stack],
+                );
+                // Slots for more headers are only allocated when there are more.
+                if matches!(result, Err(httparse::Error::TooManyHeaders))
+                    && ctx.max_headers > max_stack
+                {
+                    heap_headers = uninit_slots(ctx.max_headers);
+                    heap_indices = uninit_slots(ctx.max_headers);
+                    headers_indices = &mut heap_indices[..];
+                    res = httparse::Response::new(&mut []);
+                    result = ctx.h1_parser_config.parse_response_with_uninit_headers(
+                        &mut res,
+                        bytes,
+                        &mut heap_headers,
+                    );
+                }
+                match result {
                     Ok(httparse::Status::Complete(len)) => {
                         trace!("Response.parse Complete({})", len);
                         let status = StatusCode::from_u16(res.code.unwrap())?;
@@ -970,7 +1151,7 @@ impl Http1Transaction for Client {
                         } else {
                             Version::HTTP_10
                         };
-                        record_header_indices(bytes, &res.headers, &mut headers_indices)?;
+                        record_header_indices(bytes, &res.headers, headers_indices)?;
                         let headers_len = res.headers.len();
                         (len, status, reason, version, headers_len)
                     }
@@ -1016,12 +1197,27 @@ impl Http1Transaction for Client {
                 None
             };
 
-            headers.reserve(headers_len);
+            let mut lazy_headers = if ctx.lazy_headers {
+                Some(LazyHeaders::new(slice.clone()))
+            } else {
+                None
+            };
+
+            reserve_headers(&mut headers, headers_len, ctx.header_capacity);
             for header in &headers_indices[..headers_len] {
                 // SAFETY: array is valid up to `headers_len`
                 let header = unsafe { &*header.as_ptr() };
+                let encoded = ctx
+                    .header_value_policy
+                    .check(&slice[header.value.0..header.value.1])?;
+                if let (Some(lazy_headers), None) = (&mut lazy_headers, &encoded) {
+                    if !is_framing_header(&slice[header.name.0..header.name.1]) {
+                        lazy_headers.push(header.name, header.value);
+                        continue;
+                    }
+                }
                 let name = header_name!(&slice[header.name.0..header.name.1]);
-                let value = header_value!(slice.slice(header.value.0..header.value.1));
+                let value = encoded.unwrap_or_else(|| header_value!(slice, header.value));
 
                 if let header::CONNECTION = name {
                     // keep_alive was previously set to default for Version
@@ -1046,7 +1242,7 @@ impl Http1Transaction for Client {
                 headers.append(name, value);
             }
 
-            let mut extensions = http::Extensions::default();
+            let mut extensions = ctx.cached_extensions.take().unwrap_or_default();
 
             if let Some(header_case_map) = header_case_map {
                 extensions.insert(header_case_map);
@@ -1057,6 +1253,10 @@ impl Http1Transaction for Client {
                 extensions.insert(header_order);
             }
 
+            if let Some(lazy_headers) = lazy_headers {
+                extensions.insert(lazy_headers);
+            }
+
             if let Some(reason) = reason {
                 // Safety: httparse ensures that only valid reason phrase bytes are present in this
                 // field.
@@ -1066,7 +1266,8 @@ impl Http1Transaction for Client {
 
             #[cfg(feature = "ffi")]
             if ctx.raw_headers {
-                extensions.insert(crate::ffi::RawHeaders(crate::ffi::hyper_buf(slice)));
+                let raw = crate::ffi::hyper_buf::returned(slice);
+                extensions.insert(crate::ffi::RawHeaders(raw));
             }
 
             let head = MessageHead {
@@ -1075,7 +1276,13 @@ impl Http1Transaction for Client {
                 headers,
                 extensions,
             };
-            if let Some((decode, is_upgrade)) = Client::decoder(&head, ctx.req_method)? {
+            crate::common::trace::response_parsed(head.subject, head.version, head.headers.len());
+            if let Some((decode, is_upgrade)) = Client::decoder(
+                &head,
+                ctx.req_method,
+                ctx.allow_content_length_lists,
+                ctx.transfer_coding_policy,
+            )? {
                 return Ok(Some(ParsedMessage {
                     head,
                     decode,
@@ -1132,6 +1339,12 @@ impl Http1Transaction for Client {
         }
         extend(dst, b"\r\n");
 
+        if msg.forward_lazy_headers {
+            if let Some(lazy_headers) = msg.head.extensions.get::<LazyHeaders>() {
+                write_lazy_headers(lazy_headers, &msg.head.headers, dst);
+            }
+        }
+
         if let Some(orig_headers) = msg.head.extensions.get::<HeaderCaseMap>() {
             write_headers_original_case(
                 &msg.head.headers,
@@ -1169,6 +1382,8 @@ impl Client {
     fn decoder(
         inc: &MessageHead<StatusCode>,
         method: &mut Option<Method>,
+        allow_content_length_lists: bool,
+        transfer_coding_policy: TransferCodingPolicy,
     ) -> Result<Option<(DecodedLength, bool)>, Parse> {
         // According to https://tools.ietf.org/html/rfc7230#section-3.3.3
         // 1. HEAD responses, and Status 1xx, 204, and 304 cannot have a body.
@@ -1210,20 +1425,33 @@ impl Client {
             // If Transfer-Encoding header is present, and 'chunked' is
             // not the final encoding, and this is a Request, then it is
             // malformed. A server should respond with 400 Bad Request.
+            let has_other = || {
+                inc.headers
+                    .get_all(header::TRANSFER_ENCODING)
+                    .iter()
+                    .any(headers::has_other_coding)
+            };
             if inc.version == Version::HTTP_10 {
                 debug!("HTTP/1.0 cannot have Transfer-Encoding header");
                 Err(Parse::transfer_encoding_unexpected())
+            } else if transfer_coding_policy == TransferCodingPolicy::Reject && has_other() {
+                debug!("response with unsupported transfer-encoding");
+                Err(Parse::transfer_encoding_unsupported())
+            } else if transfer_coding_policy == TransferCodingPolicy::CloseDelimited && has_other()
+            {
+                trace!("unsupported transfer-encoding, read till eof");
+                Ok(Some((DecodedLength::CLOSE_DELIMITED, false)))
             } else if headers::transfer_encoding_is_chunked(&inc.headers) {
                 Ok(Some((DecodedLength::CHUNKED, false)))
             } else {
                 trace!("not chunked, read till eof");
                 Ok(Some((DecodedLength::CLOSE_DELIMITED, false)))
             }
-        } else if let Some(len) = headers::content_length_parse_all(&inc.headers) {
+        } else if let Some(len) = headers::content_length_parse_received(
+            inc.headers.get_all(header::CONTENT_LENGTH),
+            allow_content_length_lists,
+        )? {
             Ok(Some((DecodedLength::checked_new(len)?, false)))
-        } else if inc.headers.contains_key(header::CONTENT_LENGTH) {
-            debug!("illegal Content-Length header");
-            Err(Parse::content_length_invalid())
         } else {
             trace!("neither Transfer-Encoding nor Content-Length");
             Ok(Some((DecodedLength::CLOSE_DELIMITED, false)))
@@ -1440,6 +1668,20 @@ struct HeaderIndices {
     value: (usize, usize),
 }
 
+/// Reserves room for the parsed headers, and for at least `capacity`
+/// headers in all.
+///
+/// `HeaderMap::reserve` rounds up to a power of two, of which only 3/4 may be
+/// used, so the `capacity` is asked for with a third more, like
+/// `HeaderMap::with_capacity` does.
+fn reserve_headers(headers: &mut HeaderMap, len: usize, capacity: usize) {
+    headers.reserve(cmp::max(len, capacity + capacity / 3));
+}
+
+fn uninit_slots<T>(len: usize) -> Vec<MaybeUninit<T>> {
+    (0..len).map(|_| MaybeUninit::uninit()).collect()
+}
+
 fn record_header_indices(
     bytes: &[u8],
     headers: &[httparse::Header<'_>],
@@ -1505,6 +1747,47 @@ fn write_headers(headers: &HeaderMap, dst: &mut Vec<u8>) {
     }
 }
 
+/// Writes the headers as they were received, returning whether a `Date` was
+/// among them.
+///
+/// Hop-by-hop headers are skipped, since they only applied to the connection
+/// the headers were received on, and so is any header named in `overrides`,
+/// which the user set on the message itself.
+fn write_lazy_headers(headers: &LazyHeaders, overrides: &HeaderMap, dst: &mut Vec<u8>) -> bool {
+    let mut wrote_date = false;
+    for (name, value) in headers.iter() {
+        // The parser only accepts tokens as names, so this always works.
+        let name_str = match std::str::from_utf8(name) {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if is_hop_by_hop_header(name) || overrides.contains_key(name_str) {
+            continue;
+        }
+        wrote_date |= name.eq_ignore_ascii_case(b"date");
+        extend(dst, name);
+        extend(dst, b": ");
+        extend(dst, value);
+        extend(dst, b"\r\n");
+    }
+    wrote_date
+}
+
+fn is_hop_by_hop_header(name: &[u8]) -> bool {
+    const HOP_BY_HOP_HEADERS: [&[u8]; 5] =
+        [b"connection", b"keep-alive", b"te", b"trailer", b"upgrade"];
+    HOP_BY_HOP_HEADERS
+        .iter()
+        .any(|hop| hop.eq_ignore_ascii_case(name))
+        || name.len() >= 6 && name[..6].eq_ignore_ascii_case(b"proxy-")
+}
+
+fn is_framing_header(name: &[u8]) -> bool {
+    FRAMING_HEADERS
+        .iter()
+        .any(|framing| framing.as_bytes().eq_ignore_ascii_case(name))
+}
+
 #[cold]
 fn write_headers_original_case(
     headers: &HeaderMap,
@@ -1566,6 +1849,7 @@ mod tests {
     use bytes::BytesMut;
 
     use crate::common::time::Time;
+    use crate::proto::h1::{HeaderValuePolicy, TransferCodingPolicy};
 
     use super::*;
 
@@ -1578,15 +1862,25 @@ mod tests {
             &mut raw,
             ParseContext {
                 cached_headers: &mut None,
+                cached_extensions: &mut None,
+                header_capacity: 0,
+                max_headers: MAX_HEADERS,
                 req_method: &mut method,
                 h1_parser_config: Default::default(),
+                replace_bare_cr: false,
+                bare_cr_scanned: &mut 0,
+                allow_content_length_lists: false,
+                sanitize_request_target: false,
+                transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                header_value_policy: HeaderValuePolicy::Allow,
                 h1_header_read_timeout: None,
                 h1_header_read_timeout_fut: &mut None,
                 h1_header_read_timeout_running: &mut false,
-                timer: Time::Empty,
+                timer: Time::default(),
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
+                lazy_headers: false,
                 h09_responses: false,
                 #[cfg(feature = "ffi")]
                 on_informational: &mut None,
@@ -1611,15 +1905,25 @@ mod tests {
         let mut raw = BytesMut::from("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: false,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1639,15 +1943,25 @@ mod tests {
         let mut raw = BytesMut::from("GET htt:p// HTTP/1.1\r\nHost: hyper.rs\r\n\r\n");
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut None,
             h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: false,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1665,15 +1979,25 @@ mod tests {
         let mut raw = BytesMut::from(H09_RESPONSE);
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: true,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1693,15 +2017,25 @@ mod tests {
         let mut raw = BytesMut::from(H09_RESPONSE);
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: false,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1725,15 +2059,25 @@ mod tests {
         h1_parser_config.allow_spaces_after_header_name_in_responses(true);
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config,
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: false,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1754,15 +2098,25 @@ mod tests {
         let mut raw = BytesMut::from(RESPONSE_WITH_WHITESPACE_BETWEEN_HEADER_NAME_AND_COLON);
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut Some(crate::Method::GET),
             h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: false,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: false,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1778,15 +2132,25 @@ mod tests {
             BytesMut::from("GET / HTTP/1.1\r\nHost: hyper.rs\r\nX-BREAD: baguette\r\n\r\n");
         let ctx = ParseContext {
             cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
             req_method: &mut None,
             h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
             h1_header_read_timeout: None,
             h1_header_read_timeout_fut: &mut None,
             h1_header_read_timeout_running: &mut false,
-            timer: Time::Empty,
+            timer: Time::default(),
             preserve_header_case: true,
             #[cfg(feature = "ffi")]
             preserve_header_order: false,
+            lazy_headers: false,
             h09_responses: false,
             #[cfg(feature = "ffi")]
             on_informational: &mut None,
@@ -1815,6 +2179,446 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_lazy_headers_in_request() {
+        let mut raw = BytesMut::from(
+            "POST / HTTP/1.1\r\nHost: hyper.rs\r\nContent-Length: 3\r\nX-BREAD: baguette\r\n\r\n",
+        );
+        let ctx = ParseContext {
+            cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
+            req_method: &mut None,
+            h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
+            h1_header_read_timeout: None,
+            h1_header_read_timeout_fut: &mut None,
+            h1_header_read_timeout_running: &mut false,
+            timer: Time::default(),
+            preserve_header_case: false,
+            #[cfg(feature = "ffi")]
+            preserve_header_order: false,
+            lazy_headers: true,
+            h09_responses: false,
+            #[cfg(feature = "ffi")]
+            on_informational: &mut None,
+            #[cfg(feature = "ffi")]
+            raw_headers: false,
+        };
+        let parsed_message = Server::parse(&mut raw, ctx).unwrap().unwrap();
+        assert_eq!(parsed_message.decode, DecodedLength::new(3));
+
+        // Only the framing headers are parsed.
+        let headers = &parsed_message.head.headers;
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["content-length"], "3");
+
+        let lazy = parsed_message.head.extensions.get::<LazyHeaders>().unwrap();
+        let lazy_headers = lazy.iter().collect::<Vec<_>>();
+        assert_eq!(
+            lazy_headers,
+            [
+                (&b"Host"[..], &b"hyper.rs"[..]),
+                (&b"X-BREAD"[..], &b"baguette"[..])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_reuses_cached_extensions() {
+        let mut cached_extensions = http::Extensions::new();
+        cached_extensions.insert(5u8);
+        cached_extensions.clear();
+        let mut cached_extensions = Some(cached_extensions);
+
+        let mut raw = BytesMut::from("GET / HTTP/1.1\r\nHost: hyper.rs\r\n\r\n");
+        let ctx = ParseContext {
+            cached_headers: &mut None,
+            cached_extensions: &mut cached_extensions,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
+            req_method: &mut None,
+            h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
+            h1_header_read_timeout: None,
+            h1_header_read_timeout_fut: &mut None,
+            h1_header_read_timeout_running: &mut false,
+            timer: Time::default(),
+            preserve_header_case: false,
+            #[cfg(feature = "ffi")]
+            preserve_header_order: false,
+            lazy_headers: false,
+            h09_responses: false,
+            #[cfg(feature = "ffi")]
+            on_informational: &mut None,
+            #[cfg(feature = "ffi")]
+            raw_headers: false,
+        };
+        let parsed_message = Server::parse(&mut raw, ctx).unwrap().unwrap();
+
+        assert!(cached_extensions.is_none(), "cached extensions taken");
+        assert!(parsed_message.head.extensions.get::<u8>().is_none());
+    }
+
+    #[test]
+    fn test_parse_max_headers() {
+        fn parse(count: usize, max_headers: usize) -> ParseResult<RequestLine> {
+            let mut raw = String::from("GET / HTTP/1.1\r\n");
+            for i in 0..count {
+                raw.push_str(&format!("x-header-{}: {}\r\n", i, i));
+            }
+            raw.push_str("\r\n");
+            let ctx = ParseContext {
+                cached_headers: &mut None,
+                cached_extensions: &mut None,
+                header_capacity: 0,
+                max_headers,
+                req_method: &mut None,
+                h1_parser_config: Default::default(),
+                replace_bare_cr: false,
+                bare_cr_scanned: &mut 0,
+                allow_content_length_lists: false,
+                sanitize_request_target: false,
+                transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                header_value_policy: HeaderValuePolicy::Allow,
+                h1_header_read_timeout: None,
+                h1_header_read_timeout_fut: &mut None,
+                h1_header_read_timeout_running: &mut false,
+                timer: Time::default(),
+                preserve_header_case: false,
+                #[cfg(feature = "ffi")]
+                preserve_header_order: false,
+                lazy_headers: false,
+                h09_responses: false,
+                #[cfg(feature = "ffi")]
+                on_informational: &mut None,
+                #[cfg(feature = "ffi")]
+                raw_headers: false,
+            };
+            Server::parse(&mut BytesMut::from(raw.as_str()), ctx)
+        }
+
+        let too_large = |result: ParseResult<RequestLine>| match result {
+            Err(Parse::TooLarge) => true,
+            other => panic!("expected TooLarge: {:?}", other.map(|_| ())),
+        };
+
+        let parsed = parse(MAX_HEADERS, MAX_HEADERS).unwrap().unwrap();
+        assert_eq!(parsed.head.headers.len(), MAX_HEADERS);
+        assert!(too_large(parse(MAX_HEADERS + 1, MAX_HEADERS)));
+
+        // More than fit on the stack.
+        let parsed = parse(150, 200).unwrap().unwrap();
+        assert_eq!(parsed.head.headers.len(), 150);
+        assert_eq!(parsed.head.headers["x-header-149"], "149");
+        assert!(too_large(parse(201, 200)));
+
+        // Fewer than fit on the stack.
+        assert!(too_large(parse(11, 10)));
+    }
+
+    #[test]
+    fn test_parse_header_capacity() {
+        let mut raw = BytesMut::from("GET / HTTP/1.1\r\nHost: hyper.rs\r\n\r\n");
+        let ctx = ParseContext {
+            cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 32,
+            max_headers: MAX_HEADERS,
+            req_method: &mut None,
+            h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
+            h1_header_read_timeout: None,
+            h1_header_read_timeout_fut: &mut None,
+            h1_header_read_timeout_running: &mut false,
+            timer: Time::default(),
+            preserve_header_case: false,
+            #[cfg(feature = "ffi")]
+            preserve_header_order: false,
+            lazy_headers: false,
+            h09_responses: false,
+            #[cfg(feature = "ffi")]
+            on_informational: &mut None,
+            #[cfg(feature = "ffi")]
+            raw_headers: false,
+        };
+        let headers = Server::parse(&mut raw, ctx).unwrap().unwrap().head.headers;
+
+        assert_eq!(headers.len(), 1);
+        assert!(headers.capacity() >= 32);
+    }
+
+    #[test]
+    fn test_parse_replace_bare_cr() {
+        let parse = |raw: &mut BytesMut, replace_bare_cr, bare_cr_scanned: &mut usize| {
+            parse_headers::<Server>(
+                raw,
+                ParseContext {
+                    cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
+                    req_method: &mut None,
+                    h1_parser_config: Default::default(),
+                    replace_bare_cr,
+                    bare_cr_scanned,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
+                    h1_header_read_timeout: None,
+                    h1_header_read_timeout_fut: &mut None,
+                    h1_header_read_timeout_running: &mut false,
+                    timer: Time::default(),
+                    preserve_header_case: false,
+                    #[cfg(feature = "ffi")]
+                    preserve_header_order: false,
+                    lazy_headers: false,
+                    h09_responses: false,
+                    #[cfg(feature = "ffi")]
+                    on_informational: &mut None,
+                    #[cfg(feature = "ffi")]
+                    raw_headers: false,
+                },
+            )
+        };
+        let raw = "POST / HTTP/1.1\r\nx-a: a\rb\r\ncontent-length: 3\r\n\r\n\r!\n";
+
+        parse(&mut BytesMut::from(raw), false, &mut 0).expect_err("bare CR");
+
+        let mut buf = BytesMut::from(raw);
+        let msg = parse(&mut buf, true, &mut 0).unwrap().unwrap();
+        assert_eq!(msg.head.headers["x-a"], "a b");
+        // The body is left alone.
+        assert_eq!(buf, "\r!\n");
+
+        // A head arriving a byte at a time is searched once, not again from
+        // the start for each byte.
+        let mut buf = BytesMut::new();
+        let mut scanned = 0;
+        let mut pieces = raw.as_bytes().iter();
+        let msg = loop {
+            buf.extend_from_slice(&[*pieces.next().unwrap()]);
+            match parse(&mut buf, true, &mut scanned).unwrap() {
+                Some(msg) => break msg,
+                None => assert_eq!(scanned, buf.len() - 1),
+            }
+        };
+        assert_eq!(msg.head.headers["x-a"], "a b");
+        assert_eq!(scanned, 0);
+        buf.extend(pieces);
+        assert_eq!(buf, "\r!\n");
+    }
+
+    #[test]
+    fn test_parse_sanitize_request_target() {
+        let parse = |sanitize_request_target| {
+            Server::parse(
+                &mut BytesMut::from("GET /a<b>|c?d=%20 HTTP/1.1\r\n\r\n"),
+                ParseContext {
+                    cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
+                    req_method: &mut None,
+                    h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
+                    h1_header_read_timeout: None,
+                    h1_header_read_timeout_fut: &mut None,
+                    h1_header_read_timeout_running: &mut false,
+                    timer: Time::default(),
+                    preserve_header_case: false,
+                    #[cfg(feature = "ffi")]
+                    preserve_header_order: false,
+                    lazy_headers: false,
+                    h09_responses: false,
+                    #[cfg(feature = "ffi")]
+                    on_informational: &mut None,
+                    #[cfg(feature = "ffi")]
+                    raw_headers: false,
+                },
+            )
+        };
+
+        parse(false).expect_err("invalid uri");
+
+        let head = parse(true).unwrap().unwrap().head;
+        assert_eq!(head.subject.1, "/a%3Cb%3E%7Cc?d=%20");
+        let raw = head.extensions.get::<RawRequestTarget>().unwrap();
+        assert_eq!(raw.as_bytes(), b"/a<b>|c?d=%20");
+    }
+
+    #[test]
+    fn test_parse_content_length_kinds() {
+        use crate::error::{ErrorKind, ParseKind};
+
+        let parse = |len: &str, allow_content_length_lists| {
+            let raw = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", len);
+            Client::parse(
+                &mut BytesMut::from(raw.as_str()),
+                ParseContext {
+                    cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
+                    req_method: &mut Some(Method::GET),
+                    h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
+                    h1_header_read_timeout: None,
+                    h1_header_read_timeout_fut: &mut None,
+                    h1_header_read_timeout_running: &mut false,
+                    timer: Time::default(),
+                    preserve_header_case: false,
+                    #[cfg(feature = "ffi")]
+                    preserve_header_order: false,
+                    lazy_headers: false,
+                    h09_responses: false,
+                    #[cfg(feature = "ffi")]
+                    on_informational: &mut None,
+                    #[cfg(feature = "ffi")]
+                    raw_headers: false,
+                },
+            )
+            .map(|msg| msg.unwrap().decode)
+            .map_err(|err| match crate::Error::from(err).kind() {
+                ErrorKind::Parse(kind) => kind,
+                kind => panic!("unexpected error kind: {:?}", kind),
+            })
+        };
+
+        assert_eq!(parse("8", false), Ok(DecodedLength::new(8)));
+        assert_eq!(
+            parse("8, 8", false),
+            Err(ParseKind::ContentLengthWhitespace)
+        );
+        assert_eq!(parse("8,8", false), Err(ParseKind::ContentLength));
+        assert_eq!(parse("+8", false), Err(ParseKind::ContentLengthSigned));
+        assert_eq!(
+            parse("18446744073709551616", false),
+            Err(ParseKind::ContentLengthOverflow)
+        );
+        assert_eq!(
+            parse("8\r\ncontent-length: 9", false),
+            Err(ParseKind::ContentLengthMismatch)
+        );
+
+        assert_eq!(parse("8, 8", true), Ok(DecodedLength::new(8)));
+        assert_eq!(parse("8, 9", true), Err(ParseKind::ContentLengthMismatch));
+        assert_eq!(parse("8, -8", true), Err(ParseKind::ContentLengthSigned));
+    }
+
+    #[test]
+    fn test_parse_transfer_coding_policy() {
+        let request = |transfer_coding_policy| {
+            Server::parse(
+                &mut BytesMut::from("POST / HTTP/1.1\r\ntransfer-encoding: gzip, chunked\r\n\r\n"),
+                ParseContext {
+                    cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
+                    req_method: &mut None,
+                    h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy,
+                    header_value_policy: HeaderValuePolicy::Allow,
+                    h1_header_read_timeout: None,
+                    h1_header_read_timeout_fut: &mut None,
+                    h1_header_read_timeout_running: &mut false,
+                    timer: Time::default(),
+                    preserve_header_case: false,
+                    #[cfg(feature = "ffi")]
+                    preserve_header_order: false,
+                    lazy_headers: false,
+                    h09_responses: false,
+                    #[cfg(feature = "ffi")]
+                    on_informational: &mut None,
+                    #[cfg(feature = "ffi")]
+                    raw_headers: false,
+                },
+            )
+        };
+        let response = |transfer_coding_policy| {
+            Client::parse(
+                &mut BytesMut::from("HTTP/1.1 200 OK\r\ntransfer-encoding: gzip\r\n\r\n"),
+                ParseContext {
+                    cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
+                    req_method: &mut Some(Method::GET),
+                    h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy,
+                    header_value_policy: HeaderValuePolicy::Allow,
+                    h1_header_read_timeout: None,
+                    h1_header_read_timeout_fut: &mut None,
+                    h1_header_read_timeout_running: &mut false,
+                    timer: Time::default(),
+                    preserve_header_case: false,
+                    #[cfg(feature = "ffi")]
+                    preserve_header_order: false,
+                    lazy_headers: false,
+                    h09_responses: false,
+                    #[cfg(feature = "ffi")]
+                    on_informational: &mut None,
+                    #[cfg(feature = "ffi")]
+                    raw_headers: false,
+                },
+            )
+        };
+
+        let msg = request(TransferCodingPolicy::PassThrough).unwrap().unwrap();
+        assert_eq!(msg.decode, DecodedLength::CHUNKED);
+        assert!(msg.keep_alive);
+        let msg = request(TransferCodingPolicy::CloseDelimited)
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.decode, DecodedLength::CLOSE_DELIMITED);
+        assert!(!msg.keep_alive);
+        let err = request(TransferCodingPolicy::Reject).unwrap_err();
+        let head = Server::on_error(&err.into()).unwrap();
+        assert_eq!(head.subject, StatusCode::NOT_IMPLEMENTED);
+
+        let msg = response(TransferCodingPolicy::PassThrough)
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.decode, DecodedLength::CLOSE_DELIMITED);
+        response(TransferCodingPolicy::Reject).unwrap_err();
+    }
+
     #[test]
     fn test_decoder_request() {
         fn parse(s: &str) -> ParsedMessage<RequestLine> {
@@ -1823,15 +2627,25 @@ mod tests {
                 &mut bytes,
                 ParseContext {
                     cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -1849,15 +2663,25 @@ mod tests {
                 &mut bytes,
                 ParseContext {
                     cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -2084,15 +2908,25 @@ mod tests {
                 &mut bytes,
                 ParseContext {
                     cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut Some(Method::GET),
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -2110,15 +2944,25 @@ mod tests {
                 &mut bytes,
                 ParseContext {
                     cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut Some(m),
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -2136,15 +2980,25 @@ mod tests {
                 &mut bytes,
                 ParseContext {
                     cached_headers: &mut None,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut Some(Method::GET),
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -2451,8 +3305,13 @@ mod tests {
                 head: &mut head,
                 body: Some(BodyLength::Known(10)),
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut None,
                 title_case_headers: true,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2482,8 +3341,13 @@ mod tests {
                 head: &mut head,
                 body: Some(BodyLength::Known(10)),
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut None,
                 title_case_headers: false,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2516,8 +3380,13 @@ mod tests {
                 head: &mut head,
                 body: Some(BodyLength::Known(10)),
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut None,
                 title_case_headers: true,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2540,8 +3409,13 @@ mod tests {
                 head: &mut head,
                 body: None,
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut Some(Method::CONNECT),
                 title_case_headers: false,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2550,6 +3424,118 @@ mod tests {
         assert!(encoder.is_last());
     }
 
+    #[test]
+    fn test_server_response_encode_lazy_headers() {
+        use crate::proto::BodyLength;
+
+        let mut raw = BytesMut::from(
+            "HTTP/1.1 200 OK\r\nDate: Tue, 01 Jan 2030 00:00:00 GMT\r\nX-Upstream: yes\r\n\r\n",
+        );
+        let ctx = ParseContext {
+            cached_headers: &mut None,
+            cached_extensions: &mut None,
+            header_capacity: 0,
+            max_headers: MAX_HEADERS,
+            req_method: &mut Some(Method::GET),
+            h1_parser_config: Default::default(),
+            replace_bare_cr: false,
+            bare_cr_scanned: &mut 0,
+            allow_content_length_lists: false,
+            sanitize_request_target: false,
+            transfer_coding_policy: TransferCodingPolicy::PassThrough,
+            header_value_policy: HeaderValuePolicy::Allow,
+            h1_header_read_timeout: None,
+            h1_header_read_timeout_fut: &mut None,
+            h1_header_read_timeout_running: &mut false,
+            timer: Time::default(),
+            preserve_header_case: false,
+            #[cfg(feature = "ffi")]
+            preserve_header_order: false,
+            lazy_headers: true,
+            h09_responses: false,
+            #[cfg(feature = "ffi")]
+            on_informational: &mut None,
+            #[cfg(feature = "ffi")]
+            raw_headers: false,
+        };
+        let mut head = Client::parse(&mut raw, ctx).unwrap().unwrap().head;
+        assert!(head.headers.is_empty());
+
+        let mut head = MessageHead {
+            subject: head.subject,
+            extensions: std::mem::take(&mut head.extensions),
+            ..Default::default()
+        };
+        let mut vec = Vec::new();
+        Server::encode(
+            Encode {
+                head: &mut head,
+                body: Some(BodyLength::Known(0)),
+                keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
+                req_method: &mut None,
+                title_case_headers: false,
+                validate_header_values: false,
+                forward_lazy_headers: true,
+            },
+            &mut vec,
+        )
+        .unwrap();
+
+        // Written as received, without another Date.
+        assert_eq!(
+            &*vec,
+            &b"HTTP/1.1 200 OK\r\nDate: Tue, 01 Jan 2030 00:00:00 GMT\r\nX-Upstream: yes\r\ncontent-length: 0\r\n\r\n"[..],
+        );
+    }
+
+    #[test]
+    fn test_server_response_encode_keeps_extensions() {
+        use crate::ext::ReasonPhrase;
+        use crate::proto::BodyLength;
+
+        let mut lazy_headers = LazyHeaders::new(Bytes::from_static(b"x-upstream: yes"));
+        lazy_headers.push((0, 10), (12, 15));
+
+        let mut head = MessageHead::default();
+        head.extensions.insert(lazy_headers);
+        head.extensions
+            .insert(ReasonPhrase::from_static(b"Forwarded"));
+        head.extensions.insert(7u8);
+
+        let mut vec = Vec::new();
+        // With title case, the case map is taken out of the extensions.
+        Server::encode(
+            Encode {
+                head: &mut head,
+                body: Some(BodyLength::Known(0)),
+                keep_alive: true,
+                date_header: false,
+                chunked: true,
+                timer: &Time::default(),
+                req_method: &mut None,
+                title_case_headers: true,
+                validate_header_values: false,
+                forward_lazy_headers: true,
+            },
+            &mut vec,
+        )
+        .unwrap();
+
+        assert_eq!(
+            &*vec,
+            &b"HTTP/1.1 200 Forwarded\r\nx-upstream: yes\r\nContent-Length: 0\r\n\r\n"[..],
+        );
+
+        // Only the case map was removed.
+        assert!(head.extensions.get::<HeaderCaseMap>().is_none());
+        assert_eq!(head.extensions.get::<LazyHeaders>().unwrap().len(), 1);
+        assert!(head.extensions.get::<ReasonPhrase>().is_some());
+        assert_eq!(head.extensions.get::<u8>(), Some(&7));
+    }
+
     #[test]
     fn test_server_response_encode_title_case() {
         use crate::proto::BodyLength;
@@ -2569,8 +3555,13 @@ mod tests {
                 head: &mut head,
                 body: Some(BodyLength::Known(10)),
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut None,
                 title_case_headers: true,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2603,8 +3594,13 @@ mod tests {
                 head: &mut head,
                 body: Some(BodyLength::Known(10)),
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut None,
                 title_case_headers: false,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2637,8 +3633,13 @@ mod tests {
                 head: &mut head,
                 body: Some(BodyLength::Known(10)),
                 keep_alive: true,
+                date_header: true,
+                chunked: true,
+                timer: &Time::default(),
                 req_method: &mut None,
                 title_case_headers: true,
+                validate_header_values: false,
+                forward_lazy_headers: false,
             },
             &mut vec,
         )
@@ -2657,15 +3658,25 @@ mod tests {
             &mut bytes,
             ParseContext {
                 cached_headers: &mut None,
+                cached_extensions: &mut None,
+                header_capacity: 0,
+                max_headers: MAX_HEADERS,
                 req_method: &mut Some(Method::GET),
                 h1_parser_config: Default::default(),
+                replace_bare_cr: false,
+                bare_cr_scanned: &mut 0,
+                allow_content_length_lists: false,
+                sanitize_request_target: false,
+                transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                header_value_policy: HeaderValuePolicy::Allow,
                 h1_header_read_timeout: None,
                 h1_header_read_timeout_fut: &mut None,
                 h1_header_read_timeout_running: &mut false,
-                timer: Time::Empty,
+                timer: Time::default(),
                 preserve_header_case: false,
                 #[cfg(feature = "ffi")]
                 preserve_header_order: false,
+                lazy_headers: false,
                 h09_responses: false,
                 #[cfg(feature = "ffi")]
                 on_informational: &mut None,
@@ -2747,15 +3758,25 @@ mod tests {
                 &mut raw,
                 ParseContext {
                     cached_headers: &mut headers,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -2793,15 +3814,25 @@ mod tests {
                 &mut raw,
                 ParseContext {
                     cached_headers: &mut headers,
+                    cached_extensions: &mut None,
+                    header_capacity: 0,
+                    max_headers: MAX_HEADERS,
                     req_method: &mut None,
                     h1_parser_config: Default::default(),
+                    replace_bare_cr: false,
+                    bare_cr_scanned: &mut 0,
+                    allow_content_length_lists: false,
+                    sanitize_request_target: false,
+                    transfer_coding_policy: TransferCodingPolicy::PassThrough,
+                    header_value_policy: HeaderValuePolicy::Allow,
                     h1_header_read_timeout: None,
                     h1_header_read_timeout_fut: &mut None,
                     h1_header_read_timeout_running: &mut false,
-                    timer: Time::Empty,
+                    timer: Time::default(),
                     preserve_header_case: false,
                     #[cfg(feature = "ffi")]
                     preserve_header_order: false,
+                    lazy_headers: false,
                     h09_responses: false,
                     #[cfg(feature = "ffi")]
                     on_informational: &mut None,
@@ -2847,8 +3878,13 @@ mod tests {
                     head: &mut head,
                     body: Some(BodyLength::Known(10)),
                     keep_alive: true,
+                    date_header: true,
+                    chunked: true,
+                    timer: &Time::default(),
                     req_method: &mut Some(Method::GET),
                     title_case_headers: false,
+                    validate_header_values: false,
+                    forward_lazy_headers: false,
                 },
                 &mut vec,
             )
@@ -2875,8 +3911,13 @@ mod tests {
                     head: &mut head,
                     body: Some(BodyLength::Known(10)),
                     keep_alive: true,
+                    date_header: true,
+                    chunked: true,
+                    timer: &Time::default(),
                     req_method: &mut Some(Method::GET),
                     title_case_headers: false,
+                    validate_header_values: false,
+                    forward_lazy_headers: false,
                 },
                 &mut vec,
             )
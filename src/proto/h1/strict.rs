@@ -0,0 +1,343 @@
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue};
+#[cfg(feature = "client")]
+use httparse::ParserConfig;
+use tracing::debug;
+
+use crate::error::Parse;
+use crate::ext::ReasonPhrase;
+
+/// A preset of the checks in [`Strictness`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StrictnessLevel {
+    /// Work around what [RFC 9112] lets a recipient work around, instead of
+    /// rejecting it.
+    ///
+    /// [RFC 9112]: https://www.rfc-editor.org/rfc/rfc9112
+    Lenient,
+    /// Reject what is ambiguous, but accept whitespace in chunk size lines.
+    ///
+    /// This is the default.
+    Standard,
+    /// Reject everything [RFC 9112] doesn't allow.
+    ///
+    /// [RFC 9112]: https://www.rfc-editor.org/rfc/rfc9112
+    Strict,
+}
+
+/// How strictly received HTTP/1 messages are checked against [RFC 9112].
+///
+/// It starts from a [`StrictnessLevel`], whose individual checks can then be
+/// overridden.
+///
+/// # Example
+///
+/// ```
+/// use hyper::server::conn::{Strictness, StrictnessLevel};
+///
+/// // Strict, except for folded header values sent by older peers.
+/// let strictness = Strictness::new(StrictnessLevel::Strict).reject_obsolete_folding(false);
+/// # let _ = strictness;
+/// ```
+///
+/// [RFC 9112]: https://www.rfc-editor.org/rfc/rfc9112
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Strictness {
+    obsolete_folding: bool,
+    bare_cr: bool,
+    space_before_colon: bool,
+    chunk_size: bool,
+    content_length_lists: bool,
+}
+
+impl Strictness {
+    /// Creates the checks of a `level`.
+    pub fn new(level: StrictnessLevel) -> Strictness {
+        let reject = level != StrictnessLevel::Lenient;
+        Strictness {
+            obsolete_folding: reject,
+            bare_cr: reject,
+            space_before_colon: reject,
+            chunk_size: level == StrictnessLevel::Strict,
+            content_length_lists: reject,
+        }
+    }
+
+    /// Sets whether to reject header values continued on the next line,
+    /// instead of replacing the line break with spaces.
+    ///
+    /// This only applies to responses. Requests that do this are always
+    /// rejected.
+    pub fn reject_obsolete_folding(mut self, reject: bool) -> Strictness {
+        self.obsolete_folding = reject;
+        self
+    }
+
+    /// Sets whether to reject a CR that isn't followed by a LF in a message
+    /// head, instead of replacing it with a space.
+    pub fn reject_bare_cr(mut self, reject: bool) -> Strictness {
+        self.bare_cr = reject;
+        self
+    }
+
+    /// Sets whether to reject whitespace between a header name and the
+    /// colon after it, instead of ignoring it.
+    ///
+    /// This only applies to responses. Requests that do this are always
+    /// rejected.
+    pub fn reject_space_before_colon(mut self, reject: bool) -> Strictness {
+        self.space_before_colon = reject;
+        self
+    }
+
+    /// Sets whether to reject chunk size lines without a size, or with
+    /// whitespace that isn't followed by an extension.
+    pub fn reject_invalid_chunk_size(mut self, reject: bool) -> Strictness {
+        self.chunk_size = reject;
+        self
+    }
+
+    /// Sets whether to reject a `content-length` header with a list of
+    /// lengths, such as `42, 42`, instead of accepting it if they are all
+    /// equal.
+    ///
+    /// Lengths with a sign, too large for a `u64`, or that disagree with
+    /// another are always rejected.
+    pub fn reject_content_length_lists(mut self, reject: bool) -> Strictness {
+        self.content_length_lists = reject;
+        self
+    }
+
+    #[cfg(feature = "client")]
+    pub(crate) fn apply_to(&self, config: &mut ParserConfig) {
+        config.allow_obsolete_multiline_headers_in_responses(!self.obsolete_folding);
+        config.allow_spaces_after_header_name_in_responses(!self.space_before_colon);
+    }
+
+    pub(crate) fn replaces_bare_cr(&self) -> bool {
+        !self.bare_cr
+    }
+
+    pub(crate) fn rejects_invalid_chunk_size(&self) -> bool {
+        self.chunk_size
+    }
+
+    pub(crate) fn allows_content_length_lists(&self) -> bool {
+        !self.content_length_lists
+    }
+}
+
+impl Default for Strictness {
+    fn default() -> Strictness {
+        Strictness::new(StrictnessLevel::Standard)
+    }
+}
+
+impl From<StrictnessLevel> for Strictness {
+    fn from(level: StrictnessLevel) -> Strictness {
+        Strictness::new(level)
+    }
+}
+
+/// What to do with received HTTP/1 header values containing bytes that
+/// aren't visible ASCII, space or tab.
+///
+/// `http` accepts such bytes in a `HeaderValue`, as obsolete text, but
+/// handling them consistently is up to whoever reads the value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderValuePolicy {
+    /// Accept the value as is.
+    ///
+    /// This is the default.
+    Allow,
+    /// Reject the message, as an invalid header.
+    Reject,
+    /// Replace each such byte with its percent-encoding, such as `%C3`.
+    ///
+    /// A `%` already in the value is kept as is.
+    PercentEncode,
+}
+
+impl HeaderValuePolicy {
+    /// Checks a received value, returning the value to use instead if it
+    /// was rewritten.
+    pub(crate) fn check(self, value: &[u8]) -> Result<Option<HeaderValue>, Parse> {
+        if self == HeaderValuePolicy::Allow || value.iter().all(|&b| is_field_byte(b)) {
+            return Ok(None);
+        }
+        if self == HeaderValuePolicy::Reject {
+            debug!("header value contains bytes that aren't visible ASCII");
+            return Err(Parse::header_value_invalid());
+        }
+
+        let encoded = percent_encode(value, is_field_byte);
+        // SAFETY: every byte is now visible ASCII, space or tab.
+        Ok(Some(unsafe {
+            HeaderValue::from_maybe_shared_unchecked(encoded)
+        }))
+    }
+}
+
+impl Default for HeaderValuePolicy {
+    fn default() -> HeaderValuePolicy {
+        HeaderValuePolicy::Allow
+    }
+}
+
+/// What to do with HTTP/1 messages whose `transfer-encoding` lists a coding
+/// other than `chunked`, such as `gzip`, `deflate` or a custom one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferCodingPolicy {
+    /// Only undo `chunked`, leaving the other codings in the body for the
+    /// application, and the `transfer-encoding` header as received, so a
+    /// proxy can forward the body as is.
+    ///
+    /// If `chunked` isn't the last coding, a request is rejected with `400
+    /// Bad Request`, and a response body is read until the connection
+    /// closes.
+    ///
+    /// This is the default.
+    PassThrough,
+    /// Reject the message. A server responds with `501 Not Implemented`.
+    Reject,
+    /// Read the body, without undoing any coding, until the connection
+    /// closes.
+    CloseDelimited,
+}
+
+impl Default for TransferCodingPolicy {
+    fn default() -> TransferCodingPolicy {
+        TransferCodingPolicy::PassThrough
+    }
+}
+
+/// Replaces each byte for which `keep` is false with its percent-encoding.
+pub(crate) fn percent_encode(bytes: &[u8], keep: impl Fn(u8) -> bool) -> Bytes {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+    let mut encoded = Vec::with_capacity(bytes.len() + 8);
+    for &b in bytes {
+        if keep(b) {
+            encoded.push(b);
+        } else {
+            encoded.push(b'%');
+            encoded.push(HEX[(b >> 4) as usize]);
+            encoded.push(HEX[(b & 0x0f) as usize]);
+        }
+    }
+    Bytes::from(encoded)
+}
+
+/// Whether a byte is visible ASCII, space or tab, as RFC 9110 allows in a
+/// field value besides obsolete text.
+fn is_field_byte(b: u8) -> bool {
+    b == b'\t' || (b' '..=b'~').contains(&b)
+}
+
+/// Checks that no header value or reason phrase about to be sent has CR, LF
+/// or NUL, which would end the line early and let the rest be read as
+/// other headers or messages.
+///
+/// `http` and [`ReasonPhrase`] reject these bytes already, but not in values
+/// made with their unchecked constructors.
+pub(crate) fn check_line_bytes(
+    headers: &HeaderMap,
+    reason: Option<&ReasonPhrase>,
+) -> crate::Result<()> {
+    fn breaks_line(bytes: &[u8]) -> bool {
+        bytes.iter().any(|&b| matches!(b, b'\r' | b'\n' | b'\0'))
+    }
+
+    if reason.map_or(false, |reason| breaks_line(reason.as_bytes())) {
+        debug!("invalid outgoing reason phrase");
+        return Err(crate::Error::new_user_invalid_header(None));
+    }
+    for (name, value) in headers {
+        if breaks_line(value.as_bytes()) {
+            debug!("invalid value of outgoing header {:?}", name);
+            return Err(crate::Error::new_user_invalid_header(Some(name.clone())));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the values of headers about to be sent beyond what `http` does,
+/// rejecting any with bytes that aren't visible ASCII, space or tab, or that
+/// start or end with whitespace.
+pub(crate) fn validate_header_values(headers: &HeaderMap) -> crate::Result<()> {
+    for (name, value) in headers {
+        let bytes = value.as_bytes();
+        let padded = matches!(bytes.first(), Some(b' ' | b'\t'))
+            || matches!(bytes.last(), Some(b' ' | b'\t'));
+        if padded || !bytes.iter().all(|&b| is_field_byte(b)) {
+            debug!("invalid value of outgoing header {:?}", name);
+            return Err(crate::Error::new_user_invalid_header(Some(name.clone())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http::{HeaderMap, HeaderValue};
+
+    use super::{
+        check_line_bytes, validate_header_values, HeaderValuePolicy, Strictness, StrictnessLevel,
+    };
+    use crate::error::ErrorKind;
+    use crate::ext::ReasonPhrase;
+
+    #[test]
+    fn content_length_lists_by_level() {
+        assert!(Strictness::new(StrictnessLevel::Lenient).allows_content_length_lists());
+        assert!(!Strictness::default().allows_content_length_lists());
+        assert!(!Strictness::new(StrictnessLevel::Strict).allows_content_length_lists());
+        assert!(Strictness::default()
+            .reject_content_length_lists(false)
+            .allows_content_length_lists());
+    }
+
+    #[test]
+    fn header_value_policy() {
+        let value = "caf\u{e9} 100%".as_bytes();
+
+        assert_eq!(HeaderValuePolicy::Allow.check(value).unwrap(), None);
+        assert!(HeaderValuePolicy::Reject.check(value).is_err());
+        let encoded = HeaderValuePolicy::PercentEncode.check(value).unwrap();
+        assert_eq!(encoded.unwrap(), "caf%C3%A9 100%");
+
+        let plain = HeaderValuePolicy::Reject.check(b"plain\tvalue").unwrap();
+        assert_eq!(plain, None);
+    }
+
+    #[test]
+    fn validate_outgoing_header_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ok", HeaderValue::from_static("a b"));
+        validate_header_values(&headers).unwrap();
+
+        headers.insert("x-padded", HeaderValue::from_static("a "));
+        let err = validate_header_values(&headers).unwrap_err();
+        assert_eq!(err.invalid_header().unwrap().name().unwrap(), "x-padded");
+
+        headers.remove("x-padded");
+        headers.insert("x-obs-text", HeaderValue::from_bytes(b"\xff").unwrap());
+        validate_header_values(&headers).unwrap_err();
+    }
+
+    #[test]
+    fn check_outgoing_line_bytes() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ok", HeaderValue::from_static("a b"));
+        let ok = ReasonPhrase::from_static(b"Fine");
+        check_line_bytes(&headers, Some(&ok)).unwrap();
+
+        // Safety: only used to check that it is rejected before being sent.
+        let injected =
+            unsafe { ReasonPhrase::from_bytes_unchecked(Bytes::from_static(b"OK\r\nx-evil: 1")) };
+        let err = check_line_bytes(&headers, Some(&injected)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidHeader);
+        assert_eq!(err.invalid_header().unwrap().name(), None);
+    }
+}
@@ -1,27 +1,37 @@
+use std::collections::VecDeque;
 use std::error::Error as StdError;
-use std::time::Duration;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use futures_channel::{mpsc, oneshot};
-use futures_util::future::{self, Either, FutureExt as _, TryFutureExt as _};
-use futures_util::stream::StreamExt as _;
-use h2::client::{Builder, SendRequest};
+use futures_util::stream::{StreamExt as _, StreamFuture};
+use h2::client::{Builder, Connection, ResponseFuture, SendRequest};
+use h2::SendStream;
 use http::{Method, StatusCode};
-use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, trace, warn};
 
-use super::{ping, H2Upgraded, PipeToSendStream, SendBuf};
+use super::frames::FrameLog;
+use super::hpack::{Observer, TableTuner};
+#[cfg(feature = "debug")]
+use crate::capture::CaptureHandle;
+use super::{ping, H2Upgraded, PipeToSendStream, SendBuf, TrailerPolicy};
 use crate::body::Body;
+use crate::client::dispatch::SendWhen;
+use crate::common::exec::{H2ClientConnExec, H2ClientConnTaskExec};
+use crate::common::io::{Compat, Counted};
 use crate::common::time::Time;
-use crate::common::{exec::Exec, task, Future, Never, Pin, Poll};
+use crate::common::{task, Future, Never, Pin, Poll};
 use crate::ext::Protocol;
 use crate::headers;
-use crate::proto::h2::UpgradedSendStream;
 use crate::proto::Dispatched;
+use crate::rt::{Read, Sleep, Write};
 use crate::upgrade::Upgraded;
 use crate::{Recv, Request, Response};
 
 type ClientRx<B> = crate::client::dispatch::Receiver<Request<B>, Response<Recv>>;
+type ClientCallback<B> = crate::client::dispatch::Callback<Request<B>, Response<Recv>>;
 
 ///// An mpsc channel is used to help notify the `Connection` task when *all*
 ///// other handles to it have been dropped, so that it can shutdown.
@@ -50,6 +60,14 @@ pub(crate) struct Config {
     pub(crate) keep_alive_while_idle: bool,
     pub(crate) max_concurrent_reset_streams: Option<usize>,
     pub(crate) max_send_buffer_size: usize,
+    pub(crate) header_table_size: Option<u32>,
+    pub(crate) adaptive_header_table: Option<TableTuner>,
+    pub(crate) trailer_policy: TrailerPolicy,
+    pub(crate) max_pending_requests: Option<usize>,
+    pub(crate) pending_request_timeout: Option<Duration>,
+    pub(crate) poll_budget: usize,
+    #[cfg(feature = "debug")]
+    pub(crate) capture: Option<CaptureHandle>,
 }
 
 impl Default for Config {
@@ -64,6 +82,14 @@ impl Default for Config {
             keep_alive_while_idle: false,
             max_concurrent_reset_streams: None,
             max_send_buffer_size: DEFAULT_MAX_SEND_BUF_SIZE,
+            header_table_size: None,
+            adaptive_header_table: None,
+            trailer_policy: TrailerPolicy::default(),
+            max_pending_requests: None,
+            pending_request_timeout: None,
+            poll_budget: crate::proto::DEFAULT_POLL_BUDGET,
+            #[cfg(feature = "debug")]
+            capture: None,
         }
     }
 }
@@ -79,6 +105,13 @@ fn new_builder(config: &Config) -> Builder {
     if let Some(max) = config.max_concurrent_reset_streams {
         builder.max_concurrent_reset_streams(max);
     }
+    let table_size = match config.adaptive_header_table {
+        Some(ref tuner) => Some(tuner.table_size()),
+        None => config.header_table_size,
+    };
+    if let Some(size) = table_size {
+        builder.header_table_size(size);
+    }
     builder
 }
 
@@ -95,18 +128,22 @@ fn new_ping_config(config: &Config) -> ping::Config {
     }
 }
 
-pub(crate) async fn handshake<T, B>(
-    io: T,
+pub(crate) async fn handshake<T, B, E>(
+    io: Counted<T>,
     req_rx: ClientRx<B>,
     config: &Config,
-    exec: Exec,
+    mut exec: E,
     timer: Time,
-) -> crate::Result<ClientTask<B>>
+) -> crate::Result<ClientTask<B, E, T>>
 where
-    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
-    B: Body,
-    B::Data: Send + 'static,
+    T: Read + Write + Unpin + 'static,
+    B: Body + 'static,
+    B::Data: 'static,
+    E: H2ClientConnTaskExec<B, T> + Unpin,
 {
+    let io = FrameLog::client(Compat::new(io));
+    #[cfg(feature = "debug")]
+    let io = io.with_capture(config.capture.as_ref().and_then(CaptureHandle::start));
     let (h2_tx, mut conn) = new_builder(config)
         .handshake::<_, SendBuf<B::Data>>(io)
         .await
@@ -119,23 +156,125 @@ where
     let (conn_drop_ref, rx) = mpsc::channel(1);
     let (cancel_tx, conn_eof) = oneshot::channel();
 
-    let conn_drop_rx = rx.into_future().map(|(item, _rx)| {
-        if let Some(never) = item {
-            match never {}
-        }
-    });
-
     let ping_config = new_ping_config(&config);
 
-    let (conn, ping) = if ping_config.is_enabled() {
+    let pending = Pending::new(config, timer.clone());
+
+    let (ponger, ping) = if ping_config.is_enabled() {
         let pp = conn.ping_pong().expect("conn.ping_pong");
-        let (recorder, mut ponger) = ping::channel(pp, ping_config, timer);
+        let (recorder, ponger) = ping::channel(pp, ping_config, timer);
+        (Some(ponger), recorder)
+    } else {
+        (None, ping::disabled())
+    };
+
+    let task = ConnTask {
+        conn: Conn { ponger, conn },
+        drop_rx: Some(rx.into_future()),
+        cancel_tx: Some(cancel_tx),
+        span: crate::common::trace::conn_span("client", "HTTP/2"),
+    };
+    exec.execute_h2_conn(H2ClientFuture {
+        kind: H2ClientFutureKind::Task(Box::new(task)),
+    });
+
+    Ok(ClientTask {
+        ping,
+        conn_drop_ref,
+        conn_eof,
+        executor: exec,
+        h2_tx,
+        req_rx,
+        pending,
+        poll_budget: config.poll_budget,
+        table_observer: config
+            .adaptive_header_table
+            .as_ref()
+            .map(|tuner| Arc::new(tuner.observer())),
+        trailer_policy: config.trailer_policy,
+        _io: PhantomData,
+    })
+}
+
+/// A future spawned on the executor of an HTTP/2 client connection.
+///
+/// It is either the connection itself, a request body being sent, or a
+/// response being waited on.
+#[allow(missing_debug_implementations)]
+pub struct H2ClientFuture<B, T>
+where
+    B: Body,
+{
+    kind: H2ClientFutureKind<B, T>,
+}
+
+enum H2ClientFutureKind<B, T>
+where
+    B: Body,
+{
+    Task(Box<ConnTask<T, B>>),
+    Pipe(PipeMap<B>),
+    Send(SendWhen<Request<B>, Response<Recv>, ResponseFutMap<B>>),
+}
+
+impl<B, T> H2ClientFuture<B, T>
+where
+    B: Body,
+{
+    /// The connection task, which, unlike the other kinds, isn't tied to the
+    /// request body type.
+    pub(crate) fn into_conn_task(self) -> impl Future<Output = ()>
+    where
+        B: 'static,
+        T: Read + Write + Unpin,
+    {
+        match self.kind {
+            H2ClientFutureKind::Task(task) => task,
+            _ => unreachable!("only the connection task is spawned at handshake"),
+        }
+    }
+}
+
+impl<B, T> Future for H2ClientFuture<B, T>
+where
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    T: Read + Write + Unpin,
+{
+    type Output = ();
 
-        let conn = future::poll_fn(move |cx| {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        match self.kind {
+            H2ClientFutureKind::Task(ref mut task) => Pin::new(&mut **task).poll(cx),
+            H2ClientFutureKind::Pipe(ref mut pipe) => Pin::new(pipe).poll(cx),
+            H2ClientFutureKind::Send(ref mut send_when) => Pin::new(send_when).poll(cx),
+        }
+    }
+}
+
+/// The h2 connection, along with the ponger if pings are enabled.
+struct Conn<T, B>
+where
+    B: Body,
+{
+    ponger: Option<ping::Ponger>,
+    conn: Connection<FrameLog<Compat<Counted<T>>>, SendBuf<B::Data>>,
+}
+
+impl<T, B> Future for Conn<T, B>
+where
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    type Output = Result<(), h2::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(ref mut ponger) = this.ponger {
             match ponger.poll(cx) {
                 Poll::Ready(ping::Ponged::SizeUpdate(wnd)) => {
-                    conn.set_target_window_size(wnd);
-                    conn.set_initial_window_size(wnd)?;
+                    this.conn.set_target_window_size(wnd);
+                    this.conn.set_initial_window_size(wnd)?;
                 }
                 Poll::Ready(ping::Ponged::KeepAliveTimedOut) => {
                     debug!("connection keep-alive timed out");
@@ -143,78 +282,327 @@ where
                 }
                 Poll::Pending => {}
             }
+        }
 
-            Pin::new(&mut conn).poll(cx)
-        });
-        (Either::Left(conn), recorder)
-    } else {
-        (Either::Right(conn), ping::disabled())
-    };
-    let conn = conn.map_err(|e| debug!("connection error: {}", e));
+        Pin::new(&mut this.conn).poll(cx)
+    }
+}
+
+/// Drives the connection, and starts shutting it down once all the
+/// `SendRequest`s are dropped.
+struct ConnTask<T, B>
+where
+    B: Body,
+{
+    conn: Conn<T, B>,
+    drop_rx: Option<StreamFuture<mpsc::Receiver<Never>>>,
+    cancel_tx: Option<oneshot::Sender<Never>>,
+    // Entered while polling, with the `tracing-spans` feature.
+    span: tracing::Span,
+}
 
-    exec.execute(conn_task(conn, conn_drop_rx, cancel_tx));
+impl<T, B> Future for ConnTask<T, B>
+where
+    B: Body,
+    T: Read + Write + Unpin,
+{
+    type Output = ();
 
-    Ok(ClientTask {
-        ping,
-        conn_drop_ref,
-        conn_eof,
-        executor: exec,
-        h2_tx,
-        req_rx,
-    })
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let _entered = this.span.enter();
+        if let Some(ref mut drop_rx) = this.drop_rx {
+            if let Poll::Ready((item, _rx)) = Pin::new(drop_rx).poll(cx) {
+                if let Some(never) = item {
+                    match never {}
+                }
+                // mpsc has been dropped, hopefully polling
+                // the connection some more should start shutdown
+                // and then close
+                trace!("send_request dropped, starting conn shutdown");
+                this.drop_rx = None;
+                drop(this.cancel_tx.take());
+            }
+        }
+
+        // ok or err, the `conn` has finished
+        match ready!(Pin::new(&mut this.conn).poll(cx)) {
+            Ok(()) => crate::common::trace::conn_closed(None),
+            Err(e) => {
+                debug!("connection error: {}", e);
+                crate::common::trace::h2_error("connection", &e);
+                crate::common::trace::conn_closed(Some(&crate::Error::new_h2(e)));
+            }
+        }
+        Poll::Ready(())
+    }
 }
 
-async fn conn_task<C, D>(conn: C, drop_rx: D, cancel_tx: oneshot::Sender<Never>)
+/// Sends a request body, when it couldn't be sent right away.
+struct PipeMap<B>
 where
-    C: Future + Unpin,
-    D: Future<Output = ()> + Unpin,
+    B: Body,
 {
-    match future::select(conn, drop_rx).await {
-        Either::Left(_) => {
-            // ok or err, the `conn` has finished
+    pipe: Pin<Box<PipeToSendStream<B>>>,
+    conn_drop_ref: Option<ConnDropRef>,
+    ping: Option<ping::Recorder>,
+}
+
+impl<B> Future for PipeMap<B>
+where
+    B: Body,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        if let Err(e) = ready!(self.pipe.as_mut().poll(cx)) {
+            debug!("client request body error: {}", e);
         }
-        Either::Right(((), conn)) => {
-            // mpsc has been dropped, hopefully polling
-            // the connection some more should start shutdown
-            // and then close
-            trace!("send_request dropped, starting conn shutdown");
-            drop(cancel_tx);
-            let _ = conn.await;
+        drop(self.conn_drop_ref.take());
+        drop(self.ping.take());
+        Poll::Ready(())
+    }
+}
+
+/// Maps the h2 response of a request into a hyper `Response`.
+struct ResponseFutMap<B>
+where
+    B: Body,
+{
+    fut: ResponseFuture,
+    ping: Option<ping::Recorder>,
+    table_observer: Option<Arc<Observer>>,
+    trailer_policy: TrailerPolicy,
+    send_stream: Option<Option<SendStream<SendBuf<B::Data>>>>,
+}
+
+impl<B> Future for ResponseFutMap<B>
+where
+    B: Body + 'static,
+{
+    type Output = Result<Response<Recv>, (crate::Error, Option<Request<B>>)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let result = ready!(Pin::new(&mut self.fut).poll(cx));
+
+        let ping = self.ping.take().expect("Future polled twice");
+        let send_stream = self.send_stream.take().expect("Future polled twice");
+
+        match result {
+            Ok(res) => {
+                // record that we got the response headers
+                ping.record_non_data();
+                if let Some(ref observer) = self.table_observer {
+                    observer.observe(res.headers());
+                }
+
+                let content_length = headers::content_length_parse_all(res.headers());
+                if let (Some(mut send_stream), StatusCode::OK) = (send_stream, res.status()) {
+                    if content_length.map_or(false, |len| len != 0) {
+                        warn!("h2 connect response with non-zero body not supported");
+
+                        send_stream.send_reset(h2::Reason::INTERNAL_ERROR);
+                        return Poll::Ready(Err((
+                            crate::Error::new_h2(h2::Reason::INTERNAL_ERROR.into()),
+                            None,
+                        )));
+                    }
+                    let (parts, recv_stream) = res.into_parts();
+                    let mut res = Response::from_parts(parts, Recv::empty());
+
+                    let (pending, on_upgrade) = crate::upgrade::pending();
+                    let io = H2Upgraded::new(ping, send_stream, recv_stream);
+                    let upgraded = Upgraded::new(io, Bytes::new());
+
+                    pending.fulfill(upgraded);
+                    res.extensions_mut().insert(on_upgrade);
+
+                    Poll::Ready(Ok(res))
+                } else {
+                    let res = res.map(|stream| {
+                        let ping = ping.for_stream(&stream);
+                        crate::Recv::h2(stream, content_length.into(), ping, self.trailer_policy)
+                    });
+                    Poll::Ready(Ok(res))
+                }
+            }
+            Err(err) => {
+                ping.ensure_not_timed_out().map_err(|e| (e, None))?;
+
+                debug!("client response error: {}", err);
+                crate::common::trace::h2_error("stream", &err);
+                Poll::Ready(Err((crate::Error::new_h2(err), None)))
+            }
         }
     }
 }
 
-pub(crate) struct ClientTask<B>
+pub(crate) struct ClientTask<B, E, T>
 where
     B: Body,
 {
     ping: ping::Recorder,
     conn_drop_ref: ConnDropRef,
     conn_eof: ConnEof,
-    executor: Exec,
+    executor: E,
     h2_tx: SendRequest<SendBuf<B::Data>>,
     req_rx: ClientRx<B>,
+    pending: Pending<B>,
+    // How many requests to send in a single poll before yielding.
+    poll_budget: usize,
+    // Records the repetition of received headers, with an adaptive table.
+    table_observer: Option<Arc<Observer>>,
+    trailer_policy: TrailerPolicy,
+    _io: PhantomData<T>,
+}
+
+/// Requests waiting for the server to allow another stream.
+///
+/// This is only used if a bound or a timeout was configured. Otherwise,
+/// requests simply wait in the dispatch channel.
+struct Pending<B> {
+    queue: VecDeque<(Request<B>, ClientCallback<B>, Option<Instant>)>,
+    max: Option<usize>,
+    timeout: Option<Duration>,
+    timer: Time,
+    sleep: Option<(Instant, Box<dyn Sleep + Unpin>)>,
+    rx_closed: bool,
 }
 
-impl<B> ClientTask<B>
+impl<B, E, T> ClientTask<B, E, T>
 where
     B: Body + 'static,
 {
     pub(crate) fn is_extended_connect_protocol_enabled(&self) -> bool {
         self.h2_tx.is_extended_connect_protocol_enabled()
     }
+
+    fn poll_pending(&mut self, cx: &mut task::Context<'_>) {
+        while !self.pending.rx_closed {
+            match self.req_rx.poll_recv(cx) {
+                Poll::Ready(Some((req, cb))) => self.pending.push(req, cb),
+                Poll::Ready(None) => self.pending.rx_closed = true,
+                Poll::Pending => break,
+            }
+        }
+        self.pending.poll_expired(cx);
+    }
+
+    fn poll_next_request(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<(Request<B>, ClientCallback<B>)>> {
+        if !self.pending.is_enabled() {
+            return self.req_rx.poll_recv(cx);
+        }
+        match self.pending.queue.pop_front() {
+            Some((req, cb, _)) => Poll::Ready(Some((req, cb))),
+            None if self.pending.rx_closed => Poll::Ready(None),
+            // poll_pending registered interest in the channel
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<B> Pending<B> {
+    fn new(config: &Config, timer: Time) -> Pending<B> {
+        Pending {
+            queue: VecDeque::new(),
+            max: config.max_pending_requests,
+            timeout: config.pending_request_timeout,
+            timer,
+            sleep: None,
+            rx_closed: false,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.max.is_some() || self.timeout.is_some()
+    }
+
+    fn push(&mut self, req: Request<B>, cb: ClientCallback<B>) {
+        if let Some(max) = self.max {
+            if self.queue.len() >= max {
+                debug!("too many pending requests, max = {}", max);
+                cb.send(Err((
+                    crate::Error::new_canceled().with("too many pending requests"),
+                    Some(req),
+                )));
+                return;
+            }
+        }
+        let deadline = self.timeout.map(|timeout| self.timer.now() + timeout);
+        self.queue.push_back((req, cb, deadline));
+    }
+
+    fn poll_expired(&mut self, cx: &mut task::Context<'_>) {
+        if self.timeout.is_none() {
+            return;
+        }
+        loop {
+            let now = self.timer.now();
+            while let Some(&(_, _, Some(deadline))) = self.queue.front() {
+                if deadline > now {
+                    break;
+                }
+                let (req, cb, _) = self.queue.pop_front().expect("front exists");
+                debug!("pending request timed out");
+                cb.send(Err((
+                    crate::Error::new_canceled().with(crate::error::TimedOut),
+                    Some(req),
+                )));
+            }
+
+            let deadline = match self.queue.front() {
+                Some(&(_, _, Some(deadline))) => deadline,
+                _ => {
+                    self.sleep = None;
+                    return;
+                }
+            };
+            let timer = &self.timer;
+            let sleep = match self.sleep {
+                Some((at, ref mut sleep)) if at == deadline => sleep,
+                _ => &mut self.sleep.insert((deadline, timer.sleep_until(deadline))).1,
+            };
+            if Pin::new(sleep).poll(cx).is_pending() {
+                return;
+            }
+        }
+    }
 }
 
-impl<B> Future for ClientTask<B>
+// Queued requests are only ever moved, never pinned.
+impl<B> Unpin for Pending<B> {}
+
+impl<B> Drop for Pending<B> {
+    fn drop(&mut self) {
+        for (req, cb, _) in self.queue.drain(..) {
+            cb.send(Err((
+                crate::Error::new_canceled().with("connection closed"),
+                Some(req),
+            )));
+        }
+    }
+}
+
+impl<B, E, T> Future for ClientTask<B, E, T>
 where
-    B: Body + Send + 'static,
-    B::Data: Send,
+    B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: H2ClientConnExec<B, T> + Unpin,
+    T: Read + Write + Unpin,
 {
     type Output = crate::Result<Dispatched>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let mut budget = self.poll_budget;
         loop {
+            if self.pending.is_enabled() {
+                self.poll_pending(cx);
+            }
+
             match ready!(self.h2_tx.poll_ready(cx)) {
                 Ok(()) => (),
                 Err(err) => {
@@ -228,8 +616,16 @@ where
                 }
             };
 
-            match self.req_rx.poll_recv(cx) {
+            // Limit the requests sent in a single poll, in case they keep
+            // coming, so that other futures don't starve.
+            if budget == 0 {
+                trace!("client task yielding");
+                return task::yield_now(cx).map(|never| match never {});
+            }
+
+            match self.poll_next_request(cx) {
                 Poll::Ready(Some((req, cb))) => {
+                    budget -= 1;
                     // check that future hasn't been canceled already
                     if cb.is_canceled() {
                         trace!("request callback is canceled");
@@ -269,6 +665,7 @@ where
                         Ok(ok) => ok,
                         Err(err) => {
                             debug!("client send request error: {}", err);
+                            crate::common::trace::h2_error("stream", &err);
                             cb.send(Err((crate::Error::new_h2(err), None)));
                             continue;
                         }
@@ -276,29 +673,29 @@ where
 
                     let send_stream = if !is_connect {
                         if !eos {
-                            let mut pipe =
-                                Box::pin(PipeToSendStream::new(body, body_tx)).map(|res| {
-                                    if let Err(e) = res {
-                                        debug!("client request body error: {}", e);
-                                    }
-                                });
+                            let mut pipe = PipeMap {
+                                pipe: Box::pin(PipeToSendStream::new(
+                                    body,
+                                    body_tx,
+                                    self.trailer_policy,
+                                )),
+                                conn_drop_ref: None,
+                                ping: None,
+                            };
 
                             // eagerly see if the body pipe is ready and
                             // can thus skip allocating in the executor
                             match Pin::new(&mut pipe).poll(cx) {
-                                Poll::Ready(_) => (),
+                                Poll::Ready(()) => (),
                                 Poll::Pending => {
-                                    let conn_drop_ref = self.conn_drop_ref.clone();
+                                    pipe.conn_drop_ref = Some(self.conn_drop_ref.clone());
                                     // keep the ping recorder's knowledge of an
                                     // "open stream" alive while this body is
                                     // still sending...
-                                    let ping = ping.clone();
-                                    let pipe = pipe.map(move |x| {
-                                        drop(conn_drop_ref);
-                                        drop(ping);
-                                        x
+                                    pipe.ping = Some(ping.clone());
+                                    self.executor.execute_h2_future(H2ClientFuture {
+                                        kind: H2ClientFutureKind::Pipe(pipe),
                                     });
-                                    self.executor.execute(pipe);
                                 }
                             }
                         }
@@ -308,56 +705,16 @@ where
                         Some(body_tx)
                     };
 
-                    let fut = fut.map(move |result| match result {
-                        Ok(res) => {
-                            // record that we got the response headers
-                            ping.record_non_data();
-
-                            let content_length = headers::content_length_parse_all(res.headers());
-                            if let (Some(mut send_stream), StatusCode::OK) =
-                                (send_stream, res.status())
-                            {
-                                if content_length.map_or(false, |len| len != 0) {
-                                    warn!("h2 connect response with non-zero body not supported");
-
-                                    send_stream.send_reset(h2::Reason::INTERNAL_ERROR);
-                                    return Err((
-                                        crate::Error::new_h2(h2::Reason::INTERNAL_ERROR.into()),
-                                        None,
-                                    ));
-                                }
-                                let (parts, recv_stream) = res.into_parts();
-                                let mut res = Response::from_parts(parts, Recv::empty());
-
-                                let (pending, on_upgrade) = crate::upgrade::pending();
-                                let io = H2Upgraded {
-                                    ping,
-                                    send_stream: unsafe { UpgradedSendStream::new(send_stream) },
-                                    recv_stream,
-                                    buf: Bytes::new(),
-                                };
-                                let upgraded = Upgraded::new(io, Bytes::new());
-
-                                pending.fulfill(upgraded);
-                                res.extensions_mut().insert(on_upgrade);
-
-                                Ok(res)
-                            } else {
-                                let res = res.map(|stream| {
-                                    let ping = ping.for_stream(&stream);
-                                    crate::Recv::h2(stream, content_length.into(), ping)
-                                });
-                                Ok(res)
-                            }
-                        }
-                        Err(err) => {
-                            ping.ensure_not_timed_out().map_err(|e| (e, None))?;
-
-                            debug!("client response error: {}", err);
-                            Err((crate::Error::new_h2(err), None))
-                        }
+                    let fut = ResponseFutMap {
+                        fut,
+                        ping: Some(ping),
+                        table_observer: self.table_observer.clone(),
+                        trailer_policy: self.trailer_policy,
+                        send_stream: Some(send_stream),
+                    };
+                    self.executor.execute_h2_future(H2ClientFuture {
+                        kind: H2ClientFutureKind::Send(cb.send_when(fut)),
                     });
-                    self.executor.execute(cb.send_when(fut));
                     continue;
                 }
 
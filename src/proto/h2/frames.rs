@@ -0,0 +1,285 @@
+//! Logging of the HTTP/2 frames sent and received, and capture of the raw
+//! bytes, with the `debug` feature.
+//!
+//! The frames themselves are handled by `h2`, so this decodes the frame
+//! headers from the bytes passing through the transport instead.
+
+use std::cmp;
+use std::io::{self, IoSlice};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+#[cfg(feature = "debug")]
+use crate::capture::ConnCapture;
+
+const ENABLED: bool = cfg!(feature = "debug");
+
+/// The client connection preface, sent before any frames.
+const PREFACE_LEN: usize = 24;
+const HEADER_LEN: usize = 9;
+
+/// Wraps the transport of a connection, logging every frame.
+///
+/// Without the `debug` feature, this only passes the IO through.
+#[derive(Debug)]
+pub(crate) struct FrameLog<T> {
+    io: T,
+    read: Decoder,
+    write: Decoder,
+    #[cfg(feature = "debug")]
+    capture: Option<ConnCapture>,
+}
+
+impl<T> FrameLog<T> {
+    /// The client writes the preface, which the server reads.
+    pub(crate) fn client(io: T) -> FrameLog<T> {
+        FrameLog {
+            io,
+            read: Decoder::new(0),
+            write: Decoder::new(PREFACE_LEN),
+            #[cfg(feature = "debug")]
+            capture: None,
+        }
+    }
+
+    pub(crate) fn server(io: T) -> FrameLog<T> {
+        FrameLog {
+            io,
+            read: Decoder::new(PREFACE_LEN),
+            write: Decoder::new(0),
+            #[cfg(feature = "debug")]
+            capture: None,
+        }
+    }
+
+    /// Also pass a copy of every byte to `capture`.
+    #[cfg(feature = "debug")]
+    pub(crate) fn with_capture(mut self, capture: Option<ConnCapture>) -> FrameLog<T> {
+        self.capture = capture;
+        self
+    }
+
+    fn on_read(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "debug")]
+        if let Some(ref capture) = self.capture {
+            capture.read(bytes);
+        }
+        self.read.decode(bytes, |frame| frame.log("received"));
+    }
+
+    fn on_write(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "debug")]
+        if let Some(ref capture) = self.capture {
+            capture.wrote(bytes);
+        }
+        self.write.decode(bytes, |frame| frame.log("sent"));
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for FrameLog<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let res = Pin::new(&mut self.io).poll_read(cx, buf);
+        if ENABLED {
+            self.on_read(&buf.filled()[before..]);
+        }
+        res
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for FrameLog<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.io).poll_write(cx, buf);
+        if ENABLED {
+            if let Poll::Ready(Ok(n)) = res {
+                self.on_write(&buf[..n]);
+            }
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let res = Pin::new(&mut self.io).poll_write_vectored(cx, bufs);
+        if ENABLED {
+            if let Poll::Ready(Ok(mut n)) = res {
+                for buf in bufs {
+                    let len = cmp::min(n, buf.len());
+                    self.on_write(&buf[..len]);
+                    n -= len;
+                    if n == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Finds the frame headers in one direction of a connection.
+#[derive(Debug)]
+struct Decoder {
+    // Bytes of the preface still to skip.
+    preface: usize,
+    // Bytes of the current frame's payload still to skip.
+    payload: usize,
+    header: [u8; HEADER_LEN],
+    header_len: usize,
+}
+
+#[derive(Debug, PartialEq)]
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream_id: u32,
+    len: u32,
+}
+
+impl Decoder {
+    fn new(preface: usize) -> Decoder {
+        Decoder {
+            preface,
+            payload: 0,
+            header: [0; HEADER_LEN],
+            header_len: 0,
+        }
+    }
+
+    fn decode(&mut self, mut buf: &[u8], mut on_frame: impl FnMut(Frame)) {
+        while !buf.is_empty() {
+            if self.preface > 0 {
+                let n = cmp::min(self.preface, buf.len());
+                self.preface -= n;
+                buf = &buf[n..];
+            } else if self.payload > 0 {
+                let n = cmp::min(self.payload, buf.len());
+                self.payload -= n;
+                buf = &buf[n..];
+            } else {
+                let n = cmp::min(HEADER_LEN - self.header_len, buf.len());
+                self.header[self.header_len..self.header_len + n].copy_from_slice(&buf[..n]);
+                self.header_len += n;
+                buf = &buf[n..];
+                if self.header_len == HEADER_LEN {
+                    self.header_len = 0;
+                    let h = &self.header;
+                    let frame = Frame {
+                        len: u32::from_be_bytes([0, h[0], h[1], h[2]]),
+                        kind: h[3],
+                        flags: h[4],
+                        stream_id: u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7FFF_FFFF,
+                    };
+                    self.payload = frame.len as usize;
+                    on_frame(frame);
+                }
+            }
+        }
+    }
+}
+
+impl Frame {
+    fn kind_name(&self) -> &'static str {
+        match self.kind {
+            0x0 => "DATA",
+            0x1 => "HEADERS",
+            0x2 => "PRIORITY",
+            0x3 => "RST_STREAM",
+            0x4 => "SETTINGS",
+            0x5 => "PUSH_PROMISE",
+            0x6 => "PING",
+            0x7 => "GOAWAY",
+            0x8 => "WINDOW_UPDATE",
+            0x9 => "CONTINUATION",
+            _ => "UNKNOWN",
+        }
+    }
+
+    fn log(&self, direction: &'static str) {
+        crate::common::trace::h2_frame(
+            direction,
+            self.kind_name(),
+            self.stream_id,
+            self.flags,
+            self.len,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(decoder: &mut Decoder, chunks: &[&[u8]]) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for chunk in chunks {
+            decoder.decode(chunk, |frame| frames.push(frame));
+        }
+        frames
+    }
+
+    #[test]
+    fn decode_after_preface() {
+        let mut bytes = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+        // SETTINGS, empty, on stream 0.
+        bytes.extend_from_slice(&[0, 0, 0, 0x4, 0, 0, 0, 0, 0]);
+        // HEADERS, END_STREAM | END_HEADERS, on stream 1, 3 bytes.
+        bytes.extend_from_slice(&[0, 0, 3, 0x1, 0x5, 0, 0, 0, 1, 0xAA, 0xBB, 0xCC]);
+
+        let expected = vec![
+            Frame {
+                kind: 0x4,
+                flags: 0,
+                stream_id: 0,
+                len: 0,
+            },
+            Frame {
+                kind: 0x1,
+                flags: 0x5,
+                stream_id: 1,
+                len: 3,
+            },
+        ];
+
+        let mut decoder = Decoder::new(PREFACE_LEN);
+        assert_eq!(frames(&mut decoder, &[&bytes]), expected);
+
+        // The same, one byte at a time.
+        let mut decoder = Decoder::new(PREFACE_LEN);
+        let chunks = bytes.chunks(1).collect::<Vec<_>>();
+        assert_eq!(frames(&mut decoder, &chunks), expected);
+    }
+
+    #[test]
+    fn decode_ignores_reserved_bit() {
+        let mut decoder = Decoder::new(0);
+        let frame = frames(&mut decoder, &[&[0, 0, 0, 0x8, 0, 0x80, 0, 0, 3]]);
+        assert_eq!(frame[0].stream_id, 3);
+        assert_eq!(frame[0].kind_name(), "WINDOW_UPDATE");
+    }
+}
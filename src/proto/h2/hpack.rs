@@ -0,0 +1,133 @@
+//! Sizing of the HPACK dynamic table advertised to the peer.
+//!
+//! The advertised size bounds the table the peer's encoder may index
+//! headers into, so a larger one only pays off if the headers received
+//! repeat. `h2` only sends the setting in the connection preface, so the
+//! repetition seen on earlier connections sizes the table of later ones.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use http::HeaderMap;
+
+/// Default `SETTINGS_HEADER_TABLE_SIZE` defined in HTTP2 spec.
+const SPEC_HEADER_TABLE_SIZE: u32 = 4_096;
+/// The largest table the adaptive mode advertises.
+const MAX_ADAPTIVE_TABLE_SIZE: u32 = 64 * 1024;
+/// Keep the spec default until this many headers were observed.
+const MIN_SAMPLES: u64 = 256;
+/// The most distinct headers remembered per connection.
+const MAX_TRACKED: usize = 1024;
+
+/// Picks the table size for new connections, from the headers observed on
+/// earlier ones.
+///
+/// Clones share what was observed.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TableTuner {
+    stats: Arc<Stats>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    seen: AtomicU64,
+    repeated: AtomicU64,
+}
+
+/// Observes the headers received on a single connection.
+#[derive(Debug)]
+pub(crate) struct Observer {
+    stats: Arc<Stats>,
+    // Hashes of the `(name, value)` pairs received so far.
+    received: Mutex<HashSet<u64>>,
+}
+
+impl TableTuner {
+    /// The size to advertise to a new connection.
+    ///
+    /// It grows from the spec default towards the maximum with the share of
+    /// headers that repeated a header received earlier on its connection.
+    pub(crate) fn table_size(&self) -> u32 {
+        let seen = self.stats.seen.load(Ordering::Relaxed);
+        if seen < MIN_SAMPLES {
+            return SPEC_HEADER_TABLE_SIZE;
+        }
+        let repeated = self.stats.repeated.load(Ordering::Relaxed).min(seen);
+        let extra = u64::from(MAX_ADAPTIVE_TABLE_SIZE - SPEC_HEADER_TABLE_SIZE) * repeated / seen;
+        // Round down to a multiple of the default, so tiny changes in the
+        // ratio don't show up in every preface.
+        let extra = extra as u32 / SPEC_HEADER_TABLE_SIZE * SPEC_HEADER_TABLE_SIZE;
+        SPEC_HEADER_TABLE_SIZE + extra
+    }
+
+    pub(crate) fn observer(&self) -> Observer {
+        Observer {
+            stats: self.stats.clone(),
+            received: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+impl Observer {
+    pub(crate) fn observe(&self, headers: &HeaderMap) {
+        let mut received = self.received.lock().unwrap();
+        let mut repeated = 0;
+        for (name, value) in headers {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            value.hash(&mut hasher);
+            let hash = hasher.finish();
+            if received.contains(&hash) {
+                repeated += 1;
+            } else if received.len() < MAX_TRACKED {
+                received.insert(hash);
+            }
+        }
+        self.stats
+            .seen
+            .fetch_add(headers.len() as u64, Ordering::Relaxed);
+        self.stats.repeated.fetch_add(repeated, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn table_size_follows_repetition() {
+        let tuner = TableTuner::default();
+        assert_eq!(tuner.table_size(), SPEC_HEADER_TABLE_SIZE);
+
+        // Every request after the first repeats the same headers.
+        let observer = tuner.observer();
+        let same = headers(&[("user-agent", "test"), ("accept", "*/*")]);
+        for _ in 0..MIN_SAMPLES {
+            observer.observe(&same);
+        }
+        // All but the first two repeated, just short of the maximum.
+        assert_eq!(tuner.table_size(), 60 * 1024);
+
+        // Headers that never repeat keep the spec default.
+        let tuner = TableTuner::default();
+        let observer = tuner.observer();
+        for i in 0..MIN_SAMPLES {
+            let mut distinct = HeaderMap::new();
+            distinct.insert("x-id", i.into());
+            observer.observe(&distinct);
+        }
+        assert_eq!(tuner.table_size(), SPEC_HEADER_TABLE_SIZE);
+    }
+}
@@ -7,14 +7,20 @@ use std::error::Error as StdError;
 use std::io::{self, Cursor, IoSlice};
 use std::mem;
 use std::task::Context;
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tracing::{debug, trace, warn};
 
 use crate::body::Body;
 use crate::common::{task, Future, Pin, Poll};
 use crate::proto::h2::ping::Recorder;
+use crate::rt::{Read, ReadBufCursor, Write};
 
+mod frames;
+#[cfg(feature = "client")]
+pub(crate) mod hpack;
 pub(crate) mod ping;
+mod trailers;
+
+pub use self::trailers::TrailerPolicy;
 
 cfg_client! {
     pub(crate) mod client;
@@ -91,6 +97,8 @@ pin_project! {
     {
         body_tx: SendStream<SendBuf<S::Data>>,
         data_done: bool,
+        written: u64,
+        trailer_policy: TrailerPolicy,
         #[pin]
         stream: S,
     }
@@ -100,13 +108,25 @@ impl<S> PipeToSendStream<S>
 where
     S: Body,
 {
-    fn new(stream: S, tx: SendStream<SendBuf<S::Data>>) -> PipeToSendStream<S> {
+    fn new(
+        stream: S,
+        tx: SendStream<SendBuf<S::Data>>,
+        trailer_policy: TrailerPolicy,
+    ) -> PipeToSendStream<S> {
         PipeToSendStream {
             body_tx: tx,
             data_done: false,
+            written: 0,
+            trailer_policy,
             stream,
         }
     }
+
+    /// The number of body bytes sent so far.
+    #[cfg(feature = "server")]
+    fn bytes_written(&self) -> u64 {
+        self.written
+    }
 }
 
 impl<S> Future for PipeToSendStream<S>
@@ -149,9 +169,9 @@ where
                     .map_err(crate::Error::new_body_write)?
                 {
                     debug!("stream received RST_STREAM: {:?}", reason);
-                    return Poll::Ready(Err(crate::Error::new_body_write(::h2::Error::from(
-                        reason,
-                    ))));
+                    let err = ::h2::Error::from(reason);
+                    crate::common::trace::h2_error("stream", &err);
+                    return Poll::Ready(Err(crate::Error::new_body_write(err)));
                 }
 
                 match ready!(me.stream.as_mut().poll_data(cx)) {
@@ -163,6 +183,8 @@ where
                             is_eos,
                         );
 
+                        crate::common::trace::body_chunk("write", chunk.remaining());
+                        *me.written += chunk.remaining() as u64;
                         let buf = SendBuf::Buf(chunk);
                         me.body_tx
                             .send_data(buf, is_eos)
@@ -191,13 +213,18 @@ where
                     .map_err(crate::Error::new_body_write)?
                 {
                     debug!("stream received RST_STREAM: {:?}", reason);
-                    return Poll::Ready(Err(crate::Error::new_body_write(::h2::Error::from(
-                        reason,
-                    ))));
+                    let err = ::h2::Error::from(reason);
+                    crate::common::trace::h2_error("stream", &err);
+                    return Poll::Ready(Err(crate::Error::new_body_write(err)));
                 }
 
                 match ready!(me.stream.poll_trailers(cx)) {
-                    Ok(Some(trailers)) => {
+                    Ok(Some(mut trailers)) => {
+                        if let Err(name) = me.trailer_policy.check(&mut trailers) {
+                            let err = crate::Error::new_user_forbidden_trailer(name);
+                            me.body_tx.send_reset(err.h2_reason());
+                            return Poll::Ready(Err(err));
+                        }
                         me.body_tx
                             .send_trailers(trailers)
                             .map_err(crate::Error::new_body_write)?;
@@ -291,16 +318,34 @@ where
     send_stream: UpgradedSendStream<B>,
     recv_stream: RecvStream,
     buf: Bytes,
+    send_closed: bool,
+}
+
+impl<B> H2Upgraded<B>
+where
+    B: Buf,
+{
+    fn new(ping: Recorder, send_stream: SendStream<SendBuf<B>>, recv_stream: RecvStream) -> Self {
+        H2Upgraded {
+            ping,
+            // Safety: the send stream is only used to send data of the
+            // `Neutered` type, never the body type `B`.
+            send_stream: unsafe { UpgradedSendStream::new(send_stream) },
+            recv_stream,
+            buf: Bytes::new(),
+            send_closed: false,
+        }
+    }
 }
 
-impl<B> AsyncRead for H2Upgraded<B>
+impl<B> Read for H2Upgraded<B>
 where
     B: Buf,
 {
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        read_buf: &mut ReadBuf<'_>,
+        mut read_buf: ReadBufCursor<'_>,
     ) -> Poll<Result<(), io::Error>> {
         if self.buf.is_empty() {
             self.buf = loop {
@@ -333,7 +378,7 @@ where
     }
 }
 
-impl<B> AsyncWrite for H2Upgraded<B>
+impl<B> Write for H2Upgraded<B>
 where
     B: Buf,
 {
@@ -342,6 +387,10 @@ where
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize, io::Error>> {
+        if self.send_closed {
+            // Like writing to a socket that was shut down.
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
         if buf.is_empty() {
             return Poll::Ready(Ok(0));
         }
@@ -382,7 +431,11 @@ where
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Result<(), io::Error>> {
+        if self.send_closed {
+            return Poll::Ready(Ok(()));
+        }
         if self.send_stream.write(&[], true).is_ok() {
+            self.send_closed = true;
             return Poll::Ready(Ok(()))
         }
 
@@ -52,7 +52,7 @@ pub(super) fn channel(ping_pong: PingPong, config: Config, __timer: Time) -> (Re
     });
 
     let (bytes, next_bdp_at) = if bdp.is_some() {
-        (Some(0), Some(Instant::now()))
+        (Some(0), Some(__timer.now()))
     } else {
         (None, None)
     };
@@ -63,10 +63,10 @@ pub(super) fn channel(ping_pong: PingPong, config: Config, __timer: Time) -> (Re
         while_idle: config.keep_alive_while_idle,
         sleep: crate::common::into_pin(__timer.sleep(interval)),
         state: KeepAliveState::Init,
-        timer: __timer,
+        timer: __timer.clone(),
     });
 
-    let last_read_at = keep_alive.as_ref().map(|_| Instant::now());
+    let last_read_at = keep_alive.as_ref().map(|_| __timer.now());
 
     let shared = Arc::new(Mutex::new(Shared {
         bytes,
@@ -75,6 +75,7 @@ pub(super) fn channel(ping_pong: PingPong, config: Config, __timer: Time) -> (Re
         ping_pong,
         ping_sent_at: None,
         next_bdp_at,
+        timer: __timer,
     }));
 
     (
@@ -130,6 +131,9 @@ struct Shared {
     last_read_at: Option<Instant>,
 
     is_keep_alive_timed_out: bool,
+
+    /// Where the current time is read from.
+    timer: Time,
 }
 
 struct Bdp {
@@ -200,7 +204,7 @@ impl Recorder {
         // if not, we don't need to record bytes either
 
         if let Some(ref next_bdp_at) = locked.next_bdp_at {
-            if Instant::now() < *next_bdp_at {
+            if locked.timer.now() < *next_bdp_at {
                 return;
             } else {
                 locked.next_bdp_at = None;
@@ -259,8 +263,8 @@ impl Recorder {
 
 impl Ponger {
     pub(super) fn poll(&mut self, cx: &mut task::Context<'_>) -> Poll<Ponged> {
-        let now = Instant::now();
         let mut locked = self.shared.lock().unwrap();
+        let now = locked.timer.now();
         let is_idle = self.is_idle();
 
         if let Some(ref mut ka) = self.keep_alive {
@@ -329,7 +333,7 @@ impl Shared {
     fn send_ping(&mut self) {
         match self.ping_pong.send_ping(Ping::opaque()) {
             Ok(()) => {
-                self.ping_sent_at = Some(Instant::now());
+                self.ping_sent_at = Some(self.timer.now());
                 trace!("sent ping");
             }
             Err(err) => {
@@ -344,7 +348,7 @@ impl Shared {
 
     fn update_last_read_at(&mut self) {
         if self.last_read_at.is_some() {
-            self.last_read_at = Some(Instant::now());
+            self.last_read_at = Some(self.timer.now());
         }
     }
 
@@ -464,7 +468,7 @@ impl KeepAlive {
                 trace!("keep-alive interval ({:?}) reached", self.interval);
                 shared.send_ping();
                 self.state = KeepAliveState::PingSent;
-                let timeout = Instant::now() + self.timeout;
+                let timeout = self.timer.now() + self.timeout;
                 self.timer.reset(&mut self.sleep, timeout);
             }
             KeepAliveState::Init | KeepAliveState::PingSent => (),
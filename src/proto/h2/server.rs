@@ -1,26 +1,37 @@
+use std::collections::VecDeque;
 use std::error::Error as StdError;
 use std::marker::Unpin;
-
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::Bytes;
+use futures_channel::oneshot;
+use futures_util::task::AtomicWaker;
 use h2::server::{Connection, Handshake, SendResponse};
 use h2::{Reason, RecvStream};
 use http::{Method, Request};
 use pin_project_lite::pin_project;
-use tokio::io::{AsyncRead, AsyncWrite};
 use tracing::{debug, trace, warn};
 
-use super::{ping, PipeToSendStream, SendBuf};
+use super::frames::FrameLog;
+use super::{ping, PipeToSendStream, SendBuf, TrailerPolicy};
 use crate::body::Body;
+#[cfg(feature = "debug")]
+use crate::capture::CaptureHandle;
 use crate::common::exec::ConnStreamExec;
+use crate::common::io::Compat;
 use crate::common::time::Time;
 use crate::common::{date, task, Future, Pin, Poll};
-use crate::ext::Protocol;
+use crate::ext::{ConnExtensions, DisconnectNotifier, Protocol, ResponseOptions};
 use crate::headers;
+use crate::metrics::PollSource;
 use crate::proto::h2::ping::Recorder;
-use crate::proto::h2::{H2Upgraded, UpgradedSendStream};
-use crate::proto::Dispatched;
+use crate::proto::h2::H2Upgraded;
+use crate::proto::{Dispatched, ServiceTimeout};
+use crate::rt::{Read, Sleep, Write};
+use crate::server::conn::overflow::{OnStreamOverflow, StreamOverflow};
+use crate::server::conn::summary::{time_poll, OnRequestComplete, Record};
 use crate::service::HttpService;
 
 use crate::upgrade::{OnUpgrade, Pending, Upgraded};
@@ -51,6 +62,23 @@ pub(crate) struct Config {
     pub(crate) keep_alive_timeout: Duration,
     pub(crate) max_send_buffer_size: usize,
     pub(crate) max_header_list_size: u32,
+    pub(crate) max_request_body_size: Option<u64>,
+    pub(crate) on_request_complete: Option<OnRequestComplete>,
+    pub(crate) auto_headers: AutoHeaders,
+    pub(crate) trailer_policy: TrailerPolicy,
+    pub(crate) max_pending_responses: Option<usize>,
+    pub(crate) stream_overflow: Option<StreamOverflow>,
+    pub(crate) on_stream_overflow: Option<OnStreamOverflow>,
+    pub(crate) poll_budget: usize,
+    #[cfg(feature = "debug")]
+    pub(crate) capture: Option<CaptureHandle>,
+}
+
+/// Which headers are added to responses that lack them.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AutoHeaders {
+    pub(crate) date: bool,
+    pub(crate) content_length: bool,
 }
 
 impl Default for Config {
@@ -66,6 +94,19 @@ impl Default for Config {
             keep_alive_timeout: Duration::from_secs(20),
             max_send_buffer_size: DEFAULT_MAX_SEND_BUF_SIZE,
             max_header_list_size: DEFAULT_SETTINGS_MAX_HEADER_LIST_SIZE,
+            max_request_body_size: None,
+            on_request_complete: None,
+            auto_headers: AutoHeaders {
+                date: true,
+                content_length: true,
+            },
+            trailer_policy: TrailerPolicy::default(),
+            max_pending_responses: None,
+            stream_overflow: None,
+            on_stream_overflow: None,
+            poll_budget: crate::proto::DEFAULT_POLL_BUDGET,
+            #[cfg(feature = "debug")]
+            capture: None,
         }
     }
 }
@@ -76,10 +117,10 @@ pin_project! {
         S: HttpService<Recv>,
         B: Body,
     {
-        exec: E,
-        timer: Time,
-        service: S,
+        dispatch: Dispatch<S, E>,
         state: State<T, B>,
+        // Entered while polling, with the `tracing-spans` feature.
+        span: tracing::Span,
     }
 }
 
@@ -89,7 +130,9 @@ where
 {
     Handshaking {
         ping_config: ping::Config,
-        hs: Handshake<T, SendBuf<B::Data>>,
+        config: Config,
+        overflow: Option<Overflow>,
+        hs: Handshake<FrameLog<Compat<T>>, SendBuf<B::Data>>,
     },
     Serving(Serving<T, B>),
     Closed,
@@ -100,13 +143,30 @@ where
     B: Body,
 {
     ping: Option<(ping::Recorder, ping::Ponger)>,
-    conn: Connection<T, SendBuf<B::Data>>,
+    conn: Connection<FrameLog<Compat<T>>, SendBuf<B::Data>>,
+    config: Config,
+    // The number of streams whose response isn't finished yet.
+    active_streams: Arc<ActiveStreams>,
+    overflow: Option<Overflow>,
+    // Streams over the concurrency limit, waiting for others to finish.
+    queued: VecDeque<Accepted<B::Data>>,
     closing: Option<crate::Error>,
 }
 
+// What accepted streams are dispatched to.
+struct Dispatch<S, E> {
+    exec: E,
+    timer: Time,
+    service: S,
+    extensions: ConnExtensions,
+    service_timeout: ServiceTimeout,
+}
+
+type Accepted<D> = (Request<RecvStream>, SendResponse<SendBuf<D>>);
+
 impl<T, S, B, E> Server<T, S, B, E>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Read + Write + Unpin,
     S: HttpService<Recv, ResBody = B>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
     B: Body + 'static,
@@ -126,12 +186,24 @@ where
             .max_frame_size(config.max_frame_size)
             .max_header_list_size(config.max_header_list_size)
             .max_send_buffer_size(config.max_send_buffer_size);
-        if let Some(max) = config.max_concurrent_streams {
+        // With an overflow policy, the limit is enforced here instead, so
+        // h2 doesn't refuse the excess streams first.
+        let overflow = config.stream_overflow.and_then(|policy| {
+            config.max_concurrent_streams.map(|max| Overflow {
+                max: max as usize,
+                policy,
+                callback: config.on_stream_overflow.clone(),
+            })
+        });
+        if let (Some(max), None) = (config.max_concurrent_streams, &overflow) {
             builder.max_concurrent_streams(max);
         }
         if config.enable_connect_protocol {
             builder.enable_connect_protocol();
         }
+        let io = FrameLog::server(Compat::new(io));
+        #[cfg(feature = "debug")]
+        let io = io.with_capture(config.capture.as_ref().and_then(CaptureHandle::start));
         let handshake = builder.handshake(io);
 
         let bdp = if config.adaptive_window {
@@ -150,69 +222,73 @@ where
         };
 
         Server {
-            exec,
-            timer,
+            dispatch: Dispatch {
+                exec,
+                timer,
+                service,
+                extensions: ConnExtensions::default(),
+                service_timeout: ServiceTimeout::default(),
+            },
             state: State::Handshaking {
                 ping_config,
+                config: config.clone(),
+                overflow,
                 hs: handshake,
             },
-            service,
+            span: crate::common::trace::conn_span("server", "HTTP/2"),
         }
     }
 
-    pub(crate) fn graceful_shutdown(&mut self) {
-        trace!("graceful_shutdown");
+    pub(crate) fn conn_extensions_mut(&mut self) -> &mut ConnExtensions {
+        &mut self.dispatch.extensions
+    }
+
+    pub(crate) fn set_service_timeout(&mut self, timeout: ServiceTimeout) {
+        self.dispatch.service_timeout = timeout;
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
         match self.state {
-            State::Handshaking { .. } => {
-                // fall-through, to replace state with Closed
-            }
-            State::Serving(ref mut srv) => {
-                if srv.closing.is_none() {
-                    srv.conn.graceful_shutdown();
-                }
-                return;
-            }
-            State::Closed => {
-                return;
-            }
+            State::Serving(ref srv) => srv.active_streams.count.load(Ordering::Acquire),
+            State::Handshaking { .. } | State::Closed => 0,
         }
-        self.state = State::Closed;
     }
-}
-
-impl<T, S, B, E> Future for Server<T, S, B, E>
-where
-    T: AsyncRead + AsyncWrite + Unpin,
-    S: HttpService<Recv, ResBody = B>,
-    S::Error: Into<Box<dyn StdError + Send + Sync>>,
-    B: Body + 'static,
-    E: ConnStreamExec<S::Future, B>,
-{
-    type Output = crate::Result<Dispatched>;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
-        let me = &mut *self;
+    fn poll_state(&mut self, cx: &mut task::Context<'_>) -> Poll<crate::Result<Dispatched>> {
         loop {
-            let next = match me.state {
+            let next = match self.state {
                 State::Handshaking {
                     ref mut hs,
                     ref ping_config,
+                    ref mut config,
+                    ref mut overflow,
                 } => {
                     let mut conn = ready!(Pin::new(hs).poll(cx).map_err(crate::Error::new_h2))?;
                     let ping = if ping_config.is_enabled() {
                         let pp = conn.ping_pong().expect("conn.ping_pong");
-                        Some(ping::channel(pp, ping_config.clone(), me.timer.clone()))
+                        Some(ping::channel(
+                            pp,
+                            ping_config.clone(),
+                            self.dispatch.timer.clone(),
+                        ))
                     } else {
                         None
                     };
                     State::Serving(Serving {
                         ping,
                         conn,
+                        config: std::mem::take(config),
+                        active_streams: Arc::new(ActiveStreams {
+                            count: AtomicUsize::new(0),
+                            waker: AtomicWaker::new(),
+                        }),
+                        overflow: overflow.take(),
+                        queued: VecDeque::new(),
                         closing: None,
                     })
                 }
                 State::Serving(ref mut srv) => {
-                    ready!(srv.poll_server(cx, &mut me.service, &mut me.exec))?;
+                    ready!(srv.poll_server(cx, &mut self.dispatch))?;
                     return Poll::Ready(Ok(Dispatched::Shutdown));
                 }
                 State::Closed => {
@@ -221,21 +297,58 @@ where
                     return Poll::Ready(Ok(Dispatched::Shutdown));
                 }
             };
-            me.state = next;
+            self.state = next;
+        }
+    }
+
+    pub(crate) fn graceful_shutdown(&mut self) {
+        trace!("graceful_shutdown");
+        match self.state {
+            State::Handshaking { .. } => {
+                // fall-through, to replace state with Closed
+            }
+            State::Serving(ref mut srv) => {
+                if srv.closing.is_none() {
+                    srv.conn.graceful_shutdown();
+                }
+                return;
+            }
+            State::Closed => {
+                return;
+            }
         }
+        self.state = State::Closed;
+    }
+}
+
+impl<T, S, B, E> Future for Server<T, S, B, E>
+where
+    T: Read + Write + Unpin,
+    S: HttpService<Recv, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + 'static,
+    E: ConnStreamExec<S::Future, B>,
+{
+    type Output = crate::Result<Dispatched>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        let result = ready!(self.poll_state(cx));
+        crate::common::trace::conn_closed(result.as_ref().err());
+        Poll::Ready(result)
     }
 }
 
 impl<T, B> Serving<T, B>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Read + Write + Unpin,
     B: Body + 'static,
 {
     fn poll_server<S, E>(
         &mut self,
         cx: &mut task::Context<'_>,
-        service: &mut S,
-        exec: &mut E,
+        dispatch: &mut Dispatch<S, E>,
     ) -> Poll<crate::Result<()>>
     where
         S: HttpService<Recv, ResBody = B>,
@@ -243,59 +356,79 @@ where
         E: ConnStreamExec<S::Future, B>,
     {
         if self.closing.is_none() {
+            let mut budget = self.config.poll_budget;
             loop {
                 self.poll_ping(cx);
 
+                // Serve queued streams as others finish.
+                while let Some(max) = self.overflow.as_ref().map(|overflow| overflow.max) {
+                    if self.queued.is_empty() {
+                        break;
+                    }
+                    self.active_streams.waker.register(cx.waker());
+                    if self.active_streams.count.load(Ordering::Acquire) >= max {
+                        break;
+                    }
+                    let (req, respond) = self.queued.pop_front().expect("queued stream");
+                    if !self.serve_stream(req, respond, dispatch) {
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+
+                if let Some(max) = self.config.max_pending_responses {
+                    // Register first, so a stream finishing in between
+                    // isn't missed.
+                    self.active_streams.waker.register(cx.waker());
+                    if self.active_streams.count.load(Ordering::Acquire) >= max {
+                        trace!("max pending responses reached, pausing accept");
+                        // Keep driving the connection, while new streams
+                        // wait in h2 until some responses are finished.
+                        ready!(self.conn.poll_closed(cx).map_err(crate::Error::new_h2))?;
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+
+                // Limit the streams accepted in a single poll, in case they
+                // keep coming, so that other futures don't starve.
+                if budget == 0 {
+                    trace!("poll_server yielding");
+                    return task::yield_now(cx).map(|never| match never {});
+                }
+
                 match ready!(self.conn.poll_accept(cx)) {
                     Some(Ok((req, mut respond))) => {
-                        trace!("incoming request");
-                        let content_length = headers::content_length_parse_all(req.headers());
-                        let ping = self
-                            .ping
-                            .as_ref()
-                            .map(|ping| ping.0.clone())
-                            .unwrap_or_else(ping::disabled);
-
-                        // Record the headers received
-                        ping.record_non_data();
-
-                        let is_connect = req.method() == Method::CONNECT;
-                        let (mut parts, stream) = req.into_parts();
-                        let (mut req, connect_parts) = if !is_connect {
-                            (
-                                Request::from_parts(
-                                    parts,
-                                    crate::Recv::h2(stream, content_length.into(), ping),
-                                ),
-                                None,
-                            )
-                        } else {
-                            if content_length.map_or(false, |len| len != 0) {
-                                warn!("h2 connect request with non-zero body not supported");
-                                respond.send_reset(h2::Reason::INTERNAL_ERROR);
-                                return Poll::Ready(Ok(()));
+                        budget -= 1;
+                        if let Some(ref overflow) = self.overflow {
+                            let active = self.active_streams.count.load(Ordering::Acquire);
+                            if active >= overflow.max || !self.queued.is_empty() {
+                                match overflow.policy {
+                                    StreamOverflow::Queue(max) if self.queued.len() < max => {
+                                        trace!("max concurrent streams reached, queueing stream");
+                                        self.queued.push_back((req, respond));
+                                        continue;
+                                    }
+                                    StreamOverflow::Queue(_) | StreamOverflow::Refuse => {
+                                        debug!("max concurrent streams reached, refusing stream");
+                                        respond.send_reset(h2::Reason::REFUSED_STREAM);
+                                        overflow.notify(StreamOverflow::Refuse);
+                                        continue;
+                                    }
+                                    StreamOverflow::GoAway => {
+                                        debug!("max concurrent streams reached, going away");
+                                        respond.send_reset(h2::Reason::REFUSED_STREAM);
+                                        self.conn.graceful_shutdown();
+                                        overflow.notify(StreamOverflow::GoAway);
+                                        continue;
+                                    }
+                                }
                             }
-                            let (pending, upgrade) = crate::upgrade::pending();
-                            debug_assert!(parts.extensions.get::<OnUpgrade>().is_none());
-                            parts.extensions.insert(upgrade);
-                            (
-                                Request::from_parts(parts, crate::Recv::empty()),
-                                Some(ConnectParts {
-                                    pending,
-                                    ping,
-                                    recv_stream: stream,
-                                }),
-                            )
-                        };
-
-                        if let Some(protocol) = req.extensions_mut().remove::<h2::ext::Protocol>() {
-                            req.extensions_mut().insert(Protocol::from_inner(protocol));
                         }
-
-                        let fut = H2Stream::new(service.call(req), connect_parts, respond);
-                        exec.execute_h2stream(fut);
+                        if !self.serve_stream(req, respond, dispatch) {
+                            return Poll::Ready(Ok(()));
+                        }
                     }
                     Some(Err(e)) => {
+                        crate::common::trace::h2_error("connection", &e);
                         return Poll::Ready(Err(crate::Error::new_h2(e)));
                     }
                     None => {
@@ -321,6 +454,108 @@ where
         Poll::Ready(Err(self.closing.take().expect("polled after error")))
     }
 
+    // Dispatch an accepted stream to the service. Returns false if the
+    // connection should stop serving.
+    fn serve_stream<S, E>(
+        &mut self,
+        mut req: Request<RecvStream>,
+        mut respond: SendResponse<SendBuf<B::Data>>,
+        dispatch: &mut Dispatch<S, E>,
+    ) -> bool
+    where
+        S: HttpService<Recv, ResBody = B>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: ConnStreamExec<S::Future, B>,
+    {
+        trace!("incoming request");
+        let content_length = headers::content_length_parse_all(req.headers());
+        if let (Some(max), Some(len)) = (self.config.max_request_body_size, content_length) {
+            if len > max {
+                debug!("incoming body length ({}) is over the maximum", len);
+                respond.send_reset(h2::Reason::CANCEL);
+                return true;
+            }
+        }
+        let ping = self
+            .ping
+            .as_ref()
+            .map(|ping| ping.0.clone())
+            .unwrap_or_else(ping::disabled);
+
+        // Record the headers received
+        ping.record_non_data();
+
+        let is_connect = req.method() == Method::CONNECT;
+        let record = self
+            .config
+            .on_request_complete
+            .as_ref()
+            .map(|callback| callback.record(&mut req));
+        let mut body_limit = None;
+        let (mut parts, stream) = req.into_parts();
+        let (mut req, connect_parts) = if !is_connect {
+            let mut body = crate::Recv::h2(
+                stream,
+                content_length.into(),
+                ping,
+                self.config.trailer_policy,
+            );
+            body_limit = self
+                .config
+                .max_request_body_size
+                .map(|max| body.h2_limit(max));
+            if let Some(ref record) = record {
+                body.h2_count_received(record.read_counter());
+            }
+            (Request::from_parts(parts, body), None)
+        } else {
+            if content_length.map_or(false, |len| len != 0) {
+                warn!("h2 connect request with non-zero body not supported");
+                respond.send_reset(h2::Reason::INTERNAL_ERROR);
+                return false;
+            }
+            let (pending, upgrade) = crate::upgrade::pending();
+            debug_assert!(parts.extensions.get::<OnUpgrade>().is_none());
+            parts.extensions.insert(upgrade);
+            (
+                Request::from_parts(parts, crate::Recv::empty()),
+                Some(ConnectParts {
+                    pending,
+                    ping,
+                    recv_stream: stream,
+                }),
+            )
+        };
+
+        if let Some(protocol) = req.extensions_mut().remove::<h2::ext::Protocol>() {
+            req.extensions_mut().insert(Protocol::from_inner(protocol));
+        }
+        dispatch.extensions.apply(req.extensions_mut());
+        let disconnect = dispatch.extensions.peer_disconnect(req.extensions_mut());
+
+        let timeout = dispatch
+            .service_timeout
+            .duration
+            .map(|duration| (dispatch.service_timeout, dispatch.timer.sleep(duration)));
+        let fut = H2Stream {
+            reply: respond,
+            state: H2StreamState::Service {
+                fut: dispatch.service.call(req),
+                connect_parts,
+            },
+            timeout,
+            body_limit,
+            record,
+            disconnect,
+            active: Some(ActiveStream::new(&self.active_streams)),
+            auto_headers: self.config.auto_headers,
+            trailer_policy: self.config.trailer_policy,
+            timer: dispatch.timer.clone(),
+        };
+        dispatch.exec.execute_h2stream(fut);
+        true
+    }
+
     fn poll_ping(&mut self, cx: &mut task::Context<'_>) {
         if let Some((_, ref mut estimator)) = self.ping {
             match estimator.poll(cx) {
@@ -347,6 +582,17 @@ pin_project! {
         reply: SendResponse<SendBuf<B::Data>>,
         #[pin]
         state: H2StreamState<F, B>,
+        timeout: Option<(ServiceTimeout, Box<dyn Sleep + Unpin>)>,
+        // Completes if the request body goes over the maximum size.
+        body_limit: Option<oneshot::Receiver<()>>,
+        record: Option<Record>,
+        // Fires the request's `PeerDisconnect` if dropped before the
+        // response is written.
+        disconnect: Option<DisconnectNotifier>,
+        active: Option<ActiveStream>,
+        auto_headers: AutoHeaders,
+        trailer_policy: TrailerPolicy,
+        timer: Time,
     }
 }
 
@@ -368,34 +614,57 @@ pin_project! {
     }
 }
 
-struct ConnectParts {
-    pending: Pending,
-    ping: Recorder,
-    recv_stream: RecvStream,
+struct Overflow {
+    // The maximum number of active streams.
+    max: usize,
+    policy: StreamOverflow,
+    callback: Option<OnStreamOverflow>,
 }
 
-impl<F, B> H2Stream<F, B>
-where
-    B: Body,
-{
-    fn new(
-        fut: F,
-        connect_parts: Option<ConnectParts>,
-        respond: SendResponse<SendBuf<B::Data>>,
-    ) -> H2Stream<F, B> {
-        H2Stream {
-            reply: respond,
-            state: H2StreamState::Service { fut, connect_parts },
+impl Overflow {
+    fn notify(&self, shed: StreamOverflow) {
+        if let Some(ref callback) = self.callback {
+            callback.call(shed);
         }
     }
 }
 
+struct ActiveStreams {
+    count: AtomicUsize,
+    // Woken as streams finish, if accepting is paused.
+    waker: AtomicWaker,
+}
+
+// Counts a stream as active until dropped.
+struct ActiveStream(Arc<ActiveStreams>);
+
+impl ActiveStream {
+    fn new(streams: &Arc<ActiveStreams>) -> ActiveStream {
+        streams.count.fetch_add(1, Ordering::AcqRel);
+        ActiveStream(streams.clone())
+    }
+}
+
+impl Drop for ActiveStream {
+    fn drop(&mut self) {
+        self.0.count.fetch_sub(1, Ordering::AcqRel);
+        self.0.waker.wake();
+    }
+}
+
+struct ConnectParts {
+    pending: Pending,
+    ping: Recorder,
+    recv_stream: RecvStream,
+}
+
 macro_rules! reply {
     ($me:expr, $res:expr, $eos:expr) => {{
         match $me.reply.send_response($res, $eos) {
             Ok(tx) => tx,
             Err(e) => {
                 debug!("send response error: {}", e);
+                crate::common::trace::h2_error("stream", &e);
                 $me.reply.send_reset(Reason::INTERNAL_ERROR);
                 return Poll::Ready(Err(crate::Error::new_h2(e)));
             }
@@ -413,13 +682,27 @@ where
 {
     fn poll2(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<crate::Result<()>> {
         let mut me = self.project();
+        // Checked after polling the service, so a service that fails from
+        // reading too much of the body still gets the stream reset with
+        // `CANCEL`.
+        macro_rules! check_body_limit {
+            () => {
+                if poll_body_limit(me.body_limit, cx) {
+                    debug!("incoming body is over the maximum length");
+                    me.reply.send_reset(Reason::CANCEL);
+                    return Poll::Ready(Err(crate::Error::new_body_too_large()));
+                }
+            };
+        }
         loop {
             let next = match me.state.as_mut().project() {
                 H2StreamStateProj::Service {
                     fut: h,
                     connect_parts,
                 } => {
-                    let res = match h.poll(cx) {
+                    let res = time_poll(me.record.as_ref(), PollSource::Service, || h.poll(cx));
+                    check_body_limit!();
+                    let res = match res {
                         Poll::Ready(Ok(r)) => r,
                         Poll::Pending => {
                             // Response is not yet ready, so we want to check if the client has sent a
@@ -430,6 +713,27 @@ where
                                 debug!("stream received RST_STREAM: {:?}", reason);
                                 return Poll::Ready(Err(crate::Error::new_h2(reason.into())));
                             }
+                            if let Some((ref timeout, ref mut sleep)) = me.timeout {
+                                ready!(Pin::new(sleep).poll(cx));
+                                debug!("service timed out");
+                                if timeout.reset {
+                                    me.reply.send_reset(Reason::CANCEL);
+                                } else {
+                                    if let Some(ref mut record) = me.record {
+                                        record.response(timeout.status);
+                                    }
+                                    let mut res = ::http::Response::new(());
+                                    *res.status_mut() = timeout.status;
+                                    if me.auto_headers.date {
+                                        res.headers_mut().insert(
+                                            ::http::header::DATE,
+                                            date::update_and_header_value(me.timer),
+                                        );
+                                    }
+                                    reply!(me, res, true);
+                                }
+                                return Poll::Ready(Ok(()));
+                            }
                             return Poll::Pending;
                         }
                         Poll::Ready(Err(e)) => {
@@ -443,11 +747,22 @@ where
                     let (head, body) = res.into_parts();
                     let mut res = ::http::Response::from_parts(head, ());
                     super::strip_connection_headers(res.headers_mut(), false);
+                    if let Some(ref mut record) = me.record {
+                        record.response(res.status());
+                    }
+
+                    let date_header = res
+                        .extensions_mut()
+                        .remove::<ResponseOptions>()
+                        .map_or(true, |options| options.date_header());
 
                     // set Date header if it isn't already set...
-                    res.headers_mut()
-                        .entry(::http::header::DATE)
-                        .or_insert_with(date::update_and_header_value);
+                    if me.auto_headers.date && date_header {
+                        let timer = &*me.timer;
+                        res.headers_mut()
+                            .entry(::http::header::DATE)
+                            .or_insert_with(|| date::update_and_header_value(timer));
+                    }
 
                     if let Some(connect_parts) = connect_parts.take() {
                         if res.status().is_success() {
@@ -460,12 +775,11 @@ where
                             }
                             let send_stream = reply!(me, res, false);
                             connect_parts.pending.fulfill(Upgraded::new(
-                                H2Upgraded {
-                                    ping: connect_parts.ping,
-                                    recv_stream: connect_parts.recv_stream,
-                                    send_stream: unsafe { UpgradedSendStream::new(send_stream) },
-                                    buf: Bytes::new(),
-                                },
+                                H2Upgraded::new(
+                                    connect_parts.ping,
+                                    send_stream,
+                                    connect_parts.recv_stream,
+                                ),
                                 Bytes::new(),
                             ));
                             return Poll::Ready(Ok(()));
@@ -474,21 +788,30 @@ where
 
                     if !body.is_end_stream() {
                         // automatically set Content-Length from body...
-                        if let Some(len) = body.size_hint().exact() {
-                            headers::set_content_length_if_missing(res.headers_mut(), len);
+                        if me.auto_headers.content_length {
+                            if let Some(len) = body.size_hint().exact() {
+                                headers::set_content_length_if_missing(res.headers_mut(), len);
+                            }
                         }
 
                         let body_tx = reply!(me, res, false);
                         H2StreamState::Body {
-                            pipe: PipeToSendStream::new(body, body_tx),
+                            pipe: PipeToSendStream::new(body, body_tx, *me.trailer_policy),
                         }
                     } else {
                         reply!(me, res, true);
                         return Poll::Ready(Ok(()));
                     }
                 }
-                H2StreamStateProj::Body { pipe } => {
-                    return pipe.poll(cx);
+                H2StreamStateProj::Body { mut pipe } => {
+                    let res = time_poll(me.record.as_ref(), PollSource::Body, || {
+                        pipe.as_mut().poll(cx)
+                    });
+                    check_body_limit!();
+                    if let (Poll::Ready(Ok(())), Some(record)) = (&res, me.record.as_mut()) {
+                        record.wrote(pipe.bytes_written());
+                    }
+                    return res;
                 }
             };
             me.state.set(next);
@@ -496,6 +819,21 @@ where
     }
 }
 
+// Returns whether the request body went over the maximum size.
+fn poll_body_limit(
+    body_limit: &mut Option<oneshot::Receiver<()>>,
+    cx: &mut task::Context<'_>,
+) -> bool {
+    if let Some(ref mut exceeded) = *body_limit {
+        match Pin::new(exceeded).poll(cx) {
+            Poll::Ready(Ok(())) => return true,
+            Poll::Ready(Err(_dropped)) => *body_limit = None,
+            Poll::Pending => (),
+        }
+    }
+    false
+}
+
 impl<F, B, E> Future for H2Stream<F, B>
 where
     F: Future<Output = Result<Response<B>, E>>,
@@ -506,11 +844,25 @@ where
 {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
-        self.poll2(cx).map(|res| {
-            if let Err(e) = res {
-                debug!("stream error: {}", e);
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let res = ready!(self.as_mut().poll2(cx));
+        // If the stream errored, dropping the record and the disconnect
+        // notifier reports that.
+        let me = self.project();
+        me.active.take();
+        let record = me.record.take();
+        let disconnect = me.disconnect.take();
+        match res {
+            Ok(()) => {
+                if let Some(record) = record {
+                    record.complete();
+                }
+                if let Some(disconnect) = disconnect {
+                    disconnect.complete();
+                }
             }
-        })
+            Err(e) => debug!("stream error: {}", e),
+        }
+        Poll::Ready(())
     }
 }
@@ -0,0 +1,114 @@
+//! Checks of trailer fields against [RFC 9110, section 6.5.1].
+//!
+//! [RFC 9110, section 6.5.1]: https://www.rfc-editor.org/rfc/rfc9110#section-6.5.1
+
+use http::header::{self, HeaderName};
+use http::HeaderMap;
+use tracing::debug;
+
+/// Fields a sender must not put in trailers, since recipients act on them
+/// before any trailer is received.
+const FORBIDDEN: &[HeaderName] = &[
+    // framing
+    header::CONTENT_LENGTH,
+    header::TRANSFER_ENCODING,
+    header::TRAILER,
+    header::CONNECTION,
+    header::TE,
+    header::UPGRADE,
+    // routing
+    header::HOST,
+    // request modifiers
+    header::CACHE_CONTROL,
+    header::EXPECT,
+    header::MAX_FORWARDS,
+    header::PRAGMA,
+    header::RANGE,
+    // authentication
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::WWW_AUTHENTICATE,
+    header::PROXY_AUTHENTICATE,
+    header::SET_COOKIE,
+    // content
+    header::CONTENT_ENCODING,
+    header::CONTENT_TYPE,
+    header::CONTENT_RANGE,
+];
+
+/// What to do with fields that [RFC 9110] forbids in trailers, such as
+/// `content-length`, `host`, or `authorization`, in HTTP/2 trailers sent
+/// and received.
+///
+/// [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-6.5.1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailerPolicy {
+    /// Send and receive trailers as they are.
+    ///
+    /// This is the default.
+    Allow,
+    /// Remove forbidden fields from trailers.
+    Sanitize,
+    /// Fail the body: the stream is reset when sending, and reading the
+    /// trailers returns an error when receiving.
+    Reject,
+}
+
+impl TrailerPolicy {
+    /// Applies the policy to trailers, returning the forbidden field that
+    /// made it fail.
+    pub(crate) fn check(self, trailers: &mut HeaderMap) -> Result<(), HeaderName> {
+        if self == TrailerPolicy::Allow {
+            return Ok(());
+        }
+        for name in FORBIDDEN {
+            if !trailers.contains_key(name) {
+                continue;
+            }
+            if self == TrailerPolicy::Reject {
+                debug!("forbidden trailer field: {}", name);
+                return Err(name.clone());
+            }
+            debug!("removing forbidden trailer field: {}", name);
+            trailers.remove(name);
+        }
+        Ok(())
+    }
+}
+
+impl Default for TrailerPolicy {
+    fn default() -> TrailerPolicy {
+        TrailerPolicy::Allow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::{CONTENT_LENGTH, HOST};
+    use http::HeaderMap;
+
+    use super::TrailerPolicy;
+
+    #[test]
+    fn trailer_policy() {
+        let trailers = || {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", "0".parse().unwrap());
+            trailers.insert(CONTENT_LENGTH, "5".parse().unwrap());
+            trailers.insert(HOST, "example.com".parse().unwrap());
+            trailers
+        };
+
+        let mut allowed = trailers();
+        TrailerPolicy::Allow.check(&mut allowed).unwrap();
+        assert_eq!(allowed.len(), 3);
+
+        let mut sanitized = trailers();
+        TrailerPolicy::Sanitize.check(&mut sanitized).unwrap();
+        assert_eq!(sanitized.len(), 1);
+        assert_eq!(sanitized["grpc-status"], "0");
+
+        let err = TrailerPolicy::Reject.check(&mut trailers()).unwrap_err();
+        assert_eq!(err, CONTENT_LENGTH);
+    }
+}
@@ -16,6 +16,13 @@ cfg_feature! {
 #[cfg(feature = "http2")]
 pub(crate) mod h2;
 
+/// How many times a connection makes progress in a single poll, before
+/// yielding so other futures on the same task aren't starved.
+///
+/// 16 was chosen arbitrarily, as that is number of pipelined requests
+/// benchmarks often use.
+pub(crate) const DEFAULT_POLL_BUDGET: usize = 16;
+
 /// An Incoming Message head. Includes request/status line, and headers.
 #[derive(Debug, Default)]
 pub(crate) struct MessageHead<S> {
@@ -50,6 +57,30 @@ pub(crate) enum BodyLength {
     Unknown,
 }
 
+/// What a server does when its service is too slow to respond.
+#[cfg(feature = "server")]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ServiceTimeout {
+    /// How long the service may take to produce the response head.
+    pub(crate) duration: Option<std::time::Duration>,
+    /// The status of the response sent instead.
+    pub(crate) status: http::StatusCode,
+    /// Whether to reset the stream (or close an HTTP/1 connection) instead
+    /// of sending a response.
+    pub(crate) reset: bool,
+}
+
+#[cfg(feature = "server")]
+impl Default for ServiceTimeout {
+    fn default() -> ServiceTimeout {
+        ServiceTimeout {
+            duration: None,
+            status: http::StatusCode::SERVICE_UNAVAILABLE,
+            reset: false,
+        }
+    }
+}
+
 /// Status of when a Disaptcher future completes.
 pub(crate) enum Dispatched {
     /// Dispatcher completely shutdown connection.
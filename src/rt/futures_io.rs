@@ -0,0 +1,187 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+use super::{Read, ReadBuf, ReadBufCursor, Write};
+
+pin_project! {
+    /// A wrapper that implements hyper's IO traits for a type that
+    /// implements the `futures-io` traits, and the other way around.
+    ///
+    /// This allows using the IO types of runtimes built on `futures-io`,
+    /// such as `async-std` or `smol`, with hyper's connections. It also
+    /// allows using an upgraded connection as a `futures-io` type.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "client", feature = "http1"))]
+    /// # async fn run<S>(stream: S) -> hyper::Result<()>
+    /// # where
+    /// #     S: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin + Send + 'static,
+    /// # {
+    /// use hyper::client::conn::http1;
+    /// use hyper::rt::FuturesIo;
+    ///
+    /// let (sender, conn) = http1::handshake::<_, hyper::Recv>(FuturesIo::new(stream)).await?;
+    /// # drop(sender);
+    /// # conn.await
+    /// # }
+    /// # fn main() {}
+    /// ```
+    #[derive(Debug)]
+    pub struct FuturesIo<T> {
+        #[pin]
+        inner: T,
+    }
+}
+
+impl<T> FuturesIo<T> {
+    /// Wrap a type implementing the `futures-io` IO traits, or hyper's.
+    pub fn new(inner: T) -> Self {
+        FuturesIo { inner }
+    }
+
+    /// Borrow the inner type.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Mutably borrow the inner type.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner type.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Read for FuturesIo<T>
+where
+    T: futures_io::AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        let n = ready!(futures_io::AsyncRead::poll_read(
+            self.project().inner,
+            cx,
+            buf.initialize_unfilled()
+        ))?;
+
+        // SAFETY: The whole unfilled part was initialized above.
+        unsafe {
+            buf.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Write for FuturesIo<T>
+where
+    T: futures_io::AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        futures_io::AsyncWrite::poll_write(self.project().inner, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        futures_io::AsyncWrite::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        futures_io::AsyncWrite::poll_close(self.project().inner, cx)
+    }
+}
+
+impl<T> futures_io::AsyncRead for FuturesIo<T>
+where
+    T: Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(Read::poll_read(self.project().inner, cx, buf.unfilled()))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl<T> futures_io::AsyncWrite for FuturesIo<T>
+where
+    T: Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Write::poll_write(self.project().inner, cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        Write::poll_write_vectored(self.project().inner, cx, bufs)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Write::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Write::poll_shutdown(self.project().inner, cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::common::io::Compat;
+
+    #[tokio::test]
+    async fn read_both_directions() {
+        // hyper -> futures-io -> hyper
+        let mut io = FuturesIo::new(FuturesIo::new(Compat::new(Cursor::new(
+            b"hello world".to_vec(),
+        ))));
+
+        let mut raw = [std::mem::MaybeUninit::uninit(); 5];
+        let mut buf = ReadBuf::uninit(&mut raw);
+        futures_util::future::poll_fn(|cx| Pin::new(&mut io).poll_read(cx, buf.unfilled()))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn write_both_directions() {
+        // hyper -> futures-io -> hyper
+        let mut io = FuturesIo::new(FuturesIo::new(Compat::new(Vec::new())));
+
+        let n = futures_util::future::poll_fn(|cx| Pin::new(&mut io).poll_write(cx, b"hello"))
+            .await
+            .unwrap();
+        assert_eq!(n, 5);
+        futures_util::future::poll_fn(|cx| Pin::new(&mut io).poll_shutdown(cx))
+            .await
+            .unwrap();
+        assert_eq!(io.into_inner().into_inner().0, b"hello");
+    }
+}
@@ -0,0 +1,266 @@
+//! Runtime components
+//!
+//! The traits and types within this module are used to allow plugging in
+//! runtime types. These include:
+//!
+//! - Executors
+//! - Timers
+//! - Clocks
+//! - IO transports
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+#[cfg(feature = "futures-io")]
+mod futures_io;
+mod io;
+#[cfg(feature = "smol")]
+mod smol;
+#[cfg(feature = "tokio")]
+mod tokio;
+mod wheel;
+
+#[cfg(feature = "futures-io")]
+#[cfg_attr(docsrs, doc(cfg(feature = "futures-io")))]
+pub use self::futures_io::FuturesIo;
+pub use self::io::{Read, ReadBuf, ReadBufCursor, Write};
+#[cfg(feature = "smol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "smol")))]
+pub use self::smol::{SmolExecutor, SmolTimer};
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use self::tokio::{TokioExecutor, TokioIo, TokioTimer};
+pub use self::wheel::{TimerWheel, TimerWheelDriver};
+
+/// An executor of futures.
+pub trait Executor<Fut> {
+    /// Place the future into the executor to be run.
+    fn execute(&self, fut: Fut);
+}
+
+/// A timer which provides timer-like functions.
+pub trait Timer {
+    /// Return a future that resolves in `duration` time.
+    fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin>;
+
+    /// Return a future that resolves at `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin>;
+
+    /// Return the current instant, as this timer measures deadlines.
+    ///
+    /// The default implementation returns `Instant::now()`. A timer whose
+    /// time can be paused or advanced by hand should override it.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Return an interval that ticks every `period`, starting one `period`
+    /// from now.
+    ///
+    /// The default implementation waits on a new `sleep_until` for each
+    /// tick, using a clone of the timer. Timers with a native interval
+    /// can override it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    fn interval(&self, period: Duration) -> Box<dyn Interval + Unpin>
+    where
+        Self: Clone + Send + Sync + Unpin + Sized + 'static,
+    {
+        assert!(!period.is_zero(), "interval period must be non-zero");
+        let deadline = self.now() + period;
+        Box::new(SleepInterval {
+            timer: self.clone(),
+            period,
+            deadline,
+            sleep: self.sleep_until(deadline),
+        })
+    }
+
+    /// Reset a future to resolve at `new_deadline` instead.
+    fn reset(&self, sleep: &mut Pin<Box<dyn Sleep>>, new_deadline: Instant) {
+        *sleep = crate::common::into_pin(self.sleep_until(new_deadline));
+    }
+}
+
+/// A source of the current time.
+///
+/// hyper reads the time from a `Clock` to compute keep-alive and timeout
+/// deadlines, and to write `Date` headers. By default, [`Timer::now`] and
+/// the system clock are used. Providing a clock that is frozen, or stepped by hand, makes these
+/// deterministic in tests.
+///
+/// A clock is only read from, the sleeps of a [`Timer`] are still timed by
+/// the timer itself.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::Mutex;
+/// use std::time::{Duration, Instant, SystemTime};
+///
+/// use hyper::rt::Clock;
+///
+/// struct ManualClock {
+///     start: (Instant, SystemTime),
+///     elapsed: Mutex<Duration>,
+/// }
+///
+/// impl ManualClock {
+///     fn advance(&self, duration: Duration) {
+///         *self.elapsed.lock().unwrap() += duration;
+///     }
+/// }
+///
+/// impl Clock for ManualClock {
+///     fn now(&self) -> Instant {
+///         self.start.0 + *self.elapsed.lock().unwrap()
+///     }
+///
+///     fn system_now(&self) -> SystemTime {
+///         self.start.1 + *self.elapsed.lock().unwrap()
+///     }
+/// }
+///
+/// let clock = ManualClock {
+///     start: (Instant::now(), SystemTime::UNIX_EPOCH),
+///     elapsed: Mutex::new(Duration::ZERO),
+/// };
+/// let before = clock.now();
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(clock.now() - before, Duration::from_secs(5));
+/// ```
+pub trait Clock {
+    /// Returns the current instant, used for deadlines.
+    fn now(&self) -> Instant;
+
+    /// Returns the current system time, used for `Date` headers.
+    ///
+    /// The default implementation returns `SystemTime::now()`.
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A future returned by a `Timer`.
+pub trait Sleep: Send + Sync + Unpin + Future<Output = ()> {}
+
+/// A stream of ticks returned by a `Timer`.
+pub trait Interval: Send + Sync + Unpin {
+    /// Poll for the next tick.
+    ///
+    /// Returns the instant the tick was scheduled for, which may be earlier
+    /// than when it is observed. Ticks that were missed are not yielded
+    /// again.
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant>;
+}
+
+/// The default `Timer::interval`, made of one `sleep_until` after another.
+struct SleepInterval<T> {
+    timer: T,
+    period: Duration,
+    deadline: Instant,
+    sleep: Box<dyn Sleep + Unpin>,
+}
+
+impl<T> Interval for SleepInterval<T>
+where
+    T: Timer + Send + Sync + Unpin,
+{
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        ready!(Pin::new(&mut self.sleep).poll(cx));
+        let tick = self.deadline;
+
+        // Skip the ticks that were missed, instead of firing them in a burst.
+        let now = self.timer.now();
+        self.deadline += self.period;
+        while self.deadline <= now {
+            self.deadline += self.period;
+        }
+        self.sleep = self.timer.sleep_until(self.deadline);
+
+        Poll::Ready(tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::{Sleep, Timer};
+    use crate::common::{task, Future, Pin, Poll};
+
+    /// A timer whose time only moves by hand, and whose sleeps are ready once
+    /// their deadline has passed.
+    #[derive(Clone)]
+    struct ManualTimer {
+        now: Arc<Mutex<Instant>>,
+        deadlines: Arc<Mutex<Vec<Instant>>>,
+    }
+
+    struct ManualSleep {
+        now: Arc<Mutex<Instant>>,
+        deadline: Instant,
+    }
+
+    impl Timer for ManualTimer {
+        fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+            self.sleep_until(self.now() + duration)
+        }
+
+        fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
+            self.deadlines.lock().unwrap().push(deadline);
+            Box::new(ManualSleep {
+                now: self.now.clone(),
+                deadline,
+            })
+        }
+
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    impl Future for ManualSleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut task::Context<'_>) -> Poll<()> {
+            if *self.now.lock().unwrap() >= self.deadline {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    impl Sleep for ManualSleep {}
+
+    #[test]
+    fn default_interval_uses_timer_now() {
+        let start = Instant::now() + Duration::from_secs(3600);
+        let timer = ManualTimer {
+            now: Arc::new(Mutex::new(start)),
+            deadlines: Arc::default(),
+        };
+        let period = Duration::from_secs(10);
+        let mut interval = timer.interval(period);
+
+        let noop = futures_util::task::noop_waker();
+        let mut cx = task::Context::from_waker(&noop);
+        assert!(interval.poll_tick(&mut cx).is_pending());
+
+        // Jump past three ticks: the first is yielded, the others skipped.
+        *timer.now.lock().unwrap() = start + period * 3 + Duration::from_secs(1);
+        assert_eq!(interval.poll_tick(&mut cx), Poll::Ready(start + period));
+        assert_eq!(
+            *timer.deadlines.lock().unwrap(),
+            [start + period, start + period * 4]
+        );
+    }
+}
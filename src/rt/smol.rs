@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::{Executor, Interval, Sleep, Timer};
+
+/// An executor that spawns tasks onto the global executor shared by `smol`
+/// and `async-std`.
+///
+/// IO types of these runtimes implement the `futures-io` traits, and can be
+/// used with hyper by wrapping them in [`FuturesIo`](super::FuturesIo).
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(feature = "client", feature = "http2"))]
+/// # fn run() {
+/// use hyper::client::conn::http2;
+/// use hyper::rt::{SmolExecutor, SmolTimer};
+///
+/// let mut builder = http2::Builder::new();
+/// builder.executor(SmolExecutor::new()).timer(SmolTimer::new());
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmolExecutor;
+
+/// A timer that uses the reactor shared by `smol` and `async-std`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmolTimer;
+
+struct SmolSleep {
+    inner: async_io::Timer,
+}
+
+struct SmolInterval {
+    period: Duration,
+    deadline: Instant,
+    inner: async_io::Timer,
+}
+
+// ===== impl SmolExecutor =====
+
+impl SmolExecutor {
+    /// Create a new executor.
+    pub fn new() -> Self {
+        SmolExecutor
+    }
+}
+
+impl<Fut> Executor<Fut> for SmolExecutor
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    fn execute(&self, fut: Fut) {
+        async_global_executor::spawn(fut).detach();
+    }
+}
+
+// ===== impl SmolTimer =====
+
+impl SmolTimer {
+    /// Create a new timer.
+    pub fn new() -> Self {
+        SmolTimer
+    }
+}
+
+impl Timer for SmolTimer {
+    fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+        Box::new(SmolSleep {
+            inner: async_io::Timer::after(duration),
+        })
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
+        Box::new(SmolSleep {
+            inner: async_io::Timer::at(deadline),
+        })
+    }
+
+    fn interval(&self, period: Duration) -> Box<dyn Interval + Unpin> {
+        assert!(!period.is_zero(), "interval period must be non-zero");
+        let deadline = Instant::now() + period;
+        Box::new(SmolInterval {
+            period,
+            deadline,
+            inner: async_io::Timer::at(deadline),
+        })
+    }
+}
+
+impl Future for SmolSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.inner).poll(cx).map(|_| ())
+    }
+}
+
+impl Sleep for SmolSleep {}
+
+impl Interval for SmolInterval {
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        ready!(Pin::new(&mut self.inner).poll(cx));
+        let tick = self.deadline;
+
+        // Skip the ticks that were missed, instead of firing them in a burst.
+        let now = Instant::now();
+        self.deadline += self.period;
+        while self.deadline <= now {
+            self.deadline += self.period;
+        }
+        self.inner.set_at(self.deadline);
+
+        Poll::Ready(tick)
+    }
+}
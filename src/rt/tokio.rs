@@ -0,0 +1,281 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use pin_project_lite::pin_project;
+
+use super::{Executor, Interval, Read, ReadBuf, ReadBufCursor, Sleep, Timer, Write};
+
+/// An executor that spawns tasks onto the current Tokio runtime.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(feature = "client", feature = "http2"))]
+/// # fn run() {
+/// use hyper::client::conn::http2;
+/// use hyper::rt::{TokioExecutor, TokioTimer};
+///
+/// let mut builder = http2::Builder::new();
+/// builder.executor(TokioExecutor::new()).timer(TokioTimer::new());
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioExecutor;
+
+/// A timer that uses the time driver of the current Tokio runtime.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+pin_project! {
+    /// A wrapper that implements hyper's IO traits for a type that
+    /// implements Tokio's IO traits, and the other way around.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(all(feature = "client", feature = "http1"))]
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// use hyper::client::conn::http1;
+    /// use hyper::rt::TokioIo;
+    /// use tokio::net::TcpStream;
+    ///
+    /// let stream = TcpStream::connect("127.0.0.1:3000").await?;
+    /// let (sender, conn) = http1::handshake::<_, hyper::Recv>(TokioIo::new(stream)).await?;
+    /// # drop((sender, conn));
+    /// # Ok(())
+    /// # }
+    /// # fn main() {}
+    /// ```
+    #[derive(Debug)]
+    pub struct TokioIo<T> {
+        #[pin]
+        inner: T,
+    }
+}
+
+// Use TokioSleep to get tokio::time::Sleep to implement Unpin.
+struct TokioSleep {
+    inner: Pin<Box<tokio::time::Sleep>>,
+}
+
+struct TokioInterval {
+    inner: tokio::time::Interval,
+}
+
+// ===== impl TokioExecutor =====
+
+impl TokioExecutor {
+    /// Create a new executor.
+    pub fn new() -> Self {
+        TokioExecutor
+    }
+}
+
+impl<Fut> Executor<Fut> for TokioExecutor
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    fn execute(&self, fut: Fut) {
+        tokio::task::spawn(fut);
+    }
+}
+
+// ===== impl TokioTimer =====
+
+impl TokioTimer {
+    /// Create a new timer.
+    pub fn new() -> Self {
+        TokioTimer
+    }
+}
+
+impl Timer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+        Box::new(TokioSleep {
+            inner: Box::pin(tokio::time::sleep(duration)),
+        })
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
+        Box::new(TokioSleep {
+            inner: Box::pin(tokio::time::sleep_until(deadline.into())),
+        })
+    }
+
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn interval(&self, period: Duration) -> Box<dyn Interval + Unpin> {
+        let start = tokio::time::Instant::now() + period;
+        let mut inner = tokio::time::interval_at(start, period);
+        inner.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        Box::new(TokioInterval { inner })
+    }
+}
+
+impl Future for TokioSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl Sleep for TokioSleep {}
+
+impl Interval for TokioInterval {
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        self.inner.poll_tick(cx).map(|tick| tick.into_std())
+    }
+}
+
+// ===== impl TokioIo =====
+
+impl<T> TokioIo<T> {
+    /// Wrap a type implementing Tokio's IO traits, or hyper's.
+    pub fn new(inner: T) -> Self {
+        TokioIo { inner }
+    }
+
+    /// Borrow the inner type.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Mutably borrow the inner type.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume this wrapper and get the inner type.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T> Read for TokioIo<T>
+where
+    T: tokio::io::AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: ReadBufCursor<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        let n = unsafe {
+            let mut tbuf = tokio::io::ReadBuf::uninit(buf.as_mut());
+            match tokio::io::AsyncRead::poll_read(self.project().inner, cx, &mut tbuf) {
+                Poll::Ready(Ok(())) => tbuf.filled().len(),
+                other => return other,
+            }
+        };
+
+        unsafe {
+            buf.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> Write for TokioIo<T>
+where
+    T: tokio::io::AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        tokio::io::AsyncWrite::poll_write(self.project().inner, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        tokio::io::AsyncWrite::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        tokio::io::AsyncWrite::poll_shutdown(self.project().inner, cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        tokio::io::AsyncWrite::is_write_vectored(&self.inner)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        tokio::io::AsyncWrite::poll_write_vectored(self.project().inner, cx, bufs)
+    }
+}
+
+impl<T> tokio::io::AsyncRead for TokioIo<T>
+where
+    T: Read,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        tbuf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<Result<(), io::Error>> {
+        let filled = tbuf.filled().len();
+        let sub_filled = unsafe {
+            let mut buf = ReadBuf::uninit(tbuf.unfilled_mut());
+
+            match Read::poll_read(self.project().inner, cx, buf.unfilled()) {
+                Poll::Ready(Ok(())) => buf.filled().len(),
+                other => return other,
+            }
+        };
+
+        let n_filled = filled + sub_filled;
+        // At least sub_filled bytes had to have been initialized.
+        let n_init = sub_filled;
+        unsafe {
+            tbuf.assume_init(n_init);
+            tbuf.set_filled(n_filled);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> tokio::io::AsyncWrite for TokioIo<T>
+where
+    T: Write,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Write::poll_write(self.project().inner, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Write::poll_flush(self.project().inner, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Write::poll_shutdown(self.project().inner, cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        Write::is_write_vectored(&self.inner)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<Result<usize, io::Error>> {
+        Write::poll_write_vectored(self.project().inner, cx, bufs)
+    }
+}
@@ -0,0 +1,579 @@
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::task::AtomicWaker;
+use tracing::trace;
+
+use super::{Sleep, Timer};
+
+// Each level has 64 slots, and each slot spans all 64 slots of the level
+// below it.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+const LEVELS: usize = 6;
+// Deadlines further out than this are kept in the last level, and moved
+// down once they get closer.
+const MAX_TICKS: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+
+/// A timer that shares a single sleep of another timer between all of its
+/// sleeps.
+///
+/// A server with many idle connections has a sleep pending for each of them,
+/// such as for keep-alive pings or header read timeouts. A `TimerWheel` keeps
+/// these in a hierarchical timer wheel instead, and only the earliest one is
+/// given to the wrapped timer. Registering, resetting and dropping a sleep
+/// then only touches the wheel.
+///
+/// Deadlines are rounded up to a multiple of the wheel's resolution, so
+/// sleeps may fire up to one resolution late, but never early.
+///
+/// The wheel is advanced by a [`TimerWheelDriver`], which must be spawned on
+/// an executor. It completes once all the handles to the wheel, and the
+/// sleeps created from them, are dropped.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(feature = "server", feature = "http1", feature = "tokio"))]
+/// # async fn run() {
+/// use std::time::Duration;
+///
+/// use hyper::rt::{TimerWheel, TokioTimer};
+/// use hyper::server::conn::Http;
+///
+/// let (wheel, driver) = TimerWheel::new(TokioTimer::new(), Duration::from_millis(10));
+/// tokio::spawn(driver);
+///
+/// let mut http = Http::new().with_timer(wheel);
+/// http.http1_header_read_timeout(Duration::from_secs(30));
+/// # }
+/// # fn main() {}
+/// ```
+pub struct TimerWheel {
+    shared: Arc<Shared>,
+}
+
+/// The future advancing a [`TimerWheel`].
+#[must_use = "futures do nothing unless polled"]
+pub struct TimerWheelDriver {
+    shared: Arc<Shared>,
+    timer: Box<dyn Timer + Send + Sync>,
+    sleep: Option<(u64, Pin<Box<dyn Sleep>>)>,
+}
+
+struct Shared {
+    start: Instant,
+    resolution: Duration,
+    wheel: Mutex<Wheel>,
+    driver: AtomicWaker,
+    // The number of `TimerWheel` handles.
+    handles: AtomicUsize,
+}
+
+struct Wheel {
+    // The tick the wheel has been advanced to.
+    elapsed: u64,
+    levels: Vec<Level>,
+    // The number of entries that are neither fired nor canceled.
+    live: usize,
+    // The tick the driver sleeps until.
+    next_wake: Option<u64>,
+}
+
+struct Level {
+    // A bit is set for each slot that has entries.
+    occupied: u64,
+    slots: Vec<Vec<Arc<Entry>>>,
+}
+
+struct Entry {
+    when: u64,
+    fired: AtomicBool,
+    // Canceled entries are only removed once their slot expires.
+    canceled: AtomicBool,
+    waker: AtomicWaker,
+}
+
+struct WheelSleep {
+    shared: Arc<Shared>,
+    entry: Arc<Entry>,
+}
+
+// ===== impl TimerWheel =====
+
+impl TimerWheel {
+    /// Create a timer wheel with the given resolution, driven by `timer`.
+    ///
+    /// A coarser resolution means fewer wakeups of the driver, at the cost
+    /// of sleeps firing later.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `resolution` is zero.
+    pub fn new<M>(timer: M, resolution: Duration) -> (TimerWheel, TimerWheelDriver)
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        assert!(!resolution.is_zero(), "resolution must be non-zero");
+        let shared = Arc::new(Shared {
+            start: Instant::now(),
+            resolution,
+            wheel: Mutex::new(Wheel::new()),
+            driver: AtomicWaker::new(),
+            handles: AtomicUsize::new(1),
+        });
+        let driver = TimerWheelDriver {
+            shared: shared.clone(),
+            timer: Box::new(timer),
+            sleep: None,
+        };
+        (TimerWheel { shared }, driver)
+    }
+}
+
+impl Timer for TimerWheel {
+    fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+        self.sleep_until(Instant::now() + duration)
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
+        let entry = Arc::new(Entry {
+            when: self.shared.deadline_tick(deadline),
+            fired: AtomicBool::new(false),
+            canceled: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        });
+
+        let mut wheel = self.shared.wheel.lock().unwrap();
+        if wheel.insert(entry.clone()) {
+            wheel.live += 1;
+            // Wake the driver if this is now the earliest deadline.
+            let earliest = match wheel.next_wake {
+                Some(next) => entry.when < next,
+                None => true,
+            };
+            if earliest {
+                self.shared.driver.wake();
+            }
+        } else {
+            entry.fired.store(true, Ordering::Release);
+        }
+        drop(wheel);
+
+        Box::new(WheelSleep {
+            shared: self.shared.clone(),
+            entry,
+        })
+    }
+}
+
+impl Clone for TimerWheel {
+    fn clone(&self) -> TimerWheel {
+        self.shared.handles.fetch_add(1, Ordering::Relaxed);
+        TimerWheel {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for TimerWheel {
+    fn drop(&mut self) {
+        if self.shared.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.driver.wake();
+        }
+    }
+}
+
+impl fmt::Debug for TimerWheel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerWheel")
+            .field("resolution", &self.shared.resolution)
+            .finish()
+    }
+}
+
+// ===== impl TimerWheelDriver =====
+
+impl Future for TimerWheelDriver {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = &mut *self;
+        me.shared.driver.register(cx.waker());
+
+        loop {
+            let now = me.shared.now_tick(Instant::now());
+            let mut fired = Vec::new();
+            let (next, done) = {
+                let mut wheel = me.shared.wheel.lock().unwrap();
+                wheel.advance(now, &mut fired);
+                let next = wheel.next_expiration().map(|(_, _, deadline)| deadline);
+                wheel.next_wake = next;
+                let done = wheel.live == 0 && me.shared.handles.load(Ordering::Acquire) == 0;
+                (next, done)
+            };
+
+            // Wake outside of the lock, a woken task may register a sleep
+            // right away.
+            for entry in fired {
+                entry.waker.wake();
+            }
+
+            if done {
+                trace!("timer wheel driver complete");
+                return Poll::Ready(());
+            }
+
+            let tick = match next {
+                Some(tick) => tick,
+                None => {
+                    me.sleep = None;
+                    return Poll::Pending;
+                }
+            };
+            let deadline = me.shared.tick_instant(tick);
+            match me.sleep {
+                Some((at, _)) if at == tick => (),
+                Some((ref mut at, ref mut sleep)) => {
+                    *at = tick;
+                    me.timer.reset(sleep, deadline);
+                }
+                None => {
+                    let sleep = crate::common::into_pin(me.timer.sleep_until(deadline));
+                    me.sleep = Some((tick, sleep));
+                }
+            }
+
+            let (_, sleep) = me.sleep.as_mut().expect("sleep was just set");
+            ready!(sleep.as_mut().poll(cx));
+        }
+    }
+}
+
+impl fmt::Debug for TimerWheelDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerWheelDriver")
+            .field("resolution", &self.shared.resolution)
+            .finish()
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    // Rounds up, so that a sleep never fires early.
+    fn deadline_tick(&self, deadline: Instant) -> u64 {
+        let since = deadline.saturating_duration_since(self.start).as_nanos();
+        let resolution = self.resolution.as_nanos();
+        let mut ticks = since / resolution;
+        if ticks * resolution < since {
+            ticks += 1;
+        }
+        if ticks > u128::from(u64::MAX) {
+            u64::MAX
+        } else {
+            ticks as u64
+        }
+    }
+
+    fn now_tick(&self, now: Instant) -> u64 {
+        let since = now.saturating_duration_since(self.start).as_nanos();
+        (since / self.resolution.as_nanos()) as u64
+    }
+
+    fn tick_instant(&self, tick: u64) -> Instant {
+        let nanos = self.resolution.as_nanos() * u128::from(tick);
+        let since = Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        );
+        self.start + since
+    }
+}
+
+// ===== impl Wheel =====
+
+impl Wheel {
+    fn new() -> Wheel {
+        Wheel {
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| Level {
+                    occupied: 0,
+                    slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+                })
+                .collect(),
+            live: 0,
+            next_wake: None,
+        }
+    }
+
+    // Returns false if the entry has already expired.
+    fn insert(&mut self, entry: Arc<Entry>) -> bool {
+        if entry.when <= self.elapsed {
+            return false;
+        }
+
+        // The level is picked by the highest bit in which the deadline
+        // differs from the current tick, so that it expires from the
+        // lowest level that can hold it.
+        let mut masked = (self.elapsed ^ entry.when) | SLOT_MASK;
+        if masked >= MAX_TICKS {
+            masked = MAX_TICKS - 1;
+        }
+        let level = ((63 - masked.leading_zeros()) / SLOT_BITS) as usize;
+        let slot = ((entry.when >> (level as u32 * SLOT_BITS)) & SLOT_MASK) as usize;
+
+        let level = &mut self.levels[level];
+        level.occupied |= 1 << slot;
+        level.slots[slot].push(entry);
+        true
+    }
+
+    // The earliest slot to expire, as its level, slot and starting tick.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        // Slots of lower levels always expire before those of higher levels.
+        self.levels
+            .iter()
+            .enumerate()
+            .find_map(|(i, level)| level.next_expiration(i, self.elapsed))
+    }
+
+    // Advance to `now`, collecting the entries that fired. Entries of the
+    // expired slots of higher levels are moved down.
+    fn advance(&mut self, now: u64, fired: &mut Vec<Arc<Entry>>) {
+        while let Some((level, slot, deadline)) = self.next_expiration() {
+            if deadline > now {
+                break;
+            }
+            self.elapsed = deadline;
+
+            let level = &mut self.levels[level];
+            level.occupied &= !(1 << slot);
+            for entry in mem::take(&mut level.slots[slot]) {
+                if entry.canceled.load(Ordering::Acquire) {
+                    continue;
+                }
+                if !self.insert(entry.clone()) {
+                    entry.fired.store(true, Ordering::Release);
+                    self.live -= 1;
+                    fired.push(entry);
+                }
+            }
+        }
+
+        if now > self.elapsed {
+            self.elapsed = now;
+        }
+    }
+}
+
+// ===== impl Level =====
+
+impl Level {
+    fn next_expiration(&self, level: usize, now: u64) -> Option<(usize, usize, u64)> {
+        if self.occupied == 0 {
+            return None;
+        }
+
+        let shift = level as u32 * SLOT_BITS;
+        let slot_range = 1u64 << shift;
+        let level_range = slot_range << SLOT_BITS;
+
+        let now_slot = ((now >> shift) & SLOT_MASK) as u32;
+        let zeros = self.occupied.rotate_right(now_slot).trailing_zeros();
+        let slot = ((zeros + now_slot) as u64 & SLOT_MASK) as usize;
+
+        let level_start = now & !(level_range - 1);
+        let mut deadline = level_start + slot as u64 * slot_range;
+        if deadline <= now {
+            // Only the last level wraps around, for deadlines beyond the
+            // range of the wheel.
+            deadline += level_range;
+        }
+        Some((level, slot, deadline))
+    }
+}
+
+// ===== impl WheelSleep =====
+
+impl Future for WheelSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.entry.fired.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        self.entry.waker.register(cx.waker());
+        if self.entry.fired.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Sleep for WheelSleep {}
+
+impl Drop for WheelSleep {
+    fn drop(&mut self) {
+        let mut wheel = self.shared.wheel.lock().unwrap();
+        // Checked while locked, so the driver can't fire it in between.
+        if !self.entry.fired.load(Ordering::Acquire) {
+            self.entry.canceled.store(true, Ordering::Release);
+            wheel.live -= 1;
+            if wheel.live == 0 && self.shared.handles.load(Ordering::Acquire) == 0 {
+                self.shared.driver.wake();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(when: u64) -> Arc<Entry> {
+        Arc::new(Entry {
+            when,
+            fired: AtomicBool::new(false),
+            canceled: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        })
+    }
+
+    fn insert(wheel: &mut Wheel, when: u64) -> Arc<Entry> {
+        let entry = entry(when);
+        assert!(wheel.insert(entry.clone()));
+        wheel.live += 1;
+        entry
+    }
+
+    fn advance(wheel: &mut Wheel, now: u64) -> Vec<u64> {
+        let mut fired = Vec::new();
+        wheel.advance(now, &mut fired);
+        fired.iter().map(|entry| entry.when).collect()
+    }
+
+    #[test]
+    fn wheel_fires_on_deadline() {
+        let mut wheel = Wheel::new();
+        // One entry on each of the first levels.
+        let deadlines = [5, 70, 5_000, 300_000, 20_000_000];
+        for &when in &deadlines {
+            insert(&mut wheel, when);
+        }
+
+        for &when in &deadlines {
+            assert_eq!(advance(&mut wheel, when - 1), Vec::<u64>::new());
+            assert_eq!(advance(&mut wheel, when), vec![when]);
+        }
+        assert_eq!(wheel.live, 0);
+        assert!(wheel.next_expiration().is_none());
+    }
+
+    #[test]
+    fn wheel_fires_all_elapsed_at_once() {
+        let mut wheel = Wheel::new();
+        for &when in &[3, 100, 4_000, 90] {
+            insert(&mut wheel, when);
+        }
+
+        let mut fired = advance(&mut wheel, 10_000);
+        fired.sort_unstable();
+        assert_eq!(fired, vec![3, 90, 100, 4_000]);
+        assert_eq!(wheel.elapsed, 10_000);
+    }
+
+    #[test]
+    fn wheel_beyond_range() {
+        let mut wheel = Wheel::new();
+        let far = MAX_TICKS * 3 + 7;
+        insert(&mut wheel, far);
+
+        assert_eq!(advance(&mut wheel, far - 1), Vec::<u64>::new());
+        assert_eq!(advance(&mut wheel, far), vec![far]);
+    }
+
+    #[test]
+    fn wheel_skips_canceled() {
+        let mut wheel = Wheel::new();
+        let canceled = insert(&mut wheel, 10);
+        insert(&mut wheel, 10);
+        canceled.canceled.store(true, Ordering::Release);
+        wheel.live -= 1;
+
+        assert_eq!(advance(&mut wheel, 10).len(), 1);
+        assert!(!canceled.fired.load(Ordering::Acquire));
+        assert_eq!(wheel.live, 0);
+    }
+
+    #[test]
+    fn wheel_rejects_expired() {
+        let mut wheel = Wheel::new();
+        advance(&mut wheel, 50);
+        assert!(!wheel.insert(entry(50)));
+        assert!(wheel.insert(entry(51)));
+    }
+
+    #[derive(Clone)]
+    struct TokioTimer;
+
+    struct TokioSleep(Pin<Box<tokio::time::Sleep>>);
+
+    impl Timer for TokioTimer {
+        fn sleep(&self, duration: Duration) -> Box<dyn Sleep + Unpin> {
+            Box::new(TokioSleep(Box::pin(tokio::time::sleep(duration))))
+        }
+
+        fn sleep_until(&self, deadline: Instant) -> Box<dyn Sleep + Unpin> {
+            Box::new(TokioSleep(Box::pin(tokio::time::sleep_until(
+                deadline.into(),
+            ))))
+        }
+    }
+
+    impl Future for TokioSleep {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            self.0.as_mut().poll(cx)
+        }
+    }
+
+    impl Sleep for TokioSleep {}
+
+    #[tokio::test]
+    async fn timer_wheel_sleeps() {
+        let (wheel, driver) = TimerWheel::new(TokioTimer, Duration::from_millis(1));
+        let driver = tokio::spawn(driver);
+
+        let start = Instant::now();
+        let long = wheel.sleep(Duration::from_millis(100));
+        let short = wheel.sleep(Duration::from_millis(20));
+        let dropped = wheel.sleep(Duration::from_millis(10));
+        drop(dropped);
+
+        short.await;
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        long.await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        let mut interval = wheel.interval(Duration::from_millis(10));
+        let first = futures_util::future::poll_fn(|cx| interval.poll_tick(cx)).await;
+        let second = futures_util::future::poll_fn(|cx| interval.poll_tick(cx)).await;
+        assert_eq!(second - first, Duration::from_millis(10));
+
+        // The driver completes once nothing can use the wheel anymore.
+        drop(interval);
+        drop(wheel);
+        driver.await.unwrap();
+    }
+}
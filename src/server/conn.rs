@@ -6,7 +6,9 @@
 //! customize those things externally.
 //!
 //! ## Example
-//! A simple example that uses the `Http` struct to talk HTTP over a Tokio TCP stream
+//! A simple example that uses the `Http` struct to talk HTTP over a TCP
+//! stream. The stream can be of any type implementing hyper's
+//! [`Read`](crate::rt::Read) and [`Write`](crate::rt::Write) traits.
 //! ```no_run
 //! # #[cfg(feature = "http1")]
 //! # mod rt {
@@ -14,7 +16,13 @@
 //! use http_body_util::Full;
 //! use hyper::{server::conn::Http, service::service_fn, body::Bytes};
 //! use std::{net::SocketAddr, convert::Infallible};
-//! use tokio::net::TcpListener;
+//! # struct TcpListener;
+//! # impl TcpListener {
+//! #     async fn bind(_: SocketAddr) -> std::io::Result<Self> { unimplemented!() }
+//! #     async fn accept(&self) -> std::io::Result<(hyper::upgrade::Upgraded, SocketAddr)> {
+//! #         unimplemented!()
+//! #     }
+//! # }
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -46,17 +54,74 @@
     not(all(feature = "http1", feature = "http2"))
 ))]
 use std::marker::PhantomData;
+#[cfg(any(feature = "http1", feature = "http2"))]
 use std::sync::Arc;
 #[cfg(any(feature = "http1", feature = "http2"))]
 use std::time::Duration;
+#[cfg(all(feature = "http1", feature = "http2"))]
+use std::time::Instant;
+
+#[cfg(any(feature = "http1", feature = "http2"))]
+use http::header::HeaderName;
 
+#[cfg(feature = "http1")]
+use crate::buffer::BufferPool;
+#[cfg(all(feature = "debug", any(feature = "http1", feature = "http2")))]
+use crate::capture::{Capture, CaptureHandle};
 #[cfg(feature = "http2")]
 use crate::common::io::Rewind;
 #[cfg(all(feature = "http1", feature = "http2"))]
 use crate::error::{Kind, Parse};
+#[cfg(any(feature = "http1", feature = "http2"))]
+use crate::metrics::{Metrics, MetricsHandle};
 #[cfg(feature = "http1")]
 use crate::upgrade::Upgraded;
-use crate::{common::time::Time, rt::Timer};
+#[cfg(any(feature = "http1", feature = "http2"))]
+use crate::{
+    common::time::Time,
+    rt::{Clock, Timer},
+};
+
+#[cfg(all(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "http1", feature = "http2"))))]
+pub mod auto;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod graceful;
+#[cfg(any(feature = "http1", feature = "http2"))]
+mod limit;
+#[cfg(feature = "http2")]
+pub(crate) mod overflow;
+#[cfg(feature = "http1")]
+pub(crate) mod parse_error;
+#[cfg(any(feature = "http1", feature = "http2"))]
+pub(crate) mod summary;
+#[cfg(all(feature = "http1", feature = "http2"))]
+mod tls;
+
+#[cfg(any(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
+pub use self::graceful::{GracefulConnection, GracefulShutdown, Watched};
+#[cfg(any(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
+pub use self::limit::{ConcurrencyLimit, ConnectionPermit, Limited, LimitedFuture, WhenSaturated};
+#[cfg(feature = "http2")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+pub use self::overflow::StreamOverflow;
+#[cfg(feature = "http1")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+pub use self::parse_error::ParseErrorAction;
+#[cfg(any(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "http1", feature = "http2"))))]
+pub use self::summary::{RequestId, RequestSummary};
+#[cfg(all(feature = "http1", feature = "http2"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "http1", feature = "http2"))))]
+pub use self::tls::{TlsAccept, TlsStream};
+#[cfg(feature = "http1")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+pub use crate::proto::h1::{HeaderValuePolicy, Strictness, StrictnessLevel, TransferCodingPolicy};
+#[cfg(feature = "http2")]
+#[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+pub use crate::proto::h2::TrailerPolicy;
 
 cfg_feature! {
     #![any(feature = "http1", feature = "http2")]
@@ -66,7 +131,6 @@ cfg_feature! {
 
     use bytes::Bytes;
     use pin_project_lite::pin_project;
-    use tokio::io::{AsyncRead, AsyncWrite};
     use tracing::trace;
 
     use crate::body::{Recv, Body};
@@ -75,6 +139,7 @@ cfg_feature! {
     use crate::common::Never;
     use crate::common::exec::{ConnStreamExec, Exec};
     use crate::proto;
+    use crate::rt::{Read, Write};
     use crate::service::HttpService;
 
     pub(super) use self::upgrades::UpgradeableConnection;
@@ -93,14 +158,59 @@ pub struct Http<E = Exec> {
     h1_keep_alive: bool,
     h1_title_case_headers: bool,
     h1_preserve_header_case: bool,
+    h1_lazy_headers: bool,
+    h1_forward_lazy_headers: bool,
     #[cfg(feature = "http1")]
     h1_header_read_timeout: Option<Duration>,
+    #[cfg(feature = "http1")]
+    h1_header_read_timeout_response: bool,
+    #[cfg(feature = "http1")]
+    h1_on_parse_error: Option<parse_error::OnParseError>,
+    #[cfg(feature = "http1")]
+    h1_date_header: bool,
+    #[cfg(feature = "http1")]
+    h1_keep_alive_header: bool,
+    #[cfg(feature = "http1")]
+    h1_max_body_size: Option<u64>,
+    #[cfg(feature = "http1")]
+    h1_max_pending_responses: Option<usize>,
+    #[cfg(feature = "http1")]
+    h1_on_request_complete: Option<summary::OnRequestComplete>,
+    #[cfg(feature = "http1")]
+    h1_poll_budget: usize,
+    #[cfg(feature = "http1")]
+    h1_timings: bool,
+    #[cfg(feature = "http1")]
+    h1_buffer_pool: Option<BufferPool>,
+    #[cfg(feature = "http1")]
+    h1_body_channel_depth: usize,
+    #[cfg(feature = "http1")]
+    h1_header_capacity: usize,
+    #[cfg(feature = "http1")]
+    h1_max_headers: Option<usize>,
+    #[cfg(feature = "http1")]
+    h1_write_batch: Option<(Duration, usize)>,
+    #[cfg(feature = "http1")]
+    h1_strictness: Strictness,
+    #[cfg(feature = "http1")]
+    h1_header_value_policy: HeaderValuePolicy,
+    #[cfg(feature = "http1")]
+    h1_validate_header_values: bool,
+    #[cfg(feature = "http1")]
+    h1_sanitize_request_target: bool,
+    #[cfg(feature = "http1")]
+    h1_transfer_coding_policy: TransferCodingPolicy,
+    #[cfg(all(feature = "http1", feature = "debug"))]
+    h1_capture: Option<CaptureHandle>,
     h1_writev: Option<bool>,
     #[cfg(feature = "http2")]
     h2_builder: proto::h2::server::Config,
     mode: ConnectionMode,
     max_buf_size: Option<usize>,
     pipeline_flush: bool,
+    service_timeout: proto::ServiceTimeout,
+    connection_use: bool,
+    peer_disconnect: bool,
 }
 
 /// The internal mode of HTTP protocol which indicates the behavior when a parse error occurs.
@@ -228,19 +338,64 @@ impl Http {
     pub fn new() -> Http {
         Http {
             exec: Exec::Default,
-            timer: Time::Empty,
+            timer: Time::default(),
             h1_half_close: false,
             h1_keep_alive: true,
             h1_title_case_headers: false,
             h1_preserve_header_case: false,
+            h1_lazy_headers: false,
+            h1_forward_lazy_headers: false,
             #[cfg(feature = "http1")]
             h1_header_read_timeout: None,
+            #[cfg(feature = "http1")]
+            h1_header_read_timeout_response: false,
+            #[cfg(feature = "http1")]
+            h1_on_parse_error: None,
+            #[cfg(feature = "http1")]
+            h1_date_header: true,
+            #[cfg(feature = "http1")]
+            h1_keep_alive_header: true,
+            #[cfg(feature = "http1")]
+            h1_max_body_size: None,
+            #[cfg(feature = "http1")]
+            h1_max_pending_responses: None,
+            #[cfg(feature = "http1")]
+            h1_on_request_complete: None,
+            #[cfg(feature = "http1")]
+            h1_poll_budget: proto::DEFAULT_POLL_BUDGET,
+            #[cfg(feature = "http1")]
+            h1_timings: false,
+            #[cfg(feature = "http1")]
+            h1_buffer_pool: None,
+            #[cfg(feature = "http1")]
+            h1_body_channel_depth: crate::body::DEFAULT_CHANNEL_DEPTH,
+            #[cfg(feature = "http1")]
+            h1_header_capacity: 0,
+            #[cfg(feature = "http1")]
+            h1_max_headers: None,
+            #[cfg(feature = "http1")]
+            h1_write_batch: None,
+            #[cfg(feature = "http1")]
+            h1_strictness: Strictness::default(),
+            #[cfg(feature = "http1")]
+            h1_header_value_policy: HeaderValuePolicy::default(),
+            #[cfg(feature = "http1")]
+            h1_validate_header_values: false,
+            #[cfg(feature = "http1")]
+            h1_sanitize_request_target: false,
+            #[cfg(feature = "http1")]
+            h1_transfer_coding_policy: TransferCodingPolicy::default(),
+            #[cfg(all(feature = "http1", feature = "debug"))]
+            h1_capture: None,
             h1_writev: None,
             #[cfg(feature = "http2")]
             h2_builder: Default::default(),
             mode: ConnectionMode::default(),
             max_buf_size: None,
             pipeline_flush: false,
+            service_timeout: Default::default(),
+            connection_use: false,
+            peer_disconnect: false,
         }
     }
 }
@@ -322,6 +477,39 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set whether to only parse the request headers hyper needs.
+    ///
+    /// If enabled, the other headers are left unparsed in a
+    /// [`LazyHeaders`](crate::ext::LazyHeaders) extension on the `Request`,
+    /// instead of its `HeaderMap`.
+    ///
+    /// Note that this setting does not affect HTTP/2.
+    ///
+    /// Default is false.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_lazy_headers(&mut self, enabled: bool) -> &mut Self {
+        self.h1_lazy_headers = enabled;
+        self
+    }
+
+    /// Set whether to write the headers of a
+    /// [`LazyHeaders`](crate::ext::LazyHeaders) extension on a `Response`.
+    ///
+    /// If enabled, they are written as received, such as when forwarding
+    /// them from an upstream server. Hop-by-hop headers, and headers the
+    /// `Response` already has in its `HeaderMap`, are skipped.
+    ///
+    /// Note that this setting does not affect HTTP/2.
+    ///
+    /// Default is false.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_forward_lazy_headers(&mut self, enabled: bool) -> &mut Self {
+        self.h1_forward_lazy_headers = enabled;
+        self
+    }
+
     /// Set a timeout for reading client request headers. If a client does not
     /// transmit the entire header within this time, the connection is closed.
     ///
@@ -333,6 +521,75 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set whether to respond with `408 Request Timeout` when the
+    /// [`http1_header_read_timeout`](Http::http1_header_read_timeout)
+    /// fires, before closing the connection.
+    ///
+    /// Default is false, which closes the connection without a response.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_header_read_timeout_response(&mut self, enabled: bool) -> &mut Self {
+        self.h1_header_read_timeout_response = enabled;
+        self
+    }
+
+    /// Set a callback deciding the response to HTTP/1 requests that can't
+    /// be read.
+    ///
+    /// It is called with the error, such as an unsupported version, a URI
+    /// that is too long, an invalid header, a body over
+    /// [`max_request_body_size`](Http::max_request_body_size), or the
+    /// [`http1_header_read_timeout`](Http::http1_header_read_timeout)
+    /// firing. Match on [`Error::kind`](crate::Error::kind) to tell them
+    /// apart. The connection is closed afterwards in any case.
+    ///
+    /// It isn't called if a response was already started.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use hyper::error::{ErrorKind, ParseKind};
+    /// use hyper::server::conn::{Http, ParseErrorAction};
+    /// use hyper::{Response, StatusCode};
+    ///
+    /// let mut http = Http::new();
+    /// http.http1_on_parse_error(|err| match err.kind() {
+    ///     ErrorKind::Parse(ParseKind::UriTooLong) => ParseErrorAction::Respond(
+    ///         Response::builder()
+    ///             .status(StatusCode::URI_TOO_LONG)
+    ///             .header("content-type", "text/plain")
+    ///             .body("use a shorter link\n".into())
+    ///             .unwrap(),
+    ///     ),
+    ///     ErrorKind::Parse(ParseKind::Version) => ParseErrorAction::Close,
+    ///     _ => ParseErrorAction::Default,
+    /// });
+    /// ```
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_on_parse_error<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&crate::Error) -> ParseErrorAction + Send + Sync + 'static,
+    {
+        self.h1_on_parse_error = Some(parse_error::OnParseError::new(f));
+        self
+    }
+
+    /// Set whether to add `connection: keep-alive` to responses to HTTP/1.0
+    /// requests that asked to keep the connection alive.
+    ///
+    /// HTTP/1.0 clients close the connection unless the response has this
+    /// header, so if it is disabled, and the response doesn't set it itself,
+    /// the connection is closed after the response.
+    ///
+    /// Default is true.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_keep_alive_header(&mut self, enabled: bool) -> &mut Self {
+        self.h1_keep_alive_header = enabled;
+        self
+    }
+
     /// Set whether HTTP/1 connections should try to use vectored writes,
     /// or always flatten into a single buffer.
     ///
@@ -353,6 +610,172 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set whether to add a [`Timings`](crate::ext::Timings) extension to
+    /// each HTTP/1 request, with when it was received.
+    ///
+    /// Default is false.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_timings(&mut self, enabled: bool) -> &mut Self {
+        self.h1_timings = enabled;
+        self
+    }
+
+    /// Take the read and write buffers of HTTP/1 connections from `pool`,
+    /// and give them back once they close.
+    ///
+    /// Default is to allocate new buffers for each connection.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_buffer_pool(&mut self, pool: BufferPool) -> &mut Self {
+        self.h1_buffer_pool = Some(pool);
+        self
+    }
+
+    /// Set HTTP/1 connections to hold written responses for up to
+    /// `max_delay`, so that responses written soon after are flushed in the
+    /// same write.
+    ///
+    /// This cuts the number of writes when responses are small and written
+    /// quickly, such as for pipelined requests, at the cost of that much
+    /// latency. Once `max_bytes` are queued, they are flushed right away.
+    /// Responses are never held on a connection about to close.
+    ///
+    /// This requires a timer, set with [`with_timer`](Http::with_timer).
+    ///
+    /// Default is to flush each response as soon as it's written.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_write_batch(&mut self, max_delay: Duration, max_bytes: usize) -> &mut Self {
+        self.h1_write_batch = Some((max_delay, max_bytes));
+        self
+    }
+
+    /// Set how strictly HTTP/1 requests are checked against [RFC 9112].
+    ///
+    /// This takes a [`StrictnessLevel`], or a [`Strictness`] to override
+    /// some of its checks. Requests with obsolete line folding or whitespace
+    /// before a header colon are rejected at any level.
+    ///
+    /// Default is [`StrictnessLevel::Standard`].
+    ///
+    /// [RFC 9112]: https://www.rfc-editor.org/rfc/rfc9112
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_strictness(&mut self, strictness: impl Into<Strictness>) -> &mut Self {
+        self.h1_strictness = strictness.into();
+        self
+    }
+
+    /// Set what to do with HTTP/1 request header values containing bytes
+    /// that aren't visible ASCII, space or tab.
+    ///
+    /// A request rejected by [`HeaderValuePolicy::Reject`] gets a `400 Bad
+    /// Request` response.
+    ///
+    /// Default is [`HeaderValuePolicy::Allow`].
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_header_value_policy(&mut self, policy: HeaderValuePolicy) -> &mut Self {
+        self.h1_header_value_policy = policy;
+        self
+    }
+
+    /// Set whether HTTP/1 response header values are checked before being
+    /// sent.
+    ///
+    /// Besides what `http` already rejects, values with bytes that aren't
+    /// visible ASCII, space or tab, or that start or end with whitespace,
+    /// make writing the response fail.
+    ///
+    /// Default is false.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_validate_header_values(&mut self, enabled: bool) -> &mut Self {
+        self.h1_validate_header_values = enabled;
+        self
+    }
+
+    /// Set whether HTTP/1 request targets that `http::Uri` rejects are
+    /// percent-encoded instead of rejected.
+    ///
+    /// Some clients send bytes such as `<` or `>` unencoded. When enabled,
+    /// such a request gets a URI with the bytes RFC 3986 doesn't allow
+    /// percent-encoded, and a [`RawRequestTarget`](crate::ext::RawRequestTarget)
+    /// extension with the target as received. Targets that are still invalid once encoded, such
+    /// as those with an invalid authority, are rejected.
+    ///
+    /// Default is false.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_sanitize_request_target(&mut self, enabled: bool) -> &mut Self {
+        self.h1_sanitize_request_target = enabled;
+        self
+    }
+
+    /// Set what to do with HTTP/1 requests whose `transfer-encoding` lists a
+    /// coding other than `chunked`, such as `gzip`.
+    ///
+    /// A request rejected by [`TransferCodingPolicy::Reject`] gets a `501 Not
+    /// Implemented` response.
+    ///
+    /// Default is [`TransferCodingPolicy::PassThrough`].
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_transfer_coding_policy(&mut self, policy: TransferCodingPolicy) -> &mut Self {
+        self.h1_transfer_coding_policy = policy;
+        self
+    }
+
+    /// Set the maximum number of headers of HTTP/1 requests.
+    ///
+    /// A request with more headers is rejected with `431 Request Header
+    /// Fields Too Large`. Up to 100 headers are parsed into slots on the
+    /// stack. With a larger maximum, slots on the heap are only allocated
+    /// for requests that have more than that.
+    ///
+    /// Default is 100.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_max_headers(&mut self, max: usize) -> &mut Self {
+        self.h1_max_headers = Some(max);
+        self
+    }
+
+    /// Set how many headers HTTP/1 requests are expected to have.
+    ///
+    /// The `HeaderMap` of each request is sized for at least this many
+    /// headers, so parsing requests with about as many headers doesn't grow
+    /// it, whatever the size of the previous one on the connection.
+    ///
+    /// Default is 0, sizing it for the headers of each request.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_header_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.h1_header_capacity = capacity;
+        self
+    }
+
+    /// Set how many chunks of an HTTP/1 request body may be read before the
+    /// service receives them.
+    ///
+    /// A deeper buffer lets the connection keep reading while the service
+    /// handles earlier chunks, such as on another thread, at the cost of
+    /// holding more of the body in memory.
+    ///
+    /// Default is 1.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `depth` is 0.
+    #[cfg(feature = "http1")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http1")))]
+    pub fn http1_body_channel_depth(&mut self, depth: usize) -> &mut Self {
+        assert!(depth != 0, "http1_body_channel_depth must be at least 1");
+        self.h1_body_channel_depth = depth;
+        self
+    }
+
     /// Sets whether HTTP2 is required.
     ///
     /// Default is false
@@ -451,6 +874,44 @@ impl<E> Http<E> {
         self
     }
 
+    /// Sets what to do with streams over the
+    /// [`http2_max_concurrent_streams`](Http::http2_max_concurrent_streams)
+    /// limit.
+    ///
+    /// Without a policy, the limit is advertised in the connection settings
+    /// and h2 itself refuses excess streams. With a policy, the limit is
+    /// instead enforced by counting streams whose response isn't finished,
+    /// and isn't advertised to clients.
+    ///
+    /// This does nothing unless `http2_max_concurrent_streams` is set.
+    ///
+    /// Default is `None`.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_stream_overflow(
+        &mut self,
+        policy: impl Into<Option<StreamOverflow>>,
+    ) -> &mut Self {
+        self.h2_builder.stream_overflow = policy.into();
+        self
+    }
+
+    /// Set a callback to run whenever a stream is shed by the
+    /// [`http2_stream_overflow`](Http::http2_stream_overflow) policy.
+    ///
+    /// The callback is passed either [`StreamOverflow::Refuse`] or
+    /// [`StreamOverflow::GoAway`], for what was done with the stream. A
+    /// stream that doesn't fit in a [`StreamOverflow::Queue`] is refused.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_on_stream_overflow<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(StreamOverflow) + Send + Sync + 'static,
+    {
+        self.h2_builder.on_stream_overflow = Some(overflow::OnStreamOverflow::new(f));
+        self
+    }
+
     /// Sets an interval for HTTP2 Ping frames should be sent to keep a
     /// connection alive.
     ///
@@ -520,6 +981,34 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set what to do with fields that RFC 9110 forbids in trailers, such as
+    /// `content-length` or `host`, in HTTP/2 request and response trailers.
+    ///
+    /// With [`TrailerPolicy::Reject`], reading the trailers of a request
+    /// body returns an error, and a response body's stream is reset instead
+    /// of its trailers being sent.
+    ///
+    /// Default is [`TrailerPolicy::Allow`].
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_trailer_policy(&mut self, policy: TrailerPolicy) -> &mut Self {
+        self.h2_builder.trailer_policy = policy;
+        self
+    }
+
+    /// Set whether to add a `content-length` header to HTTP/2 responses whose
+    /// body has a known size, if the response doesn't have one.
+    ///
+    /// Unlike HTTP/1, HTTP/2 doesn't need it to delimit the body.
+    ///
+    /// Default is true.
+    #[cfg(feature = "http2")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http2")))]
+    pub fn http2_content_length_header(&mut self, enabled: bool) -> &mut Self {
+        self.h2_builder.auto_headers.content_length = enabled;
+        self
+    }
+
     /// Set the maximum buffer size for the connection.
     ///
     /// Default is ~400kb.
@@ -538,6 +1027,27 @@ impl<E> Http<E> {
         self
     }
 
+    /// Set whether to add a `date` header to responses that don't have one.
+    ///
+    /// Disabling this, along with
+    /// [`http1_keep_alive_header`](Http::http1_keep_alive_header) and
+    /// [`http2_content_length_header`](Http::http2_content_length_header),
+    /// leaves only the headers needed to delimit an HTTP/1 message, such as
+    /// `content-length` or `transfer-encoding`, to be added to responses.
+    ///
+    /// Default is true.
+    pub fn auto_date_header(&mut self, enabled: bool) -> &mut Self {
+        #[cfg(feature = "http1")]
+        {
+            self.h1_date_header = enabled;
+        }
+        #[cfg(feature = "http2")]
+        {
+            self.h2_builder.auto_headers.date = enabled;
+        }
+        self
+    }
+
     /// Aggregates flushes to better support pipelined responses.
     ///
     /// Experimental, may have bugs.
@@ -548,51 +1058,372 @@ impl<E> Http<E> {
         self
     }
 
-    /// Set the executor used to spawn background tasks.
+    /// Set the maximum size of request bodies, in bytes.
     ///
-    /// Default uses implicit default (like `tokio::spawn`).
-    pub fn with_executor<E2>(self, exec: E2) -> Http<E2> {
-        Http {
-            exec,
-            timer: self.timer,
-            h1_half_close: self.h1_half_close,
-            h1_keep_alive: self.h1_keep_alive,
-            h1_title_case_headers: self.h1_title_case_headers,
-            h1_preserve_header_case: self.h1_preserve_header_case,
-            #[cfg(feature = "http1")]
-            h1_header_read_timeout: self.h1_header_read_timeout,
-            h1_writev: self.h1_writev,
-            #[cfg(feature = "http2")]
-            h2_builder: self.h2_builder,
-            mode: self.mode,
-            max_buf_size: self.max_buf_size,
-            pipeline_flush: self.pipeline_flush,
+    /// A request that declares a larger `content-length` is rejected before
+    /// it reaches the service. If a body turns out larger while it is read,
+    /// reading stops and the body yields an error for which
+    /// [`is_body_too_large`](crate::Error::is_body_too_large) is true.
+    ///
+    /// HTTP/1 connections respond with `413 Payload Too Large`, if a
+    /// response wasn't started yet, and are then closed. HTTP/2 streams are
+    /// reset with `CANCEL`.
+    ///
+    /// Default is None.
+    pub fn max_request_body_size(&mut self, max: u64) -> &mut Self {
+        #[cfg(feature = "http1")]
+        {
+            self.h1_max_body_size = Some(max);
+        }
+        #[cfg(feature = "http2")]
+        {
+            self.h2_builder.max_request_body_size = Some(max);
         }
+        self
     }
 
-    /// Set the timer used in background tasks.
-    pub fn with_timer<M>(self, timer: M) -> Http<E>
+    /// Set the maximum number of responses that may be pending on a
+    /// connection before it stops reading new requests.
+    ///
+    /// For HTTP/1, this counts responses that are written but not yet
+    /// flushed to the connection, such as with
+    /// [`pipeline_flush`](Http::pipeline_flush). Once the limit is reached,
+    /// pipelined requests aren't read until those responses are flushed.
+    ///
+    /// For HTTP/2, this counts streams whose response isn't finished yet.
+    /// Once the limit is reached, new streams aren't accepted until some of
+    /// those finish.
+    ///
+    /// This bounds the memory a client can make the server use by sending
+    /// requests faster than it reads the responses.
+    ///
+    /// Default is None.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `max` is 0.
+    pub fn max_pending_responses(&mut self, max: usize) -> &mut Self {
+        assert!(max != 0, "max_pending_responses must be at least 1");
+        #[cfg(feature = "http1")]
+        {
+            self.h1_max_pending_responses = Some(max);
+        }
+        #[cfg(feature = "http2")]
+        {
+            self.h2_builder.max_pending_responses = Some(max);
+        }
+        self
+    }
+
+    /// Set how much work a connection does each time it is polled, before
+    /// yielding to other futures on the same task.
+    ///
+    /// For HTTP/1, this is how many times the connection is read, written
+    /// and flushed in a row, such as for pipelined requests. For HTTP/2, this
+    /// is how many new streams are accepted.
+    ///
+    /// A smaller budget keeps a busy connection from starving others polled
+    /// by the same task, at the cost of more wakeups.
+    ///
+    /// Default is 16.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `budget` is 0.
+    pub fn poll_budget(&mut self, budget: usize) -> &mut Self {
+        assert!(budget != 0, "poll_budget must be at least 1");
+        #[cfg(feature = "http1")]
+        {
+            self.h1_poll_budget = budget;
+        }
+        #[cfg(feature = "http2")]
+        {
+            self.h2_builder.poll_budget = budget;
+        }
+        self
+    }
+
+    /// Set a callback to run as each request completes, such as to write an
+    /// access log.
+    ///
+    /// It is called with a [`RequestSummary`] once the response has been
+    /// completely written, or when the request fails before that.
+    ///
+    /// Default is None.
+    pub fn on_request_complete<F>(&mut self, f: F) -> &mut Self
     where
-        M: Timer + Send + Sync + 'static,
+        F: Fn(&RequestSummary) + Send + Sync + 'static,
+    {
+        let mut hook = self.request_complete_hook();
+        hook.set_callback(f);
+        self.set_request_complete_hook(hook);
+        self
+    }
+
+    /// Report the bytes, durations, and statuses of requests to `metrics`.
+    ///
+    /// These are reported as each request completes, along with the
+    /// [`on_request_complete`](Http::on_request_complete) callback.
+    ///
+    /// Default is to not report anything.
+    pub fn metrics<M>(&mut self, metrics: M) -> &mut Self
+    where
+        M: Metrics + 'static,
+    {
+        let mut hook = self.request_complete_hook();
+        hook.set_metrics(MetricsHandle::new(metrics));
+        self.set_request_complete_hook(hook);
+        self
+    }
+
+    /// Create a [`ConcurrencyLimit`] that reports to the
+    /// [`metrics`](Http::metrics) of this builder.
+    ///
+    /// The metrics must be set before the limit is created.
+    pub fn concurrency_limit(&self, max_connections: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit::with_metrics(max_connections, self.request_complete_hook().metrics())
+    }
+
+    /// Assign an ID to each request, generated by `generate`.
+    ///
+    /// The ID is inserted as a [`RequestId`] in the request's extensions,
+    /// and is in the [`RequestSummary`] passed to the
+    /// [`on_request_complete`](Http::on_request_complete) callback, so the
+    /// request can be correlated across systems.
+    ///
+    /// Default is to not assign IDs.
+    pub fn request_id<F>(&mut self, generate: F) -> &mut Self
+    where
+        F: Fn() -> RequestId + Send + Sync + 'static,
     {
+        let mut hook = self.request_complete_hook();
+        hook.set_request_id_generator(generate);
+        self.set_request_complete_hook(hook);
+        self
+    }
+
+    /// Assign an ID to each request, adopting it from the `name` header,
+    /// such as `x-request-id`, if the request has one.
+    ///
+    /// Requests without the header get an ID from the generator set with
+    /// [`request_id`](Http::request_id), or from [`RequestId::generate`] if
+    /// there is none. The ID is exposed the same way as with `request_id`.
+    ///
+    /// Default is to not assign IDs.
+    pub fn request_id_header(&mut self, name: HeaderName) -> &mut Self {
+        let mut hook = self.request_complete_hook();
+        hook.set_request_id_header(name);
+        self.set_request_complete_hook(hook);
+        self
+    }
+
+    /// Report polls of the service, or of a response body, that take longer
+    /// than `threshold`.
+    ///
+    /// A poll that takes long is usually the service blocking the thread,
+    /// which holds up every other connection driven by it. Slow polls are
+    /// reported to the [`metrics`](Http::metrics), and as a `slow poll`
+    /// event with the `tracing-spans` feature.
+    ///
+    /// Default is to not time polls.
+    pub fn slow_poll_threshold(&mut self, threshold: Duration) -> &mut Self {
+        let mut hook = self.request_complete_hook();
+        hook.set_slow_poll_threshold(threshold);
+        self.set_request_complete_hook(hook);
+        self
+    }
+
+    /// Pass a copy of the bytes read and written on each connection to
+    /// `capture`.
+    ///
+    /// Default is to not capture anything.
+    #[cfg(feature = "debug")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "debug")))]
+    pub fn capture<C>(&mut self, capture: C) -> &mut Self
+    where
+        C: Capture + 'static,
+    {
+        let capture = CaptureHandle::new(capture);
+        #[cfg(feature = "http1")]
+        {
+            self.h1_capture = Some(capture.clone());
+        }
+        #[cfg(feature = "http2")]
+        {
+            self.h2_builder.capture = Some(capture);
+        }
+        self
+    }
+
+    fn request_complete_hook(&self) -> summary::OnRequestComplete {
+        #[cfg(feature = "http1")]
+        let hook = self.h1_on_request_complete.clone();
+        #[cfg(not(feature = "http1"))]
+        let hook = self.h2_builder.on_request_complete.clone();
+        hook.unwrap_or_default()
+    }
+
+    fn set_request_complete_hook(&mut self, hook: summary::OnRequestComplete) {
+        #[cfg(feature = "http1")]
+        {
+            self.h1_on_request_complete = Some(hook.clone());
+        }
+        #[cfg(feature = "http2")]
+        {
+            self.h2_builder.on_request_complete = Some(hook);
+        }
+    }
+
+    /// Set a timeout for the service to produce a response.
+    ///
+    /// If a service future hasn't resolved to the response head within this
+    /// time, it is dropped, and a response with the
+    /// [`service_timeout_status`](Http::service_timeout_status) is sent in its
+    /// place, so a stuck service can't hold up a connection forever.
+    ///
+    /// This requires a timer to be set with [`with_timer`](Http::with_timer).
+    ///
+    /// Default is None.
+    pub fn service_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.service_timeout.duration = timeout;
+        self
+    }
+
+    /// Set the status of the response sent when the
+    /// [`service_timeout`](Http::service_timeout) elapses, usually
+    /// `503 Service Unavailable` or `504 Gateway Timeout`.
+    ///
+    /// Default is `503 Service Unavailable`.
+    pub fn service_timeout_status(&mut self, status: http::StatusCode) -> &mut Self {
+        self.service_timeout.status = status;
+        self
+    }
+
+    /// Set whether to abort instead of responding when the
+    /// [`service_timeout`](Http::service_timeout) elapses.
+    ///
+    /// HTTP/2 streams are reset with `CANCEL`, while HTTP/1 connections are
+    /// closed, since there is no other way to abort a response.
+    ///
+    /// Default is false.
+    pub fn service_timeout_reset(&mut self, enabled: bool) -> &mut Self {
+        self.service_timeout.reset = enabled;
+        self
+    }
+
+    /// Set whether to add a [`ConnectionUse`](crate::ext::ConnectionUse)
+    /// extension to each request, with how many requests its connection has
+    /// received.
+    ///
+    /// A connection that switches from HTTP/1 to HTTP/2 keeps counting.
+    ///
+    /// Default is false.
+    pub fn connection_use(&mut self, enabled: bool) -> &mut Self {
+        self.connection_use = enabled;
+        self
+    }
+
+    /// Set whether to add a [`PeerDisconnect`](crate::ext::PeerDisconnect)
+    /// extension to each request, which notifies the service if the peer
+    /// goes away before the response is written.
+    ///
+    /// Default is false.
+    pub fn peer_disconnect(&mut self, enabled: bool) -> &mut Self {
+        self.peer_disconnect = enabled;
+        self
+    }
+
+    /// Set the executor used to spawn background tasks.
+    ///
+    /// Default uses implicit default (like `tokio::spawn`).
+    pub fn with_executor<E2>(self, exec: E2) -> Http<E2> {
         Http {
-            exec: self.exec,
-            timer: Time::Timer(Arc::new(timer)),
+            exec,
+            timer: self.timer,
             h1_half_close: self.h1_half_close,
             h1_keep_alive: self.h1_keep_alive,
             h1_title_case_headers: self.h1_title_case_headers,
             h1_preserve_header_case: self.h1_preserve_header_case,
+            h1_lazy_headers: self.h1_lazy_headers,
+            h1_forward_lazy_headers: self.h1_forward_lazy_headers,
             #[cfg(feature = "http1")]
             h1_header_read_timeout: self.h1_header_read_timeout,
+            #[cfg(feature = "http1")]
+            h1_header_read_timeout_response: self.h1_header_read_timeout_response,
+            #[cfg(feature = "http1")]
+            h1_on_parse_error: self.h1_on_parse_error,
+            #[cfg(feature = "http1")]
+            h1_date_header: self.h1_date_header,
+            #[cfg(feature = "http1")]
+            h1_keep_alive_header: self.h1_keep_alive_header,
+            #[cfg(feature = "http1")]
+            h1_max_body_size: self.h1_max_body_size,
+            #[cfg(feature = "http1")]
+            h1_max_pending_responses: self.h1_max_pending_responses,
+            #[cfg(feature = "http1")]
+            h1_on_request_complete: self.h1_on_request_complete,
+            #[cfg(feature = "http1")]
+            h1_poll_budget: self.h1_poll_budget,
+            #[cfg(feature = "http1")]
+            h1_timings: self.h1_timings,
+            #[cfg(feature = "http1")]
+            h1_buffer_pool: self.h1_buffer_pool,
+            #[cfg(feature = "http1")]
+            h1_body_channel_depth: self.h1_body_channel_depth,
+            #[cfg(feature = "http1")]
+            h1_header_capacity: self.h1_header_capacity,
+            #[cfg(feature = "http1")]
+            h1_max_headers: self.h1_max_headers,
+            #[cfg(feature = "http1")]
+            h1_write_batch: self.h1_write_batch,
+            #[cfg(feature = "http1")]
+            h1_strictness: self.h1_strictness,
+            #[cfg(feature = "http1")]
+            h1_header_value_policy: self.h1_header_value_policy,
+            #[cfg(feature = "http1")]
+            h1_validate_header_values: self.h1_validate_header_values,
+            #[cfg(feature = "http1")]
+            h1_sanitize_request_target: self.h1_sanitize_request_target,
+            #[cfg(feature = "http1")]
+            h1_transfer_coding_policy: self.h1_transfer_coding_policy,
+            #[cfg(all(feature = "http1", feature = "debug"))]
+            h1_capture: self.h1_capture,
             h1_writev: self.h1_writev,
             #[cfg(feature = "http2")]
             h2_builder: self.h2_builder,
             mode: self.mode,
             max_buf_size: self.max_buf_size,
             pipeline_flush: self.pipeline_flush,
+            service_timeout: self.service_timeout,
+            connection_use: self.connection_use,
+            peer_disconnect: self.peer_disconnect,
         }
     }
 
+    /// Set the timer used in background tasks.
+    pub fn with_timer<M>(mut self, timer: M) -> Http<E>
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        self.timer.set_timer(Arc::new(timer));
+        self
+    }
+
+    /// Set the clock used to read the current time.
+    ///
+    /// The clock is used for the header read timeout, keep-alive pings, and
+    /// the `Date` header of responses. With a custom clock, the `Date`
+    /// header is rendered for each response, instead of being cached.
+    ///
+    /// Default uses the system clock.
+    pub fn with_clock<C>(mut self, clock: C) -> Http<E>
+    where
+        C: Clock + Send + Sync + 'static,
+    {
+        self.timer.set_clock(Arc::new(clock));
+        self
+    }
+
     /// Bind a connection together with a [`Service`](crate::service::Service).
     ///
     /// This returns a Future that must be polled in order for HTTP to be
@@ -604,10 +1435,10 @@ impl<E> Http<E> {
     /// # use hyper::{Recv, Request, Response};
     /// # use hyper::service::Service;
     /// # use hyper::server::conn::Http;
-    /// # use tokio::io::{AsyncRead, AsyncWrite};
+    /// # use hyper::rt::{Read, Write};
     /// # async fn run<I, S>(some_io: I, some_service: S)
     /// # where
-    /// #     I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    /// #     I: Read + Write + Unpin + Send + 'static,
     /// #     S: Service<hyper::Request<Recv>, Response=hyper::Response<Recv>> + Send + 'static,
     /// #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
     /// #     S::Future: Send,
@@ -627,7 +1458,88 @@ impl<E> Http<E> {
         S::Error: Into<Box<dyn StdError + Send + Sync>>,
         Bd: Body + 'static,
         Bd::Error: Into<Box<dyn StdError + Send + Sync>>,
-        I: AsyncRead + AsyncWrite + Unpin,
+        I: Read + Write + Unpin,
+        E: ConnStreamExec<S::Future, Bd>,
+    {
+        self.serve_connection_with_mode(io, service, &self.mode)
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service),
+    /// making `ctx` available to every request served on it.
+    ///
+    /// The context is attached as a request extension, so the service reads
+    /// it with `req.extensions().get::<C>()`. It is cloned for each request,
+    /// so larger values, like the peer's TLS certificate chain, are best put
+    /// behind an `Arc`.
+    ///
+    /// This is the same as calling
+    /// [`with_extension`](Connection::with_extension) on the connection
+    /// returned by [`serve_connection`](Http::serve_connection).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::net::SocketAddr;
+    /// # use hyper::{Recv, Request, Response};
+    /// # use hyper::service::Service;
+    /// # use hyper::server::conn::Http;
+    /// # use hyper::rt::{Read, Write};
+    /// # async fn run<I, S>(some_io: I, some_service: S)
+    /// # where
+    /// #     I: Read + Write + Unpin + Send + 'static,
+    /// #     S: Service<hyper::Request<Recv>, Response=hyper::Response<Recv>> + Send + 'static,
+    /// #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    /// #     S::Future: Send,
+    /// # {
+    /// #[derive(Clone)]
+    /// struct TlsInfo {
+    ///     sni: Option<String>,
+    ///     alpn: Option<Vec<u8>>,
+    /// }
+    ///
+    /// let ctx = TlsInfo {
+    ///     sni: Some("example.com".into()),
+    ///     alpn: Some(b"http/1.1".to_vec()),
+    /// };
+    /// let http = Http::new();
+    /// let conn = http.serve_connection_with_context(some_io, ctx, some_service);
+    ///
+    /// if let Err(e) = conn.await {
+    ///     eprintln!("server connection error: {}", e);
+    /// }
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn serve_connection_with_context<S, I, Bd, C>(
+        &self,
+        io: I,
+        ctx: C,
+        service: S,
+    ) -> Connection<I, S, E>
+    where
+        S: HttpService<Recv, ResBody = Bd>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Bd: Body + 'static,
+        Bd::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin,
+        E: ConnStreamExec<S::Future, Bd>,
+        C: Clone + Send + Sync + 'static,
+    {
+        self.serve_connection(io, service).with_extension(ctx)
+    }
+
+    fn serve_connection_with_mode<S, I, Bd>(
+        &self,
+        io: I,
+        service: S,
+        mode: &ConnectionMode,
+    ) -> Connection<I, S, E>
+    where
+        S: HttpService<Recv, ResBody = Bd>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        Bd: Body + 'static,
+        Bd::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin,
         E: ConnStreamExec<S::Future, Bd>,
     {
         #[cfg(feature = "http1")]
@@ -649,10 +1561,34 @@ impl<E> Http<E> {
                 if self.h1_preserve_header_case {
                     conn.set_preserve_header_case();
                 }
+                if self.h1_lazy_headers {
+                    conn.set_lazy_headers();
+                }
+                if self.h1_forward_lazy_headers {
+                    conn.set_forward_lazy_headers();
+                }
                 #[cfg(feature = "http1")]
                 if let Some(header_read_timeout) = self.h1_header_read_timeout {
                     conn.set_http1_header_read_timeout(header_read_timeout);
                 }
+                if self.h1_header_read_timeout_response {
+                    conn.set_http1_header_read_timeout_response();
+                }
+                if let Some(ref hook) = self.h1_on_parse_error {
+                    conn.set_on_parse_error(hook.clone());
+                }
+                if !self.h1_date_header {
+                    conn.disable_date_header();
+                }
+                if !self.h1_keep_alive_header {
+                    conn.disable_keep_alive_header();
+                }
+                if let Some(max) = self.h1_max_body_size {
+                    conn.set_max_body_size(max);
+                }
+                if let Some(max) = self.h1_max_pending_responses {
+                    conn.set_max_queued_responses(max);
+                }
                 if let Some(writev) = self.h1_writev {
                     if writev {
                         conn.set_write_strategy_queue();
@@ -664,14 +1600,55 @@ impl<E> Http<E> {
                 if let Some(max) = self.max_buf_size {
                     conn.set_max_buf_size(max);
                 }
-                let sd = proto::h1::dispatch::Server::new(service);
-                ProtoServer::H1 {
-                    h1: proto::h1::Dispatcher::new(sd, conn),
+                if let Some(ref pool) = self.h1_buffer_pool {
+                    conn.set_buffer_pool(pool.clone());
+                }
+                conn.set_body_channel_depth(self.h1_body_channel_depth);
+                conn.set_header_capacity(self.h1_header_capacity);
+                if let Some(max) = self.h1_max_headers {
+                    conn.set_max_headers(max);
                 }
+                if let Some((max_delay, max_bytes)) = self.h1_write_batch {
+                    conn.set_write_batch(max_delay, max_bytes);
+                }
+                conn.set_strictness(self.h1_strictness);
+                conn.set_header_value_policy(self.h1_header_value_policy);
+                conn.set_transfer_coding_policy(self.h1_transfer_coding_policy);
+                if self.h1_validate_header_values {
+                    conn.set_validate_header_values();
+                }
+                if self.h1_sanitize_request_target {
+                    conn.set_sanitize_request_target();
+                }
+                #[cfg(feature = "debug")]
+                if let Some(capture) = self.h1_capture.as_ref().and_then(CaptureHandle::start) {
+                    conn.set_capture(capture);
+                }
+                let mut sd = proto::h1::dispatch::Server::new(service);
+                sd.set_service_timeout(self.service_timeout, self.timer.clone());
+                if self.connection_use {
+                    sd.extensions.count_exchanges();
+                }
+                if self.peer_disconnect {
+                    sd.extensions.notify_peer_disconnect();
+                }
+                if let Some(ref callback) = self.h1_on_request_complete {
+                    sd.set_on_request_complete(callback.clone());
+                }
+                if self.h1_timings {
+                    sd.set_timings(crate::ext::ConnTimings {
+                        connected: self.timer.now(),
+                        tls_handshaked: None,
+                        time: self.timer.clone(),
+                    });
+                }
+                let mut h1 = proto::h1::Dispatcher::new(sd, conn);
+                h1.set_poll_budget(self.h1_poll_budget);
+                ProtoServer::H1 { h1 }
             }};
         }
 
-        let proto = match self.mode {
+        let proto = match *mode {
             #[cfg(feature = "http1")]
             #[cfg(not(feature = "http2"))]
             ConnectionMode::H1Only => h1!(),
@@ -681,13 +1658,20 @@ impl<E> Http<E> {
             #[cfg(feature = "http2")]
             ConnectionMode::H2Only => {
                 let rewind_io = Rewind::new(io);
-                let h2 = proto::h2::Server::new(
+                let mut h2 = proto::h2::Server::new(
                     rewind_io,
                     service,
                     &self.h2_builder,
                     self.exec.clone(),
                     self.timer.clone(),
                 );
+                h2.set_service_timeout(self.service_timeout);
+                if self.connection_use {
+                    h2.conn_extensions_mut().count_exchanges();
+                }
+                if self.peer_disconnect {
+                    h2.conn_extensions_mut().notify_peer_disconnect();
+                }
                 ProtoServer::H2 { h2 }
             }
         };
@@ -695,7 +1679,7 @@ impl<E> Http<E> {
         Connection {
             conn: Some(proto),
             #[cfg(all(feature = "http1", feature = "http2"))]
-            fallback: if self.mode == ConnectionMode::Fallback {
+            fallback: if *mode == ConnectionMode::Fallback {
                 Fallback::ToHttp2(
                     self.h2_builder.clone(),
                     self.exec.clone(),
@@ -717,7 +1701,7 @@ impl<I, B, S, E> Connection<I, S, E>
 where
     S: HttpService<Recv, ResBody = B>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
-    I: AsyncRead + AsyncWrite + Unpin,
+    I: Read + Write + Unpin,
     B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
     E: ConnStreamExec<S::Future, B>,
@@ -732,11 +1716,20 @@ where
     /// This should only be called while the `Connection` future is still
     /// pending. If called after `Connection::poll` has resolved, this does
     /// nothing.
-    pub fn graceful_shutdown(mut self: Pin<&mut Self>) {
+    pub fn graceful_shutdown(self: Pin<&mut Self>) {
+        self.start_graceful_shutdown(false);
+    }
+
+    #[cfg_attr(not(feature = "http1"), allow(unused_variables))]
+    fn start_graceful_shutdown(mut self: Pin<&mut Self>, close_unused: bool) {
         match self.conn {
             #[cfg(feature = "http1")]
             Some(ProtoServer::H1 { ref mut h1, .. }) => {
-                h1.disable_keep_alive();
+                if close_unused {
+                    h1.disable_keep_alive_or_close_unused();
+                } else {
+                    h1.disable_keep_alive();
+                }
             }
             #[cfg(feature = "http2")]
             Some(ProtoServer::H2 { ref mut h2 }) => {
@@ -751,6 +1744,93 @@ where
         }
     }
 
+    /// Attach a value to every request served on this connection, as a
+    /// request extension.
+    ///
+    /// This is meant for metadata about the connection, such as the peer's
+    /// `SocketAddr` returned when accepting it, so the service can read it
+    /// with `req.extensions().get::<SocketAddr>()`. It can be called several
+    /// times to attach values of different types.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use hyper::{Recv, Request, Response};
+    /// # use hyper::service::Service;
+    /// # use hyper::server::conn::Http;
+    /// # use hyper::rt::{Read, Write};
+    /// # async fn run<I, S>(stream: I, peer_addr: std::net::SocketAddr, some_service: S)
+    /// # where
+    /// #     I: Read + Write + Unpin + Send + 'static,
+    /// #     S: Service<hyper::Request<Recv>, Response=hyper::Response<Recv>> + Send + 'static,
+    /// #     S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    /// #     S::Future: Send,
+    /// # {
+    /// let conn = Http::new()
+    ///     .serve_connection(stream, some_service)
+    ///     .with_extension(peer_addr);
+    /// # drop(conn);
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn with_extension<X>(mut self, value: X) -> Self
+    where
+        X: Clone + Send + Sync + 'static,
+    {
+        match self.conn {
+            #[cfg(feature = "http1")]
+            Some(ProtoServer::H1 { ref mut h1, .. }) => h1.conn_extensions_mut().insert(value),
+            #[cfg(feature = "http2")]
+            Some(ProtoServer::H2 { ref mut h2 }) => h2.conn_extensions_mut().insert(value),
+            None => (),
+
+            #[cfg(not(feature = "http1"))]
+            Some(ProtoServer::H1 { ref mut h1, .. }) => match h1.0 {},
+            #[cfg(not(feature = "http2"))]
+            Some(ProtoServer::H2 { ref mut h2 }) => match h2.0 {},
+        }
+        self
+    }
+
+    /// Record that the connection was served after a TLS handshake, from
+    /// `started` until `done`, in the timings of HTTP/1 requests.
+    #[cfg(all(feature = "http1", feature = "http2"))]
+    pub(crate) fn with_tls_handshake(mut self, started: Instant, done: Instant) -> Self {
+        if let Some(ProtoServer::H1 { ref mut h1, .. }) = self.conn {
+            h1.set_tls_handshake(started, done);
+        }
+        self
+    }
+
+    /// The number of requests currently being served on this connection.
+    ///
+    /// A request counts from when its head is received until its response
+    /// has been completely written. For HTTP/1 this is at most one, or two
+    /// when the next request is read while pipelining. For HTTP/2 this is
+    /// the number of streams whose response isn't finished yet.
+    pub fn in_flight(&self) -> usize {
+        match self.conn {
+            #[cfg(feature = "http1")]
+            Some(ProtoServer::H1 { ref h1, .. }) => h1.in_flight(),
+            #[cfg(feature = "http2")]
+            Some(ProtoServer::H2 { ref h2 }) => h2.in_flight(),
+            None => 0,
+
+            #[cfg(not(feature = "http1"))]
+            Some(ProtoServer::H1 { ref h1, .. }) => match h1.0 {},
+            #[cfg(not(feature = "http2"))]
+            Some(ProtoServer::H2 { ref h2 }) => match h2.0 {},
+        }
+    }
+
+    /// Returns whether no requests are currently being served on this
+    /// connection.
+    ///
+    /// This is the same as `in_flight() == 0`.
+    pub fn is_idle(&self) -> bool {
+        self.in_flight() == 0
+    }
+
     /// Return the inner IO object, and additional information.
     ///
     /// If the IO object has been "rewound" the io will not contain those bytes rewound.
@@ -807,7 +1887,7 @@ where
                     Ok(()) => return Poll::Ready(Ok(())),
                     Err(e) => {
                         #[cfg(feature = "http2")]
-                        match *e.kind() {
+                        match *e.inner_kind() {
                             Kind::Parse(Parse::VersionH2) if self.fallback.to_h2() => {
                                 self.upgrade_h2();
                                 continue;
@@ -832,6 +1912,11 @@ where
     /// Prevent shutdown of the underlying IO object at the end of service the request,
     /// instead run `into_parts`. This is a convenience wrapper over `poll_without_shutdown`.
     ///
+    /// To hand the IO object to another protocol handler after the current
+    /// exchange, disable [`http1_keep_alive`](Http::http1_keep_alive) or call
+    /// `graceful_shutdown` once the request was received. Any bytes read
+    /// after that request are returned in [`Parts::read_buf`].
+    ///
     /// # Error
     ///
     /// This errors if the underlying connection protocol is not HTTP/1.
@@ -870,13 +1955,17 @@ where
             Fallback::ToHttp2(ref builder, ref exec, ref timer) => (builder, exec, timer),
             Fallback::Http1Only => unreachable!("upgrade_h2 with Fallback::Http1Only"),
         };
-        let h2 = proto::h2::Server::new(
+        let extensions = dispatch.extensions.clone();
+        let service_timeout = dispatch.service_timeout;
+        let mut h2 = proto::h2::Server::new(
             rewind_io,
             dispatch.into_service(),
             builder,
             exec.clone(),
             timer.clone(),
         );
+        *h2.conn_extensions_mut() = extensions;
+        h2.set_service_timeout(service_timeout);
 
         debug_assert!(self.conn.is_none());
         self.conn = Some(ProtoServer::H2 { h2 });
@@ -898,7 +1987,7 @@ impl<I, B, S, E> Future for Connection<I, S, E>
 where
     S: HttpService<Recv, ResBody = B>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
-    I: AsyncRead + AsyncWrite + Unpin + 'static,
+    I: Read + Write + Unpin + 'static,
     B: Body + 'static,
     B::Error: Into<Box<dyn StdError + Send + Sync>>,
     E: ConnStreamExec<S::Future, B>,
@@ -925,7 +2014,7 @@ where
                 Err(e) => {
                     #[cfg(feature = "http1")]
                     #[cfg(feature = "http2")]
-                    match *e.kind() {
+                    match *e.inner_kind() {
                         Kind::Parse(Parse::VersionH2) if self.fallback.to_h2() => {
                             self.upgrade_h2();
                             continue;
@@ -975,7 +2064,7 @@ impl Default for ConnectionMode {
 #[cfg(any(feature = "http1", feature = "http2"))]
 impl<T, B, S, E> Future for ProtoServer<T, B, S, E>
 where
-    T: AsyncRead + AsyncWrite + Unpin,
+    T: Read + Write + Unpin,
     S: HttpService<Recv, ResBody = B>,
     S::Error: Into<Box<dyn StdError + Send + Sync>>,
     B: Body + 'static,
@@ -1020,7 +2109,7 @@ mod upgrades {
     where
         S: HttpService<Recv, ResBody = B>,
         S::Error: Into<Box<dyn StdError + Send + Sync>>,
-        I: AsyncRead + AsyncWrite + Unpin,
+        I: Read + Write + Unpin,
         B: Body + 'static,
         B::Error: Into<Box<dyn StdError + Send + Sync>>,
         E: ConnStreamExec<S::Future, B>,
@@ -1032,13 +2121,26 @@ mod upgrades {
         pub fn graceful_shutdown(mut self: Pin<&mut Self>) {
             Pin::new(&mut self.inner).graceful_shutdown()
         }
+
+        /// The number of requests currently being served on this connection.
+        ///
+        /// See [`Connection::in_flight`].
+        pub fn in_flight(&self) -> usize {
+            self.inner.in_flight()
+        }
+
+        /// Returns whether no requests are currently being served on this
+        /// connection.
+        pub fn is_idle(&self) -> bool {
+            self.inner.is_idle()
+        }
     }
 
     impl<I, B, S, E> Future for UpgradeableConnection<I, S, E>
     where
         S: HttpService<Recv, ResBody = B>,
         S::Error: Into<Box<dyn StdError + Send + Sync>>,
-        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        I: Read + Write + Unpin + Send + 'static,
         B: Body + 'static,
         B::Error: Into<Box<dyn StdError + Send + Sync>>,
         E: ConnStreamExec<S::Future, B>,
@@ -1066,7 +2168,7 @@ mod upgrades {
                     Err(e) => {
                         #[cfg(feature = "http1")]
                         #[cfg(feature = "http2")]
-                        match *e.kind() {
+                        match *e.inner_kind() {
                             Kind::Parse(Parse::VersionH2) if self.inner.fallback.to_h2() => {
                                 self.inner.upgrade_h2();
                                 continue;
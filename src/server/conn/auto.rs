@@ -0,0 +1,363 @@
+//! Serve HTTP/1 and HTTP/2 on the same connection.
+//!
+//! The [`Builder`] in this module picks the protocol for each connection,
+//! so the same accept loop can serve clients of either version:
+//!
+//! - If the TLS acceptor negotiated a protocol with ALPN, pass it to
+//!   [`serve_connection_with_alpn`](Builder::serve_connection_with_alpn) and
+//!   that protocol is used right away. Or let
+//!   [`serve_tls_connection`](Builder::serve_tls_connection) perform the
+//!   TLS handshake with a [`TlsAccept`], and use the negotiated protocol.
+//! - Otherwise, the connection starts as HTTP/1, and switches to HTTP/2 if the
+//!   client sends the HTTP/2 connection preface
+//!   (`PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n`) as its first bytes.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # async fn accept() -> std::io::Result<(hyper::upgrade::Upgraded, std::net::SocketAddr)> {
+//! #     unimplemented!()
+//! # }
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::convert::Infallible;
+//!
+//! use bytes::Bytes;
+//! use http_body_util::Full;
+//! use hyper::server::conn::auto;
+//! use hyper::service::service_fn;
+//! use hyper::{Recv, Request, Response};
+//!
+//! async fn hello(_req: Request<Recv>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     Ok(Response::new(Full::new(Bytes::from("Hello World!"))))
+//! }
+//!
+//! let builder = auto::Builder::new();
+//! loop {
+//!     // Any IO implementing `hyper::rt::Read` and `hyper::rt::Write`.
+//!     let (stream, _) = accept().await?;
+//!     let conn = builder.serve_connection(stream, service_fn(hello));
+//!     tokio::spawn(async move {
+//!         if let Err(err) = conn.await {
+//!             eprintln!("server connection error: {}", err);
+//!         }
+//!     });
+//! }
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Instant;
+
+use pin_project_lite::pin_project;
+
+use super::{Connection, ConnectionMode, Http, TlsAccept, TlsStream, UpgradeableConnection};
+use crate::body::{Body, Recv};
+use crate::common::exec::{ConnStreamExec, Exec};
+use crate::common::{task, Future, Pin, Poll};
+use crate::ext::{PeerCertificates, ServerName};
+use crate::rt::{Clock, Read, Timer, Write};
+use crate::service::HttpService;
+
+/// A configuration for connections that can be either HTTP/1 or HTTP/2.
+///
+/// The HTTP/1 and HTTP/2 options are those of the wrapped [`Http`], which
+/// can be passed in with `From`. Its `http1_only` and `http2_only` options
+/// are ignored, since the protocol is chosen per connection.
+#[derive(Clone, Debug)]
+pub struct Builder<E = Exec> {
+    http: Http<E>,
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Creates a new builder with the default options.
+    pub fn new() -> Builder {
+        Builder { http: Http::new() }
+    }
+}
+
+impl<E> Builder<E> {
+    /// Set the executor used to spawn background tasks.
+    ///
+    /// Default uses implicit default (like `tokio::spawn`).
+    pub fn with_executor<E2>(self, exec: E2) -> Builder<E2> {
+        Builder {
+            http: self.http.with_executor(exec),
+        }
+    }
+
+    /// Set the timer used in background tasks.
+    pub fn with_timer<M>(self, timer: M) -> Builder<E>
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        Builder {
+            http: self.http.with_timer(timer),
+        }
+    }
+
+    /// Set the clock used to read the current time.
+    ///
+    /// See [`Http::with_clock`] for what the clock is used for.
+    pub fn with_clock<C>(self, clock: C) -> Builder<E>
+    where
+        C: Clock + Send + Sync + 'static,
+    {
+        Builder {
+            http: self.http.with_clock(clock),
+        }
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service),
+    /// detecting the protocol from the client connection preface.
+    ///
+    /// This returns a Future that must be polled in order for HTTP to be
+    /// driven on the connection.
+    pub fn serve_connection<S, I, B>(&self, io: I, service: S) -> Connection<I, S, E>
+    where
+        S: HttpService<Recv, ResBody = B>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin,
+        E: ConnStreamExec<S::Future, B>,
+    {
+        self.serve_connection_with_alpn(io, None, service)
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service),
+    /// using the protocol negotiated with ALPN.
+    ///
+    /// `alpn` is the protocol the TLS acceptor selected, if any. `h2` serves
+    /// HTTP/2, while `http/1.1` and `http/1.0` serve HTTP/1. For any other
+    /// value, or `None`, the protocol is detected from the client connection
+    /// preface, as with [`serve_connection`](Builder::serve_connection).
+    pub fn serve_connection_with_alpn<S, I, B>(
+        &self,
+        io: I,
+        alpn: Option<&[u8]>,
+        service: S,
+    ) -> Connection<I, S, E>
+    where
+        S: HttpService<Recv, ResBody = B>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin,
+        E: ConnStreamExec<S::Future, B>,
+    {
+        self.http
+            .serve_connection_with_mode(io, service, &alpn_mode(alpn))
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service),
+    /// after performing a TLS handshake on it with `acceptor`.
+    ///
+    /// The protocol negotiated with ALPN is then used as with
+    /// [`serve_connection_with_alpn`](Builder::serve_connection_with_alpn).
+    /// The client certificates and server name of the TLS session, if any,
+    /// are added to each request as [`PeerCertificates`] and [`ServerName`]
+    /// extensions.
+    /// If the handshake fails, the returned future resolves with an error
+    /// for which [`is_tls`](crate::Error::is_tls) is true.
+    pub fn serve_tls_connection<S, I, A, B>(
+        &self,
+        io: I,
+        acceptor: &A,
+        service: S,
+    ) -> TlsConnection<I, A, S, E>
+    where
+        A: TlsAccept<I>,
+        S: HttpService<Recv, ResBody = B>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        E: ConnStreamExec<S::Future, B>,
+    {
+        TlsConnection {
+            state: TlsState::Handshaking {
+                started: self.http.timer.now(),
+                accept: acceptor.accept(io),
+                http: Some(self.http.clone()),
+                service: Some(service),
+            },
+        }
+    }
+
+    /// Bind a connection together with a [`Service`](crate::service::Service),
+    /// detecting the protocol from the client connection preface, with
+    /// support for [upgrades](crate::upgrade).
+    ///
+    /// This way, the service can use [`hyper::upgrade::on`](crate::upgrade::on)
+    /// the same for either protocol: an HTTP/1 request responded to with
+    /// `101 Switching Protocols`, such as a WebSocket handshake, as well as
+    /// an HTTP/2 extended `CONNECT` request responded to with a success
+    /// status.
+    ///
+    /// HTTP/2 clients can only send extended `CONNECT` requests if that was
+    /// enabled with
+    /// [`Http::http2_enable_connect_protocol`](Http::http2_enable_connect_protocol)
+    /// on the `Http` this builder was created from.
+    pub fn serve_connection_with_upgrades<S, I, B>(
+        &self,
+        io: I,
+        service: S,
+    ) -> UpgradeableConnection<I, S, E>
+    where
+        S: HttpService<Recv, ResBody = B>,
+        S::Error: Into<Box<dyn StdError + Send + Sync>>,
+        B: Body + 'static,
+        B::Error: Into<Box<dyn StdError + Send + Sync>>,
+        I: Read + Write + Unpin + Send,
+        E: ConnStreamExec<S::Future, B>,
+    {
+        self.serve_connection(io, service).with_upgrades()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+impl<E> From<Http<E>> for Builder<E> {
+    fn from(http: Http<E>) -> Builder<E> {
+        Builder { http }
+    }
+}
+
+fn alpn_mode(alpn: Option<&[u8]>) -> ConnectionMode {
+    match alpn {
+        Some(b"h2") => ConnectionMode::H2Only,
+        Some(b"http/1.1") | Some(b"http/1.0") => ConnectionMode::H1Only,
+        _ => ConnectionMode::Fallback,
+    }
+}
+
+pin_project! {
+    /// A future serving a connection once its TLS handshake is done.
+    ///
+    /// This is returned by [`Builder::serve_tls_connection`].
+    #[must_use = "futures do nothing unless polled"]
+    pub struct TlsConnection<I, A, S, E = Exec>
+    where
+        A: TlsAccept<I>,
+        S: HttpService<Recv>,
+    {
+        #[pin]
+        state: TlsState<I, A, S, E>,
+    }
+}
+
+pin_project! {
+    #[project = TlsStateProj]
+    enum TlsState<I, A, S, E>
+    where
+        A: TlsAccept<I>,
+        S: HttpService<Recv>,
+    {
+        Handshaking {
+            started: Instant,
+            #[pin]
+            accept: A::Future,
+            http: Option<Http<E>>,
+            service: Option<S>,
+        },
+        Serving {
+            #[pin]
+            conn: Connection<A::Stream, S, E>,
+        },
+        Closed,
+    }
+}
+
+// ===== impl TlsConnection =====
+
+impl<I, A, S, E, B> TlsConnection<I, A, S, E>
+where
+    A: TlsAccept<I>,
+    S: HttpService<Recv, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: ConnStreamExec<S::Future, B>,
+{
+    /// Start a graceful shutdown process for this connection.
+    ///
+    /// If the TLS handshake isn't done yet, the connection is closed right
+    /// away. Otherwise, this is the same as
+    /// [`Connection::graceful_shutdown`].
+    pub fn graceful_shutdown(self: Pin<&mut Self>) {
+        self.start_graceful_shutdown(false);
+    }
+
+    pub(super) fn start_graceful_shutdown(self: Pin<&mut Self>, close_unused: bool) {
+        let mut state = self.project().state;
+        match state.as_mut().project() {
+            TlsStateProj::Handshaking { .. } => state.set(TlsState::Closed),
+            TlsStateProj::Serving { conn } => conn.start_graceful_shutdown(close_unused),
+            TlsStateProj::Closed => (),
+        }
+    }
+}
+
+impl<I, A, S, E> fmt::Debug for TlsConnection<I, A, S, E>
+where
+    A: TlsAccept<I>,
+    S: HttpService<Recv>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnection").finish()
+    }
+}
+
+impl<I, A, S, E, B> Future for TlsConnection<I, A, S, E>
+where
+    A: TlsAccept<I>,
+    A::Stream: 'static,
+    S: HttpService<Recv, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: ConnStreamExec<S::Future, B>,
+{
+    type Output = crate::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.project().state;
+        loop {
+            match state.as_mut().project() {
+                TlsStateProj::Handshaking {
+                    started,
+                    accept,
+                    http,
+                    service,
+                } => {
+                    let stream = ready!(accept.poll(cx)).map_err(crate::Error::new_tls)?;
+                    let http = http.take().expect("polled after complete");
+                    let service = service.take().expect("polled after complete");
+                    let mode = alpn_mode(stream.alpn_protocol());
+                    let certs = stream.peer_certificates().map(PeerCertificates::new);
+                    let name = stream.server_name().map(ServerName::new);
+                    let done = http.timer.now();
+                    let mut conn = http
+                        .serve_connection_with_mode(stream, service, &mode)
+                        .with_tls_handshake(*started, done);
+                    if let Some(certs) = certs {
+                        conn = conn.with_extension(certs);
+                    }
+                    if let Some(name) = name {
+                        conn = conn.with_extension(name);
+                    }
+                    state.set(TlsState::Serving { conn });
+                }
+                TlsStateProj::Serving { conn } => return conn.poll(cx),
+                TlsStateProj::Closed => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
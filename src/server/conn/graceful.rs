@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::Duration;
+
+use futures_util::future::{self, Either};
+use pin_project_lite::pin_project;
+
+use super::{Connection, UpgradeableConnection};
+use crate::body::{Body, Recv};
+use crate::common::exec::ConnStreamExec;
+use crate::common::time::Time;
+use crate::common::{task, Future, Pin, Poll};
+use crate::rt::{Read, Timer, Write};
+use crate::service::HttpService;
+
+/// Coordinates the graceful shutdown of many connections.
+///
+/// Wrap each connection with [`watch`](GracefulShutdown::watch) before
+/// spawning it. Calling [`shutdown`](GracefulShutdown::shutdown) then starts
+/// a graceful shutdown of every watched connection, and waits for all of
+/// them to finish.
+///
+/// This works for HTTP/1 and HTTP/2 connections alike, including those from
+/// the [`auto`](super::auto) builder.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn accept() -> std::io::Result<(hyper::upgrade::Upgraded, std::net::SocketAddr)> {
+/// #     unimplemented!()
+/// # }
+/// # async fn run() -> hyper::Result<()> {
+/// # use std::convert::Infallible;
+/// # use bytes::Bytes;
+/// # use http_body_util::Full;
+/// # use hyper::{Recv, Request, Response};
+/// # async fn hello(_: Request<Recv>) -> Result<Response<Full<Bytes>>, Infallible> {
+/// #     Ok(Response::new(Full::new(Bytes::from("Hello World!"))))
+/// # }
+/// # let ctrl_c = std::future::pending::<()>();
+/// use hyper::server::conn::{GracefulShutdown, Http};
+/// use hyper::service::service_fn;
+///
+/// let http = Http::new();
+/// let graceful = GracefulShutdown::new();
+/// tokio::pin!(ctrl_c);
+///
+/// loop {
+///     tokio::select! {
+///         Ok((stream, _)) = accept() => {
+///             let conn = http.serve_connection(stream, service_fn(hello));
+///             let conn = graceful.watch(conn);
+///             tokio::spawn(async move {
+///                 if let Err(err) = conn.await {
+///                     eprintln!("server connection error: {}", err);
+///                 }
+///             });
+///         }
+///         _ = &mut ctrl_c => break,
+///     }
+/// }
+///
+/// graceful.shutdown().await;
+/// # Ok(())
+/// # }
+/// # fn main() {}
+/// ```
+pub struct GracefulShutdown {
+    shared: Arc<Shared>,
+    timer: Time,
+}
+
+struct Shared {
+    state: Mutex<State>,
+}
+
+struct State {
+    signaled: bool,
+    connections: usize,
+    next_id: usize,
+    // Watched connections waiting for the signal, by id.
+    watchers: HashMap<usize, Waker>,
+    // `shutdown`, waiting for the connections to finish.
+    finished: Option<Waker>,
+}
+
+/// A connection that can be gracefully shutdown.
+///
+/// This is implemented for the server connection futures, so they can be
+/// passed to [`GracefulShutdown::watch`].
+pub trait GracefulConnection: Future<Output = crate::Result<()>> + sealed::Sealed {
+    /// Start a graceful shutdown process for this connection.
+    fn graceful_shutdown(self: Pin<&mut Self>);
+}
+
+pin_project! {
+    /// A connection watched by a [`GracefulShutdown`].
+    ///
+    /// Polling this future drives the wrapped connection.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct Watched<C> {
+        #[pin]
+        conn: C,
+        guard: Guard,
+        signaled: bool,
+    }
+}
+
+// Counts a watched connection as live until dropped.
+struct Guard {
+    shared: Arc<Shared>,
+    id: usize,
+}
+
+// ===== impl GracefulShutdown =====
+
+impl GracefulShutdown {
+    /// Creates a new `GracefulShutdown`, not watching any connections yet.
+    pub fn new() -> GracefulShutdown {
+        GracefulShutdown {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    signaled: false,
+                    connections: 0,
+                    next_id: 0,
+                    watchers: HashMap::new(),
+                    finished: None,
+                }),
+            }),
+            timer: Time::default(),
+        }
+    }
+
+    /// Set the timer used by [`shutdown_timeout`](GracefulShutdown::shutdown_timeout).
+    pub fn with_timer<M>(mut self, timer: M) -> GracefulShutdown
+    where
+        M: Timer + Send + Sync + 'static,
+    {
+        self.timer.set_timer(Arc::new(timer));
+        self
+    }
+
+    /// Watch a connection, so it is gracefully shutdown along with the
+    /// others.
+    ///
+    /// The returned future must be polled instead of the connection.
+    pub fn watch<C: GracefulConnection>(&self, conn: C) -> Watched<C> {
+        let id = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.connections += 1;
+            state.next_id = state.next_id.wrapping_add(1);
+            state.next_id
+        };
+        Watched {
+            conn,
+            guard: Guard {
+                shared: self.shared.clone(),
+                id,
+            },
+            signaled: false,
+        }
+    }
+
+    /// The number of watched connections that haven't finished yet.
+    pub fn count(&self) -> usize {
+        self.shared.state.lock().unwrap().connections
+    }
+
+    /// Start a graceful shutdown of all watched connections, and wait for
+    /// them to finish.
+    pub async fn shutdown(self) {
+        let watchers = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.signaled = true;
+            std::mem::take(&mut state.watchers)
+        };
+        for (_, waker) in watchers {
+            waker.wake();
+        }
+
+        future::poll_fn(|cx| {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.connections == 0 {
+                return Poll::Ready(());
+            }
+            state.finished = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await;
+    }
+
+    /// Like [`shutdown`](GracefulShutdown::shutdown), but gives up waiting
+    /// after `timeout`.
+    ///
+    /// If the timeout elapses first, this returns an error for which
+    /// [`is_timeout`](crate::Error::is_timeout) is true. The remaining
+    /// connections keep running, and it is up to the caller to abort them,
+    /// such as by dropping their tasks.
+    ///
+    /// # Panics
+    ///
+    /// This panics if no timer was set with
+    /// [`with_timer`](GracefulShutdown::with_timer).
+    pub async fn shutdown_timeout(self, timeout: Duration) -> crate::Result<()> {
+        let sleep = self.timer.sleep(timeout);
+        let shutdown = Box::pin(self.shutdown());
+        match future::select(shutdown, sleep).await {
+            Either::Left(((), _)) => Ok(()),
+            Either::Right(((), _)) => Err(crate::Error::new_graceful_shutdown_timeout()),
+        }
+    }
+}
+
+impl Default for GracefulShutdown {
+    fn default() -> GracefulShutdown {
+        GracefulShutdown::new()
+    }
+}
+
+impl fmt::Debug for GracefulShutdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GracefulShutdown")
+            .field("connections", &self.count())
+            .finish()
+    }
+}
+
+// ===== impl Watched =====
+
+impl<C: GracefulConnection> Future for Watched<C> {
+    type Output = crate::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let mut me = self.project();
+        if !*me.signaled {
+            let signaled = {
+                let mut state = me.guard.shared.state.lock().unwrap();
+                if !state.signaled {
+                    state.watchers.insert(me.guard.id, cx.waker().clone());
+                }
+                state.signaled
+            };
+            if signaled {
+                *me.signaled = true;
+                me.conn.as_mut().graceful_shutdown();
+            }
+        }
+        me.conn.poll(cx)
+    }
+}
+
+impl<C> fmt::Debug for Watched<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watched").finish()
+    }
+}
+
+// ===== impl Guard =====
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let finished = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.watchers.remove(&self.id);
+            state.connections -= 1;
+            if state.connections == 0 {
+                state.finished.take()
+            } else {
+                None
+            }
+        };
+        if let Some(waker) = finished {
+            waker.wake();
+        }
+    }
+}
+
+// ===== impl GracefulConnection =====
+
+impl<I, B, S, E> GracefulConnection for Connection<I, S, E>
+where
+    S: HttpService<Recv, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Unpin + 'static,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: ConnStreamExec<S::Future, B>,
+{
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        // Unlike `Connection::graceful_shutdown`, HTTP/1 connections that
+        // haven't received a request yet are closed right away.
+        self.start_graceful_shutdown(true);
+    }
+}
+
+impl<I, B, S, E> GracefulConnection for UpgradeableConnection<I, S, E>
+where
+    S: HttpService<Recv, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Unpin + Send + 'static,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: ConnStreamExec<S::Future, B>,
+{
+    fn graceful_shutdown(mut self: Pin<&mut Self>) {
+        Pin::new(&mut self.inner).start_graceful_shutdown(true);
+    }
+}
+
+#[cfg(all(feature = "http1", feature = "http2"))]
+impl<I, A, B, S, E> GracefulConnection for super::auto::TlsConnection<I, A, S, E>
+where
+    A: super::TlsAccept<I>,
+    A::Stream: 'static,
+    S: HttpService<Recv, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+    E: ConnStreamExec<S::Future, B>,
+{
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        self.start_graceful_shutdown(true);
+    }
+}
+
+mod sealed {
+    use super::{Connection, HttpService, Recv, UpgradeableConnection};
+
+    pub trait Sealed {}
+
+    impl<I, S: HttpService<Recv>, E> Sealed for Connection<I, S, E> {}
+
+    impl<I, S: HttpService<Recv>, E> Sealed for UpgradeableConnection<I, S, E> {}
+
+    #[cfg(all(feature = "http1", feature = "http2"))]
+    impl<I, A, S, E> Sealed for crate::server::conn::auto::TlsConnection<I, A, S, E>
+    where
+        A: crate::server::conn::TlsAccept<I>,
+        S: HttpService<Recv>,
+    {
+    }
+}
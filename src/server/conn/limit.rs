@@ -0,0 +1,651 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+
+use http::header::{HeaderValue, CONNECTION};
+use http::{Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+
+use crate::common::{task, Future, Pin, Poll};
+use crate::metrics::{LimitScope, Metrics, MetricsHandle};
+use crate::service::Service;
+
+/// Limits how many connections, and optionally requests, are served at once.
+///
+/// Before accepting a connection, an accept loop calls
+/// [`acquire`](ConcurrencyLimit::acquire) to get a [`ConnectionPermit`], and
+/// then wraps the connection's service with
+/// [`service`](ConcurrencyLimit::service). The permit is released when the
+/// connection, and so the service, is dropped.
+///
+/// What happens once the limit is reached depends on [`WhenSaturated`].
+/// Requests over the [`max_requests`](ConcurrencyLimit::max_requests) limit
+/// are always answered with a `503 Service Unavailable`, since they have
+/// already been read by then.
+///
+/// A `ConcurrencyLimit` is cheap to clone, and clones share the same counts.
+/// Options should be set before cloning.
+///
+/// A limit created with [`Http::concurrency_limit`] reports when it is
+/// saturated, what it rejects, and how many connections and requests it is
+/// serving to the builder's [`metrics`](Http::metrics).
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn accept() -> std::io::Result<(hyper::upgrade::Upgraded, std::net::SocketAddr)> {
+/// #     unimplemented!()
+/// # }
+/// # async fn run() -> std::io::Result<()> {
+/// # use std::convert::Infallible;
+/// # use bytes::Bytes;
+/// # use http_body_util::Full;
+/// # use hyper::{Recv, Request, Response};
+/// # async fn hello(_: Request<Recv>) -> Result<Response<Full<Bytes>>, Infallible> {
+/// #     Ok(Response::new(Full::new(Bytes::from("Hello World!"))))
+/// # }
+/// use hyper::server::conn::Http;
+/// use hyper::service::service_fn;
+///
+/// let http = Http::new();
+/// let mut limit = http.concurrency_limit(1_000);
+/// limit.max_requests(5_000);
+///
+/// loop {
+///     let permit = limit.acquire().await;
+///     let (stream, _) = accept().await?;
+///     let service = limit.service(permit, service_fn(hello));
+///     let conn = http.serve_connection(stream, service);
+///     tokio::spawn(async move {
+///         if let Err(err) = conn.await {
+///             eprintln!("server connection error: {}", err);
+///         }
+///     });
+/// }
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    max_connections: usize,
+    connections: Arc<Semaphore>,
+    requests: Option<(usize, Arc<Semaphore>)>,
+    rejected: Arc<AtomicU64>,
+    when_saturated: WhenSaturated,
+    metrics: Option<MetricsHandle>,
+}
+
+/// What a [`ConcurrencyLimit`] does when the connection limit is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhenSaturated {
+    /// Wait in [`acquire`](ConcurrencyLimit::acquire) until a connection
+    /// finishes, leaving new connections in the listener's backlog.
+    StopAccepting,
+    /// Accept the connection anyway, and answer each of its requests with a
+    /// `503 Service Unavailable`, closing the connection afterwards.
+    RespondUnavailable,
+}
+
+/// Permission to serve a connection, from [`ConcurrencyLimit::acquire`].
+pub struct ConnectionPermit {
+    permit: Option<SemaphorePermit>,
+}
+
+/// A service wrapped by [`ConcurrencyLimit::service`].
+pub struct Limited<S> {
+    inner: S,
+    permit: ConnectionPermit,
+    requests: Option<Arc<Semaphore>>,
+    rejected: Arc<AtomicU64>,
+    metrics: Option<MetricsHandle>,
+}
+
+pin_project! {
+    /// The future returned by a [`Limited`] service.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct LimitedFuture<F, B> {
+        #[pin]
+        state: State<F, B>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<F, B> {
+        Inner {
+            #[pin]
+            future: F,
+            permit: Option<SemaphorePermit>,
+        },
+        Unavailable {
+            res: Option<Response<B>>,
+        },
+    }
+}
+
+// ===== impl ConcurrencyLimit =====
+
+impl ConcurrencyLimit {
+    /// Creates a limit of `max_connections` connections served at once.
+    pub fn new(max_connections: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit::with_metrics(max_connections, None)
+    }
+
+    pub(super) fn with_metrics(
+        max_connections: usize,
+        metrics: Option<MetricsHandle>,
+    ) -> ConcurrencyLimit {
+        let gauge = metrics.as_ref().map(|metrics| Gauge {
+            metrics: metrics.clone(),
+            report: |metrics, count| metrics.connections_in_flight(count),
+        });
+        ConcurrencyLimit {
+            max_connections,
+            connections: Arc::new(Semaphore::new(max_connections, gauge)),
+            requests: None,
+            rejected: Arc::new(AtomicU64::new(0)),
+            when_saturated: WhenSaturated::StopAccepting,
+            metrics,
+        }
+    }
+
+    /// Set the maximum number of requests being served at once, across all
+    /// connections.
+    ///
+    /// A request counts until its response head has been returned by the
+    /// service.
+    ///
+    /// Default is `None`, for no limit.
+    pub fn max_requests(&mut self, max: impl Into<Option<usize>>) -> &mut Self {
+        let metrics = &self.metrics;
+        self.requests = max.into().map(|max| {
+            let gauge = metrics.as_ref().map(|metrics| Gauge {
+                metrics: metrics.clone(),
+                report: |metrics, count| metrics.requests_in_flight(count),
+            });
+            (max, Arc::new(Semaphore::new(max, gauge)))
+        });
+        self
+    }
+
+    /// Set what happens once the connection limit is reached.
+    ///
+    /// Default is `WhenSaturated::StopAccepting`.
+    pub fn when_saturated(&mut self, behavior: WhenSaturated) -> &mut Self {
+        self.when_saturated = behavior;
+        self
+    }
+
+    /// Get permission to serve another connection.
+    ///
+    /// With `WhenSaturated::StopAccepting`, this waits until there is room
+    /// for another connection. Otherwise, it returns right away, possibly
+    /// with a permit that only lets the connection answer `503`.
+    pub async fn acquire(&self) -> ConnectionPermit {
+        if let Some(permit) = self.connections.clone().try_acquire_owned() {
+            return ConnectionPermit {
+                permit: Some(permit),
+            };
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.get().limit_saturated();
+        }
+
+        let permit = match self.when_saturated {
+            WhenSaturated::StopAccepting => Some(self.connections.clone().acquire_owned().await),
+            WhenSaturated::RespondUnavailable => {
+                reject(&self.rejected, &self.metrics, LimitScope::Connection);
+                None
+            }
+        };
+        ConnectionPermit { permit }
+    }
+
+    /// Wrap the service of a connection, enforcing the limits.
+    pub fn service<S>(&self, permit: ConnectionPermit, service: S) -> Limited<S> {
+        Limited {
+            inner: service,
+            permit,
+            requests: self.requests.as_ref().map(|(_, sem)| sem.clone()),
+            rejected: self.rejected.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// The number of connections currently being served.
+    pub fn connections(&self) -> usize {
+        self.max_connections - self.connections.available_permits()
+    }
+
+    /// The number of requests currently being served, if they are limited.
+    pub fn requests(&self) -> Option<usize> {
+        self.requests
+            .as_ref()
+            .map(|(max, sem)| max - sem.available_permits())
+    }
+
+    /// The total number of connections and requests that were over a limit.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for ConcurrencyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrencyLimit")
+            .field("max_connections", &self.max_connections)
+            .field("connections", &self.connections())
+            .field("requests", &self.requests())
+            .field("when_saturated", &self.when_saturated)
+            .finish()
+    }
+}
+
+// ===== impl ConnectionPermit =====
+
+impl ConnectionPermit {
+    /// Returns true if the connection was over the limit, and will only
+    /// answer `503 Service Unavailable`.
+    pub fn is_rejected(&self) -> bool {
+        self.permit.is_none()
+    }
+}
+
+impl fmt::Debug for ConnectionPermit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPermit")
+            .field("rejected", &self.is_rejected())
+            .finish()
+    }
+}
+
+// ===== impl Limited =====
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Limited<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = LimitedFuture<S::Future, ResBody>;
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if self.permit.is_rejected() {
+            let mut res = unavailable();
+            res.headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+            return LimitedFuture::unavailable(res);
+        }
+
+        let permit = match self.requests {
+            Some(ref sem) => match sem.clone().try_acquire_owned() {
+                Some(permit) => Some(permit),
+                None => {
+                    reject(&self.rejected, &self.metrics, LimitScope::Request);
+                    return LimitedFuture::unavailable(unavailable());
+                }
+            },
+            None => None,
+        };
+        LimitedFuture {
+            state: State::Inner {
+                future: self.inner.call(req),
+                permit,
+            },
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Limited<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Limited")
+            .field("inner", &self.inner)
+            .field("permit", &self.permit)
+            .finish()
+    }
+}
+
+fn reject(rejected: &AtomicU64, metrics: &Option<MetricsHandle>, scope: LimitScope) {
+    rejected.fetch_add(1, Ordering::Relaxed);
+    if let Some(ref metrics) = *metrics {
+        metrics.get().limit_rejected(scope);
+    }
+}
+
+fn unavailable<B: Default>() -> Response<B> {
+    let mut res = Response::new(B::default());
+    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    res
+}
+
+// ===== impl LimitedFuture =====
+
+impl<F, B> LimitedFuture<F, B> {
+    fn unavailable(res: Response<B>) -> Self {
+        LimitedFuture {
+            state: State::Unavailable { res: Some(res) },
+        }
+    }
+}
+
+impl<F, B, E> Future for LimitedFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        match self.project().state.project() {
+            StateProj::Inner { future, permit } => {
+                let res = ready!(future.poll(cx));
+                // The request no longer counts once its response is ready.
+                permit.take();
+                Poll::Ready(res)
+            }
+            StateProj::Unavailable { res } => {
+                Poll::Ready(Ok(res.take().expect("polled after complete")))
+            }
+        }
+    }
+}
+
+impl<F, B> fmt::Debug for LimitedFuture<F, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LimitedFuture").finish()
+    }
+}
+
+// ===== impl Semaphore =====
+
+/// A counting semaphore, so the limits don't depend on a runtime.
+struct Semaphore {
+    state: Mutex<SemaphoreState>,
+    max: usize,
+    gauge: Option<Gauge>,
+}
+
+/// Reports the permits of a `Semaphore` in use, each time they change.
+struct Gauge {
+    metrics: MetricsHandle,
+    report: fn(&dyn Metrics, usize),
+}
+
+struct SemaphoreState {
+    permits: usize,
+    // Tasks waiting in `acquire_owned`, all woken when a permit is released.
+    waiters: Vec<Waker>,
+}
+
+/// A permit of a `Semaphore`, released when dropped.
+struct SemaphorePermit {
+    sem: Arc<Semaphore>,
+}
+
+impl Semaphore {
+    fn new(permits: usize, gauge: Option<Gauge>) -> Semaphore {
+        Semaphore {
+            state: Mutex::new(SemaphoreState {
+                permits,
+                waiters: Vec::new(),
+            }),
+            max: permits,
+            gauge,
+        }
+    }
+
+    // Called with the state locked, so the counts are reported in order.
+    fn report(&self, state: &SemaphoreState) {
+        if let Some(ref gauge) = self.gauge {
+            (gauge.report)(gauge.metrics.get(), self.max - state.permits);
+        }
+    }
+
+    fn available_permits(&self) -> usize {
+        self.state.lock().unwrap().permits
+    }
+
+    fn try_acquire_owned(self: Arc<Self>) -> Option<SemaphorePermit> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.permits == 0 {
+                return None;
+            }
+            state.permits -= 1;
+            self.report(&state);
+        }
+        Some(SemaphorePermit { sem: self })
+    }
+
+    async fn acquire_owned(self: Arc<Self>) -> SemaphorePermit {
+        futures_util::future::poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            if state.permits == 0 {
+                if !state.waiters.iter().any(|w| w.will_wake(cx.waker())) {
+                    state.waiters.push(cx.waker().clone());
+                }
+                return Poll::Pending;
+            }
+            state.permits -= 1;
+            self.report(&state);
+            Poll::Ready(())
+        })
+        .await;
+        SemaphorePermit { sem: self }
+    }
+}
+
+impl Drop for SemaphorePermit {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.sem.state.lock().unwrap();
+            state.permits += 1;
+            self.sem.report(&state);
+            std::mem::take(&mut state.waiters)
+        };
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    use bytes::Bytes;
+    use http::{Request, Response, StatusCode};
+    use http_body_util::Empty;
+
+    use super::{ConcurrencyLimit, WhenSaturated};
+    use crate::metrics::{LimitScope, Metrics};
+    use crate::server::conn::Http;
+    use crate::service::{service_fn, Service};
+    use crate::Recv;
+
+    fn ok() -> Result<Response<Empty<Bytes>>, Infallible> {
+        Ok(Response::new(Empty::new()))
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Event {
+        Saturated,
+        Rejected(LimitScope),
+        Connections(usize),
+        Requests(usize),
+    }
+
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<Event>>>);
+
+    impl Events {
+        fn take(&self) -> Vec<Event> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl Metrics for Events {
+        fn limit_saturated(&self) {
+            self.0.lock().unwrap().push(Event::Saturated);
+        }
+
+        fn limit_rejected(&self, scope: LimitScope) {
+            self.0.lock().unwrap().push(Event::Rejected(scope));
+        }
+
+        fn connections_in_flight(&self, count: usize) {
+            self.0.lock().unwrap().push(Event::Connections(count));
+        }
+
+        fn requests_in_flight(&self, count: usize) {
+            self.0.lock().unwrap().push(Event::Requests(count));
+        }
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn rejected_connection_responds_unavailable() {
+        let mut limit = ConcurrencyLimit::new(1);
+        limit.when_saturated(WhenSaturated::RespondUnavailable);
+
+        let first = limit.acquire().await;
+        assert!(!first.is_rejected());
+        let second = limit.acquire().await;
+        assert!(second.is_rejected());
+        assert_eq!(limit.connections(), 1);
+        assert_eq!(limit.rejected(), 1);
+
+        let hello = || service_fn(|_: Request<Recv>| async { ok() });
+        let res = limit
+            .service(first, hello())
+            .call(Request::new(Recv::empty()))
+            .await;
+        assert_eq!(res.unwrap().status(), StatusCode::OK);
+
+        let res = limit
+            .service(second, hello())
+            .call(Request::new(Recv::empty()))
+            .await;
+        let res = res.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(res.headers()["connection"], "close");
+
+        // Both services were dropped, releasing the connection.
+        assert_eq!(limit.connections(), 0);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn requests_over_limit_respond_unavailable() {
+        let mut limit = ConcurrencyLimit::new(10);
+        limit.max_requests(1);
+
+        let (tx, rx) = futures_channel::oneshot::channel::<()>();
+        let mut rx = Some(rx);
+        let mut svc = limit.service(
+            limit.acquire().await,
+            service_fn(move |_: Request<Recv>| {
+                let rx = rx.take();
+                async move {
+                    if let Some(rx) = rx {
+                        let _ = rx.await;
+                    }
+                    ok()
+                }
+            }),
+        );
+
+        let slow = svc.call(Request::new(Recv::empty()));
+        assert_eq!(limit.requests(), Some(1));
+
+        let res = svc.call(Request::new(Recv::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(limit.rejected(), 1);
+
+        tx.send(()).unwrap();
+        assert_eq!(slow.await.unwrap().status(), StatusCode::OK);
+        assert_eq!(limit.requests(), Some(0));
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn reports_to_builder_metrics() {
+        let events = Events::default();
+        let mut http = Http::new();
+        http.metrics(events.clone());
+        let mut limit = http.concurrency_limit(1);
+        limit
+            .max_requests(1)
+            .when_saturated(WhenSaturated::RespondUnavailable);
+
+        let first = limit.acquire().await;
+        let second = limit.acquire().await;
+        assert!(second.is_rejected());
+        assert_eq!(
+            events.take(),
+            [
+                Event::Connections(1),
+                Event::Saturated,
+                Event::Rejected(LimitScope::Connection),
+            ]
+        );
+
+        let (tx, rx) = futures_channel::oneshot::channel::<()>();
+        let mut rx = Some(rx);
+        let mut svc = limit.service(
+            first,
+            service_fn(move |_: Request<Recv>| {
+                let rx = rx.take();
+                async move {
+                    if let Some(rx) = rx {
+                        let _ = rx.await;
+                    }
+                    ok()
+                }
+            }),
+        );
+        let slow = svc.call(Request::new(Recv::empty()));
+        let res = svc.call(Request::new(Recv::empty())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+        tx.send(()).unwrap();
+        slow.await.unwrap();
+        drop(svc);
+        drop(second);
+        assert_eq!(
+            events.take(),
+            [
+                Event::Requests(1),
+                Event::Rejected(LimitScope::Request),
+                Event::Requests(0),
+                Event::Connections(0),
+            ]
+        );
+        assert_eq!(limit.rejected(), 2);
+    }
+
+    #[cfg(not(miri))]
+    #[tokio::test]
+    async fn reports_saturation_while_waiting_to_accept() {
+        let events = Events::default();
+        let mut http = Http::new();
+        http.metrics(events.clone());
+        let limit = http.concurrency_limit(1);
+
+        let first = limit.acquire().await;
+        let mut second = tokio_test::task::spawn(limit.acquire());
+        assert!(second.poll().is_pending());
+        assert_eq!(events.take(), [Event::Connections(1), Event::Saturated]);
+
+        drop(first);
+        let second = second.await;
+        assert!(!second.is_rejected());
+        assert_eq!(
+            events.take(),
+            [Event::Connections(0), Event::Connections(1)]
+        );
+        // Waiting isn't a rejection.
+        assert_eq!(limit.rejected(), 0);
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// What an HTTP/2 connection does with streams over its concurrency limit.
+///
+/// This is set with
+/// [`Http::http2_stream_overflow`](super::Http::http2_stream_overflow).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamOverflow {
+    /// Reset the stream with `REFUSED_STREAM`, so the client may retry it.
+    Refuse,
+    /// Hold up to this many streams until others finish, and refuse any
+    /// more.
+    Queue(usize),
+    /// Refuse the stream, and gracefully close the connection with a
+    /// `GOAWAY`.
+    GoAway,
+}
+
+/// The callback set with `Http::http2_on_stream_overflow`.
+#[derive(Clone)]
+pub(crate) struct OnStreamOverflow(Arc<dyn Fn(StreamOverflow) + Send + Sync>);
+
+impl OnStreamOverflow {
+    pub(crate) fn new<F>(f: F) -> OnStreamOverflow
+    where
+        F: Fn(StreamOverflow) + Send + Sync + 'static,
+    {
+        OnStreamOverflow(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, shed: StreamOverflow) {
+        (self.0)(shed)
+    }
+}
+
+impl fmt::Debug for OnStreamOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnStreamOverflow").finish()
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::Response;
+
+/// What an HTTP/1 connection does about a request it couldn't read.
+///
+/// This is returned by the callback set with
+/// [`Http::http1_on_parse_error`](super::Http::http1_on_parse_error).
+///
+/// Whatever is returned, the connection is closed afterwards, since the
+/// bytes after an invalid message can't be trusted to start the next one.
+#[derive(Debug)]
+pub enum ParseErrorAction {
+    /// Send hyper's response for the error, such as `400 Bad Request` for an
+    /// invalid header, or `431 Request Header Fields Too Large` for a head
+    /// that is too large.
+    Default,
+    /// Send this response instead, then close the connection.
+    ///
+    /// A `content-length` header is set from the body.
+    Respond(Response<Bytes>),
+    /// Close the connection without a response.
+    Close,
+}
+
+/// The callback set with `Http::http1_on_parse_error`.
+#[derive(Clone)]
+pub(crate) struct OnParseError(Arc<dyn Fn(&crate::Error) -> ParseErrorAction + Send + Sync>);
+
+impl OnParseError {
+    pub(crate) fn new<F>(f: F) -> OnParseError
+    where
+        F: Fn(&crate::Error) -> ParseErrorAction + Send + Sync + 'static,
+    {
+        OnParseError(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, err: &crate::Error) -> ParseErrorAction {
+        (self.0)(err)
+    }
+}
+
+impl fmt::Debug for OnParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnParseError").finish()
+    }
+}
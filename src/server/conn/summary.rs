@@ -0,0 +1,344 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use http::header::{HeaderName, HeaderValue};
+use http::{HeaderMap, Method, Request, StatusCode, Uri, Version};
+
+use crate::metrics::{MetricsHandle, PollSource, StatusClass};
+
+/// A summary of a finished request, such as for an access log.
+///
+/// This is passed to the callback set with
+/// [`Http::on_request_complete`](super::Http::on_request_complete).
+#[derive(Debug)]
+pub struct RequestSummary {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    status: Option<StatusCode>,
+    bytes_read: u64,
+    bytes_written: u64,
+    elapsed: Duration,
+    is_error: bool,
+    request_id: Option<RequestId>,
+}
+
+impl RequestSummary {
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The target of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// The HTTP version of the request.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The status of the response, if one was sent.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// The number of request body bytes read.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// The number of response body bytes written.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// The time from receiving the request head until the response was
+    /// completely written, or the request failed.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Whether the request failed before its response was completely
+    /// written, such as when the connection or stream errored.
+    pub fn is_error(&self) -> bool {
+        self.is_error
+    }
+
+    /// The ID of the request, if IDs are assigned with
+    /// [`Http::request_id`](super::Http::request_id) or
+    /// [`Http::request_id_header`](super::Http::request_id_header).
+    pub fn request_id(&self) -> Option<&RequestId> {
+        self.request_id.as_ref()
+    }
+}
+
+/// The ID of a request, to correlate it across systems.
+///
+/// When IDs are assigned, with [`Http::request_id`](super::Http::request_id)
+/// or [`Http::request_id_header`](super::Http::request_id_header), each
+/// request has one in its extensions.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RequestId(HeaderValue);
+
+impl RequestId {
+    /// Create an ID from a header value.
+    pub fn new(value: HeaderValue) -> RequestId {
+        RequestId(value)
+    }
+
+    /// Generate a new ID, unique within this process.
+    ///
+    /// It is a random prefix, chosen once per process, and a counter, such
+    /// as `5e3b7c2a9d0f4e61-42`.
+    pub fn generate() -> RequestId {
+        static PREFIX: AtomicU64 = AtomicU64::new(0);
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let mut prefix = PREFIX.load(Ordering::Relaxed);
+        if prefix == 0 {
+            // The hasher is randomly keyed, so this is a random number.
+            let random = RandomState::new().build_hasher().finish() | 1;
+            // Another thread may have set it first.
+            prefix = PREFIX
+                .compare_exchange(0, random, Ordering::Relaxed, Ordering::Relaxed)
+                .map(|_| random)
+                .unwrap_or_else(|other| other);
+        }
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{:016x}-{}", prefix, n);
+        RequestId(HeaderValue::from_str(&id).expect("hex and digits are valid header values"))
+    }
+
+    /// The ID as a header value, such as to forward it in a request.
+    pub fn as_header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.to_str() {
+            Ok(s) => f.write_str(s),
+            Err(_) => fmt::Debug::fmt(&self.0, f),
+        }
+    }
+}
+
+/// How request IDs are assigned.
+#[derive(Clone, Default)]
+struct RequestIds {
+    header: Option<HeaderName>,
+    generate: Option<Arc<dyn Fn() -> RequestId + Send + Sync>>,
+}
+
+impl RequestIds {
+    fn assign(&self, headers: &HeaderMap) -> RequestId {
+        let incoming = self
+            .header
+            .as_ref()
+            .and_then(|name| headers.get(name))
+            .filter(|value| !value.is_empty());
+        match (incoming, &self.generate) {
+            (Some(value), _) => RequestId(value.clone()),
+            (None, Some(generate)) => generate(),
+            (None, None) => RequestId::generate(),
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn(&RequestSummary) + Send + Sync>;
+
+/// The callback set with `Http::on_request_complete`, the metrics set with
+/// `Http::metrics`, how request IDs are assigned, and the threshold of slow
+/// polls.
+#[derive(Clone, Default)]
+pub(crate) struct OnRequestComplete {
+    callback: Option<Callback>,
+    metrics: Option<MetricsHandle>,
+    request_ids: Option<RequestIds>,
+    slow_poll_threshold: Option<Duration>,
+}
+
+impl OnRequestComplete {
+    pub(crate) fn set_callback<F>(&mut self, f: F)
+    where
+        F: Fn(&RequestSummary) + Send + Sync + 'static,
+    {
+        self.callback = Some(Arc::new(f));
+    }
+
+    pub(crate) fn set_metrics(&mut self, metrics: MetricsHandle) {
+        self.metrics = Some(metrics);
+    }
+
+    pub(crate) fn metrics(&self) -> Option<MetricsHandle> {
+        self.metrics.clone()
+    }
+
+    pub(crate) fn set_request_id_generator<F>(&mut self, f: F)
+    where
+        F: Fn() -> RequestId + Send + Sync + 'static,
+    {
+        self.request_ids
+            .get_or_insert_with(Default::default)
+            .generate = Some(Arc::new(f));
+    }
+
+    pub(crate) fn set_request_id_header(&mut self, name: HeaderName) {
+        self.request_ids.get_or_insert_with(Default::default).header = Some(name);
+    }
+
+    pub(crate) fn set_slow_poll_threshold(&mut self, threshold: Duration) {
+        self.slow_poll_threshold = Some(threshold);
+    }
+
+    /// Start recording a request whose head was just received, and assign
+    /// its ID.
+    pub(crate) fn record<B>(&self, req: &mut Request<B>) -> Record {
+        let request_id = self.request_ids.as_ref().map(|ids| {
+            let id = ids.assign(req.headers());
+            req.extensions_mut().insert(id.clone());
+            id
+        });
+        Record {
+            callback: self.clone(),
+            started: Instant::now(),
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            version: req.version(),
+            status: None,
+            bytes_read: Arc::new(AtomicU64::new(0)),
+            bytes_written: 0,
+            request_id,
+            slow_poll: self.slow_poll_threshold.map(|threshold| SlowPoll {
+                threshold,
+                metrics: self.metrics.clone(),
+            }),
+            done: false,
+        }
+    }
+}
+
+impl fmt::Debug for OnRequestComplete {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnRequestComplete").finish()
+    }
+}
+
+/// Collects the summary of one request.
+///
+/// If dropped before `complete` is called, the request is reported as
+/// errored.
+pub(crate) struct Record {
+    callback: OnRequestComplete,
+    started: Instant,
+    method: Method,
+    uri: Uri,
+    version: Version,
+    status: Option<StatusCode>,
+    // Shared, since an HTTP/2 request body counts what it receives.
+    bytes_read: Arc<AtomicU64>,
+    bytes_written: u64,
+    request_id: Option<RequestId>,
+    slow_poll: Option<SlowPoll>,
+    done: bool,
+}
+
+/// Reports polls that take longer than a threshold.
+struct SlowPoll {
+    threshold: Duration,
+    metrics: Option<MetricsHandle>,
+}
+
+impl SlowPoll {
+    fn time<T>(&self, source: PollSource, poll: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let ret = poll();
+        let elapsed = started.elapsed();
+        if elapsed >= self.threshold {
+            crate::common::trace::slow_poll(source.as_str(), elapsed);
+            if let Some(ref metrics) = self.metrics {
+                metrics.get().slow_poll(source, elapsed);
+            }
+        }
+        ret
+    }
+}
+
+/// Calls `poll`, reporting it if the `record` has a slow poll threshold and
+/// it took longer than that.
+pub(crate) fn time_poll<T>(
+    record: Option<&Record>,
+    source: PollSource,
+    poll: impl FnOnce() -> T,
+) -> T {
+    match record.and_then(|record| record.slow_poll.as_ref()) {
+        Some(slow_poll) => slow_poll.time(source, poll),
+        None => poll(),
+    }
+}
+
+impl Record {
+    pub(crate) fn response(&mut self, status: StatusCode) {
+        self.status = Some(status);
+    }
+
+    #[cfg(feature = "http1")]
+    pub(crate) fn read(&self, len: usize) {
+        self.bytes_read.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "http2")]
+    pub(crate) fn read_counter(&self) -> Arc<AtomicU64> {
+        self.bytes_read.clone()
+    }
+
+    pub(crate) fn wrote(&mut self, len: u64) {
+        self.bytes_written += len;
+    }
+
+    pub(crate) fn complete(mut self) {
+        self.finish(false);
+    }
+
+    fn finish(&mut self, is_error: bool) {
+        self.done = true;
+        let summary = RequestSummary {
+            method: std::mem::take(&mut self.method),
+            uri: std::mem::take(&mut self.uri),
+            version: self.version,
+            status: self.status,
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written,
+            elapsed: self.started.elapsed(),
+            is_error,
+            request_id: self.request_id.take(),
+        };
+        if let Some(ref metrics) = self.callback.metrics {
+            let metrics = metrics.get();
+            metrics.bytes_read(summary.bytes_read);
+            metrics.bytes_written(summary.bytes_written);
+            metrics.request_duration(summary.elapsed);
+            if let Some(status) = summary.status {
+                metrics.response_status(StatusClass::of(status));
+            }
+        }
+        if let Some(ref callback) = self.callback.callback {
+            callback(&summary);
+        }
+    }
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        if !self.done {
+            self.finish(true);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::error::Error as StdError;
+
+use bytes::Bytes;
+
+use crate::common::Future;
+use crate::rt::{Read, Write};
+
+/// Performs the server side of a TLS handshake.
+///
+/// This lets
+/// [`auto::Builder::serve_tls_connection`](super::auto::Builder::serve_tls_connection)
+/// run the handshake itself, and then serve HTTP/1 or HTTP/2 depending on
+/// the protocol negotiated with ALPN. Implement it for the acceptor of the
+/// TLS library in use, configured to offer the `h2` and `http/1.1`
+/// protocols.
+pub trait TlsAccept<I> {
+    /// The TLS stream established by the handshake.
+    type Stream: TlsStream + Read + Write + Unpin;
+    /// The error if the handshake fails.
+    type Error: Into<Box<dyn StdError + Send + Sync>>;
+    /// The future performing the handshake.
+    type Future: Future<Output = Result<Self::Stream, Self::Error>>;
+
+    /// Start the TLS handshake on `io`.
+    fn accept(&self, io: I) -> Self::Future;
+}
+
+/// An established TLS stream.
+pub trait TlsStream {
+    /// The protocol negotiated with ALPN, if any.
+    fn alpn_protocol(&self) -> Option<&[u8]>;
+
+    /// The certificate chain the client authenticated with, if any.
+    ///
+    /// The certificates are DER-encoded, starting with the client's own.
+    /// If this returns some, each request gets a
+    /// [`PeerCertificates`](crate::ext::PeerCertificates) extension.
+    ///
+    /// The default implementation returns `None`.
+    fn peer_certificates(&self) -> Option<Vec<Bytes>> {
+        None
+    }
+
+    /// The server name the client requested with SNI, if any.
+    ///
+    /// If this returns some, each request gets a
+    /// [`ServerName`](crate::ext::ServerName) extension.
+    ///
+    /// The default implementation returns `None`.
+    fn server_name(&self) -> Option<&str> {
+        None
+    }
+}
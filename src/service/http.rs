@@ -6,6 +6,49 @@ use crate::service::service::Service;
 use crate::{Request, Response};
 
 /// An asynchronous function from `Request` to `Response`.
+///
+/// This is what servers take to serve a connection. It is sealed, and
+/// implemented for every [`Service`] of `Request` to `Response`, so a type is
+/// made an `HttpService` by implementing `Service` for it. The trait can be
+/// used in bounds, such as of code that is generic over the services it
+/// routes to.
+///
+/// Naming the future type of `Service` avoids boxing a future per request.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(all(any(feature = "http1", feature = "http2"), feature = "server"))]
+/// # fn doc() {
+/// use std::convert::Infallible;
+/// use std::future::{ready, Ready};
+///
+/// use bytes::Bytes;
+/// use http_body_util::Full;
+/// use hyper::service::{HttpService, Service};
+/// use hyper::{Recv, Request, Response, StatusCode};
+///
+/// struct Router;
+///
+/// impl Service<Request<Recv>> for Router {
+///     type Response = Response<Full<Bytes>>;
+///     type Error = Infallible;
+///     type Future = Ready<Result<Self::Response, Self::Error>>;
+///
+///     fn call(&mut self, req: Request<Recv>) -> Self::Future {
+///         let mut res = Response::new(Full::new(Bytes::from_static(b"home")));
+///         if req.uri().path() != "/" {
+///             *res.status_mut() = StatusCode::NOT_FOUND;
+///         }
+///         ready(Ok(res))
+///     }
+/// }
+///
+/// fn serves_http<S: HttpService<Recv>>(_: &S) {}
+///
+/// serves_http(&Router);
+/// # }
+/// ```
 pub trait HttpService<ReqBody>: sealed::Sealed<ReqBody> {
     /// The `Body` body of the `http::Response`.
     type ResBody: Body;
@@ -20,7 +63,7 @@ pub trait HttpService<ReqBody>: sealed::Sealed<ReqBody> {
     /// The `Future` returned by this `Service`.
     type Future: Future<Output = Result<Response<Self::ResBody>, Self::Error>>;
 
-    #[doc(hidden)]
+    /// Process the request and return the response asynchronously.
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future;
 }
 
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+use crate::common::Future;
+use crate::service::service::Service;
+use crate::{Request, Response};
+
+/// A `Service` that maps the request and response bodies of another.
+///
+/// The request body is mapped before the inner service is called, and the
+/// response body once its response is ready. Pass `|body| body` to keep
+/// either one as it is.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+///
+/// use bytes::Bytes;
+/// use http_body_util::{BodyExt, Full};
+/// use hyper::service::{service_fn, MapBody};
+/// use hyper::{Recv, Request, Response};
+///
+/// let hello = service_fn(|_req: Request<Recv>| async {
+///     Ok::<_, Infallible>(Response::new(Full::<Bytes>::from("Hello World")))
+/// });
+///
+/// // Responds with a boxed body, like other services it may be routed with.
+/// let service = MapBody::new(hello, |body: Recv| body, |body: Full<Bytes>| body.boxed());
+/// ```
+#[derive(Clone)]
+pub struct MapBody<S, F, G> {
+    inner: S,
+    map_request: F,
+    map_response: G,
+}
+
+impl<S, F, G> MapBody<S, F, G> {
+    /// Wraps a service, mapping request bodies with `map_request`, and
+    /// response bodies with `map_response`.
+    pub fn new(inner: S, map_request: F, map_response: G) -> Self {
+        MapBody {
+            inner,
+            map_request,
+            map_response,
+        }
+    }
+
+    /// Returns the wrapped service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, G, ReqBody, InnerReqBody, InnerResBody, ResBody> Service<Request<ReqBody>>
+    for MapBody<S, F, G>
+where
+    S: Service<Request<InnerReqBody>, Response = Response<InnerResBody>>,
+    F: FnMut(ReqBody) -> InnerReqBody,
+    G: FnOnce(InnerResBody) -> ResBody + Clone,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = MapBodyFuture<S::Future, G>;
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        MapBodyFuture {
+            future: self.inner.call(req.map(&mut self.map_request)),
+            map_response: Some(self.map_response.clone()),
+        }
+    }
+}
+
+impl<S, F, G> fmt::Debug for MapBody<S, F, G>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapBody")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+pin_project! {
+    /// The future returned by a [`MapBody`] service.
+    #[must_use = "futures do nothing unless polled"]
+    pub struct MapBodyFuture<Fut, G> {
+        #[pin]
+        future: Fut,
+        map_response: Option<G>,
+    }
+}
+
+impl<Fut, G, InnerResBody, ResBody, E> Future for MapBodyFuture<Fut, G>
+where
+    Fut: Future<Output = Result<Response<InnerResBody>, E>>,
+    G: FnOnce(InnerResBody) -> ResBody,
+{
+    type Output = Result<Response<ResBody>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let me = self.project();
+        let res = ready!(me.future.poll(cx))?;
+        let map_response = me.map_response.take().expect("polled after complete");
+        Poll::Ready(Ok(res.map(map_response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use http_body::Body as _;
+    use http_body_util::{BodyExt, Full, StreamBody};
+
+    use super::MapBody;
+    use crate::service::service::Service;
+    use crate::service::service_fn;
+    use crate::{Recv, Request, Response};
+
+    #[tokio::test]
+    async fn map_body_maps_request_and_response() {
+        let echo = service_fn(|req: Request<Full<Bytes>>| async move {
+            let body = crate::body::to_bytes(req.into_body()).await.unwrap();
+            Ok::<_, &'static str>(Response::new(Full::new(body)))
+        });
+        let mut service = MapBody::new(
+            echo,
+            |_body: Recv| Full::new(Bytes::from("mapped request")),
+            |body: Full<Bytes>| body.map_data(|data| Bytes::from(data.to_ascii_uppercase())),
+        );
+
+        let res = service.call(Request::new(Recv::empty())).await.unwrap();
+        let body = crate::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, "MAPPED REQUEST");
+    }
+
+    #[tokio::test]
+    async fn map_body_passes_errors_through() {
+        let fails = service_fn(|_req: Request<Recv>| async {
+            Err::<Response<Full<Bytes>>, _>("service failed")
+        });
+        let mut service = MapBody::new(
+            fails,
+            |body: Recv| body,
+            |body: Full<Bytes>| panic!("response body mapped after error: {:?}", body),
+        );
+
+        let err = service.call(Request::new(Recv::empty())).await.unwrap_err();
+        assert_eq!(err, "service failed");
+    }
+
+    #[tokio::test]
+    async fn map_body_passes_body_errors_through() {
+        let fails = service_fn(|_req: Request<Recv>| async {
+            let chunks = vec![Ok(Bytes::from("partial")), Err("body failed")];
+            let body = StreamBody::new(futures_util::stream::iter(chunks));
+            Ok::<_, &'static str>(Response::new(body))
+        });
+        let mut service = MapBody::new(
+            fails,
+            |body: Recv| body,
+            |body: StreamBody<_>| body.map_err(|err: &'static str| format!("mapped: {}", err)),
+        );
+
+        let res = service.call(Request::new(Recv::empty())).await.unwrap();
+        let mut body = res.into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "partial");
+        let err = body.data().await.unwrap().unwrap_err();
+        assert_eq!(err, "mapped: body failed");
+    }
+}
@@ -8,7 +8,7 @@
 //! Therefore, hyper uses several "trait aliases" to reduce clutter around
 //! bounds. These are:
 //!
-//! - `HttpService`: This is blanketly implemented for all types that
+//! - [`HttpService`](HttpService): This is blanketly implemented for all types that
 //!   implement `Service<http::Request<B1>, Response = http::Response<B2>>`.
 //!
 //! # HttpService
@@ -20,17 +20,33 @@
 //! The helper [`service_fn`](service_fn) should be sufficient for most cases, but
 //! if you need to implement `Service` for a type manually, you can follow the example
 //! in `service_struct_impl.rs`.
+//!
+//! # Tower
+//!
+//! With the `tower` feature, `TowerToHyperService` lets a `tower::Service`
+//! be used where hyper expects a `Service`, and `HyperToTowerService` does
+//! the opposite.
 
 mod http;
+mod map_body;
 mod service;
+#[cfg(feature = "tower")]
+mod tower;
 mod util;
 
-#[cfg(all(any(feature = "http1", feature = "http2"), feature = "server"))]
-pub(super) use self::http::HttpService;
+#[cfg(all(
+    any(feature = "http1", feature = "http2"),
+    any(feature = "server", feature = "client")
+))]
+pub use self::http::HttpService;
+pub use self::map_body::{MapBody, MapBodyFuture};
 #[cfg(all(
     any(feature = "http1", feature = "http2"),
     any(feature = "server", feature = "client")
 ))]
 pub use self::service::Service;
 
-pub use self::util::service_fn;
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub use self::tower::{HyperToTowerService, TowerToHyperService, TowerToHyperServiceFuture};
+pub use self::util::{service_fn, service_fn_with_state};
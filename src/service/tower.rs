@@ -0,0 +1,163 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use pin_project_lite::pin_project;
+
+use crate::common::Future;
+use crate::service::service::Service;
+
+/// Use a [`tower::Service`] where hyper expects a [`Service`].
+///
+/// hyper calls its services without asking if they are ready, so each call
+/// clones the inner service, and the returned future waits for
+/// `poll_ready` of the clone before calling it. Services that share their
+/// capacity between clones, such as ones behind a `Buffer`, still apply
+/// backpressure this way.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "tower")]
+/// # fn doc() {
+/// use std::convert::Infallible;
+///
+/// use hyper::service::{service_fn, HyperToTowerService, TowerToHyperService};
+/// use hyper::{Recv, Request, Response};
+///
+/// // A tower service, here made from a hyper one, to pass to tower layers.
+/// let svc = HyperToTowerService::new(service_fn(|req: Request<Recv>| async move {
+///     Ok::<_, Infallible>(Response::new(req.into_body()))
+/// }));
+///
+/// // Usable with `serve_connection` again.
+/// let svc = TowerToHyperService::new(svc);
+/// # drop(svc);
+/// # }
+/// ```
+///
+/// [`tower::Service`]: tower_service::Service
+#[derive(Clone, Debug)]
+pub struct TowerToHyperService<S> {
+    service: S,
+}
+
+impl<S> TowerToHyperService<S> {
+    /// Wraps a `tower::Service`.
+    pub fn new(service: S) -> Self {
+        TowerToHyperService { service }
+    }
+
+    /// Returns the wrapped service.
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<S, R> Service<R> for TowerToHyperService<S>
+where
+    S: tower_service::Service<R> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TowerToHyperServiceFuture<S, R>;
+
+    fn call(&mut self, req: R) -> Self::Future {
+        TowerToHyperServiceFuture {
+            state: State::NotReady {
+                service: self.service.clone(),
+                req: Some(req),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// The future returned by a [`TowerToHyperService`].
+    #[must_use = "futures do nothing unless polled"]
+    pub struct TowerToHyperServiceFuture<S, R>
+    where
+        S: tower_service::Service<R>,
+    {
+        #[pin]
+        state: State<S, R>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, R>
+    where
+        S: tower_service::Service<R>,
+    {
+        NotReady {
+            service: S,
+            req: Option<R>,
+        },
+        Called {
+            #[pin]
+            future: S::Future,
+        },
+    }
+}
+
+impl<S, R> Future for TowerToHyperServiceFuture<S, R>
+where
+    S: tower_service::Service<R>,
+{
+    type Output = Result<S::Response, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut me = self.project();
+        loop {
+            let future = match me.state.as_mut().project() {
+                StateProj::NotReady { service, req } => {
+                    ready!(service.poll_ready(cx))?;
+                    let req = req.take().expect("polled after ready");
+                    service.call(req)
+                }
+                StateProj::Called { future } => return future.poll(cx),
+            };
+            me.state.set(State::Called { future });
+        }
+    }
+}
+
+/// Use a [`Service`] where a [`tower::Service`] is expected.
+///
+/// hyper services are always ready, so `poll_ready` returns `Ready`
+/// right away.
+///
+/// [`tower::Service`]: tower_service::Service
+#[derive(Clone, Debug)]
+pub struct HyperToTowerService<S> {
+    service: S,
+}
+
+impl<S> HyperToTowerService<S> {
+    /// Wraps a hyper `Service`.
+    pub fn new(service: S) -> Self {
+        HyperToTowerService { service }
+    }
+
+    /// Returns the wrapped service.
+    pub fn into_inner(self) -> S {
+        self.service
+    }
+}
+
+impl<S, R> tower_service::Service<R> for HyperToTowerService<S>
+where
+    S: Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        self.service.call(req)
+    }
+}
@@ -80,3 +80,136 @@ where
 }
 
 impl<F, R> Copy for ServiceFn<F, R> where F: Copy {}
+
+/// Create a `Service` from a function that also takes some state.
+///
+/// The state is cloned for each request, and passed along with it, so it
+/// should be cheap to clone, such as an `Arc` or a connection pool handle.
+///
+/// # Example
+///
+/// ```
+/// use std::convert::Infallible;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// use bytes::Bytes;
+/// use hyper::{Recv, Request, Response};
+/// use http_body_util::Full;
+/// use hyper::service::service_fn_with_state;
+///
+/// let hits = Arc::new(AtomicUsize::new(0));
+/// let service = service_fn_with_state(hits, |_req: Request<Recv>, hits: Arc<AtomicUsize>| {
+///     async move {
+///         let n = hits.fetch_add(1, Ordering::Relaxed) + 1;
+///         Ok::<_, Infallible>(Response::new(Full::<Bytes>::from(format!("hit {}", n))))
+///     }
+/// });
+/// ```
+pub fn service_fn_with_state<T, F, R, S>(state: T, f: F) -> ServiceFnWithState<T, F, R>
+where
+    T: Clone,
+    F: FnMut(Request<R>, T) -> S,
+    S: Future,
+{
+    ServiceFnWithState {
+        state,
+        f,
+        _req: PhantomData,
+    }
+}
+
+/// Service returned by [`service_fn_with_state`]
+pub struct ServiceFnWithState<T, F, R> {
+    state: T,
+    f: F,
+    _req: PhantomData<fn(R)>,
+}
+
+impl<T, F, ReqBody, Ret, ResBody, E> Service<Request<ReqBody>> for ServiceFnWithState<T, F, ReqBody>
+where
+    T: Clone,
+    F: FnMut(Request<ReqBody>, T) -> Ret,
+    ReqBody: Body,
+    Ret: Future<Output = Result<Response<ResBody>, E>>,
+    E: Into<Box<dyn StdError + Send + Sync>>,
+    ResBody: Body,
+{
+    type Response = crate::Response<ResBody>;
+    type Error = E;
+    type Future = Ret;
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        (self.f)(req, self.state.clone())
+    }
+}
+
+impl<T, F, R> fmt::Debug for ServiceFnWithState<T, F, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("impl Service").finish()
+    }
+}
+
+impl<T, F, R> Clone for ServiceFnWithState<T, F, R>
+where
+    T: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        ServiceFnWithState {
+            state: self.state.clone(),
+            f: self.f.clone(),
+            _req: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use http_body_util::Full;
+
+    use super::service_fn_with_state;
+    use crate::service::service::Service;
+    use crate::{Recv, Request, Response};
+
+    // Counts how many times it was cloned.
+    struct State(Arc<AtomicUsize>);
+
+    impl Clone for State {
+        fn clone(&self) -> State {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            State(self.0.clone())
+        }
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[tokio::test]
+    async fn service_fn_with_state_clones_per_call() {
+        let clones = Arc::new(AtomicUsize::new(0));
+        let mut service = service_fn_with_state(
+            State(clones.clone()),
+            |_req: Request<Recv>, state: State| async move {
+                let n = state.0.load(Ordering::SeqCst);
+                Ok::<_, Infallible>(Response::new(Full::<Bytes>::from(n.to_string())))
+            },
+        );
+        assert_eq!(clones.load(Ordering::SeqCst), 0);
+
+        for n in 1..=2 {
+            let fut = service.call(Request::new(Recv::empty()));
+            // Cloned when called, not when polled.
+            assert_eq!(clones.load(Ordering::SeqCst), n);
+            // The state is `Send`, so the future is too.
+            assert_send(&fut);
+            let res = fut.await.unwrap();
+            let body = crate::body::to_bytes(res.into_body()).await.unwrap();
+            assert_eq!(body, n.to_string());
+        }
+    }
+}
@@ -13,6 +13,9 @@
 //! then use [`on`][] to grab a `Future` which will resolve to the upgraded
 //! connection object, or an error if the upgrade fails.
 //!
+//! For WebSockets, the `websocket` module, enabled by the `websocket`
+//! feature, validates the handshake request and builds the response.
+//!
 //! [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Protocol_upgrade_mechanism
 //!
 //! # Client
@@ -44,15 +47,26 @@ use std::error::Error as StdError;
 use std::fmt;
 use std::io;
 use std::marker::Unpin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 use bytes::Bytes;
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
-use tokio::sync::oneshot;
+use futures_channel::oneshot;
 #[cfg(any(feature = "http1", feature = "http2"))]
 use tracing::trace;
 
 use crate::common::io::Rewind;
 use crate::common::{task, Future, Pin, Poll};
+use crate::rt::{Read, ReadBufCursor, Write};
+
+pub use self::tunnel::{tunnel, Tunnel};
+
+#[cfg(all(target_os = "linux", feature = "splice"))]
+mod splice;
+pub mod tls;
+mod tunnel;
+#[cfg(feature = "websocket")]
+#[cfg_attr(docsrs, doc(cfg(feature = "websocket")))]
+pub mod websocket;
 
 /// An upgraded HTTP connection.
 ///
@@ -62,10 +76,36 @@ use crate::common::{task, Future, Pin, Poll};
 ///
 /// Alternatively, if the exact type is known, this can be deconstructed
 /// into its parts.
+///
+/// An HTTP/1 upgrade takes over the whole connection, while an HTTP/2
+/// extended `CONNECT` only takes over one stream of it. Either way, an
+/// `Upgraded` behaves the same, so code speaking the new protocol doesn't
+/// need to know which version it came from:
+///
+/// - Reads start with the first bytes the peer sent after the upgrade,
+///   including any that hyper had already buffered, and return EOF once the
+///   peer has shut down its side.
+/// - Shutting down closes the write side only, so the peer reads EOF while
+///   reads here can continue. Shutting down again succeeds, and writing
+///   afterwards fails with [`BrokenPipe`](std::io::ErrorKind::BrokenPipe).
+/// - Dropping it closes the connection, or resets the HTTP/2 stream.
+///
+/// Only [`downcast`](Upgraded::downcast) differs, since an HTTP/2 stream has
+/// no IO object of its own to return.
 pub struct Upgraded {
     io: Rewind<Box<dyn Io + Send>>,
 }
 
+/// The read half of an [`Upgraded`], from [`Upgraded::split`].
+pub struct ReadHalf {
+    inner: Arc<Mutex<Upgraded>>,
+}
+
+/// The write half of an [`Upgraded`], from [`Upgraded::split`].
+pub struct WriteHalf {
+    inner: Arc<Mutex<Upgraded>>,
+}
+
 /// A future for a possible HTTP upgrade.
 ///
 /// If no upgrade was available, or it doesn't succeed, yields an `Error`.
@@ -101,6 +141,15 @@ pub struct Parts<T> {
 /// - `http::Response<B>`
 /// - `&mut http::Request<B>`
 /// - `&mut http::Response<B>`
+/// - `http::request::Parts`
+/// - `http::response::Parts`
+/// - `&mut http::request::Parts`
+/// - `&mut http::response::Parts`
+///
+/// The pending upgrade is removed from the message's extensions, so services
+/// that split a request with `into_parts` can still await the upgrade
+/// afterwards. Calling this a second time on the same message returns an
+/// `OnUpgrade` that resolves to an error.
 pub fn on<T: sealed::CanUpgrade>(msg: T) -> OnUpgrade {
     msg.on_upgrade()
 }
@@ -122,7 +171,7 @@ impl Upgraded {
     #[cfg(any(feature = "http1", feature = "http2", test))]
     pub(super) fn new<T>(io: T, read_buf: Bytes) -> Self
     where
-        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        T: Read + Write + Unpin + Send + 'static,
     {
         Upgraded {
             io: Rewind::new_buffered(Box::new(io), read_buf),
@@ -133,7 +182,17 @@ impl Upgraded {
     ///
     /// On success, returns the downcasted parts. On error, returns the
     /// `Upgraded` back.
-    pub fn downcast<T: AsyncRead + AsyncWrite + Unpin + 'static>(self) -> Result<Parts<T>, Self> {
+    ///
+    /// Bytes that hyper read past the upgrade point, such as the first
+    /// frames of the new protocol that arrived along with a `101` response,
+    /// are returned in [`Parts::read_buf`], minus any that were already read
+    /// from this `Upgraded`. They should be processed before reading from
+    /// [`Parts::io`].
+    ///
+    /// The type is that of the IO object hyper was given, such as a
+    /// `TcpStream`. An upgrade from an HTTP/2 `CONNECT` request is a stream
+    /// of the HTTP/2 connection, and can't be downcast.
+    pub fn downcast<T: Read + Write + Unpin + 'static>(self) -> Result<Parts<T>, Self> {
         let (io, buf) = self.io.into_inner();
         match io.__hyper_downcast() {
             Ok(t) => Ok(Parts {
@@ -148,17 +207,42 @@ impl Upgraded {
     }
 }
 
-impl AsyncRead for Upgraded {
+impl Upgraded {
+    /// The socket under this connection, if it is a `TcpStream` with no
+    /// buffered bytes left to read.
+    #[cfg(all(target_os = "linux", feature = "splice"))]
+    pub(super) fn tcp_stream(&self) -> Option<&tokio::net::TcpStream> {
+        let io = self.io.get_ref_if_drained()?;
+        io.__hyper_downcast_ref::<crate::rt::TokioIo<tokio::net::TcpStream>>()
+            .map(|io| io.inner())
+    }
+
+    /// Splits into a read half and a write half, which can be used from
+    /// separate tasks.
+    ///
+    /// The halves can be put back together with [`ReadHalf::unsplit`].
+    pub fn split(self) -> (ReadHalf, WriteHalf) {
+        let inner = Arc::new(Mutex::new(self));
+        (
+            ReadHalf {
+                inner: inner.clone(),
+            },
+            WriteHalf { inner },
+        )
+    }
+}
+
+impl Read for Upgraded {
     fn poll_read(
         mut self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
-        buf: &mut ReadBuf<'_>,
+        buf: ReadBufCursor<'_>,
     ) -> Poll<io::Result<()>> {
         Pin::new(&mut self.io).poll_read(cx, buf)
     }
 }
 
-impl AsyncWrite for Upgraded {
+impl Write for Upgraded {
     fn poll_write(
         mut self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
@@ -194,6 +278,87 @@ impl fmt::Debug for Upgraded {
     }
 }
 
+// ===== impl ReadHalf =====
+
+impl ReadHalf {
+    /// Puts the halves from [`Upgraded::split`] back together.
+    ///
+    /// # Panics
+    ///
+    /// This panics if `write` isn't the other half of the same `Upgraded`.
+    pub fn unsplit(self, write: WriteHalf) -> Upgraded {
+        assert!(
+            Arc::ptr_eq(&self.inner, &write.inner),
+            "unsplit() called with halves of different Upgraded"
+        );
+        drop(write);
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => inner.into_inner().unwrap_or_else(PoisonError::into_inner),
+            Err(_) => unreachable!("both halves were given"),
+        }
+    }
+}
+
+impl Read for ReadHalf {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *lock(&self.inner)).poll_read(cx, buf)
+    }
+}
+
+impl fmt::Debug for ReadHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHalf").finish()
+    }
+}
+
+// ===== impl WriteHalf =====
+
+impl Write for WriteHalf {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *lock(&self.inner)).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut *lock(&self.inner)).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *lock(&self.inner)).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *lock(&self.inner)).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        lock(&self.inner).is_write_vectored()
+    }
+}
+
+impl fmt::Debug for WriteHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHalf").finish()
+    }
+}
+
+// The lock is only held for a single poll, so it is never contended for
+// long.
+fn lock(inner: &Mutex<Upgraded>) -> MutexGuard<'_, Upgraded> {
+    inner.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 // ===== impl OnUpgrade =====
 
 impl OnUpgrade {
@@ -246,6 +411,21 @@ impl Pending {
     }
 }
 
+/// Whether a comma-separated header, such as `Connection` or `Upgrade`,
+/// contains `token`.
+fn has_token(headers: &http::HeaderMap, name: http::header::HeaderName, token: &str) -> bool {
+    tokens(headers, name).any(|t| t.eq_ignore_ascii_case(token))
+}
+
+fn tokens(headers: &http::HeaderMap, name: http::header::HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|token| token.trim())
+}
+
 // ===== impl UpgradeExpected =====
 
 /// Error cause returned when an upgrade was expected but canceled
@@ -265,13 +445,13 @@ impl StdError for UpgradeExpected {}
 
 // ===== impl Io =====
 
-pub(super) trait Io: AsyncRead + AsyncWrite + Unpin + 'static {
+pub(super) trait Io: Read + Write + Unpin + 'static {
     fn __hyper_type_id(&self) -> TypeId {
         TypeId::of::<Self>()
     }
 }
 
-impl<T: AsyncRead + AsyncWrite + Unpin + 'static> Io for T {}
+impl<T: Read + Write + Unpin + 'static> Io for T {}
 
 impl dyn Io + Send {
     fn __hyper_is<T: Io>(&self) -> bool {
@@ -279,6 +459,15 @@ impl dyn Io + Send {
         self.__hyper_type_id() == t
     }
 
+    #[cfg(all(target_os = "linux", feature = "splice"))]
+    fn __hyper_downcast_ref<T: Io>(&self) -> Option<&T> {
+        if self.__hyper_is::<T>() {
+            unsafe { Some(&*(self as *const Self as *const T)) }
+        } else {
+            None
+        }
+    }
+
     fn __hyper_downcast<T: Io>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
         if self.__hyper_is::<T>() {
             // Taken from `std::error::Error::downcast()`.
@@ -330,6 +519,38 @@ mod sealed {
                 .unwrap_or_else(OnUpgrade::none)
         }
     }
+
+    impl CanUpgrade for http::request::Parts {
+        fn on_upgrade(mut self) -> OnUpgrade {
+            self.extensions
+                .remove::<OnUpgrade>()
+                .unwrap_or_else(OnUpgrade::none)
+        }
+    }
+
+    impl CanUpgrade for &'_ mut http::request::Parts {
+        fn on_upgrade(self) -> OnUpgrade {
+            self.extensions
+                .remove::<OnUpgrade>()
+                .unwrap_or_else(OnUpgrade::none)
+        }
+    }
+
+    impl CanUpgrade for http::response::Parts {
+        fn on_upgrade(mut self) -> OnUpgrade {
+            self.extensions
+                .remove::<OnUpgrade>()
+                .unwrap_or_else(OnUpgrade::none)
+        }
+    }
+
+    impl CanUpgrade for &'_ mut http::response::Parts {
+        fn on_upgrade(self) -> OnUpgrade {
+            self.extensions
+                .remove::<OnUpgrade>()
+                .unwrap_or_else(OnUpgrade::none)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -340,25 +561,59 @@ mod tests {
     fn upgraded_downcast() {
         let upgraded = Upgraded::new(Mock, Bytes::new());
 
-        let upgraded = upgraded.downcast::<std::io::Cursor<Vec<u8>>>().unwrap_err();
+        let upgraded = upgraded
+            .downcast::<crate::common::io::Compat<std::io::Cursor<Vec<u8>>>>()
+            .unwrap_err();
 
         upgraded.downcast::<Mock>().unwrap();
     }
 
+    #[tokio::test]
+    async fn upgraded_split() {
+        use crate::common::io::Compat;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let upgraded = Upgraded::new(Mock, Bytes::from_static(b"hello"));
+        let (read, write) = upgraded.split();
+        let (mut read, mut write) = (Compat::new(read), Compat::new(write));
+
+        let mut buf = [0; 5];
+        read.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        write.write_all(b"world").await.unwrap();
+
+        read.0.unsplit(write.0).downcast::<Mock>().unwrap();
+    }
+
+    #[tokio::test]
+    async fn on_request_parts() {
+        let (pending, on_upgrade) = pending();
+        let mut req = http::Request::new(());
+        req.extensions_mut().insert(on_upgrade);
+        let (mut parts, _) = req.into_parts();
+
+        let on_upgrade = on(&mut parts);
+        pending.fulfill(Upgraded::new(Mock, Bytes::new()));
+        on_upgrade.await.unwrap().downcast::<Mock>().unwrap();
+
+        // The upgrade was taken by the first call.
+        on(parts).await.unwrap_err();
+    }
+
     // TODO: replace with tokio_test::io when it can test write_buf
     struct Mock;
 
-    impl AsyncRead for Mock {
+    impl Read for Mock {
         fn poll_read(
             self: Pin<&mut Self>,
             _cx: &mut task::Context<'_>,
-            _buf: &mut ReadBuf<'_>,
+            _buf: ReadBufCursor<'_>,
         ) -> Poll<io::Result<()>> {
             unreachable!("Mock::poll_read")
         }
     }
 
-    impl AsyncWrite for Mock {
+    impl Write for Mock {
         fn poll_write(
             self: Pin<&mut Self>,
             _: &mut task::Context<'_>,
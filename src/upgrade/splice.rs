@@ -0,0 +1,264 @@
+use std::any::TypeId;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use tokio::io::Interest;
+use tokio::net::TcpStream;
+
+use super::Upgraded;
+use crate::common::{task, Poll};
+use crate::rt::TokioIo;
+
+/// The most bytes moved by a single `splice(2)`, the default capacity of a
+/// pipe.
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// Copies data in both directions between two TCP sockets, with `splice(2)`.
+///
+/// This is what [`tunnel`](super::tunnel) runs when both of its sides are
+/// plain TCP sockets: the data moves through a pipe in the kernel, and so is
+/// never copied into userspace.
+pub(super) struct Splice {
+    a_to_b: Transfer,
+    b_to_a: Transfer,
+}
+
+enum Transfer {
+    Running(Pipe),
+    Done(u64),
+}
+
+/// A pipe holding the bytes read from one socket until they are written to
+/// the other.
+struct Pipe {
+    read: RawFd,
+    write: RawFd,
+    len: usize,
+    amt: u64,
+    read_done: bool,
+}
+
+/// The socket under `io`, if it is a `TcpStream` that can be spliced.
+///
+/// That is the case for a `TokioIo<TcpStream>`, and for an `Upgraded`
+/// connection served over one.
+pub(super) fn tcp_stream<T: ?Sized + 'static>(io: &T) -> Option<&TcpStream> {
+    let id = TypeId::of::<T>();
+    if id == TypeId::of::<TokioIo<TcpStream>>() {
+        let io = unsafe { &*(io as *const T as *const TokioIo<TcpStream>) };
+        Some(io.inner())
+    } else if id == TypeId::of::<Upgraded>() {
+        let io = unsafe { &*(io as *const T as *const Upgraded) };
+        io.tcp_stream()
+    } else {
+        None
+    }
+}
+
+// ===== impl Splice =====
+
+impl Splice {
+    pub(super) fn new() -> io::Result<Splice> {
+        Ok(Splice {
+            a_to_b: Transfer::Running(Pipe::new()?),
+            b_to_a: Transfer::Running(Pipe::new()?),
+        })
+    }
+
+    pub(super) fn poll(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        a: &TcpStream,
+        b: &TcpStream,
+    ) -> Poll<io::Result<(u64, u64)>> {
+        let a_to_b = self.a_to_b.poll(cx, a, b)?;
+        let b_to_a = self.b_to_a.poll(cx, b, a)?;
+
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+// ===== impl Transfer =====
+
+impl Transfer {
+    fn poll(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        reader: &TcpStream,
+        writer: &TcpStream,
+    ) -> Poll<io::Result<u64>> {
+        match self {
+            Transfer::Running(pipe) => {
+                let amt = ready!(pipe.poll_copy(cx, reader, writer))?;
+                // Propagate the half-close, as `tunnel` does.
+                cvt(unsafe { libc::shutdown(writer.as_raw_fd(), libc::SHUT_WR) })?;
+                *self = Transfer::Done(amt);
+                Poll::Ready(Ok(amt))
+            }
+            Transfer::Done(amt) => Poll::Ready(Ok(*amt)),
+        }
+    }
+}
+
+// ===== impl Pipe =====
+
+impl Pipe {
+    fn new() -> io::Result<Pipe> {
+        let mut fds = [0; 2];
+        cvt(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) })?;
+        Ok(Pipe {
+            read: fds[0],
+            write: fds[1],
+            len: 0,
+            amt: 0,
+            read_done: false,
+        })
+    }
+
+    fn poll_copy(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        reader: &TcpStream,
+        writer: &TcpStream,
+    ) -> Poll<io::Result<u64>> {
+        loop {
+            if self.len == 0 && !self.read_done {
+                ready!(reader.poll_read_ready(cx))?;
+                let (from, to) = (reader.as_raw_fd(), self.write);
+                match reader.try_io(Interest::READABLE, || splice_fds(from, to, PIPE_SIZE)) {
+                    Ok(0) => self.read_done = true,
+                    Ok(n) => self.len = n,
+                    // The readiness was cleared, so poll it again.
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+
+            while self.len > 0 {
+                ready!(writer.poll_write_ready(cx))?;
+                let (from, to) = (self.read, writer.as_raw_fd());
+                match writer.try_io(Interest::WRITABLE, || splice_fds(from, to, self.len)) {
+                    Ok(0) => {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::WriteZero,
+                            "write zero bytes into tunnel",
+                        )));
+                    }
+                    Ok(n) => {
+                        self.len -= n;
+                        self.amt += n as u64;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            }
+
+            if self.read_done {
+                return Poll::Ready(Ok(self.amt));
+            }
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+fn splice_fds(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+    let n = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if n < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(n as usize)
+    }
+}
+
+fn cvt(ret: libc::c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::tcp_stream;
+    use crate::rt::TokioIo;
+    use crate::upgrade::{tunnel, Upgraded};
+
+    async fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (connected, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        (connected.unwrap(), accepted.unwrap().0)
+    }
+
+    #[tokio::test]
+    async fn tcp_stream_only_for_drained_tcp_sockets() {
+        let (a, b) = pair().await;
+        let a = TokioIo::new(a);
+        assert!(tcp_stream(&a).is_some());
+
+        let upgraded = Upgraded::new(a, Bytes::new());
+        assert!(tcp_stream(&upgraded).is_some());
+
+        let buffered = Upgraded::new(TokioIo::new(b), Bytes::from_static(b"early"));
+        assert!(tcp_stream(&buffered).is_none());
+
+        let (duplex, _) = tokio::io::duplex(64);
+        assert!(tcp_stream(&TokioIo::new(duplex)).is_none());
+    }
+
+    #[tokio::test]
+    async fn tunnel_splices_both_ways_and_propagates_eof() {
+        let (mut client, a) = pair().await;
+        let (b, mut server) = pair().await;
+        let mut a = Upgraded::new(TokioIo::new(a), Bytes::new());
+        let mut b = TokioIo::new(b);
+
+        let copy = tokio::spawn(async move { tunnel(&mut a, &mut b).await });
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Half-close from the client reaches the server, but the server can
+        // still respond.
+        client.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        server.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+
+        server.write_all(&[b'x'; 100_000]).await.unwrap();
+        server.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), 100_000);
+
+        let (sent, received) = copy.await.unwrap().unwrap();
+        assert_eq!((sent, received), (4, 100_000));
+    }
+}
@@ -0,0 +1,126 @@
+//! Upgrading HTTP/1.1 connections to TLS.
+//!
+//! [RFC 2817] lets a client start on a plaintext connection and ask to
+//! switch it to TLS with `Upgrade: TLS/1.0`. This module builds and checks
+//! the messages of that exchange. Once the `101 Switching Protocols`
+//! response is sent, [`upgrade::on`](super::on) resolves to the raw
+//! connection, and the TLS handshake can be run on it:
+//!
+//! - A server hands the upgraded connection to its TLS acceptor and resumes
+//!   serving HTTP on the TLS stream, for instance with
+//!   [`auto::Builder::serve_tls_connection`](crate::server::conn::auto::Builder::serve_tls_connection).
+//! - A client sends the [`request`] built here, checks the response with
+//!   [`is_accepted`], runs its TLS connector on the upgraded connection,
+//!   and performs a new [`handshake`](crate::client::conn::http1::handshake)
+//!   on the TLS stream.
+//!
+//! The request that asked for the upgrade isn't answered again over TLS, so
+//! clients should upgrade with an `OPTIONS *` request, as [`request`] does,
+//! and send their actual requests once TLS is established.
+//!
+//! [RFC 2817]: https://datatracker.ietf.org/doc/html/rfc2817
+
+use http::header::{HeaderValue, CONNECTION, HOST, UPGRADE};
+use http::{Method, Request, Response, StatusCode, Uri, Version};
+
+use super::{has_token, tokens};
+
+/// The upgrade token offered by clients, which also covers later TLS
+/// versions.
+const TLS: &str = "TLS/1.0";
+
+/// Builds the response accepting a TLS upgrade, if `req` asks for one.
+///
+/// The request must be HTTP/1.1, with `Connection: upgrade` and a `TLS/1.x`
+/// protocol in its `Upgrade` header. The response is a
+/// `101 Switching Protocols` confirming that protocol.
+///
+/// If this returns `None`, the request can be served as usual, or rejected
+/// with [`upgrade_required`] if the server only accepts TLS.
+pub fn accept<B, T: Default>(req: &Request<B>) -> Option<Response<T>> {
+    if req.version() != Version::HTTP_11 || !has_token(req.headers(), CONNECTION, "upgrade") {
+        return None;
+    }
+    let token = tokens(req.headers(), UPGRADE).find(|token| is_tls(token))?;
+    let upgrade = HeaderValue::from_str(&format!("{}, HTTP/1.1", token)).ok()?;
+
+    let mut res = Response::new(T::default());
+    *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    res.headers_mut().insert(UPGRADE, upgrade);
+    res.headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    Some(res)
+}
+
+/// Builds a `426 Upgrade Required` response, telling the client to upgrade
+/// to TLS before retrying its request.
+pub fn upgrade_required<T: Default>() -> Response<T> {
+    let mut res = Response::new(T::default());
+    *res.status_mut() = StatusCode::UPGRADE_REQUIRED;
+    res.headers_mut()
+        .insert(UPGRADE, HeaderValue::from_static("TLS/1.0, HTTP/1.1"));
+    res.headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    res
+}
+
+/// Builds an `OPTIONS *` request asking the server at `host` to upgrade the
+/// connection to TLS.
+pub fn request<T: Default>(host: HeaderValue) -> Request<T> {
+    let mut req = Request::new(T::default());
+    *req.method_mut() = Method::OPTIONS;
+    *req.uri_mut() = Uri::from_static("*");
+    let headers = req.headers_mut();
+    headers.insert(HOST, host);
+    headers.insert(UPGRADE, HeaderValue::from_static(TLS));
+    headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+    req
+}
+
+/// Returns whether `res` accepts an upgrade to TLS.
+///
+/// If so, [`upgrade::on`](super::on) resolves to the connection to run the
+/// TLS handshake on.
+pub fn is_accepted<B>(res: &Response<B>) -> bool {
+    res.status() == StatusCode::SWITCHING_PROTOCOLS && tokens(res.headers(), UPGRADE).any(is_tls)
+}
+
+fn is_tls(token: &str) -> bool {
+    token.len() > 4 && token[..4].eq_ignore_ascii_case("TLS/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_upgrade() {
+        let req = request::<()>(HeaderValue::from_static("example.com"));
+        assert_eq!(req.uri(), "*");
+        let res = accept::<_, ()>(&req).expect("accept");
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(res.headers()[UPGRADE], "TLS/1.0, HTTP/1.1");
+        assert!(is_accepted(&res));
+    }
+
+    #[test]
+    fn ignore_other_requests() {
+        let plain = Request::get("/").body(()).unwrap();
+        assert!(accept::<_, ()>(&plain).is_none());
+
+        let websocket = Request::get("/")
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(accept::<_, ()>(&websocket).is_none());
+
+        let missing_connection = Request::get("/")
+            .header(UPGRADE, "TLS/1.2")
+            .body(())
+            .unwrap();
+        assert!(accept::<_, ()>(&missing_connection).is_none());
+
+        assert!(!is_accepted(&upgrade_required::<()>()));
+    }
+}
@@ -0,0 +1,268 @@
+use std::fmt;
+use std::io;
+
+use crate::common::{task, Future, Pin, Poll};
+use crate::rt::{Read, ReadBuf, Write};
+
+#[cfg(all(target_os = "linux", feature = "splice"))]
+use super::splice;
+
+const BUF_SIZE: usize = 8 * 1024;
+
+/// Copies data in both directions between `a` and `b`.
+///
+/// This is the loop at the heart of a `CONNECT` proxy: once both sides have
+/// been upgraded, bytes read from one are written to the other until both
+/// directions reach EOF.
+///
+/// When one side reaches EOF, the other side's write half is shut down, so a
+/// half-close is propagated through the tunnel while data keeps flowing in
+/// the other direction.
+///
+/// The returned future resolves to the number of bytes copied from `a` to
+/// `b` and from `b` to `a`, respectively. If an error occurs in either
+/// direction, the future resolves to that error immediately.
+///
+/// The data is copied through a buffer in userspace. With the `splice`
+/// feature on Linux, when both sides are plain TCP sockets, the data moves
+/// through a pipe in the kernel with `splice(2)` instead. A side counts as a
+/// TCP socket if it is a `TokioIo<TcpStream>`, or an
+/// [`Upgraded`](super::Upgraded) connection served over one with no
+/// buffered bytes left to read.
+///
+/// # Example
+///
+/// ```no_run
+/// # use hyper::rt::{Read, Write};
+/// # async fn run<T>(req: hyper::Request<hyper::body::Recv>, mut upstream: T)
+/// # where
+/// #     T: Read + Write + Unpin + 'static,
+/// # {
+/// let mut upgraded = hyper::upgrade::on(req).await.unwrap();
+/// let (sent, received) = hyper::upgrade::tunnel(&mut upgraded, &mut upstream)
+///     .await
+///     .unwrap();
+/// println!("client wrote {} bytes and received {} bytes", sent, received);
+/// # }
+/// ```
+pub fn tunnel<'a, A, B>(a: &'a mut A, b: &'a mut B) -> Tunnel<'a, A, B>
+where
+    A: Read + Write + Unpin + ?Sized + 'static,
+    B: Read + Write + Unpin + ?Sized + 'static,
+{
+    #[cfg(all(target_os = "linux", feature = "splice"))]
+    let splice = match (splice::tcp_stream(&*a), splice::tcp_stream(&*b)) {
+        // If the pipes can't be created, fall back to copying.
+        (Some(_), Some(_)) => splice::Splice::new().ok(),
+        _ => None,
+    };
+
+    Tunnel {
+        a,
+        b,
+        a_to_b: Transfer::Running(CopyBuffer::new()),
+        b_to_a: Transfer::Running(CopyBuffer::new()),
+        #[cfg(all(target_os = "linux", feature = "splice"))]
+        splice,
+    }
+}
+
+/// A future copying data in both directions, returned by [`tunnel`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Tunnel<'a, A: ?Sized, B: ?Sized> {
+    a: &'a mut A,
+    b: &'a mut B,
+    a_to_b: Transfer,
+    b_to_a: Transfer,
+    #[cfg(all(target_os = "linux", feature = "splice"))]
+    splice: Option<splice::Splice>,
+}
+
+enum Transfer {
+    Running(CopyBuffer),
+    ShuttingDown(u64),
+    Done(u64),
+}
+
+struct CopyBuffer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    read_done: bool,
+    need_flush: bool,
+}
+
+// ===== impl Tunnel =====
+
+impl<A, B> Future for Tunnel<'_, A, B>
+where
+    A: Read + Write + Unpin + ?Sized + 'static,
+    B: Read + Write + Unpin + ?Sized + 'static,
+{
+    type Output = io::Result<(u64, u64)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let me = self.get_mut();
+
+        #[cfg(all(target_os = "linux", feature = "splice"))]
+        if let Some(ref mut splice) = me.splice {
+            let a = splice::tcp_stream(&*me.a).expect("checked in tunnel()");
+            let b = splice::tcp_stream(&*me.b).expect("checked in tunnel()");
+            return splice.poll(cx, a, b);
+        }
+
+        let a_to_b = me.a_to_b.poll(cx, &mut *me.a, &mut *me.b)?;
+        let b_to_a = me.b_to_a.poll(cx, &mut *me.b, &mut *me.a)?;
+
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl<A: ?Sized, B: ?Sized> fmt::Debug for Tunnel<'_, A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tunnel").finish()
+    }
+}
+
+// ===== impl Transfer =====
+
+impl Transfer {
+    fn poll<R, W>(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Poll<io::Result<u64>>
+    where
+        R: Read + Unpin + ?Sized,
+        W: Write + Unpin + ?Sized,
+    {
+        loop {
+            match self {
+                Transfer::Running(buf) => {
+                    let amt = ready!(buf.poll_copy(cx, reader, writer))?;
+                    *self = Transfer::ShuttingDown(amt);
+                }
+                Transfer::ShuttingDown(amt) => {
+                    ready!(Pin::new(&mut *writer).poll_shutdown(cx))?;
+                    *self = Transfer::Done(*amt);
+                }
+                Transfer::Done(amt) => return Poll::Ready(Ok(*amt)),
+            }
+        }
+    }
+}
+
+// ===== impl CopyBuffer =====
+
+impl CopyBuffer {
+    fn new() -> CopyBuffer {
+        CopyBuffer {
+            buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            amt: 0,
+            read_done: false,
+            need_flush: false,
+        }
+    }
+
+    fn poll_copy<R, W>(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Poll<io::Result<u64>>
+    where
+        R: Read + Unpin + ?Sized,
+        W: Write + Unpin + ?Sized,
+    {
+        loop {
+            if self.pos == self.cap && !self.read_done {
+                let mut buf = ReadBuf::new(&mut self.buf);
+                match Pin::new(&mut *reader).poll_read(cx, buf.unfilled()) {
+                    Poll::Ready(Ok(())) => {
+                        let n = buf.filled().len();
+                        if n == 0 {
+                            self.read_done = true;
+                        } else {
+                            self.pos = 0;
+                            self.cap = n;
+                        }
+                    }
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {
+                        // Nothing more to read for now, so make sure what
+                        // was already written isn't left sitting in a buffer.
+                        if self.need_flush {
+                            ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                            self.need_flush = false;
+                        }
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            while self.pos < self.cap {
+                let n =
+                    ready!(Pin::new(&mut *writer).poll_write(cx, &self.buf[self.pos..self.cap]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "write zero bytes into tunnel",
+                    )));
+                }
+                self.pos += n;
+                self.amt += n as u64;
+                self.need_flush = true;
+            }
+
+            if self.read_done {
+                ready!(Pin::new(&mut *writer).poll_flush(cx))?;
+                return Poll::Ready(Ok(self.amt));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::tunnel;
+    use crate::common::io::Compat;
+
+    #[tokio::test]
+    async fn tunnel_copies_both_ways_and_propagates_eof() {
+        let (mut client, a) = tokio::io::duplex(1024);
+        let (b, mut server) = tokio::io::duplex(1024);
+        let (mut a, mut b) = (Compat::new(a), Compat::new(b));
+
+        let copy = tokio::spawn(async move { tunnel(&mut a, &mut b).await });
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // Half-close from the client reaches the server, but the server can
+        // still respond.
+        client.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        server.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+
+        server.write_all(&[b'x'; 1000]).await.unwrap();
+        server.shutdown().await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), 1000);
+
+        let (sent, received) = copy.await.unwrap().unwrap();
+        assert_eq!((sent, received), (4, 1000));
+    }
+}
@@ -0,0 +1,272 @@
+//! WebSocket handshakes.
+//!
+//! This module validates the request that opens a WebSocket, and builds the
+//! response accepting it, for both [RFC 6455] `Upgrade: websocket` requests
+//! over HTTP/1.1, and [RFC 8441] extended `CONNECT` requests over HTTP/2.
+//! Once the response is sent, [`upgrade::on`](super::on) resolves to the
+//! connection to speak the WebSocket protocol on. Framing is left to other
+//! crates.
+//!
+//! [RFC 6455]: https://datatracker.ietf.org/doc/html/rfc6455
+//! [RFC 8441]: https://datatracker.ietf.org/doc/html/rfc8441
+//!
+//! # Example
+//!
+//! ```
+//! # use bytes::Bytes;
+//! # use http_body_util::Empty;
+//! use hyper::upgrade::websocket;
+//! use hyper::{Recv, Request, Response};
+//!
+//! async fn handle(mut req: Request<Recv>) -> Result<Response<Empty<Bytes>>, hyper::Error> {
+//!     let res = match websocket::accept(&req) {
+//!         Ok(res) => res,
+//!         Err(err) => return Ok(err.into_response()),
+//!     };
+//!     tokio::spawn(async move {
+//!         match hyper::upgrade::on(&mut req).await {
+//!             Ok(upgraded) => { /* speak WebSocket on `upgraded` */ }
+//!             Err(e) => eprintln!("upgrade error: {}", e),
+//!         }
+//!     });
+//!     Ok(res)
+//! }
+//! # fn main() {}
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use http::header::{
+    HeaderMap, HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+    SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use http::{Method, Request, Response, StatusCode, Version};
+use sha1::{Digest, Sha1};
+
+use super::has_token;
+
+/// The WebSocket protocol version this module supports.
+const VERSION: &str = "13";
+
+/// The GUID appended to the `Sec-WebSocket-Key` by RFC 6455.
+const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Validates a WebSocket handshake request, and builds the response
+/// accepting it.
+///
+/// For HTTP/1.1, the request must be a `GET` with `Connection: upgrade`,
+/// `Upgrade: websocket` and a valid `Sec-WebSocket-Key`, and the response is
+/// a `101 Switching Protocols` with the matching `Sec-WebSocket-Accept`.
+///
+/// For HTTP/2, the request must be an extended `CONNECT` with the
+/// `websocket` [`Protocol`](crate::ext::Protocol), and the response is a
+/// `200 OK`. The server connection must have been configured with
+/// `http2_enable_connect_protocol` for clients to send one.
+///
+/// Either way, the request must ask for `Sec-WebSocket-Version: 13`.
+///
+/// Headers such as `Sec-WebSocket-Protocol` can be added to the response
+/// before sending it.
+pub fn accept<B, T: Default>(req: &Request<B>) -> Result<Response<T>, HandshakeError> {
+    let mut res = Response::new(T::default());
+    match req.version() {
+        #[cfg(feature = "http2")]
+        Version::HTTP_2 => {
+            if req.method() != Method::CONNECT {
+                return Err(HandshakeError::new(Kind::Method));
+            }
+            match req.extensions().get::<crate::ext::Protocol>() {
+                Some(protocol) if protocol.as_str().eq_ignore_ascii_case("websocket") => (),
+                _ => return Err(HandshakeError::new(Kind::Upgrade)),
+            }
+            check_version(req.headers())?;
+        }
+        Version::HTTP_11 => {
+            if req.method() != Method::GET {
+                return Err(HandshakeError::new(Kind::Method));
+            }
+            let headers = req.headers();
+            if !has_token(headers, CONNECTION, "upgrade")
+                || !has_token(headers, UPGRADE, "websocket")
+            {
+                return Err(HandshakeError::new(Kind::Upgrade));
+            }
+            check_version(headers)?;
+            let key = headers
+                .get(SEC_WEBSOCKET_KEY)
+                .filter(|key| is_valid_key(key.as_bytes()))
+                .ok_or_else(|| HandshakeError::new(Kind::Key))?;
+
+            *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+            let headers = res.headers_mut();
+            headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+            headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+            headers.insert(SEC_WEBSOCKET_ACCEPT, accept_key(key.as_bytes()));
+        }
+        _ => return Err(HandshakeError::new(Kind::HttpVersion)),
+    }
+    Ok(res)
+}
+
+/// Computes the `Sec-WebSocket-Accept` value for a `Sec-WebSocket-Key`.
+///
+/// Clients can use this to check the response to their handshake request.
+pub fn accept_key(key: &[u8]) -> HeaderValue {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(GUID);
+    let encoded = STANDARD.encode(sha1.finalize());
+    HeaderValue::from_str(&encoded).expect("base64 is a valid header value")
+}
+
+/// An invalid WebSocket handshake request.
+pub struct HandshakeError {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    HttpVersion,
+    Method,
+    Upgrade,
+    Version,
+    Key,
+}
+
+impl HandshakeError {
+    fn new(kind: Kind) -> HandshakeError {
+        HandshakeError { kind }
+    }
+
+    /// Builds the response rejecting the handshake.
+    ///
+    /// This is a `426 Upgrade Required` listing the supported version if
+    /// the request asked for another, and a `400 Bad Request` otherwise.
+    pub fn into_response<T: Default>(self) -> Response<T> {
+        let mut res = Response::new(T::default());
+        match self.kind {
+            Kind::Version => {
+                *res.status_mut() = StatusCode::UPGRADE_REQUIRED;
+                res.headers_mut()
+                    .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static(VERSION));
+            }
+            _ => *res.status_mut() = StatusCode::BAD_REQUEST,
+        }
+        res
+    }
+}
+
+impl fmt::Debug for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HandshakeError").field(&self.kind).finish()
+    }
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.kind {
+            Kind::HttpVersion => "websocket handshake requires HTTP/1.1 or HTTP/2",
+            Kind::Method => "websocket handshake has the wrong method",
+            Kind::Upgrade => "request is not a websocket upgrade",
+            Kind::Version => "unsupported websocket version",
+            Kind::Key => "missing or invalid Sec-WebSocket-Key",
+        })
+    }
+}
+
+impl StdError for HandshakeError {}
+
+fn check_version(headers: &HeaderMap) -> Result<(), HandshakeError> {
+    match headers.get(SEC_WEBSOCKET_VERSION) {
+        Some(version) if version == VERSION => Ok(()),
+        _ => Err(HandshakeError::new(Kind::Version)),
+    }
+}
+
+// A key is 16 bytes, base64 encoded. Decoding rejects the encodings with
+// stray bits in the last character, which no 16-byte key has.
+fn is_valid_key(key: &[u8]) -> bool {
+    key.len() == 24 && STANDARD.decode(key).map_or(false, |key| key.len() == 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_rfc_example() {
+        // From RFC 6455, section 1.3.
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    fn request() -> http::request::Builder {
+        Request::get("/chat")
+            .header("connection", "keep-alive, Upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+    }
+
+    #[test]
+    fn accept_h1() {
+        let req = request().body(()).unwrap();
+        let res = accept::<_, ()>(&req).unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(res.headers()["upgrade"], "websocket");
+        assert_eq!(
+            res.headers()["sec-websocket-accept"],
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn reject_h1() {
+        let mut req = request().body(()).unwrap();
+        req.headers_mut()
+            .insert(SEC_WEBSOCKET_KEY, HeaderValue::from_static("short"));
+        let err = accept::<_, ()>(&req).unwrap_err();
+        assert_eq!(err.into_response::<()>().status(), StatusCode::BAD_REQUEST);
+
+        // Not the encoding of 16 bytes, since the last character has bits
+        // past them.
+        let mut req = request().body(()).unwrap();
+        req.headers_mut().insert(
+            SEC_WEBSOCKET_KEY,
+            HeaderValue::from_static("dGhlIHNhbXBsZSBub25jZR=="),
+        );
+        accept::<_, ()>(&req).unwrap_err();
+
+        let mut req = request().body(()).unwrap();
+        req.headers_mut()
+            .insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("8"));
+        let res = accept::<_, ()>(&req).unwrap_err().into_response::<()>();
+        assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(res.headers()["sec-websocket-version"], "13");
+
+        let req = request().method("POST").body(()).unwrap();
+        accept::<_, ()>(&req).unwrap_err();
+    }
+
+    #[cfg(feature = "http2")]
+    #[test]
+    fn accept_h2() {
+        let mut req = Request::connect("/chat")
+            .version(Version::HTTP_2)
+            .header("sec-websocket-version", "13")
+            .body(())
+            .unwrap();
+        let err = accept::<_, ()>(&req).unwrap_err();
+        assert_eq!(err.into_response::<()>().status(), StatusCode::BAD_REQUEST);
+
+        req.extensions_mut().insert(crate::ext::Protocol::WEBSOCKET);
+        let res = accept::<_, ()>(&req).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get("sec-websocket-accept").is_none());
+    }
+}
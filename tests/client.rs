@@ -15,6 +15,7 @@ use std::time::Duration;
 use http::uri::PathAndQuery;
 use http_body_util::{BodyExt, StreamBody};
 use hyper::body::to_bytes as concat;
+use hyper::client::conn::http1::Strictness;
 use hyper::header::HeaderValue;
 use hyper::{Method, Request, StatusCode, Uri, Version};
 
@@ -24,6 +25,7 @@ use futures_core::{Future, TryFuture};
 use futures_util::future::{self, FutureExt, TryFutureExt};
 use tokio::net::TcpStream;
 mod support;
+use support::TokioIo;
 
 fn s(buf: &[u8]) -> &str {
     std::str::from_utf8(buf).expect("from_utf8")
@@ -53,6 +55,13 @@ impl Error {
         }
     }
 
+    fn incomplete_message(&self) -> Option<hyper::error::Incomplete> {
+        match self {
+            Self::Hyper(err) => err.incomplete_message(),
+            _ => None,
+        }
+    }
+
     fn is_parse(&self) -> bool {
         match self {
             Self::Hyper(err) => err.is_parse(),
@@ -305,7 +314,7 @@ macro_rules! test {
                 req.headers_mut().append("Host", HeaderValue::from_str(&host).unwrap());
             }
 
-            let (mut sender, conn) = builder.handshake(stream).await?;
+            let (mut sender, conn) = builder.handshake(TokioIo::new(stream)).await?;
 
             tokio::task::spawn(async move {
                 if let Err(err) = conn.await {
@@ -953,6 +962,28 @@ test! {
         error: |err| err.is_incomplete_message(),
 }
 
+test! {
+    name: client_error_unexpected_eof_stage,
+
+    server:
+        expected: "\
+            GET /err HTTP/1.1\r\n\
+            host: {addr}\r\n\
+            \r\n\
+            ",
+        reply: "",
+
+    client:
+        request: {
+            method: GET,
+            url: "http://{addr}/err",
+        },
+        error: |err| err.incomplete_message().map_or(false, |incomplete| {
+            incomplete.stage() == hyper::error::IncompleteStage::BeforeHead
+                && incomplete.request_sent()
+        }),
+}
+
 test! {
     name: client_error_parse_version,
 
@@ -1247,6 +1278,9 @@ test! {
             abc\r\n",
 
     client:
+        options: {
+            http1_strictness: (Strictness::default().reject_content_length_lists(false)),
+        },
         request: {
             method: GET,
             url: "http://{addr}/foo",
@@ -1258,6 +1292,25 @@ test! {
             body: &b"abc"[..],
 }
 
+test! {
+    name: client_rejects_contentlength_values_on_same_line_by_default,
+
+    server:
+        expected: "GET /foo HTTP/1.1\r\nhost: {addr}\r\n\r\n",
+        reply: "\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 3,3\r\n\
+            \r\n\
+            abc\r\n",
+
+    client:
+        request: {
+            method: GET,
+            url: "http://{addr}/foo",
+        },
+        error: |err| err.is_parse(),
+}
+
 test! {
     name: client_allows_http09_when_requested,
 
@@ -1339,7 +1392,7 @@ mod conn {
 
     use super::{concat, s, support, tcp_connect, FutureHyperExt};
 
-    use support::{TokioExecutor, TokioTimer};
+    use support::{TokioExecutor, TokioIo, TokioTimer};
 
     #[tokio::test]
     async fn get() {
@@ -1367,7 +1420,9 @@ mod conn {
 
         let client = async move {
             let tcp = tcp_connect(&addr).await.expect("connect");
-            let (mut client, conn) = conn::http1::handshake(tcp).await.expect("handshake");
+            let (mut client, conn) = conn::http1::handshake(TokioIo::new(tcp))
+                .await
+                .expect("handshake");
 
             tokio::task::spawn(async move {
                 conn.await.expect("http conn");
@@ -1385,6 +1440,243 @@ mod conn {
         future::join(server, client).await;
     }
 
+    #[tokio::test]
+    async fn http1_timings() {
+        let _ = ::pretty_env_logger::try_init();
+        let (io, mut server) = tokio::io::duplex(1024);
+
+        let (mut client, conn) = conn::http1::Builder::new()
+            .timings(true)
+            .handshake(TokioIo::new(io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = client.send_request(req);
+
+        let server = async move {
+            let mut buf = [0; 4096];
+            let _ = server.read(&mut buf).await.expect("read request");
+            server
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            server.write_all(b"hello").await.unwrap();
+            server
+        };
+
+        let (res, _server) = future::join(res, server).await;
+        let res = res.expect("send_request");
+        let timings = res
+            .extensions()
+            .get::<hyper::ext::Timings>()
+            .cloned()
+            .expect("timings");
+        assert!(timings.tls_handshaked().is_none());
+        let written = timings.request_written().expect("request_written");
+        let first_byte = timings.first_byte().expect("first_byte");
+        let headers = timings.headers_complete().expect("headers_complete");
+        assert!(timings.connected() <= written);
+        assert!(written <= first_byte);
+        assert!(first_byte <= headers);
+
+        concat(res.into_body()).await.unwrap();
+        let body = timings.body_complete().expect("body_complete");
+        assert!(headers <= body);
+    }
+
+    #[tokio::test]
+    async fn http1_connection_use() {
+        use hyper::ext::ConnectionUse;
+
+        let _ = ::pretty_env_logger::try_init();
+        let (io, mut server) = tokio::io::duplex(1024);
+
+        let (mut client, conn) = conn::http1::Builder::new()
+            .connection_use(true)
+            .handshake(TokioIo::new(io))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+
+        let server = tokio::spawn(async move {
+            let mut buf = [0; 4096];
+            for _ in 0..2 {
+                let _ = server.read(&mut buf).await.expect("read request");
+                server
+                    .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+            server
+        });
+
+        for exchanges in 1..=2 {
+            future::poll_fn(|ctx| client.poll_ready(ctx))
+                .await
+                .expect("client should be ready");
+            let req = Request::builder()
+                .uri("/a")
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let res = client.send_request(req).await.expect("send_request");
+            let usage = res
+                .extensions()
+                .get::<ConnectionUse>()
+                .copied()
+                .expect("connection use");
+            assert_eq!(usage.exchanges(), exchanges);
+            assert_eq!(usage.is_fresh(), exchanges == 1);
+            assert_eq!(usage.is_reused(), exchanges > 1);
+        }
+        let _server = server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn without_shutdown() {
+        let _ = ::pretty_env_logger::try_init();
+        let (io, mut server) = tokio::io::duplex(1024);
+
+        let (mut client, conn) = conn::http1::handshake(TokioIo::new(io)).await.unwrap();
+        let conn = tokio::spawn(conn.without_shutdown());
+
+        let req = Request::builder()
+            .uri("/a")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = client.send_request(req);
+        let mut buf = [0; 4096];
+        let n = server.read(&mut buf).await.unwrap();
+        assert_eq!(s(&buf[..n]), "GET /a HTTP/1.1\r\n\r\n");
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        let res = res.await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        drop(client);
+
+        let parts = conn.await.unwrap().unwrap();
+        assert!(parts.read_buf.is_empty());
+        let mut io = parts.io.into_inner();
+        io.write_all(b"foo=bar").await.unwrap();
+        let n = server.read(&mut buf).await.unwrap();
+        assert_eq!(s(&buf[..n]), "foo=bar");
+    }
+
+    #[tokio::test]
+    async fn http1_rejects_http2_version_policy() {
+        let (io, _server) = tokio::io::duplex(1024);
+
+        let (mut client, conn) = conn::http1::handshake(TokioIo::new(io)).await.unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let mut req = Request::new(Empty::<Bytes>::new());
+        req.extensions_mut()
+            .insert(hyper::ext::VersionPolicy::Http2PriorKnowledge);
+        let err = client
+            .send_request(req)
+            .await
+            .expect_err("request should be rejected");
+        assert!(err.is_user(), "{:?}", err);
+
+        // the connection is still usable for other requests
+        future::poll_fn(|ctx| client.poll_ready(ctx))
+            .await
+            .expect("client should be ready");
+    }
+
+    #[tokio::test]
+    async fn auto_builder_follows_version_policy() {
+        use hyper::ext::VersionPolicy;
+        use hyper::Version;
+
+        fn req(policy: Option<VersionPolicy>) -> Request<Empty<Bytes>> {
+            let mut req = Request::get("/a").body(Empty::<Bytes>::new()).unwrap();
+            if let Some(policy) = policy {
+                req.extensions_mut().insert(policy);
+            }
+            req
+        }
+
+        let mut builder = conn::auto::Builder::new();
+        builder.http2().executor(TokioExecutor);
+
+        let cases: &[(VersionPolicy, Option<&[u8]>, hyper::Version)] = &[
+            (VersionPolicy::Http1Only, Some(b"h2"), Version::HTTP_11),
+            (VersionPolicy::Http2PriorKnowledge, None, Version::HTTP_2),
+            (VersionPolicy::Negotiated, Some(b"h2"), Version::HTTP_2),
+            (
+                VersionPolicy::Negotiated,
+                Some(b"http/1.1"),
+                Version::HTTP_11,
+            ),
+            (VersionPolicy::Negotiated, None, Version::HTTP_11),
+        ];
+        for &(policy, alpn, version) in cases {
+            let (io, _server) = tokio::io::duplex(1024);
+            let (mut client, conn) = builder
+                .handshake::<_, Empty<Bytes>>(TokioIo::new(io), policy, alpn)
+                .await
+                .unwrap();
+            tokio::spawn(async move {
+                let _ = conn.await;
+            });
+            assert_eq!(client.version(), version, "{:?} {:?}", policy, alpn);
+            assert_eq!(client.version_policy(), policy);
+
+            // Only requests for the same policy are sent, except that any
+            // HTTP/1 connection can send `Http1Only` ones.
+            let others = [
+                VersionPolicy::Http1Only,
+                VersionPolicy::Http2PriorKnowledge,
+                VersionPolicy::Negotiated,
+            ];
+            for other in others.iter().copied() {
+                if other == policy
+                    || (other == VersionPolicy::Http1Only && version == Version::HTTP_11)
+                {
+                    continue;
+                }
+                let err = client
+                    .send_request(req(Some(other)))
+                    .await
+                    .expect_err("request should be rejected");
+                assert!(err.is_user(), "{:?}", err);
+            }
+        }
+
+        // An accepted request is sent on the connection.
+        let (io, mut server) = tokio::io::duplex(1024);
+        let (mut client, conn) = builder
+            .handshake(
+                TokioIo::new(io),
+                VersionPolicy::Negotiated,
+                Some(b"http/1.1"),
+            )
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let _ = conn.await;
+        });
+        let res = client.send_request(req(Some(VersionPolicy::Negotiated)));
+        let mut buf = [0; 4096];
+        let n = server.read(&mut buf).await.unwrap();
+        assert_eq!(s(&buf[..n]), "GET /a HTTP/1.1\r\n\r\n");
+        server
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(res.await.unwrap().status(), StatusCode::OK);
+    }
+
     #[tokio::test]
     async fn get_custom_reason_phrase() {
         let _ = ::pretty_env_logger::try_init();
@@ -1411,7 +1703,9 @@ mod conn {
 
         let client = async move {
             let tcp = tcp_connect(&addr).await.expect("connect");
-            let (mut client, conn) = conn::http1::handshake(tcp).await.expect("handshake");
+            let (mut client, conn) = conn::http1::handshake(TokioIo::new(tcp))
+                .await
+                .expect("handshake");
 
             tokio::task::spawn(async move {
                 conn.await.expect("http conn");
@@ -1469,7 +1763,9 @@ mod conn {
 
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
-        let (mut client, conn) = rt.block_on(conn::http1::handshake(tcp)).unwrap();
+        let (mut client, conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(tcp)))
+            .unwrap();
 
         rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
 
@@ -1490,6 +1786,56 @@ mod conn {
         assert_eq!(chunk.len(), 5);
     }
 
+    #[tokio::test]
+    async fn incoming_body_closed_early() {
+        let _ = ::pretty_env_logger::try_init();
+        let listener = TkTcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = async move {
+            let mut sock = listener.accept().await.unwrap().0;
+            let mut buf = [0; 4096];
+            sock.read(&mut buf).await.expect("read 1");
+
+            // Closes 5 bytes into a 10 byte body.
+            sock.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello")
+                .await
+                .unwrap();
+        };
+
+        let client = async move {
+            let tcp = tcp_connect(&addr).await.expect("connect");
+            let (mut client, conn) = conn::http1::handshake(TokioIo::new(tcp))
+                .await
+                .expect("handshake");
+
+            tokio::task::spawn(async move {
+                let _ = conn.await;
+            });
+
+            let req = Request::builder()
+                .uri("/a")
+                .body(Empty::<Bytes>::new())
+                .unwrap();
+            let mut res = client.send_request(req).await.expect("send_request");
+            let chunk = res.body_mut().data().await.unwrap().expect("chunk");
+            assert_eq!(chunk, "hello");
+
+            // An incomplete message, not a body error.
+            let err = res.body_mut().data().await.unwrap().unwrap_err();
+            assert!(err.is_incomplete_message(), "{:?}", err);
+            assert_ne!(err.kind(), hyper::error::ErrorKind::Body);
+            assert!(!err.is_body_write_aborted());
+            let incomplete = err.incomplete_message().unwrap();
+            assert_eq!(incomplete.stage(), hyper::error::IncompleteStage::Body);
+            assert!(incomplete.request_sent());
+        };
+
+        future::join(server, client).await;
+    }
+
     #[test]
     fn aborted_body_isnt_completed() {
         let _ = ::pretty_env_logger::try_init();
@@ -1515,7 +1861,9 @@ mod conn {
 
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
-        let (mut client, conn) = rt.block_on(conn::http1::handshake(tcp)).unwrap();
+        let (mut client, conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(tcp)))
+            .unwrap();
 
         rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
 
@@ -1572,7 +1920,9 @@ mod conn {
 
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
-        let (mut client, conn) = rt.block_on(conn::http1::handshake(tcp)).unwrap();
+        let (mut client, conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(tcp)))
+            .unwrap();
 
         rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
 
@@ -1617,7 +1967,9 @@ mod conn {
 
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
-        let (mut client, conn) = rt.block_on(conn::http1::handshake(tcp)).unwrap();
+        let (mut client, conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(tcp)))
+            .unwrap();
 
         rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
 
@@ -1659,7 +2011,9 @@ mod conn {
 
         let tcp = rt.block_on(tcp_connect(&addr)).unwrap();
 
-        let (mut client, conn) = rt.block_on(conn::http1::handshake(tcp)).unwrap();
+        let (mut client, conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(tcp)))
+            .unwrap();
 
         rt.spawn(conn.map_err(|e| panic!("conn error: {}", e)).map(|_| ()));
 
@@ -1729,7 +2083,9 @@ mod conn {
             shutdown_called: false,
         };
 
-        let (mut client, mut conn) = rt.block_on(conn::http1::handshake(io)).unwrap();
+        let (mut client, mut conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(io)))
+            .unwrap();
 
         {
             let until_upgrade = poll_fn(|ctx| conn.poll_without_shutdown(ctx));
@@ -1758,7 +2114,7 @@ mod conn {
         }
 
         let parts = conn.into_parts();
-        let mut io = parts.io;
+        let mut io = parts.io.into_inner();
         let buf = parts.read_buf;
 
         assert_eq!(buf, b"foobar=ready"[..]);
@@ -1815,7 +2171,9 @@ mod conn {
             shutdown_called: false,
         };
 
-        let (mut client, mut conn) = rt.block_on(conn::http1::handshake(io)).unwrap();
+        let (mut client, mut conn) = rt
+            .block_on(conn::http1::handshake(TokioIo::new(io)))
+            .unwrap();
 
         {
             let until_tunneled = poll_fn(|ctx| conn.poll_without_shutdown(ctx));
@@ -1849,7 +2207,7 @@ mod conn {
         }
 
         let parts = conn.into_parts();
-        let mut io = parts.io;
+        let mut io = parts.io.into_inner();
         let buf = parts.read_buf;
 
         assert_eq!(buf, b"foobar=ready"[..]);
@@ -1892,7 +2250,7 @@ mod conn {
 
                         let mut shdn_rx = shdn_rx.clone();
                         tokio::task::spawn(async move {
-                            let mut conn = Http::new().with_executor(TokioExecutor).http2_only(true).serve_connection(stream, service);
+                            let mut conn = Http::new().with_executor(TokioExecutor).http2_only(true).serve_connection(TokioIo::new(stream), service);
 
                             tokio::select! {
                                 res = &mut conn => {
@@ -1915,7 +2273,7 @@ mod conn {
         let io = tcp_connect(&addr).await.expect("tcp connect");
         let (mut client, conn) = conn::http2::Builder::new()
             .executor(TokioExecutor)
-            .handshake(io)
+            .handshake(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -1952,6 +2310,199 @@ mod conn {
             .expect_err("client should be closed");
     }
 
+    #[tokio::test]
+    async fn http2_pending_request_timeout() {
+        use futures_util::future;
+
+        let _ = pretty_env_logger::try_init();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let listener = TkTcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // a server that allows a single stream, and never responds
+        tokio::task::spawn(async move {
+            use hyper::server::conn::Http;
+            use hyper::service::service_fn;
+
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|_: Request<Recv>| {
+                future::pending::<Result<Response<Empty<Bytes>>, hyper::Error>>()
+            });
+            let _ = Http::new()
+                .with_executor(TokioExecutor)
+                .http2_only(true)
+                .http2_max_concurrent_streams(1)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new()
+            .executor(TokioExecutor)
+            .timer(TokioTimer)
+            .http2_pending_request_timeout(Duration::from_millis(100))
+            .handshake(TokioIo::new(io))
+            .await
+            .expect("http handshake");
+
+        tokio::task::spawn(async move {
+            let _ = conn.await;
+        });
+
+        let req = || {
+            Request::builder()
+                .uri(format!("http://{}/", addr))
+                .body(Empty::<Bytes>::new())
+                .expect("request builder")
+        };
+
+        // the first request holds the only stream
+        tokio::task::spawn(client.send_request(req()));
+        // allow time for the server's settings to be received
+        TokioTimer.sleep(Duration::from_millis(100)).await;
+        // the second request waits inside the h2 connection
+        tokio::task::spawn(client.send_request(req()));
+        TokioTimer.sleep(Duration::from_millis(50)).await;
+
+        // and the third one waits in the pending queue
+        let err = client
+            .send_request(req())
+            .await
+            .expect_err("pending request should timeout");
+        assert!(err.is_timeout(), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn http2_local_executor() {
+        use std::convert::Infallible;
+        use std::rc::Rc;
+
+        use hyper::server::conn::Http;
+        use hyper::service::service_fn;
+
+        let _ = pretty_env_logger::try_init();
+
+        let listener = TkTcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // an echo server
+        tokio::task::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|req: Request<Recv>| async move {
+                Ok::<_, hyper::Error>(Response::new(req.into_body()))
+            });
+            let _ = Http::new()
+                .with_executor(TokioExecutor)
+                .http2_only(true)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        #[derive(Clone)]
+        struct LocalExec;
+
+        impl<F> hyper::rt::Executor<F> for LocalExec
+        where
+            F: std::future::Future + 'static,
+        {
+            fn execute(&self, fut: F) {
+                tokio::task::spawn_local(fut);
+            }
+        }
+
+        tokio::task::LocalSet::new()
+            .run_until(async move {
+                let io = tcp_connect(&addr).await.expect("tcp connect");
+                let (mut client, conn) = conn::http2::Builder::new()
+                    .with_executor(LocalExec)
+                    .handshake(TokioIo::new(io))
+                    .await
+                    .expect("http handshake");
+
+                tokio::task::spawn_local(async move {
+                    conn.await.expect("client conn");
+                });
+
+                // a request body that isn't `Send`
+                let local = Rc::new(Bytes::from_static(b"hello"));
+                let body = StreamBody::new(futures_util::stream::once(async move {
+                    TokioTimer.sleep(Duration::from_millis(10)).await;
+                    Ok::<_, Infallible>((*local).clone())
+                }));
+                let req = Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("http://{}/", addr))
+                    .body(body)
+                    .expect("request builder");
+
+                let res = client.send_request(req).await.expect("send_request");
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = concat(res.into_body()).await.expect("body");
+                assert_eq!(body, "hello");
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn http2_poll_budget() {
+        use hyper::server::conn::Http;
+        use hyper::service::service_fn;
+
+        let _ = pretty_env_logger::try_init();
+
+        let listener = TkTcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // an echo server
+        tokio::task::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let service = service_fn(|req: Request<Recv>| async move {
+                Ok::<_, hyper::Error>(Response::new(req.into_body()))
+            });
+            let _ = Http::new()
+                .with_executor(TokioExecutor)
+                .http2_only(true)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+
+        let io = tcp_connect(&addr).await.expect("tcp connect");
+        let (mut client, conn) = conn::http2::Builder::new()
+            .executor(TokioExecutor)
+            .http2_poll_budget(1)
+            .handshake(TokioIo::new(io))
+            .await
+            .expect("http handshake");
+
+        tokio::task::spawn(async move {
+            conn.await.expect("client conn");
+        });
+
+        // More requests are queued at once than the budget, so starting
+        // them takes several polls.
+        let requests = (0..5)
+            .map(|i| {
+                let req = Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("http://{}/", addr))
+                    .body(http_body_util::Full::new(Bytes::from(i.to_string())))
+                    .expect("request builder");
+                client.send_request(req).map(move |res| (i, res))
+            })
+            .collect::<Vec<_>>();
+        for (i, res) in future::join_all(requests).await {
+            let res = res.expect("send_request");
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = concat(res.into_body()).await.expect("body");
+            assert_eq!(body, i.to_string());
+        }
+    }
+
     #[tokio::test]
     async fn http2_keep_alive_detects_unresponsive_server() {
         let _ = pretty_env_logger::try_init();
@@ -1982,7 +2533,7 @@ mod conn {
             .http2_keep_alive_timeout(Duration::from_secs(1))
             // enable while idle since we aren't sending requests
             .http2_keep_alive_while_idle(true)
-            .handshake::<_, Recv>(io)
+            .handshake::<_, Recv>(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -2014,7 +2565,7 @@ mod conn {
             .timer(TokioTimer)
             .http2_keep_alive_interval(Duration::from_secs(1))
             .http2_keep_alive_timeout(Duration::from_secs(1))
-            .handshake::<_, Recv>(io)
+            .handshake::<_, Recv>(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -2051,7 +2602,7 @@ mod conn {
             .timer(TokioTimer)
             .http2_keep_alive_interval(Duration::from_secs(1))
             .http2_keep_alive_timeout(Duration::from_secs(1))
-            .handshake(io)
+            .handshake(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -2098,7 +2649,7 @@ mod conn {
                 .with_timer(TokioTimer)
                 .http2_only(true)
                 .serve_connection(
-                    sock,
+                    TokioIo::new(sock),
                     service_fn(|req| async move {
                         tokio::spawn(async move {
                             let _ = hyper::body::aggregate(req.into_body())
@@ -2118,7 +2669,7 @@ mod conn {
             .timer(TokioTimer)
             .http2_keep_alive_interval(Duration::from_secs(1))
             .http2_keep_alive_timeout(Duration::from_secs(1))
-            .handshake(io)
+            .handshake(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -2140,6 +2691,49 @@ mod conn {
             .expect("client should be open");
     }
 
+    #[tokio::test]
+    async fn upgrade_downcast_keeps_buffered_bytes() {
+        let _ = pretty_env_logger::try_init();
+        let (io, mut server) = tokio::io::duplex(1024);
+
+        let (mut client, conn) = conn::http1::handshake(TokioIo::new(io)).await.unwrap();
+        tokio::spawn(async move {
+            conn.await.expect("http conn");
+        });
+
+        let req = Request::builder()
+            .uri("/a")
+            .header("upgrade", "foobar")
+            .header("connection", "upgrade")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = client.send_request(req);
+        let mut buf = [0; 4096];
+        let _ = server.read(&mut buf).await.unwrap();
+        // The first bytes of the new protocol arrive along with the 101.
+        server
+            .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: foobar\r\n\r\nfoobar=ready")
+            .await
+            .unwrap();
+        let res = res.await.unwrap();
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        let mut upgraded = TokioIo::new(hyper::upgrade::on(res).await.unwrap());
+        let mut start = [0; 3];
+        upgraded.read_exact(&mut start).await.unwrap();
+        assert_eq!(&start, b"foo");
+
+        let parts = upgraded
+            .into_inner()
+            .downcast::<TokioIo<tokio::io::DuplexStream>>()
+            .expect("downcast");
+        assert_eq!(parts.read_buf, "bar=ready");
+        let mut io = parts.io.into_inner();
+        io.write_all(b"foo=bar").await.unwrap();
+        let n = server.read(&mut buf).await.unwrap();
+        assert_eq!(s(&buf[..n]), "foo=bar");
+    }
+
     #[tokio::test]
     async fn h2_connect() {
         let _ = pretty_env_logger::try_init();
@@ -2176,7 +2770,7 @@ mod conn {
         let io = tcp_connect(&addr).await.expect("tcp connect");
         let (mut client, conn) = conn::http2::Builder::new()
             .executor(TokioExecutor)
-            .handshake(io)
+            .handshake(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -2190,7 +2784,7 @@ mod conn {
         let res = client.send_request(req).await.expect("send_request");
         assert_eq!(res.status(), StatusCode::OK);
 
-        let mut upgraded = hyper::upgrade::on(res).await.unwrap();
+        let mut upgraded = TokioIo::new(hyper::upgrade::on(res).await.unwrap());
 
         let mut vec = vec![];
         upgraded.read_to_end(&mut vec).await.unwrap();
@@ -2232,7 +2826,7 @@ mod conn {
         let io = tcp_connect(&addr).await.expect("tcp connect");
         let (mut client, conn) = conn::http2::Builder::new()
             .executor(TokioExecutor)
-            .handshake::<_, Empty<Bytes>>(io)
+            .handshake::<_, Empty<Bytes>>(TokioIo::new(io))
             .await
             .expect("http handshake");
 
@@ -2257,6 +2851,74 @@ mod conn {
         done_tx.send(()).unwrap();
     }
 
+    #[tokio::test]
+    async fn h2_extended_connect() {
+        use hyper::ext::Protocol;
+        use hyper::server::conn::Http;
+        use hyper::service::service_fn;
+
+        let _ = pretty_env_logger::try_init();
+
+        let (io, server) = tokio::io::duplex(1024);
+        let svc = service_fn(|mut req: Request<Recv>| async move {
+            let protocol = req.extensions().get::<Protocol>().cloned();
+            assert_eq!(protocol, Some(Protocol::WEBSOCKET));
+            tokio::spawn(async move {
+                let mut upgraded = TokioIo::new(hyper::upgrade::on(&mut req).await.unwrap());
+                upgraded.write_all(b"Bread?").await.unwrap();
+                upgraded.shutdown().await.unwrap();
+                let mut vec = vec![];
+                upgraded.read_to_end(&mut vec).await.unwrap();
+                assert_eq!(s(&vec), "Baguette!");
+            });
+            Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        });
+        tokio::spawn(
+            Http::new()
+                .with_executor(TokioExecutor)
+                .http2_only(true)
+                .http2_enable_connect_protocol()
+                .serve_connection(TokioIo::new(server), svc),
+        );
+
+        let (mut client, conn) = conn::http2::Builder::new()
+            .executor(TokioExecutor)
+            .handshake(TokioIo::new(io))
+            .await
+            .expect("http handshake");
+
+        // Wait for the server's SETTINGS to arrive.
+        for _ in 0..100 {
+            if conn.is_extended_connect_protocol_enabled() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert!(conn.is_extended_connect_protocol_enabled());
+        tokio::spawn(async move {
+            conn.await.expect("client conn shouldn't error");
+        });
+
+        let req = Request::connect("http://localhost/chat")
+            .extension(Protocol::WEBSOCKET)
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let res = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut upgraded = TokioIo::new(hyper::upgrade::on(res).await.unwrap());
+        let mut vec = vec![];
+        upgraded.read_to_end(&mut vec).await.unwrap();
+        assert_eq!(s(&vec), "Bread?");
+
+        // Shutting down behaves like on an HTTP/1 upgrade.
+        upgraded.write_all(b"Baguette!").await.unwrap();
+        upgraded.shutdown().await.unwrap();
+        upgraded.shutdown().await.unwrap();
+        let err = upgraded.write_all(b"more").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
     async fn drain_til_eof<T: AsyncRead + Unpin>(mut sock: T) -> io::Result<()> {
         let mut buf = [0u8; 1024];
         loop {
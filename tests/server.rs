@@ -7,12 +7,12 @@ use std::io::{self, Read, Write};
 use std::net::TcpListener as StdTcpListener;
 use std::net::{Shutdown, SocketAddr, TcpStream};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 use bytes::Bytes;
 use futures_channel::oneshot;
@@ -22,7 +22,7 @@ use h2::{RecvStream, SendStream};
 use http::header::{HeaderName, HeaderValue};
 use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, StreamBody};
 use hyper::rt::Timer;
-use support::{TokioExecutor, TokioTimer};
+use support::{TokioExecutor, TokioIo, TokioTimer};
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener as TkTcpListener, TcpListener, TcpStream as TkTcpStream};
@@ -956,7 +956,7 @@ async fn expect_continue_waits_for_body_poll() {
 
     Http::new()
         .serve_connection(
-            socket,
+            TokioIo::new(socket),
             service_fn(|req| {
                 assert_eq!(req.headers()["expect"], "100-continue");
                 // But! We're never going to poll the body!
@@ -1110,6 +1110,46 @@ fn http_11_uri_too_long() {
     assert_eq!(s(&buf[..expected.len()]), expected);
 }
 
+#[tokio::test]
+async fn http1_on_parse_error_responds_or_closes() {
+    use hyper::error::{ErrorKind, ParseKind};
+    use hyper::server::conn::ParseErrorAction;
+
+    let mut http = Http::new();
+    http.http1_on_parse_error(|err| match err.kind() {
+        ErrorKind::Parse(ParseKind::Header) => ParseErrorAction::Respond(
+            Response::builder()
+                .status(StatusCode::IM_A_TEAPOT)
+                .body(Bytes::from_static(b"bad header"))
+                .unwrap(),
+        ),
+        _ => ParseErrorAction::Close,
+    });
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = tokio::spawn(http.serve_connection(TokioIo::new(server), HelloWorld));
+    client
+        .write_all(b"GET / HTTP/1.1\r\nbad header\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let res = s(&buf);
+    assert!(res.starts_with("HTTP/1.1 418 "), "{:?}", res);
+    assert!(res.contains("content-length: 10\r\n"), "{:?}", res);
+    assert!(res.ends_with("\r\n\r\nbad header"), "{:?}", res);
+    conn.await.unwrap().expect_err("parse error");
+
+    // Any other error closes without a response.
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = tokio::spawn(http.serve_connection(TokioIo::new(server), HelloWorld));
+    client.write_all(b"GET / HTTP/9.9\r\n\r\n").await.unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(buf.is_empty(), "{:?}", s(&buf));
+    conn.await.unwrap().expect_err("parse error");
+}
+
 #[tokio::test]
 async fn disable_keep_alive_mid_request() {
     let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
@@ -1129,7 +1169,7 @@ async fn disable_keep_alive_mid_request() {
     });
 
     let (socket, _) = listener.accept().await.unwrap();
-    let srv = Http::new().serve_connection(socket, HelloWorld);
+    let srv = Http::new().serve_connection(TokioIo::new(socket), HelloWorld);
     future::try_select(srv, rx1)
         .then(|r| match r {
             Ok(Either::Left(_)) => panic!("expected rx first"),
@@ -1182,7 +1222,7 @@ async fn disable_keep_alive_post_request() {
         stream: socket,
         _debug: dropped2,
     };
-    let server = Http::new().serve_connection(transport, HelloWorld);
+    let server = Http::new().serve_connection(TokioIo::new(transport), HelloWorld);
     let fut = future::try_select(server, rx1).then(|r| match r {
         Ok(Either::Left(_)) => panic!("expected rx first"),
         Ok(Either::Right(((), mut conn))) => {
@@ -1211,7 +1251,7 @@ async fn empty_parse_eof_does_not_return_error() {
 
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
-        .serve_connection(socket, HelloWorld)
+        .serve_connection(TokioIo::new(socket), HelloWorld)
         .await
         .expect("empty parse eof is ok");
 }
@@ -1228,7 +1268,7 @@ async fn nonempty_parse_eof_returns_error() {
 
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
-        .serve_connection(socket, HelloWorld)
+        .serve_connection(TokioIo::new(socket), HelloWorld)
         .await
         .expect_err("partial parse eof is error");
 }
@@ -1255,7 +1295,7 @@ async fn http1_allow_half_close() {
     Http::new()
         .http1_half_close(true)
         .serve_connection(
-            socket,
+            TokioIo::new(socket),
             service_fn(|_| {
                 TokioTimer
                     .sleep(Duration::from_millis(500))
@@ -1284,7 +1324,7 @@ async fn disconnect_after_reading_request_before_responding() {
     Http::new()
         .http1_half_close(false)
         .serve_connection(
-            socket,
+            TokioIo::new(socket),
             service_fn(|_| {
                 TokioTimer.sleep(Duration::from_secs(2)).map(
                     |_| -> Result<Response<Recv>, hyper::Error> {
@@ -1315,7 +1355,7 @@ async fn returning_1xx_response_is_error() {
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
         .serve_connection(
-            socket,
+            TokioIo::new(socket),
             service_fn(|_| async move {
                 Ok::<_, hyper::Error>(
                     Response::builder()
@@ -1382,7 +1422,7 @@ async fn header_read_timeout_slow_writes() {
         .with_timer(TokioTimer)
         .http1_header_read_timeout(Duration::from_secs(5))
         .serve_connection(
-            socket,
+            TokioIo::new(socket),
             service_fn(|_| {
                 let res = Response::builder()
                     .status(200)
@@ -1394,6 +1434,196 @@ async fn header_read_timeout_slow_writes() {
     conn.without_shutdown().await.expect_err("header timeout");
 }
 
+#[tokio::test]
+async fn header_read_timeout_responds_408() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let conn = Http::new()
+        .with_timer(TokioTimer)
+        .http1_header_read_timeout(Duration::from_millis(50))
+        .http1_header_read_timeout_response(true)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    let conn = tokio::spawn(conn);
+
+    client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    let expected = "HTTP/1.1 408 Request Timeout\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    conn.await.unwrap().expect_err("header timeout");
+}
+
+#[tokio::test]
+async fn header_read_timeout_with_mock_timer() {
+    use hyper::rt::{Interval, Sleep, Timer};
+    use std::time::Instant;
+
+    // A timer where every deadline has already passed.
+    struct ExpiredTimer;
+
+    struct Expired;
+
+    impl Future for Expired {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            Poll::Ready(())
+        }
+    }
+
+    impl Sleep for Expired {}
+
+    impl Interval for Expired {
+        fn poll_tick(&mut self, _cx: &mut Context<'_>) -> Poll<Instant> {
+            Poll::Ready(Instant::now())
+        }
+    }
+
+    impl Timer for ExpiredTimer {
+        fn sleep(&self, _duration: Duration) -> Box<dyn Sleep + Unpin> {
+            Box::new(Expired)
+        }
+
+        fn sleep_until(&self, _deadline: Instant) -> Box<dyn Sleep + Unpin> {
+            Box::new(Expired)
+        }
+
+        fn interval(&self, _period: Duration) -> Box<dyn Interval + Unpin> {
+            Box::new(Expired)
+        }
+    }
+
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let conn = Http::new()
+        .with_timer(ExpiredTimer)
+        .http1_header_read_timeout(Duration::from_secs(60 * 60))
+        .http1_header_read_timeout_response(true)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    let conn = tokio::spawn(conn);
+
+    client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    let expected = "HTTP/1.1 408 Request Timeout\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    conn.await.unwrap().expect_err("header timeout");
+}
+
+#[tokio::test]
+async fn header_read_timeout_with_timer_wheel() {
+    use hyper::rt::TimerWheel;
+
+    let _ = pretty_env_logger::try_init();
+    let (wheel, driver) = TimerWheel::new(TokioTimer, Duration::from_millis(5));
+    let driver = tokio::spawn(driver);
+
+    let mut http = Http::new().with_timer(wheel);
+    http.http1_header_read_timeout(Duration::from_millis(50))
+        .http1_header_read_timeout_response(true);
+
+    // Connections share the wheel for their timeouts.
+    let mut clients = Vec::new();
+    for _ in 0..10 {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let conn = http.serve_connection(TokioIo::new(server), HelloWorld);
+        tokio::spawn(async move {
+            conn.await.expect_err("header timeout");
+        });
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        clients.push(client);
+    }
+
+    for mut client in clients {
+        let mut buf = vec![];
+        client.read_to_end(&mut buf).await.unwrap();
+        let expected = "HTTP/1.1 408 Request Timeout\r\n";
+        assert_eq!(s(&buf[..expected.len()]), expected);
+    }
+
+    drop(http);
+    driver.await.unwrap();
+}
+
+// A clock stuck at a fixed point in time.
+struct FrozenClock {
+    now: Instant,
+    system_now: SystemTime,
+}
+
+impl hyper::rt::Clock for FrozenClock {
+    fn now(&self) -> Instant {
+        self.now
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_now
+    }
+}
+
+#[tokio::test]
+async fn header_read_timeout_with_clock() {
+    let _ = pretty_env_logger::try_init();
+
+    // The deadline is computed from the frozen clock, so it has already
+    // passed, despite the long timeout.
+    let clock = FrozenClock {
+        now: Instant::now() - Duration::from_secs(60),
+        system_now: SystemTime::now(),
+    };
+    let mut http = Http::new().with_timer(TokioTimer).with_clock(clock);
+    http.http1_header_read_timeout(Duration::from_secs(30))
+        .http1_header_read_timeout_response(true);
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = http.serve_connection(TokioIo::new(server), HelloWorld);
+    tokio::spawn(async move {
+        conn.await.expect_err("header timeout");
+    });
+    client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+
+    let mut buf = vec![];
+    tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut buf))
+        .await
+        .expect("timed out by the clock")
+        .unwrap();
+    let expected = "HTTP/1.1 408 Request Timeout\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+}
+
+#[tokio::test]
+async fn date_header_with_clock() {
+    let _ = pretty_env_logger::try_init();
+
+    let clock = FrozenClock {
+        now: Instant::now(),
+        system_now: SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777),
+    };
+    let http = Http::new().with_clock(clock);
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = http.serve_connection(TokioIo::new(server), HelloWorld);
+    tokio::spawn(async move {
+        conn.await.expect("server conn");
+    });
+    client
+        .write_all(b"GET / HTTP/1.1\r\nconnection: close\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let response = s(&buf);
+    assert!(
+        response.contains("date: Sun, 06 Nov 1994 08:49:37 GMT\r\n"),
+        "response = {:?}",
+        response
+    );
+}
+
 #[tokio::test]
 async fn header_read_timeout_slow_writes_multiple_requests() {
     let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
@@ -1458,7 +1688,7 @@ async fn header_read_timeout_slow_writes_multiple_requests() {
         .with_timer(TokioTimer)
         .http1_header_read_timeout(Duration::from_secs(5))
         .serve_connection(
-            socket,
+            TokioIo::new(socket),
             service_fn(|_| {
                 let res = Response::builder()
                     .status(200)
@@ -1503,7 +1733,7 @@ async fn upgrades() {
 
     let (socket, _) = listener.accept().await.unwrap();
     let conn = Http::new().serve_connection(
-        socket,
+        TokioIo::new(socket),
         service_fn(|_| {
             let res = Response::builder()
                 .status(101)
@@ -1520,13 +1750,41 @@ async fn upgrades() {
     // wait so that we don't write until other side saw 101 response
     rx.await.unwrap();
 
-    let mut io = parts.io;
+    let mut io = parts.io.into_inner();
     io.write_all(b"foo=bar").await.unwrap();
     let mut vec = vec![];
     io.read_to_end(&mut vec).await.unwrap();
     assert_eq!(vec, b"bar=foo");
 }
 
+#[tokio::test]
+async fn without_shutdown_after_exchange() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .http1_keep_alive(false)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nother protocol")
+        .await
+        .unwrap();
+    let parts = conn.without_shutdown().await.unwrap();
+    assert_eq!(parts.read_buf, "other protocol");
+
+    let mut buf = vec![0; 1024];
+    let mut len = 0;
+    while !s(&buf[..len]).ends_with(HELLO) {
+        len += client.read(&mut buf[len..]).await.unwrap();
+    }
+
+    // The IO object is still open.
+    let mut io = parts.io.into_inner();
+    io.write_all(b"foo=bar").await.unwrap();
+    let n = client.read(&mut buf).await.unwrap();
+    assert_eq!(s(&buf[..n]), "foo=bar");
+}
+
 #[tokio::test]
 async fn http_connect() {
     let _ = pretty_env_logger::try_init();
@@ -1558,7 +1816,7 @@ async fn http_connect() {
 
     let (socket, _) = listener.accept().await.unwrap();
     let conn = Http::new().serve_connection(
-        socket,
+        TokioIo::new(socket),
         service_fn(|_| {
             let res = Response::builder()
                 .status(200)
@@ -1574,7 +1832,7 @@ async fn http_connect() {
     // wait so that we don't write until other side saw 101 response
     rx.await.unwrap();
 
-    let mut io = parts.io;
+    let mut io = parts.io.into_inner();
     io.write_all(b"foo=bar").await.unwrap();
     let mut vec = vec![];
     io.read_to_end(&mut vec).await.unwrap();
@@ -1630,7 +1888,7 @@ async fn upgrades_new() {
 
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
-        .serve_connection(socket, svc)
+        .serve_connection(TokioIo::new(socket), svc)
         .with_upgrades()
         .await
         .unwrap();
@@ -1641,16 +1899,48 @@ async fn upgrades_new() {
     read_101_rx.await.unwrap();
 
     let upgraded = on_upgrade.await.expect("on_upgrade");
-    let parts = upgraded.downcast::<TkTcpStream>().unwrap();
+    let parts = upgraded.downcast::<TokioIo<TkTcpStream>>().unwrap();
     assert_eq!(parts.read_buf, "eagerly optimistic");
 
-    let mut io = parts.io;
+    let mut io = parts.io.into_inner();
     io.write_all(b"foo=bar").await.unwrap();
     let mut vec = vec![];
     io.read_to_end(&mut vec).await.unwrap();
     assert_eq!(s(&vec), "bar=foo");
 }
 
+#[tokio::test]
+async fn upgrades_survive_graceful_shutdown() {
+    let _ = pretty_env_logger::try_init();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let child = thread::spawn(move || {
+        let mut tcp = connect(&addr);
+        tcp.write_all(
+            b"\
+            GET / HTTP/1.1\r\n\
+            Upgrade: foobar\r\n\
+            Connection: upgrade\r\n\
+            \r\n\
+        ",
+        )
+        .expect("write 1");
+        let buf = read_until(&mut tcp, |buf| buf.ends_with(b"Bread?")).expect("read 1");
+        assert!(s(&buf).starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+
+        tcp.write_all(b"Baguette!").expect("write 2");
+        tcp.shutdown(Shutdown::Write).unwrap();
+        let mut vec = vec![];
+        tcp.read_to_end(&mut vec).expect("read 2");
+        assert!(vec.is_empty(), "{:?}", s(&vec));
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    serve_upgrade_during_graceful_shutdown(socket, false).await;
+    child.join().unwrap();
+}
+
 #[tokio::test]
 async fn upgrades_ignored() {
     let _ = pretty_env_logger::try_init();
@@ -1667,7 +1957,7 @@ async fn upgrades_ignored() {
             let (socket, _) = listener.accept().await.unwrap();
             tokio::task::spawn(async move {
                 Http::new()
-                    .serve_connection(socket, svc)
+                    .serve_connection(TokioIo::new(socket), svc)
                     .with_upgrades()
                     .await
                     .expect("server task");
@@ -1738,7 +2028,7 @@ async fn http_connect_new() {
 
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
-        .serve_connection(socket, svc)
+        .serve_connection(TokioIo::new(socket), svc)
         .with_upgrades()
         .await
         .unwrap();
@@ -1749,10 +2039,10 @@ async fn http_connect_new() {
     read_200_rx.await.unwrap();
 
     let upgraded = on_upgrade.await.expect("on_upgrade");
-    let parts = upgraded.downcast::<TkTcpStream>().unwrap();
+    let parts = upgraded.downcast::<TokioIo<TkTcpStream>>().unwrap();
     assert_eq!(parts.read_buf, "eagerly optimistic");
 
-    let mut io = parts.io;
+    let mut io = parts.io.into_inner();
     io.write_all(b"foo=bar").await.unwrap();
     let mut vec = vec![];
     io.read_to_end(&mut vec).await.unwrap();
@@ -1800,7 +2090,7 @@ async fn h2_connect() {
         let on_upgrade = hyper::upgrade::on(req);
 
         tokio::spawn(async move {
-            let mut upgraded = on_upgrade.await.expect("on_upgrade");
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
             upgraded.write_all(b"Bread?").await.unwrap();
 
             let mut vec = vec![];
@@ -1822,12 +2112,107 @@ async fn h2_connect() {
     Http::new()
         .with_executor(TokioExecutor)
         .http2_only(true)
-        .serve_connection(socket, svc)
+        .serve_connection(TokioIo::new(socket), svc)
         .with_upgrades()
         .await
         .unwrap();
 }
 
+#[tokio::test]
+async fn h2_connect_survives_graceful_shutdown() {
+    let _ = pretty_env_logger::try_init();
+    let listener = tcp_bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let conn = connect_async(addr).await;
+
+    let (h2, connection) = h2::client::handshake(conn).await.unwrap();
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    let mut h2 = h2.ready().await.unwrap();
+
+    let child = tokio::spawn(async move {
+        let request = Request::connect("localhost").body(()).unwrap();
+        let (response, mut send_stream) = h2.send_request(request, false).unwrap();
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut recv_stream = response.into_body();
+        let bytes = recv_stream.data().await.unwrap().unwrap();
+        assert_eq!(&bytes[..], b"Bread?");
+        let _ = recv_stream.flow_control().release_capacity(bytes.len());
+
+        send_stream.send_data("Baguette!".into(), true).unwrap();
+        assert!(recv_stream.data().await.unwrap().unwrap().is_empty());
+    });
+
+    let (socket, _) = listener.accept().await.unwrap();
+    serve_upgrade_during_graceful_shutdown(socket, true).await;
+    child.await.unwrap();
+}
+
+// Serves an upgrade, starting a graceful shutdown of the connection while
+// the service handles the request. The upgraded IO then writes "Bread?",
+// expects "Baguette!" until the peer shuts down, and shuts down too.
+async fn serve_upgrade_during_graceful_shutdown(socket: TkTcpStream, http2: bool) {
+    let (called_tx, called_rx) = oneshot::channel();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let (upgraded_tx, upgraded_rx) = oneshot::channel();
+    let mut called_tx = Some(called_tx);
+    let mut shutdown_rx = Some(shutdown_rx);
+    let mut upgraded_tx = Some(upgraded_tx);
+
+    let svc = service_fn(move |req: Request<Recv>| {
+        let on_upgrade = hyper::upgrade::on(req);
+        let upgraded_tx = upgraded_tx.take().expect("one request");
+        tokio::spawn(async move {
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
+            upgraded.write_all(b"Bread?").await.unwrap();
+
+            let mut vec = vec![];
+            upgraded.read_to_end(&mut vec).await.unwrap();
+            assert_eq!(s(&vec), "Baguette!");
+
+            upgraded.shutdown().await.unwrap();
+            let _ = upgraded_tx.send(());
+        });
+
+        let _ = called_tx.take().expect("one request").send(());
+        let shutdown = shutdown_rx.take().expect("one request");
+        async move {
+            shutdown.await.expect("graceful shutdown");
+            let res = if http2 {
+                Response::new(Empty::<Bytes>::new())
+            } else {
+                Response::builder()
+                    .status(101)
+                    .header("upgrade", "foobar")
+                    .body(Empty::<Bytes>::new())
+                    .unwrap()
+            };
+            Ok::<_, hyper::Error>(res)
+        }
+    });
+
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(http2)
+        .serve_connection(TokioIo::new(socket), svc)
+        .with_upgrades();
+    tokio::pin!(conn);
+
+    let mut conn = match future::select(conn, called_rx).await {
+        Either::Left((res, _)) => panic!("connection ended before the request: {:?}", res),
+        Either::Right((_, conn)) => conn,
+    };
+    conn.as_mut().graceful_shutdown();
+    shutdown_tx.send(()).unwrap();
+
+    // The upgrade still completes, and the connection ends cleanly.
+    conn.await.expect("connection");
+    upgraded_rx.await.expect("upgraded");
+}
+
 #[tokio::test]
 async fn h2_connect_multiplex() {
     use futures_util::stream::FuturesUnordered;
@@ -1896,7 +2281,7 @@ async fn h2_connect_multiplex() {
                 assert!(upgrade_res.expect_err("upgrade cancelled").is_canceled());
                 return;
             }
-            let mut upgraded = upgrade_res.expect("upgrade successful");
+            let mut upgraded = TokioIo::new(upgrade_res.expect("upgrade successful"));
 
             upgraded.write_all(b"Bread?").await.unwrap();
 
@@ -1935,7 +2320,7 @@ async fn h2_connect_multiplex() {
     Http::new()
         .with_executor(TokioExecutor)
         .http2_only(true)
-        .serve_connection(socket, svc)
+        .serve_connection(TokioIo::new(socket), svc)
         .with_upgrades()
         .await
         .unwrap();
@@ -1987,7 +2372,7 @@ async fn h2_connect_large_body() {
         let on_upgrade = hyper::upgrade::on(req);
 
         tokio::spawn(async move {
-            let mut upgraded = on_upgrade.await.expect("on_upgrade");
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
             upgraded.write_all(b"Bread?").await.unwrap();
 
             let mut vec = vec![];
@@ -2011,7 +2396,7 @@ async fn h2_connect_large_body() {
     Http::new()
         .with_executor(TokioExecutor)
         .http2_only(true)
-        .serve_connection(socket, svc)
+        .serve_connection(TokioIo::new(socket), svc)
         .with_upgrades()
         .await
         .unwrap();
@@ -2062,7 +2447,7 @@ async fn h2_connect_empty_frames() {
         let on_upgrade = hyper::upgrade::on(req);
 
         tokio::spawn(async move {
-            let mut upgraded = on_upgrade.await.expect("on_upgrade");
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
             upgraded.write_all(b"Bread?").await.unwrap();
 
             let mut vec = vec![];
@@ -2084,7 +2469,7 @@ async fn h2_connect_empty_frames() {
     Http::new()
         .with_executor(TokioExecutor)
         .http2_only(true)
-        .serve_connection(socket, svc)
+        .serve_connection(TokioIo::new(socket), svc)
         .with_upgrades()
         .await
         .unwrap();
@@ -2107,7 +2492,7 @@ async fn parse_errors_send_4xx_response() {
 
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
-        .serve_connection(socket, HelloWorld)
+        .serve_connection(TokioIo::new(socket), HelloWorld)
         .await
         .expect_err("HTTP parse error");
 }
@@ -2130,11 +2515,1775 @@ async fn illegal_request_length_returns_400_response() {
 
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
-        .serve_connection(socket, HelloWorld)
+        .serve_connection(TokioIo::new(socket), HelloWorld)
         .await
         .expect_err("illegal Content-Length should error");
 }
 
+#[tokio::test]
+async fn auto_builder_detects_h2_preface() {
+    let _ = pretty_env_logger::try_init();
+    let (client, server) = tokio::io::duplex(1024);
+
+    tokio::spawn(async move {
+        hyper::server::conn::auto::Builder::new()
+            .with_executor(TokioExecutor)
+            .serve_connection(TokioIo::new(server), HelloWorld)
+            .await
+            .expect("serve_connection");
+    });
+
+    let (h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(async move {
+        connection.await.unwrap();
+    });
+    let mut h2 = h2.ready().await.unwrap();
+
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = h2.send_request(req, true).unwrap();
+    let res = res.await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.version(), Version::HTTP_2);
+}
+
+#[tokio::test]
+async fn connection_extension_is_attached_to_requests() {
+    let _ = pretty_env_logger::try_init();
+    let peer: SocketAddr = ([10, 0, 0, 1], 4321).into();
+
+    let svc = service_fn(|req: Request<Recv>| {
+        let peer = req.extensions().get::<SocketAddr>().copied();
+        async move {
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(format!("{:?}", peer)))))
+        }
+    });
+
+    // HTTP/1
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .serve_connection(TokioIo::new(server), svc.clone())
+        .with_extension(peer);
+    tokio::spawn(conn);
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).ends_with("Some(10.0.0.1:4321)"), "{}", s(&buf));
+
+    // HTTP/2, after falling back from HTTP/1
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .serve_connection(TokioIo::new(server), svc)
+        .with_extension(peer);
+    tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = h2.send_request(req, true).unwrap();
+    let mut body = res.await.unwrap().into_body();
+    let data = body.data().await.unwrap().unwrap();
+    assert_eq!(data, "Some(10.0.0.1:4321)");
+}
+
+#[tokio::test]
+async fn serve_connection_with_context() {
+    let _ = pretty_env_logger::try_init();
+
+    #[derive(Clone)]
+    struct Ctx(Arc<String>);
+
+    let svc = service_fn(|req: Request<Recv>| {
+        let sni = req.extensions().get::<Ctx>().map(|ctx| ctx.0.to_string());
+        async move { Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(format!("{:?}", sni))))) }
+    });
+
+    let (client, server) = tokio::io::duplex(1024);
+    let ctx = Ctx(Arc::new("example.com".into()));
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .serve_connection_with_context(TokioIo::new(server), ctx, svc);
+    tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+
+    // Every request on the connection sees the same context.
+    for _ in 0..2 {
+        let req = Request::get("http://localhost/").body(()).unwrap();
+        let (res, _) = h2.send_request(req, true).unwrap();
+        let mut body = res.await.unwrap().into_body();
+        let data = body.data().await.unwrap().unwrap();
+        assert_eq!(data, "Some(\"example.com\")");
+    }
+}
+
+#[tokio::test]
+async fn connection_in_flight_counts_h2_streams() {
+    let _ = pretty_env_logger::try_init();
+    let (tx, rx) = tokio::sync::watch::channel(false);
+
+    let svc = service_fn(move |_req: Request<Recv>| {
+        let mut gate = rx.clone();
+        async move {
+            while !*gate.borrow() {
+                gate.changed().await.unwrap();
+            }
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+        }
+    });
+
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::pin!(conn);
+    assert!(conn.is_idle());
+
+    let (mut h2, connection) = tokio::select! {
+        res = h2::client::handshake(client) => res.unwrap(),
+        res = &mut conn => panic!("connection closed: {:?}", res),
+    };
+    tokio::spawn(connection);
+
+    let mut responses = Vec::new();
+    for _ in 0..2 {
+        let req = Request::get("http://localhost/").body(()).unwrap();
+        responses.push(h2.send_request(req, true).unwrap().0);
+    }
+
+    // Drive the connection until both streams are accepted.
+    future::poll_fn(|cx| {
+        if let Poll::Ready(res) = conn.as_mut().poll(cx) {
+            panic!("connection closed: {:?}", res);
+        }
+        if conn.in_flight() == 2 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+    assert!(!conn.is_idle());
+
+    tx.send(true).unwrap();
+    let bodies = async {
+        for res in responses {
+            let mut body = res.await.unwrap().into_body();
+            while let Some(chunk) = body.data().await {
+                chunk.unwrap();
+            }
+        }
+    };
+    tokio::select! {
+        _ = bodies => (),
+        res = &mut conn => panic!("connection closed: {:?}", res),
+    }
+    assert_eq!(conn.in_flight(), 0);
+    assert!(conn.is_idle());
+}
+
+#[tokio::test]
+async fn connection_in_flight_h1() {
+    let _ = pretty_env_logger::try_init();
+    let (tx, rx) = tokio::sync::watch::channel(false);
+
+    let svc = service_fn(move |_req: Request<Recv>| {
+        let mut gate = rx.clone();
+        async move {
+            while !*gate.borrow() {
+                gate.changed().await.unwrap();
+            }
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+        }
+    });
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = Http::new().serve_connection(TokioIo::new(server), svc);
+    tokio::pin!(conn);
+    assert!(conn.is_idle());
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    future::poll_fn(|cx| {
+        if let Poll::Ready(res) = conn.as_mut().poll(cx) {
+            panic!("connection closed: {:?}", res);
+        }
+        if conn.in_flight() == 1 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+
+    tx.send(true).unwrap();
+    let mut buf = [0; 1024];
+    let read = async {
+        let mut len = 0;
+        while !s(&buf[..len]).ends_with("hello") {
+            len += client.read(&mut buf[len..]).await.unwrap();
+        }
+    };
+    tokio::select! {
+        _ = read => (),
+        res = &mut conn => panic!("connection closed: {:?}", res),
+    }
+    assert!(conn.is_idle());
+}
+
+#[tokio::test]
+async fn disable_implicit_headers_h1() {
+    let _ = pretty_env_logger::try_init();
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .auto_date_header(false)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    tokio::spawn(conn);
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(s(&buf), "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello");
+
+    // An HTTP/1.0 keep-alive request is closed, instead of adding a header.
+    let (mut client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .auto_date_header(false)
+        .http1_keep_alive_header(false)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    tokio::spawn(conn);
+    client
+        .write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(s(&buf), "HTTP/1.0 200 OK\r\ncontent-length: 5\r\n\r\nhello");
+}
+
+#[tokio::test]
+async fn disable_implicit_headers_h2() {
+    let _ = pretty_env_logger::try_init();
+
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .auto_date_header(false)
+        .http2_content_length_header(false)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = h2.send_request(req, true).unwrap();
+    let res = res.await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().is_empty(), "{:?}", res.headers());
+    let data = res.into_body().data().await.unwrap().unwrap();
+    assert_eq!(data, HELLO);
+}
+
+#[tokio::test]
+async fn auto_builder_with_upgrades() {
+    use hyper::server::conn::auto;
+
+    let _ = pretty_env_logger::try_init();
+
+    // Responds to either upgrade by echoing back one message.
+    let svc = service_fn(|req: Request<Recv>| {
+        let status = if req.version() == Version::HTTP_2 {
+            StatusCode::OK
+        } else {
+            StatusCode::SWITCHING_PROTOCOLS
+        };
+        let on_upgrade = hyper::upgrade::on(req);
+        tokio::spawn(async move {
+            let mut upgraded = TokioIo::new(on_upgrade.await.expect("on_upgrade"));
+            let mut buf = [0; 5];
+            upgraded.read_exact(&mut buf).await.unwrap();
+            upgraded.write_all(&buf).await.unwrap();
+            upgraded.shutdown().await.unwrap();
+        });
+        future::ok::<_, hyper::Error>(
+            Response::builder()
+                .status(status)
+                .header("upgrade", "echo")
+                .body(Empty::<Bytes>::new())
+                .unwrap(),
+        )
+    });
+
+    let mut http = Http::new();
+    http.http2_enable_connect_protocol();
+    let builder = auto::Builder::from(http.with_executor(TokioExecutor));
+
+    // HTTP/1
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(builder.serve_connection_with_upgrades(TokioIo::new(server), svc.clone()));
+    client
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: echo\r\nConnection: upgrade\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut buf = vec![0; 1024];
+    let n = client.read(&mut buf).await.unwrap();
+    assert!(s(&buf[..n]).starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    client.write_all(b"hello").await.unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(s(&buf), "hello");
+
+    // HTTP/2, with extended CONNECT
+    let (client, server) = tokio::io::duplex(1024);
+    tokio::spawn(builder.serve_connection_with_upgrades(TokioIo::new(server), svc));
+    let (h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+    let mut h2 = h2.ready().await.unwrap();
+    // Wait for the server's SETTINGS to arrive.
+    for _ in 0..100 {
+        if h2.is_extended_connect_protocol_enabled() {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    assert!(h2.is_extended_connect_protocol_enabled());
+    let req = Request::connect("http://localhost/")
+        .extension(h2::ext::Protocol::from_static("echo"))
+        .body(())
+        .unwrap();
+    let (res, mut send_stream) = h2.send_request(req, false).unwrap();
+    let res = res.await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    send_stream.send_data("hello".into(), true).unwrap();
+    let mut body = res.into_body();
+    let data = body.data().await.unwrap().unwrap();
+    assert_eq!(data, "hello");
+}
+
+#[tokio::test]
+async fn early_response_drains_unread_body() {
+    use hyper::ext::UnreadBody;
+
+    let _ = pretty_env_logger::try_init();
+
+    // Responds without reading the body, with the given policy for it.
+    fn reject(
+        policy: UnreadBody,
+    ) -> impl Service<
+        Request<Recv>,
+        Response = Response<Full<Bytes>>,
+        Error = hyper::Error,
+        Future = future::Ready<Result<Response<Full<Bytes>>, hyper::Error>>,
+    > + Clone {
+        service_fn(move |_req: Request<Recv>| {
+            let mut res = Response::new(Full::new(Bytes::from("nope")));
+            res.extensions_mut().insert(policy);
+            future::ok(res)
+        })
+    }
+
+    async fn read_response(client: &mut tokio::io::DuplexStream) -> String {
+        let mut buf = vec![0; 1024];
+        let mut len = 0;
+        while !s(&buf[..len]).ends_with("nope") {
+            let n = client.read(&mut buf[len..]).await.unwrap();
+            assert_ne!(n, 0, "connection closed early");
+            len += n;
+        }
+        s(&buf[..len]).to_string()
+    }
+
+    // The rest of the body is drained, keeping the connection alive.
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(
+        Http::new().serve_connection(TokioIo::new(server), reject(UnreadBody::Drain(1024))),
+    );
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello")
+        .await
+        .unwrap();
+    let res = read_response(&mut client).await;
+    assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+    client
+        .write_all(b"worldGET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).starts_with("HTTP/1.1 200 OK\r\n"), "{}", s(&buf));
+    assert!(s(&buf).ends_with("nope"));
+
+    // A body longer than the drain limit closes the connection.
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(Http::new().serve_connection(TokioIo::new(server), reject(UnreadBody::Drain(4))));
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\nhello")
+        .await
+        .unwrap();
+    read_response(&mut client).await;
+    let _ = client.write_all(b"more body").await;
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(buf.is_empty());
+
+    // Closing doesn't wait for the rest of the body.
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(Http::new().serve_connection(TokioIo::new(server), reject(UnreadBody::Close)));
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).ends_with("nope"));
+}
+
+#[tokio::test]
+async fn response_completes_while_body_uploads() {
+    let _ = pretty_env_logger::try_init();
+    let (body_tx, body_rx) = oneshot::channel();
+    let body_tx = Mutex::new(Some(body_tx));
+
+    // Responds right away, and reads the body afterwards.
+    let svc = service_fn(move |req: Request<Recv>| {
+        let body_tx = body_tx.lock().unwrap().take().unwrap();
+        tokio::spawn(async move {
+            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+            let _ = body_tx.send(body);
+        });
+        future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+    });
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    tokio::spawn(Http::new().serve_connection(TokioIo::new(server), svc));
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut buf = vec![0; 1024];
+    let mut len = 0;
+    while !s(&buf[..len]).ends_with("hello") {
+        len += client.read(&mut buf[len..]).await.unwrap();
+    }
+    client.write_all(b"world").await.unwrap();
+    assert_eq!(body_rx.await.unwrap(), "helloworld");
+}
+
+#[tokio::test]
+async fn max_pending_responses_h1() {
+    use std::sync::atomic::AtomicUsize;
+
+    let _ = pretty_env_logger::try_init();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    let svc = service_fn(move |_req: Request<Recv>| {
+        calls2.fetch_add(1, Ordering::SeqCst);
+        future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+    });
+
+    // Small enough that two responses fill up the pipe.
+    let (client, server) = tokio::io::duplex(100);
+    let conn = Http::new()
+        .pipeline_flush(true)
+        .max_pending_responses(2)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    let (mut client_rx, mut client_tx) = tokio::io::split(client);
+    tokio::spawn(async move {
+        for _ in 0..6 {
+            client_tx
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+        }
+        client_tx
+    });
+
+    // The client isn't reading responses, so the server stops reading
+    // requests once two are queued.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    let mut buf = vec![0; 4096];
+    let mut len = 0;
+    while s(&buf[..len]).matches("hello").count() < 6 {
+        len += client_rx.read(&mut buf[len..]).await.unwrap();
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 6);
+}
+
+#[tokio::test]
+async fn http1_write_batch_flushes_pipelined_responses_together() {
+    use std::sync::atomic::AtomicUsize;
+
+    struct CountWrites {
+        io: tokio::io::DuplexStream,
+        writes: Arc<AtomicUsize>,
+    }
+
+    impl AsyncRead for CountWrites {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.io).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for CountWrites {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            Pin::new(&mut self.io).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.io).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.io).poll_shutdown(cx)
+        }
+    }
+
+    let _ = pretty_env_logger::try_init();
+    let writes = Arc::new(AtomicUsize::new(0));
+    let (mut client, server) = tokio::io::duplex(4096);
+    let server = CountWrites {
+        io: server,
+        writes: writes.clone(),
+    };
+    let svc = service_fn(|_req: Request<Recv>| {
+        future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+    });
+    let conn = Http::new()
+        .with_timer(TokioTimer)
+        .http1_write_batch(Duration::from_millis(50), 64 * 1024)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(&b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".repeat(3))
+        .await
+        .unwrap();
+
+    let mut buf = vec![0; 4096];
+    let mut len = 0;
+    while s(&buf[..len]).matches("hello").count() < 3 {
+        len += client.read(&mut buf[len..]).await.unwrap();
+    }
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn max_pending_responses_h2() {
+    use std::sync::atomic::AtomicUsize;
+
+    let _ = pretty_env_logger::try_init();
+    let (tx, rx) = tokio::sync::watch::channel(false);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    let svc = service_fn(move |_req: Request<Recv>| {
+        calls2.fetch_add(1, Ordering::SeqCst);
+        let mut gate = rx.clone();
+        async move {
+            while !*gate.borrow() {
+                gate.changed().await.unwrap();
+            }
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+        }
+    });
+
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .max_pending_responses(1)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        let req = Request::get("http://localhost/").body(()).unwrap();
+        responses.push(h2.send_request(req, true).unwrap().0);
+    }
+
+    // Only one stream is accepted while its response is pending.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    tx.send(true).unwrap();
+    for res in responses {
+        let mut body = res.await.unwrap().into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn poll_budget_h1() {
+    use std::sync::atomic::AtomicUsize;
+
+    let _ = pretty_env_logger::try_init();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let calls2 = calls.clone();
+    let svc = service_fn(move |_req: Request<Recv>| {
+        calls2.fetch_add(1, Ordering::SeqCst);
+        future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+    });
+
+    let (mut client, server) = tokio::io::duplex(4096);
+    for _ in 0..3 {
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+    }
+
+    let mut conn = Http::new()
+        .poll_budget(1)
+        .serve_connection(TokioIo::new(server), svc);
+
+    // All the requests are buffered, but the connection yields after
+    // serving one of them.
+    let polled = future::poll_fn(|cx| Poll::Ready(Pin::new(&mut conn).poll(cx))).await;
+    assert!(polled.is_pending());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    tokio::spawn(conn);
+    let mut buf = vec![0; 4096];
+    let mut len = 0;
+    while s(&buf[..len]).matches("hello").count() < 3 {
+        len += client.read(&mut buf[len..]).await.unwrap();
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn poll_budget_h2() {
+    let _ = pretty_env_logger::try_init();
+    let svc = service_fn(|_req: Request<Recv>| {
+        future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+    });
+
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .poll_budget(1)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+
+    // More streams arrive at once than the budget, so accepting them takes
+    // several polls.
+    let mut responses = Vec::new();
+    for _ in 0..5 {
+        let req = Request::get("http://localhost/").body(()).unwrap();
+        responses.push(h2.send_request(req, true).unwrap().0);
+    }
+
+    for res in responses {
+        let mut body = res.await.unwrap().into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+    }
+}
+
+#[tokio::test]
+async fn h2_stream_overflow_queue() {
+    use hyper::server::conn::StreamOverflow;
+
+    let _ = pretty_env_logger::try_init();
+    let (tx, rx) = tokio::sync::watch::channel(false);
+    let svc = service_fn(move |_req: Request<Recv>| {
+        let mut gate = rx.clone();
+        async move {
+            while !*gate.borrow() {
+                gate.changed().await.unwrap();
+            }
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+        }
+    });
+
+    let shed = Arc::new(Mutex::new(Vec::new()));
+    let shed2 = shed.clone();
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .http2_max_concurrent_streams(1)
+        .http2_stream_overflow(StreamOverflow::Queue(1))
+        .http2_on_stream_overflow(move |policy| shed2.lock().unwrap().push(policy))
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        let req = Request::get("http://localhost/").body(()).unwrap();
+        responses.push(h2.send_request(req, true).unwrap().0);
+    }
+    let refused = responses.pop().unwrap();
+    let err = refused.await.expect_err("third stream should be refused");
+    assert_eq!(err.reason(), Some(h2::Reason::REFUSED_STREAM));
+    assert_eq!(*shed.lock().unwrap(), [StreamOverflow::Refuse]);
+
+    // The queued stream is served once the first one finishes.
+    tx.send(true).unwrap();
+    for res in responses {
+        let mut body = res.await.unwrap().into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+    }
+}
+
+#[tokio::test]
+async fn h2_stream_overflow_goaway() {
+    use hyper::server::conn::StreamOverflow;
+
+    let _ = pretty_env_logger::try_init();
+    let (tx, rx) = tokio::sync::watch::channel(false);
+    let svc = service_fn(move |_req: Request<Recv>| {
+        let mut gate = rx.clone();
+        async move {
+            while !*gate.borrow() {
+                gate.changed().await.unwrap();
+            }
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("hello"))))
+        }
+    });
+
+    let shed = Arc::new(Mutex::new(Vec::new()));
+    let shed2 = shed.clone();
+    let (client, server) = tokio::io::duplex(1024);
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .http2_max_concurrent_streams(1)
+        .http2_stream_overflow(StreamOverflow::GoAway)
+        .http2_on_stream_overflow(move |policy| shed2.lock().unwrap().push(policy))
+        .serve_connection(TokioIo::new(server), svc);
+    let conn = tokio::spawn(conn);
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let first = h2.send_request(req, true).unwrap().0;
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let second = h2.send_request(req, true).unwrap().0;
+    let err = second.await.expect_err("second stream should be refused");
+    assert_eq!(err.reason(), Some(h2::Reason::REFUSED_STREAM));
+    assert_eq!(*shed.lock().unwrap(), [StreamOverflow::GoAway]);
+
+    // The active stream still finishes, and then the connection closes.
+    tx.send(true).unwrap();
+    let mut body = first.await.unwrap().into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), "hello");
+    drop(body);
+    drop(h2);
+    conn.await.unwrap().expect("connection");
+}
+
+#[tokio::test]
+async fn auto_builder_tls_alpn() {
+    use hyper::server::conn::auto;
+
+    let _ = pretty_env_logger::try_init();
+    let builder = auto::Builder::new().with_executor(TokioExecutor);
+
+    // ALPN selects HTTP/2, without the client sending a preface first.
+    let (client, server) = tokio::io::duplex(1024);
+    let acceptor = FakeTlsAcceptor::new(Ok(Some(b"h2")));
+    tokio::spawn(builder.serve_tls_connection(TokioIo::new(server), &acceptor, HelloWorld));
+    let (mut h2, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = h2.send_request(req, true).unwrap();
+    let mut body = res.await.unwrap().into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), HELLO);
+
+    // ALPN selects HTTP/1.1.
+    let (mut client, server) = tokio::io::duplex(1024);
+    let acceptor = FakeTlsAcceptor::new(Ok(Some(b"http/1.1")));
+    tokio::spawn(builder.serve_tls_connection(TokioIo::new(server), &acceptor, HelloWorld));
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(s(&buf).ends_with(HELLO));
+}
+
+#[tokio::test]
+async fn auto_builder_tls_handshake_error() {
+    use hyper::server::conn::auto;
+
+    let _ = pretty_env_logger::try_init();
+    let (_client, server) = tokio::io::duplex(1024);
+    let acceptor = FakeTlsAcceptor::new(Err("bad certificate"));
+    let err = auto::Builder::new()
+        .serve_tls_connection(TokioIo::new(server), &acceptor, HelloWorld)
+        .await
+        .expect_err("handshake should fail");
+    assert!(err.is_tls(), "{:?}", err);
+}
+
+#[tokio::test]
+async fn auto_builder_tls_client_identity() {
+    use hyper::ext::{PeerCertificates, ServerName};
+    use hyper::server::conn::auto;
+
+    let _ = pretty_env_logger::try_init();
+    let svc = service_fn(|req: Request<Recv>| {
+        let certs = req.extensions().get::<PeerCertificates>().unwrap();
+        let name = req.extensions().get::<ServerName>().unwrap();
+        let body = format!("{:?} {}", certs.as_slice(), name.as_str());
+        future::ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(body))))
+    });
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let mut acceptor = FakeTlsAcceptor::new(Ok(Some(b"http/1.1")));
+    acceptor.client_cert = Some(b"client-cert");
+    acceptor.server_name = Some("example.com");
+    tokio::spawn(auto::Builder::new().serve_tls_connection(TokioIo::new(server), &acceptor, svc));
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(
+        s(&buf).ends_with("[b\"client-cert\"] example.com"),
+        "{}",
+        s(&buf)
+    );
+}
+
+#[tokio::test]
+async fn upgrade_to_tls() {
+    use hyper::server::conn::auto;
+    use hyper::upgrade::tls;
+
+    let _ = pretty_env_logger::try_init();
+    let svc = service_fn(|mut req: Request<Recv>| {
+        let res: Response<Empty<Bytes>> = match tls::accept(&req) {
+            Some(res) => res,
+            None => return future::ok::<_, hyper::Error>(tls::upgrade_required()),
+        };
+        tokio::spawn(async move {
+            let upgraded = hyper::upgrade::on(&mut req).await.expect("on_upgrade");
+            let acceptor = FakeTlsAcceptor::new(Ok(None));
+            auto::Builder::new()
+                .with_executor(TokioExecutor)
+                .serve_tls_connection(upgraded, &acceptor, HelloWorld)
+                .await
+                .expect("serve over tls");
+        });
+        future::ok(res)
+    });
+
+    let (io, server) = tokio::io::duplex(1024);
+    tokio::spawn(async move {
+        Http::new()
+            .serve_connection(TokioIo::new(server), svc)
+            .with_upgrades()
+            .await
+            .expect("serve plaintext");
+    });
+
+    let (mut client, conn) = hyper::client::conn::http1::handshake(TokioIo::new(io))
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+
+    let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+    let res = client.send_request(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+
+    let req = tls::request(HeaderValue::from_static("localhost"));
+    let res = client.send_request(req).await.unwrap();
+    assert!(tls::is_accepted(&res));
+
+    // The fake TLS stream is the upgraded connection itself.
+    let upgraded = hyper::upgrade::on(res).await.unwrap();
+    let (mut client, conn) = hyper::client::conn::http1::handshake(upgraded)
+        .await
+        .unwrap();
+    tokio::spawn(conn);
+    let req = Request::get("/").body(Empty::<Bytes>::new()).unwrap();
+    let mut body = client.send_request(req).await.unwrap().into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), HELLO);
+}
+
+// Skips the handshake, pretending to have negotiated the given session.
+struct FakeTlsAcceptor {
+    alpn: Result<Option<&'static [u8]>, &'static str>,
+    client_cert: Option<&'static [u8]>,
+    server_name: Option<&'static str>,
+}
+
+impl FakeTlsAcceptor {
+    fn new(alpn: Result<Option<&'static [u8]>, &'static str>) -> FakeTlsAcceptor {
+        FakeTlsAcceptor {
+            alpn,
+            client_cert: None,
+            server_name: None,
+        }
+    }
+}
+
+struct FakeTlsStream<I = TokioIo<tokio::io::DuplexStream>> {
+    io: I,
+    alpn: Option<&'static [u8]>,
+    client_cert: Option<&'static [u8]>,
+    server_name: Option<&'static str>,
+}
+
+impl<I: hyper::rt::Read + hyper::rt::Write + Unpin> hyper::server::conn::TlsAccept<I>
+    for FakeTlsAcceptor
+{
+    type Stream = FakeTlsStream<I>;
+    type Error = io::Error;
+    type Future = future::Ready<io::Result<FakeTlsStream<I>>>;
+
+    fn accept(&self, io: I) -> Self::Future {
+        future::ready(match self.alpn {
+            Ok(alpn) => Ok(FakeTlsStream {
+                io,
+                alpn,
+                client_cert: self.client_cert,
+                server_name: self.server_name,
+            }),
+            Err(msg) => Err(io::Error::new(io::ErrorKind::InvalidData, msg)),
+        })
+    }
+}
+
+impl<I> hyper::server::conn::TlsStream for FakeTlsStream<I> {
+    fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn
+    }
+
+    fn peer_certificates(&self) -> Option<Vec<Bytes>> {
+        self.client_cert.map(|cert| vec![Bytes::from_static(cert)])
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        self.server_name
+    }
+}
+
+impl<I: hyper::rt::Read + Unpin> hyper::rt::Read for FakeTlsStream<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<I: hyper::rt::Write + Unpin> hyper::rt::Write for FakeTlsStream<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+#[tokio::test]
+async fn graceful_shutdown_drains_h1_and_h2_connections() {
+    use hyper::server::conn::{auto, GracefulShutdown};
+
+    let _ = pretty_env_logger::try_init();
+    let graceful = GracefulShutdown::new().with_timer(TokioTimer);
+    let builder = auto::Builder::new().with_executor(TokioExecutor);
+
+    let (h1_client, h1_server) = tokio::io::duplex(1024);
+    let (h2_client, h2_server) = tokio::io::duplex(1024);
+    let h1 =
+        tokio::spawn(graceful.watch(builder.serve_connection(TokioIo::new(h1_server), HelloWorld)));
+    let h2 =
+        tokio::spawn(graceful.watch(builder.serve_connection(TokioIo::new(h2_server), HelloWorld)));
+    assert_eq!(graceful.count(), 2);
+
+    let (mut h2_client, connection) = h2::client::handshake(h2_client).await.unwrap();
+    tokio::spawn(connection);
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = h2_client.send_request(req, true).unwrap();
+    assert_eq!(res.await.unwrap().status(), StatusCode::OK);
+
+    graceful
+        .shutdown_timeout(Duration::from_secs(5))
+        .await
+        .expect("shutdown");
+    h1.await.unwrap().expect("h1 connection");
+    // The h2 client may hang up first after the GOAWAY, so only check
+    // that the connection finished.
+    let _ = h2.await.unwrap();
+    drop(h1_client);
+}
+
+#[tokio::test]
+async fn graceful_shutdown_timeout() {
+    use hyper::server::conn::GracefulShutdown;
+
+    let _ = pretty_env_logger::try_init();
+    let graceful = GracefulShutdown::new().with_timer(TokioTimer);
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let svc = service_fn(|_| future::pending::<Result<Response<Empty<Bytes>>, hyper::Error>>());
+    let conn = graceful.watch(Http::new().serve_connection(TokioIo::new(server), svc));
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::task::yield_now().await;
+
+    let err = graceful
+        .shutdown_timeout(Duration::from_millis(50))
+        .await
+        .expect_err("shutdown should time out");
+    assert!(err.is_timeout());
+}
+
+#[tokio::test]
+async fn service_timeout_h1_responds_with_status() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let svc = service_fn(|_| future::pending::<Result<Response<Empty<Bytes>>, hyper::Error>>());
+    let conn = Http::new()
+        .with_timer(TokioTimer)
+        .service_timeout(Some(Duration::from_millis(50)))
+        .service_timeout_status(StatusCode::GATEWAY_TIMEOUT)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(async move {
+        conn.await.expect("serve_connection");
+    });
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    let expected = "HTTP/1.1 504 Gateway Timeout\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    assert!(s(&buf).contains("content-length: 0\r\n"));
+}
+
+#[tokio::test]
+async fn service_timeout_h2_resets_stream() {
+    let _ = pretty_env_logger::try_init();
+    let (client, server) = tokio::io::duplex(1024);
+
+    let svc = service_fn(|_| future::pending::<Result<Response<Empty<Bytes>>, hyper::Error>>());
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .with_timer(TokioTimer)
+        .http2_only(true)
+        .service_timeout(Some(Duration::from_millis(50)))
+        .service_timeout_reset(true)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    let (mut client, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = client.send_request(req, true).unwrap();
+    let err = res.await.expect_err("stream should be reset");
+    assert_eq!(err.reason(), Some(h2::Reason::CANCEL));
+}
+
+#[tokio::test]
+async fn max_request_body_size_h1_rejects_declared_length() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let conn = Http::new()
+        .max_request_body_size(4)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    let conn = tokio::spawn(conn);
+
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    let expected = "HTTP/1.1 413 Payload Too Large\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    let err = conn.await.unwrap().expect_err("conn should error");
+    assert!(err.is_body_too_large());
+}
+
+#[tokio::test]
+async fn max_request_body_size_h1_closes_before_declared_body() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let conn = Http::new()
+        .max_request_body_size(4)
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    let conn = tokio::spawn(conn);
+
+    // The body is never sent, so only the connection closing ends the read.
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 100\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    tokio::time::timeout(Duration::from_secs(5), client.read_to_end(&mut buf))
+        .await
+        .expect("connection should close")
+        .unwrap();
+
+    let expected = "HTTP/1.1 413 Payload Too Large\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    let err = conn.await.unwrap().expect_err("conn should error");
+    assert!(err.is_body_too_large());
+}
+
+#[tokio::test]
+async fn max_request_body_size_h1_errors_chunked_body() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let (body_tx, body_rx) = oneshot::channel();
+    let mut body_tx = Some(body_tx);
+
+    let svc = service_fn(move |req: Request<Recv>| {
+        let body_tx = body_tx.take().unwrap();
+        async move {
+            let res = hyper::body::to_bytes(req.into_body()).await;
+            body_tx.send(res.map(|_| ())).unwrap();
+            Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        }
+    });
+    let conn = Http::new()
+        .max_request_body_size(4)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    let expected = "HTTP/1.1 413 Payload Too Large\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    // The connection may close before the service gets to see the error.
+    if let Ok(res) = body_rx.await {
+        assert!(res.expect_err("body should error").is_body_too_large());
+    }
+}
+
+#[tokio::test]
+async fn max_request_body_size_h2_resets_stream() {
+    let _ = pretty_env_logger::try_init();
+    let (client, server) = tokio::io::duplex(1024);
+
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok::<_, hyper::Error>(Response::new(Full::new(body)))
+    });
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .max_request_body_size(4)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    let (mut client, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+    let req = Request::post("http://localhost/").body(()).unwrap();
+    let (res, mut body) = client.send_request(req, false).unwrap();
+    body.send_data(Bytes::from_static(b"hello"), true).unwrap();
+    let err = res.await.expect_err("stream should be reset");
+    assert_eq!(err.reason(), Some(h2::Reason::CANCEL));
+}
+
+#[tokio::test]
+async fn on_request_complete_h1() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok::<_, hyper::Error>(Response::new(Full::new(body)))
+    });
+    let conn = Http::new()
+        .on_request_complete(move |summary| {
+            let _ = tx.lock().unwrap().send((
+                summary.method().clone(),
+                summary.uri().clone(),
+                summary.status(),
+                summary.bytes_read(),
+                summary.bytes_written(),
+                summary.is_error(),
+            ));
+        })
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).ends_with("hello"));
+
+    let (method, uri, status, read, written, is_error) = rx.recv().unwrap();
+    assert_eq!(method, Method::POST);
+    assert_eq!(uri, "/echo");
+    assert_eq!(status, Some(StatusCode::OK));
+    assert_eq!(read, 5);
+    assert_eq!(written, 5);
+    assert!(!is_error);
+}
+
+#[tokio::test]
+async fn on_request_complete_h2() {
+    let _ = pretty_env_logger::try_init();
+    let (client, server) = tokio::io::duplex(1024);
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+
+    let conn = Http::new()
+        .with_executor(TokioExecutor)
+        .http2_only(true)
+        .on_request_complete(move |summary| {
+            let _ = tx.lock().unwrap().send((
+                summary.status(),
+                summary.bytes_written(),
+                summary.is_error(),
+            ));
+        })
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    tokio::spawn(conn);
+
+    let (mut client, connection) = h2::client::handshake(client).await.unwrap();
+    tokio::spawn(connection);
+    let req = Request::get("http://localhost/").body(()).unwrap();
+    let (res, _) = client.send_request(req, true).unwrap();
+    let mut body = res.await.unwrap().into_body();
+    while let Some(chunk) = body.data().await {
+        chunk.unwrap();
+    }
+
+    let (status, written, is_error) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(status, Some(StatusCode::OK));
+    assert_eq!(written, HELLO.len() as u64);
+    assert!(!is_error);
+}
+
+#[tokio::test]
+async fn metrics_with_on_request_complete() {
+    use hyper::metrics::{Metrics, StatusClass};
+
+    #[derive(Default)]
+    struct Counts {
+        read: AtomicU64,
+        written: AtomicU64,
+        successes: AtomicU64,
+    }
+
+    struct Recorder(Arc<Counts>);
+
+    impl Metrics for Recorder {
+        fn bytes_read(&self, bytes: u64) {
+            self.0.read.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+        fn bytes_written(&self, bytes: u64) {
+            self.0.written.fetch_add(bytes, Ordering::SeqCst);
+        }
+
+        fn response_status(&self, class: StatusClass) {
+            if class == StatusClass::Success {
+                self.0.successes.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let counts = Arc::new(Counts::default());
+
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        Ok::<_, hyper::Error>(Response::new(Full::new(body)))
+    });
+    // Metrics are reported before the callback is called.
+    let conn = Http::new()
+        .metrics(Recorder(counts.clone()))
+        .on_request_complete(move |_| {
+            let _ = tx.lock().unwrap().send(());
+        })
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    assert!(s(&buf).ends_with("hello"));
+
+    rx.recv().unwrap();
+    assert_eq!(counts.read.load(Ordering::SeqCst), 5);
+    assert_eq!(counts.written.load(Ordering::SeqCst), 5);
+    assert_eq!(counts.successes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn slow_poll_reported_to_metrics() {
+    use hyper::metrics::{Metrics, PollSource};
+
+    struct Recorder(Arc<Mutex<Vec<PollSource>>>);
+
+    impl Metrics for Recorder {
+        fn slow_poll(&self, source: PollSource, duration: Duration) {
+            assert!(duration >= Duration::from_millis(20));
+            self.0.lock().unwrap().push(source);
+        }
+    }
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let slow = Arc::new(Mutex::new(Vec::new()));
+
+    let svc = service_fn(|_req: Request<Recv>| async move {
+        // Blocks the thread, instead of yielding.
+        thread::sleep(Duration::from_millis(50));
+        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("done"))))
+    });
+    let conn = Http::new()
+        .metrics(Recorder(slow.clone()))
+        .slow_poll_threshold(Duration::from_millis(20))
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    assert!(s(&buf).ends_with("done"));
+    assert_eq!(*slow.lock().unwrap(), [PollSource::Service]);
+}
+
+#[tokio::test]
+async fn request_id_adopted_or_generated() {
+    use hyper::server::conn::RequestId;
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let id = req.extensions().get::<RequestId>().expect("request id");
+        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(id.to_string()))))
+    });
+    let conn = Http::new()
+        .request_id(|| RequestId::new(HeaderValue::from_static("generated")))
+        .request_id_header(HeaderName::from_static("x-request-id"))
+        .on_request_complete(move |summary| {
+            let id = summary.request_id().map(ToString::to_string);
+            let _ = tx.send(id);
+        })
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"GET /a HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc-123\r\n\r\n")
+        .await
+        .unwrap();
+    assert_eq!(rx.recv().await.unwrap().as_deref(), Some("abc-123"));
+
+    client
+        .write_all(b"GET /b HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).contains("abc-123"));
+    assert!(s(&buf).ends_with("generated"));
+    assert_eq!(rx.recv().await.unwrap().as_deref(), Some("generated"));
+}
+
+#[tokio::test]
+async fn connection_use_extension() {
+    use hyper::ext::ConnectionUse;
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let usage = req
+            .extensions()
+            .get::<ConnectionUse>()
+            .expect("connection use");
+        let body = format!("[{} {}]", usage.exchanges(), usage.is_reused());
+        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(body))))
+    });
+    let conn = Http::new()
+        .connection_use(true)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"GET /a HTTP/1.1\r\nHost: localhost\r\n\r\nGET /b HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).contains("[1 false]"));
+    assert!(s(&buf).ends_with("[2 true]"));
+}
+
+#[tokio::test]
+async fn peer_disconnect_fires_before_response() {
+    use hyper::ext::PeerDisconnect;
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let (tx, rx) = oneshot::channel();
+    let tx = Mutex::new(Some(tx));
+
+    let svc = service_fn(move |req: Request<Recv>| {
+        let disconnect = req
+            .extensions()
+            .get::<PeerDisconnect>()
+            .cloned()
+            .expect("peer disconnect");
+        let tx = tx.lock().unwrap().take().expect("one request");
+        tokio::spawn(async move {
+            disconnect.disconnected().await;
+            let _ = tx.send(disconnect.is_disconnected());
+        });
+        // Never responds.
+        future::pending::<Result<Response<Empty<Bytes>>, hyper::Error>>()
+    });
+    let conn = Http::new()
+        .peer_disconnect(true)
+        .serve_connection(TokioIo::new(server), svc);
+    tokio::spawn(conn);
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    drop(client);
+
+    let fired = tokio::time::timeout(Duration::from_secs(5), rx)
+        .await
+        .expect("disconnect timeout")
+        .expect("disconnect sent");
+    assert!(fired);
+}
+
+#[tokio::test]
+async fn http1_lazy_headers() {
+    use hyper::ext::LazyHeaders;
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let svc = service_fn(|req: Request<Recv>| async move {
+        assert!(req.headers().get("x-forwarded-for").is_none());
+        let lazy = req.extensions().get::<LazyHeaders>().unwrap().clone();
+        assert_eq!(lazy.get("x-forwarded-for").unwrap(), "10.0.0.1");
+
+        // Echo the unparsed headers back as they were received.
+        let mut res = Response::new(Full::new(Bytes::from("lazy")));
+        res.headers_mut()
+            .insert("x-id", HeaderValue::from_static("overridden"));
+        res.extensions_mut().insert(lazy);
+        Ok::<_, hyper::Error>(res)
+    });
+    let conn = Http::new()
+        .http1_lazy_headers(true)
+        .http1_forward_lazy_headers(true)
+        .serve_connection(TokioIo::new(server), svc);
+    let conn = tokio::spawn(conn);
+
+    client
+        .write_all(
+            b"GET / HTTP/1.1\r\n\
+              X-Forwarded-For: 10.0.0.1\r\n\
+              X-Id: 1\r\n\
+              Keep-Alive: timeout=5\r\n\
+              Proxy-Authorization: secret\r\n\
+              Connection: close\r\n\
+              \r\n",
+        )
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let res = s(&buf);
+    assert!(res.contains("\r\nX-Forwarded-For: 10.0.0.1\r\n"), "{}", res);
+    assert!(res.contains("\r\nx-id: overridden\r\n"), "{}", res);
+    assert!(!res.contains("X-Id"), "{}", res);
+    assert!(!res.contains("Keep-Alive"), "{}", res);
+    assert!(!res.contains("Proxy-Authorization"), "{}", res);
+    assert!(res.ends_with("lazy"));
+
+    conn.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn http1_lazy_headers_not_forwarded_by_default() {
+    use hyper::ext::LazyHeaders;
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let lazy = req.extensions().get::<LazyHeaders>().unwrap().clone();
+        let mut res = Response::new(Full::new(Bytes::from("lazy")));
+        res.extensions_mut().insert(lazy);
+        Ok::<_, hyper::Error>(res)
+    });
+    let conn = Http::new()
+        .http1_lazy_headers(true)
+        .serve_connection(TokioIo::new(server), svc);
+    let conn = tokio::spawn(conn);
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nX-Forwarded-For: 10.0.0.1\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    let res = s(&buf);
+    assert!(!res.contains("X-Forwarded-For"), "{}", res);
+    assert!(res.ends_with("lazy"));
+
+    conn.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn http1_body_channel_depth_reads_ahead() {
+    let _ = pretty_env_logger::try_init();
+    // The pipe only holds a few chunks, so the whole body can only be
+    // written if the connection reads it ahead of the service.
+    let (mut client, server) = tokio::io::duplex(64);
+    let (tx, rx) = oneshot::channel::<()>();
+    let rx = Arc::new(Mutex::new(Some(rx)));
+    let svc = service_fn(move |req: Request<Recv>| {
+        let rx = rx.lock().unwrap().take().unwrap();
+        async move {
+            // Hold the body without reading it until the client is done.
+            let _ = rx.await;
+            drop(req);
+            Ok::<_, hyper::Error>(Response::new(Empty::<Bytes>::new()))
+        }
+    });
+    let conn = Http::new()
+        .http1_body_channel_depth(64)
+        .serve_connection(TokioIo::new(server), svc);
+    let conn = tokio::spawn(conn);
+
+    client
+        .write_all(b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2048\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(5), client.write_all(&[b'x'; 2048]))
+        .await
+        .expect("body read ahead")
+        .unwrap();
+
+    tx.send(()).unwrap();
+    let mut buf = [0; 17];
+    client.read_exact(&mut buf).await.unwrap();
+    assert_eq!(s(&buf), "HTTP/1.1 200 OK\r\n");
+    drop(client);
+    let _ = conn.await.unwrap();
+}
+
+#[tokio::test]
+async fn http1_buffer_pool_reused_across_connections() {
+    use hyper::buffer::BufferPool;
+
+    let _ = pretty_env_logger::try_init();
+    let pool = BufferPool::new(4);
+    let mut http = Http::new();
+    http.http1_buffer_pool(pool.clone());
+
+    for _ in 0..2 {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let svc = service_fn(|_req: Request<Recv>| async {
+            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("pooled"))))
+        });
+        let conn = http.serve_connection(TokioIo::new(server), svc);
+        let conn = tokio::spawn(conn);
+
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut buf = vec![];
+        client.read_to_end(&mut buf).await.unwrap();
+        assert!(s(&buf).ends_with("pooled"));
+
+        conn.await.unwrap().unwrap();
+        // The read and write buffers were given back.
+        assert_eq!(pool.idle(), 2);
+    }
+}
+
+#[test]
+fn request_id_generate_is_unique() {
+    use hyper::server::conn::RequestId;
+
+    let a = RequestId::generate();
+    let b = RequestId::generate();
+    assert_ne!(a, b);
+}
+
+#[tokio::test]
+async fn http1_timings() {
+    use hyper::ext::Timings;
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let svc = service_fn(|req: Request<Recv>| async move {
+        let timings = req.extensions().get::<Timings>().cloned().expect("timings");
+        assert!(timings.tls_handshaked().is_none());
+        assert!(timings.request_written().is_none());
+        let first_byte = timings.first_byte().expect("first_byte");
+        let headers = timings.headers_complete().expect("headers_complete");
+        assert!(timings.connected() <= first_byte);
+        assert!(first_byte <= headers);
+
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let complete = timings.body_complete().expect("body_complete");
+        assert!(headers <= complete);
+        Ok::<_, hyper::Error>(Response::new(Full::new(body)))
+    });
+    let conn = Http::new()
+        .http1_timings(true)
+        .serve_connection(TokioIo::new(server), svc);
+    let conn = tokio::spawn(conn);
+
+    client
+        .write_all(b"POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    client.write_all(b"hello").await.unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+    assert!(s(&buf).ends_with("hello"));
+    conn.await.unwrap().unwrap();
+}
+
+#[cfg(feature = "debug")]
+#[tokio::test]
+async fn capture_h1_bytes() {
+    use hyper::capture::{Direction, RingBuffer};
+
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+    let ring = RingBuffer::new(4096);
+
+    let conn = Http::new()
+        .capture(ring.clone())
+        .serve_connection(TokioIo::new(server), HelloWorld);
+    let conn = tokio::spawn(conn);
+
+    let req = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    client.write_all(req).await.unwrap();
+    let mut res = vec![];
+    client.read_to_end(&mut res).await.unwrap();
+    conn.await.unwrap().unwrap();
+
+    let chunks = ring.snapshot();
+    let id = chunks[0].id();
+    assert!(chunks.iter().all(|chunk| chunk.id() == id));
+    let captured = |direction| {
+        chunks
+            .iter()
+            .filter(|chunk| chunk.direction() == direction)
+            .flat_map(|chunk| chunk.bytes().to_vec())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(captured(Direction::Read), req);
+    assert_eq!(captured(Direction::Write), res);
+}
+
+#[tokio::test]
+async fn auto_builder_uses_alpn_protocol() {
+    let _ = pretty_env_logger::try_init();
+    let (mut client, server) = tokio::io::duplex(1024);
+
+    let conn = hyper::server::conn::auto::Builder::new()
+        .with_executor(TokioExecutor)
+        .serve_connection_with_alpn(TokioIo::new(server), Some(b"http/1.1"), HelloWorld);
+    tokio::spawn(async move {
+        conn.await.expect("serve_connection_with_alpn");
+    });
+
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).await.unwrap();
+
+    let expected = "HTTP/1.1 200 OK\r\n";
+    assert_eq!(s(&buf[..expected.len()]), expected);
+    assert!(s(&buf).ends_with(HELLO));
+}
+
 #[cfg(feature = "http1")]
 #[test]
 #[should_panic]
@@ -2173,7 +4322,7 @@ async fn max_buf_size() {
     let (socket, _) = listener.accept().await.unwrap();
     Http::new()
         .max_buf_size(MAX)
-        .serve_connection(socket, HelloWorld)
+        .serve_connection(TokioIo::new(socket), HelloWorld)
         .await
         .expect_err("should TooLarge error");
 }
@@ -2428,7 +4577,7 @@ async fn http2_keep_alive_detects_unresponsive_client() {
         .http2_only(true)
         .http2_keep_alive_interval(Duration::from_secs(1))
         .http2_keep_alive_timeout(Duration::from_secs(1))
-        .serve_connection(socket, unreachable_service())
+        .serve_connection(TokioIo::new(socket), unreachable_service())
         .await
         .expect_err("serve_connection should error");
 
@@ -2451,7 +4600,7 @@ async fn http2_keep_alive_with_responsive_client() {
             .http2_only(true)
             .http2_keep_alive_interval(Duration::from_secs(1))
             .http2_keep_alive_timeout(Duration::from_secs(1))
-            .serve_connection(socket, HelloWorld)
+            .serve_connection(TokioIo::new(socket), HelloWorld)
             .await
             .expect("serve_connection");
     });
@@ -2459,7 +4608,7 @@ async fn http2_keep_alive_with_responsive_client() {
     let tcp = connect_async(addr).await;
     let (mut client, conn) = hyper::client::conn::http2::Builder::new()
         .executor(TokioExecutor)
-        .handshake(tcp)
+        .handshake(TokioIo::new(tcp))
         .await
         .expect("http handshake");
 
@@ -2519,7 +4668,7 @@ async fn http2_keep_alive_count_server_pings() {
             .http2_only(true)
             .http2_keep_alive_interval(Duration::from_secs(1))
             .http2_keep_alive_timeout(Duration::from_secs(1))
-            .serve_connection(socket, unreachable_service())
+            .serve_connection(TokioIo::new(socket), unreachable_service())
             .await
             .expect("serve_connection");
     });
@@ -2909,7 +5058,7 @@ impl ServeOptions {
                                         reply: reply_rx,
                                     };
 
-                                    http.serve_connection(stream, service).await.unwrap();
+                                    http.serve_connection(TokioIo::new(stream), service).await.unwrap();
                                 });
                             }
                             _ = &mut shutdown_rx => {
@@ -3083,7 +5232,7 @@ impl TestClient {
         if self.http2_only {
             let (mut sender, conn) = hyper::client::conn::http2::Builder::new()
                 .executor(TokioExecutor)
-                .handshake(stream)
+                .handshake(TokioIo::new(stream))
                 .await
                 .unwrap();
             tokio::task::spawn(async move {
@@ -3094,7 +5243,7 @@ impl TestClient {
         } else {
             let (mut sender, conn) = hyper::client::conn::http1::Builder::new()
                 .executor(TokioExecutor)
-                .handshake(stream)
+                .handshake(TokioIo::new(stream))
                 .await
                 .unwrap();
             tokio::task::spawn(async move {
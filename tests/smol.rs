@@ -0,0 +1,229 @@
+#![deny(warnings)]
+#![warn(rust_2018_idioms)]
+
+// These tests run hyper without Tokio, using the `smol` feature's executor
+// and timer, and `FuturesIo` over `async-io` sockets.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use async_io::Async;
+use bytes::Bytes;
+use futures_channel::oneshot;
+use http_body_util::Full;
+use hyper::body::to_bytes as concat;
+use hyper::client::conn;
+use hyper::rt::{FuturesIo, SmolExecutor, SmolTimer, Timer};
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Method, Recv, Request, Response, StatusCode, Version};
+
+fn block_on<F: Future>(fut: F) -> F::Output {
+    async_global_executor::block_on(fut)
+}
+
+fn spawn<F>(fut: F)
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    async_global_executor::spawn(fut).detach();
+}
+
+fn bind() -> (Async<TcpListener>, SocketAddr) {
+    let listener = Async::<TcpListener>::bind(([127, 0, 0, 1], 0)).expect("bind");
+    let addr = listener.get_ref().local_addr().expect("local_addr");
+    (listener, addr)
+}
+
+async fn connect(addr: SocketAddr) -> FuturesIo<Async<TcpStream>> {
+    FuturesIo::new(Async::<TcpStream>::connect(addr).await.expect("connect"))
+}
+
+// Serves connections with an echo service, using the given builder.
+fn echo_server(listener: Async<TcpListener>, http: Http<SmolExecutor>) {
+    spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.expect("accept");
+            let service = service_fn(|req: Request<Recv>| async move {
+                Ok::<_, Infallible>(Response::new(req.into_body()))
+            });
+            let conn = http.serve_connection(FuturesIo::new(stream), service);
+            spawn(async move {
+                let _ = conn.await;
+            });
+        }
+    });
+}
+
+#[test]
+fn http1_post() {
+    let _ = pretty_env_logger::try_init();
+
+    block_on(async {
+        let (listener, addr) = bind();
+        let mut http = Http::new().with_executor(SmolExecutor::new());
+        http.http1_only(true);
+        echo_server(listener, http);
+
+        let (mut client, conn) = conn::http1::Builder::new()
+            .executor(SmolExecutor::new())
+            .handshake(connect(addr).await)
+            .await
+            .expect("http handshake");
+        spawn(async move {
+            conn.await.expect("client conn");
+        });
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{}/", addr))
+            .body(Full::new(Bytes::from_static(b"hello smol")))
+            .expect("request builder");
+        let res = client.send_request(req).await.expect("send_request");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.version(), Version::HTTP_11);
+        let body = concat(res.into_body()).await.expect("body");
+        assert_eq!(body, "hello smol");
+    });
+}
+
+#[test]
+fn http2_post() {
+    let _ = pretty_env_logger::try_init();
+
+    block_on(async {
+        let (listener, addr) = bind();
+        let mut http = Http::new()
+            .with_executor(SmolExecutor::new())
+            .with_timer(SmolTimer::new());
+        http.http2_only(true);
+        echo_server(listener, http);
+
+        let (mut client, conn) = conn::http2::Builder::new()
+            .executor(SmolExecutor::new())
+            .timer(SmolTimer::new())
+            .handshake(connect(addr).await)
+            .await
+            .expect("http handshake");
+        spawn(async move {
+            conn.await.expect("client conn");
+        });
+
+        for _ in 0..3 {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(format!("http://{}/", addr))
+                .body(Full::new(Bytes::from_static(b"hello smol")))
+                .expect("request builder");
+            let res = client.send_request(req).await.expect("send_request");
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.version(), Version::HTTP_2);
+            let body = concat(res.into_body()).await.expect("body");
+            assert_eq!(body, "hello smol");
+        }
+    });
+}
+
+#[test]
+fn http1_header_read_timeout() {
+    let _ = pretty_env_logger::try_init();
+
+    block_on(async {
+        let (listener, addr) = bind();
+        let (tx, rx) = oneshot::channel();
+        spawn(async move {
+            let (stream, _) = listener.accept().await.expect("accept");
+            let service = service_fn(|_: Request<Recv>| async {
+                Ok::<_, Infallible>(Response::new(Full::new(Bytes::new())))
+            });
+            let mut http = Http::new()
+                .with_executor(SmolExecutor::new())
+                .with_timer(SmolTimer::new());
+            http.http1_only(true)
+                .http1_header_read_timeout(Duration::from_millis(100));
+            let res = http.serve_connection(FuturesIo::new(stream), service).await;
+            let _ = tx.send(res);
+        });
+
+        let stream = Async::<TcpStream>::connect(addr).await.expect("connect");
+        stream
+            .write_with(|mut s| s.write(b"GET / HTTP/1.1\r\n"))
+            .await
+            .expect("write");
+
+        // The server gives up on the incomplete head and closes.
+        let mut buf = [0; 1024];
+        loop {
+            let n = stream
+                .read_with(|mut s| s.read(&mut buf))
+                .await
+                .expect("read");
+            if n == 0 {
+                break;
+            }
+        }
+        rx.await
+            .expect("server task")
+            .expect_err("header read should time out");
+    });
+}
+
+#[test]
+fn http2_keep_alive_detects_unresponsive_server() {
+    let _ = pretty_env_logger::try_init();
+
+    block_on(async {
+        let (listener, addr) = bind();
+
+        // spawn a server that reads but doesn't write
+        spawn(async move {
+            let (stream, _) = listener.accept().await.expect("accept");
+            let mut buf = [0; 1024];
+            loop {
+                let n = stream
+                    .read_with(|mut s| s.read(&mut buf))
+                    .await
+                    .expect("server read");
+                if n == 0 {
+                    break;
+                }
+            }
+        });
+
+        let (_client, conn) = conn::http2::Builder::new()
+            .executor(SmolExecutor::new())
+            .timer(SmolTimer::new())
+            .http2_keep_alive_interval(Duration::from_millis(100))
+            .http2_keep_alive_timeout(Duration::from_millis(100))
+            // enable while idle since we aren't sending requests
+            .http2_keep_alive_while_idle(true)
+            .handshake::<_, Recv>(connect(addr).await)
+            .await
+            .expect("http handshake");
+
+        conn.await.expect_err("conn should time out");
+    });
+}
+
+#[test]
+fn timer_interval_skips_missed_ticks() {
+    block_on(async {
+        let period = Duration::from_millis(20);
+        let mut interval = SmolTimer::new().interval(period);
+
+        let first = futures_util::future::poll_fn(|cx| interval.poll_tick(cx)).await;
+
+        // Miss a few ticks. The pending one is still yielded, but the ones
+        // missed after it are skipped.
+        std::thread::sleep(period * 3 + period / 2);
+        let now = Instant::now();
+        let next = futures_util::future::poll_fn(|cx| interval.poll_tick(cx)).await;
+        assert_eq!(next, first + period);
+        let next = futures_util::future::poll_fn(|cx| interval.poll_tick(cx)).await;
+        assert!(next > now, "missed ticks are not yielded");
+    });
+}
@@ -20,8 +20,7 @@ pub use futures_util::{
 pub use hyper::{HeaderMap, StatusCode};
 pub use std::net::SocketAddr;
 
-mod tokiort;
-pub use tokiort::{TokioExecutor, TokioTimer};
+pub use hyper::rt::{TokioExecutor, TokioIo, TokioTimer};
 
 #[allow(unused_macros)]
 macro_rules! t {
@@ -386,7 +385,7 @@ async fn async_test(cfg: __TestConfig) {
                 Http::new()
                     .with_executor(TokioExecutor)
                     .http2_only(http2_only)
-                    .serve_connection(stream, service)
+                    .serve_connection(TokioIo::new(stream), service)
                     .await
                     .expect("server error");
             });
@@ -423,7 +422,7 @@ async fn async_test(cfg: __TestConfig) {
             let res = if http2_only {
                 let (mut sender, conn) = hyper::client::conn::http2::Builder::new()
                     .executor(TokioExecutor)
-                    .handshake(stream)
+                    .handshake(TokioIo::new(stream))
                     .await
                     .unwrap();
 
@@ -435,7 +434,7 @@ async fn async_test(cfg: __TestConfig) {
                 sender.send_request(req).await.unwrap()
             } else {
                 let (mut sender, conn) = hyper::client::conn::http1::Builder::new()
-                    .handshake(stream)
+                    .handshake(TokioIo::new(stream))
                     .await
                     .unwrap();
 
@@ -522,7 +521,7 @@ async fn naive_proxy(cfg: ProxyConfig) -> (SocketAddr, impl Future<Output = ()>)
                         let resp = if http2_only {
                             let (mut sender, conn) = hyper::client::conn::http2::Builder::new()
                                 .executor(TokioExecutor)
-                                .handshake(stream)
+                                .handshake(TokioIo::new(stream))
                                 .await
                                 .unwrap();
 
@@ -535,7 +534,8 @@ async fn naive_proxy(cfg: ProxyConfig) -> (SocketAddr, impl Future<Output = ()>)
                             sender.send_request(req).await?
                         } else {
                             let builder = hyper::client::conn::http1::Builder::new();
-                            let (mut sender, conn) = builder.handshake(stream).await.unwrap();
+                            let (mut sender, conn) =
+                                builder.handshake(TokioIo::new(stream)).await.unwrap();
 
                             tokio::task::spawn(async move {
                                 if let Err(err) = conn.await {
@@ -563,7 +563,7 @@ async fn naive_proxy(cfg: ProxyConfig) -> (SocketAddr, impl Future<Output = ()>)
                 Http::new()
                     .with_executor(TokioExecutor)
                     .http2_only(http2_only)
-                    .serve_connection(stream, service)
+                    .serve_connection(TokioIo::new(stream), service)
                     .await
                     .unwrap();
             }
@@ -1 +0,0 @@
-../../benches/support/tokiort.rs
\ No newline at end of file